@@ -47,6 +47,14 @@ const COMPILE_TIME_FEATURE_CHECKS: [&str; 16] = [
     "websockets",
 ];
 
+/// Maximum size of the datagrams accepted by the vendored libcoap build (the Ethernet MTU minus the
+/// IPv4 and UDP headers, which is also the receive buffer size libcoap uses by default).
+///
+/// The receive buffer of the vendored build is one byte larger than this, so that a datagram that
+/// fills the entire buffer is known to exceed this size (and to possibly have been truncated by the
+/// socket), which libcoap does not report itself.
+const VENDORED_MAX_DATAGRAM_SIZE: usize = 1472;
+
 /// Data structure describing meta-information about the used version of libcoap.
 #[derive(Debug)]
 struct LibcoapMetadata {
@@ -532,6 +540,12 @@ fn build_vendored_library(
         .disable("tests", None)
         .disable("examples", None)
         .disable("gcov", None);
+    // Reserve an additional byte in the receive buffer to detect oversized datagrams, see
+    // VENDORED_MAX_DATAGRAM_SIZE.
+    let rxbuffer_define = format!("-DCOAP_RXBUFFER_SIZE={}", VENDORED_MAX_DATAGRAM_SIZE + 1);
+    build_config.cflag(&rxbuffer_define);
+    builder = builder.clang_arg(&rxbuffer_define);
+    println!("cargo:max_datagram_size={}", VENDORED_MAX_DATAGRAM_SIZE);
 
     // Enable debug symbols if enabled in Rust
     match env::var_os("DEBUG")
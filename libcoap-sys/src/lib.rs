@@ -88,10 +88,12 @@
 
 use std::ffi::c_void;
 
+// On Windows, the socket types are generated by bindgen from the Winsock headers instead.
 #[allow(unused_imports)]
+#[cfg(unix)]
 use libc::{fd_set, memcmp, sa_family_t, sockaddr, sockaddr_in, sockaddr_in6, socklen_t, time_t};
 #[allow(unused_imports)]
-#[cfg(not(target_os = "espidf"))]
+#[cfg(all(unix, not(target_os = "espidf")))]
 use libc::epoll_event;
 // use dtls backend libraries in cases where they set our linker flags, otherwise cargo will
 // optimize them out.
@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * payload_allocations.rs - Benchmark for the allocations caused by large payloads.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Measures the memory allocated by the server while handling requests to a resource that echoes
+//! a 64 KiB request payload, comparing a handler that uses the borrowed request payload and a
+//! shared response payload to one that copies both (as was required before these were available).
+//!
+//! Run using `cargo bench --bench payload_allocations`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use libcoap_rs::{
+    message::{CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+/// Size of the payload that is echoed.
+const PAYLOAD_SIZE: usize = 64 * 1024;
+
+/// Number of requests sent for each resource.
+const ITERATIONS: usize = 20;
+
+/// Global allocator that counts the allocations made while counting is enabled.
+struct CountingAllocator {
+    counting: AtomicBool,
+    allocations: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.counting.load(Ordering::Relaxed) {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator {
+    counting: AtomicBool::new(false),
+    allocations: AtomicUsize::new(0),
+    bytes: AtomicUsize::new(0),
+};
+
+/// Echoes the request payload using a shared response payload, without copying the request.
+fn echo_borrowed(_: &mut (), session: &mut CoapServerSession, request: &CoapRequest, mut response: CoapResponse) {
+    response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+    response.set_data_shared(Some(Arc::<[u8]>::from(request.payload())));
+    session.send(response).unwrap();
+}
+
+/// Echoes the request payload after copying the request, using an owned response payload.
+fn echo_copied(_: &mut (), session: &mut CoapServerSession, request: &CoapRequest, mut response: CoapResponse) {
+    let request = request.clone();
+    response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+    response.set_data(Some(request.data().unwrap().to_vec()));
+    session.send(response).unwrap();
+}
+
+/// Sends requests to the given resource and returns the average number of allocations and
+/// allocated bytes of the server per request.
+fn measure(
+    server_context: &mut CoapContext,
+    context: &mut CoapContext,
+    session: &CoapClientSession,
+    path: &str,
+) -> (usize, usize) {
+    let payload: Vec<u8> = (0..PAYLOAD_SIZE).map(|i| i as u8).collect();
+    let allocations = ALLOCATOR.allocations.load(Ordering::Relaxed);
+    let bytes = ALLOCATOR.bytes.load(Ordering::Relaxed);
+    for _ in 0..ITERATIONS {
+        let request = CoapRequestBuilder::new(CoapRequestCode::Post)
+            .uri_path([path])
+            .payload(payload.clone())
+            .build()
+            .unwrap();
+        let req_handle = session.send_request(request).unwrap();
+        let start = Instant::now();
+        loop {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "timeout while waiting for response"
+            );
+            // Only the allocations of the server are counted.
+            ALLOCATOR.counting.store(true, Ordering::Relaxed);
+            server_context.do_io(Some(Duration::from_millis(1))).unwrap();
+            ALLOCATOR.counting.store(false, Ordering::Relaxed);
+            context.do_io(Some(Duration::from_millis(1))).unwrap();
+            if let Some(response) = session.poll_handle(&req_handle).next() {
+                assert_eq!(response.payload(), payload.as_slice());
+                break;
+            }
+        }
+        session.remove_handle(req_handle);
+    }
+    (
+        (ALLOCATOR.allocations.load(Ordering::Relaxed) - allocations) / ITERATIONS,
+        (ALLOCATOR.bytes.load(Ordering::Relaxed) - bytes) / ITERATIONS,
+    )
+}
+
+fn main() {
+    let server_address = "127.0.0.1:0".parse().unwrap();
+    let mut server_context = CoapContext::new().unwrap();
+    let endpoint = server_context.add_endpoint_udp(server_address).unwrap();
+    let server_address = server_context
+        .with_endpoint(endpoint, |ep| ep.local_addr())
+        .flatten()
+        .unwrap();
+    let resource = CoapResource::new("borrowed", (), false);
+    resource.set_method_handler(CoapRequestCode::Post, Some(CoapRequestHandler::new(echo_borrowed)));
+    server_context.add_resource(resource);
+    let resource = CoapResource::new("copied", (), false);
+    resource.set_method_handler(CoapRequestCode::Post, Some(CoapRequestHandler::new(echo_copied)));
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    for path in ["borrowed", "copied"] {
+        let (allocations, bytes) = measure(&mut server_context, &mut context, &session, path);
+        println!(
+            "{path}: {allocations} allocations ({bytes} bytes) per {} KiB echo",
+            PAYLOAD_SIZE / 1024
+        );
+    }
+}
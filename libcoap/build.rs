@@ -7,6 +7,15 @@ fn main() {
     println!("cargo::rustc-check-cfg=cfg(dtls_cid_support)");
     println!("cargo::rustc-check-cfg=cfg(coap_uri_buf_unused)");
     println!("cargo::rustc-check-cfg=cfg(dtls)");
+    println!("cargo::rustc-check-cfg=cfg(tls)");
+    println!("cargo::rustc-check-cfg=cfg(tls_engine_support)");
+    println!("cargo::rustc-check-cfg=cfg(session_reconnect_support)");
+    println!("cargo::rustc-check-cfg=cfg(coap_rxbuffer_headroom)");
+    // Vendored builds of libcoap have a receive buffer that is one byte larger than the maximum
+    // datagram size, which allows detecting oversized (and therefore truncated) datagrams.
+    if std::env::var_os("DEP_COAP_3_MAX_DATAGRAM_SIZE").is_some() {
+        println!("cargo:rustc-cfg=coap_rxbuffer_headroom");
+    }
     if let Ok(libcoap_version) = std::env::var("DEP_COAP_3_LIBCOAP_VERSION") {
         let version = Version::from(libcoap_version.as_ref()).expect("invalid libcoap version");
         // libcoap >= 4.3.5rc2 no longer uses the buf and buflen parameters in
@@ -26,7 +35,23 @@ fn main() {
             },
             _ => {},
         }
+        // libcoap >= 4.3.5 allows configuring the TLS engine using coap_tls_engine_configure() and
+        // reconnecting client sessions using coap_context_set_session_reconnect_time().
+        match version.compare(Version::from("4.3.5").unwrap()) {
+            Cmp::Gt | Cmp::Eq => {
+                println!("cargo:rustc-cfg=tls_engine_support");
+                println!("cargo:rustc-cfg=session_reconnect_support");
+            },
+            _ => {},
+        }
     }
+    // The dtls and tls cfg flags indicate whether encrypted sessions can actually be created, which
+    // also requires a way to provide credentials.
     #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk", feature = "dtls-psk"))]
-    println!("cargo:rustc-cfg=dtls")
+    println!("cargo:rustc-cfg=dtls");
+    #[cfg(all(
+        feature = "tls",
+        any(feature = "dtls-pki", feature = "dtls-rpk", feature = "dtls-psk")
+    ))]
+    println!("cargo:rustc-cfg=tls");
 }
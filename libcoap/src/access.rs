@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * access.rs - Types for restricting access to resources.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for restricting access to resources based on the security parameters
+//! of the session a request was received on.
+//!
+//! Access hooks are set for individual resources using
+//! [CoapResource::set_access_hook()](crate::CoapResource::set_access_hook), resources without a
+//! hook of their own use the default hook of their context (see
+//! [CoapContext::set_default_access_hook()](crate::CoapContext::set_default_access_hook)).
+//! The hook is called before the request handler and decides whether the handler may be called
+//! (see [CoapAccessDecision]).
+//!
+//! Hooks are called for every request dispatched to a resource, including requests that register
+//! (or cancel) an observation. libcoap registers observers before the request handler is
+//! called and removes them again if the request is not answered with a success response, so
+//! denying a request with an Observe option also refuses the registration. As libcoap calls the
+//! GET handler again for every notification, hooks can also end existing observations by denying
+//! the requests (e.g., once the permissions of a peer have been revoked).
+//!
+//! # Example
+//! ```no_run
+//! use libcoap_rs::{
+//!     access::CoapAccessDecision,
+//!     protocol::{CoapRequestCode, CoapResponseCode},
+//!     CoapResource,
+//! };
+//!
+//! let resource = CoapResource::new("config", (), false);
+//! // Only allow peers with the "admin" PSK identity to change the resource.
+//! resource.set_access_hook(|crypto_info, method, _request| {
+//!     if method == CoapRequestCode::Get {
+//!         return CoapAccessDecision::Allow;
+//!     }
+//!     match crypto_info.and_then(|info| info.psk_identity()) {
+//!         Some(b"admin") => CoapAccessDecision::Allow,
+//!         Some(_) => CoapAccessDecision::deny(CoapResponseCode::Forbidden),
+//!         None => CoapAccessDecision::deny(CoapResponseCode::Unauthorized),
+//!     }
+//! });
+//! ```
+
+use std::{fmt::Debug, rc::Rc};
+
+use crate::{
+    crypto::CoapCryptoSessionInfo,
+    message::request::CoapRequest,
+    protocol::{CoapRequestCode, CoapResponseCode},
+};
+
+/// Function type of access hooks, see
+/// [CoapResource::set_access_hook()](crate::CoapResource::set_access_hook).
+///
+/// The hook is called with the security parameters of the session the request was received on
+/// (None for sessions that are neither encrypted nor protected using OSCORE, see
+/// [CoapSessionCommon::crypto_info()](crate::session::CoapSessionCommon::crypto_info)), the method
+/// of the request and the request itself.
+pub type CoapAccessHook = dyn Fn(Option<&CoapCryptoSessionInfo>, CoapRequestCode, &CoapRequest) -> CoapAccessDecision;
+
+/// Decision of a [CoapAccessHook] on whether a request may be passed to the request handler.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoapAccessDecision {
+    /// The request is passed to the request handler.
+    Allow,
+    /// The request is answered with the given response code (usually 4.01 (Unauthorized) or 4.03
+    /// (Forbidden)) and the diagnostic message as its payload (if provided), without calling the
+    /// request handler.
+    Deny {
+        code: CoapResponseCode,
+        diagnostic: Option<String>,
+    },
+    /// The request is only passed to the request handler if it contains a valid Echo value (see the
+    /// [echo](crate::echo) module), otherwise it is answered with a 4.01 (Unauthorized) response
+    /// containing a fresh Echo value.
+    ///
+    /// This allows verifying that the peer is reachable at its claimed source address before
+    /// handling requests from unauthenticated sessions.
+    Challenge,
+}
+
+impl CoapAccessDecision {
+    /// Returns a decision denying the request with the given response code and no diagnostic
+    /// message.
+    pub fn deny(code: CoapResponseCode) -> CoapAccessDecision {
+        CoapAccessDecision::Deny { code, diagnostic: None }
+    }
+
+    /// Returns a decision denying the request with the given response code and diagnostic
+    /// message.
+    pub fn deny_with_diagnostic<S: Into<String>>(code: CoapResponseCode, diagnostic: S) -> CoapAccessDecision {
+        CoapAccessDecision::Deny {
+            code,
+            diagnostic: Some(diagnostic.into()),
+        }
+    }
+}
+
+/// Shared handle to an access hook, which can be stored in types deriving [Debug].
+#[derive(Clone)]
+pub(crate) struct AccessHookHandle(Rc<CoapAccessHook>);
+
+impl AccessHookHandle {
+    pub(crate) fn new<
+        F: Fn(Option<&CoapCryptoSessionInfo>, CoapRequestCode, &CoapRequest) -> CoapAccessDecision + 'static,
+    >(
+        hook: F,
+    ) -> AccessHookHandle {
+        AccessHookHandle(Rc::new(hook))
+    }
+
+    /// Calls the access hook for the given request.
+    pub(crate) fn decide(
+        &self,
+        crypto_info: Option<&CoapCryptoSessionInfo>,
+        method: CoapRequestCode,
+        request: &CoapRequest,
+    ) -> CoapAccessDecision {
+        (self.0)(crypto_info, method, request)
+    }
+}
+
+impl Debug for AccessHookHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessHookHandle").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,238 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * cache.rs - Types for accessing the server-side cache of libcoap.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for libcoap's server-side cache.
+//!
+//! The cache allows request handlers to store application data for requests, so that subsequent
+//! identical requests can be answered without recomputing the response. Entries are identified by
+//! a cache key derived from the options and payload of a request (excluding the options set using
+//! [CoapContext::cache_ignore_options()](crate::CoapContext::cache_ignore_options)), and are
+//! removed by libcoap once their idle timeout has elapsed.
+//!
+//! See the [libcoap documentation](https://libcoap.net/doc/reference/4.3.5/group__cache.html)
+//! for more information.
+
+use std::{any::Any, ffi::c_void, rc::Rc, time::Duration};
+
+use libcoap_sys::{
+    coap_cache_derive_key, coap_cache_entry_t, coap_cache_get_app_data, coap_cache_get_by_key, coap_cache_key_t,
+    coap_cache_record_pdu_t, coap_cache_session_based_t, coap_cache_set_app_data, coap_delete_cache_entry,
+    coap_delete_cache_key, coap_delete_pdu, coap_new_cache_entry, coap_pdu_t, coap_session_get_context,
+};
+
+use crate::{
+    error::CacheError,
+    message::request::CoapRequest,
+    session::{CoapServerSession, CoapSessionCommon},
+    unwind::catch_callback_panic,
+};
+
+/// An entry of libcoap's server-side cache.
+///
+/// Instances of this type refer to the entry using its cache key, i.e., the entry may be removed
+/// by libcoap (e.g., because its idle timeout has elapsed) while an instance still exists. In that
+/// case, [CoapCacheEntry::app_data()] returns None and [CoapCacheEntry::set_app_data()] fails.
+///
+/// As an instance keeps a reference to the session it was obtained for, it should not be kept
+/// after the context has been dropped.
+#[derive(Debug)]
+pub struct CoapCacheEntry<'a> {
+    session: CoapServerSession<'a>,
+    key: *mut coap_cache_key_t,
+}
+
+impl<'a> CoapCacheEntry<'a> {
+    /// Looks up the cache entry for the given request received on `session`.
+    ///
+    /// If `session_based` is true, only entries that were created for the same session are
+    /// considered.
+    ///
+    /// Returns None if there is no entry for this request.
+    ///
+    /// # Errors
+    /// Returns [CacheError::InvalidRequest] if the cache key could not be derived from the
+    /// request (e.g., because it has no message ID).
+    pub fn lookup(
+        session: &CoapServerSession<'a>,
+        request: &CoapRequest,
+        session_based: bool,
+    ) -> Result<Option<CoapCacheEntry<'a>>, CacheError> {
+        let entry = CoapCacheEntry::for_request(session, request, session_based)?;
+        Ok(entry.raw_entry().map(|_| entry))
+    }
+
+    /// Creates a new cache entry for the given request received on `session`.
+    ///
+    /// If `session_based` is true, the entry is only valid for the given session and is removed
+    /// alongside it. The entry is removed once it has not been used for `idle_timeout` (rounded up
+    /// to full seconds), or never if `idle_timeout` is None.
+    ///
+    /// # Errors
+    /// Returns [CacheError::InvalidRequest] if the cache key could not be derived from the
+    /// request, [CacheError::AlreadyExists] if there already is an entry for this request, or
+    /// [CacheError::Unknown] if libcoap was unable to create the entry.
+    pub fn create(
+        session: &CoapServerSession<'a>,
+        request: &CoapRequest,
+        session_based: bool,
+        idle_timeout: Option<Duration>,
+    ) -> Result<CoapCacheEntry<'a>, CacheError> {
+        let entry = CoapCacheEntry::for_request(session, request, session_based)?;
+        if entry.raw_entry().is_some() {
+            return Err(CacheError::AlreadyExists);
+        }
+        // libcoap uses an idle timeout of zero to indicate that the entry never expires.
+        let idle_timeout = idle_timeout.map_or(0, |timeout| {
+            let mut seconds = u32::try_from(timeout.as_secs()).unwrap_or(u32::MAX);
+            if timeout.subsec_nanos() > 0 {
+                seconds = seconds.saturating_add(1);
+            }
+            seconds.max(1)
+        });
+        let raw_entry = with_raw_request_pdu(session, request, |raw_pdu| {
+            // SAFETY: Session and PDU are valid, the PDU is copied if it is recorded.
+            unsafe {
+                coap_new_cache_entry(
+                    session.raw_session_mut(),
+                    raw_pdu,
+                    coap_cache_record_pdu_t::COAP_CACHE_NOT_RECORD_PDU,
+                    raw_session_based(session_based),
+                    idle_timeout,
+                )
+            }
+        })?;
+        if raw_entry.is_null() {
+            return Err(CacheError::Unknown);
+        }
+        Ok(entry)
+    }
+
+    /// Derives the cache key for the given request and creates an instance referring to it.
+    fn for_request(
+        session: &CoapServerSession<'a>,
+        request: &CoapRequest,
+        session_based: bool,
+    ) -> Result<CoapCacheEntry<'a>, CacheError> {
+        let key = with_raw_request_pdu(session, request, |raw_pdu| {
+            // SAFETY: Session and PDU are valid, the returned key is owned by us.
+            unsafe { coap_cache_derive_key(session.raw_session(), raw_pdu, raw_session_based(session_based)) }
+        })?;
+        if key.is_null() {
+            return Err(CacheError::Unknown);
+        }
+        Ok(CoapCacheEntry {
+            session: session.clone(),
+            key,
+        })
+    }
+
+    /// Returns the raw cache entry this instance refers to, or None if it does not exist
+    /// (anymore).
+    fn raw_entry(&self) -> Option<*mut coap_cache_entry_t> {
+        // SAFETY: The session (and therefore its context) is valid for as long as we hold a
+        // reference to it, the key is owned by us.
+        let raw_entry =
+            unsafe { coap_cache_get_by_key(coap_session_get_context(self.session.raw_session()), self.key) };
+        (!raw_entry.is_null()).then_some(raw_entry)
+    }
+
+    /// Returns whether this cache entry still exists.
+    pub fn exists(&self) -> bool {
+        self.raw_entry().is_some()
+    }
+
+    /// Returns the application data stored in this cache entry.
+    ///
+    /// Returns None if no data is set, the stored data is not of type `T`, or the entry has been
+    /// removed.
+    pub fn app_data<T: Any>(&self) -> Option<Rc<T>> {
+        let raw_entry = self.raw_entry()?;
+        // SAFETY: The raw entry is valid, and its application data is either null or was set by
+        // set_app_data().
+        let app_data = unsafe { coap_cache_get_app_data(raw_entry) } as *const Rc<dyn Any>;
+        if app_data.is_null() {
+            return None;
+        }
+        // SAFETY: See above.
+        Rc::clone(unsafe { &*app_data }).downcast().ok()
+    }
+
+    /// Stores the given application data in this cache entry, replacing (and dropping) any
+    /// previously stored value.
+    ///
+    /// The data is kept alive until libcoap removes the entry (or until references obtained
+    /// using [CoapCacheEntry::app_data()] are dropped, whichever is later).
+    ///
+    /// # Errors
+    /// Returns [CacheError::EntryRemoved] if the entry does not exist anymore.
+    pub fn set_app_data<T: Any>(&self, data: T) -> Result<(), CacheError> {
+        let raw_entry = self.raw_entry().ok_or(CacheError::EntryRemoved)?;
+        let new_app_data = Box::into_raw(Box::new(Rc::new(data) as Rc<dyn Any>));
+        // SAFETY: The raw entry is valid. libcoap does not free previously set application data
+        // when setting new data, so this is done here. All application data of cache entries is
+        // set using this function and is therefore a valid pointer to a Box<Rc<dyn Any>>.
+        unsafe {
+            let old_app_data = coap_cache_get_app_data(raw_entry);
+            coap_cache_set_app_data(
+                raw_entry,
+                new_app_data as *mut c_void,
+                Some(cache_app_data_free_callback),
+            );
+            if !old_app_data.is_null() {
+                cache_app_data_free_callback(old_app_data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes this entry from the cache, dropping its application data (if any).
+    pub fn remove(self) {
+        if let Some(raw_entry) = self.raw_entry() {
+            // SAFETY: The session (and therefore its context) as well as the entry are valid.
+            unsafe { coap_delete_cache_entry(coap_session_get_context(self.session.raw_session()), raw_entry) }
+        }
+    }
+}
+
+impl Drop for CoapCacheEntry<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The key was created by coap_cache_derive_key() and is owned by us.
+        unsafe { coap_delete_cache_key(self.key) }
+    }
+}
+
+/// Converts the given request into a raw PDU, calls `f` with it and frees the PDU afterwards.
+fn with_raw_request_pdu<R>(
+    session: &CoapServerSession,
+    request: &CoapRequest,
+    f: impl FnOnce(*mut coap_pdu_t) -> R,
+) -> Result<R, CacheError> {
+    let raw_pdu = request.clone().into_message().into_raw_pdu(session)?;
+    let result = f(raw_pdu);
+    // SAFETY: The PDU was created above and is not referenced by libcoap.
+    unsafe { coap_delete_pdu(raw_pdu) };
+    Ok(result)
+}
+
+fn raw_session_based(session_based: bool) -> coap_cache_session_based_t {
+    if session_based {
+        coap_cache_session_based_t::COAP_CACHE_IS_SESSION_BASED
+    } else {
+        coap_cache_session_based_t::COAP_CACHE_NOT_SESSION_BASED
+    }
+}
+
+/// Frees application data that was set using [CoapCacheEntry::set_app_data()], called by libcoap
+/// when a cache entry is removed.
+unsafe extern "C" fn cache_app_data_free_callback(data: *mut c_void) {
+    // The destructor of the application data may panic.
+    catch_callback_panic(std::ptr::null_mut(), (), || {
+        std::mem::drop(Box::from_raw(data as *mut Rc<dyn Any>))
+    })
+}
@@ -0,0 +1,244 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * client.rs - Helpers for sending single requests without managing a context.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing functions for sending single requests without setting up a context.
+//!
+//! Each of the functions in this module creates a [CoapContext] and a session for the host of
+//! the given URI (using UDP, TCP or DTLS depending on the URI scheme), performs IO until the
+//! response has been received or the timeout has elapsed, and tears everything down again
+//! afterwards.
+//! They are intended for scripts, tests and simple tools: applications sending more than a few
+//! requests should create a context and session themselves, as this avoids repeating the
+//! connection setup (and (D)TLS handshake) for every request.
+//!
+//! Block-wise transfers are handled by libcoap, i.e., large request payloads are sent in blocks
+//! and the returned response contains the complete (reassembled) body.
+//!
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use libcoap_rs::client::{self, RequestOptions};
+//!
+//! let options = RequestOptions::new().timeout(Duration::from_secs(5));
+//! let response = client::get("coap://[::1]/hello", options).unwrap();
+//! println!("{:?}", response.data());
+//! ```
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "dtls-psk")]
+use crate::crypto::psk::{ClientPskContextBuilder, PskKey};
+use crate::{
+    error::{ClientRequestError, UriParsingError},
+    message::{request::CoapRequestBuilder, response::CoapResponse},
+    protocol::{CoapContentFormat, CoapRequestCode},
+    session::{CoapClientSession, RetryPolicy},
+    types::{CoapUri, CoapUriScheme},
+    CoapContext,
+};
+
+/// Default total timeout of requests sent using the functions of this module.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Options for requests sent using the functions of the [client](crate::client) module.
+#[derive(Debug, Clone)]
+pub struct RequestOptions {
+    timeout: Duration,
+    confirmable: Option<bool>,
+    accept: Option<CoapContentFormat>,
+    content_format: Option<CoapContentFormat>,
+    payload: Option<Vec<u8>>,
+    retry: Option<RetryPolicy>,
+    #[cfg(feature = "dtls-psk")]
+    psk: Option<PskKey<'static>>,
+}
+
+impl RequestOptions {
+    /// Creates options for a confirmable request without payload and a total timeout of ten
+    /// seconds.
+    pub fn new() -> RequestOptions {
+        RequestOptions {
+            timeout: DEFAULT_TIMEOUT,
+            confirmable: None,
+            accept: None,
+            content_format: None,
+            payload: None,
+            retry: None,
+            #[cfg(feature = "dtls-psk")]
+            psk: None,
+        }
+    }
+
+    /// Sets the total timeout of the request, which includes the time required to establish the
+    /// session (e.g., to perform the DTLS handshake).
+    pub fn timeout(mut self, timeout: Duration) -> RequestOptions {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets whether the request should be sent as a confirmable (the default) or non-confirmable
+    /// message.
+    pub fn confirmable(mut self, confirmable: bool) -> RequestOptions {
+        self.confirmable = Some(confirmable);
+        self
+    }
+
+    /// Sets the content format the response should have (Accept option).
+    pub fn accept(mut self, accept: CoapContentFormat) -> RequestOptions {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Sets the payload of the request alongside its content format.
+    pub fn payload<P: Into<Vec<u8>>>(mut self, content_format: CoapContentFormat, payload: P) -> RequestOptions {
+        self.content_format = Some(content_format);
+        self.payload = Some(payload.into());
+        self
+    }
+
+    /// Sets the policy for re-sending the request if it is answered with 5.03 (Service
+    /// Unavailable), see [CoapClientSession::set_retry_policy()].
+    ///
+    /// All attempts have to complete within the total timeout of the request.
+    pub fn retry(mut self, policy: RetryPolicy) -> RequestOptions {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Sets the pre-shared key used for requests to `coaps` URIs.
+    #[cfg(feature = "dtls-psk")]
+    pub fn psk(mut self, psk: PskKey<'static>) -> RequestOptions {
+        self.psk = Some(psk);
+        self
+    }
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sends a GET request for the given URI and returns the response.
+///
+/// See [request()] for more information.
+pub fn get<U>(uri: U, options: RequestOptions) -> Result<CoapResponse, ClientRequestError>
+where
+    U: TryInto<CoapUri>,
+    UriParsingError: From<U::Error>,
+{
+    request(CoapRequestCode::Get, uri, options)
+}
+
+/// Sends a POST request for the given URI and returns the response.
+///
+/// See [request()] for more information.
+pub fn post<U>(uri: U, options: RequestOptions) -> Result<CoapResponse, ClientRequestError>
+where
+    U: TryInto<CoapUri>,
+    UriParsingError: From<U::Error>,
+{
+    request(CoapRequestCode::Post, uri, options)
+}
+
+/// Sends a PUT request for the given URI and returns the response.
+///
+/// See [request()] for more information.
+pub fn put<U>(uri: U, options: RequestOptions) -> Result<CoapResponse, ClientRequestError>
+where
+    U: TryInto<CoapUri>,
+    UriParsingError: From<U::Error>,
+{
+    request(CoapRequestCode::Put, uri, options)
+}
+
+/// Sends a DELETE request for the given URI and returns the response.
+///
+/// See [request()] for more information.
+pub fn delete<U>(uri: U, options: RequestOptions) -> Result<CoapResponse, ClientRequestError>
+where
+    U: TryInto<CoapUri>,
+    UriParsingError: From<U::Error>,
+{
+    request(CoapRequestCode::Delete, uri, options)
+}
+
+/// Sends a request with the given code for the given URI and returns the (first) response.
+///
+/// The session is created for the host and port of the URI, using UDP for `coap`, TCP for
+/// `coap+tcp` and DTLS (with the pre-shared key set using [RequestOptions::psk()]) for `coaps`
+/// URIs. The path and query of the URI are used as the path and query of the request.
+///
+/// Responses with error codes are returned as responses, use
+/// [CoapResponse::is_success()] to check whether the request was successful.
+///
+/// # Errors
+/// Returns [ClientRequestError::InvalidUri] if the URI could not be parsed,
+/// [ClientRequestError::MissingCredentials] for `coaps` URIs if no credentials were provided,
+/// [ClientRequestError::Session] if no session could be created (e.g., because the URI scheme is
+/// not supported or its host could not be resolved, or
+/// [SessionCreationError::HandshakeFailed](crate::error::SessionCreationError::HandshakeFailed)
+/// if the DTLS handshake failed), [ClientRequestError::ConnectionFailed] if the session failed to
+/// connect for another reason, [ClientRequestError::TimedOut] if no response was received
+/// within the timeout, and [ClientRequestError::Reset] if the peer rejected the request.
+pub fn request<U>(code: CoapRequestCode, uri: U, options: RequestOptions) -> Result<CoapResponse, ClientRequestError>
+where
+    U: TryInto<CoapUri>,
+    UriParsingError: From<U::Error>,
+{
+    let start = Instant::now();
+    let uri = uri.try_into().map_err(UriParsingError::from)?;
+    let timeout = options.timeout;
+    let remaining_time = || {
+        timeout
+            .checked_sub(start.elapsed())
+            .filter(|v| !v.is_zero())
+            .ok_or(ClientRequestError::TimedOut)
+    };
+
+    let mut context = CoapContext::new()?;
+    let session = connect(&mut context, &uri, &options)?;
+    context.wait_for_session_established(&session, remaining_time()?)?;
+    session.set_retry_policy(options.retry);
+
+    let mut builder = CoapRequestBuilder::new(code).uri(&uri).timeout(remaining_time()?);
+    if let Some(confirmable) = options.confirmable {
+        builder = builder.confirmable(confirmable);
+    }
+    if let Some(accept) = options.accept {
+        builder = builder.accept(accept);
+    }
+    if let Some(content_format) = options.content_format {
+        builder = builder.content_format(content_format);
+    }
+    if let Some(payload) = options.payload {
+        builder = builder.payload(payload);
+    }
+    Ok(context.send_and_wait(&session, builder.build()?, remaining_time()?)?)
+}
+
+/// Creates a session for the host of the given URI, choosing the transport based on its scheme.
+fn connect<'a>(
+    context: &mut CoapContext<'a>,
+    uri: &CoapUri,
+    options: &RequestOptions,
+) -> Result<CoapClientSession<'a>, ClientRequestError> {
+    if uri.scheme() != Some(CoapUriScheme::Coaps) {
+        return Ok(CoapClientSession::connect_uri(context, uri.clone())?);
+    }
+    #[cfg(feature = "dtls-psk")]
+    if let Some(psk) = &options.psk {
+        let crypto_ctx = ClientPskContextBuilder::new(psk.clone()).build();
+        return Ok(CoapClientSession::connect_dtls_uri(context, uri.clone(), crypto_ctx)?);
+    }
+    #[cfg(not(feature = "dtls-psk"))]
+    let _ = options;
+    Err(ClientRequestError::MissingCredentials)
+}
@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * clock.rs - Clock abstraction for the timeouts tracked by the wrapper.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing the clock that is used for the timeouts tracked by this crate.
+//!
+//! Some timeouts are not handled by libcoap itself but by this crate, namely:
+//! - the timeouts of requests (see [CoapRequest::set_timeout()](crate::message::CoapRequest::set_timeout)),
+//! - handshake deadlines (see [CoapContext::set_handshake_deadline()](crate::CoapContext::set_handshake_deadline)),
+//! - the grace periods of replaced endpoints (see [CoapContext::rebind_endpoint()](crate::CoapContext::rebind_endpoint)),
+//! - the idle time of server-side sessions (see
+//!   [CoapContext::set_idle_session_hook()](crate::CoapContext::set_idle_session_hook)),
+//! - the freshness of cached responses (see
+//!   [CoapClientSession::set_response_cache_capacity()](crate::session::CoapClientSession::set_response_cache_capacity)),
+//! - and the coalesce intervals of notifications (see
+//!   [CoapResource::set_coalesce_interval()](crate::CoapResource::set_coalesce_interval)).
+//!
+//! These timeouts are based on the [CoapClock] of the context (see
+//! [CoapContext::set_clock()](crate::CoapContext::set_clock)), which is the monotonic system
+//! clock ([SystemClock]) by default.
+//!
+//! Timers of libcoap itself (e.g., retransmissions of confirmable messages, reconnection attempts
+//! and session timeouts) always follow the system clock, as libcoap does not provide a way to
+//! replace its time source.
+//!
+//! If the `test-util` feature is enabled, [MockClock] can be used to advance the time seen by
+//! these timeouts manually, which allows testing timeout behavior without actually waiting (see
+//! also `CoapContext::advance_time_for_test()`).
+
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::time::Instant;
+#[cfg(feature = "test-util")]
+use std::{cell::Cell, time::Duration};
+
+/// A source of monotonic time for the timeouts tracked by this crate.
+pub trait CoapClock: Debug {
+    /// Returns the current time.
+    ///
+    /// Subsequent calls must never return an earlier time than previous ones.
+    fn now(&self) -> Instant;
+}
+
+/// Clock that returns the current time of the monotonic system clock (see [Instant::now()]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemClock;
+
+impl CoapClock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock whose time only changes when it is advanced manually.
+///
+/// Clones of a mock clock share the same time, so a test can keep a clone of the clock provided
+/// to a context and advance it later on.
+///
+/// This type is only available if the `test-util` feature is enabled.
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+#[cfg(feature = "test-util")]
+impl MockClock {
+    /// Creates a mock clock starting at the current time of the system clock.
+    pub fn new() -> MockClock {
+        MockClock::starting_at(Instant::now())
+    }
+
+    /// Creates a mock clock starting at the given time.
+    pub fn starting_at(start: Instant) -> MockClock {
+        MockClock {
+            start,
+            elapsed: Rc::new(Cell::new(Duration::ZERO)),
+        }
+    }
+
+    /// Advances the time of this clock (and all of its clones) by the given duration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resulting time can not be represented by an [Instant].
+    pub fn advance(&self, duration: Duration) {
+        let elapsed = self.elapsed.get() + duration;
+        assert!(
+            self.start.checked_add(elapsed).is_some(),
+            "mock clock advanced beyond the range of Instant"
+        );
+        self.elapsed.set(elapsed);
+    }
+
+    /// Returns how far this clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl CoapClock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
+
+/// Shared handle to the clock of a context, which defaults to the [SystemClock].
+#[derive(Clone, Debug)]
+pub(crate) struct ClockHandle(Rc<dyn CoapClock>);
+
+impl ClockHandle {
+    pub(crate) fn new<C: CoapClock + 'static>(clock: C) -> ClockHandle {
+        ClockHandle(Rc::new(clock))
+    }
+
+    /// Returns the current time of the clock.
+    pub(crate) fn now(&self) -> Instant {
+        self.0.now()
+    }
+}
+
+impl Default for ClockHandle {
+    fn default() -> Self {
+        ClockHandle::new(SystemClock)
+    }
+}
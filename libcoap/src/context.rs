@@ -9,70 +9,1119 @@
 
 //! Module containing context-internal types and traits.
 
-#[cfg(feature = "dtls-pki")]
-use std::ffi::CString;
+#[cfg(all(feature = "af-unix", unix, not(feature = "dtls-pki")))]
+use std::path::Path;
 #[cfg(dtls)]
 use std::ptr::NonNull;
-use std::{any::Any, ffi::c_void, fmt::Debug, net::SocketAddr, ops::Sub, sync::Once, time::Duration};
+#[cfg(any(unix, windows))]
+use std::sync::Arc;
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::{c_void, CString},
+    fmt::{Debug, Display, Formatter},
+    marker::PhantomData,
+    net::{IpAddr, SocketAddr, SocketAddrV6},
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 #[cfg(all(feature = "dtls-pki", unix))]
 use std::{os::unix::ffi::OsStrExt, path::Path};
 
-use libc::c_uint;
+use libc::{c_int, c_uint};
 #[cfg(feature = "dtls-pki")]
 use libcoap_sys::coap_context_set_pki_root_cas;
+#[cfg(session_reconnect_support)]
+use libcoap_sys::coap_context_set_session_reconnect_time;
 use libcoap_sys::{
-    coap_add_resource, coap_can_exit, coap_context_get_csm_max_message_size, coap_context_get_csm_timeout,
-    coap_context_get_max_handshake_sessions, coap_context_get_max_idle_sessions, coap_context_get_session_timeout,
-    coap_context_set_block_mode, coap_context_set_csm_max_message_size, coap_context_set_csm_timeout,
-    coap_context_set_keepalive, coap_context_set_max_handshake_sessions, coap_context_set_max_idle_sessions,
+    coap_add_resource, coap_addr_tuple_t, coap_bin_const_t, coap_cache_ignore_options, coap_can_exit,
+    coap_context_get_csm_max_message_size, coap_context_get_csm_timeout_ms, coap_context_get_max_handshake_sessions,
+    coap_context_get_max_idle_sessions, coap_context_get_session_timeout, coap_context_set_block_mode,
+    coap_context_set_csm_max_message_size, coap_context_set_csm_timeout_ms, coap_context_set_keepalive,
+    coap_context_set_max_handshake_sessions, coap_context_set_max_idle_sessions, coap_context_set_max_token_size,
     coap_context_set_session_timeout, coap_context_t, coap_event_t, coap_free_context, coap_get_app_data,
-    coap_io_process, coap_new_context, coap_proto_t, coap_register_event_handler, coap_register_response_handler,
-    coap_set_app_data, coap_startup_with_feature_checks, COAP_BLOCK_SINGLE_BODY, COAP_BLOCK_USE_LIBCOAP, COAP_IO_WAIT,
+    coap_get_resource_from_uri_path, coap_io_process, coap_join_mcast_group_intf, coap_make_str_const,
+    coap_new_context, coap_persist_observe_add, coap_persist_startup, coap_persist_stop, coap_persist_track_funcs,
+    coap_print_link, coap_print_status_t, coap_proto_t, coap_register_event_handler, coap_register_nack_handler,
+    coap_register_option, coap_register_pong_handler, coap_register_response_handler, coap_resource_set_get_observable,
+    coap_resource_t, coap_session_get_context, coap_session_t, coap_set_app_data, COAP_BLOCK_SINGLE_BODY,
+    COAP_BLOCK_USE_LIBCOAP, COAP_IO_WAIT, COAP_OPT_FILTER_LONG, COAP_OPT_FILTER_SHORT, COAP_PRINT_STATUS_ERROR,
+    COAP_PRINT_STATUS_TRUNC,
 };
+#[cfg(unix)]
+use libcoap_sys::{coap_context_get_coap_fd, coap_io_prepare_epoll, coap_tick_t, coap_ticks};
 
+#[cfg(feature = "test-util")]
+use crate::clock::MockClock;
 #[cfg(any(feature = "dtls-rpk", feature = "dtls-pki"))]
 use crate::crypto::pki_rpk::ServerPkiRpkCryptoContext;
 #[cfg(feature = "dtls-psk")]
 use crate::crypto::psk::ServerPskContext;
+#[cfg(dtls)]
+use crate::crypto::tls_backend;
+#[cfg(dtls)]
+use crate::crypto::ClientCryptoContext;
+#[cfg(any(unix, windows))]
+use crate::error::ContextHandleError;
+#[cfg(dtls)]
+use crate::error::FallbackConnectError;
+#[cfg(any(unix, windows))]
+use crate::handle::{CoapContextHandle, HandleShared, StopHandle, COAP_IO_NO_WAIT};
+#[cfg(unix)]
+use crate::io::{CoapSocketInterest, CoapSocketReadiness, CoapSocketRef};
+#[cfg(any(unix, windows))]
+use crate::job::PendingJob;
+#[cfg(feature = "oscore")]
+use crate::oscore::OscoreConfStorage;
+#[cfg(any(unix, windows))]
+use crate::session::GuardedSession;
+#[cfg(dtls)]
+use crate::session::{DtlsFallbackPolicy, FallbackSession};
 use crate::{
-    error::{ContextConfigurationError, EndpointCreationError, IoProcessError},
-    event::{event_handler_callback, CoapEventHandler},
-    mem::{CoapLendableFfiRcCell, CoapLendableFfiWeakCell, DropInnerExclusively},
-    resource::{CoapResource, UntypedCoapResource},
-    session::{session_response_handler, CoapServerSession, CoapSession},
-    transport::CoapEndpoint,
+    access::{AccessHookHandle, CoapAccessDecision},
+    clock::{ClockHandle, CoapClock},
+    crypto::CoapCryptoSessionInfo,
+    echo::{CoapEchoValueProvider, RandomEchoValueProvider},
+    error::{
+        ContextBuildError, ContextConfigurationError, ContextGetAppDataError, EndpointCreationError, IoProcessError,
+        PersistError, RequestPollError, RequestWaitError, ResourceRemoved, ResourceTreeError, SessionCreationError,
+        SessionEstablishError, UriParsingError,
+    },
+    event::{
+        event_handler_callback, nack_handler_callback, pong_handler_callback, CoapEndpointRebindPhase, CoapEventHandler,
+    },
+    limits::{CoapInputLimitExceeded, CoapInputLimits},
+    mem::{CoapFfiWeakCell, CoapLendableFfiRcCell, CoapLendableFfiWeakCell, DropInnerExclusively},
+    message::{
+        inspect::CoapPduInspector, request::CoapRequest, response::CoapResponse, CoapMessageCommon, CoapPduDirection,
+        CoapPduView,
+    },
+    persist::{
+        persist_dyn_resource_added_callback, persist_observe_added_callback, persist_observe_deleted_callback,
+        persist_observe_value_callback, persist_resource_deleted_callback, CoapObserveKey, CoapObserveRecord,
+        PersistConfig, PersistHandlerCell,
+    },
+    protocol::{
+        CoapContentFormat, CoapMessageCode, CoapMessageType, CoapOptionNum, CoapRequestCode, CoapResponseCode,
+        CoapToken, Echo, DEFAULT_LEISURE, DEFAULT_MAX_TOKEN_SIZE, DEFAULT_PROBING_RATE, MAX_EXTENDED_TOKEN_SIZE,
+    },
+    proxy::{CoapReverseProxyResource, ReverseProxyState},
+    rate_limit::{CoapRateLimit, CoapRateLimitAction, CoapThrottledRequest, RateLimiter},
+    resource::{
+        complete_pending_notifications, send_paced_notifications, CoapResource, CoapResourceInner,
+        CoapResourceNotifyState, CoapResourcePath, NotificationPacing, RawRequestHandler, UntypedCoapResource,
+    },
+    resource_tree::{CoapResourceTree, CoapResourceTreeHandle},
+    session::{
+        client::{resolve_uri, WeakCoapClientSession},
+        decline_idle_session, fail_handshake, fail_queued_requests, handshake_timed_out, idle_since, local_socket_addr,
+        pool::{SessionPool, SessionPoolKey},
+        record_handshake_failure, record_request_mid, record_retransmission, record_stats, remote_socket_addr,
+        session_response_handler, set_refuse_requests, take_send_queue_drained, update_csm_state,
+        update_reconnect_state, CoapClientSession, CoapServerSession, CoapSession, CoapSessionCloseReason,
+        CoapSessionCommon, CoapSessionId, CoapSessionState, ReconnectPolicy, ReconnectUpdate, SendQueueLimits,
+    },
+    startup::{self, LibraryGuard},
+    stats::{CoapMemoryLimits, CoapMemoryReport, CoapServerSessionStats, CoapStats, MemoryAccounting},
+    token::{CoapTokenGenerator, RandomTokens, MAX_TOKEN_GENERATION_ATTEMPTS},
+    transport::{check_bind_addr, endpoint_creation_failure, CoapEndpoint, CoapEndpointHandle, CoapIpv6Mode},
+    types::{CoapAddress, CoapMessageId, CoapProtocol, CoapUri, CoapUriScheme, Ownership},
 };
 
-static COAP_STARTUP_ONCE: Once = Once::new();
+/// Part of the state of a context that is shared with its sessions, so that sessions can access it
+/// while the context itself is borrowed (e.g., while an event handler is called).
+#[derive(Default)]
+pub(crate) struct CoapContextShared {
+    /// Traffic statistics of all sessions of this context.
+    pub(crate) stats: RefCell<CoapStats>,
+    /// Inspector that is called for every sent and received PDU, see
+    /// [CoapContext::set_pdu_inspector()].
+    pub(crate) pdu_inspector: RefCell<Option<Box<CoapPduInspector>>>,
+    /// Access hook for resources without a hook of their own, see
+    /// [CoapContext::set_default_access_hook()].
+    pub(crate) default_access_hook: RefCell<Option<AccessHookHandle>>,
+    /// Deadlines of requests with a timeout sent using sessions of this context (which may
+    /// include requests that have already been answered), used to wake up in time to fail them.
+    pub(crate) request_deadlines: RefCell<Vec<Instant>>,
+    /// Policy for reconnecting client sessions, see [CoapContext::set_reconnect_policy()].
+    pub(crate) reconnect_policy: Cell<Option<ReconnectPolicy>>,
+    /// Number of requests sent using sessions of this context that have not received a response
+    /// yet (excluding requests sent before the last call to [CoapContext::abort_pending()]).
+    pub(crate) unanswered_requests: Cell<usize>,
+    /// Number of calls to [CoapContext::abort_pending()], used by sessions to determine which of
+    /// their requests have been aborted.
+    pub(crate) abort_count: Cell<u64>,
+    /// Whether pending transmissions have been aborted and no message has been sent since.
+    pub(crate) pending_aborted: Cell<bool>,
+    /// Maximum number of pending requests per session, see
+    /// [CoapContext::set_max_pending_requests()].
+    pub(crate) max_pending_requests: Cell<Option<usize>>,
+    /// DEFAULT_LEISURE applied to new sessions (if configured), see
+    /// [CoapContext::set_default_leisure()].
+    pub(crate) default_leisure: Cell<Option<Duration>>,
+    /// PROBING_RATE applied to new sessions (if configured), see
+    /// [CoapContext::set_probing_rate()].
+    pub(crate) probing_rate: Cell<Option<u32>>,
+    /// Clock used for the timeouts tracked by the wrapper, see [CoapContext::set_clock()].
+    pub(crate) clock: RefCell<ClockHandle>,
+    /// Raw resources that route requests to the resources for different hosts, by URI path (see
+    /// [CoapResourceBuilder::host()](crate::CoapResourceBuilder::host)).
+    ///
+    /// Cleared before the resources of the context are dropped.
+    pub(crate) virtual_host_paths: RefCell<HashMap<String, *mut coap_resource_t>>,
+    /// Limits for the send queues of sessions, see [CoapContext::set_send_queue_limits()].
+    pub(crate) send_queue_limits: Cell<Option<SendQueueLimits>>,
+    /// Number of calls to [CoapContext::do_io()] after which libcoap had no outstanding
+    /// transmissions, used by sessions to determine which messages are no longer queued.
+    pub(crate) send_queue_epoch: Cell<u64>,
+    /// Raw sessions of this context whose send queues are congested, which are checked for
+    /// having drained in [CoapContext::do_io()].
+    ///
+    /// Sessions remove themselves from this map when they are dropped.
+    pub(crate) congested_sessions: RefCell<HashMap<CoapSessionId, *mut coap_session_t>>,
+    /// Rule for enabling tracing of new sessions, see [CoapContext::trace_next_sessions()].
+    session_trace_rule: RefCell<Option<SessionTraceRule>>,
+    /// Pacing of notifications for resources without a pacing of their own, see
+    /// [CoapContext::set_notification_pacing()].
+    pub(crate) notification_pacing: Cell<NotificationPacing>,
+    /// Memory used by the sessions of this context and the limits for it, see
+    /// [CoapContext::memory_usage()].
+    pub(crate) memory: MemoryAccounting,
+    /// Number of received requests and responses that were passed to resources and sessions, see
+    /// [CoapContext::do_io_budgeted()].
+    pub(crate) dispatched_pdus: Cell<usize>,
+    /// Generator for the tokens of requests, see [CoapContext::set_token_generator()] (None if
+    /// the default generator has not been created yet).
+    token_generator: RefCell<Option<Box<dyn CoapTokenGenerator>>>,
+    /// Endpoints and raw context of the dropped context, which are released once this state is
+    /// dropped (i.e., once no sessions or resources referring to them are left).
+    teardown: RefCell<Option<DeferredTeardown>>,
+    /// Payload of the most recent panic that was caught in a callback invoked by libcoap for this
+    /// context, see [CoapContext::take_last_handler_panic()].
+    pub(crate) last_handler_panic: RefCell<Option<Box<dyn Any + Send>>>,
+}
+
+/// Rule for enabling tracing of new sessions, see [CoapContext::trace_next_sessions()].
+struct SessionTraceRule {
+    /// Number of matching sessions that are still to be traced (never 0).
+    remaining: usize,
+    predicate: Box<dyn FnMut(&SocketAddr) -> bool>,
+}
+
+/// Parts of a dropped context that are released once no sessions or resources of the context
+/// remain, as libcoap frees the raw sessions and resources alongside them.
+///
+/// This allows session and resource handles to outlive their [CoapContext]. As no IO is performed
+/// after the context has been dropped, these handles can still be used (and dropped) safely, but
+/// will no longer send or receive messages. For the same reason, the crypto contexts of the
+/// context are not retained, as libcoap only uses them while performing IO.
+struct DeferredTeardown {
+    /// Raw context to free using `coap_free_context()` (None for borrowed raw contexts).
+    raw_context: Option<*mut coap_context_t>,
+    endpoints: Vec<CoapEndpoint>,
+    /// Values referred to by the OSCORE configurations of the context's sessions, which are kept
+    /// until the raw context has been freed.
+    #[cfg(feature = "oscore")]
+    oscore_storage: Vec<OscoreConfStorage>,
+    /// Keeps libcoap started until the raw context has been freed (fields are dropped after
+    /// [Drop::drop()] has run).
+    _library_guard: Option<LibraryGuard>,
+}
+
+impl Drop for DeferredTeardown {
+    fn drop(&mut self) {
+        // Clear endpoints first because coap_free_context() would free their underlying raw
+        // structs.
+        self.endpoints.clear();
+        if let Some(raw_context) = self.raw_context {
+            // SAFETY: All sessions and resources of the context have been dropped, the raw context
+            // is owned by the dropped context and has not been freed yet.
+            unsafe { coap_free_context(raw_context) };
+        }
+    }
+}
+
+impl CoapContextShared {
+    /// Returns the current time of the clock of the context, see [CoapContext::set_clock()].
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.borrow().now()
+    }
+
+    /// Generates a token for a request of the session with the given ID using the token generator
+    /// of the context, see [CoapContext::set_token_generator()].
+    ///
+    /// Returns None if the generator failed or only generated tokens for which `in_use` returned
+    /// true (within [MAX_TOKEN_GENERATION_ATTEMPTS] attempts).
+    pub(crate) fn generate_token(&self, session: CoapSessionId, in_use: &dyn Fn(&[u8]) -> bool) -> Option<CoapToken> {
+        let mut generator = self.token_generator.borrow_mut();
+        let generator = generator.get_or_insert_with(|| Box::new(RandomTokens::default()));
+        (0..MAX_TOKEN_GENERATION_ATTEMPTS)
+            .map_while(|_| generator.generate(session, in_use))
+            .find(|token| !in_use(token))
+    }
+
+    /// Returns whether a new session for the given peer address should be traced according to the
+    /// rule set using [CoapContext::trace_next_sessions()], counting it towards that rule if so.
+    pub(crate) fn take_session_trace(&self, addr_remote: Option<SocketAddr>) -> bool {
+        let Ok(mut rule_slot) = self.session_trace_rule.try_borrow_mut() else {
+            return false;
+        };
+        let (Some(rule), Some(addr_remote)) = (rule_slot.as_mut(), addr_remote) else {
+            return false;
+        };
+        if !(rule.predicate)(&addr_remote) {
+            return false;
+        }
+        rule.remaining -= 1;
+        if rule.remaining == 0 {
+            *rule_slot = None;
+        }
+        true
+    }
+
+    /// Returns the nearest request deadline that has not passed yet, forgetting all others.
+    fn next_request_deadline(&self) -> Option<Instant> {
+        let now = self.now();
+        let mut deadlines = self.request_deadlines.borrow_mut();
+        deadlines.retain(|deadline| *deadline > now);
+        deadlines.iter().min().copied()
+    }
+}
 
-#[inline(always)]
-pub(crate) fn ensure_coap_started() {
-    COAP_STARTUP_ONCE.call_once(coap_startup_with_feature_checks);
+impl Debug for CoapContextShared {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapContextShared")
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug)]
 struct CoapContextInner<'a> {
     /// Reference to the raw context this context wraps around.
     raw_context: *mut coap_context_t,
+    /// Whether the raw context should be freed when this context is dropped.
+    ownership: Ownership,
     /// A list of endpoints that this context is currently associated with.
     endpoints: Vec<CoapEndpoint>,
+    /// Endpoints that are being replaced using [CoapContext::rebind_endpoint()].
+    draining_endpoints: Vec<DrainingEndpoint>,
+    /// Endpoint libcoap created for the listen address of the context (see
+    /// [CoapContext::new_with_listen_addr()]), whose address is used as the source address of
+    /// client sessions.
+    listen_endpoint: Option<CoapEndpointHandle>,
     /// A list of resources associated with this context.
     resources: Vec<Box<dyn UntypedCoapResource>>,
+    /// Notification states of the resources associated with this context, in the same order as
+    /// [CoapContextInner::resources].
+    resource_notify_states: Vec<Rc<RefCell<CoapResourceNotifyState>>>,
+    /// Resources that have been removed using [CoapResourceHandle::remove()] but are still known
+    /// to libcoap, along with their notification states (see
+    /// [CoapContext::release_removed_resources()]).
+    removed_resources: Vec<(Box<dyn UntypedCoapResource>, Rc<RefCell<CoapResourceNotifyState>>)>,
     /// A list of server-side sessions that are currently active.
     server_sessions: Vec<CoapServerSession<'a>>,
+    /// State of the reverse proxy added using [CoapContext::add_reverse_proxy()] (if any).
+    reverse_proxy: Option<Rc<RefCell<ReverseProxyState>>>,
+    /// Client-side sessions that are reused by [CoapContext::get_or_connect()].
+    session_pool: SessionPool<'a>,
     /// The event handler responsible for library-user side handling of events.
     event_handler: Option<Box<dyn CoapEventHandler>>,
+    /// Application-specific data stored alongside this context, see [CoapContext::set_app_data()].
+    app_data: Option<Rc<dyn Any>>,
+    /// Whether persistence of server state has been enabled using
+    /// [CoapContext::enable_persistence()].
+    persistence_enabled: bool,
+    /// Handler for persistence callbacks (boxed, as its address is provided to libcoap).
+    persist_handler: Option<Box<PersistHandlerCell>>,
+    /// State shared with the sessions of this context (traffic statistics and PDU inspector).
+    shared: Rc<CoapContextShared>,
+    /// The block-wise transfer mode currently set for the raw context (used for server sessions),
+    /// either [BLOCK_MODE] or [STREAMING_BLOCK_MODE].
+    block_mode: u8,
+    /// The current delay before reconnecting client sessions (which is increased after each
+    /// failed reconnection attempt, see [ReconnectPolicy::backoff_factor]).
+    reconnect_delay: Duration,
+    /// The currently configured keepalive interval (libcoap does not provide a getter for this).
+    keepalive: Option<Duration>,
+    /// The currently configured maximum token size (libcoap does not provide a getter for this).
+    max_token_size: usize,
+    /// Number of received packets that were dropped by libcoap because they could not be parsed.
+    bad_packet_count: u64,
+    /// Maximum number of request message IDs tracked per session, see
+    /// [CoapContext::set_dedup_capacity()].
+    dedup_capacity: usize,
+    /// Number of received requests that were detected to be retransmissions.
+    duplicate_request_count: u64,
+    /// Client sessions that have to be established before the given deadline, see
+    /// [CoapContext::set_handshake_deadline()].
+    handshake_deadlines: Vec<(WeakCoapClientSession<'a>, Instant)>,
+    /// Mock clock installed by [CoapContext::advance_time_for_test()] (if it is still in use).
+    #[cfg(feature = "test-util")]
+    mock_clock: Option<MockClock>,
+    /// Host (in lowercase) assumed for requests without a Uri-Host option, see
+    /// [CoapContext::set_default_host()].
+    default_host: Option<String>,
+    /// Resource serving `/.well-known/core` as configured using
+    /// [CoapContext::set_wellknown_core_mode()], if the mode is not the default one.
+    well_known_core: Option<CoapResourceHandle<'a, ()>>,
+    /// Name of the mode set using [CoapContext::set_wellknown_core_mode()] (see
+    /// [CoapWellKnownCoreMode::name()]).
+    well_known_core_mode: &'static str,
+    /// Hook deciding whether idle server-side sessions are kept alive, see
+    /// [CoapContext::set_idle_session_hook()].
+    idle_session_hook: Option<Box<IdleSessionHook>>,
+    /// Lifecycle counters of server-side sessions by transport, see
+    /// [CoapContext::server_session_stats_by_protocol()].
+    server_session_stats: Vec<(CoapProtocol, CoapServerSessionStats)>,
+    /// Provider of Echo values for resources that require them (created once the first Echo
+    /// value is verified or generated if none was set).
+    echo_value_provider: Option<Box<dyn CoapEchoValueProvider>>,
+    /// Token buckets of the peers of this context, see [CoapContext::set_rate_limit()].
+    rate_limiter: Option<RateLimiter>,
+    /// Hook called for throttled requests, see [CoapContext::set_rate_limit_hook()].
+    rate_limit_hook: Option<Box<RateLimitHook>>,
+    /// Limits for requests to resources without their own limits, see
+    /// [CoapContext::set_input_limits()].
+    input_limits: CoapInputLimits,
+    /// Option numbers that were registered using [CoapContext::register_custom_option()]
+    /// (libcoap does not provide a getter for these).
+    custom_options: Vec<CoapOptionNum>,
+    /// Whether stateless cookie verification of DTLS handshakes was required using
+    /// [CoapContext::require_handshake_cookie()].
+    #[cfg(dtls)]
+    require_handshake_cookie: bool,
+    /// Number of DTLS handshakes that passed the cookie exchange (i.e., resulted in a new
+    /// server-side session).
+    #[cfg(dtls)]
+    dtls_handshakes_verified: u64,
+    /// Number of DTLS handshakes with clients that were completed successfully.
+    #[cfg(dtls)]
+    dtls_handshakes_completed: u64,
+    /// Number of DTLS handshakes with clients that failed after the cookie exchange.
+    #[cfg(dtls)]
+    dtls_handshakes_failed: u64,
     /// PSK context for encrypted server-side sessions.
     #[cfg(feature = "dtls-psk")]
     psk_context: Option<ServerPskContext<'a>>,
+    /// PSK contexts that have been replaced using [CoapContext::replace_psk_context()].
+    ///
+    /// libcoap may still refer to keys these contexts provided during earlier handshakes, so they
+    /// are kept until the raw context is freed.
+    #[cfg(feature = "dtls-psk")]
+    replaced_psk_contexts: Vec<ServerPskContext<'a>>,
     /// PKI context for encrypted server-side sessions.
     #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
     pki_rpk_context: Option<ServerPkiRpkCryptoContext<'a>>,
+    /// Whether default root CAs have been set using [CoapContext::set_pki_root_cas()].
+    #[cfg(feature = "dtls-pki")]
+    pki_root_cas_set: bool,
+    /// State shared with the [CoapContextHandle]s of this context (created once the first handle
+    /// is requested).
+    #[cfg(any(unix, windows))]
+    handle_shared: Option<Arc<HandleShared>>,
+    /// Jobs started using [CoapJobHandle](crate::CoapJobHandle) that have not been completed yet,
+    /// by their IDs.
+    #[cfg(any(unix, windows))]
+    jobs: HashMap<u64, PendingJob>,
+    /// ID of the next job started using [CoapJobHandle](crate::CoapJobHandle).
+    #[cfg(any(unix, windows))]
+    next_job_id: u64,
+    /// Sessions kept alive by [SessionGuard](crate::session::SessionGuard)s, by the IDs of their
+    /// registrations.
+    #[cfg(any(unix, windows))]
+    session_guards: HashMap<u64, GuardedSession<'a>>,
+    /// ID of the next registration of a [SessionGuard](crate::session::SessionGuard).
+    #[cfg(any(unix, windows))]
+    next_session_guard_id: u64,
+    /// Values referred to by OSCORE configurations that were passed to libcoap (dropped after the
+    /// raw context is freed).
+    #[cfg(feature = "oscore")]
+    oscore_storage: Vec<OscoreConfStorage>,
+    /// Keeps libcoap started while this context exists (moved into the [DeferredTeardown] when the
+    /// context is dropped, so that it is only dropped after the raw context is freed).
+    library_guard: Option<LibraryGuard>,
+}
+
+/// An endpoint that is being replaced using [CoapContext::rebind_endpoint()].
+#[derive(Debug)]
+struct DrainingEndpoint {
+    handle: CoapEndpointHandle,
+    replacement: CoapEndpointHandle,
+    /// Time at which the endpoint should be removed (None if the grace period is too long to be
+    /// represented).
+    deadline: Option<Instant>,
+}
+
+/// Block-wise transfer mode used for contexts and client sessions (see
+/// `coap_context_set_block_mode()`).
+// In some versions of libcoap, bindgen infers COAP_BLOCK_USE_LIBCOAP and COAP_BLOCK_SINGLE_BODY to
+// be u32, while the function parameter is u8.
+// Therefore, we use `as` to convert to the right type (the constants are small enough to fit).
+const BLOCK_MODE: u8 = (COAP_BLOCK_USE_LIBCOAP | COAP_BLOCK_SINGLE_BODY) as u8;
+
+/// Block-wise transfer mode used for contexts with resources that receive uploads block by block
+/// (see [ResourceFlags::STREAM_BLOCK1](crate::ResourceFlags::STREAM_BLOCK1)).
+///
+/// All other resources are created with `COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY`, so their request
+/// bodies are still reassembled by libcoap.
+const STREAMING_BLOCK_MODE: u8 = COAP_BLOCK_USE_LIBCOAP as u8;
+
+/// Default maximum number of request message IDs tracked per session for deduplication.
+const DEFAULT_DEDUP_CAPACITY: usize = 32;
+
+/// Session timeout used by libcoap if none is configured (`COAP_DEFAULT_SESSION_TIMEOUT`).
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time before the session timeout of an idle server-side session elapses at which the idle
+/// session hook is called (at most half of the session timeout), see
+/// [CoapContext::set_idle_session_hook()].
+const IDLE_SESSION_HOOK_MARGIN: Duration = Duration::from_secs(1);
+
+/// Hook deciding whether an idle server-side session is kept alive, see
+/// [CoapContext::set_idle_session_hook()].
+type IdleSessionHook = dyn FnMut(&mut CoapServerSession<'_>) -> bool;
+
+/// Hook called for requests that exceed the rate limit of their peer, see
+/// [CoapContext::set_rate_limit_hook()].
+type RateLimitHook = dyn FnMut(&CoapThrottledRequest);
+
+/// URI path of the resource listing the resources of a server ([RFC 6690](https://datatracker.ietf.org/doc/html/rfc6690)).
+const WELL_KNOWN_CORE_PATH: &str = ".well-known/core";
+
+/// Snapshot of the effective configuration of a [CoapContext], see [CoapContext::config()].
+///
+/// The snapshot contains all context-wide settings that can be changed using this crate.
+/// Settings of individual sessions (e.g., deadlines set using
+/// [CoapContext::set_handshake_deadline()]) and hooks or handlers are not part of the snapshot.
+/// Secrets (e.g., keys of crypto contexts) are never part of the snapshot either, only whether a
+/// crypto context has been configured.
+///
+/// If the `serde` feature is enabled, snapshots can be serialized (e.g., in order to log them).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct CoapContextConfig {
+    /// Raw block-wise transfer mode flags (`COAP_BLOCK_*`) passed to libcoap for server sessions.
+    ///
+    /// `COAP_BLOCK_SINGLE_BODY` is no longer set once a resource with
+    /// [ResourceFlags::STREAM_BLOCK1](crate::ResourceFlags::STREAM_BLOCK1) has been added.
+    /// Client sessions always use `COAP_BLOCK_USE_LIBCOAP|COAP_BLOCK_SINGLE_BODY`.
+    pub block_mode: u8,
+    /// See [CoapContext::session_timeout()].
+    pub session_timeout: Duration,
+    /// See [CoapContext::max_handshake_sessions()].
+    pub max_handshake_sessions: c_uint,
+    /// See [CoapContext::max_idle_sessions()].
+    pub max_idle_sessions: c_uint,
+    /// See [CoapContext::csm_max_message_size()].
+    pub csm_max_message_size: u32,
+    /// See [CoapContext::csm_timeout()].
+    pub csm_timeout: Duration,
+    /// See [CoapContext::keepalive()].
+    pub keepalive: Option<Duration>,
+    /// See [CoapContext::max_token_size()].
+    pub max_token_size: usize,
+    /// See [CoapContext::dedup_capacity()].
+    pub dedup_capacity: usize,
+    /// See [CoapContext::default_leisure()].
+    pub default_leisure: Duration,
+    /// See [CoapContext::probing_rate()].
+    pub probing_rate: u32,
+    /// See [CoapContext::input_limits()].
+    pub input_limits: CoapInputLimits,
+    /// See [CoapContext::rate_limit()].
+    pub rate_limit: Option<CoapRateLimit>,
+    /// See [CoapContext::memory_limits()].
+    pub memory_limits: CoapMemoryLimits,
+    /// See [CoapContext::send_queue_limits()].
+    pub send_queue_limits: Option<SendQueueLimits>,
+    /// Mode set using [CoapContext::set_wellknown_core_mode()] (`default`, `disabled` or
+    /// `custom`).
+    pub wellknown_core_mode: String,
+    /// See [CoapContext::reconnect_policy()].
+    pub reconnect_policy: Option<ReconnectPolicy>,
+    /// See [CoapContext::max_pending_requests()].
+    pub max_pending_requests: Option<usize>,
+    /// See [CoapContext::notification_pacing()].
+    pub notification_pacing: NotificationPacing,
+    /// See [CoapContext::default_host()].
+    pub default_host: Option<String>,
+    /// See [CoapContext::session_pool_capacity()].
+    pub session_pool_capacity: usize,
+    /// See [CoapContext::session_pool_idle_timeout()].
+    pub session_pool_idle_timeout: Option<Duration>,
+    /// Endpoints the context is bound to, in the order they were added.
+    pub endpoints: Vec<CoapEndpointConfig>,
+    /// Whether a server-side PSK context has been set.
+    pub psk: bool,
+    /// Whether a server-side PKI/RPK context has been set.
+    pub pki_rpk: bool,
+    /// Whether default root CAs for PKI have been set.
+    pub pki_root_cas: bool,
+    /// Whether stateless DTLS handshake verification is required (see
+    /// [CoapContext::require_handshake_cookie()]).
+    pub require_handshake_cookie: bool,
+}
+
+/// Description of an endpoint in a [CoapContextConfig].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoapEndpointConfig {
+    /// Transport protocol of the endpoint (e.g., `udp`).
+    pub proto: String,
+    /// Local address or Unix socket path the endpoint is bound to.
+    pub addr: String,
+}
+
+impl Display for CoapEndpointConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} endpoint at {}", self.proto, self.addr)
+    }
+}
+
+impl CoapContextConfig {
+    /// Returns a human-readable list of the changes between this configuration and `other`.
+    ///
+    /// Each entry describes one changed setting (e.g., `session_timeout: 300s -> 60s`) or one
+    /// endpoint that was added or removed. An empty list indicates that both configurations are
+    /// equal.
+    pub fn diff(&self, other: &CoapContextConfig) -> Vec<String> {
+        let mut changes: Vec<String> = self
+            .settings()
+            .into_iter()
+            .zip(other.settings())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((name, old), (_, new))| format!("{}: {} -> {}", name, old, new))
+            .collect();
+        for endpoint in self.endpoints.iter().filter(|v| !other.endpoints.contains(v)) {
+            changes.push(format!("removed {}", endpoint));
+        }
+        for endpoint in other.endpoints.iter().filter(|v| !self.endpoints.contains(v)) {
+            changes.push(format!("added {}", endpoint));
+        }
+        changes
+    }
+
+    /// Returns the names and formatted values of all settings of this configuration except for
+    /// the endpoints, which is the single place that determines how settings are compared by
+    /// [CoapContextConfig::diff()].
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("block_mode", format!("{:#x}", self.block_mode)),
+            ("session_timeout", format!("{:?}", self.session_timeout)),
+            ("max_handshake_sessions", self.max_handshake_sessions.to_string()),
+            ("max_idle_sessions", self.max_idle_sessions.to_string()),
+            ("csm_max_message_size", self.csm_max_message_size.to_string()),
+            ("csm_timeout", format!("{:?}", self.csm_timeout)),
+            ("keepalive", format!("{:?}", self.keepalive)),
+            ("max_token_size", self.max_token_size.to_string()),
+            ("dedup_capacity", self.dedup_capacity.to_string()),
+            ("default_leisure", format!("{:?}", self.default_leisure)),
+            ("probing_rate", self.probing_rate.to_string()),
+            ("input_limits", format!("{:?}", self.input_limits)),
+            ("rate_limit", format!("{:?}", self.rate_limit)),
+            ("memory_limits", format!("{:?}", self.memory_limits)),
+            ("send_queue_limits", format!("{:?}", self.send_queue_limits)),
+            ("wellknown_core_mode", self.wellknown_core_mode.clone()),
+            ("reconnect_policy", format!("{:?}", self.reconnect_policy)),
+            ("max_pending_requests", format!("{:?}", self.max_pending_requests)),
+            ("notification_pacing", format!("{:?}", self.notification_pacing)),
+            ("default_host", format!("{:?}", self.default_host)),
+            ("session_pool_capacity", self.session_pool_capacity.to_string()),
+            (
+                "session_pool_idle_timeout",
+                format!("{:?}", self.session_pool_idle_timeout),
+            ),
+            ("psk", self.psk.to_string()),
+            ("pki_rpk", self.pki_rpk.to_string()),
+            ("pki_root_cas", self.pki_root_cas.to_string()),
+            ("require_handshake_cookie", self.require_handshake_cookie.to_string()),
+        ]
+    }
+}
+
+/// Options for running the IO loop of a context using [CoapContext::run()].
+#[cfg(any(unix, windows))]
+pub struct RunOptions<'r> {
+    io_timeout: Option<Duration>,
+    idle_callback: Option<Box<dyn for<'c> FnMut(&mut CoapContext<'c>) + 'r>>,
+    validate_configuration: bool,
+}
+
+#[cfg(any(unix, windows))]
+impl<'r> RunOptions<'r> {
+    /// Creates options that run the IO loop without an idle callback, validating the context
+    /// configuration before starting.
+    pub fn new() -> RunOptions<'r> {
+        RunOptions {
+            io_timeout: None,
+            idle_callback: None,
+            validate_configuration: true,
+        }
+    }
+
+    /// Sets the maximum duration a single iteration of the IO loop waits for IO, i.e., the
+    /// minimum frequency at which the idle callback is called even if no IO happens.
+    ///
+    /// By default, each iteration waits until IO happens or libcoap needs to handle a timeout.
+    pub fn io_timeout(mut self, timeout: Duration) -> RunOptions<'r> {
+        self.io_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a callback that is called after each iteration of the IO loop, e.g., to perform
+    /// housekeeping tasks like notifying observers.
+    pub fn idle_callback<F: for<'c> FnMut(&mut CoapContext<'c>) + 'r>(mut self, callback: F) -> RunOptions<'r> {
+        self.idle_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets whether the configuration of the context should be checked using
+    /// [CoapContext::validate_configuration()] before starting the IO loop (enabled by default).
+    pub fn validate_configuration(mut self, validate: bool) -> RunOptions<'r> {
+        self.validate_configuration = validate;
+        self
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl Default for RunOptions<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl Debug for RunOptions<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("io_timeout", &self.io_timeout)
+            .field("idle_callback", &self.idle_callback.as_ref().map(|_| "<callback>"))
+            .field("validate_configuration", &self.validate_configuration)
+            .finish()
+    }
+}
+
+/// Result of a call to [CoapContext::do_io_budgeted()].
+#[cfg(any(unix, windows))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BudgetedIoOutcome {
+    /// Time spent performing IO (including the time spent waiting for IO).
+    pub elapsed: Duration,
+    /// Number of received requests that were passed to resources and received responses that
+    /// were passed to client sessions.
+    pub handled_pdus: usize,
+    /// Whether processing stopped because the budget was exhausted while further input was
+    /// waiting to be processed (see [CoapContext::do_io_budgeted()]).
+    pub work_remaining: bool,
+}
+
+/// How a context serves `/.well-known/core`, see [CoapContext::set_wellknown_core_mode()].
+pub enum CoapWellKnownCoreMode {
+    /// The resource provided by libcoap (or the one provided by the context for resources
+    /// restricted to hosts, see [CoapContext::add_resource()]), which lists all resources.
+    Default,
+    /// Requests for `/.well-known/core` are answered with 4.04 (Not Found).
+    Disabled,
+    /// GET requests for `/.well-known/core` are answered with the link-format content returned
+    /// by the given handler (see [CoapContext::link_format()]), other methods with 4.05 (Method
+    /// Not Allowed).
+    ///
+    /// The handler is passed the request, e.g., to apply the filters of its query
+    /// ([RFC 6690, Section 4.1](https://datatracker.ietf.org/doc/html/rfc6690#section-4.1)).
+    Custom(Box<dyn FnMut(&CoapContext<'_>, &CoapRequest) -> Vec<u8>>),
+}
+
+impl CoapWellKnownCoreMode {
+    /// Returns the name of this mode as used in [CoapContextConfig::wellknown_core_mode].
+    fn name(&self) -> &'static str {
+        match self {
+            CoapWellKnownCoreMode::Default => "default",
+            CoapWellKnownCoreMode::Disabled => "disabled",
+            CoapWellKnownCoreMode::Custom(_) => "custom",
+        }
+    }
+}
+
+impl Debug for CoapWellKnownCoreMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoapWellKnownCoreMode::Default => f.write_str("Default"),
+            CoapWellKnownCoreMode::Disabled => f.write_str("Disabled"),
+            CoapWellKnownCoreMode::Custom(_) => f.debug_tuple("Custom").field(&"<handler>").finish(),
+        }
+    }
+}
+
+/// Builder for [CoapContext]s, which collects the configuration of a context (and the endpoints
+/// it should provide) and applies it in a single [CoapContextBuilder::build()] call.
+///
+/// In contrast to creating a context using [CoapContext::new()] and configuring it using its
+/// setters, incompatible settings (e.g., a DTLS endpoint without server-side credentials) are
+/// detected before the context is created.
+/// Settings that libcoap allows to be changed later on can still be changed using the setters of
+/// the built context.
+///
+/// Endpoints are created using the respective `add_endpoint_*` functions of the context after all
+/// other settings have been applied, i.e., they are tracked by the context just like any other
+/// endpoint (in contrast to the listen address that can be provided to `coap_new_context()`).
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use libcoap_rs::CoapContextBuilder;
+///
+/// let context = CoapContextBuilder::new()
+///     .endpoint_udp("[::]:5683".parse().unwrap())
+///     .session_timeout(Duration::from_secs(120))
+///     .max_idle_sessions(64)
+///     .max_token_size(16)
+///     .build()
+///     .unwrap();
+/// assert_eq!(context.endpoints().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct CoapContextBuilder<'a> {
+    endpoints: Vec<(CoapProtocol, SocketAddr)>,
+    #[cfg(feature = "dtls-psk")]
+    psk_context: Option<ServerPskContext<'a>>,
+    #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+    pki_rpk_context: Option<ServerPkiRpkCryptoContext<'a>>,
+    #[cfg(dtls)]
+    require_handshake_cookie: Option<bool>,
+    event_handler: Option<Box<dyn CoapEventHandler>>,
+    session_timeout: Option<Duration>,
+    max_handshake_sessions: Option<c_uint>,
+    max_idle_sessions: Option<c_uint>,
+    csm_max_message_size: Option<u32>,
+    csm_timeout: Option<Duration>,
+    max_token_size: Option<usize>,
+    dedup_capacity: Option<usize>,
+    keepalive: Option<Duration>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    default_leisure: Option<Duration>,
+    probing_rate: Option<u32>,
+    _context_lifetime_marker: PhantomData<&'a ()>,
+}
+
+impl<'a> CoapContextBuilder<'a> {
+    /// Creates a new builder for a context without endpoints that uses the default settings of
+    /// libcoap.
+    pub fn new() -> CoapContextBuilder<'a> {
+        CoapContextBuilder {
+            endpoints: Vec::new(),
+            #[cfg(feature = "dtls-psk")]
+            psk_context: None,
+            #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+            pki_rpk_context: None,
+            #[cfg(dtls)]
+            require_handshake_cookie: None,
+            event_handler: None,
+            session_timeout: None,
+            max_handshake_sessions: None,
+            max_idle_sessions: None,
+            csm_max_message_size: None,
+            csm_timeout: None,
+            max_token_size: None,
+            dedup_capacity: None,
+            keepalive: None,
+            reconnect_policy: None,
+            default_leisure: None,
+            probing_rate: None,
+            _context_lifetime_marker: PhantomData,
+        }
+    }
+
+    /// Adds a UDP endpoint bound to the given address, see [CoapContext::add_endpoint_udp()].
+    pub fn endpoint_udp(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Udp, addr));
+        self
+    }
+
+    /// Adds a TCP endpoint bound to the given address, see [CoapContext::add_endpoint_tcp()].
+    #[cfg(feature = "tcp")]
+    pub fn endpoint_tcp(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Tcp, addr));
+        self
+    }
+
+    /// Adds a DTLS endpoint bound to the given address, see [CoapContext::add_endpoint_dtls()].
+    ///
+    /// Building the context fails if no server-side credentials (PSK or PKI/RPK) are configured.
+    #[cfg(dtls)]
+    pub fn endpoint_dtls(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Dtls, addr));
+        self
+    }
+
+    /// Adds a TLS endpoint bound to the given address, see [CoapContext::add_endpoint_tls()].
+    ///
+    /// Building the context fails if no server-side credentials (PSK or PKI/RPK) are configured.
+    #[cfg(tls)]
+    pub fn endpoint_tls(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Tls, addr));
+        self
+    }
+
+    /// Adds a WebSocket endpoint bound to the given address, see [CoapContext::add_endpoint_ws()].
+    #[cfg(feature = "websockets")]
+    pub fn endpoint_ws(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Ws, addr));
+        self
+    }
+
+    /// Adds a secure WebSocket endpoint bound to the given address, see
+    /// [CoapContext::add_endpoint_wss()].
+    ///
+    /// Building the context fails if no server-side credentials (PSK or PKI/RPK) are configured.
+    #[cfg(all(feature = "websockets", dtls))]
+    pub fn endpoint_wss(mut self, addr: SocketAddr) -> Self {
+        self.endpoints.push((CoapProtocol::Wss, addr));
+        self
+    }
+
+    /// Sets the server-side PSK information provider, see [CoapContext::set_psk_context()].
+    #[cfg(feature = "dtls-psk")]
+    pub fn psk_context(mut self, psk_context: ServerPskContext<'a>) -> Self {
+        self.psk_context = Some(psk_context);
+        self
+    }
+
+    /// Sets the server-side PKI/RPK information provider, see
+    /// [CoapContext::set_pki_rpk_context()].
+    #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+    pub fn pki_rpk_context(mut self, pki_rpk_context: impl Into<ServerPkiRpkCryptoContext<'a>>) -> Self {
+        self.pki_rpk_context = Some(pki_rpk_context.into());
+        self
+    }
+
+    /// Sets whether stateless cookie verification of DTLS handshakes is required, see
+    /// [CoapContext::require_handshake_cookie()].
+    #[cfg(dtls)]
+    pub fn require_handshake_cookie(mut self, require: bool) -> Self {
+        self.require_handshake_cookie = Some(require);
+        self
+    }
+
+    /// Sets the event handler of the context, see [CoapContext::set_event_handler()].
+    pub fn event_handler<H: CoapEventHandler + 'static>(mut self, handler: H) -> Self {
+        self.event_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets the idle timeout of server-side sessions, see [CoapContext::set_session_timeout()].
+    pub fn session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of sessions in the handshake state, see
+    /// [CoapContext::set_max_handshake_sessions()].
+    pub fn max_handshake_sessions(mut self, max_handshake_sessions: c_uint) -> Self {
+        self.max_handshake_sessions = Some(max_handshake_sessions);
+        self
+    }
+
+    /// Sets the maximum number of idle server-side sessions, see
+    /// [CoapContext::set_max_idle_sessions()].
+    pub fn max_idle_sessions(mut self, max_idle_sessions: c_uint) -> Self {
+        self.max_idle_sessions = Some(max_idle_sessions);
+        self
+    }
+
+    /// Sets the maximum message size signaled in CSMs, see
+    /// [CoapContext::set_csm_max_message_size()].
+    pub fn csm_max_message_size(mut self, csm_max_message_size: u32) -> Self {
+        self.csm_max_message_size = Some(csm_max_message_size);
+        self
+    }
+
+    /// Sets the timeout for waiting for CSMs of peers, see [CoapContext::set_csm_timeout()].
+    pub fn csm_timeout(mut self, csm_timeout: Duration) -> Self {
+        self.csm_timeout = Some(csm_timeout);
+        self
+    }
+
+    /// Sets the maximum supported token size, see [CoapContext::set_max_token_size()].
+    ///
+    /// Building the context fails if the size is not supported by libcoap.
+    pub fn max_token_size(mut self, max_token_size: usize) -> Self {
+        self.max_token_size = Some(max_token_size);
+        self
+    }
+
+    /// Sets the number of message IDs tracked to detect retransmitted requests, see
+    /// [CoapContext::set_dedup_capacity()].
+    pub fn dedup_capacity(mut self, capacity: usize) -> Self {
+        self.dedup_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the interval in which keepalive pings are sent, see [CoapContext::set_keepalive()].
+    pub fn keepalive(mut self, timeout: Duration) -> Self {
+        self.keepalive = Some(timeout);
+        self
+    }
+
+    /// Sets the policy for reconnecting client sessions, see
+    /// [CoapContext::set_reconnect_policy()].
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// Sets the DEFAULT_LEISURE of sessions, see [CoapContext::set_default_leisure()].
+    pub fn default_leisure(mut self, leisure: Duration) -> Self {
+        self.default_leisure = Some(leisure);
+        self
+    }
+
+    /// Sets the PROBING_RATE of sessions, see [CoapContext::set_probing_rate()].
+    pub fn probing_rate(mut self, rate: u32) -> Self {
+        self.probing_rate = Some(rate);
+        self
+    }
+
+    /// Checks whether the collected configuration can be applied, without creating a context.
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::InvalidMaxTokenSize] if the maximum token size is not
+    /// supported by libcoap, or [ContextConfigurationError::MissingServerCredentials] if a DTLS
+    /// endpoint was requested without configuring server-side credentials.
+    pub fn validate(&self) -> Result<(), ContextConfigurationError> {
+        if let Some(max_token_size) = self
+            .max_token_size
+            .filter(|v| !(DEFAULT_MAX_TOKEN_SIZE..=MAX_EXTENDED_TOKEN_SIZE).contains(v))
+        {
+            return Err(ContextConfigurationError::InvalidMaxTokenSize(max_token_size));
+        }
+        #[allow(unused_mut)]
+        let mut has_server_credentials = false;
+        #[cfg(feature = "dtls-psk")]
+        {
+            has_server_credentials |= self.psk_context.is_some();
+        }
+        #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+        {
+            has_server_credentials |= self.pki_rpk_context.is_some();
+        }
+        if let Some((proto, _)) = self
+            .endpoints
+            .iter()
+            .find(|(proto, _)| proto.is_secure() && !has_server_credentials)
+        {
+            return Err(ContextConfigurationError::MissingServerCredentials(*proto));
+        }
+        Ok(())
+    }
+
+    /// Creates the context, applies the collected configuration and creates the requested
+    /// endpoints (in the order they were added).
+    ///
+    /// # Errors
+    /// Returns [ContextBuildError::Configuration] if the configuration is invalid (see
+    /// [CoapContextBuilder::validate()]) or could not be applied (see the respective setters of
+    /// [CoapContext]), or [ContextBuildError::Endpoint] if one of the endpoints could not be
+    /// created.
+    ///
+    /// # Panics
+    /// Panics if any of the configured durations is too large to be provided to libcoap (see the
+    /// respective setters of [CoapContext]).
+    pub fn build(self) -> Result<CoapContext<'a>, ContextBuildError> {
+        self.validate()?;
+        let mut context = CoapContext::new()?;
+        #[cfg(feature = "dtls-psk")]
+        if let Some(psk_context) = self.psk_context {
+            context.set_psk_context(psk_context)?;
+        }
+        #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+        if let Some(pki_rpk_context) = self.pki_rpk_context {
+            context.set_pki_rpk_context(pki_rpk_context)?;
+        }
+        #[cfg(dtls)]
+        if let Some(require) = self.require_handshake_cookie {
+            context.require_handshake_cookie(require)?;
+        }
+        context.inner.borrow_mut().event_handler = self.event_handler;
+        if let Some(timeout) = self.session_timeout {
+            context.set_session_timeout(timeout);
+        }
+        if let Some(max_handshake_sessions) = self.max_handshake_sessions {
+            context.set_max_handshake_sessions(max_handshake_sessions);
+        }
+        if let Some(max_idle_sessions) = self.max_idle_sessions {
+            context.set_max_idle_sessions(max_idle_sessions);
+        }
+        if let Some(csm_max_message_size) = self.csm_max_message_size {
+            context.set_csm_max_message_size(csm_max_message_size);
+        }
+        if let Some(csm_timeout) = self.csm_timeout {
+            context.set_csm_timeout(csm_timeout);
+        }
+        if let Some(max_token_size) = self.max_token_size {
+            context.set_max_token_size(max_token_size)?;
+        }
+        if let Some(capacity) = self.dedup_capacity {
+            context.set_dedup_capacity(capacity);
+        }
+        if self.keepalive.is_some() {
+            context.set_keepalive(self.keepalive);
+        }
+        if self.reconnect_policy.is_some() {
+            context.set_reconnect_policy(self.reconnect_policy)?;
+        }
+        if let Some(leisure) = self.default_leisure {
+            context.set_default_leisure(leisure);
+        }
+        if let Some(rate) = self.probing_rate {
+            context.set_probing_rate(rate);
+        }
+        for (proto, addr) in self.endpoints {
+            let result = match proto {
+                #[cfg(feature = "tcp")]
+                CoapProtocol::Tcp => context.add_endpoint_tcp(addr),
+                #[cfg(dtls)]
+                CoapProtocol::Dtls => context.add_endpoint_dtls(addr),
+                #[cfg(tls)]
+                CoapProtocol::Tls => context.add_endpoint_tls(addr),
+                #[cfg(feature = "websockets")]
+                CoapProtocol::Ws => context.add_endpoint_ws(addr),
+                #[cfg(all(feature = "websockets", dtls))]
+                CoapProtocol::Wss => context.add_endpoint_wss(addr),
+                CoapProtocol::Udp => context.add_endpoint_udp(addr),
+                _ => unreachable!("builder only adds endpoints of supported protocols"),
+            };
+            result.map_err(|e| ContextBuildError::Endpoint(proto, addr, e))?;
+        }
+        Ok(context)
+    }
+}
+
+impl Default for CoapContextBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// A CoAP Context — container for general state and configuration information relating to CoAP
 ///
 /// The equivalent to the [coap_context_t] type in libcoap.
+///
+/// # Multiple Contexts
+///
+/// Multiple contexts may be used simultaneously in the same process, either on the same thread or
+/// on different threads (each context is bound to the thread it was created on).
+/// Contexts operate independently of each other: libcoap callbacks always resolve the context (and
+/// the session or resource) they refer to from the raw pointers provided by libcoap, not from any
+/// process-global state.
+///
+/// Note, however, that some libcoap settings are inherently process-global and therefore shared
+/// between all contexts, most notably the PRNG (see the [prng](crate::prng) module) and the log
+/// level.
 #[derive(Debug)]
 pub struct CoapContext<'a> {
     inner: CoapLendableFfiRcCell<CoapContextInner<'a>>,
@@ -85,48 +1134,221 @@ impl<'a> CoapContext<'a> {
     /// Returns an error if the underlying libcoap library was unable to create a new context
     /// (probably an allocation error?).
     pub fn new() -> Result<CoapContext<'a>, ContextConfigurationError> {
-        ensure_coap_started();
+        let library_guard = LibraryGuard::acquire();
         // SAFETY: Providing null here is fine, the context will just not be bound to an endpoint
         // yet.
         let raw_context = unsafe { coap_new_context(std::ptr::null()) };
         if raw_context.is_null() {
             return Err(ContextConfigurationError::Unknown);
         }
-        // SAFETY: We checked that raw_context is not null.
-        unsafe {
-            coap_context_set_block_mode(
-                raw_context,
-                // In some versions of libcoap, bindgen infers COAP_BLOCK_USE_LIBCOAP and
-                // COAP_BLOCK_SINGLE_BODY to be u32, while the function parameter is u8.
-                // Therefore, we use `try_into()` to convert to the right type, and panic if this is
-                // not possible (should never happen)
-                (COAP_BLOCK_USE_LIBCOAP | COAP_BLOCK_SINGLE_BODY)
-                    .try_into()
-                    .expect("coap_context_set_block_mode() flags have invalid type for function"),
-            );
-            coap_register_response_handler(raw_context, Some(session_response_handler));
+        // SAFETY: We checked that raw_context is not null and just created it.
+        Ok(unsafe { Self::wrap_raw(raw_context, Ownership::Owned, library_guard) })
+    }
+
+    /// Creates a new context that is bound to the given UDP listen address, i.e., a context that
+    /// uses a single local port for both its client and server roles ("shared source port" mode).
+    ///
+    /// The listen address is passed to `coap_new_context()`, which makes libcoap create a UDP
+    /// endpoint for it. This endpoint is represented as the listen endpoint of the context (see
+    /// [CoapContext::listen_endpoint()] and [CoapContext::endpoints()]), but as libcoap does not
+    /// expose it, it is owned by libcoap and freed alongside the context, i.e., it can neither be
+    /// removed nor rebound, and [CoapEndpoint::set_default_mtu()] has no effect on it.
+    /// The listen endpoint always reports `addr` as its local address: if the port of `addr` is 0,
+    /// the port chosen by the operating system can not be determined, so a fixed port should be
+    /// used if peers need to know it.
+    ///
+    /// # Shared source port mode
+    /// UDP client sessions that are created on this context without an explicit local address
+    /// (e.g., using [CoapClientSession::connect_udp()](crate::session::CoapClientSession::connect_udp))
+    /// are bound to the address of the listen endpoint if its address family matches the one of
+    /// the peer, i.e., peers see the listen port as the source port of all requests.
+    /// Client traffic is not sent using the socket of the listen endpoint: libcoap still creates
+    /// a separate socket for each client session, which is bound to the same address (relying on
+    /// `SO_REUSEADDR`) and connected to the peer. Datagrams from peers without a client session
+    /// are received by the listen endpoint.
+    ///
+    /// # Errors
+    /// Returns [ContextBuildError::Endpoint] if libcoap was unable to create the context or the
+    /// endpoint for the listen address (which libcoap does not distinguish).
+    pub fn new_with_listen_addr(addr: SocketAddr) -> Result<CoapContext<'a>, ContextBuildError> {
+        let endpoint_error = |e| ContextBuildError::Endpoint(CoapProtocol::Udp, addr, e);
+        check_bind_addr(addr).map_err(endpoint_error)?;
+        let library_guard = LibraryGuard::acquire();
+        let listen_addr = CoapAddress::from(addr);
+        // SAFETY: The address is valid for the duration of the call. If libcoap is unable to create
+        // the endpoint for it, it frees the context again and returns null.
+        let raw_context = unsafe { coap_new_context(listen_addr.as_raw_address()) };
+        if raw_context.is_null() {
+            return Err(endpoint_error(endpoint_creation_failure()));
+        }
+        // SAFETY: We checked that raw_context is not null and just created it.
+        let context = unsafe { Self::wrap_raw(raw_context, Ownership::Owned, library_guard) };
+        let endpoint = CoapEndpoint::listen_endpoint(addr);
+        let mut inner_ref = context.inner.borrow_mut();
+        inner_ref.listen_endpoint = Some(endpoint.handle());
+        inner_ref.endpoints.push(endpoint);
+        std::mem::drop(inner_ref);
+        Ok(context)
+    }
+
+    /// Wraps an existing raw context that was created using libcoap-sys, allowing code that uses
+    /// libcoap-sys directly to be migrated to this wrapper incrementally.
+    ///
+    /// The wrapper takes over the application data pointer of the raw context, sets the block
+    /// mode used by this crate and replaces the response, event, pong and NACK handlers of the
+    /// raw context with its own ones, i.e., any previously registered handlers are no longer
+    /// called.
+    /// Responses and events for raw client sessions that were not wrapped using
+    /// [CoapClientSession::from_raw_external()](crate::session::CoapClientSession::from_raw_external)
+    /// are ignored.
+    ///
+    /// Endpoints, resources and sessions that were created using this wrapper are managed by it as
+    /// usual (and removed from the raw context when the wrapper is dropped), while those that were
+    /// created using raw libcoap-sys calls are neither tracked nor reconciled by it (e.g., they do
+    /// not show up in [CoapContext::validate_configuration()] and are not torn down).
+    ///
+    /// If `ownership` is [Ownership::Borrowed], the raw context stays valid after the wrapper is
+    /// dropped: its handlers and application data are reset and the caller remains responsible
+    /// for freeing it using `coap_free_context()`.
+    /// If `ownership` is [Ownership::Owned], the raw context is freed when the wrapper is dropped,
+    /// just like for contexts created using [CoapContext::new()].
+    ///
+    /// As libcoap is presumably also used by raw libcoap-sys code, wrapping a raw context prevents
+    /// this crate from ever calling `coap_cleanup()` (see [startup_with()](crate::startup_with)).
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::AppDataInUse] if the raw context already has
+    /// application data (e.g., because it is already wrapped by another [CoapContext]).
+    ///
+    /// # Safety
+    /// The provided pointer must point to a valid raw context that remains valid for the lifetime
+    /// of the returned wrapper (i.e., it must not be freed by the caller while the wrapper exists).
+    /// While the wrapper exists, raw libcoap-sys calls on the context must not interfere with it,
+    /// most notably, the caller must not replace the handlers or application data of the raw
+    /// context, free endpoints, resources or sessions that were created using the wrapper, or
+    /// free the raw context itself.
+    /// If `ownership` is [Ownership::Owned], the caller must no longer use the raw context after the
+    /// wrapper has been dropped.
+    pub unsafe fn from_raw(
+        raw_context: *mut coap_context_t,
+        ownership: Ownership,
+    ) -> Result<CoapContext<'a>, ContextConfigurationError> {
+        assert!(!raw_context.is_null(), "provided raw context was null");
+        if !coap_get_app_data(raw_context).is_null() {
+            return Err(ContextConfigurationError::AppDataInUse);
         }
+        // libcoap is also used by raw code, which must not be interrupted by a coap_cleanup().
+        startup::disable_cleanup();
+        Ok(Self::wrap_raw(raw_context, ownership, LibraryGuard::acquire()))
+    }
+
+    /// Releases the raw context from this wrapper, returning it to the caller.
+    ///
+    /// All endpoints, resources and server-side sessions managed by this wrapper are removed
+    /// (just like when dropping it), and the handlers and application data of the raw context are
+    /// reset.
+    /// The caller is responsible for freeing the returned raw context using `coap_free_context()`,
+    /// regardless of the [Ownership] the context was wrapped with.
+    ///
+    /// Just like before dropping a context, all client sessions created using this context should
+    /// be dropped (or released using [CoapClientSession::into_raw()](crate::session::CoapClientSession::into_raw))
+    /// beforehand.
+    pub fn into_raw(self) -> *mut coap_context_t {
+        let mut inner = self.inner.borrow_mut();
+        inner.ownership = Ownership::Borrowed;
+        let raw_context = inner.raw_context;
+        std::mem::drop(inner);
+        std::mem::drop(self);
+        raw_context
+    }
+
+    /// Sets up the given raw context for use with this wrapper.
+    ///
+    /// # Safety
+    /// The provided pointer must point to a valid raw context without application data.
+    unsafe fn wrap_raw(
+        raw_context: *mut coap_context_t,
+        ownership: Ownership,
+        library_guard: LibraryGuard,
+    ) -> CoapContext<'a> {
+        coap_context_set_block_mode(raw_context, BLOCK_MODE);
+        coap_register_response_handler(raw_context, Some(session_response_handler));
         let inner = CoapLendableFfiRcCell::new(CoapContextInner {
             raw_context,
+            ownership,
             endpoints: Vec::new(),
+            draining_endpoints: Vec::new(),
+            listen_endpoint: None,
             resources: Vec::new(),
+            resource_notify_states: Vec::new(),
+            removed_resources: Vec::new(),
             server_sessions: Vec::new(),
+            reverse_proxy: None,
+            session_pool: SessionPool::default(),
             event_handler: None,
+            app_data: None,
+            persistence_enabled: false,
+            persist_handler: None,
+            shared: Rc::new(CoapContextShared::default()),
+            block_mode: BLOCK_MODE,
+            reconnect_delay: Duration::ZERO,
+            keepalive: None,
+            max_token_size: DEFAULT_MAX_TOKEN_SIZE,
+            bad_packet_count: 0,
+            dedup_capacity: DEFAULT_DEDUP_CAPACITY,
+            duplicate_request_count: 0,
+            handshake_deadlines: Vec::new(),
+            #[cfg(feature = "test-util")]
+            mock_clock: None,
+            default_host: None,
+            well_known_core: None,
+            well_known_core_mode: CoapWellKnownCoreMode::Default.name(),
+            idle_session_hook: None,
+            server_session_stats: Vec::new(),
+            echo_value_provider: None,
+            rate_limiter: None,
+            rate_limit_hook: None,
+            input_limits: CoapInputLimits::default(),
+            custom_options: Vec::new(),
+            #[cfg(dtls)]
+            require_handshake_cookie: false,
+            #[cfg(dtls)]
+            dtls_handshakes_verified: 0,
+            #[cfg(dtls)]
+            dtls_handshakes_completed: 0,
+            #[cfg(dtls)]
+            dtls_handshakes_failed: 0,
             #[cfg(feature = "dtls-psk")]
             psk_context: None,
+            #[cfg(feature = "dtls-psk")]
+            replaced_psk_contexts: Vec::new(),
             #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
             pki_rpk_context: None,
+            #[cfg(feature = "dtls-pki")]
+            pki_root_cas_set: false,
+            #[cfg(any(unix, windows))]
+            handle_shared: None,
+            #[cfg(any(unix, windows))]
+            jobs: HashMap::new(),
+            #[cfg(any(unix, windows))]
+            next_job_id: 0,
+            #[cfg(any(unix, windows))]
+            session_guards: HashMap::new(),
+            #[cfg(any(unix, windows))]
+            next_session_guard_id: 0,
+            #[cfg(feature = "oscore")]
+            oscore_storage: Vec::new(),
+            library_guard: Some(library_guard),
         });
 
-        // SAFETY: We checked that the raw context is not null, the provided function is valid and
-        // the app data pointer provided must be valid as we just created it using
-        // `create_raw_weak_box()`.
-        unsafe {
-            coap_set_app_data(raw_context, inner.create_raw_weak_box() as *mut c_void);
-            coap_register_event_handler(raw_context, Some(event_handler_callback));
-        }
+        // The provided functions are valid and the app data pointer provided must be valid as we
+        // just created it using `create_raw_weak_box()`.
+        coap_set_app_data(raw_context, inner.create_raw_weak_box() as *mut c_void);
+        coap_register_event_handler(raw_context, Some(event_handler_callback));
+        coap_register_pong_handler(raw_context, Some(pong_handler_callback));
+        coap_register_nack_handler(raw_context, Some(nack_handler_callback));
 
-        Ok(CoapContext { inner })
+        CoapContext { inner }
     }
 
     /// Restores a CoapContext from its raw counterpart.
@@ -134,7 +1356,7 @@ impl<'a> CoapContext<'a> {
     /// # Safety
     /// Provided pointer must point to as valid instance of a raw context whose application data
     /// points to a `*mut CoapLendableFfiWeakCell<CoapContextInner>`.
-    pub(crate) unsafe fn from_raw(raw_context: *mut coap_context_t) -> CoapContext<'a> {
+    pub(crate) unsafe fn restore_from_raw(raw_context: *mut coap_context_t) -> CoapContext<'a> {
         assert!(!raw_context.is_null());
         let inner = CoapLendableFfiRcCell::clone_raw_weak_box(
             coap_get_app_data(raw_context) as *mut CoapLendableFfiWeakCell<CoapContextInner>
@@ -143,9 +1365,91 @@ impl<'a> CoapContext<'a> {
         CoapContext { inner }
     }
 
-    /// Handle an incoming event provided by libcoap.
-    pub(crate) fn handle_event(&self, mut session: CoapSession<'a>, event: coap_event_t) {
-        let inner_ref = &mut *self.inner.borrow_mut();
+    /// Returns the state shared by all sessions of the given raw context.
+    ///
+    /// Returns a detached instance if the context is not managed by this wrapper or if it is
+    /// currently unavailable.
+    ///
+    /// # Safety
+    /// The provided pointer must point to a valid raw context.
+    pub(crate) unsafe fn shared_state_of_raw(raw_context: *mut coap_context_t) -> Rc<CoapContextShared> {
+        if coap_get_app_data(raw_context).is_null() {
+            return Rc::default();
+        }
+        let context = CoapContext::restore_from_raw(raw_context);
+        let shared = context.inner.try_borrow().map(|inner| Rc::clone(&inner.shared));
+        shared.unwrap_or_default()
+    }
+
+    /// Handle an incoming event provided by libcoap.
+    pub(crate) fn handle_event(&self, mut session: CoapSession<'a>, event: coap_event_t) {
+        let inner_ref = &mut *self.inner.borrow_mut();
+        if event == coap_event_t::COAP_EVENT_BAD_PACKET {
+            inner_ref.bad_packet_count += 1;
+        }
+        if event == coap_event_t::COAP_EVENT_DTLS_ERROR || event == coap_event_t::COAP_EVENT_TLS_ERROR {
+            record_stats(&session, |stats| stats.handshake_failures += 1);
+            record_handshake_failure(&session);
+        }
+        #[cfg(dtls)]
+        if matches!(session, CoapSession::Server(_)) && session.proto() == CoapProtocol::Dtls {
+            match event {
+                coap_event_t::COAP_EVENT_SERVER_SESSION_NEW => inner_ref.dtls_handshakes_verified += 1,
+                coap_event_t::COAP_EVENT_DTLS_CONNECTED => inner_ref.dtls_handshakes_completed += 1,
+                // libcoap reports errors before disconnecting the session, so sessions whose
+                // handshake failed have not been established yet.
+                coap_event_t::COAP_EVENT_DTLS_ERROR if session.state() != CoapSessionState::Established => {
+                    inner_ref.dtls_handshakes_failed += 1
+                },
+                _ => {},
+            }
+        }
+        if let CoapSession::Client(client_session) = &session {
+            let closed = matches!(
+                event,
+                coap_event_t::COAP_EVENT_SESSION_CLOSED
+                    | coap_event_t::COAP_EVENT_SESSION_FAILED
+                    | coap_event_t::COAP_EVENT_TCP_CLOSED
+                    | coap_event_t::COAP_EVENT_TCP_FAILED
+                    | coap_event_t::COAP_EVENT_DTLS_CLOSED
+                    | coap_event_t::COAP_EVENT_DTLS_ERROR
+            );
+            let policy = inner_ref.shared.reconnect_policy.get();
+            let delay = match (update_reconnect_state(client_session, event, policy), policy) {
+                (ReconnectUpdate::Established, Some(policy)) => Some(policy.initial_delay),
+                (ReconnectUpdate::AttemptFailed, Some(policy)) => Some(policy.next_delay(inner_ref.reconnect_delay)),
+                _ => None,
+            };
+            if let Some(delay) = delay.filter(|v| *v != inner_ref.reconnect_delay) {
+                inner_ref.reconnect_delay = delay;
+                // SAFETY: raw context is valid.
+                #[cfg(session_reconnect_support)]
+                unsafe {
+                    set_raw_reconnect_delay(inner_ref.raw_context, Some(delay))
+                };
+            }
+            // Sessions that are being reconnected by libcoap remain usable (and keep their queued
+            // requests).
+            if closed && !client_session.is_reconnecting() {
+                inner_ref.session_pool.mark_closed(client_session);
+                fail_queued_requests(client_session, RequestPollError::SessionClosed);
+            }
+        }
+        let csm_timed_out = update_csm_state(&session, event);
+        let retransmission = (event == coap_event_t::COAP_EVENT_MSG_RETRANSMITTED)
+            .then(|| record_retransmission(&session))
+            .flatten();
+        if let CoapSession::Server(server_session) = &session {
+            if event == coap_event_t::COAP_EVENT_SERVER_SESSION_NEW
+                && (inner_ref
+                    .draining_endpoints
+                    .iter()
+                    .any(|v| is_draining_endpoint_session(inner_ref, v, server_session))
+                    || inner_ref.shared.memory.check_session().is_err())
+            {
+                set_refuse_requests(server_session, true);
+            }
+        }
         // Call event handler for event.
         if let Some(handler) = &mut inner_ref.event_handler {
             match event {
@@ -195,12 +1499,22 @@ impl<'a> CoapContext<'a> {
                     // TODO probably a log message is justified here.
                 },
             }
+            if csm_timed_out {
+                handler.handle_csm_timeout(&mut session);
+            }
+            if let Some(retransmission) = &retransmission {
+                handler.handle_retransmission(&mut session, retransmission);
+            }
         }
         // For server-side sessions: Ensure that server-side session wrappers are either kept in memory or dropped when needed.
         if let CoapSession::Server(serv_sess) = session {
             match event {
-                coap_event_t::COAP_EVENT_SERVER_SESSION_NEW => inner_ref.server_sessions.push(serv_sess),
+                coap_event_t::COAP_EVENT_SERVER_SESSION_NEW => {
+                    Self::server_session_stats_mut(inner_ref, serv_sess.proto()).record_created();
+                    inner_ref.server_sessions.push(serv_sess)
+                },
                 coap_event_t::COAP_EVENT_SERVER_SESSION_DEL => {
+                    Self::server_session_stats_mut(inner_ref, serv_sess.proto()).record_reaped();
                     std::mem::drop(inner_ref.server_sessions.remove(
                         inner_ref.server_sessions.iter().position(|v| v.eq(&serv_sess)).expect(
                             "attempted to remove session wrapper from context that was never associated with it",
@@ -213,13 +1527,376 @@ impl<'a> CoapContext<'a> {
         }
     }
 
+    /// Handle the receipt of a pong message for the given session.
+    pub(crate) fn handle_pong(&self, mut session: CoapSession<'a>, mid: CoapMessageId) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_pong(&mut session, mid)
+        }
+    }
+
+    /// Handle a change of the remote address of the given session.
+    pub(crate) fn handle_address_changed(
+        &self,
+        mut session: CoapSession<'a>,
+        old_addr: SocketAddr,
+        new_addr: SocketAddr,
+    ) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_address_changed(&mut session, old_addr, new_addr)
+        }
+    }
+
+    /// Handle a response for the given session that was received from an unexpected address.
+    pub(crate) fn handle_response_address_mismatch(
+        &self,
+        mut session: CoapSession<'a>,
+        expected_addr: SocketAddr,
+        actual_addr: SocketAddr,
+        accepted: bool,
+    ) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_response_address_mismatch(&mut session, expected_addr, actual_addr, accepted)
+        }
+    }
+
+    /// Handle a request for the given session that was rejected because it exceeded its input
+    /// limits.
+    pub(crate) fn handle_input_limit_exceeded(
+        &self,
+        mut session: CoapServerSession<'a>,
+        exceeded: &CoapInputLimitExceeded,
+    ) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_input_limit_exceeded(&mut session, exceeded)
+        }
+    }
+
+    /// Handle a datagram of the given size received using the given session that was dropped as it
+    /// may have been truncated.
+    pub(crate) fn handle_truncated_datagram(&self, mut session: CoapSession<'a>, len: usize) {
+        let inner_ref = &mut *self.inner.borrow_mut();
+        inner_ref.bad_packet_count += 1;
+        if let Some(handler) = &mut inner_ref.event_handler {
+            handler.handle_truncated_datagram(&mut session, len)
+        }
+    }
+
+    /// Handle a ping for the given session that was not answered.
+    pub(crate) fn handle_ping_timeout(&self, mut session: CoapSession<'a>, mid: CoapMessageId) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_ping_timeout(&mut session, mid)
+        }
+    }
+
+    /// Handle a session whose congested send queue has drained.
+    pub(crate) fn handle_send_queue_drained(&self, mut session: CoapSession<'a>) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_send_queue_drained(&mut session)
+        }
+    }
+
+    /// Removes the observers with the given session and token from the resources of this context
+    /// after one of their notifications could not be delivered, calling the event handler for
+    /// each of them.
+    pub(crate) fn handle_observer_unreachable(&self, mut session: CoapServerSession<'a>, token: &[u8]) {
+        let mut inner = self.inner.borrow_mut();
+        let removed: Vec<_> = inner
+            .resource_notify_states
+            .iter()
+            .filter_map(|state| state.borrow_mut().remove_unreachable_observer(session.id(), token))
+            .collect();
+        if let Some(handler) = &mut inner.event_handler {
+            for observer in removed {
+                handler.handle_observer_unreachable(&mut session, &observer)
+            }
+        }
+    }
+
+    #[cfg(dtls)]
+    fn handle_dtls_fallback(&self, mut session: CoapSession<'a>, dtls_error: &SessionEstablishError) {
+        if let Some(handler) = &mut self.inner.borrow_mut().event_handler {
+            handler.handle_dtls_fallback(&mut session, dtls_error)
+        }
+    }
+
+    /// Keeps the values referred to by an OSCORE configuration that was passed to libcoap alive
+    /// until this context is dropped.
+    #[cfg(feature = "oscore")]
+    pub(crate) fn retain_oscore_storage(&self, storage: OscoreConfStorage) {
+        self.inner.borrow_mut().oscore_storage.push(storage);
+    }
+
+    /// Sets the event handler for this context, replacing any previously set event handler.
+    ///
+    /// The event handler is called from within [do_io()](CoapContext::do_io) whenever libcoap
+    /// reports an event (see [CoapEventHandler] for a list of events).
+    pub fn set_event_handler<H: CoapEventHandler + 'static>(&mut self, handler: H) {
+        self.inner.borrow_mut().event_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the PDU inspector for this context, replacing any previously set inspector.
+    ///
+    /// The inspector is called for every request and response that is sent or received using a
+    /// session of this context, with the direction, the session and a read-only view of the PDU
+    /// (which also provides its encoded representation, see [CoapPduView::encoded()]).
+    /// Sent PDUs are inspected right before they are passed to libcoap, received ones before they
+    /// are passed to the request handler or stored as a response.
+    ///
+    /// PDUs that are handled internally by libcoap (such as empty ACKs, pings, retransmissions or
+    /// block-wise transfer fragments) are not inspected, and neither are PDUs that are sent by the
+    /// inspector itself.
+    ///
+    /// If no inspector is set, PDUs are neither copied nor encoded for inspection.
+    pub fn set_pdu_inspector<F: FnMut(CoapPduDirection, &CoapSession<'_>, &CoapPduView<'_>) + 'static>(
+        &mut self,
+        inspector: F,
+    ) {
+        *self.inner.borrow().shared.pdu_inspector.borrow_mut() = Some(Box::new(inspector));
+    }
+
+    /// Removes the PDU inspector of this context (if any), see [CoapContext::set_pdu_inspector()].
+    pub fn clear_pdu_inspector(&mut self) {
+        *self.inner.borrow().shared.pdu_inspector.borrow_mut() = None;
+    }
+
+    /// Enables tracing (see [CoapSessionCommon::set_trace()]) for the next `count` sessions of
+    /// this context (client- or server-side) whose peer address matches the given predicate,
+    /// replacing any previously set rule.
+    ///
+    /// The predicate is called once for every new session until `count` sessions have matched.
+    /// Setting a count of 0 removes the rule, sessions that are already traced remain traced.
+    pub fn trace_next_sessions<F: FnMut(&SocketAddr) -> bool + 'static>(&mut self, count: usize, predicate: F) {
+        *self.inner.borrow().shared.session_trace_rule.borrow_mut() = (count > 0).then(|| SessionTraceRule {
+            remaining: count,
+            predicate: Box::new(predicate),
+        });
+    }
+
+    /// Sets the access hook for resources of this context that do not have an access hook of
+    /// their own, replacing any previously set default hook.
+    ///
+    /// The hook is called before the request handler of a resource and decides whether the
+    /// request may be handled, see the [access](crate::access) module and
+    /// [CoapResource::set_access_hook()] for more information.
+    /// This also applies to requests forwarded by a reverse proxy (see
+    /// [CoapContext::add_reverse_proxy()]).
+    pub fn set_default_access_hook<
+        F: Fn(Option<&CoapCryptoSessionInfo>, CoapRequestCode, &CoapRequest) -> CoapAccessDecision + 'static,
+    >(
+        &mut self,
+        hook: F,
+    ) {
+        *self.inner.borrow().shared.default_access_hook.borrow_mut() = Some(AccessHookHandle::new(hook));
+    }
+
+    /// Removes the default access hook of this context (if any), see
+    /// [CoapContext::set_default_access_hook()].
+    pub fn clear_default_access_hook(&mut self) {
+        *self.inner.borrow().shared.default_access_hook.borrow_mut() = None;
+    }
+
+    /// Returns the application-specific data stored alongside this context.
+    ///
+    /// Request handlers can access this data using [CoapSessionCommon::context_app_data()] on the
+    /// session they were called for.
+    ///
+    /// Returns `Ok(None)` if no data is set, and [ContextGetAppDataError::WrongType] if the stored
+    /// data is not of type `T`.
+    pub fn app_data<T: Any>(&self) -> Result<Option<Rc<T>>, ContextGetAppDataError> {
+        self.inner
+            .borrow()
+            .app_data
+            .as_ref()
+            .map(|v| v.clone().downcast().map_err(|_v| ContextGetAppDataError::WrongType))
+            .transpose()
+    }
+
+    /// Sets the application-specific data stored alongside this context, replacing (and dropping)
+    /// any previously stored value.
+    ///
+    /// As handlers only obtain shared references to the data, state that should be modified by
+    /// handlers needs to be wrapped in a type providing interior mutability, e.g., a [RefCell].
+    /// Overlapping mutable borrows (e.g., by a handler that is called while another one still
+    /// holds a mutable borrow) are then detected by the [RefCell] instead of causing undefined
+    /// behavior.
+    ///
+    /// The data is dropped alongside the context (unless references obtained using
+    /// [CoapContext::app_data()] are still held).
+    pub fn set_app_data<T: Any>(&mut self, value: Option<T>) {
+        self.inner.borrow_mut().app_data = value.map(|v| Rc::new(v) as Rc<dyn Any>);
+    }
+
+    /// Clears the application-specific data stored alongside this context.
+    pub fn clear_app_data(&mut self) {
+        self.inner.borrow_mut().app_data = None;
+    }
+
+    /// Returns the application-specific data of this context for use by handlers, failing with
+    /// [ContextGetAppDataError::Unavailable] instead of panicking if the context is currently
+    /// borrowed.
+    pub(crate) fn try_app_data<T: Any>(&self) -> Result<Option<Rc<T>>, ContextGetAppDataError> {
+        self.inner
+            .try_borrow()
+            .ok_or(ContextGetAppDataError::Unavailable)?
+            .app_data
+            .as_ref()
+            .map(|v| v.clone().downcast().map_err(|_v| ContextGetAppDataError::WrongType))
+            .transpose()
+    }
+
+    /// Returns (and removes) the payload of the most recent panic that occurred in a handler or
+    /// callback invoked by libcoap (e.g., a request handler, an event handler or a key provider).
+    ///
+    /// As unwinding into libcoap is undefined behavior, such panics are caught and replaced with a
+    /// sensible fallback: request handlers answer the request with a 5.00 (Internal Server Error)
+    /// response (unless they already sent a response), DTLS key and certificate callbacks reject
+    /// the handshake, and all other callbacks are treated as if they had returned normally.
+    ///
+    /// Only the most recent panic of a callback invoked for this context is kept. Panics in
+    /// callbacks that are not tied to a specific context (a custom PRNG, see the
+    /// [prng](crate::prng) module, the destructor of cache entry application data, or an OSCORE
+    /// sequence number saver) are caught as well, but not reported by any context.
+    pub fn take_last_handler_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.inner.borrow().shared.last_handler_panic.take()
+    }
+
+    /// Enables persistence of observe relationships, observe numbers and dynamically created
+    /// resources, so that this state can be restored after a restart of the server (see the
+    /// [persist](crate::persist) module).
+    ///
+    /// For [PersistConfig::Files], the state stored in the given files is restored immediately,
+    /// so all resources that may be observed have to be added to the context beforehand.
+    /// For [PersistConfig::Callbacks], the state has to be restored by the application using
+    /// [CoapContext::restore_observer()] and [CoapResource::set_observe_number()].
+    ///
+    /// The tracked state is kept when the context is dropped.
+    ///
+    /// # Errors
+    /// Returns [PersistError::AlreadyEnabled] if persistence has already been enabled,
+    /// [PersistError::InvalidPath] if a file path cannot be provided to libcoap (e.g., because it
+    /// contains a null byte), or [PersistError::Unknown] if libcoap was unable to restore the
+    /// state stored in the given files.
+    pub fn enable_persistence(&mut self, config: PersistConfig) -> Result<(), PersistError> {
+        if self.inner.borrow().persistence_enabled {
+            return Err(PersistError::AlreadyEnabled);
+        }
+        let raw_context = self.inner.borrow().raw_context;
+        match config {
+            PersistConfig::Files {
+                dyn_resource_file,
+                observe_file,
+                observe_number_file,
+                save_frequency,
+            } => {
+                let to_c_path = |path: Option<PathBuf>| {
+                    path.map(|path| {
+                        path.into_os_string()
+                            .into_string()
+                            .ok()
+                            .and_then(|path| CString::new(path).ok())
+                            .ok_or(PersistError::InvalidPath)
+                    })
+                    .transpose()
+                };
+                let dyn_resource_file = to_c_path(dyn_resource_file)?;
+                let observe_file = to_c_path(observe_file)?;
+                let observe_number_file = to_c_path(observe_number_file)?;
+                let as_ptr = |path: &Option<CString>| path.as_ref().map_or(std::ptr::null(), |v| v.as_ptr());
+                // SAFETY: Raw context is valid, libcoap copies the provided paths. The context must
+                // not be borrowed here, as restoring the state creates sessions and therefore
+                // causes events to be handled.
+                if unsafe {
+                    coap_persist_startup(
+                        raw_context,
+                        as_ptr(&dyn_resource_file),
+                        as_ptr(&observe_file),
+                        as_ptr(&observe_number_file),
+                        save_frequency,
+                    )
+                } != 1
+                {
+                    return Err(PersistError::Unknown);
+                }
+            },
+            PersistConfig::Callbacks {
+                handler,
+                save_frequency,
+            } => {
+                let handler = Box::new(PersistHandlerCell::new(handler));
+                // SAFETY: Raw context is valid, the handler is kept alive until tracking is stopped
+                // in the destructor of the context.
+                unsafe {
+                    coap_persist_track_funcs(
+                        raw_context,
+                        Some(persist_observe_added_callback),
+                        Some(persist_observe_deleted_callback),
+                        Some(persist_observe_value_callback),
+                        Some(persist_dyn_resource_added_callback),
+                        Some(persist_resource_deleted_callback),
+                        save_frequency,
+                        handler.as_ref() as *const PersistHandlerCell as *mut c_void,
+                    )
+                };
+                self.inner.borrow_mut().persist_handler = Some(handler);
+            },
+        }
+        self.inner.borrow_mut().persistence_enabled = true;
+        Ok(())
+    }
+
+    /// Restores an observe relationship that was previously reported to
+    /// [CoapPersistHandler::observe_added()](crate::persist::CoapPersistHandler::observe_added).
+    ///
+    /// The resource the relationship refers to must already have been added to this context.
+    /// Returns the key of the restored relationship, which replaces the key that was previously
+    /// reported for it.
+    ///
+    /// # Errors
+    /// Returns [PersistError::Unknown] if libcoap was unable to restore the relationship (e.g.,
+    /// because there is no endpoint for the address stored in the record).
+    pub fn restore_observer(&mut self, record: &CoapObserveRecord) -> Result<CoapObserveKey, PersistError> {
+        let raw_context = self.inner.borrow().raw_context;
+        let endpoint_addr = CoapAddress::from(record.endpoint_addr);
+        let addr_info = coap_addr_tuple_t {
+            remote: CoapAddress::from(record.remote_addr).into_raw_address(),
+            local: CoapAddress::from(record.local_addr).into_raw_address(),
+        };
+        let raw_packet = coap_bin_const_t {
+            length: record.raw_packet.len(),
+            s: record.raw_packet.as_ptr(),
+        };
+        let oscore_info = record.oscore_info.as_ref().map(|v| coap_bin_const_t {
+            length: v.len(),
+            s: v.as_ptr(),
+        });
+        // SAFETY: Raw context and all provided values are valid, libcoap copies them if required.
+        // The context must not be borrowed here, as restoring the relationship creates a session
+        // and therefore causes events to be handled.
+        let raw_subscription = unsafe {
+            coap_persist_observe_add(
+                raw_context,
+                record.proto.into(),
+                endpoint_addr.as_raw_address(),
+                &addr_info,
+                &raw_packet,
+                oscore_info
+                    .as_ref()
+                    .map_or(std::ptr::null(), |v| v as *const coap_bin_const_t),
+            )
+        };
+        if raw_subscription.is_null() {
+            return Err(PersistError::Unknown);
+        }
+        Ok(CoapObserveKey::from_raw(raw_subscription))
+    }
+
     /// Sets the server-side cryptography information provider.
     ///
     /// # Errors
     ///
     /// Returns [`ContextConfigurationError::Unknown`] if the call to the underlying libcoap library
     /// function fails and [`ContextConfigurationError::CryptoContextAlreadySet`] if the PSK context
-    /// has already been set previously.
+    /// has already been set previously (use [CoapContext::replace_psk_context()] to replace it).
     #[cfg(feature = "dtls-psk")]
     pub fn set_psk_context(&mut self, psk_context: ServerPskContext<'a>) -> Result<(), ContextConfigurationError> {
         let mut inner = self.inner.borrow_mut();
@@ -239,6 +1916,46 @@ impl<'a> CoapContext<'a> {
         }
     }
 
+    /// Replaces the server-side PSK information provider (or sets it if none has been set yet),
+    /// e.g., to rotate the keys accepted by the server without recreating the context.
+    ///
+    /// The new provider is used for all handshakes that start after this call, sessions that have
+    /// already been established keep using the keys they were established with.
+    ///
+    /// Returns the previous provider, if any. This context keeps a reference to it until the
+    /// context is dropped, as libcoap may still refer to keys it provided during earlier
+    /// handshakes (its key providers are no longer called, though).
+    ///
+    /// Note that libcoap remembers the keys provided for a Server Name Indication (see
+    /// [ServerPskSniKeyProvider](crate::crypto::psk::ServerPskSniKeyProvider)) and may reuse them
+    /// for later handshakes with the same SNI, even after the provider has been replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContextConfigurationError::Unknown`] if the call to the underlying libcoap library
+    /// function fails. In this case, the previous provider remains set, but libcoap may not be
+    /// able to use it for new handshakes until a provider has been set successfully.
+    #[cfg(feature = "dtls-psk")]
+    pub fn replace_psk_context(
+        &mut self,
+        psk_context: ServerPskContext<'a>,
+    ) -> Result<Option<ServerPskContext<'a>>, ContextConfigurationError> {
+        let mut inner = self.inner.borrow_mut();
+        // SAFETY: raw context is valid, and both the new and the previous encryption contexts are
+        // kept until the raw coap_context_t is cleaned up (ensuring they outlive the CoAP context).
+        let result = unsafe { psk_context.apply_to_context(NonNull::new(inner.raw_context).unwrap()) };
+        if let Err(e) = result {
+            // libcoap may already refer to parts of the new configuration.
+            inner.replaced_psk_contexts.push(psk_context);
+            return Err(e);
+        }
+        let previous = inner.psk_context.replace(psk_context);
+        if let Some(previous) = &previous {
+            inner.replaced_psk_contexts.push(previous.clone());
+        }
+        Ok(previous)
+    }
+
     /// Sets the server-side cryptography information provider.
     ///
     /// # Errors
@@ -325,7 +2042,7 @@ impl<'a> CoapContext<'a> {
         ca_file: Option<CString>,
         ca_dir: Option<CString>,
     ) -> Result<(), ContextConfigurationError> {
-        let inner = self.inner.borrow();
+        let mut inner = self.inner.borrow_mut();
 
         let result = unsafe {
             coap_context_set_pki_root_cas(
@@ -335,46 +2052,301 @@ impl<'a> CoapContext<'a> {
             )
         };
         if result == 1 {
+            inner.pki_root_cas_set = true;
             Ok(())
         } else {
             Err(ContextConfigurationError::Unknown)
         }
     }
+
+    /// Sets a deadline for the establishment of the given client session, failing the session if
+    /// it is not established within `timeout` (measured from now) while IO is performed using
+    /// [CoapContext::do_io()].
+    ///
+    /// This function should be called directly after creating the session (e.g., using
+    /// [CoapClientSession::connect_dtls()]), replacing any deadline set for it before.
+    /// Unlike the timeout of [CoapContext::wait_for_session_established()], the deadline is
+    /// enforced regardless of how IO is performed (e.g., while sending requests to the session
+    /// before it is established).
+    ///
+    /// Once the deadline has passed, the session is failed: requests sent using it that are
+    /// awaiting responses fail with [RequestPollError::SessionFailed](crate::error::RequestPollError::SessionFailed),
+    /// new requests are refused with
+    /// [MessageConversionError::SessionFailed](crate::error::MessageConversionError::SessionFailed)
+    /// and [CoapClientSession::connect_error()] returns
+    /// [SessionCreationError::HandshakeTimeout](crate::error::SessionCreationError::HandshakeTimeout).
+    /// libcoap does not provide a way to abort an ongoing handshake, so the half-open raw session
+    /// is only released once the session (and all of its clones) is dropped, which should be done
+    /// after it has failed.
+    ///
+    /// The pace of the handshake itself is determined by libcoap and the TLS library: for DTLS,
+    /// retransmissions of handshake messages are based on the ACK timeout and maximum number of
+    /// retransmissions of the session (see [CoapSessionCommon::set_ack_timeout()] and
+    /// [CoapSessionCommon::set_max_retransmit()]) for most TLS libraries.
+    /// For server-side sessions, the number of concurrent handshakes can be limited using
+    /// [CoapContext::set_max_handshake_sessions()].
+    pub fn set_handshake_deadline(&self, session: &CoapClientSession<'a>, timeout: Duration) {
+        let mut inner = self.inner.borrow_mut();
+        let deadline = inner.shared.now() + timeout;
+        inner
+            .handshake_deadlines
+            .retain(|(other, _)| other.upgrade().is_some_and(|other| other != *session));
+        inner.handshake_deadlines.push((session.downgrade(), deadline));
+    }
+
+    /// Sets the clock that is used for the timeouts tracked by the wrapper (see the
+    /// [clock](crate::clock) module for the affected timeouts).
+    ///
+    /// The clock should be set before sending requests or setting deadlines, as deadlines that
+    /// were already computed using the previous clock are compared against the new one.
+    /// Timers of libcoap itself always follow the system clock.
+    pub fn set_clock<C: CoapClock + 'static>(&mut self, clock: C) {
+        let mut inner = self.inner.borrow_mut();
+        #[cfg(feature = "test-util")]
+        {
+            inner.mock_clock = None;
+        }
+        *inner.shared.clock.borrow_mut() = ClockHandle::new(clock);
+    }
+
+    /// Advances the time seen by the timeouts tracked by the wrapper by `duration` and performs
+    /// currently outstanding IO operations without waiting, so that timeouts which have elapsed
+    /// as a result are handled (e.g., requests whose timeout has elapsed are failed).
+    ///
+    /// On its first call (and on the first call after [CoapContext::set_clock()]), this function
+    /// replaces the clock of the context with a [MockClock] starting at the current time of the
+    /// previous clock. Afterwards, time no longer passes for the wrapper unless it is advanced
+    /// using this function.
+    ///
+    /// libcoap does not provide a way to replace its time source (`coap_ticks()`), so its own
+    /// timers (e.g., retransmissions of confirmable messages) are not affected and still follow
+    /// the system clock.
+    ///
+    /// This function is only available if the `test-util` feature is enabled.
+    ///
+    /// # Errors
+    /// Returns any [IoProcessError] returned by [CoapContext::do_io()].
+    #[cfg(feature = "test-util")]
+    pub fn advance_time_for_test(&mut self, duration: Duration) -> Result<(), IoProcessError> {
+        {
+            let mut inner = self.inner.borrow_mut();
+            let inner = &mut *inner;
+            let shared = &inner.shared;
+            let mock_clock = inner.mock_clock.get_or_insert_with(|| {
+                let clock = MockClock::starting_at(shared.now());
+                *shared.clock.borrow_mut() = ClockHandle::new(clock.clone());
+                clock
+            });
+            mock_clock.advance(duration);
+        }
+        self.do_io(Some(Duration::from_millis(1))).map(|_| ())
+    }
 }
 
-impl CoapContext<'_> {
+impl<'a> CoapContext<'a> {
     /// Performs a controlled shutdown of the CoAP context.
     ///
-    /// This will perform all still outstanding IO operations until [coap_can_exit()] confirms that
-    /// the context has no more outstanding IO and can be dropped without interrupting sessions.
+    /// This will perform all still outstanding IO operations until [CoapContext::can_exit()]
+    /// confirms that the context has no more outstanding IO and can be dropped without
+    /// interrupting sessions, waiting for a maximum duration of `exit_wait_timeout` (or
+    /// indefinitely if it is None).
+    /// Use [CoapContext::abort_pending()] beforehand to shut down without waiting.
+    ///
+    /// The context is dropped in any case, even if an error is returned.
+    ///
+    /// # Errors
+    /// Returns [IoProcessError::ShutdownTimeout] if there still was outstanding IO (e.g.,
+    /// unacknowledged confirmable messages) once `exit_wait_timeout` had passed, or any other
+    /// [IoProcessError] returned by [CoapContext::do_io()].
     pub fn shutdown(mut self, exit_wait_timeout: Option<Duration>) -> Result<(), IoProcessError> {
-        let mut remaining_time = exit_wait_timeout;
+        let deadline = exit_wait_timeout.and_then(|v| Instant::now().checked_add(v));
         // Send remaining packets until we can cleanly shutdown.
-        // SAFETY: Provided context is always valid as an invariant of this struct.
-        while unsafe { coap_can_exit(self.inner.borrow_mut().raw_context) } == 0 {
-            let spent_time = self.do_io(remaining_time)?;
-            remaining_time = remaining_time.map(|v| v.sub(spent_time));
+        while !self.can_exit() {
+            let remaining_time = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    // do_io() would wait indefinitely if the remaining time was zero.
+                    Some(remaining_time) if !remaining_time.is_zero() => Some(remaining_time),
+                    _ => return Err(IoProcessError::ShutdownTimeout),
+                },
+                None => None,
+            };
+            self.do_io(remaining_time)?;
         }
         Ok(())
     }
 
+    /// Returns whether the context has no more outstanding IO (e.g., unacknowledged confirmable
+    /// messages), i.e., whether it can be dropped without interrupting the transmission of any
+    /// messages (see `coap_can_exit()`).
+    ///
+    /// After pending transmissions have been aborted using [CoapContext::abort_pending()], this
+    /// function returns true until the next message is sent.
+    pub fn can_exit(&self) -> bool {
+        let inner = self.inner.borrow();
+        // SAFETY: Provided context is always valid as an invariant of this struct.
+        inner.shared.pending_aborted.get() || unsafe { coap_can_exit(inner.raw_context) != 0 }
+    }
+
+    /// Returns the number of requests sent using sessions of this context that are still awaiting
+    /// their first response.
+    ///
+    /// libcoap does not expose its transmission queues, so this number is determined by the
+    /// wrapper instead: it includes confirmable requests that have already been acknowledged by an
+    /// empty ACK (while the separate response is still outstanding) and block-wise requests whose
+    /// blocks are still being sent, but not messages sent by libcoap itself (e.g., pings) or
+    /// responses. Requests that timed out, failed or whose handle was removed are not counted.
+    /// Use [CoapContext::can_exit()] to check whether libcoap itself has outstanding IO.
+    pub fn pending_transmissions(&self) -> usize {
+        self.inner.borrow().shared.unanswered_requests.get()
+    }
+
+    /// Aborts all pending transmissions, allowing the context to be shut down immediately.
+    ///
+    /// Requests sent using sessions of this context that have not received a response yet fail
+    /// with [RequestPollError::Aborted](crate::error::RequestPollError::Aborted) and are no longer
+    /// counted by [CoapContext::pending_transmissions()].
+    /// Until the next message is sent, [CoapContext::can_exit()] returns true, so
+    /// [CoapContext::shutdown()] no longer waits for outstanding IO.
+    ///
+    /// libcoap does not provide a way to remove messages from its transmission queues, so messages
+    /// that are still queued (e.g., unacknowledged confirmable messages) are only discarded once
+    /// the context is dropped, and may be retransmitted while IO is performed until then.
+    pub fn abort_pending(&self) {
+        let inner = self.inner.borrow();
+        let shared = &inner.shared;
+        shared.abort_count.set(shared.abort_count.get() + 1);
+        shared.unanswered_requests.set(0);
+        shared.pending_aborted.set(true);
+    }
+
+    /// Runs the IO loop of this context until it is stopped using a [StopHandle] (see
+    /// [CoapContext::stop_handle()]) or a [CoapContextHandle], or an error occurs.
+    ///
+    /// Each iteration of the loop performs IO using [CoapContext::do_io()] and calls the idle
+    /// callback set in `options` (if any) afterwards.
+    /// Stopping the loop interrupts it even if it is currently waiting for IO.
+    /// After the loop has been stopped, it may be started again by calling this function again.
+    ///
+    /// # Errors
+    /// Returns [IoProcessError::InvalidConfiguration] with the first problem found by
+    /// [CoapContext::validate_configuration()] if the configuration of this context is
+    /// inconsistent (unless disabled in `options`), or any other [IoProcessError] returned by
+    /// [CoapContext::do_io()].
+    #[cfg(any(unix, windows))]
+    pub fn run(&mut self, mut options: RunOptions<'_>) -> Result<(), IoProcessError> {
+        if options.validate_configuration {
+            if let Some(problem) = self.validate_configuration().into_iter().next() {
+                return Err(IoProcessError::InvalidConfiguration(problem));
+            }
+        }
+        // Ensure that do_io() can be interrupted by stop requests.
+        let shared = self
+            .handle_shared()
+            .map_err(|e| e.raw_os_error().map_or(IoProcessError::Unknown, IoProcessError::Os))?;
+        loop {
+            if shared.take_stop_request() {
+                return Ok(());
+            }
+            if shared.shutdown_requested() && self.can_exit() {
+                shared.clear_shutdown_request();
+                return Ok(());
+            }
+            self.do_io(options.io_timeout)?;
+            if let Some(callback) = options.idle_callback.as_mut() {
+                callback(self);
+            }
+        }
+    }
+
     /// Store reference to the endpoint
-    fn add_endpoint(&mut self, addr: SocketAddr, proto: coap_proto_t) -> Result<(), EndpointCreationError> {
+    fn add_endpoint(
+        &mut self,
+        addr: SocketAddr,
+        proto: coap_proto_t,
+    ) -> Result<CoapEndpointHandle, EndpointCreationError> {
         let endpoint = CoapEndpoint::new_endpoint(self, addr, proto)?;
+        let handle = endpoint.handle();
 
         let mut inner_ref = self.inner.borrow_mut();
         inner_ref.endpoints.push(endpoint);
-        Ok(())
+        Ok(handle)
     }
 
     /// Creates a new UDP endpoint that is bound to the given address.
-    pub fn add_endpoint_udp(&mut self, addr: SocketAddr) -> Result<(), EndpointCreationError> {
+    ///
+    /// Endpoints bound to IPv6 addresses are dual-stack wherever the platform allows it, see
+    /// [CoapContext::add_endpoint_udp_ipv6()]. libcoap sets `SO_REUSEADDR` on all endpoint sockets
+    /// and does not allow adjusting any other socket options.
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::Ipv4MappedAddress] if `addr` is an IPv4-mapped IPv6
+    /// address, [EndpointCreationError::MissingScopeId] if `addr` is an IPv6 link-local address
+    /// without a zone ID, or another [EndpointCreationError] if libcoap was unable to create the
+    /// endpoint (e.g., [EndpointCreationError::AddressInUse]).
+    pub fn add_endpoint_udp(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
         self.add_endpoint(addr, coap_proto_t::COAP_PROTO_UDP)
     }
 
+    /// Creates a new UDP endpoint that is bound to the given IPv6 address, ensuring that the
+    /// endpoint uses the given [CoapIpv6Mode].
+    ///
+    /// A dual-stack endpoint bound to the unspecified address (`[::]`) also receives requests sent
+    /// to the IPv4 addresses of the host, the addresses of such peers are reported as IPv4-mapped
+    /// IPv6 addresses (e.g., by
+    /// [CoapSessionCommon::addr_remote()](crate::session::CoapSessionCommon::addr_remote)).
+    /// The zone (scope) ID and flow information of `addr` are passed on to the socket unchanged,
+    /// and zone IDs of link-local peers are reported in the same way.
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::Ipv6ModeUnsupported] if `mode` can not be used on this
+    /// platform (see [CoapIpv6Mode::is_supported()]), or one of the errors described in
+    /// [CoapContext::add_endpoint_udp()].
+    pub fn add_endpoint_udp_ipv6(
+        &mut self,
+        addr: SocketAddrV6,
+        mode: CoapIpv6Mode,
+    ) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        if !mode.is_supported() {
+            return Err(EndpointCreationError::Ipv6ModeUnsupported(mode));
+        }
+        self.add_endpoint(addr.into(), coap_proto_t::COAP_PROTO_UDP)
+    }
+
+    /// Creates a new endpoint that is bound to the Unix domain socket at the given `path`.
+    ///
+    /// Unix domain socket endpoints use the same (datagram-based) semantics as UDP endpoints and
+    /// can be used for local communication without opening any network ports.
+    /// Clients can connect to this endpoint using
+    /// [CoapClientSession::connect_unix()](crate::session::CoapClientSession::connect_unix).
+    ///
+    /// If a stale socket file exists at the given path, it is removed before the endpoint is
+    /// created. The socket file is removed again once the endpoint is dropped.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::InvalidUnixPath] if the path is too long, or if it refers to
+    /// an existing file that is not a socket, or [EndpointCreationError::Unknown] if libcoap was
+    /// unable to create the endpoint.
+    #[cfg(all(feature = "af-unix", unix))]
+    pub fn add_endpoint_unix<P: AsRef<Path>>(&mut self, path: P) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        let endpoint = CoapEndpoint::new_unix_endpoint(self, path.as_ref())?;
+        let handle = endpoint.handle();
+        self.inner.borrow_mut().endpoints.push(endpoint);
+        Ok(handle)
+    }
+
     /// Creates a new TCP endpoint that is bound to the given address.
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::TransportUnsupported] if libcoap was built without TCP
+    /// support, or [EndpointCreationError::Unknown] if libcoap was unable to create the endpoint.
     #[cfg(feature = "tcp")]
-    pub fn add_endpoint_tcp(&mut self, addr: SocketAddr) -> Result<(), EndpointCreationError> {
+    pub fn add_endpoint_tcp(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
         self.add_endpoint(addr, coap_proto_t::COAP_PROTO_TCP)
     }
 
@@ -382,211 +2354,2692 @@ impl CoapContext<'_> {
     ///
     /// Note that in order to actually connect to DTLS clients, you need to set a crypto provider
     /// using [CoapContext::set_psk_context] and/or [CoapContext::set_pki_rpk_context].
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::MissingServerCredentials] if no crypto provider has been set
+    /// yet (in which case all DTLS handshakes would fail),
+    /// [EndpointCreationError::TransportUnsupported] if libcoap was built without DTLS support, or
+    /// [EndpointCreationError::Unknown] if libcoap was unable to create the endpoint.
     #[cfg(dtls)]
-    pub fn add_endpoint_dtls(&mut self, addr: SocketAddr) -> Result<(), EndpointCreationError> {
+    pub fn add_endpoint_dtls(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        if !self.has_server_crypto_context() {
+            return Err(EndpointCreationError::MissingServerCredentials);
+        }
         self.add_endpoint(addr, coap_proto_t::COAP_PROTO_DTLS)
     }
 
-    // /// TODO
-    // #[cfg(all(feature = "tcp", dtls))]
-    // pub fn add_endpoint_tls(&mut self, _addr: SocketAddr) -> Result<(), EndpointCreationError> {
-    //     todo!()
-    //     // TODO: self.add_endpoint(addr, coap_proto_t::COAP_PROTO_TLS)
-    // }
+    /// Creates a new TLS endpoint (i.e., CoAP over TCP secured using TLS) that is bound to the
+    /// given address.
+    ///
+    /// The TLS layer uses the same server-side credentials as DTLS endpoints, i.e., a crypto
+    /// provider has to be set using [CoapContext::set_psk_context] and/or
+    /// [CoapContext::set_pki_rpk_context] beforehand.
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::MissingServerCredentials] if no crypto provider has been set
+    /// yet, [EndpointCreationError::TransportUnsupported] if libcoap (or its TLS library) does not
+    /// support TLS, or [EndpointCreationError::Unknown] if libcoap was unable to create the
+    /// endpoint.
+    #[cfg(tls)]
+    pub fn add_endpoint_tls(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        if !self.has_server_crypto_context() {
+            return Err(EndpointCreationError::MissingServerCredentials);
+        }
+        self.add_endpoint(addr, coap_proto_t::COAP_PROTO_TLS)
+    }
 
-    /// Adds the given resource to the resource pool of this context.
-    pub fn add_resource<D: Any + ?Sized + Debug>(&mut self, res: CoapResource<D>) {
-        let mut inner_ref = self.inner.borrow_mut();
-        inner_ref.resources.push(Box::new(res));
-        // SAFETY: raw context is valid, raw resource is also guaranteed to be valid as long as
-        // contract of CoapResource is upheld.
-        unsafe {
-            coap_add_resource(
-                inner_ref.raw_context,
-                inner_ref.resources.last_mut().unwrap().raw_resource(),
-            );
-        };
+    /// Creates a new WebSocket endpoint that is bound to the given address.
+    ///
+    /// Clients connect to the endpoint using an HTTP upgrade request for the
+    /// `/.well-known/coap` path (see
+    /// [RFC 8323, Section 4](https://datatracker.ietf.org/doc/html/rfc8323#section-4)), e.g.,
+    /// using [CoapClientSession::connect_ws()](crate::session::CoapClientSession::connect_ws).
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::TransportUnsupported] if libcoap was built without
+    /// WebSocket support, or [EndpointCreationError::Unknown] if libcoap was unable to create the
+    /// endpoint.
+    #[cfg(feature = "websockets")]
+    pub fn add_endpoint_ws(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        self.add_endpoint(addr, coap_proto_t::COAP_PROTO_WS)
     }
 
-    /// Performs currently outstanding IO operations, waiting for a maximum duration of `timeout`.
+    /// Creates a new WebSocket endpoint secured using TLS that is bound to the given address.
     ///
-    /// This is the function where most of the IO operations made using this library are actually
-    /// executed. It is recommended to call this function in a loop for as long as the CoAP context
-    /// is used.
-    pub fn do_io(&mut self, timeout: Option<Duration>) -> Result<Duration, IoProcessError> {
-        let mut inner_ref = self.inner.borrow_mut();
-        // Round up the duration if it is not a clean number of seconds.
-        let timeout = if let Some(timeout) = timeout {
-            let mut temp_timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
-            if timeout.subsec_micros() > 0 || timeout.subsec_nanos() > 0 {
-                temp_timeout = temp_timeout.saturating_add(1);
+    /// The TLS layer uses the same server-side credentials as DTLS endpoints, i.e., a crypto
+    /// provider has to be set using [CoapContext::set_psk_context] and/or
+    /// [CoapContext::set_pki_rpk_context] beforehand.
+    ///
+    /// Returns a handle that can be used to refer to the new endpoint later on.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::MissingServerCredentials] if no crypto provider has been set
+    /// yet, [EndpointCreationError::TransportUnsupported] if libcoap (or its TLS library) does not
+    /// support secure WebSockets, or [EndpointCreationError::Unknown] if libcoap was unable to
+    /// create the endpoint.
+    #[cfg(all(feature = "websockets", dtls))]
+    pub fn add_endpoint_wss(&mut self, addr: SocketAddr) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        if !self.has_server_crypto_context() {
+            return Err(EndpointCreationError::MissingServerCredentials);
+        }
+        self.add_endpoint(addr, coap_proto_t::COAP_PROTO_WSS)
+    }
+
+    /// Replaces the endpoint referred to by `endpoint` with a new endpoint of the same type that is
+    /// bound to `new_addr`, without interrupting ongoing exchanges.
+    ///
+    /// The new endpoint is created first, afterwards the old endpoint is draining for the duration
+    /// of `grace_period`: Sessions that already existed on the old endpoint continue to be served
+    /// (allowing, e.g., block-wise transfers in progress to complete), while requests on sessions
+    /// that are newly created on the old endpoint are answered with 5.03 Service Unavailable.
+    /// Once the grace period has ended, the old endpoint is removed during the next call to
+    /// [CoapContext::do_io()] alongside all of its remaining sessions
+    /// ([CoapEventHandler::handle_server_session_del()] is called for each of them).
+    ///
+    /// Sessions of the old endpoint that are still referenced outside of the context once the
+    /// grace period has ended are disconnected rather than freed, see
+    /// [CoapContext::remove_endpoint()].
+    ///
+    /// The phases of the rebinding are reported to [CoapEventHandler::handle_endpoint_rebind()].
+    ///
+    /// Returns the handle of the new endpoint.
+    ///
+    /// # Errors
+    /// Returns [EndpointCreationError::UnknownEndpoint] if `endpoint` does not refer to an
+    /// endpoint of this context (or the endpoint is already draining),
+    /// [EndpointCreationError::UnsupportedEndpoint] if the endpoint cannot be rebound to a socket
+    /// address (e.g., if it is a Unix domain socket endpoint or the listen endpoint of the context,
+    /// see [CoapContext::new_with_listen_addr()]), or any error that occurs while
+    /// creating the new endpoint (in which case the old endpoint is left unchanged).
+    pub fn rebind_endpoint(
+        &mut self,
+        endpoint: CoapEndpointHandle,
+        new_addr: SocketAddr,
+        grace_period: Duration,
+    ) -> Result<CoapEndpointHandle, EndpointCreationError> {
+        let proto = {
+            let inner = self.inner.borrow();
+            if inner.draining_endpoints.iter().any(|v| v.handle == endpoint) {
+                return Err(EndpointCreationError::UnknownEndpoint);
             }
-            temp_timeout
-        } else {
-            // If no timeout is set, wait indefinitely.
+            let old_endpoint = inner
+                .endpoints
+                .iter()
+                .find(|v| v.handle() == endpoint)
+                .ok_or(EndpointCreationError::UnknownEndpoint)?;
+            if old_endpoint.local_addr().is_none() || old_endpoint.is_listen_endpoint() {
+                return Err(EndpointCreationError::UnsupportedEndpoint);
+            }
+            old_endpoint.proto()
+        };
+        let new_endpoint = match proto {
+            CoapProtocol::Udp => self.add_endpoint_udp(new_addr),
+            #[cfg(feature = "tcp")]
+            CoapProtocol::Tcp => self.add_endpoint_tcp(new_addr),
+            #[cfg(dtls)]
+            CoapProtocol::Dtls => self.add_endpoint_dtls(new_addr),
+            #[cfg(tls)]
+            CoapProtocol::Tls => self.add_endpoint_tls(new_addr),
+            #[cfg(feature = "websockets")]
+            CoapProtocol::Ws => self.add_endpoint_ws(new_addr),
+            #[cfg(all(feature = "websockets", dtls))]
+            CoapProtocol::Wss => self.add_endpoint_wss(new_addr),
+            _ => Err(EndpointCreationError::UnsupportedEndpoint),
+        }?;
+
+        let inner_ref = &mut *self.inner.borrow_mut();
+        inner_ref.draining_endpoints.push(DrainingEndpoint {
+            handle: endpoint,
+            replacement: new_endpoint,
+            deadline: inner_ref.shared.now().checked_add(grace_period),
+        });
+        if let Some(handler) = &mut inner_ref.event_handler {
+            handler.handle_endpoint_rebind(endpoint, new_endpoint, CoapEndpointRebindPhase::Bound);
+            handler.handle_endpoint_rebind(endpoint, new_endpoint, CoapEndpointRebindPhase::Draining);
+        }
+        Ok(new_endpoint)
+    }
+
+    /// Returns the handle of the endpoint libcoap created for the listen address of this context
+    /// (see [CoapContext::new_with_listen_addr()]), or None if the context was created without a
+    /// listen address.
+    pub fn listen_endpoint(&self) -> Option<CoapEndpointHandle> {
+        self.inner.borrow().listen_endpoint
+    }
+
+    /// Returns the local address UDP client sessions with the given peer are bound to if no local
+    /// address was requested explicitly, i.e., the bound address of the listen endpoint (if any)
+    /// if its address family matches the peer's (see the shared source port mode described in
+    /// [CoapContext::new_with_listen_addr()]).
+    pub(crate) fn shared_source_addr(&self, peer: SocketAddr) -> Option<SocketAddr> {
+        let inner = self.inner.borrow();
+        let endpoint = inner.listen_endpoint?;
+        inner
+            .endpoints
+            .iter()
+            .find(|v| v.handle() == endpoint)
+            .and_then(CoapEndpoint::local_addr)
+            .filter(|addr| addr.is_ipv4() == peer.is_ipv4())
+    }
+
+    /// Returns the handles of all endpoints of this context (including endpoints that are
+    /// currently draining, see [CoapContext::rebind_endpoint()]).
+    pub fn endpoints(&self) -> Vec<CoapEndpointHandle> {
+        self.inner.borrow().endpoints.iter().map(CoapEndpoint::handle).collect()
+    }
+
+    /// Calls `f` with the endpoint referred to by `endpoint`, allowing its properties to be
+    /// inspected or changed.
+    ///
+    /// Returns the value returned by `f`, or None if `endpoint` does not refer to an endpoint of
+    /// this context.
+    pub fn with_endpoint<R, F: FnOnce(&mut CoapEndpoint) -> R>(&self, endpoint: CoapEndpointHandle, f: F) -> Option<R> {
+        self.inner
+            .borrow_mut()
+            .endpoints
+            .iter_mut()
+            .find(|v| v.handle() == endpoint)
+            .map(f)
+    }
+
+    /// Returns the handle of the endpoint the given server-side session was created on, i.e., the
+    /// endpoint its requests were received on.
+    ///
+    /// libcoap does not expose the endpoint of a session, so it is determined by comparing the
+    /// protocol and local address of the session to the ones of the endpoints of this context,
+    /// preferring endpoints bound to the exact local address over ones bound to the unspecified
+    /// address. Returns None if no endpoint matches (e.g., for sessions of Unix domain socket
+    /// endpoints or of endpoints that have been removed).
+    pub fn session_endpoint(&self, session: &CoapServerSession) -> Option<CoapEndpointHandle> {
+        self.inner
+            .borrow()
+            .endpoints
+            .iter()
+            .filter(|v| endpoint_serves_session(v, session))
+            .max_by_key(|v| v.local_addr().is_some_and(|addr| !addr.ip().is_unspecified()))
+            .map(CoapEndpoint::handle)
+    }
+
+    /// Closes and removes the endpoint referred to by `endpoint`.
+    ///
+    /// All server-side sessions of the endpoint are closed alongside it, with
+    /// [CoapEventHandler::handle_server_session_del()] being called for each of them. If the
+    /// endpoint is currently draining or replacing a draining endpoint (see
+    /// [CoapContext::rebind_endpoint()]), the rebinding is aborted without reporting
+    /// [CoapEndpointRebindPhase::Removed].
+    ///
+    /// Sessions that are still referenced outside of the context (e.g., by handles obtained
+    /// using [CoapContext::session_by_peer()]) are disconnected (see
+    /// [CoapServerSession::disconnect()]), so their handles remain safe to use, and are freed once
+    /// the last of these handles is dropped.
+    ///
+    /// Returns false if `endpoint` does not refer to an endpoint of this context, or if it refers
+    /// to the listen endpoint of the context, which is owned by libcoap (see
+    /// [CoapContext::new_with_listen_addr()]).
+    pub fn remove_endpoint(&mut self, endpoint: CoapEndpointHandle) -> bool {
+        let inner = &mut *self.inner.borrow_mut();
+        if inner.listen_endpoint == Some(endpoint) {
+            return false;
+        }
+        inner
+            .draining_endpoints
+            .retain(|v| v.handle != endpoint && v.replacement != endpoint);
+        Self::free_endpoint(inner, endpoint, &|inner, session| {
+            inner
+                .endpoints
+                .iter()
+                .find(|v| v.handle() == endpoint)
+                .is_some_and(|v| endpoint_serves_session(v, session))
+        })
+    }
+
+    /// Frees the endpoint referred to by `endpoint` alongside all server-side sessions for which
+    /// `is_endpoint_session` returns true, calling the event handler for each of these sessions.
+    ///
+    /// Returns false if `endpoint` does not refer to an endpoint of this context.
+    fn free_endpoint(
+        inner: &mut CoapContextInner,
+        endpoint: CoapEndpointHandle,
+        is_endpoint_session: &dyn Fn(&CoapContextInner, &CoapServerSession) -> bool,
+    ) -> bool {
+        let Some(position) = inner.endpoints.iter().position(|v| v.handle() == endpoint) else {
+            return false;
+        };
+        // Release our references to the server sessions of the endpoint, as libcoap frees them
+        // alongside the endpoint.
+        let (removed_sessions, kept_sessions): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.server_sessions)
+            .into_iter()
+            .partition(|v| is_endpoint_session(inner, v));
+        inner.server_sessions = kept_sessions;
+        for mut session in removed_sessions {
+            Self::server_session_stats_mut(inner, session.proto()).record_reaped();
+            if let Some(handler) = &mut inner.event_handler {
+                handler.handle_server_session_del(&mut session);
+            }
+            if session.has_other_handles() {
+                // The raw session must outlive the remaining handles, so it is detached from the
+                // endpoint instead of being freed alongside it.
+                session.disconnect(CoapSessionCloseReason::Abort);
+                // SAFETY: libcoap is currently not processing any sessions, the session was taken
+                // from the list of server-side sessions of this context.
+                unsafe { session.release_disconnected() };
+            } else {
+                session.drop_exclusively();
+            }
+        }
+        // SAFETY: Raw context is valid, the event handler is disabled while freeing the endpoint
+        // because the context is currently borrowed and the session events were already handled
+        // above.
+        unsafe { coap_register_event_handler(inner.raw_context, None) };
+        std::mem::drop(inner.endpoints.remove(position));
+        unsafe { coap_register_event_handler(inner.raw_context, Some(event_handler_callback)) };
+        true
+    }
+
+    /// Removes all draining endpoints whose grace period has ended (see
+    /// [CoapContext::rebind_endpoint()]).
+    fn remove_drained_endpoints(inner: &mut CoapContextInner) {
+        let now = inner.shared.now();
+        let (drained, draining): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.draining_endpoints)
+            .into_iter()
+            .partition(|v| matches!(v.deadline, Some(deadline) if deadline <= now));
+        inner.draining_endpoints = draining;
+        for drained_endpoint in drained {
+            Self::free_endpoint(inner, drained_endpoint.handle, &|inner, session| {
+                is_draining_endpoint_session(inner, &drained_endpoint, session)
+            });
+            if let Some(handler) = &mut inner.event_handler {
+                handler.handle_endpoint_rebind(
+                    drained_endpoint.handle,
+                    drained_endpoint.replacement,
+                    CoapEndpointRebindPhase::Removed,
+                );
+            }
+        }
+    }
+
+    /// Fails all client sessions that were not established before their handshake deadline and
+    /// forgets the deadlines of sessions that were established or dropped (see
+    /// [CoapContext::set_handshake_deadline()]).
+    fn expire_handshake_deadlines(inner: &mut CoapContextInner) {
+        let now = inner.shared.now();
+        inner.handshake_deadlines.retain(|(session, deadline)| {
+            let Some(session) = session.upgrade() else {
+                return false;
+            };
+            if session.state() == CoapSessionState::Established {
+                return false;
+            }
+            if *deadline > now {
+                return true;
+            }
+            fail_handshake(&session);
+            false
+        });
+    }
+
+    /// Releases the server-side sessions that were disconnected using
+    /// [CoapServerSession::disconnect()], calling the event handler for each of these sessions.
+    fn release_disconnected_sessions(inner: &mut CoapContextInner) {
+        if inner.server_sessions.iter().all(|v| v.close_reason().is_none()) {
+            return;
+        }
+        let (disconnected, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.server_sessions)
+            .into_iter()
+            .partition(|v| v.close_reason().is_some());
+        inner.server_sessions = kept;
+        for mut session in disconnected {
+            for state in &inner.resource_notify_states {
+                state.borrow_mut().remove_session(&session);
+            }
+            Self::server_session_stats_mut(inner, session.proto()).record_reaped();
+            if let Some(handler) = &mut inner.event_handler {
+                handler.handle_session_closed(&mut session.clone().into());
+                handler.handle_server_session_del(&mut session);
+            }
+            // SAFETY: libcoap is currently not processing any sessions, the session was taken
+            // from the list of server-side sessions of this context.
+            unsafe { session.release_disconnected() };
+        }
+    }
+
+    /// Returns the lifecycle counters of server-side sessions using the given transport.
+    fn server_session_stats_mut<'b>(
+        inner: &'b mut CoapContextInner,
+        proto: CoapProtocol,
+    ) -> &'b mut CoapServerSessionStats {
+        let position = match inner.server_session_stats.iter().position(|(v, _)| *v == proto) {
+            Some(position) => position,
+            None => {
+                inner
+                    .server_session_stats
+                    .push((proto, CoapServerSessionStats::default()));
+                inner.server_session_stats.len() - 1
+            },
+        };
+        &mut inner.server_session_stats[position].1
+    }
+
+    /// Returns the idle time after which the idle session hook is called for server-side
+    /// sessions, or None if no hook is set (see [CoapContext::set_idle_session_hook()]).
+    fn idle_session_hook_threshold(inner: &CoapContextInner) -> Option<Duration> {
+        inner.idle_session_hook.as_ref()?;
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        let timeout = match unsafe { coap_context_get_session_timeout(inner.raw_context) } {
+            0 => DEFAULT_SESSION_TIMEOUT,
+            timeout => Duration::from_secs(timeout as u64),
+        };
+        Some(timeout.saturating_sub(IDLE_SESSION_HOOK_MARGIN.min(timeout / 2)))
+    }
+
+    /// Returns the time at which the idle session hook has to be called next, if any.
+    fn next_idle_session_deadline(inner: &CoapContextInner) -> Option<Instant> {
+        let threshold = Self::idle_session_hook_threshold(inner)?;
+        inner
+            .server_sessions
+            .iter()
+            .filter(|v| v.close_reason().is_none())
+            .filter_map(|v| idle_since(v).and_then(|idle_since| idle_since.checked_add(threshold)))
+            .min()
+    }
+
+    /// Calls the idle session hook for all server-side sessions that are about to reach the
+    /// session timeout, sending a ping on the sessions that the hook decides to keep alive.
+    fn query_idle_session_hook(inner: &mut CoapContextInner) {
+        let Some(threshold) = Self::idle_session_hook_threshold(inner) else {
+            return;
+        };
+        let now = inner.shared.now();
+        let idle_sessions: Vec<_> = inner
+            .server_sessions
+            .iter()
+            .filter(|v| v.close_reason().is_none())
+            .filter(|v| idle_since(*v).is_some_and(|idle_since| now.saturating_duration_since(idle_since) >= threshold))
+            .cloned()
+            .collect();
+        let Some(hook) = &mut inner.idle_session_hook else {
+            return;
+        };
+        for mut session in idle_sessions {
+            // Sending a ping resets the idle time (of both libcoap and the wrapper).
+            if !hook(&mut session) || session.send_ping().is_err() {
+                decline_idle_session(&session);
+            }
+        }
+    }
+
+    /// Advances the send queue epoch if libcoap has no outstanding transmissions and calls the
+    /// event handler for sessions whose congested send queues have drained.
+    fn notify_drained_send_queues(inner: &mut CoapContextInner<'a>) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        if unsafe { coap_can_exit(inner.raw_context) != 0 } {
+            let epoch = &inner.shared.send_queue_epoch;
+            epoch.set(epoch.get() + 1);
+        }
+        let congested: Vec<_> = inner.shared.congested_sessions.borrow().values().copied().collect();
+        for raw_session in congested {
+            // SAFETY: Sessions are removed from the map of congested sessions before they are
+            // dropped, so the raw session is still valid and managed by this wrapper.
+            let mut session = unsafe { CoapSession::from_raw(raw_session) };
+            if take_send_queue_drained(&session) {
+                if let Some(handler) = &mut inner.event_handler {
+                    handler.handle_send_queue_drained(&mut session);
+                }
+            }
+        }
+    }
+
+    /// Returns whether any server-side crypto provider has been set for this context.
+    #[cfg(dtls)]
+    fn has_server_crypto_context(&self) -> bool {
+        let inner = self.inner.borrow();
+        #[allow(unused_mut)]
+        let mut has_crypto_context = false;
+        #[cfg(feature = "dtls-psk")]
+        {
+            has_crypto_context |= inner.psk_context.is_some();
+        }
+        #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+        {
+            has_crypto_context |= inner.pki_rpk_context.is_some();
+        }
+        has_crypto_context
+    }
+
+    /// Checks this context's configuration for consistency, returning a list of all problems that
+    /// were found.
+    ///
+    /// An empty list indicates that no problems were found (which does not necessarily mean that
+    /// the configuration is valid for your use case).
+    ///
+    /// Currently, the following checks are performed:
+    /// - Encrypted endpoints must have server-side credentials configured
+    ///   ([ContextConfigurationError::MissingServerCredentials]).
+    pub fn validate_configuration(&self) -> Vec<ContextConfigurationError> {
+        #[allow(unused_mut)]
+        let mut problems = Vec::new();
+        #[cfg(dtls)]
+        if !self.has_server_crypto_context() {
+            problems.extend(
+                self.inner
+                    .borrow()
+                    .endpoints
+                    .iter()
+                    .map(CoapEndpoint::proto)
+                    .filter(|proto| proto.is_secure())
+                    .map(ContextConfigurationError::MissingServerCredentials),
+            );
+        }
+        problems
+    }
+
+    /// Adds the given resource to the resource pool of this context.
+    ///
+    /// If the context already has a resource with the same URI path and host (see
+    /// [CoapResourceBuilder::host()](crate::CoapResourceBuilder::host)), requests are passed to
+    /// the resource added last.
+    ///
+    /// Once the first resource restricted to a host (or the first resource whose path has segments
+    /// that have to be percent-encoded, see [CoapResourcePath]) is added, the context serves its
+    /// own `/.well-known/core` resource (unless a resource for this path has been added before, see
+    /// also [CoapContext::set_wellknown_core_mode()]),
+    /// which only lists the resources visible for the requested host and percent-encodes their
+    /// paths. Unlike the resource provided by libcoap, it does not support filtering the list using
+    /// query parameters.
+    ///
+    /// Returns a handle that can be used to access and remove the resource later on (see
+    /// [CoapResourceHandle]).
+    pub fn add_resource<D: Any + ?Sized + Debug>(&mut self, res: CoapResource<D>) -> CoapResourceHandle<'a, D> {
+        let handle = CoapResourceHandle {
+            context: self.inner.downgrade(),
+            resource: res.downgrade(),
+        };
+        let path = res.uri_path().to_string();
+        let host_scoped = res.host().is_some();
+        let needs_encoding = res.path().is_some_and(|path| path.needs_encoding());
+        let mut inner_ref = self.inner.borrow_mut();
+        if res.streams_block1() && inner_ref.block_mode != STREAMING_BLOCK_MODE {
+            // Request bodies for all other resources are still reassembled, as they are created
+            // with COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY.
+            inner_ref.block_mode = STREAMING_BLOCK_MODE;
+            // SAFETY: raw context is valid.
+            unsafe { coap_context_set_block_mode(inner_ref.raw_context, STREAMING_BLOCK_MODE) };
+        }
+        inner_ref.resource_notify_states.push(res.notify_state());
+        res.retain_context_shared(Rc::clone(&inner_ref.shared));
+        let observable = res.is_observable();
+        let first_host_scoped = host_scoped && !inner_ref.shared.virtual_host_paths.borrow().contains_key(&path);
+        let add_well_known_core = (first_host_scoped || needs_encoding)
+            && inner_ref.resources.iter().all(|v| v.uri_path() != WELL_KNOWN_CORE_PATH);
+        // libcoap only manages one resource per raw path, which passes requests to the resource for
+        // the requested host (and path, see CoapResourcePath::to_raw()) if multiple resources share
+        // the raw path or resources for it are restricted to hosts.
+        let primary = inner_ref.resources.iter().position(|v| v.uri_path() == path);
+        // libcoap would free the raw resource of a removed resource that is still registered for
+        // the path, which is instead replaced once the removed resource is released.
+        let removal_pending = inner_ref.removed_resources.iter().any(|(v, _)| v.uri_path() == path);
+        inner_ref.resources.push(Box::new(res));
+        match primary {
+            None if removal_pending => {},
+            None => {
+                let resource = inner_ref.resources.last_mut().unwrap();
+                if host_scoped {
+                    resource.route_virtual_hosts();
+                }
+                // SAFETY: raw context is valid, raw resource is also guaranteed to be valid as long
+                // as contract of CoapResource is upheld.
+                unsafe {
+                    let raw_resource = resource.raw_resource();
+                    if host_scoped {
+                        inner_ref
+                            .shared
+                            .virtual_host_paths
+                            .borrow_mut()
+                            .insert(path, raw_resource);
+                    }
+                    coap_add_resource(inner_ref.raw_context, raw_resource);
+                };
+            },
+            Some(primary) => {
+                let resource = &mut inner_ref.resources[primary];
+                resource.route_virtual_hosts();
+                // SAFETY: Raw resource is valid as long as contract of CoapResource is upheld.
+                let raw_resource = unsafe { resource.raw_resource() };
+                if observable {
+                    // SAFETY: Raw resource is valid (see above).
+                    unsafe { coap_resource_set_get_observable(raw_resource, 1) };
+                }
+                inner_ref
+                    .shared
+                    .virtual_host_paths
+                    .borrow_mut()
+                    .insert(path, raw_resource);
+            },
+        }
+        std::mem::drop(inner_ref);
+        if add_well_known_core {
+            self.add_resource(new_well_known_core_resource());
+        }
+        handle
+    }
+
+    /// Removes the given resource from the resource pool of the given context, see
+    /// [CoapResourceHandle::remove()].
+    ///
+    /// libcoap keeps passing requests to the raw resource until it is released by
+    /// [CoapContext::release_removed_resources()], as raw resources must not be freed while
+    /// libcoap may be using them (e.g., because this function is called from a request handler).
+    fn remove_resource<D: Any + ?Sized + Debug>(
+        inner: &mut CoapContextInner<'a>,
+        mut resource: CoapResource<D>,
+    ) -> Result<(), ResourceRemoved> {
+        // SAFETY: Raw resources are only compared.
+        let raw_resource = unsafe { UntypedCoapResource::raw_resource(&mut resource) };
+        let index = inner
+            .resources
+            .iter_mut()
+            // SAFETY: Raw resources are only compared.
+            .position(|v| unsafe { v.raw_resource() } == raw_resource)
+            .ok_or(ResourceRemoved)?;
+        Self::remove_resource_at(inner, index);
+        Ok(())
+    }
+
+    /// Removes all resources with the given path (regardless of the hosts they are restricted to)
+    /// from the resource pool of this context, see [CoapContextHandle::remove_resource()].
+    ///
+    /// Returns the number of removed resources.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn remove_resources_by_path(&mut self, path: &CoapResourcePath) -> usize {
+        let mut inner = self.inner.borrow_mut();
+        let mut removed = 0;
+        while let Some(index) = inner.resources.iter().position(|v| v.path().as_ref() == Some(path)) {
+            Self::remove_resource_at(&mut inner, index);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Removes the resource at the given index of [CoapContextInner::resources], keeping it (and
+    /// its notification state) until it is released by [CoapContext::release_removed_resources()].
+    fn remove_resource_at(inner: &mut CoapContextInner<'a>, index: usize) {
+        let removed = inner.resources.remove(index);
+        let notify_state = inner.resource_notify_states.remove(index);
+        removed.mark_removed();
+        inner.removed_resources.push((removed, notify_state));
+    }
+
+    /// Releases the raw resources of resources that have been removed from this context and lets
+    /// libcoap pass requests for their paths to the remaining resources (if any).
+    ///
+    /// Must not be called while the context is lent to code running during
+    /// [CoapContext::do_io()], as libcoap may still be using the raw resources.
+    fn release_removed_resources(inner: &mut CoapContextInner<'a>) {
+        for (mut resource, notify_state) in std::mem::take(&mut inner.removed_resources) {
+            notify_state.borrow_mut().clear_deferred();
+            let path = resource.uri_path().to_string();
+            // SAFETY: Raw context is valid, raw resource is valid as long as contract of
+            // CoapResource is upheld, and the raw path is only used during the lookup.
+            let registered = unsafe {
+                let raw_path = coap_make_str_const(path.as_ptr(), path.len());
+                coap_get_resource_from_uri_path(inner.raw_context, raw_path) == resource.raw_resource()
+            };
+            if registered {
+                inner.shared.virtual_host_paths.borrow_mut().remove(&path);
+            }
+            resource.detach_raw_resource();
+            if registered {
+                Self::register_resource_path(inner, &path);
+            }
+        }
+    }
+
+    /// Registers the resource added first for the given path with libcoap, which routes requests
+    /// to the resources for different hosts (see [CoapContext::add_resource()]) if necessary.
+    fn register_resource_path(inner: &mut CoapContextInner<'a>, path: &str) {
+        let Some(primary) = inner.resources.iter().position(|v| v.uri_path() == path) else {
+            return;
+        };
+        let candidates = || inner.resources.iter().filter(|v| v.uri_path() == path);
+        let routes = candidates().count() > 1 || inner.resources[primary].host().is_some();
+        let observable = candidates().any(|v| v.is_observable());
+        let resource = &mut inner.resources[primary];
+        if routes {
+            resource.route_virtual_hosts();
+        }
+        // SAFETY: raw context is valid, raw resource is also guaranteed to be valid as long as
+        // contract of CoapResource is upheld.
+        unsafe {
+            let raw_resource = resource.raw_resource();
+            if routes {
+                if observable {
+                    coap_resource_set_get_observable(raw_resource, 1);
+                }
+                inner
+                    .shared
+                    .virtual_host_paths
+                    .borrow_mut()
+                    .insert(path.to_string(), raw_resource);
+            }
+            coap_add_resource(inner.raw_context, raw_resource);
+        }
+    }
+
+    /// Adds all resources of the given resource tree to this context, creating resources for
+    /// parent paths that neither the tree nor the context has a resource for.
+    ///
+    /// Resources are added parent-first. If any node of the tree can not be created, no resource
+    /// is added to the context. See [CoapResourceTree] for more information.
+    ///
+    /// # Errors
+    /// Returns a [ResourceTreeError] if the path of a node is invalid or used by multiple nodes
+    /// (or by a resource of this context for the same host), or if a resource can not be built.
+    pub fn add_resource_tree<D: Any + ?Sized + Debug>(
+        &mut self,
+        tree: CoapResourceTree<D>,
+    ) -> Result<CoapResourceTreeHandle<'a, D>, ResourceTreeError> {
+        tree.register(self)
+    }
+
+    /// Returns the hosts the resources of this context with the given URI path are restricted to
+    /// (None for resources that are not restricted to a host).
+    pub(crate) fn resource_hosts(&self, uri_path: &CoapResourcePath) -> Vec<Option<String>> {
+        self.inner
+            .borrow()
+            .resources
+            .iter()
+            .filter(|v| v.path().as_ref() == Some(uri_path))
+            .map(|v| v.host())
+            .collect()
+    }
+
+    /// Adds the given reverse proxy to this context, which forwards requests for its resource
+    /// tree to upstream servers (see the [proxy](crate::proxy) module).
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::ReverseProxyAlreadySet] if a reverse proxy has already
+    /// been added to this context.
+    pub fn add_reverse_proxy(&mut self, proxy: CoapReverseProxyResource) -> Result<(), ContextConfigurationError> {
+        if self.inner.borrow().reverse_proxy.is_some() {
+            return Err(ContextConfigurationError::ReverseProxyAlreadySet);
+        }
+        let (resource, state) = proxy.into_resource();
+        self.inner.borrow_mut().reverse_proxy = Some(state);
+        self.add_resource(resource);
+        Ok(())
+    }
+
+    /// Returns a handle to the resource with the given URI path whose user data is of type `D`.
+    ///
+    /// The path is given in its textual representation, i.e., as `/`-separated segments that are
+    /// percent-encoded (e.g., `"config/wifi%2Fmain"` for a resource with the segments `config` and
+    /// `wifi/main`), see [CoapResourcePath::parse()].
+    ///
+    /// Returns None if this context has no resource with the given URI path, or if its user data
+    /// is of a different type. If resources for the path are restricted to hosts, the one serving
+    /// requests without a Uri-Host option is returned (see
+    /// [CoapContext::typed_resource_by_uri_path_and_host()]).
+    ///
+    /// The returned handle refers to the same resource as the one added to this context (i.e.,
+    /// changes to its user data are visible to its request handlers) and can be moved into the
+    /// request handlers of other resources. Handles that outlive this context keep its raw context
+    /// alive until they are dropped, but their resource is no longer served.
+    ///
+    /// See [CoapResource::user_data_mut()] for an example.
+    pub fn typed_resource_by_uri_path<D: Any + ?Sized + Debug>(&self, uri_path: &str) -> Option<CoapResource<D>> {
+        self.typed_resource_by_uri_path_and_host(uri_path, None)
+    }
+
+    /// Returns a handle to the resource with the given URI path whose user data is of type `D` that
+    /// serves requests for the given host (see
+    /// [CoapResourceBuilder::host()](crate::CoapResourceBuilder::host)).
+    ///
+    /// The path is given in its textual representation, see
+    /// [CoapContext::typed_resource_by_uri_path()].
+    ///
+    /// If `host` is None, the resource serving requests without a Uri-Host option is returned (see
+    /// [CoapContext::set_default_host()]). If no resource with the given path is restricted to the
+    /// host, the resource with the path that is not restricted to any host is returned.
+    ///
+    /// Returns None if this context has no such resource, or if its user data is of a different
+    /// type.
+    pub fn typed_resource_by_uri_path_and_host<D: Any + ?Sized + Debug>(
+        &self,
+        uri_path: &str,
+        host: Option<&str>,
+    ) -> Option<CoapResource<D>> {
+        let inner = self.inner.borrow();
+        let index = Self::resource_index_for_host(&inner, &CoapResourcePath::parse(uri_path), host)?;
+        inner.resources[index]
+            .as_any()
+            .downcast_ref::<CoapResource<D>>()
+            .map(CoapResource::clone_handle)
+    }
+
+    /// Sets the host that is assumed for requests without a Uri-Host option, or None to only pass
+    /// such requests to resources that are not restricted to a host (the default).
+    ///
+    /// See [CoapResourceBuilder::host()](crate::CoapResourceBuilder::host) for how requests are
+    /// passed to resources for different hosts. Host names are compared case-insensitively.
+    pub fn set_default_host(&mut self, host: Option<&str>) {
+        self.inner.borrow_mut().default_host = host.map(str::to_ascii_lowercase);
+    }
+
+    /// Returns the host that is assumed for requests without a Uri-Host option, see
+    /// [CoapContext::set_default_host()].
+    pub fn default_host(&self) -> Option<String> {
+        self.inner.borrow().default_host.clone()
+    }
+
+    /// Returns the index of the resource serving requests for the given path and host (or the
+    /// default host if `host` is None), see
+    /// [CoapResourceBuilder::host()](crate::CoapResourceBuilder::host).
+    fn resource_index_for_host(
+        inner: &CoapContextInner<'a>,
+        path: &CoapResourcePath,
+        host: Option<&str>,
+    ) -> Option<usize> {
+        let host = host.map(str::to_ascii_lowercase).or_else(|| inner.default_host.clone());
+        let candidates = || {
+            inner
+                .resources
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, resource)| resource.path().as_ref() == Some(path))
+        };
+        host.and_then(|host| candidates().find(|(_, resource)| resource.host().as_ref() == Some(&host)))
+            .or_else(|| candidates().find(|(_, resource)| resource.host().is_none()))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the raw resource and raw request handler for the given method of the resource
+    /// serving requests for the given path and host (see [CoapContext::resource_index_for_host()]).
+    pub(crate) fn route_virtual_host(
+        &self,
+        path: &CoapResourcePath,
+        host: Option<&str>,
+        code: CoapRequestCode,
+    ) -> Option<(*mut coap_resource_t, Option<RawRequestHandler>)> {
+        let mut inner = self.inner.borrow_mut();
+        let index = Self::resource_index_for_host(&inner, path, host)?;
+        let resource = &mut inner.resources[index];
+        // SAFETY: The raw resource is only passed to its own request handler.
+        Some((unsafe { resource.raw_resource() }, resource.raw_method_handler(code)))
+    }
+
+    /// Sets how this context serves `/.well-known/core`.
+    ///
+    /// In the [CoapWellKnownCoreMode::Disabled] and [CoapWellKnownCoreMode::Custom] modes, the
+    /// context adds its own resource for `/.well-known/core` (which takes precedence over
+    /// resources for this path that have been added before), replacing the one of the previous
+    /// mode.
+    pub fn set_wellknown_core_mode(&mut self, mode: CoapWellKnownCoreMode) {
+        let previous = {
+            let mut inner = self.inner.borrow_mut();
+            inner.well_known_core_mode = mode.name();
+            inner.well_known_core.take()
+        };
+        if let Some(previous) = previous {
+            // The resource of the previous mode may have been removed by the application.
+            let _ = previous.remove();
+        }
+        let resource = match mode {
+            CoapWellKnownCoreMode::Default => {
+                let inner = self.inner.borrow();
+                let needs_own_resource = inner
+                    .resources
+                    .iter()
+                    .any(|v| v.host().is_some() || v.path().is_some_and(|path| path.needs_encoding()));
+                let add_well_known_core =
+                    needs_own_resource && inner.resources.iter().all(|v| v.uri_path() != WELL_KNOWN_CORE_PATH);
+                std::mem::drop(inner);
+                // The context's own resource is not added while another mode is set.
+                if add_well_known_core {
+                    self.add_resource(new_well_known_core_resource());
+                }
+                return;
+            },
+            CoapWellKnownCoreMode::Disabled => CoapResource::builder(WELL_KNOWN_CORE_PATH, ())
+                .fallback(|_, session, _request, mut response| {
+                    response.set_code(CoapMessageCode::Response(CoapResponseCode::NotFound));
+                    // If sending fails, libcoap will answer the request with an empty ACK instead.
+                    let _ = session.send(response);
+                })
+                .build()
+                .expect("default resource flags are valid"),
+            CoapWellKnownCoreMode::Custom(mut handler) => CoapResource::builder(WELL_KNOWN_CORE_PATH, ())
+                .get(move |_, session, request, mut response| {
+                    // SAFETY: The session belongs to a context that is currently performing IO, so
+                    // the raw context is valid.
+                    let context =
+                        unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+                    let links = handler(&context, request);
+                    response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                    response.set_content_format(Some(CoapContentFormat::LinkFormat.into()));
+                    response.set_data(Some(links));
+                    // If sending fails, libcoap will answer the request with an empty ACK instead.
+                    let _ = session.send(response);
+                })
+                .build()
+                .expect("default resource flags are valid"),
+        };
+        let handle = self.add_resource(resource);
+        self.inner.borrow_mut().well_known_core = Some(handle);
+    }
+
+    /// Returns the links to the resources of this context that are visible for the given host (or
+    /// the default host if `host` is None) in the CoRE link format
+    /// ([RFC 6690](https://datatracker.ietf.org/doc/html/rfc6690)), as served by
+    /// `/.well-known/core`.
+    ///
+    /// `filter` is called with the path and the link of each resource (including its attributes,
+    /// e.g., `</sensors/temp>;rt="temperature-c"`), only the links it returns true for are
+    /// included. Resources for `/.well-known/core` itself are never included. The filter must not
+    /// access this context.
+    ///
+    /// This allows custom `/.well-known/core` handlers (see [CoapWellKnownCoreMode::Custom]) to
+    /// hide resources without reimplementing the link format.
+    pub fn link_format<F: FnMut(&CoapResourcePath, &str) -> bool>(&self, host: Option<&str>, mut filter: F) -> Vec<u8> {
+        self.links_for_host(host, |path, link| {
+            path.to_raw() != WELL_KNOWN_CORE_PATH && filter(path, &String::from_utf8_lossy(link))
+        })
+    }
+
+    /// Returns the links to the resources visible for the given host whose paths are direct
+    /// children of `parent_path` (i.e., consist of `parent_path` and one more segment) in the CoRE
+    /// link format.
+    pub(crate) fn child_links_for_host(&self, parent_path: &CoapResourcePath, host: Option<&str>) -> Vec<u8> {
+        self.links_for_host(host, |path, _| {
+            path.segments().len() == parent_path.segments().len() + 1
+                && path.segments().starts_with(parent_path.segments())
+                && path.segments().last().is_some_and(|v| !v.is_empty())
+        })
+    }
+
+    /// Returns the links to the resources visible for the given host that are accepted by
+    /// `include` (which is passed their paths and links) in the CoRE link format.
+    fn links_for_host<F: FnMut(&CoapResourcePath, &[u8]) -> bool>(
+        &self,
+        host: Option<&str>,
+        mut include: F,
+    ) -> Vec<u8> {
+        let mut inner = self.inner.borrow_mut();
+        let mut paths: Vec<CoapResourcePath> = Vec::new();
+        for path in inner.resources.iter().filter_map(|resource| resource.path()) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        let mut links = Vec::new();
+        for path in paths {
+            let Some(index) = Self::resource_index_for_host(&inner, &path, host) else {
+                continue;
+            };
+            // SAFETY: Raw context is valid, raw resource is valid as long as contract of
+            // CoapResource is upheld, and the raw path is only used during the lookup.
+            unsafe {
+                let raw_path = path.to_raw();
+                let raw_path = coap_make_str_const(raw_path.as_ptr(), raw_path.len());
+                // Skips resources that libcoap does not serve by path (e.g., the resource for
+                // unknown paths of a reverse proxy).
+                if coap_get_resource_from_uri_path(inner.raw_context, raw_path).is_null() {
+                    continue;
+                }
+                if let Some(link) = raw_resource_link(inner.resources[index].raw_resource()) {
+                    let link = encode_link_target(link, &path);
+                    if !include(&path, &link) {
+                        continue;
+                    }
+                    if !links.is_empty() {
+                        links.push(b',');
+                    }
+                    links.extend_from_slice(&link);
+                }
+            }
+        }
+        links
+    }
+
+    /// Returns the server-side session with the peer at the given remote address, if there is one.
+    ///
+    /// Sessions that have been disconnected using [CoapServerSession::disconnect()] and sessions
+    /// of peers on Unix domain sockets are not returned.
+    pub fn session_by_peer(&self, addr: SocketAddr) -> Option<CoapServerSession<'a>> {
+        self.inner
+            .borrow()
+            .server_sessions
+            .iter()
+            .find(|session| session.close_reason().is_none() && remote_socket_addr(session) == Some(addr))
+            .cloned()
+    }
+
+    /// Returns the server-side session with the given ID, if it has not been disconnected yet
+    /// (see [CoapContextHandle::close_session()]).
+    #[cfg(any(unix, windows))]
+    pub(crate) fn server_session_by_id(&self, id: CoapSessionId) -> Option<CoapServerSession<'a>> {
+        self.inner
+            .borrow()
+            .server_sessions
+            .iter()
+            .find(|session| session.close_reason().is_none() && session.id() == id)
+            .cloned()
+    }
+
+    /// Performs currently outstanding IO operations, waiting for a maximum duration of `timeout`.
+    ///
+    /// This is the function where most of the IO operations made using this library are actually
+    /// executed. It is recommended to call this function in a loop for as long as the CoAP context
+    /// is used.
+    ///
+    /// Operations queued using a [CoapContextHandle] are executed both before and after performing
+    /// IO, and queuing an operation causes this function to return early if it is currently
+    /// waiting for IO.
+    /// This function also returns early once the timeout of a request sent using one of the
+    /// sessions of this context elapses (see [CoapRequest::set_timeout()]).
+    pub fn do_io(&mut self, timeout: Option<Duration>) -> Result<Duration, IoProcessError> {
+        #[cfg(any(unix, windows))]
+        self.execute_queued_commands();
+        let result = self.do_io_inner(timeout);
+        #[cfg(any(unix, windows))]
+        self.execute_queued_commands();
+        result
+    }
+
+    /// Performs outstanding IO operations like [CoapContext::do_io()], but returns once at most
+    /// `max_pdus` received messages have been handled, even if more are waiting to be processed.
+    ///
+    /// This prevents a single chatty peer from monopolizing the IO loop of the caller, which can
+    /// perform its other duties (e.g., notifying observers) in between calls. Handled messages
+    /// are the received requests that are passed to resources (i.e., invocations of request
+    /// handlers and of the checks performed before them) and the received responses that are
+    /// passed to client sessions.
+    ///
+    /// The first iteration waits for IO for at most `timeout` just like [CoapContext::do_io()],
+    /// further iterations only process messages that have already been received and stop as soon
+    /// as the budget is exhausted or no message was handled. As libcoap handles a batch of
+    /// received messages in each iteration (usually at most one per socket), the number of handled
+    /// messages may exceed `max_pdus` by the size of the last batch.
+    ///
+    /// If the budget is exhausted, the sockets of the context are probed (without processing
+    /// anything) for further input. If [BudgetedIoOutcome::work_remaining] is true, the caller
+    /// should call this function again without waiting once its other duties are done. If libcoap
+    /// does not use epoll for this context, the sockets can not be probed, and remaining work is
+    /// reported whenever the budget is exhausted.
+    ///
+    /// # Errors
+    /// Returns [IoProcessError::InvalidBudget] if `max_pdus` is 0, or an [IoProcessError] if
+    /// processing IO failed.
+    #[cfg(any(unix, windows))]
+    pub fn do_io_budgeted(
+        &mut self,
+        timeout: Option<Duration>,
+        max_pdus: usize,
+    ) -> Result<BudgetedIoOutcome, IoProcessError> {
+        if max_pdus == 0 {
+            return Err(IoProcessError::InvalidBudget);
+        }
+        let dispatched_pdus = |context: &Self| context.inner.borrow().shared.dispatched_pdus.get();
+        let initial = dispatched_pdus(self);
+        let mut elapsed = self.do_io(timeout)?;
+        loop {
+            let handled_pdus = dispatched_pdus(self).wrapping_sub(initial);
+            if handled_pdus >= max_pdus {
+                return Ok(BudgetedIoOutcome {
+                    elapsed,
+                    handled_pdus,
+                    work_remaining: self.input_pending(),
+                });
+            }
+            let before = dispatched_pdus(self);
+            elapsed += self.do_io_no_wait()?;
+            if dispatched_pdus(self) == before {
+                return Ok(BudgetedIoOutcome {
+                    elapsed,
+                    handled_pdus,
+                    work_remaining: false,
+                });
+            }
+        }
+    }
+
+    /// Returns whether input is waiting to be processed by libcoap, without processing it.
+    ///
+    /// Returns true if libcoap does not use epoll for this context, as its sockets can not be
+    /// probed in that case.
+    #[cfg(any(unix, windows))]
+    fn input_pending(&self) -> bool {
+        #[cfg(unix)]
+        {
+            // SAFETY: Properly initialized CoapContext always has a valid raw_context.
+            let epoll_fd = unsafe { coap_context_get_coap_fd(self.inner.borrow().raw_context) };
+            if epoll_fd >= 0 {
+                let mut fd = libc::pollfd {
+                    fd: epoll_fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // SAFETY: fd is a valid pollfd struct, a timeout of 0 does not wait for events.
+                return unsafe { libc::poll(&mut fd, 1, 0) } > 0;
+            }
+        }
+        true
+    }
+
+    /// Performs the IO operations that can be performed without waiting, returning the duration of
+    /// the call.
+    #[cfg(any(unix, windows))]
+    fn do_io_no_wait(&mut self) -> Result<Duration, IoProcessError> {
+        self.execute_queued_commands();
+        let result = {
+            let mut inner_ref = self.inner.borrow_mut();
+            let now = inner_ref.shared.now();
+            self.prepare_wrapper_io(&mut inner_ref, now);
+            // SAFETY: See do_io_inner(). libcoap does not wait for IO, so there is no need to wait
+            // for queued operations as well.
+            self.process_raw_io(&mut inner_ref, |raw_ctx_ptr| unsafe {
+                coap_io_process(raw_ctx_ptr, COAP_IO_NO_WAIT)
+            })
+        };
+        self.execute_queued_commands();
+        Ok(Duration::from_millis(result?.unsigned_abs() as u64))
+    }
+
+    fn do_io_inner(&mut self, timeout: Option<Duration>) -> Result<Duration, IoProcessError> {
+        let mut inner_ref = self.inner.borrow_mut();
+        let now = inner_ref.shared.now();
+        let next_deadline = self.prepare_wrapper_io(&mut inner_ref, now);
+        let timeout = match next_deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(now).max(Duration::from_millis(1));
+                Some(timeout.map_or(remaining, |v| v.min(remaining)))
+            },
+            None => timeout,
+        };
+        // Round up the duration if it is not a clean number of seconds.
+        let timeout = if let Some(timeout) = timeout {
+            let mut temp_timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+            if timeout.subsec_micros() > 0 || timeout.subsec_nanos() > 0 {
+                temp_timeout = temp_timeout.saturating_add(1);
+            }
+            temp_timeout
+        } else {
+            // If no timeout is set, wait indefinitely.
             COAP_IO_WAIT
         };
-        let raw_ctx_ptr = inner_ref.raw_context;
-        // Lend the current mutable reference to potential callers of CoapContext functions on the
-        // other side of the FFI barrier.
-        let lend_handle = self.inner.lend_ref_mut(&mut inner_ref);
+        #[cfg(any(unix, windows))]
+        let handle_shared = inner_ref.handle_shared.clone();
         // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
         // deleted until the CoapContextInner is dropped.
         // Other raw structs used by libcoap are encapsulated in a way that they cannot be in use
         // while in this function (considering that they are all !Send).
-        let spent_time = unsafe { coap_io_process(raw_ctx_ptr, timeout) };
+        #[cfg(any(unix, windows))]
+        let spent_time = self.process_raw_io(&mut inner_ref, |raw_ctx_ptr| unsafe {
+            match &handle_shared {
+                Some(handle_shared) => handle_shared.io_process(raw_ctx_ptr, timeout),
+                None => coap_io_process(raw_ctx_ptr, timeout),
+            }
+        })?;
+        #[cfg(not(any(unix, windows)))]
+        let spent_time = self.process_raw_io(&mut inner_ref, |raw_ctx_ptr| unsafe {
+            coap_io_process(raw_ctx_ptr, timeout)
+        })?;
+        // Return with duration of call.
+        Ok(Duration::from_millis(spent_time.unsigned_abs() as u64))
+    }
+
+    /// Performs the housekeeping of this wrapper that has to happen before libcoap is called to
+    /// perform IO, returning the time at which IO has to be performed again at the latest.
+    fn prepare_wrapper_io(&self, inner_ref: &mut CoapContextInner<'a>, now: Instant) -> Option<Instant> {
+        Self::release_removed_resources(inner_ref);
+        // Mark resources whose notifications were postponed as dirty once their coalesce interval
+        // has expired, so that the notifications are sent by this call.
+        for resource in &inner_ref.resources {
+            resource.flush_coalesced_notification();
+        }
+        // Send the paced notifications that are due before determining when to wake up, as this
+        // depends on the remaining allowance of each resource.
+        let default_pacing = inner_ref.shared.notification_pacing.get();
+        let paced_states: Vec<_> = inner_ref
+            .resource_notify_states
+            .iter()
+            .filter(|state| state.borrow().queued_notifications() != 0)
+            .cloned()
+            .collect();
+        if !paced_states.is_empty() {
+            let sessions: HashMap<_, _> = inner_ref
+                .server_sessions
+                .iter()
+                .filter(|session| session.close_reason().is_none())
+                // SAFETY: The raw session is only used while the session is listed in the context.
+                .map(|session| (session.id(), unsafe { session.raw_session_mut() }))
+                .collect();
+            // Notifications are generated by request handlers, which may call functions of the
+            // context.
+            let lend_handle = self.inner.lend_ref_mut(inner_ref);
+            for state in paced_states {
+                // SAFETY: Resources are only dropped alongside the context, and server-side
+                // sessions are only released while performing IO (or in do_io() itself).
+                unsafe { send_paced_notifications(&state, default_pacing, now, &sessions) };
+            }
+            lend_handle.unlend();
+        }
+        // Wake up in time to remove draining endpoints once their grace period has ended, to
+        // fail requests and sessions whose timeout has elapsed, to send postponed and paced
+        // notifications and to call the idle session hook (a zero timeout would make libcoap wait
+        // indefinitely).
+        inner_ref
+            .draining_endpoints
+            .iter()
+            .filter_map(|v| v.deadline)
+            .chain(inner_ref.shared.next_request_deadline())
+            .chain(inner_ref.handshake_deadlines.iter().map(|(_, deadline)| *deadline))
+            .chain(Self::next_idle_session_deadline(inner_ref))
+            .chain(
+                inner_ref
+                    .resource_notify_states
+                    .iter()
+                    .filter_map(|state| state.borrow().coalesce_deadline(now)),
+            )
+            .chain(
+                inner_ref
+                    .resource_notify_states
+                    .iter()
+                    .filter_map(|state| state.borrow().pacing_deadline(default_pacing, now)),
+            )
+            .min()
+    }
+
+    /// Calls `io_fn` with the raw context to let libcoap perform IO, lending the context to
+    /// callbacks and performing the housekeeping of this wrapper that has to happen afterwards.
+    ///
+    /// `io_fn` must return a negative value if (and only if) performing IO failed, in which case
+    /// the OS error (if any) is returned.
+    fn process_raw_io<F: FnOnce(*mut coap_context_t) -> c_int>(
+        &self,
+        inner_ref: &mut CoapContextInner<'a>,
+        io_fn: F,
+    ) -> Result<c_int, IoProcessError> {
+        let raw_ctx_ptr = inner_ref.raw_context;
+        let reverse_proxy = inner_ref.reverse_proxy.clone();
+        // libcoap sends notifications for all resources that are dirty at the start of
+        // coap_io_process(), so all notifications that are pending now will have been sent once it
+        // returns.
+        let pending_notifications: Vec<_> = inner_ref
+            .resource_notify_states
+            .iter()
+            .filter_map(|state| {
+                let state_ref = state.borrow();
+                state_ref.is_pending().then(|| (state.clone(), state_ref.notify_seq()))
+            })
+            .collect();
+        // Lend the current mutable reference to potential callers of CoapContext functions on the
+        // other side of the FFI barrier.
+        let lend_handle = self.inner.lend_ref_mut(inner_ref);
+        let result = io_fn(raw_ctx_ptr);
+        for (state, seq) in pending_notifications {
+            // SAFETY: Resources are only dropped alongside the context.
+            unsafe { complete_pending_notifications(&state, seq) };
+        }
+        // Send the responses that the reverse proxy received from upstream servers while the
+        // context is still lent, as sending them may call functions of the context.
+        if let Some(reverse_proxy) = reverse_proxy {
+            reverse_proxy.borrow_mut().forward_responses();
+        }
         // Demand the return of the lent handle, ensuring that the mutable reference is no longer
         // used anywhere.
         lend_handle.unlend();
+        Self::release_removed_resources(inner_ref);
+        Self::remove_drained_endpoints(inner_ref);
+        Self::expire_handshake_deadlines(inner_ref);
+        Self::release_disconnected_sessions(inner_ref);
+        Self::notify_drained_send_queues(inner_ref);
+        Self::query_idle_session_hook(inner_ref);
+        inner_ref.session_pool.remove_stale();
         // Check for errors.
-        if spent_time < 0 {
-            return Err(IoProcessError::Unknown);
+        if result < 0 {
+            return Err(match std::io::Error::last_os_error().raw_os_error() {
+                Some(errno) if errno != 0 => IoProcessError::Os(errno),
+                _ => IoProcessError::Unknown,
+            });
         }
-        // Return with duration of call.
-        Ok(Duration::from_millis(spent_time.unsigned_abs() as u64))
+        Ok(result)
     }
 
-    /// Return the duration that idle server-side sessions are kept alive if they are not referenced
+    /// Prepares the sockets and timers of this context for a wait performed by the caller, as the
+    /// first phase of IO processing for custom schedulers (see the [io](crate::io) module).
+    ///
+    /// This executes operations queued using a [CoapContextHandle], sends pending notifications
+    /// and retransmissions, and returns the sockets to wait for and the time after which
+    /// [CoapContext::complete_io()] has to be called even if none of the sockets became ready
+    /// (or None if there are no timers).
+    /// Callers must call [CoapContext::complete_io()] once any of the sockets is ready or the
+    /// timeout has elapsed, and then call this function again before waiting anew.
+    ///
+    /// # Errors
+    /// Returns [IoProcessError::SplitIoUnsupported] if libcoap does not use epoll for this
+    /// context (i.e., if it was built without epoll support), or an [IoProcessError] if preparing
+    /// IO failed.
+    #[cfg(unix)]
+    pub fn prepare_io(&mut self) -> Result<(Vec<CoapSocketRef>, Option<Duration>), IoProcessError> {
+        self.execute_queued_commands();
+        let mut inner_ref = self.inner.borrow_mut();
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context.
+        let epoll_fd = unsafe { coap_context_get_coap_fd(inner_ref.raw_context) };
+        if epoll_fd < 0 {
+            return Err(IoProcessError::SplitIoUnsupported);
+        }
+        let now = inner_ref.shared.now();
+        let next_deadline = self.prepare_wrapper_io(&mut inner_ref, now);
+        // SAFETY: See do_io_inner(). coap_io_prepare_epoll() sends pending notifications and
+        // retransmissions (possibly calling callbacks) and returns the time until libcoap has to
+        // be called again, or 0 if there are no timers.
+        let next_timer = self.process_raw_io(&mut inner_ref, |raw_ctx_ptr| unsafe {
+            let mut ticks: coap_tick_t = 0;
+            coap_ticks(&mut ticks);
+            c_int::try_from(coap_io_prepare_epoll(raw_ctx_ptr, ticks)).unwrap_or(c_int::MAX)
+        })?;
+        let timer_timeout = (next_timer > 0).then(|| Duration::from_millis(next_timer.unsigned_abs() as u64));
+        let wrapper_timeout = next_deadline.map(|deadline| deadline.saturating_duration_since(now));
+        let timeout = match (timer_timeout, wrapper_timeout) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let mut sockets = vec![CoapSocketRef::new(epoll_fd, CoapSocketInterest::READ)];
+        if let Some(handle_shared) = &inner_ref.handle_shared {
+            sockets.push(CoapSocketRef::new(handle_shared.waker_fd(), CoapSocketInterest::READ));
+        }
+        Ok((sockets, timeout))
+    }
+
+    /// Processes IO after a wait performed by the caller, as the second phase of IO processing for
+    /// custom schedulers (see [CoapContext::prepare_io()] and the [io](crate::io) module).
+    ///
+    /// `ready` lists the sockets returned by [CoapContext::prepare_io()] that became ready, it
+    /// may be empty if the timeout elapsed instead. All received messages are processed, request
+    /// handlers and other callbacks are called from within this function (which may create new
+    /// sessions or endpoints, these are included in the sockets returned by the next call to
+    /// [CoapContext::prepare_io()]). Operations queued using a [CoapContextHandle] are executed
+    /// afterwards.
+    ///
+    /// # Errors
+    /// Returns an [IoProcessError] if processing IO failed.
+    #[cfg(unix)]
+    pub fn complete_io(&mut self, ready: &[CoapSocketReadiness]) -> Result<(), IoProcessError> {
+        {
+            let mut inner_ref = self.inner.borrow_mut();
+            if let Some(handle_shared) = inner_ref.handle_shared.clone() {
+                if ready.iter().any(|v| v.fd == handle_shared.waker_fd()) {
+                    handle_shared.drain_waker();
+                }
+            }
+            // SAFETY: See do_io_inner(). libcoap performs a non-blocking wait on its epoll file
+            // descriptor, so only events that are already pending are processed.
+            self.process_raw_io(&mut inner_ref, |raw_ctx_ptr| unsafe {
+                coap_io_process(raw_ctx_ptr, COAP_IO_NO_WAIT)
+            })?;
+        }
+        self.execute_queued_commands();
+        Ok(())
+    }
+
+    /// Returns a thread-safe handle that can be used to queue operations on this context from other
+    /// threads (see [CoapContextHandle]).
+    ///
+    /// All handles of a context share the same queue, i.e., this function may be called multiple
+    /// times (or the returned handle may be cloned).
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::WakerCreationFailed] if this is the first handle of this
+    /// context and the pipe used to interrupt [CoapContext::do_io()] can not be created (e.g.,
+    /// because the process has run out of file descriptors).
+    #[cfg(any(unix, windows))]
+    pub fn handle(&self) -> Result<CoapContextHandle, ContextHandleError> {
+        self.handle_shared()
+            .map(CoapContextHandle::new)
+            .map_err(|e| ContextHandleError::WakerCreationFailed(e.kind()))
+    }
+
+    /// Returns a handle that can be used to stop [CoapContext::run()] from other threads or from
+    /// signal handlers.
+    ///
+    /// # Errors
+    /// See [CoapContext::handle()].
+    #[cfg(any(unix, windows))]
+    pub fn stop_handle(&self) -> Result<StopHandle, ContextHandleError> {
+        self.handle_shared()
+            .map(StopHandle::new)
+            .map_err(|e| ContextHandleError::WakerCreationFailed(e.kind()))
+    }
+
+    /// Returns the state shared with the handles of this context, creating it if necessary.
+    #[cfg(any(unix, windows))]
+    fn handle_shared(&self) -> std::io::Result<Arc<HandleShared>> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(shared) = inner.handle_shared.as_ref() {
+            return Ok(Arc::clone(shared));
+        }
+        let shared = Arc::new(HandleShared::new()?);
+        inner.handle_shared = Some(Arc::clone(&shared));
+        Ok(shared)
+    }
+
+    /// Returns whether stopping the IO loop of this context was requested using
+    /// [CoapContextHandle::request_stop()] or [StopHandle::stop()] (and the request has not been
+    /// handled by [CoapContext::run()] yet).
+    #[cfg(any(unix, windows))]
+    pub fn stop_requested(&self) -> bool {
+        self.inner
+            .borrow()
+            .handle_shared
+            .as_ref()
+            .is_some_and(|shared| shared.stop_requested())
+    }
+
+    /// Executes all operations that were queued using a [CoapContextHandle].
+    #[cfg(any(unix, windows))]
+    fn execute_queued_commands(&mut self) {
+        let Some(shared) = self.inner.borrow().handle_shared.clone() else {
+            return;
+        };
+        for command in shared.take_commands() {
+            command(self);
+        }
+    }
+
+    /// Keeps the given job until it is completed, returning its ID.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn register_job(&self, job: PendingJob) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.next_job_id;
+        inner.next_job_id += 1;
+        inner.jobs.insert(id, job);
+        id
+    }
+
+    /// Returns the resource of the job with the given ID, or None if the job has been completed or
+    /// its resource has been removed.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn job_resource<D: Any + ?Sized + Debug>(&self, id: u64) -> Option<CoapResource<D>> {
+        self.inner.borrow().jobs.get(&id).and_then(PendingJob::resource)
+    }
+
+    /// Removes the job with the given ID (if it has not been completed yet).
+    #[cfg(any(unix, windows))]
+    pub(crate) fn take_job(&self, id: u64) -> Option<PendingJob> {
+        self.inner.borrow_mut().jobs.remove(&id)
+    }
+
+    /// Keeps the given session alive until [CoapContext::release_session_guard()] is called with
+    /// the returned ID.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn register_session_guard(&self, session: GuardedSession<'a>) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.next_session_guard_id;
+        inner.next_session_guard_id += 1;
+        inner.session_guards.insert(id, session);
+        id
+    }
+
+    /// Returns the session kept alive for the session guard registration with the given ID.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn guarded_session(&self, id: u64) -> Option<CoapSession<'a>> {
+        self.inner
+            .borrow()
+            .session_guards
+            .get(&id)
+            .map(|session| session.session().clone())
+    }
+
+    /// Stops keeping the session of the session guard registration with the given ID alive.
+    #[cfg(any(unix, windows))]
+    pub(crate) fn release_session_guard(&self, id: u64) {
+        let session = self.inner.borrow_mut().session_guards.remove(&id);
+        // Release the session only after the context is no longer borrowed.
+        std::mem::drop(session);
+    }
+
+    /// Notifies the observers of the resource with the given URI path, returning false if there is
+    /// no such resource (or it has no observers).
+    #[cfg(any(unix, windows))]
+    pub(crate) fn notify_resource_observers(&self, uri_path: &CoapResourcePath) -> bool {
+        self.inner
+            .borrow()
+            .resources
+            .iter()
+            .find(|resource| resource.path().as_ref() == Some(uri_path))
+            .is_some_and(|resource| resource.notify_observers())
+    }
+
+    /// Performs IO operations until the provided session is established, waiting for a maximum
+    /// duration of `timeout`.
+    ///
+    /// Sessions created using e.g.
+    /// [CoapClientSession::connect_dtls()](crate::session::CoapClientSession::connect_dtls) may not
+    /// be established immediately (e.g., because the DTLS handshake is still in progress).
+    /// This function can be used to wait for the session to become usable before sending requests.
+    ///
+    /// # Errors
+    /// Returns [SessionEstablishError::HandshakeFailed] if the (D)TLS handshake of the session
+    /// failed (including the alert that caused the failure, if known),
+    /// [SessionEstablishError::Failed] if the session failed to connect for another reason,
+    /// [SessionEstablishError::HandshakeTimeout] if the session was not
+    /// established before its handshake deadline (see [CoapContext::set_handshake_deadline()]),
+    /// [SessionEstablishError::Timeout] if the session did not become established within the
+    /// provided timeout and [SessionEstablishError::Io] if an error occurred while performing IO.
+    pub fn wait_for_session_established<'s, S: CoapSessionCommon<'s>>(
+        &mut self,
+        session: &S,
+        timeout: Duration,
+    ) -> Result<(), SessionEstablishError> {
+        let start = Instant::now();
+        loop {
+            if handshake_timed_out(session) {
+                return Err(SessionEstablishError::HandshakeTimeout);
+            }
+            match session.state() {
+                CoapSessionState::Established => return Ok(()),
+                CoapSessionState::None => {
+                    return Err(match session.last_error() {
+                        Some(SessionCreationError::HandshakeFailed { alert, backend_message }) => {
+                            SessionEstablishError::HandshakeFailed { alert, backend_message }
+                        },
+                        _ => SessionEstablishError::Failed,
+                    })
+                },
+                _ => {},
+            }
+            let remaining_time = timeout
+                .checked_sub(start.elapsed())
+                .filter(|v| !v.is_zero())
+                .ok_or(SessionEstablishError::Timeout)?;
+            self.do_io(Some(remaining_time))?;
+        }
+    }
+
+    /// Creates a client-side session with the given peer `addr`, using DTLS with the given
+    /// `crypto_ctx` and/or unencrypted CoAP over UDP according to the given `policy`
+    /// (opportunistic security).
+    ///
+    /// For [DtlsFallbackPolicy::PreferDtlsFallbackPlain], this performs IO operations until the
+    /// DTLS session is established (see [CoapContext::wait_for_session_established()]). If the
+    /// handshake fails or does not complete within the timeout of the policy, the DTLS session is
+    /// released and an unencrypted session with the same peer is created instead, which is
+    /// reported to [CoapEventHandler::handle_dtls_fallback()].
+    ///
+    /// The returned [FallbackSession] records which transport is used, so applications can refuse
+    /// to send sensitive payloads using unencrypted sessions.
+    ///
+    /// # Errors
+    /// Returns [FallbackConnectError::Creation] if libcoap was unable to create a session (see
+    /// [CoapClientSession::connect_dtls()] and [CoapClientSession::connect_udp()]), and
+    /// [FallbackConnectError::Io] if an error occurred while performing IO during the DTLS
+    /// handshake.
+    #[cfg(dtls)]
+    pub fn connect_with_fallback(
+        &mut self,
+        addr: SocketAddr,
+        policy: DtlsFallbackPolicy,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<FallbackSession<'a>, FallbackConnectError> {
+        let handshake_timeout = match policy {
+            DtlsFallbackPolicy::RequireDtls => {
+                let session = CoapClientSession::connect_dtls(self, addr, crypto_ctx)?;
+                return Ok(FallbackSession::new(session, None));
+            },
+            DtlsFallbackPolicy::PlainOnly => {
+                let session = CoapClientSession::connect_udp(self, addr)?;
+                return Ok(FallbackSession::new(session, None));
+            },
+            DtlsFallbackPolicy::PreferDtlsFallbackPlain { handshake_timeout } => handshake_timeout,
+        };
+        let dtls_session = CoapClientSession::connect_dtls(self, addr, crypto_ctx)?;
+        let dtls_error = match self.wait_for_session_established(&dtls_session, handshake_timeout) {
+            Ok(()) => return Ok(FallbackSession::new(dtls_session, None)),
+            Err(SessionEstablishError::Io(e)) => return Err(e.into()),
+            Err(e) => e,
+        };
+        // Release the DTLS session right away (if there are no other handles to it), so that an
+        // unfinished handshake is not continued alongside the unencrypted session.
+        let _ = dtls_session.disconnect();
+        let session = CoapClientSession::connect_udp(self, addr)?;
+        self.handle_dtls_fallback(session.clone().into(), &dtls_error);
+        Ok(FallbackSession::new(session, Some(dtls_error)))
+    }
+
+    /// Sends the given request using the provided session and performs IO operations until the
+    /// (first) response to it has been received, waiting for a maximum duration of `timeout`.
+    ///
+    /// The session must belong to this context, as IO is only performed for this context's
+    /// sessions. While waiting, all other sessions, endpoints and pending requests of the context
+    /// are serviced as usual, i.e., this is equivalent to sending the request and calling
+    /// [CoapContext::do_io()] until [CoapSessionCommon::try_poll_handle()] returns a response.
+    /// Block-wise responses are reassembled by libcoap, so the returned response always contains
+    /// the full body.
+    /// The request handle is removed before returning, so further responses (e.g., notifications
+    /// if the request contained an Observe option) are rejected.
+    ///
+    /// # Errors
+    /// Returns [RequestWaitError::Send] if the request could not be sent (see
+    /// [CoapSessionCommon::send_request()]), [RequestWaitError::NoResponseExpected] if the request
+    /// suppresses all responses, [RequestWaitError::Request] if the request failed before a
+    /// response was received (see [CoapSessionCommon::try_poll_handle()]),
+    /// [RequestWaitError::Timeout] if no response was received within the provided timeout and
+    /// [RequestWaitError::Io] if an error occurred while performing IO.
+    pub fn send_and_wait<'s, S: CoapSessionCommon<'s>>(
+        &mut self,
+        session: &S,
+        request: CoapRequest,
+        timeout: Duration,
+    ) -> Result<CoapResponse, RequestWaitError> {
+        let start = Instant::now();
+        let req_handle = session.send_request(request)?;
+        if !req_handle.expects_response() {
+            session.remove_handle(req_handle);
+            return Err(RequestWaitError::NoResponseExpected);
+        }
+        let result = loop {
+            match session
+                .try_poll_handle(&req_handle)
+                .map(|mut responses| responses.next())
+            {
+                Ok(Some(response)) => break Ok(response),
+                Ok(None) => {},
+                Err(e) => break Err(e.into()),
+            }
+            let Some(remaining_time) = timeout.checked_sub(start.elapsed()).filter(|v| !v.is_zero()) else {
+                break Err(RequestWaitError::Timeout);
+            };
+            if let Err(e) = self.do_io(Some(remaining_time)) {
+                break Err(e.into());
+            }
+        };
+        session.remove_handle(req_handle);
+        result
+    }
+
+    /// Return the duration that idle server-side sessions are kept alive if they are not referenced
     /// or used anywhere else.
     pub fn session_timeout(&self) -> Duration {
         // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
         // deleted until the CoapContextInner is dropped.
-        let timeout = unsafe { coap_context_get_session_timeout(self.inner.borrow().raw_context) };
-        Duration::from_secs(timeout as u64)
+        let timeout = unsafe { coap_context_get_session_timeout(self.inner.borrow().raw_context) };
+        Duration::from_secs(timeout as u64)
+    }
+
+    /// Set the duration that idle server-side sessions are kept alive if they are not referenced or
+    /// used anywhere else.
+    ///
+    /// # Panics
+    /// Panics if the provided duration is too large to be provided to libcoap (larger than a
+    /// [libc::c_uint]).
+    pub fn set_session_timeout(&self, timeout: Duration) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe {
+            coap_context_set_session_timeout(
+                self.inner.borrow_mut().raw_context,
+                timeout
+                    .as_secs()
+                    .try_into()
+                    .expect("provided session timeout is too large for libcoap (> u32::MAX)"),
+            )
+        }
+    }
+
+    /// Sets the hook that decides whether idle server-side sessions are kept alive, replacing any
+    /// previously set hook.
+    ///
+    /// The hook is called from within [do_io()](CoapContext::do_io) shortly before a server-side
+    /// session has been idle for the session timeout (see [CoapContext::set_session_timeout()]),
+    /// i.e., before libcoap would free it. If the hook returns true (e.g., because the application
+    /// still holds state for the peer), a ping is sent on the session (see
+    /// [CoapSessionCommon::send_ping()]), which resets the idle time tracked by libcoap, and the
+    /// hook is called again once the session has been idle for another session timeout. If it
+    /// returns false, libcoap is left to free the session, and the hook is not called for it again
+    /// unless there is new activity on the session.
+    ///
+    /// libcoap does not report the idle time of sessions, so it is derived from the messages sent
+    /// and received using this wrapper (based on the clock of the context, see
+    /// [CoapContext::set_clock()]). As libcoap also counts messages it handles internally (e.g.,
+    /// retransmissions), the hook may be called earlier than necessary, but not later.
+    /// Sessions that are freed because the maximum number of idle sessions is exceeded (see
+    /// [CoapContext::set_max_idle_sessions()]) are not passed to the hook.
+    pub fn set_idle_session_hook<F: FnMut(&mut CoapServerSession<'_>) -> bool + 'static>(&mut self, hook: F) {
+        self.inner.borrow_mut().idle_session_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the idle session hook of this context (if any), see
+    /// [CoapContext::set_idle_session_hook()].
+    pub fn clear_idle_session_hook(&mut self) {
+        self.inner.borrow_mut().idle_session_hook = None;
+    }
+
+    /// Returns the maximum number of server-side sessions that can concurrently be in a handshake
+    /// state.
+    ///
+    /// If this number is exceeded, no new handshakes will be accepted.
+    pub fn max_handshake_sessions(&self) -> c_uint {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_get_max_handshake_sessions(self.inner.borrow().raw_context) }
+    }
+
+    /// Sets the maximum number of server-side sessions that can concurrently be in a handshake
+    /// state.
+    ///
+    /// If this number is exceeded, no new handshakes will be accepted.
+    pub fn set_max_handshake_sessions(&self, max_handshake_sessions: c_uint) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_set_max_handshake_sessions(self.inner.borrow().raw_context, max_handshake_sessions) };
+    }
+
+    /// Returns the maximum number of idle server-side sessions for this context.
+    ///
+    /// If this number is exceeded, the oldest unreferenced session will be freed.
+    pub fn max_idle_sessions(&self) -> c_uint {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_get_max_idle_sessions(self.inner.borrow().raw_context) }
+    }
+
+    /// Sets the maximum number of idle server-side sessions for this context.
+    ///
+    /// If this number is exceeded, the oldest unreferenced session will be freed.
+    pub fn set_max_idle_sessions(&self, max_idle_sessions: c_uint) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_set_max_idle_sessions(self.inner.borrow().raw_context, max_idle_sessions) };
+    }
+
+    /// Returns the maximum size for Capabilities and Settings Messages
+    ///
+    /// CSMs are used in CoAP over TCP as specified in
+    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
+    pub fn csm_max_message_size(&self) -> u32 {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_get_csm_max_message_size(self.inner.borrow().raw_context) }
+    }
+
+    /// Sets the maximum size for Capabilities and Settings Messages
+    ///
+    /// CSMs are used in CoAP over TCP as specified in
+    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
+    pub fn set_csm_max_message_size(&self, csm_max_message_size: u32) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_set_csm_max_message_size(self.inner.borrow().raw_context, csm_max_message_size) };
+    }
+
+    /// Returns the timeout for Capabilities and Settings Messages
+    ///
+    /// CSMs are used in CoAP over TCP as specified in
+    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
+    pub fn csm_timeout(&self) -> Duration {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        let timeout = unsafe { coap_context_get_csm_timeout_ms(self.inner.borrow().raw_context) };
+        Duration::from_millis(timeout as u64)
+    }
+
+    /// Sets the timeout for Capabilities and Settings Messages
+    ///
+    /// CSMs are used in CoAP over TCP as specified in
+    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
+    ///
+    ///
+    /// The timeout is truncated to full milliseconds. If the peer of a reliable session does not
+    /// send its CSM within this timeout, the session fails and
+    /// [CoapEventHandler::handle_csm_timeout()] is called.
+    ///
+    /// # Panics
+    /// Panics if the provided timeout is too large for libcoap (> [u32::MAX] milliseconds).
+    pub fn set_csm_timeout(&self, csm_timeout: Duration) {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe {
+            coap_context_set_csm_timeout_ms(
+                self.inner.borrow().raw_context,
+                csm_timeout
+                    .as_millis()
+                    .try_into()
+                    .expect("provided CSM timeout is too large for libcoap (> u32::MAX ms)"),
+            )
+        };
+    }
+
+    /// Returns the traffic statistics of all sessions of this context (including sessions that no
+    /// longer exist).
+    ///
+    /// See [CoapSessionCommon::stats()] for the statistics of individual sessions.
+    pub fn stats(&self) -> CoapStats {
+        *self.inner.borrow().shared.stats.borrow()
+    }
+
+    /// Resets the traffic statistics of this context (without affecting the statistics of its
+    /// sessions).
+    pub fn reset_stats(&self) {
+        *self.inner.borrow().shared.stats.borrow_mut() = CoapStats::default();
+    }
+
+    /// Returns the lifecycle counters of the server-side sessions of this context for all
+    /// transports.
+    pub fn server_session_stats(&self) -> CoapServerSessionStats {
+        self.inner
+            .borrow()
+            .server_session_stats
+            .iter()
+            .fold(CoapServerSessionStats::default(), |sum, (_, stats)| sum + *stats)
+    }
+
+    /// Returns the lifecycle counters of the server-side sessions of this context for each
+    /// transport that has been used by a server-side session so far.
+    pub fn server_session_stats_by_protocol(&self) -> Vec<(CoapProtocol, CoapServerSessionStats)> {
+        self.inner.borrow().server_session_stats.clone()
+    }
+
+    /// Returns the number of received packets that were dropped because they could not be parsed.
+    ///
+    /// This includes packets that libcoap reports as bad (see
+    /// [CoapEventHandler::handle_bad_packet()]) as well as UDP datagrams that may have been
+    /// truncated by the socket (see [CoapEventHandler::handle_truncated_datagram()]).
+    /// As libcoap does not report truncation (`MSG_TRUNC`), truncated datagrams are only detected
+    /// with the vendored libcoap build, whose receive buffer is one byte larger than the largest
+    /// datagram it accepts (so only datagrams that exceed this size fill the entire buffer).
+    /// Truncated datagrams are never passed to request handlers or client sessions. Truncated
+    /// requests for resources that do not exist are answered by libcoap itself without involving
+    /// this wrapper and are therefore not counted.
+    /// The event handler is notified about each of these packets, which also provides the session
+    /// (and therefore the address) of the peer that sent the packet.
+    pub fn bad_packet_count(&self) -> u64 {
+        self.inner.borrow().bad_packet_count
+    }
+
+    /// Returns the maximum token size (in bytes) supported by this context.
+    pub fn max_token_size(&self) -> usize {
+        self.inner.borrow().max_token_size
+    }
+
+    /// Sets the maximum token size (in bytes) supported by this context, 8 bytes by default.
+    ///
+    /// Values larger than 8 enable support for extended token lengths as specified in
+    /// [RFC 8974](https://datatracker.ietf.org/doc/html/rfc8974): The maximum token size is
+    /// signaled to peers in the CSM of CoAP over TCP (Extended-Token-Length option), and
+    /// libcoap probes whether peers support extended tokens on unreliable transports.
+    /// Messages with tokens larger than the maximum token size of a session (i.e., the values
+    /// supported by both this context and the peer) are not sent (see
+    /// [CoapSessionCommon::send()]).
+    ///
+    /// As the maximum token size is negotiated when a session is established, changes only apply to
+    /// sessions established afterwards.
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::InvalidMaxTokenSize] if `max_token_size` is smaller
+    /// than [DEFAULT_MAX_TOKEN_SIZE] or larger than [MAX_EXTENDED_TOKEN_SIZE].
+    pub fn set_max_token_size(&self, max_token_size: usize) -> Result<(), ContextConfigurationError> {
+        if !(DEFAULT_MAX_TOKEN_SIZE..=MAX_EXTENDED_TOKEN_SIZE).contains(&max_token_size) {
+            return Err(ContextConfigurationError::InvalidMaxTokenSize(max_token_size));
+        }
+        let mut inner = self.inner.borrow_mut();
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_set_max_token_size(inner.raw_context, max_token_size) };
+        inner.max_token_size = max_token_size;
+        Ok(())
+    }
+
+    /// Sets the options that are ignored when deriving the cache key of a request (see
+    /// [CoapCacheEntry](crate::cache::CoapCacheEntry)), replacing any previously set options.
+    ///
+    /// Requests that only differ in the given options are therefore served by the same cache
+    /// entry.
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::Unknown] if the call to the underlying libcoap library
+    /// function fails.
+    pub fn cache_ignore_options(&self, options: &[CoapOptionNum]) -> Result<(), ContextConfigurationError> {
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped, libcoap copies the provided options.
+        match unsafe { coap_cache_ignore_options(self.inner.borrow().raw_context, options.as_ptr(), options.len()) } {
+            1 => Ok(()),
+            _ => Err(ContextConfigurationError::Unknown),
+        }
+    }
+
+    /// Returns the maximum number of request message IDs that are tracked per session in order to
+    /// detect retransmissions (see [CoapRequest::is_retransmission()]).
+    pub fn dedup_capacity(&self) -> usize {
+        self.inner.borrow().dedup_capacity
+    }
+
+    /// Sets the maximum number of request message IDs that are tracked per session in order to
+    /// detect retransmissions (see [CoapRequest::is_retransmission()]), 32 by default.
+    ///
+    /// Message IDs of requests received on unreliable transports (UDP/DTLS) are tracked for
+    /// [CoapSessionCommon::exchange_lifetime()] (confirmable requests) or
+    /// [CoapSessionCommon::non_lifetime()] (non-confirmable requests), which amounts to roughly
+    /// four minutes with the default transmission parameters.
+    /// If a client sends more requests than the capacity within this time span, the oldest message
+    /// IDs are evicted early and retransmissions of these requests are no longer detected (and
+    /// passed to request handlers as if they were new requests).
+    /// Each tracked message ID requires a few bytes of memory per session, setting the capacity to 0
+    /// disables tracking entirely.
+    ///
+    /// libcoap itself does not provide a way to configure its own (internal) handling of
+    /// duplicates, so this setting only affects the tracking done by this crate.
+    /// Changes only apply to requests received afterwards.
+    pub fn set_dedup_capacity(&self, capacity: usize) {
+        self.inner.borrow_mut().dedup_capacity = capacity;
+    }
+
+    /// Returns the number of received requests that were detected to be retransmissions of earlier
+    /// requests (see [CoapRequest::is_retransmission()]).
+    pub fn duplicate_request_count(&self) -> u64 {
+        self.inner.borrow().duplicate_request_count
+    }
+
+    /// Records the receipt of the given request on the given server-side session and returns
+    /// whether the request is a retransmission of an earlier request.
+    pub(crate) fn track_request(&self, session: &CoapServerSession, request: &CoapRequest) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let mid = match request.mid() {
+            Some(mid) if !session.proto().is_reliable() => mid,
+            _ => return false,
+        };
+        let retransmission = record_request_mid(
+            session,
+            mid,
+            request.type_() == CoapMessageType::Con,
+            inner.dedup_capacity,
+        );
+        if retransmission {
+            inner.duplicate_request_count += 1;
+            record_stats(session, |stats| stats.retransmissions_received += 1);
+        }
+        retransmission
+    }
+
+    /// Sets the provider used to generate and verify Echo values for resources that require
+    /// requests to contain a fresh Echo value (see
+    /// [CoapResource::set_echo_policy()](crate::CoapResource::set_echo_policy)).
+    ///
+    /// If no provider is set, a [RandomEchoValueProvider] with its default freshness window is
+    /// used.
+    pub fn set_echo_value_provider<P: CoapEchoValueProvider + 'static>(&mut self, provider: P) {
+        self.inner.borrow_mut().echo_value_provider = Some(Box::new(provider));
+    }
+
+    /// Sets the generator used to generate the tokens of requests sent using sessions of this
+    /// context, see the [module documentation of token](crate::token).
+    ///
+    /// If no generator is set, a [RandomTokens] generator for tokens of
+    /// [DEFAULT_MAX_TOKEN_SIZE] bytes is used.
+    pub fn set_token_generator<G: CoapTokenGenerator + 'static>(&mut self, generator: G) {
+        *self.inner.borrow().shared.token_generator.borrow_mut() = Some(Box::new(generator));
+    }
+
+    /// Verifies the Echo value of the given request received on the given server-side session.
+    ///
+    /// Returns an error containing the Echo value the request should be challenged with if the
+    /// request does not contain a valid Echo value (which is None if no value could be
+    /// generated).
+    pub(crate) fn verify_echo(&self, session: &CoapServerSession, request: &CoapRequest) -> Result<(), Option<Echo>> {
+        let mut inner = self.inner.borrow_mut();
+        let provider = inner
+            .echo_value_provider
+            .get_or_insert_with(|| Box::new(RandomEchoValueProvider::default()));
+        match request.echo() {
+            Some(value) if provider.verify(session, value) => Ok(()),
+            _ => Err(provider.generate(session)),
+        }
+    }
+
+    /// Limits the rate of requests accepted from each peer, or removes the limit if `limit` is
+    /// None (the default).
+    ///
+    /// Requests exceeding the limit are not passed to the resource they are addressed to, but
+    /// dropped or answered with 4.29 (Too Many Requests), see the [rate_limit](crate::rate_limit)
+    /// module for details. The rate limit is based on the clock of the context (see
+    /// [CoapContext::set_clock()]).
+    ///
+    /// Setting a rate limit discards the token buckets of all peers, i.e., each peer may send a
+    /// full burst of requests afterwards.
+    pub fn set_rate_limit(&mut self, limit: Option<CoapRateLimit>) {
+        self.inner.borrow_mut().rate_limiter = limit.map(RateLimiter::new);
+    }
+
+    /// Returns the rate limit applied to the requests of each peer, if any (see
+    /// [CoapContext::set_rate_limit()]).
+    pub fn rate_limit(&self) -> Option<CoapRateLimit> {
+        self.inner.borrow().rate_limiter.as_ref().map(RateLimiter::limit)
+    }
+
+    /// Sets the hook that is called for each request exceeding the rate limit of its peer (see
+    /// [CoapContext::set_rate_limit()]), replacing any previously set hook.
+    ///
+    /// The hook is called before the request is dropped or rejected, e.g., in order to log
+    /// offending peers. It is called while the context is borrowed and must therefore not use
+    /// the context.
+    pub fn set_rate_limit_hook<F: FnMut(&CoapThrottledRequest) + 'static>(&mut self, hook: F) {
+        self.inner.borrow_mut().rate_limit_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the rate limit hook of this context (if any), see
+    /// [CoapContext::set_rate_limit_hook()].
+    pub fn clear_rate_limit_hook(&mut self) {
+        self.inner.borrow_mut().rate_limit_hook = None;
+    }
+
+    /// Sets the limits for the options and payloads of requests to resources that do not have
+    /// their own limits (see [CoapResource::set_input_limits()]).
+    ///
+    /// Requests exceeding the limits are rejected before they are passed to the resource, see the
+    /// [limits](crate::limits) module for details. By default, [CoapInputLimits::default()] is
+    /// used.
+    pub fn set_input_limits(&self, limits: CoapInputLimits) {
+        self.inner.borrow_mut().input_limits = limits;
+    }
+
+    /// Returns the limits for the options and payloads of requests, see
+    /// [CoapContext::set_input_limits()].
+    pub fn input_limits(&self) -> CoapInputLimits {
+        self.inner.borrow().input_limits
+    }
+
+    /// Applies the rate limit of this context (if any) to the given request received on the
+    /// given server-side session.
+    ///
+    /// Returns None if the request may be passed on to its resource, or the action for the
+    /// throttled request and the time after which the peer may send its next request.
+    pub(crate) fn apply_rate_limit(
+        &self,
+        session: &CoapServerSession,
+        request: &CoapRequest,
+    ) -> Option<(CoapRateLimitAction, Duration)> {
+        let CoapMessageCode::Request(code) = request.code() else {
+            return None;
+        };
+        let mut inner = self.inner.borrow_mut();
+        inner.rate_limiter.as_ref()?;
+        // Peers are identified by their IP address, requests of other peers (e.g., on Unix domain
+        // sockets) are not throttled.
+        let peer = remote_socket_addr(session)?.ip();
+        let now = inner.shared.now();
+        let (action, retry_after) = inner.rate_limiter.as_mut()?.take_token(peer, request.type_(), now)?;
+        record_stats(session, |stats| stats.requests_throttled += 1);
+        if let Some(hook) = &mut inner.rate_limit_hook {
+            hook(&CoapThrottledRequest {
+                peer,
+                code,
+                type_: request.type_(),
+                action,
+                retry_after,
+            });
+        }
+        Some((action, retry_after))
+    }
+
+    /// Requires DTLS handshakes to be verified statelessly using a cookie exchange before any
+    /// per-client state is allocated.
+    ///
+    /// If stateless verification is used, a server answers the initial ClientHello with a
+    /// HelloVerifyRequest containing a cookie and only creates a session once the client repeats
+    /// the ClientHello with this cookie, which prevents clients with spoofed source addresses from
+    /// allocating server state ([RFC 6347, Section 4.2.1](https://datatracker.ietf.org/doc/html/rfc6347#section-4.2.1)).
+    ///
+    /// libcoap always performs the cookie exchange if the DTLS library supports it (see
+    /// [TlsBackend::stateless_handshake_verification_supported()](crate::crypto::TlsBackend::stateless_handshake_verification_supported)),
+    /// this function therefore does not change libcoap's behavior, but allows deployments to
+    /// ensure that the DTLS library in use actually provides this protection.
+    /// Disabling the requirement is always possible, but does not disable the cookie exchange.
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::UnsupportedFeature] if `require` is true but the DTLS
+    /// library libcoap was built with does not support stateless handshake verification. In this
+    /// case, deployments exposed to the internet should be protected against spoofed handshakes
+    /// by other means.
+    #[cfg(dtls)]
+    pub fn require_handshake_cookie(&mut self, require: bool) -> Result<(), ContextConfigurationError> {
+        if require && !tls_backend().stateless_handshake_verification_supported() {
+            return Err(ContextConfigurationError::UnsupportedFeature(
+                "stateless DTLS handshake verification",
+            ));
+        }
+        self.inner.borrow_mut().require_handshake_cookie = require;
+        Ok(())
+    }
+
+    /// Returns whether stateless cookie verification of DTLS handshakes is required (see
+    /// [CoapContext::require_handshake_cookie()]).
+    #[cfg(dtls)]
+    pub fn requires_handshake_cookie(&self) -> bool {
+        self.inner.borrow().require_handshake_cookie
+    }
+
+    /// Returns the number of DTLS handshakes that passed the cookie exchange, i.e., the number of
+    /// DTLS server-side sessions that were created.
+    ///
+    /// ClientHellos that were answered with a HelloVerifyRequest, but never repeated with a valid
+    /// cookie (e.g. because their source address was spoofed) are not reported by libcoap and
+    /// therefore not counted, as no state is kept for them. For the same reason, there is no
+    /// counter for handshakes rejected at the cookie stage.
+    /// The difference between this value and the sum of [CoapContext::dtls_handshakes_completed()]
+    /// and [CoapContext::dtls_handshakes_failed()] is the number of handshakes that are still in
+    /// progress after the cookie exchange.
+    #[cfg(dtls)]
+    pub fn dtls_handshakes_verified(&self) -> u64 {
+        self.inner.borrow().dtls_handshakes_verified
+    }
+
+    /// Returns the number of DTLS handshakes with clients that were completed successfully.
+    #[cfg(dtls)]
+    pub fn dtls_handshakes_completed(&self) -> u64 {
+        self.inner.borrow().dtls_handshakes_completed
+    }
+
+    /// Returns the number of DTLS handshakes with clients that passed the cookie exchange, but
+    /// failed afterwards (e.g., because the client used unknown credentials).
+    #[cfg(dtls)]
+    pub fn dtls_handshakes_failed(&self) -> u64 {
+        self.inner.borrow().dtls_handshakes_failed
+    }
+
+    /// Returns a snapshot of the effective configuration of this context.
+    ///
+    /// Snapshots can be compared using [CoapContextConfig::diff()] in order to detect changes made
+    /// at runtime.
+    pub fn config(&self) -> CoapContextConfig {
+        let inner = self.inner.borrow();
+        #[allow(unused_mut)]
+        let mut config = CoapContextConfig {
+            block_mode: inner.block_mode,
+            session_timeout: self.session_timeout(),
+            max_handshake_sessions: self.max_handshake_sessions(),
+            max_idle_sessions: self.max_idle_sessions(),
+            csm_max_message_size: self.csm_max_message_size(),
+            csm_timeout: self.csm_timeout(),
+            keepalive: inner.keepalive,
+            max_token_size: inner.max_token_size,
+            dedup_capacity: inner.dedup_capacity,
+            default_leisure: self.default_leisure(),
+            probing_rate: self.probing_rate(),
+            input_limits: inner.input_limits,
+            rate_limit: self.rate_limit(),
+            memory_limits: self.memory_limits(),
+            send_queue_limits: self.send_queue_limits(),
+            wellknown_core_mode: inner.well_known_core_mode.to_string(),
+            reconnect_policy: self.reconnect_policy(),
+            max_pending_requests: self.max_pending_requests(),
+            notification_pacing: self.notification_pacing(),
+            default_host: inner.default_host.clone(),
+            session_pool_capacity: self.session_pool_capacity(),
+            session_pool_idle_timeout: self.session_pool_idle_timeout(),
+            endpoints: inner
+                .endpoints
+                .iter()
+                .map(|endpoint| CoapEndpointConfig {
+                    proto: endpoint.proto().to_string(),
+                    addr: endpoint.display_addr().to_string(),
+                })
+                .collect(),
+            psk: false,
+            pki_rpk: false,
+            pki_root_cas: false,
+            require_handshake_cookie: false,
+        };
+        #[cfg(feature = "dtls-psk")]
+        {
+            config.psk = inner.psk_context.is_some();
+        }
+        #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+        {
+            config.pki_rpk = inner.pki_rpk_context.is_some();
+        }
+        #[cfg(feature = "dtls-pki")]
+        {
+            config.pki_root_cas = inner.pki_root_cas_set;
+        }
+        #[cfg(dtls)]
+        {
+            config.require_handshake_cookie = inner.require_handshake_cookie;
+        }
+        config
+    }
+
+    /// Returns the time to wait before sending a CoAP keepalive message (ping) for idle sessions.
+    ///
+    /// Returns None if CoAP-level keepalive messages are disabled (the default).
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.inner.borrow().keepalive
+    }
+
+    /// Sets the time to wait before sending a CoAP keepalive message (ping) for idle sessions.
+    ///
+    /// If the provided value is None, CoAP-level keepalive messages will be disabled.
+    ///
+    /// Keepalive messages are sent as part of the regular IO processing in
+    /// [do_io()](CoapContext::do_io), i.e., no additional calls are required to keep sessions (and
+    /// e.g. NAT bindings for UDP sessions) alive.
+    /// If a keepalive message is not answered, the event handler's
+    /// [handle_keepalive_failure()](CoapEventHandler::handle_keepalive_failure) function is called.
+    ///
+    /// libcoap only supports keepalive intervals in whole seconds, the provided duration will
+    /// therefore be truncated to whole seconds (a duration shorter than one second will disable
+    /// keepalive messages).
+    ///
+    /// # Panics
+    /// Panics if the provided duration is too large to be provided to libcoap (larger than a
+    /// [libc::c_uint]).
+    pub fn set_keepalive(&self, timeout: Option<Duration>) {
+        let timeout_secs: c_uint = timeout.map_or(0, |v| {
+            v.as_secs()
+                .try_into()
+                .expect("provided keepalive time is too large for libcoap (> c_uint)")
+        });
+        let mut inner = self.inner.borrow_mut();
+        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+        // deleted until the CoapContextInner is dropped.
+        unsafe { coap_context_set_keepalive(inner.raw_context, timeout_secs) };
+        inner.keepalive = (timeout_secs != 0).then(|| Duration::from_secs(timeout_secs.into()));
     }
 
-    /// Set the duration that idle server-side sessions are kept alive if they are not referenced or
-    /// used anywhere else.
+    /// Sets the policy for reconnecting client sessions of this context whose connection was lost
+    /// (e.g., because the server was restarted), or disables reconnecting if `policy` is None.
     ///
-    /// # Panics
-    /// Panics if the provided duration is too large to be provided to libcoap (larger than a
-    /// [libc::c_uint]).
-    pub fn set_session_timeout(&self, timeout: Duration) {
+    /// If a policy is set, libcoap tries to re-establish client sessions that lose their
+    /// connection after they had been established, re-running the (D)TLS handshake using the
+    /// cryptographic information the session was created with. If
+    /// [ReconnectPolicy::reregister_observations] is set, the observations of the session are
+    /// registered again once it has been re-established. The session keeps its identity
+    /// throughout, i.e., all existing handles to the session (and requests handles for
+    /// observations) remain valid.
+    /// Use [CoapClientSession::is_reconnecting()](crate::session::CoapClientSession::is_reconnecting) to check whether a session is currently
+    /// reconnecting, requests sent in the meantime are handled as described in
+    /// [ReconnectPolicy::max_queued_requests].
+    ///
+    /// As reconnection is based on connection events, it only applies to reliable and
+    /// DTLS-secured sessions.
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::UnsupportedFeature] if `policy` is not None but the
+    /// libcoap version in use does not support reconnecting sessions (libcoap < 4.3.5).
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) -> Result<(), ContextConfigurationError> {
+        #[cfg(not(session_reconnect_support))]
+        if policy.is_some() {
+            return Err(ContextConfigurationError::UnsupportedFeature("session reconnection"));
+        }
+        let mut inner = self.inner.borrow_mut();
+        inner.shared.reconnect_policy.set(policy);
+        inner.reconnect_delay = policy.map_or(Duration::ZERO, |v| v.initial_delay);
         // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
         // deleted until the CoapContextInner is dropped.
+        #[cfg(session_reconnect_support)]
         unsafe {
-            coap_context_set_session_timeout(
-                self.inner.borrow_mut().raw_context,
-                timeout
-                    .as_secs()
-                    .try_into()
-                    .expect("provided session timeout is too large for libcoap (> u32::MAX)"),
-            )
+            set_raw_reconnect_delay(inner.raw_context, policy.map(|v| v.initial_delay))
+        };
+        Ok(())
+    }
+
+    /// Returns the policy for reconnecting client sessions of this context, see
+    /// [CoapContext::set_reconnect_policy()].
+    pub fn reconnect_policy(&self) -> Option<ReconnectPolicy> {
+        self.inner.borrow().shared.reconnect_policy.get()
+    }
+
+    /// Sets the maximum number of pending requests per session, i.e., of requests whose handles
+    /// have not been removed using
+    /// [CoapSessionCommon::remove_handle()](crate::session::CoapSessionCommon::remove_handle) yet
+    /// (see [CoapSessionCommon::pending_requests()](crate::session::CoapSessionCommon::pending_requests)).
+    ///
+    /// Sending further requests on a session that has reached the limit fails with
+    /// [MessageConversionError::TooManyPendingRequests](crate::error::MessageConversionError::TooManyPendingRequests),
+    /// which protects against unbounded growth of the pending request tables (and of the values
+    /// stored alongside requests) if handles are not removed. By default, there is no limit.
+    pub fn set_max_pending_requests(&self, limit: Option<usize>) {
+        self.inner.borrow().shared.max_pending_requests.set(limit);
+    }
+
+    /// Returns the maximum number of pending requests per session, see
+    /// [CoapContext::set_max_pending_requests()].
+    pub fn max_pending_requests(&self) -> Option<usize> {
+        self.inner.borrow().shared.max_pending_requests.get()
+    }
+
+    /// Returns the memory used by the data structures of this wrapper for this context, broken
+    /// down by category (see [CoapMemoryReport]).
+    ///
+    /// Sessions (including client sessions, which are not stored by the context) keep track of
+    /// the memory used by their bookkeeping whenever entries are inserted or removed, while the
+    /// usage of resources and their notifications is determined when this function is called.
+    pub fn memory_usage(&self) -> CoapMemoryReport {
+        let inner = self.inner.borrow();
+        let mut report = inner.shared.memory.report();
+        let resources = inner
+            .resources
+            .iter()
+            .chain(inner.removed_resources.iter().map(|(resource, _)| resource));
+        report.resources = resources.map(|resource| resource.memory_usage()).sum();
+        let notify_states = inner
+            .resource_notify_states
+            .iter()
+            .chain(inner.removed_resources.iter().map(|(_, state)| state));
+        for state in notify_states {
+            let usage = state.borrow().memory_usage();
+            report.resources += usage.resources;
+            report.cached_representations += usage.cached_representations;
+            report.queued_notifications += usage.queued_notifications;
         }
+        report
     }
 
-    /// Returns the maximum number of server-side sessions that can concurrently be in a handshake
-    /// state.
+    /// Sets the limits for the memory used by the data structures of this wrapper for this
+    /// context (see [CoapContext::memory_usage()]).
     ///
-    /// If this number is exceeded, no new handshakes will be accepted.
-    pub fn max_handshake_sessions(&self) -> c_uint {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_get_max_handshake_sessions(self.inner.borrow().raw_context) }
+    /// Once the memory used by sessions has reached its limit, creating client sessions fails
+    /// with [SessionCreationError::MemoryLimitExceeded] and new server-side sessions answer all
+    /// requests with 5.03 (Service Unavailable). Once the memory used by pending requests has
+    /// reached its limit, sending further requests fails with
+    /// [MessageConversionError::MemoryLimitExceeded](crate::error::MessageConversionError::MemoryLimitExceeded).
+    /// Rejections are counted in [CoapMemoryReport::sessions_rejected] and
+    /// [CoapMemoryReport::requests_rejected]. By default, there are no limits.
+    pub fn set_memory_limits(&self, limits: CoapMemoryLimits) {
+        self.inner.borrow().shared.memory.set_limits(limits);
     }
 
-    /// Sets the maximum number of server-side sessions that can concurrently be in a handshake
-    /// state.
+    /// Returns the limits for the memory used by the data structures of this wrapper, see
+    /// [CoapContext::set_memory_limits()].
+    pub fn memory_limits(&self) -> CoapMemoryLimits {
+        self.inner.borrow().shared.memory.limits()
+    }
+
+    /// Checks whether a new client session may be created without exceeding the memory limit for
+    /// sessions (see [CoapContext::set_memory_limits()]).
+    pub(crate) fn check_session_memory(&self) -> Result<(), SessionCreationError> {
+        self.inner
+            .borrow()
+            .shared
+            .memory
+            .check_session()
+            .map_err(SessionCreationError::MemoryLimitExceeded)
+    }
+
+    /// Sets the high-water and low-water marks for the send queues of the sessions of this
+    /// context (see [CoapSessionCommon::queued_bytes()](crate::session::CoapSessionCommon::queued_bytes)).
     ///
-    /// If this number is exceeded, no new handshakes will be accepted.
+    /// Once the queued bytes of a session reach the high-water mark, sending requests fails with
+    /// [MessageConversionError::SendQueueFull](crate::error::MessageConversionError::SendQueueFull)
+    /// and [CoapResource::try_notify_observers()] refuses to notify observers using the session,
+    /// until the send queue has drained to the low-water mark, which is reported to
+    /// [CoapEventHandler::handle_send_queue_drained()].
+    /// This allows applications to slow down when a peer (or the network) can not keep up,
+    /// instead of queuing up more and more messages in libcoap.
+    ///
+    /// Only confirmable messages sent on unreliable transports are queued, and the queue of a
+    /// session is only approximate, see
+    /// [CoapSessionCommon::queued_pdus()](crate::session::CoapSessionCommon::queued_pdus).
+    /// Drained send queues are detected whenever a response or rejection is received and in
+    /// [CoapContext::do_io()].
+    ///
+    /// By default (or if `None` is provided), send queues are not limited.
+    pub fn set_send_queue_limits(&mut self, limits: Option<SendQueueLimits>) {
+        self.inner.borrow().shared.send_queue_limits.set(limits);
+    }
 
-    pub fn set_max_handshake_sessions(&self, max_handshake_sessions: c_uint) {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_set_max_handshake_sessions(self.inner.borrow().raw_context, max_handshake_sessions) };
+    /// Returns the limits for the send queues of sessions, see
+    /// [CoapContext::set_send_queue_limits()].
+    pub fn send_queue_limits(&self) -> Option<SendQueueLimits> {
+        self.inner.borrow().shared.send_queue_limits.get()
     }
 
-    /// Returns the maximum number of idle server-side sessions for this context.
+    /// Sets how the observe notifications of resources without a pacing of their own are spread
+    /// across multiple calls to [CoapContext::do_io()] (see
+    /// [CoapResource::set_notification_pacing()]).
     ///
-    /// If this number is exceeded, the oldest unreferenced session will be freed.
-    pub fn max_idle_sessions(&self) -> c_uint {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_get_max_idle_sessions(self.inner.borrow().raw_context) }
+    /// Without pacing (i.e., with [NotificationPacing::Unlimited], the default), libcoap sends the
+    /// notifications to all observers of a resource at once, which may saturate the uplink for
+    /// popular resources. With pacing, notifying the observers queues a notification for each of
+    /// them (in the order of their registration, see [CoapResource::observers()]), and each call
+    /// to `do_io()` sends as many of them as the pacing allows (returning early once further
+    /// notifications may be sent). The budget applies to each resource separately.
+    ///
+    /// Observers have at most one queued notification each, which is generated when it is
+    /// sent. If observers are notified again while notifications are still queued, the queued
+    /// notifications are superseded by the new one (using the latest snapshot, see
+    /// [CoapResource::notify_observers_with_snapshot()]) and keep their position in the queue,
+    /// which preserves the order of notifications for each observer. Applications may use
+    /// [CoapContext::queued_notifications()] or [CoapResource::queued_notifications()] to delay
+    /// the next snapshot until the queue has drained.
+    ///
+    /// Paced notifications are generated by passing the registration request of the observer to
+    /// the GET handler again (or by sending the pending snapshot) and are sent by the wrapper as
+    /// separate responses, so they must fit into a single PDU.
+    pub fn set_notification_pacing(&mut self, pacing: NotificationPacing) {
+        self.inner.borrow().shared.notification_pacing.set(pacing);
     }
 
-    /// Sets the maximum number of idle server-side sessions for this context.
+    /// Returns the pacing of notifications for resources without a pacing of their own, see
+    /// [CoapContext::set_notification_pacing()].
+    pub fn notification_pacing(&self) -> NotificationPacing {
+        self.inner.borrow().shared.notification_pacing.get()
+    }
+
+    /// Returns the number of paced notifications of all resources of this context that have not
+    /// been sent yet, see [CoapContext::set_notification_pacing()].
+    pub fn queued_notifications(&self) -> usize {
+        self.inner
+            .borrow()
+            .resource_notify_states
+            .iter()
+            .map(|state| state.borrow().queued_notifications())
+            .sum()
+    }
+
+    /// Returns a client-side session with the peer referred to by the given URI, reusing a session
+    /// previously returned by this function if possible.
     ///
-    /// If this number is exceeded, the oldest unreferenced session will be freed.
-    pub fn set_max_idle_sessions(&self, max_idle_sessions: c_uint) {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_set_max_idle_sessions(self.inner.borrow().raw_context, max_idle_sessions) };
+    /// The URI may use any of the schemes supported by
+    /// [CoapClientSession::connect_uri()], which also determines the transport of the session.
+    /// Its host part is resolved into a list of socket addresses, and a session is reused if it
+    /// was created for one of these addresses using the same transport (i.e., sessions using
+    /// different transports, such as UDP and DTLS, for the same address are kept separately).
+    /// Otherwise (or if the session has failed, was closed or has been evicted from the pool), a
+    /// new session is created and added to the pool. Sessions whose connection or handshake is
+    /// still in progress are reused as well.
+    ///
+    /// Sessions are removed from the pool once libcoap reports them as closed (unless they are
+    /// being reconnected, see [CoapContext::set_reconnect_policy()]), after they have not been used
+    /// for the idle timeout of the pool (see [CoapContext::set_session_pool_idle_timeout()]), and
+    /// in least recently used order if the capacity of the pool is exceeded (see
+    /// [CoapContext::set_session_pool_capacity()]). Removed sessions remain usable for as long as
+    /// clones of them are held by the application.
+    ///
+    /// # Errors
+    /// See [CoapClientSession::connect_uri()].
+    pub fn get_or_connect<U>(&mut self, uri: U) -> Result<CoapClientSession<'a>, SessionCreationError>
+    where
+        U: TryInto<CoapUri>,
+        UriParsingError: From<U::Error>,
+    {
+        let uri = uri.try_into().map_err(UriParsingError::from)?;
+        let (scheme, addrs) = resolve_uri(
+            &uri,
+            &[
+                CoapUriScheme::Coap,
+                #[cfg(feature = "tcp")]
+                CoapUriScheme::CoapTcp,
+                #[cfg(feature = "websockets")]
+                CoapUriScheme::CoapWs,
+            ],
+        )?;
+        let proto = match scheme {
+            #[cfg(feature = "tcp")]
+            CoapUriScheme::CoapTcp => CoapProtocol::Tcp,
+            #[cfg(feature = "websockets")]
+            CoapUriScheme::CoapWs => CoapProtocol::Ws,
+            _ => CoapProtocol::Udp,
+        };
+        self.get_or_insert_pooled(&addrs, proto, None, |context| {
+            CoapClientSession::connect_resolved(context, scheme, &addrs)
+        })
     }
 
-    /// Returns the maximum size for Capabilities and Settings Messages
+    /// Returns a DTLS encrypted client-side session with the peer referred to by the given
+    /// `coaps://` URI using the given `crypto_ctx`, reusing a session previously returned by this
+    /// function if possible.
     ///
-    /// CSMs are used in CoAP over TCP as specified in
-    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
-    pub fn csm_max_message_size(&self) -> u32 {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_get_csm_max_message_size(self.inner.borrow().raw_context) }
+    /// Sessions are only reused if they were created using the same crypto context (or a clone of
+    /// it), i.e., separately built crypto contexts result in separate sessions even if they contain
+    /// the same credentials. See [CoapContext::get_or_connect()] for how sessions are pooled.
+    ///
+    /// # Errors
+    /// See [CoapClientSession::connect_dtls_uri()].
+    #[cfg(dtls)]
+    pub fn get_or_connect_dtls<U>(
+        &mut self,
+        uri: U,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError>
+    where
+        U: TryInto<CoapUri>,
+        UriParsingError: From<U::Error>,
+    {
+        let crypto_ctx = crypto_ctx.into();
+        let uri = uri.try_into().map_err(UriParsingError::from)?;
+        let (_, addrs) = resolve_uri(&uri, &[CoapUriScheme::Coaps])?;
+        let credentials = Some(crypto_ctx.credentials_id());
+        self.get_or_insert_pooled(&addrs, CoapProtocol::Dtls, credentials, |context| {
+            CoapClientSession::connect_dtls_resolved(context, &addrs, crypto_ctx)
+        })
     }
 
-    /// Sets the maximum size for Capabilities and Settings Messages
+    /// Returns the pooled session for any of the given addresses with the given transport and
+    /// credentials, or creates a new one using `connect` and adds it to the pool.
+    fn get_or_insert_pooled<F: FnOnce(&mut Self) -> Result<CoapClientSession<'a>, SessionCreationError>>(
+        &mut self,
+        addrs: &[SocketAddr],
+        proto: CoapProtocol,
+        credentials: Option<usize>,
+        connect: F,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        let keys: Vec<SessionPoolKey> = addrs
+            .iter()
+            .map(|addr| SessionPoolKey {
+                addr: *addr,
+                proto,
+                credentials,
+            })
+            .collect();
+        if let Some(session) = self.inner.borrow_mut().session_pool.get(&keys) {
+            return Ok(session);
+        }
+        let session = connect(self)?;
+        // Sessions are only pooled by their IP socket address, sessions without one (which can
+        // not be created from URIs anyway) are returned without adding them to the pool.
+        if let Some(addr) = remote_socket_addr(&session) {
+            let key = SessionPoolKey {
+                addr,
+                proto,
+                credentials,
+            };
+            self.inner.borrow_mut().session_pool.insert(key, session.clone());
+        }
+        Ok(session)
+    }
+
+    /// Sets the maximum number of sessions held by the session pool of this context (64 by
+    /// default, see [CoapContext::get_or_connect()]).
     ///
-    /// CSMs are used in CoAP over TCP as specified in
-    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
-    pub fn set_csm_max_message_size(&self, csm_max_message_size: u32) {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe { coap_context_set_csm_max_message_size(self.inner.borrow().raw_context, csm_max_message_size) };
+    /// If the pool holds more sessions, the least recently used ones are removed from it.
+    pub fn set_session_pool_capacity(&self, capacity: usize) {
+        self.inner.borrow_mut().session_pool.set_capacity(capacity);
     }
 
-    /// Returns the timeout for Capabilities and Settings Messages
+    /// Returns the maximum number of sessions held by the session pool of this context, see
+    /// [CoapContext::set_session_pool_capacity()].
+    pub fn session_pool_capacity(&self) -> usize {
+        self.inner.borrow().session_pool.capacity()
+    }
+
+    /// Sets the time after which sessions are removed from the session pool of this context if
+    /// they have neither been returned by [CoapContext::get_or_connect()] nor had pending requests
+    /// since (None to keep them until they are closed or evicted, the default).
     ///
-    /// CSMs are used in CoAP over TCP as specified in
-    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
-    pub fn csm_timeout(&self) -> Duration {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        let timeout = unsafe { coap_context_get_csm_timeout(self.inner.borrow().raw_context) };
-        Duration::from_secs(timeout as u64)
+    /// Idle sessions are removed while performing IO (see [CoapContext::do_io()]).
+    pub fn set_session_pool_idle_timeout(&self, timeout: Option<Duration>) {
+        self.inner.borrow_mut().session_pool.set_idle_timeout(timeout);
     }
 
-    /// Sets the timeout for Capabilities and Settings Messages
+    /// Returns the time after which unused sessions are removed from the session pool of this
+    /// context, see [CoapContext::set_session_pool_idle_timeout()].
+    pub fn session_pool_idle_timeout(&self) -> Option<Duration> {
+        self.inner.borrow().session_pool.idle_timeout()
+    }
+
+    /// Returns the number of sessions currently held by the session pool of this context (see
+    /// [CoapContext::get_or_connect()]).
+    pub fn pooled_sessions(&self) -> usize {
+        self.inner.borrow().session_pool.len()
+    }
+
+    /// Sets the DEFAULT_LEISURE of sessions of this context that are created afterwards, i.e., the
+    /// time span over which responses to multicast requests are spread (see
+    /// [CoapSessionCommon::set_default_leisure()](crate::session::CoapSessionCommon::set_default_leisure)).
     ///
-    /// CSMs are used in CoAP over TCP as specified in
-    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3).
+    /// [RFC 7252, Section 8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-8.2)
+    /// recommends deriving the leisure from the expected number of responding servers, their
+    /// response size and the PROBING_RATE, so that responses from a large group of servers do not
+    /// congest the network. As libcoap only allows setting this value per session, existing
+    /// sessions keep their current value.
+    pub fn set_default_leisure(&self, leisure: Duration) {
+        self.inner.borrow().shared.default_leisure.set(Some(leisure));
+    }
+
+    /// Returns the DEFAULT_LEISURE of new sessions of this context, see
+    /// [CoapContext::set_default_leisure()] (5 seconds by default).
+    pub fn default_leisure(&self) -> Duration {
+        self.inner
+            .borrow()
+            .shared
+            .default_leisure
+            .get()
+            .unwrap_or(DEFAULT_LEISURE)
+    }
+
+    /// Sets the PROBING_RATE (in bytes per second) of sessions of this context that are created
+    /// afterwards (see
+    /// [CoapSessionCommon::set_probing_rate()](crate::session::CoapSessionCommon::set_probing_rate)).
     ///
-    /// # Panics
-    /// Panics if the provided timeout is too large for libcoap (> [u32::MAX]).
-    pub fn set_csm_timeout(&self, csm_timeout: Duration) {
-        // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe {
-            coap_context_set_csm_timeout(
-                self.inner.borrow().raw_context,
-                csm_timeout
-                    .as_secs()
-                    .try_into()
-                    .expect("provided session timeout is too large for libcoap (> u32::MAX)"),
-            )
-        };
+    /// As libcoap only allows setting this value per session, existing sessions keep their current
+    /// value.
+    pub fn set_probing_rate(&self, rate: u32) {
+        self.inner.borrow().shared.probing_rate.set(Some(rate));
+    }
+
+    /// Returns the PROBING_RATE (in bytes per second) of new sessions of this context, see
+    /// [CoapContext::set_probing_rate()] (1 byte per second by default).
+    pub fn probing_rate(&self) -> u32 {
+        self.inner
+            .borrow()
+            .shared
+            .probing_rate
+            .get()
+            .unwrap_or(DEFAULT_PROBING_RATE)
     }
 
-    /// Sets the number of seconds to wait before sending a CoAP keepalive message for idle
-    /// sessions.
+    /// Joins the given multicast group on the UDP endpoints of this context, so that requests sent
+    /// to the group are passed to resources that support multicast (see
+    /// [ResourceFlags::HAS_MCAST_SUPPORT](crate::ResourceFlags::HAS_MCAST_SUPPORT)).
     ///
-    /// If the provided value is None, CoAP-level keepalive messages will be disabled.
+    /// If `interface` is None, the interface is chosen by the operating system. The group is only
+    /// joined on endpoints that exist at the time of the call.
     ///
-    /// # Panics
-    /// Panics if the provided duration is too large to be provided to libcoap (larger than a
-    /// [libc::c_uint]).
-    pub fn set_keepalive(&self, timeout: Option<Duration>) {
+    /// Responses to multicast requests are delayed by libcoap, see
+    /// [CoapContext::set_default_leisure()].
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::MulticastJoinFailed] if libcoap could not join the group.
+    pub fn join_multicast_group(
+        &mut self,
+        group: IpAddr,
+        interface: Option<&str>,
+    ) -> Result<(), ContextConfigurationError> {
+        let group_name = CString::new(group.to_string()).expect("IP addresses never contain null bytes");
+        let interface = interface
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| ContextConfigurationError::MulticastJoinFailed(group))?;
         // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
-        // deleted until the CoapContextInner is dropped.
-        unsafe {
-            coap_context_set_keepalive(
-                self.inner.borrow().raw_context,
-                timeout.map_or(0, |v| {
-                    v.as_secs()
-                        .try_into()
-                        .expect("provided keepalive time is too large for libcoap (> c_uint)")
-                }),
+        // deleted until the CoapContextInner is dropped, the strings are valid for the duration of
+        // the call.
+        let result = unsafe {
+            coap_join_mcast_group_intf(
+                self.inner.borrow_mut().raw_context,
+                group_name.as_ptr(),
+                interface.as_ref().map_or(std::ptr::null(), |v| v.as_ptr()),
             )
         };
+        match result {
+            0 => Ok(()),
+            _ => Err(ContextConfigurationError::MulticastJoinFailed(group)),
+        }
+    }
+
+    /// Registers an option number that is not known to libcoap, so that requests containing it
+    /// are passed to the request handlers of resources.
+    ///
+    /// Requests containing an unknown critical option (i.e., one with an odd option number, see
+    /// [RFC 7252, Section 5.4.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.4.1))
+    /// are otherwise rejected by libcoap with a 4.02 (Bad Option) response.
+    /// Registered options can be read from the request using
+    /// [CoapOptionSet::iter_raw()](crate::message::CoapOptionSet::iter_raw).
+    ///
+    /// Registering an option number multiple times has no further effect. libcoap does not
+    /// support unregistering options, and there is no way to disable the checking of critical
+    /// options altogether.
+    ///
+    /// # Errors
+    /// libcoap can only store a limited number of registered options (six options with a number
+    /// below 256 and two with a larger number). Returns
+    /// [ContextConfigurationError::TooManyCustomOptions] if this limit has been reached.
+    pub fn register_custom_option(&mut self, number: CoapOptionNum) -> Result<(), ContextConfigurationError> {
+        self.register_custom_options([number])
+    }
+
+    /// Registers multiple option numbers that are not known to libcoap, see
+    /// [CoapContext::register_custom_option()].
+    ///
+    /// # Errors
+    /// Returns [ContextConfigurationError::TooManyCustomOptions] if not all of the given options
+    /// can be registered, in which case none of them are.
+    pub fn register_custom_options<I: IntoIterator<Item = CoapOptionNum>>(
+        &mut self,
+        numbers: I,
+    ) -> Result<(), ContextConfigurationError> {
+        let mut inner = self.inner.borrow_mut();
+        let mut new_options: Vec<CoapOptionNum> = Vec::new();
+        for number in numbers {
+            if inner.custom_options.contains(&number) || new_options.contains(&number) {
+                continue;
+            }
+            let (is_long, limit) = match number {
+                0..=255 => (false, COAP_OPT_FILTER_SHORT as usize),
+                _ => (true, COAP_OPT_FILTER_LONG as usize),
+            };
+            let used = inner
+                .custom_options
+                .iter()
+                .chain(new_options.iter())
+                .filter(|v| (**v > 255) == is_long)
+                .count();
+            if used >= limit {
+                return Err(ContextConfigurationError::TooManyCustomOptions(number));
+            }
+            new_options.push(number);
+        }
+        for number in new_options {
+            // SAFETY: Properly initialized CoapContext always has a valid raw_context that is not
+            // deleted until the CoapContextInner is dropped, we have checked above that the
+            // option filter has space for the option.
+            unsafe { coap_register_option(inner.raw_context, number) };
+            inner.custom_options.push(number);
+        }
+        Ok(())
+    }
+
+    /// Returns the option numbers that were registered using
+    /// [CoapContext::register_custom_option()].
+    pub fn custom_options(&self) -> Vec<CoapOptionNum> {
+        self.inner.borrow().custom_options.clone()
     }
 
     /// Returns a reference to the raw context contained in this struct.
@@ -602,8 +5055,6 @@ impl CoapContext<'_> {
     /// - Calling `coap_free_context()` on this context (for obvious reasons, this will probably
     ///   cause a segfault if you don't immediately [std::mem::forget()] the CoapContext and never
     ///   use anything related to the context again, but why would you do that?)
-    // Kept here for consistency, even though it is unused.
-    #[allow(unused)]
     pub(crate) unsafe fn as_raw_context(&self) -> &coap_context_t {
         // SAFETY: raw_context is checked to be a valid pointer on struct instantiation, cannot be
         // freed by anything outside of here (assuming the contract of this function is kept), and
@@ -633,9 +5084,265 @@ impl CoapContext<'_> {
         &mut *self.inner.borrow_mut().raw_context
     }
 
+    /// Calls the given function (which is expected to create a client session) with the raw
+    /// context, ensuring that the block-wise transfer mode of the raw context is [BLOCK_MODE] while
+    /// the function is running.
+    ///
+    /// libcoap copies the block-wise transfer mode of the context into each session on creation.
+    /// If the context uses [STREAMING_BLOCK_MODE] for its server sessions, client sessions would
+    /// otherwise receive each block of block-wise responses individually.
+    pub(crate) fn with_client_block_mode<R, F: FnOnce(*mut coap_context_t) -> R>(&mut self, f: F) -> R {
+        // The inner context must not be borrowed while calling the function, as creating sessions
+        // may invoke callbacks.
+        let (raw_context, block_mode) = {
+            let inner = self.inner.borrow();
+            (inner.raw_context, inner.block_mode)
+        };
+        if block_mode == BLOCK_MODE {
+            return f(raw_context);
+        }
+        // SAFETY: raw context is valid.
+        unsafe { coap_context_set_block_mode(raw_context, BLOCK_MODE) };
+        let result = f(raw_context);
+        // SAFETY: raw context is valid.
+        unsafe { coap_context_set_block_mode(raw_context, block_mode) };
+        result
+    }
+
     // TODO coap_session_get_by_peer
 }
 
+/// Returns whether the given endpoint is the one the given server-side session was created on.
+fn endpoint_serves_session(endpoint: &CoapEndpoint, session: &CoapServerSession) -> bool {
+    match (endpoint.local_addr(), local_socket_addr(session)) {
+        (Some(endpoint_addr), Some(session_addr)) => {
+            endpoint.proto() == session.proto()
+                && endpoint_addr.port() == session_addr.port()
+                && (endpoint_addr.ip().is_unspecified() || endpoint_addr.ip() == session_addr.ip())
+        },
+        _ => false,
+    }
+}
+
+/// Creates the `/.well-known/core` resource for contexts with resources restricted to hosts (see
+/// [CoapContext::add_resource()]), which only lists the resources visible for the requested host.
+fn new_well_known_core_resource() -> CoapResource<()> {
+    CoapResource::builder(WELL_KNOWN_CORE_PATH, ())
+        .get(|_, session, request, mut response| {
+            // SAFETY: The session belongs to a context that is currently performing IO, so the raw
+            // context is valid.
+            let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+            let host = request
+                .uri()
+                .host()
+                .map(|host| String::from_utf8_lossy(host).into_owned());
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            response.set_content_format(Some(CoapContentFormat::LinkFormat.into()));
+            response.set_data(Some(context.link_format(host.as_deref(), |_, _| true)));
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+        })
+        .build()
+        .expect("default resource flags are valid")
+}
+
+/// Returns the description of the given raw resource in the CoRE link format (including its
+/// attributes), or None if libcoap fails to generate it.
+///
+/// # Safety
+/// The raw resource must be valid.
+unsafe fn raw_resource_link(raw_resource: *mut coap_resource_t) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; 256];
+    loop {
+        let mut len = buf.len();
+        let mut offset = 0;
+        let status = coap_print_link(raw_resource, buf.as_mut_ptr(), &mut len, &mut offset);
+        if status & COAP_PRINT_STATUS_ERROR as coap_print_status_t != 0 {
+            return None;
+        }
+        if status & COAP_PRINT_STATUS_TRUNC as coap_print_status_t == 0 {
+            buf.truncate(len);
+            return Some(buf);
+        }
+        buf.resize(buf.len() * 2, 0);
+    }
+}
+
+/// Replaces the target of the given link as generated by libcoap (which contains the raw path of
+/// the resource, see [CoapResourcePath::to_raw()]) with the percent-encoded path of the resource.
+fn encode_link_target(link: Vec<u8>, path: &CoapResourcePath) -> Vec<u8> {
+    if !path.needs_encoding() {
+        return link;
+    }
+    let Some(end) = link.iter().position(|c| *c == b'>') else {
+        return link;
+    };
+    let mut encoded = format!("</{}", path).into_bytes();
+    encoded.extend_from_slice(&link[end..]);
+    encoded
+}
+
+/// Returns whether the given server-side session belongs to the given draining endpoint (and not
+/// to the endpoint replacing it).
+fn is_draining_endpoint_session(
+    inner: &CoapContextInner,
+    draining: &DrainingEndpoint,
+    session: &CoapServerSession,
+) -> bool {
+    let serves = |handle: CoapEndpointHandle| {
+        inner
+            .endpoints
+            .iter()
+            .find(|v| v.handle() == handle)
+            .is_some_and(|v| endpoint_serves_session(v, session))
+    };
+    serves(draining.handle) && !serves(draining.replacement)
+}
+
+/// Handle to a resource that has been added to a context, returned by
+/// [CoapContext::add_resource()].
+///
+/// Unlike [CoapResource], handles do not keep the resource (or the context) alive, so they can be
+/// moved into the request handlers of other resources without creating reference cycles. Handles
+/// are only valid while the resource is part of the context: once it has been removed or the
+/// context has been dropped, all functions return [ResourceRemoved].
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::{message::CoapMessageCommon, protocol::CoapResponseCode, session::CoapSessionCommon};
+/// use libcoap_rs::{CoapContext, CoapResource};
+///
+/// let mut context = CoapContext::new().unwrap();
+/// let sensor = context.add_resource(CoapResource::builder("sensors/1", 0u32).build().unwrap());
+/// context.add_resource(
+///     CoapResource::builder("sensors/1/decommission", ())
+///         .post(move |_, session, _request, mut response| {
+///             let code = match sensor.clone().remove() {
+///                 Ok(()) => CoapResponseCode::Deleted,
+///                 Err(_) => CoapResponseCode::NotFound,
+///             };
+///             response.set_code(code);
+///             session.send(response).unwrap();
+///         })
+///         .build()
+///         .unwrap(),
+/// );
+/// ```
+#[derive(Debug)]
+pub struct CoapResourceHandle<'a, D: Any + ?Sized + Debug> {
+    context: CoapLendableFfiWeakCell<CoapContextInner<'a>>,
+    resource: CoapFfiWeakCell<CoapResourceInner<D>>,
+}
+
+impl<'a, D: Any + ?Sized + Debug> CoapResourceHandle<'a, D> {
+    /// Returns the resource this handle refers to.
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource has been removed from its context or if the
+    /// context has been dropped.
+    pub fn resource(&self) -> Result<CoapResource<D>, ResourceRemoved> {
+        self.context.upgrade().ok_or(ResourceRemoved)?;
+        let resource = self.resource.upgrade().map(CoapResource::from).ok_or(ResourceRemoved)?;
+        if resource.is_removed() {
+            return Err(ResourceRemoved);
+        }
+        Ok(resource)
+    }
+
+    /// Returns whether the resource has been removed from its context (or the context has been
+    /// dropped), i.e., whether all other functions of this handle fail.
+    pub fn is_removed(&self) -> bool {
+        self.resource().is_err()
+    }
+
+    /// Notifies the observers of the resource about changes to it, see
+    /// [CoapResource::notify_observers()].
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource is no longer part of its context.
+    pub fn notify_observers(&self) -> Result<bool, ResourceRemoved> {
+        Ok(self.resource()?.notify_observers())
+    }
+
+    /// Adds a link attribute to the description of the resource, see
+    /// [CoapResource::add_attribute()].
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource is no longer part of its context.
+    pub fn add_attribute(&self, name: &str, value: Option<&str>) -> Result<(), ResourceRemoved> {
+        self.resource()?.add_attribute(name, value);
+        Ok(())
+    }
+
+    /// Sets whether the resource can be observed, see [CoapResource::set_get_observable()].
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource is no longer part of its context.
+    pub fn set_get_observable(&self, observable: bool) -> Result<(), ResourceRemoved> {
+        self.resource()?.set_get_observable(observable);
+        Ok(())
+    }
+
+    /// Calls the given function with a reference to the user data of the resource and returns its
+    /// result.
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource is no longer part of its context.
+    ///
+    /// # Panics
+    /// Panics if the user data is currently borrowed mutably (see [CoapResource::user_data()]).
+    pub fn with_user_data<R, F: FnOnce(&D) -> R>(&self, f: F) -> Result<R, ResourceRemoved> {
+        let resource = self.resource()?;
+        let user_data = resource.user_data();
+        Ok(f(&user_data))
+    }
+
+    /// Calls the given function with a mutable reference to the user data of the resource and
+    /// returns its result.
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource is no longer part of its context.
+    ///
+    /// # Panics
+    /// Panics if the user data is currently borrowed (see [CoapResource::user_data_mut()]).
+    pub fn with_user_data_mut<R, F: FnOnce(&mut D) -> R>(&self, f: F) -> Result<R, ResourceRemoved> {
+        let resource = self.resource()?;
+        let mut user_data = resource.user_data_mut();
+        Ok(f(&mut user_data))
+    }
+
+    /// Removes the resource from its context.
+    ///
+    /// Requests for the path of the resource are passed to the remaining resources for the path
+    /// (e.g., ones restricted to other hosts) or answered with 4.04 (Not Found) afterwards. Clones
+    /// of the resource that are still held by the application remain usable, but are no longer
+    /// served.
+    ///
+    /// This function may be called from within request handlers (including those of the removed
+    /// resource itself), in which case libcoap keeps passing requests to the resource until the
+    /// current call to [CoapContext::do_io()] returns; those requests are answered with 4.04 (Not
+    /// Found) as well.
+    ///
+    /// # Errors
+    /// Returns [ResourceRemoved] if the resource has already been removed or if the context has
+    /// been dropped.
+    pub fn remove(self) -> Result<(), ResourceRemoved> {
+        let resource = self.resource()?;
+        let context = self.context.upgrade().ok_or(ResourceRemoved)?;
+        let mut inner = context.borrow_mut();
+        CoapContext::remove_resource(&mut inner, resource)
+    }
+}
+
+impl<D: Any + ?Sized + Debug> Clone for CoapResourceHandle<'_, D> {
+    fn clone(&self) -> Self {
+        CoapResourceHandle {
+            context: self.context.clone(),
+            resource: self.resource.clone(),
+        }
+    }
+}
+
 impl Drop for CoapContextInner<'_> {
     fn drop(&mut self) {
         // Disable event handler before dropping, as we would otherwise need to lend our reference
@@ -648,31 +5355,90 @@ impl Drop for CoapContextInner<'_> {
         // documentation.
         unsafe {
             coap_register_event_handler(self.raw_context, None);
+            coap_register_pong_handler(self.raw_context, None);
+            coap_register_nack_handler(self.raw_context, None);
+            if self.ownership == Ownership::Borrowed {
+                coap_register_response_handler(self.raw_context, None);
+            }
         }
-        for session in std::mem::take(&mut self.server_sessions).into_iter() {
-            session.drop_exclusively();
+        // Keep the tracked state of persisted observe relationships instead of reporting them as
+        // deleted, so that they can be restored later on.
+        if self.persistence_enabled {
+            // SAFETY: Raw context is valid, tracking is stopped before the handler is dropped.
+            unsafe { coap_persist_stop(self.raw_context) };
         }
-        // Clear endpoints because coap_free_context() would free their underlying raw structs.
-        self.endpoints.clear();
+        // Drop application data first, as it might contain values that refer to the raw context
+        // (such as sessions).
+        self.app_data = None;
+        // Release the sessions kept alive by session guards while the raw context still exists.
+        #[cfg(any(unix, windows))]
+        self.session_guards.clear();
+        // Operations queued by handles can no longer be executed.
+        #[cfg(any(unix, windows))]
+        if let Some(shared) = &self.handle_shared {
+            shared.close();
+        }
+        // Release the sessions of any deferred requests while the raw context still exists.
+        let removed_resources = std::mem::take(&mut self.removed_resources);
+        let removed_states = removed_resources.iter().map(|(_, state)| Rc::clone(state));
+        for state in std::mem::take(&mut self.resource_notify_states)
+            .into_iter()
+            .chain(removed_states)
+        {
+            state.borrow_mut().clear_deferred();
+        }
+        // Same for requests forwarded by the reverse proxy and its sessions with upstream servers.
+        if let Some(reverse_proxy) = self.reverse_proxy.take() {
+            reverse_proxy.borrow_mut().clear();
+        }
+        self.session_pool.clear();
+        // Server sessions may still be referenced by the application, in which case their raw
+        // sessions (and therefore the endpoints and raw context) are released once the last of
+        // these references is dropped.
+        self.server_sessions.clear();
         // Extract reference to CoapContextInner from raw context and drop it.
         // SAFETY: Value is set upon construction of the inner context and never deleted.
         unsafe {
             std::mem::drop(CoapLendableFfiWeakCell::<CoapContextInner>::from_raw_box(
                 coap_get_app_data(self.raw_context) as *mut CoapLendableFfiWeakCell<CoapContextInner>,
-            ))
-        }
-        // Attempt to regain sole ownership over all resources.
-        // As long as [CoapResource::into_inner] isn't used and we haven't given out owned
-        // CoapResource instances whose raw resource is attached to the raw context, this should
-        // never fail.
-        std::mem::take(&mut self.resources)
-            .into_iter()
-            .for_each(UntypedCoapResource::drop_inner_exclusive);
-        // SAFETY: We have already dropped all endpoints and contexts which could be freed alongside
-        // the actual context, and our raw context reference is valid (as long as the contracts of
-        // [as_mut_raw_context()] and [as_mut_context()] are fulfilled).
-        unsafe {
-            coap_free_context(self.raw_context);
+            ));
+            coap_set_app_data(self.raw_context, std::ptr::null_mut());
         }
+        // Handlers may hold handles to other resources (obtained using
+        // [CoapContext::typed_resource_by_uri_path()]), so they are dropped beforehand. Resources
+        // that are still referenced by the application keep the raw context alive until they are
+        // dropped.
+        self.shared.virtual_host_paths.borrow_mut().clear();
+        let mut resources = std::mem::take(&mut self.resources);
+        resources.extend(removed_resources.into_iter().map(|(resource, _)| resource));
+        resources.iter().for_each(|resource| resource.drop_handlers());
+        std::mem::drop(resources);
+        // The endpoints and raw context are released once neither this context nor any of its
+        // sessions and resources hold the shared state anymore.
+        *self.shared.teardown.borrow_mut() = Some(DeferredTeardown {
+            // Borrowed raw contexts are freed by their owner.
+            raw_context: (self.ownership == Ownership::Owned).then_some(self.raw_context),
+            endpoints: std::mem::take(&mut self.endpoints),
+            #[cfg(feature = "oscore")]
+            oscore_storage: std::mem::take(&mut self.oscore_storage),
+            _library_guard: self.library_guard.take(),
+        });
     }
 }
+
+/// Sets the delay after which libcoap tries to reconnect client sessions that lost their
+/// connection, or disables reconnecting if `delay` is None (rounding up to full seconds).
+///
+/// # Safety
+/// The provided pointer must point to a valid raw context.
+#[cfg(session_reconnect_support)]
+unsafe fn set_raw_reconnect_delay(raw_context: *mut coap_context_t, delay: Option<Duration>) {
+    let seconds = delay.map_or(0, |delay| {
+        let mut seconds = c_uint::try_from(delay.as_secs()).unwrap_or(c_uint::MAX);
+        if delay.subsec_nanos() > 0 {
+            seconds = seconds.saturating_add(1);
+        }
+        seconds.max(1)
+    });
+    coap_context_set_session_reconnect_time(raw_context, seconds);
+}
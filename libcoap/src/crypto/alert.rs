@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * crypto/alert.rs - (D)TLS alerts reported for failed handshakes.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::fmt::{Display, Formatter};
+
+/// A (D)TLS alert, as defined in [RFC 8446, Section 6](https://datatracker.ietf.org/doc/html/rfc8446#section-6)
+/// (and [RFC 5246, Section 7.2](https://datatracker.ietf.org/doc/html/rfc5246#section-7.2) for
+/// alerts only used by earlier protocol versions).
+///
+/// The [Display] implementation returns the name of the alert as used in the RFCs (e.g.,
+/// `unknown_psk_identity`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CoapTlsAlert {
+    /// `close_notify` (0).
+    CloseNotify,
+    /// `unexpected_message` (10).
+    UnexpectedMessage,
+    /// `bad_record_mac` (20).
+    BadRecordMac,
+    /// `decryption_failed` (21).
+    DecryptionFailed,
+    /// `record_overflow` (22).
+    RecordOverflow,
+    /// `decompression_failure` (30).
+    DecompressionFailure,
+    /// `handshake_failure` (40).
+    HandshakeFailure,
+    /// `bad_certificate` (42).
+    BadCertificate,
+    /// `unsupported_certificate` (43).
+    UnsupportedCertificate,
+    /// `certificate_revoked` (44).
+    CertificateRevoked,
+    /// `certificate_expired` (45).
+    CertificateExpired,
+    /// `certificate_unknown` (46).
+    CertificateUnknown,
+    /// `illegal_parameter` (47).
+    IllegalParameter,
+    /// `unknown_ca` (48).
+    UnknownCa,
+    /// `access_denied` (49).
+    AccessDenied,
+    /// `decode_error` (50).
+    DecodeError,
+    /// `decrypt_error` (51).
+    DecryptError,
+    /// `protocol_version` (70).
+    ProtocolVersion,
+    /// `insufficient_security` (71).
+    InsufficientSecurity,
+    /// `internal_error` (80).
+    InternalError,
+    /// `inappropriate_fallback` (86).
+    InappropriateFallback,
+    /// `user_canceled` (90).
+    UserCanceled,
+    /// `no_renegotiation` (100).
+    NoRenegotiation,
+    /// `missing_extension` (109).
+    MissingExtension,
+    /// `unsupported_extension` (110).
+    UnsupportedExtension,
+    /// `unrecognized_name` (112).
+    UnrecognizedName,
+    /// `bad_certificate_status_response` (113).
+    BadCertificateStatusResponse,
+    /// `unknown_psk_identity` (115).
+    UnknownPskIdentity,
+    /// `certificate_required` (116).
+    CertificateRequired,
+    /// `no_application_protocol` (120).
+    NoApplicationProtocol,
+    /// An alert that is unknown to this version of libcoap-rs, identified by its alert
+    /// description value.
+    Other(u8),
+}
+
+/// Alert description values and names of all known alerts.
+const KNOWN_ALERTS: [(CoapTlsAlert, u8, &str); 30] = [
+    (CoapTlsAlert::CloseNotify, 0, "close_notify"),
+    (CoapTlsAlert::UnexpectedMessage, 10, "unexpected_message"),
+    (CoapTlsAlert::BadRecordMac, 20, "bad_record_mac"),
+    (CoapTlsAlert::DecryptionFailed, 21, "decryption_failed"),
+    (CoapTlsAlert::RecordOverflow, 22, "record_overflow"),
+    (CoapTlsAlert::DecompressionFailure, 30, "decompression_failure"),
+    (CoapTlsAlert::HandshakeFailure, 40, "handshake_failure"),
+    (CoapTlsAlert::BadCertificate, 42, "bad_certificate"),
+    (CoapTlsAlert::UnsupportedCertificate, 43, "unsupported_certificate"),
+    (CoapTlsAlert::CertificateRevoked, 44, "certificate_revoked"),
+    (CoapTlsAlert::CertificateExpired, 45, "certificate_expired"),
+    (CoapTlsAlert::CertificateUnknown, 46, "certificate_unknown"),
+    (CoapTlsAlert::IllegalParameter, 47, "illegal_parameter"),
+    (CoapTlsAlert::UnknownCa, 48, "unknown_ca"),
+    (CoapTlsAlert::AccessDenied, 49, "access_denied"),
+    (CoapTlsAlert::DecodeError, 50, "decode_error"),
+    (CoapTlsAlert::DecryptError, 51, "decrypt_error"),
+    (CoapTlsAlert::ProtocolVersion, 70, "protocol_version"),
+    (CoapTlsAlert::InsufficientSecurity, 71, "insufficient_security"),
+    (CoapTlsAlert::InternalError, 80, "internal_error"),
+    (CoapTlsAlert::InappropriateFallback, 86, "inappropriate_fallback"),
+    (CoapTlsAlert::UserCanceled, 90, "user_canceled"),
+    (CoapTlsAlert::NoRenegotiation, 100, "no_renegotiation"),
+    (CoapTlsAlert::MissingExtension, 109, "missing_extension"),
+    (CoapTlsAlert::UnsupportedExtension, 110, "unsupported_extension"),
+    (CoapTlsAlert::UnrecognizedName, 112, "unrecognized_name"),
+    (
+        CoapTlsAlert::BadCertificateStatusResponse,
+        113,
+        "bad_certificate_status_response",
+    ),
+    (CoapTlsAlert::UnknownPskIdentity, 115, "unknown_psk_identity"),
+    (CoapTlsAlert::CertificateRequired, 116, "certificate_required"),
+    (CoapTlsAlert::NoApplicationProtocol, 120, "no_application_protocol"),
+];
+
+impl CoapTlsAlert {
+    /// Returns the alert with the given alert description value.
+    pub fn from_code(code: u8) -> CoapTlsAlert {
+        KNOWN_ALERTS
+            .iter()
+            .find(|(_, known_code, _)| *known_code == code)
+            .map_or(CoapTlsAlert::Other(code), |(alert, ..)| *alert)
+    }
+
+    /// Returns the alert description value of this alert.
+    pub fn code(&self) -> u8 {
+        match self {
+            CoapTlsAlert::Other(code) => *code,
+            alert => KNOWN_ALERTS
+                .iter()
+                .find(|(known_alert, ..)| known_alert == alert)
+                .map(|(_, code, _)| *code)
+                .unwrap(),
+        }
+    }
+
+    /// Extracts the alert reported in the given log message of the TLS library integration of
+    /// libcoap, if any.
+    ///
+    /// libcoap does not provide an API for alerts, but its TLS library integrations log them
+    /// (depending on the library) either with their numeric value (e.g., `Alert '115'`) or with
+    /// their name (e.g., `unknown PSK identity`).
+    pub(crate) fn from_log_message(message: &str) -> Option<CoapTlsAlert> {
+        let normalized = message.to_ascii_lowercase().replace(['_', '-'], " ");
+        if let Some(code) = normalized
+            .split_once("alert '")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .and_then(|(code, _)| code.parse::<u8>().ok())
+        {
+            return Some(CoapTlsAlert::from_code(code));
+        }
+        if !normalized.contains("alert") {
+            return None;
+        }
+        // The longest matching name is the most specific one.
+        KNOWN_ALERTS
+            .iter()
+            .filter(|(_, _, name)| normalized.contains(&name.replace('_', " ")))
+            .max_by_key(|(_, _, name)| name.len())
+            .map(|(alert, ..)| *alert)
+    }
+}
+
+impl Display for CoapTlsAlert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoapTlsAlert::Other(code) => write!(f, "alert {}", code),
+            alert => f.write_str(
+                KNOWN_ALERTS
+                    .iter()
+                    .find(|(known_alert, ..)| known_alert == alert)
+                    .map(|(_, _, name)| *name)
+                    .unwrap(),
+            ),
+        }
+    }
+}
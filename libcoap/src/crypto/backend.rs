@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * crypto/backend.rs - Information on the TLS library used by libcoap.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::fmt::{Display, Formatter};
+
+use libcoap_sys::{
+    coap_dtls_is_supported, coap_dtls_pki_is_supported, coap_dtls_psk_is_supported, coap_dtls_rpk_is_supported,
+    coap_get_tls_library_version, coap_oscore_is_supported, coap_proto_t, coap_tcp_is_supported, coap_tls_is_supported,
+    coap_tls_library_t, coap_ws_is_supported, coap_wss_is_supported,
+};
+
+use crate::startup::ensure_coap_started;
+
+/// TLS library that libcoap was built with.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TlsLibrary {
+    /// libcoap was built without a TLS library, i.e., without support for encryption.
+    NoTls,
+    /// TinyDTLS
+    TinyDtls,
+    /// OpenSSL
+    OpenSsl,
+    /// GnuTLS
+    GnuTls,
+    /// Mbed TLS
+    MbedTls,
+    /// A TLS library that is unknown to this version of libcoap-rs.
+    Unknown,
+}
+
+impl From<coap_tls_library_t> for TlsLibrary {
+    fn from(raw_library: coap_tls_library_t) -> Self {
+        match raw_library {
+            coap_tls_library_t::COAP_TLS_LIBRARY_NOTLS => TlsLibrary::NoTls,
+            coap_tls_library_t::COAP_TLS_LIBRARY_TINYDTLS => TlsLibrary::TinyDtls,
+            coap_tls_library_t::COAP_TLS_LIBRARY_OPENSSL => TlsLibrary::OpenSsl,
+            coap_tls_library_t::COAP_TLS_LIBRARY_GNUTLS => TlsLibrary::GnuTls,
+            coap_tls_library_t::COAP_TLS_LIBRARY_MBEDTLS => TlsLibrary::MbedTls,
+            _ => TlsLibrary::Unknown,
+        }
+    }
+}
+
+impl Display for TlsLibrary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TlsLibrary::NoTls => "none",
+            TlsLibrary::TinyDtls => "TinyDTLS",
+            TlsLibrary::OpenSsl => "OpenSSL",
+            TlsLibrary::GnuTls => "GnuTLS",
+            TlsLibrary::MbedTls => "Mbed TLS",
+            TlsLibrary::Unknown => "unknown",
+        })
+    }
+}
+
+/// Version number of a TLS library.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TlsVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl TlsVersion {
+    /// Decodes a raw version number of the given library as reported by libcoap.
+    ///
+    /// Each library uses its own encoding, see `coap_get_tls_library_version(3)`.
+    fn from_raw(library: TlsLibrary, raw_version: u64) -> Option<TlsVersion> {
+        let byte = |shift: u32| ((raw_version >> shift) & 0xff) as u8;
+        match library {
+            // 0xMNN00PP0 for OpenSSL >= 3.0, 0xMNNFFPPS (with FF being the patch version) for
+            // older versions.
+            TlsLibrary::OpenSsl => {
+                let major = ((raw_version >> 28) & 0xf) as u8;
+                let patch = if major >= 3 { byte(4) } else { byte(12) };
+                Some(TlsVersion {
+                    major,
+                    minor: byte(20),
+                    patch,
+                })
+            },
+            // 0xMMNNPP00
+            TlsLibrary::MbedTls => Some(TlsVersion {
+                major: byte(24),
+                minor: byte(16),
+                patch: byte(8),
+            }),
+            // 0xMMNNPP
+            TlsLibrary::GnuTls | TlsLibrary::TinyDtls => Some(TlsVersion {
+                major: byte(16),
+                minor: byte(8),
+                patch: byte(0),
+            }),
+            TlsLibrary::NoTls | TlsLibrary::Unknown => None,
+        }
+    }
+}
+
+impl Display for TlsVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Information on the TLS library libcoap was built with and the features it supports.
+///
+/// Obtained using [tls_backend()].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TlsBackend {
+    library: TlsLibrary,
+    version: Option<TlsVersion>,
+    built_version: Option<TlsVersion>,
+}
+
+impl TlsBackend {
+    /// Returns the TLS library libcoap was built with.
+    pub fn library(&self) -> TlsLibrary {
+        self.library
+    }
+
+    /// Returns the version of the TLS library that is used at runtime, or None if libcoap was
+    /// built without a TLS library (or the version could not be decoded).
+    pub fn version(&self) -> Option<TlsVersion> {
+        self.version
+    }
+
+    /// Returns the version of the TLS library that libcoap was compiled against, which may differ
+    /// from [TlsBackend::version()] if the TLS library is linked dynamically.
+    pub fn built_version(&self) -> Option<TlsVersion> {
+        self.built_version
+    }
+
+    /// Returns whether DTLS is supported.
+    pub fn dtls_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_dtls_is_supported() == 1 }
+    }
+
+    /// Returns whether TLS (i.e., CoAP over TCP with TLS) is supported.
+    pub fn tls_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_tls_is_supported() == 1 }
+    }
+
+    /// Returns whether CoAP over WebSockets secured using TLS is supported.
+    pub fn wss_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_wss_is_supported() == 1 }
+    }
+
+    /// Returns whether OSCORE is supported.
+    pub fn oscore_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_oscore_is_supported() == 1 }
+    }
+
+    /// Returns whether (D)TLS using pre-shared keys is supported.
+    pub fn psk_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_dtls_psk_is_supported() == 1 }
+    }
+
+    /// Returns whether (D)TLS using a public key infrastructure is supported.
+    pub fn pki_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_dtls_pki_is_supported() == 1 }
+    }
+
+    /// Returns whether (D)TLS using raw public keys is supported.
+    pub fn rpk_supported(&self) -> bool {
+        // SAFETY: Only queries compile-time information of libcoap.
+        unsafe { coap_dtls_rpk_is_supported() == 1 }
+    }
+
+    /// Returns whether libcoap invokes additional TLS setup hooks for PKI/RPK sessions with this
+    /// library (see `PkiRpkContextBuilder::tls_setup_hook()`).
+    ///
+    /// This is only the case for OpenSSL and GnuTLS.
+    pub fn tls_setup_hook_supported(&self) -> bool {
+        matches!(self.library, TlsLibrary::OpenSsl | TlsLibrary::GnuTls)
+    }
+
+    /// Returns whether this library verifies DTLS handshakes statelessly using a cookie exchange
+    /// before any per-client state is allocated.
+    ///
+    /// With stateless verification, a server answers the initial ClientHello with a
+    /// HelloVerifyRequest containing a cookie and only creates a session once the client repeats
+    /// the ClientHello with this cookie, which prevents clients with spoofed source addresses from
+    /// allocating server state ([RFC 6347, Section 4.2.1](https://datatracker.ietf.org/doc/html/rfc6347#section-4.2.1)).
+    ///
+    /// libcoap always performs the cookie exchange if the library supports it, deployments that
+    /// rely on it can use
+    /// [CoapContext::require_handshake_cookie()](crate::CoapContext::require_handshake_cookie) to
+    /// ensure that this is the case. Stateless verification is provided by OpenSSL, GnuTLS and
+    /// TinyDTLS. With other libraries (e.g., Mbed TLS), handshake state is allocated for each
+    /// ClientHello (limited by
+    /// [CoapContext::set_max_handshake_sessions()](crate::CoapContext::set_max_handshake_sessions)),
+    /// so deployments exposed to the internet should be protected against spoofed handshakes by
+    /// other means.
+    pub fn stateless_handshake_verification_supported(&self) -> bool {
+        matches!(
+            self.library,
+            TlsLibrary::OpenSsl | TlsLibrary::GnuTls | TlsLibrary::TinyDtls
+        )
+    }
+}
+
+impl Display for TlsBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.library)?;
+        if let Some(version) = self.version {
+            write!(f, " {}", version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns information on the TLS library libcoap was built with.
+///
+/// Note that the available features also depend on the features libcoap-rs was compiled with: For
+/// instance, even if [TlsBackend::psk_supported()] returns true, PSK can only be used if the
+/// `dtls-psk` feature is enabled.
+pub fn tls_backend() -> TlsBackend {
+    ensure_coap_started();
+    // SAFETY: coap_get_tls_library_version() always returns a pointer to a static struct.
+    let raw_version = unsafe { &*coap_get_tls_library_version() };
+    let library = TlsLibrary::from(raw_version.type_);
+    TlsBackend {
+        library,
+        version: TlsVersion::from_raw(library, raw_version.version),
+        built_version: TlsVersion::from_raw(library, raw_version.built_version),
+    }
+}
+
+/// Returns whether libcoap supports the given transport protocol.
+pub(crate) fn transport_supported(proto: coap_proto_t) -> bool {
+    // SAFETY: Only queries compile-time information of libcoap.
+    unsafe {
+        match proto {
+            coap_proto_t::COAP_PROTO_DTLS => coap_dtls_is_supported() == 1,
+            coap_proto_t::COAP_PROTO_TCP => coap_tcp_is_supported() == 1,
+            coap_proto_t::COAP_PROTO_TLS => coap_tls_is_supported() == 1,
+            coap_proto_t::COAP_PROTO_WS => coap_ws_is_supported() == 1,
+            coap_proto_t::COAP_PROTO_WSS => coap_wss_is_supported() == 1,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * crypto/info.rs - Information on the security parameters of established sessions.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::ffi::c_void;
+
+use crate::{
+    crypto::{TlsLibrary, TlsVersion},
+    types::CoapProtocol,
+};
+
+/// Security parameters of a session, as returned by
+/// [CoapSessionCommon::crypto_info()](crate::session::CoapSessionCommon::crypto_info).
+///
+/// libcoap does not expose the negotiated (D)TLS protocol version and cipher suite. If these are
+/// required, they can be queried from the TLS library using the raw TLS session (see
+/// [CoapCryptoSessionInfo::raw_tls_session()]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoapCryptoSessionInfo {
+    pub(crate) proto: CoapProtocol,
+    pub(crate) tls_library: TlsLibrary,
+    pub(crate) tls_library_version: Option<TlsVersion>,
+    pub(crate) raw_tls_session: *mut c_void,
+    #[cfg(feature = "dtls-psk")]
+    pub(crate) psk_identity: Option<Box<[u8]>>,
+    #[cfg(feature = "dtls-psk")]
+    pub(crate) psk_hint: Option<Box<[u8]>>,
+    #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+    pub(crate) peer_certificate: Option<Box<[u8]>>,
+    pub(crate) oscore: bool,
+}
+
+impl CoapCryptoSessionInfo {
+    /// Returns the transport protocol of the session (e.g., [CoapProtocol::Dtls]).
+    pub fn proto(&self) -> CoapProtocol {
+        self.proto
+    }
+
+    /// Returns whether the transport of the session is encrypted using (D)TLS.
+    pub fn is_transport_secure(&self) -> bool {
+        self.proto.is_secure()
+    }
+
+    /// Returns the TLS library that secures the transport of the session.
+    pub fn tls_library(&self) -> TlsLibrary {
+        self.tls_library
+    }
+
+    /// Returns the version of the TLS library that secures the transport of the session.
+    pub fn tls_library_version(&self) -> Option<TlsVersion> {
+        self.tls_library_version
+    }
+
+    /// Returns a human-readable description of the TLS library (e.g., `OpenSSL 3.0.2`).
+    pub fn tls_library_description(&self) -> String {
+        match self.tls_library_version {
+            Some(version) => format!("{} {}", self.tls_library, version),
+            None => self.tls_library.to_string(),
+        }
+    }
+
+    /// Returns the library-specific TLS session object (e.g., an `SSL*` for OpenSSL or a
+    /// `gnutls_session_t` for GnuTLS, see [CoapCryptoSessionInfo::tls_library()]), or a null
+    /// pointer if the (D)TLS session has not been set up.
+    ///
+    /// The pointer is only valid for as long as the (D)TLS session of the CoAP session exists,
+    /// which may end at any time the context performs IO. It must not be used to modify the
+    /// TLS session.
+    pub fn raw_tls_session(&self) -> *mut c_void {
+        self.raw_tls_session
+    }
+
+    /// Returns the PSK identity used by the client of the session (if PSKs are used).
+    #[cfg(feature = "dtls-psk")]
+    pub fn psk_identity(&self) -> Option<&[u8]> {
+        self.psk_identity.as_deref()
+    }
+
+    /// Returns the PSK identity hint provided by the server of the session (if any).
+    #[cfg(feature = "dtls-psk")]
+    pub fn psk_hint(&self) -> Option<&[u8]> {
+        self.psk_hint.as_deref()
+    }
+
+    /// Returns the DER-encoded certificate of the peer (for PKI) or its DER-encoded public key
+    /// (for RPK), as presented during the (D)TLS handshake.
+    ///
+    /// This is only available if the certificate was validated by libcoap during the handshake,
+    /// the application can use it to extract the fields it requires (e.g., the subject).
+    #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+    pub fn peer_certificate(&self) -> Option<&[u8]> {
+        self.peer_certificate.as_deref()
+    }
+
+    /// Returns whether messages of the session are protected using OSCORE.
+    pub fn is_oscore(&self) -> bool {
+        self.oscore
+    }
+}
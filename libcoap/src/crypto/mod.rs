@@ -17,9 +17,8 @@
 //! - (D)TLS using a public key infrastructure (PKI): Uses asymmetric key pairs signed by a
 //!   certificate authority, which are authenticated by the TLS library using a set of
 //!   pre-configured (or provided) root certificate authorities (the way most of the internet works).
-//! - OSCORE (*unsupported by libcoap-rs, see
-//!   [issue #23](https://github.com/namib-project/libcoap-rs/issues/23)*): Uses Object Security for
-//!   Constrained RESTful Environments (OSCORE, [RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613)) to encrypt messages on the application
+//! - OSCORE (*only supported on top of DTLS by libcoap-rs, see the `oscore` module*): Uses Object
+//!   Security for Constrained RESTful Environments (OSCORE, [RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613)) to encrypt messages on the application
 //!   layer.
 //!
 //! # Configuration
@@ -54,13 +53,28 @@
 //! the three DTLS variant features while using a TLS library that does not support this feature
 //! will result in either a compilation error or a panic on when calling [`CoapContext::new`](crate::CoapContext::new),
 //! irrespective of whether you actually use DTLS.
+//!
+//! The TLS library in use and the features it supports can be queried at runtime using
+//! [`tls_backend()`], which is available regardless of the enabled features.
 
+mod alert;
+mod backend;
+mod info;
 #[cfg(any(feature = "dtls-rpk", feature = "dtls-pki"))]
 pub mod pki_rpk;
 #[cfg(feature = "dtls-psk")]
 pub mod psk;
 
-use std::fmt::Debug;
+#[cfg(dtls)]
+use std::{ffi::CString, fmt::Debug, net::IpAddr};
+
+#[cfg(dtls)]
+use crate::error::ClientSniError;
+
+pub use alert::CoapTlsAlert;
+pub(crate) use backend::transport_supported;
+pub use backend::{tls_backend, TlsBackend, TlsLibrary, TlsVersion};
+pub use info::CoapCryptoSessionInfo;
 
 /// Client-side context for cryptography.
 ///
@@ -69,6 +83,7 @@ use std::fmt::Debug;
 ///
 /// The available enum variants depend on the enabled DTLS features (`dtls-psk`, `dtls-pki`, and/or
 /// `dtls-rpk`).
+#[cfg(dtls)]
 #[derive(Clone, Debug)]
 pub enum ClientCryptoContext<'a> {
     /// Context for a client-side DTLS session with pre-shared keys.
@@ -82,3 +97,50 @@ pub enum ClientCryptoContext<'a> {
     #[cfg(feature = "dtls-rpk")]
     Rpk(pki_rpk::PkiRpkContext<'a, pki_rpk::Rpk>),
 }
+
+#[cfg(dtls)]
+impl ClientCryptoContext<'_> {
+    /// Returns a value identifying the credentials of this context.
+    ///
+    /// The identifier is shared by all clones of a context, while separately built contexts have
+    /// different identifiers (even if they contain the same credentials) for as long as they
+    /// exist.
+    pub(crate) fn credentials_id(&self) -> usize {
+        match self {
+            #[cfg(feature = "dtls-psk")]
+            ClientCryptoContext::Psk(ctx) => ctx.credentials_id(),
+            #[cfg(feature = "dtls-pki")]
+            ClientCryptoContext::Pki(ctx) => ctx.credentials_id(),
+            #[cfg(feature = "dtls-rpk")]
+            ClientCryptoContext::Rpk(ctx) => ctx.credentials_id(),
+        }
+    }
+}
+
+/// Validates the given server name indication and converts it into the null-terminated byte
+/// string that is provided to libcoap as `client_sni`.
+///
+/// The server name is not converted implicitly in any way: Internationalized domain names must
+/// already be provided in their ASCII form, and IP address literals are rejected, as RFC 6066
+/// only permits host names in server name indications.
+#[cfg(dtls)]
+pub(crate) fn client_sni_to_raw(client_sni: Vec<u8>) -> Result<Box<[u8]>, ClientSniError> {
+    if client_sni.is_empty() {
+        return Err(ClientSniError::Empty);
+    }
+    if !client_sni.is_ascii() {
+        return Err(ClientSniError::NonAscii);
+    }
+    // Host names are ASCII, so the conversion into a str can only fail for non-ASCII input.
+    let host = std::str::from_utf8(&client_sni).map_err(|_| ClientSniError::NonAscii)?;
+    // IPv6 literals may also be given in their bracketed URI form.
+    let unbracketed = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    if unbracketed.parse::<IpAddr>().is_ok() {
+        return Err(ClientSniError::IpAddress);
+    }
+    // For some reason, client_sni is not immutable in the libcoap structures.
+    // While I don't see any reason why libcoap would modify the string, it is not strictly
+    // forbidden for it to do so, so simply using CString::into_raw() is not an option (as it
+    // does not allow modifications to client_sni that change the length).
+    Ok(CString::new(client_sni)?.into_bytes_with_nul().into_boxed_slice())
+}
@@ -302,16 +302,22 @@ pub use rpk::*;
 
 pub use key::*;
 
-use crate::error::{ContextConfigurationError, SessionCreationError};
-use crate::session::CoapSession;
+use crate::crypto::{client_sni_to_raw, tls_backend, TlsLibrary};
+use crate::error::{ClientSniError, ContextConfigurationError, SessionCreationError, TlsSetupHookError};
+#[cfg(feature = "oscore")]
+use crate::oscore::OscoreConf;
+use crate::session::{record_peer_certificate, CoapSession};
 use crate::types::CoapAddress;
+use crate::unwind::{catch_callback_panic, catch_session_callback_panic};
 use crate::CoapContext;
+#[cfg(feature = "oscore")]
+use libcoap_sys::coap_new_client_session_oscore_pki;
 use libcoap_sys::{
     coap_context_set_pki, coap_context_t, coap_dtls_key_t, coap_dtls_pki_t, coap_new_client_session_pki, coap_proto_t,
     coap_session_t, COAP_DTLS_PKI_SETUP_VERSION,
 };
 use std::cell::RefCell;
-use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString, NulError};
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
 use std::ptr::NonNull;
@@ -414,7 +420,8 @@ impl<'a, KTY: KeyType> PkiRpkContextBuilder<'a, KTY, NonCertVerifying> {
                     is_rpk_not_cert: 0,
                     use_cid: 0,
                     reserved: Default::default(),
-                    validate_cn_call_back: None,
+                    // Always set to record the certificate of the peer, see cn_callback().
+                    validate_cn_call_back: Some(dtls_pki_cn_callback::<KTY>),
                     cn_call_back_arg: std::ptr::null_mut(),
                     validate_sni_call_back: None,
                     sni_call_back_arg: std::ptr::null_mut(),
@@ -426,6 +433,7 @@ impl<'a, KTY: KeyType> PkiRpkContextBuilder<'a, KTY, NonCertVerifying> {
                 provided_key_descriptors: vec![],
                 cn_callback: None,
                 sni_key_provider: None,
+                tls_setup_hook: None,
                 client_sni: None,
             },
             verifying: Default::default(),
@@ -454,12 +462,16 @@ impl<KTY: KeyType, V: CertVerificationMode> PkiRpkContextBuilder<'_, KTY, V> {
     /// Sets the server name indication that should be sent to servers if the built
     /// [`PkiRpkContext`] is used in a client-side session.
     ///
-    /// `client_sni` should be convertible into a byte string that does not contain null bytes.
-    /// Typically, you would provide a `&str` or `String`.
+    /// `client_sni` should be convertible into a byte string containing an ASCII host name.
+    /// Typically, you would provide a `&str` or `String`. Internationalized domain names must be
+    /// converted into their ASCII form (e.g., `xn--bcher-kva.example` instead of `bücher.example`)
+    /// beforehand, as the server name is passed to libcoap as-is.
     ///
     /// # Errors
     ///
-    /// Will return [`NulError`] if the provided byte string contains null bytes.
+    /// Will return a [`ClientSniError`] if the provided byte string is empty, contains null bytes
+    /// or non-ASCII characters, or is an IP address literal (which may not be used as a server
+    /// name indication).
     ///
     /// # Implementation details (informative, not covered by semver guarantees)
     ///
@@ -467,14 +479,8 @@ impl<KTY: KeyType, V: CertVerificationMode> PkiRpkContextBuilder<'_, KTY, V> {
     ///
     /// The provided `client_sni` will be converted into a `Box<[u8]>`, which will be owned and
     /// stored by the built context.
-    pub fn client_sni(mut self, client_sni: impl Into<Vec<u8>>) -> Result<Self, NulError> {
-        // For some reason, client_sni is not immutable here.
-        // While I don't see any reason why libcoap would modify the string, it is not strictly
-        // forbidden for it to do so, so simply using CString::into_raw() is not an option (as it
-        // does not allow modifications to client_sni that change the length).
-        let sni = CString::new(client_sni.into())?
-            .into_bytes_with_nul()
-            .into_boxed_slice();
+    pub fn client_sni(mut self, client_sni: impl Into<Vec<u8>>) -> Result<Self, ClientSniError> {
+        let sni = client_sni_to_raw(client_sni.into())?;
         self.ctx.client_sni = Some(sni);
         self.ctx.raw_cfg.client_sni = self.ctx.client_sni.as_mut().unwrap().as_mut_ptr() as *mut c_char;
         Ok(self)
@@ -599,6 +605,49 @@ impl<'a, KTY: KeyType, V: CertVerificationMode> PkiRpkContextBuilder<'a, KTY, V>
         self
     }
 
+    /// Sets a hook that is called with the TLS library's native session whenever a TLS session
+    /// using the built context is set up, allowing settings that are not wrapped by libcoap (e.g.,
+    /// cipher suite lists, minimum/maximum protocol versions or OCSP settings) to be applied.
+    ///
+    /// The hook is called after libcoap has configured the session, and before the handshake
+    /// starts (on servers, while processing the Client Hello). If it returns false, the handshake
+    /// is aborted.
+    ///
+    /// Only OpenSSL and GnuTLS support this hook, see [RawTlsSession] for the provided session
+    /// types. Settings that libcoap exposes itself (e.g., the certificate chain verification depth
+    /// or CRL checks, see [PkiRpkContextBuilder::cert_chain_validation()] and
+    /// [PkiRpkContextBuilder::check_cert_revocation()]) should be configured using the builder
+    /// instead.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`TlsSetupHookError::UnsupportedLibrary`] if the TLS library libcoap was built
+    /// with does not support this hook (see [TlsBackend::tls_setup_hook_supported()](crate::crypto::TlsBackend::tls_setup_hook_supported)).
+    ///
+    /// # Safety
+    ///
+    /// The hook operates directly on the TLS library's session, which libcoap relies on being in
+    /// a certain state. The hook must not free the session, store the pointer for use after it
+    /// returned, perform IO on the session or replace any callbacks, BIOs or user data set by
+    /// libcoap.
+    ///
+    /// # Implementation details (informative, not covered by semver guarantees)
+    ///
+    /// Setting a `tls_setup_hook` will set the `additional_tls_setup_call_back` of the underlying
+    /// [`coap_dtls_pki_t`] to a wrapper function, which will then call the hook.
+    pub unsafe fn tls_setup_hook(
+        mut self,
+        tls_setup_hook: impl PkiRpkTlsSetupHook + 'a,
+    ) -> Result<Self, TlsSetupHookError> {
+        let backend = tls_backend();
+        if !backend.tls_setup_hook_supported() {
+            return Err(TlsSetupHookError::UnsupportedLibrary(backend.library()));
+        }
+        self.ctx.tls_setup_hook = Some(Box::new(tls_setup_hook));
+        self.ctx.raw_cfg.additional_tls_setup_call_back = Some(dtls_pki_tls_setup_callback::<KTY>);
+        Ok(self)
+    }
+
     /// Builds the configured `PkiRpkContext` by consuming this builder.
     pub fn build(self) -> PkiRpkContext<'a, KTY> {
         let ctx = Rc::new(RefCell::new(self.ctx));
@@ -635,6 +684,8 @@ struct PkiRpkContextInner<'a, KTY: KeyType> {
     cn_callback: Option<CnCallback<'a>>,
     /// User-provided SNI key provider.
     sni_key_provider: Option<Box<dyn PkiRpkSniKeyProvider<KTY> + 'a>>,
+    /// User-provided hook for additional TLS session setup.
+    tls_setup_hook: Option<Box<dyn PkiRpkTlsSetupHook + 'a>>,
     /// Byte string that client-side sessions using this context should send as SNI.
     ///
     /// Is referenced in raw_cfg and must therefore not be mutated for the lifetime of this context.
@@ -658,6 +709,7 @@ impl<KTY: KeyType> Debug for PkiRpkContextInner<'_, KTY> {
             )
             .field("cn_callback", &"(value does not implement Debug)")
             .field("sni_key_provider", &"(value does not implement Debug)")
+            .field("tls_setup_hook", &"(value does not implement Debug)")
             .field("client_sni", &self.client_sni)
             .finish()
     }
@@ -702,6 +754,12 @@ pub struct PkiRpkContext<'a, KTY: KeyType> {
 }
 
 impl<KTY: KeyType> PkiRpkContext<'_, KTY> {
+    /// Returns a value identifying this context and its clones, see
+    /// [ClientCryptoContext::credentials_id()](crate::crypto::ClientCryptoContext::credentials_id).
+    pub(crate) fn credentials_id(&self) -> usize {
+        Rc::as_ptr(&self.inner) as *const () as usize
+    }
+
     /// Creates a raw [`coap_session_t`] that is bound and uses this encryption context.
     ///
     /// # Safety
@@ -710,6 +768,7 @@ impl<KTY: KeyType> PkiRpkContext<'_, KTY> {
     pub(crate) unsafe fn create_raw_session(
         &self,
         ctx: &mut CoapContext<'_>,
+        local_addr: Option<&CoapAddress>,
         addr: &CoapAddress,
         proto: coap_proto_t,
     ) -> Result<NonNull<coap_session_t>, SessionCreationError> {
@@ -717,19 +776,53 @@ impl<KTY: KeyType> PkiRpkContext<'_, KTY> {
         // raw_cfg is of valid format (as constructed by the builder).
         {
             let mut inner = (*self.inner).borrow_mut();
-            NonNull::new(unsafe {
+            NonNull::new(ctx.with_client_block_mode(|raw_context| unsafe {
                 coap_new_client_session_pki(
-                    ctx.as_mut_raw_context(),
-                    std::ptr::null(),
+                    raw_context,
+                    local_addr.map_or(std::ptr::null(), |v| v.as_raw_address()),
                     addr.as_raw_address(),
                     proto,
                     inner.raw_cfg.as_mut(),
                 )
-            })
+            }))
             .ok_or(SessionCreationError::Unknown)
         }
     }
 
+    /// Creates a raw [`coap_session_t`] that is bound and uses this encryption context and
+    /// additionally protects messages using the given OSCORE configuration.
+    ///
+    /// The OSCORE configuration is consumed by libcoap, even if the session could not be created.
+    ///
+    /// # Safety
+    ///
+    /// This PkiRpkContext must outlive the returned [`coap_session_t`].
+    #[cfg(feature = "oscore")]
+    pub(crate) unsafe fn create_raw_oscore_session(
+        &self,
+        ctx: &mut CoapContext<'_>,
+        local_addr: Option<&CoapAddress>,
+        addr: &CoapAddress,
+        proto: coap_proto_t,
+        oscore_conf: OscoreConf,
+    ) -> Result<NonNull<coap_session_t>, SessionCreationError> {
+        let raw_oscore_conf = oscore_conf.into_raw(ctx);
+        // SAFETY: See create_raw_session(), the OSCORE configuration is valid and owned by libcoap
+        // afterwards.
+        let mut inner = (*self.inner).borrow_mut();
+        NonNull::new(ctx.with_client_block_mode(|raw_context| unsafe {
+            coap_new_client_session_oscore_pki(
+                raw_context,
+                local_addr.map_or(std::ptr::null(), |v| v.as_raw_address()),
+                addr.as_raw_address(),
+                proto,
+                inner.raw_cfg.as_mut(),
+                raw_oscore_conf,
+            )
+        }))
+        .ok_or(SessionCreationError::Unknown)
+    }
+
     /// Configures the provided raw [`coap_context_t`] to use this encryption context for RPK or PKI
     /// based server-side operation.
     ///
@@ -755,9 +848,11 @@ impl<KTY: KeyType> PkiRpkContext<'_, KTY> {
 impl<'a, KTY: KeyType> PkiRpkContext<'a, KTY> {
     /// Wrapper function for the user-provided CN callback.
     ///
-    /// Calls the user-provided CN callback and converts its return value into the integer values
-    /// libcoap expects.
-    // cn and depth are unused only if dtls-pki feature is not enabled
+    /// Records the certificate of the peer (depth 0) in the session (see
+    /// [CoapCryptoSessionInfo::peer_certificate()](crate::crypto::CoapCryptoSessionInfo::peer_certificate)),
+    /// calls the user-provided CN callback (if any, otherwise the certificate is accepted) and
+    /// converts its return value into the integer values libcoap expects.
+    // cn is unused only if dtls-pki feature is not enabled
     #[cfg_attr(not(feature = "dtls-pki"), allow(unused_variables))]
     fn cn_callback(
         &self,
@@ -767,14 +862,16 @@ impl<'a, KTY: KeyType> PkiRpkContext<'a, KTY> {
         depth: c_uint,
         validated: bool,
     ) -> c_int {
+        if depth == 0 && validated {
+            record_peer_certificate(session, asn1_public_cert);
+        }
         let inner = (*self.inner).borrow();
-        // This function is only ever called if a CN key provider is set, so it's fine to unwrap
-        // here.
-        if match inner.cn_callback.as_ref().unwrap() {
+        if match inner.cn_callback.as_ref() {
             #[cfg(feature = "dtls-pki")]
-            CnCallback::Pki(pki) => pki.validate_cn(cn, asn1_public_cert, session, depth, validated),
+            Some(CnCallback::Pki(pki)) => pki.validate_cn(cn, asn1_public_cert, session, depth, validated),
             #[cfg(feature = "dtls-rpk")]
-            CnCallback::Rpk(rpk) => rpk.validate_rpk(asn1_public_cert, session, validated),
+            Some(CnCallback::Rpk(rpk)) => rpk.validate_rpk(asn1_public_cert, session, validated),
+            None => true,
         } {
             1
         } else {
@@ -804,6 +901,27 @@ impl<'a, KTY: KeyType> PkiRpkContext<'a, KTY> {
         }
     }
 
+    /// Wrapper function for the user-provided TLS setup hook.
+    ///
+    /// Converts the native TLS session into a [RawTlsSession] for the TLS library in use and the
+    /// return value of the hook into the integer values libcoap expects.
+    fn tls_setup_callback(&self, tls_session: NonNull<c_void>) -> c_int {
+        let session = match tls_backend().library() {
+            TlsLibrary::OpenSsl => RawTlsSession::OpenSsl(tls_session),
+            TlsLibrary::GnuTls => RawTlsSession::GnuTls(tls_session),
+            // The hook can only be set for supported libraries.
+            _ => return 0,
+        };
+        let inner = (*self.inner).borrow();
+        // This function is only ever called if a TLS setup hook is set, so it's fine to unwrap
+        // here.
+        if inner.tls_setup_hook.as_ref().unwrap().setup_tls_session(session) {
+            1
+        } else {
+            0
+        }
+    }
+
     /// Restores a [`PkiRpkContext`] from a pointer to its inner structure (i.e. from the
     /// user-provided pointer given to DTLS callbacks).
     ///
@@ -855,6 +973,32 @@ impl<KTY: KeyType, T: Fn(&CStr) -> Option<Box<dyn KeyDef<KeyType = KTY>>>> PkiRp
     }
 }
 
+/// Native session of the TLS library libcoap was built with, provided to a
+/// [`PkiRpkTlsSetupHook`].
+///
+/// The contained pointers are only valid while the hook is running.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RawTlsSession {
+    /// OpenSSL session, the pointer is an `SSL*`.
+    OpenSsl(NonNull<c_void>),
+    /// GnuTLS session, the pointer is a `gnutls_session_t`.
+    GnuTls(NonNull<c_void>),
+}
+
+/// Trait for hooks that apply additional configuration to native TLS sessions, see
+/// [`PkiRpkContextBuilder::tls_setup_hook`].
+pub trait PkiRpkTlsSetupHook {
+    /// Configures the given TLS session, returning false if the handshake should be aborted.
+    fn setup_tls_session(&self, session: RawTlsSession) -> bool;
+}
+
+impl<T: Fn(RawTlsSession) -> bool> PkiRpkTlsSetupHook for T {
+    fn setup_tls_session(&self, session: RawTlsSession) -> bool {
+        self(session)
+    }
+}
+
 /// Raw CN callback that can be provided to libcoap.
 ///
 /// # Safety
@@ -874,12 +1018,15 @@ unsafe extern "C" fn dtls_pki_cn_callback<KTY: KeyType>(
     validated: c_int,
     arg: *mut c_void,
 ) -> c_int {
-    let session = CoapSession::from_raw(session);
-    let cn = CStr::from_ptr(cn);
-    let asn1_public_cert = std::slice::from_raw_parts(asn1_public_cert, asn1_length);
-    let validated = validated == 1;
-    let context = PkiRpkContext::from_raw(arg as *const RefCell<PkiRpkContextInner<KTY>>);
-    context.cn_callback(cn, asn1_public_cert, &session, depth, validated)
+    // A panicking validator rejects the certificate.
+    catch_session_callback_panic(session, 0, || {
+        let session = CoapSession::from_raw(session);
+        let cn = CStr::from_ptr(cn);
+        let asn1_public_cert = std::slice::from_raw_parts(asn1_public_cert, asn1_length);
+        let validated = validated == 1;
+        let context = PkiRpkContext::from_raw(arg as *const RefCell<PkiRpkContextInner<KTY>>);
+        context.cn_callback(cn, asn1_public_cert, &session, depth, validated)
+    })
 }
 
 /// Raw PKI/RPK SNI callback that can be provided to libcoap.
@@ -893,6 +1040,35 @@ unsafe extern "C" fn dtls_pki_cn_callback<KTY: KeyType>(
 /// of `PkiRpkContext` matches the key type of this function).
 unsafe extern "C" fn dtls_pki_sni_callback<KTY: KeyType>(sni: *const c_char, arg: *mut c_void) -> *mut coap_dtls_key_t {
     let sni = CStr::from_ptr(sni);
-    let context = PkiRpkContext::from_raw(arg as *const RefCell<PkiRpkContextInner<KTY>>);
-    context.sni_callback(sni)
+    // A panicking key provider causes the handshake to be aborted.
+    catch_callback_panic(std::ptr::null_mut(), std::ptr::null_mut(), || {
+        let context = PkiRpkContext::from_raw(arg as *const RefCell<PkiRpkContextInner<KTY>>);
+        context.sni_callback(sni)
+    })
+}
+
+/// Raw additional TLS setup callback that can be provided to libcoap.
+///
+/// # Safety
+///
+/// This function expects the arguments to be provided in a way that libcoap would when invoking
+/// this function as an additional TLS setup callback.
+///
+/// Additionally, the `cn_call_back_arg` of `setup_data` must be a valid argument to
+/// [`PkiRpkContext::from_raw`] (where the key type of `PkiRpkContext` matches the key type of this
+/// function), which is always the case for configurations created by [`PkiRpkContextBuilder`].
+unsafe extern "C" fn dtls_pki_tls_setup_callback<KTY: KeyType>(
+    tls_session: *mut c_void,
+    setup_data: *mut coap_dtls_pki_t,
+) -> c_int {
+    // A panicking hook causes the handshake to be aborted.
+    catch_callback_panic(std::ptr::null_mut(), 0, || {
+        let Some(tls_session) = NonNull::new(tls_session) else {
+            return 0;
+        };
+        // libcoap provides its copy of the configuration, which still references our context.
+        let context =
+            PkiRpkContext::from_raw((*setup_data).cn_call_back_arg as *const RefCell<PkiRpkContextInner<KTY>>);
+        context.tls_setup_callback(tls_session)
+    })
 }
@@ -7,17 +7,23 @@
  * See the README as well as the LICENSE file for more information.
  */
 
+use crate::crypto::client_sni_to_raw;
 use crate::crypto::psk::key::PskKey;
-use crate::error::SessionCreationError;
+use crate::error::{ClientSniError, PskKeyError, SessionCreationError};
+#[cfg(feature = "oscore")]
+use crate::oscore::OscoreConf;
 use crate::session::CoapClientSession;
 use crate::types::CoapAddress;
+use crate::unwind::catch_session_callback_panic;
 use crate::CoapContext;
+#[cfg(feature = "oscore")]
+use libcoap_sys::coap_new_client_session_oscore_psk;
 use libcoap_sys::{
     coap_dtls_cpsk_info_t, coap_dtls_cpsk_t, coap_new_client_session_psk2, coap_proto_t, coap_session_t,
     coap_str_const_t, COAP_DTLS_CPSK_SETUP_VERSION,
 };
 use std::cell::RefCell;
-use std::ffi::{c_char, c_void, CString, NulError};
+use std::ffi::{c_char, c_void};
 use std::fmt::Debug;
 use std::ptr::NonNull;
 use std::rc::{Rc, Weak};
@@ -35,7 +41,30 @@ impl<'a> ClientPskContextBuilder<'a> {
     ///
     /// Providing a raw public key will set `psk_info` to the provided key in the underlying
     /// [`coap_dtls_cpsk_t`] structure.
+    ///
+    /// The key is validated (see [PskKey::validate()]) once a session is created using the built
+    /// context, creating sessions fails with [SessionCreationError::InvalidPsk] if the key can not
+    /// be used.
     pub fn new(psk: PskKey<'a>) -> Self {
+        let default_key_error = psk.validate().err();
+        Self::with_default_key(psk, default_key_error)
+    }
+
+    /// Creates a new context builder without a default key, which selects the key exclusively
+    /// using the given `key_provider` once the server has sent its (possibly empty) identity hint.
+    ///
+    /// This is useful for providers that can only select keys based on identity hints (see
+    /// [ClientPskContextBuilder::key_provider()]). If the key provider does not provide a key
+    /// for the hint sent by the server, the handshake is aborted.
+    ///
+    /// # Implementation details (informative, not covered by semver guarantees)
+    ///
+    /// The `psk_info` of the underlying [`coap_dtls_cpsk_t`] structure is left empty.
+    pub fn from_key_provider(key_provider: impl ClientPskHintKeyProvider<'a> + 'a) -> Self {
+        Self::with_default_key(PskKey::new(None::<Vec<u8>>, Vec::new()), None).key_provider(key_provider)
+    }
+
+    fn with_default_key(psk: PskKey<'a>, default_key_error: Option<PskKeyError>) -> Self {
         Self {
             ctx: ClientPskContextInner {
                 raw_cfg: Box::new(coap_dtls_cpsk_t {
@@ -53,6 +82,7 @@ impl<'a> ClientPskContextBuilder<'a> {
                 key_provider: None,
                 provided_keys: Vec::new(),
                 client_sni: None,
+                default_key_error,
             },
         }
     }
@@ -66,7 +96,8 @@ impl<'a> ClientPskContextBuilder<'a> {
     /// [`coap_dtls_cpsk_t`] to a wrapper function, which will then call the key provider.
     ///
     /// Keys returned by the key provider will be stored in the context for at least as long as they
-    /// are used by the respective session.
+    /// are used by the respective session. Keys that can not be used by libcoap (see
+    /// [PskKey::validate()]) abort the handshake.
     pub fn key_provider(mut self, key_provider: impl ClientPskHintKeyProvider<'a> + 'a) -> Self {
         self.ctx.key_provider = Some(Box::new(key_provider));
         self.ctx.raw_cfg.validate_ih_call_back = Some(dtls_psk_client_ih_callback);
@@ -119,12 +150,16 @@ impl ClientPskContextBuilder<'_> {
     /// Sets the server name indication that should be sent to servers if the built
     /// [`ClientPskContext`] is used.
     ///
-    /// `client_sni` should be convertible into a byte string that does not contain null bytes.
-    /// Typically, you would provide a `&str` or `String`.
+    /// `client_sni` should be convertible into a byte string containing an ASCII host name.
+    /// Typically, you would provide a `&str` or `String`. Internationalized domain names must be
+    /// converted into their ASCII form (e.g., `xn--bcher-kva.example` instead of `bücher.example`)
+    /// beforehand, as the server name is passed to libcoap as-is.
     ///
     /// # Errors
     ///
-    /// Will return [`NulError`] if the provided byte string contains null bytes.
+    /// Will return a [`ClientSniError`] if the provided byte string is empty, contains null bytes
+    /// or non-ASCII characters, or is an IP address literal (which may not be used as a server
+    /// name indication).
     ///
     /// # Implementation details (informative, not covered by semver guarantees)
     ///
@@ -132,14 +167,8 @@ impl ClientPskContextBuilder<'_> {
     ///
     /// The provided `client_sni` will be converted into a `Box<[u8]>`, which will be owned and
     /// stored by the built context.
-    pub fn client_sni<T: Into<Vec<u8>>>(mut self, client_sni: T) -> Result<Self, NulError> {
-        // For some reason, client_sni is not immutable here.
-        // While I don't see any reason why libcoap would modify the string, it is not strictly
-        // forbidden for it to do so, so simply using CString::into_raw() is not an option (as it
-        // does not allow modifications to client_sni that change the length).
-        let sni = CString::new(client_sni.into())?
-            .into_bytes_with_nul()
-            .into_boxed_slice();
+    pub fn client_sni<T: Into<Vec<u8>>>(mut self, client_sni: T) -> Result<Self, ClientSniError> {
+        let sni = client_sni_to_raw(client_sni.into())?;
         self.ctx.client_sni = Some(sni);
         self.ctx.raw_cfg.client_sni = self.ctx.client_sni.as_mut().unwrap().as_mut_ptr() as *mut c_char;
         Ok(self)
@@ -176,7 +205,7 @@ impl ClientPskContext<'_> {
             .unwrap()
             .key_for_identity_hint(identity_hint, session);
 
-        if let Some(key) = key {
+        if let Some(key) = key.filter(|key| key.validate().is_ok()) {
             let boxed_key_info = Box::new(key.into_raw_cpsk_info());
             let boxed_key_ptr = Box::into_raw(boxed_key_info);
             // TODO remove these entries prematurely if the underlying session is removed (would
@@ -196,6 +225,7 @@ impl ClientPskContext<'_> {
     pub(crate) unsafe fn create_raw_session(
         &self,
         ctx: &mut CoapContext<'_>,
+        local_addr: Option<&CoapAddress>,
         addr: &CoapAddress,
         proto: coap_proto_t,
     ) -> Result<NonNull<coap_session_t>, SessionCreationError> {
@@ -203,21 +233,67 @@ impl ClientPskContext<'_> {
         // raw_cfg is of valid format (as constructed by the builder).
         {
             let mut inner = (*self.inner).borrow_mut();
-            NonNull::new(unsafe {
+            if let Some(error) = inner.default_key_error {
+                return Err(SessionCreationError::InvalidPsk(error));
+            }
+            NonNull::new(ctx.with_client_block_mode(|raw_context| unsafe {
                 coap_new_client_session_psk2(
-                    ctx.as_mut_raw_context(),
-                    std::ptr::null(),
+                    raw_context,
+                    local_addr.map_or(std::ptr::null(), |v| v.as_raw_address()),
                     addr.as_raw_address(),
                     proto,
                     inner.raw_cfg.as_mut(),
                 )
-            })
+            }))
             .ok_or(SessionCreationError::Unknown)
         }
     }
+
+    /// Creates a raw CoAP session object that is bound to and utilizes this encryption context
+    /// and additionally protects messages using the given OSCORE configuration.
+    ///
+    /// The OSCORE configuration is consumed by libcoap, even if the session could not be created.
+    ///
+    /// # Safety
+    ///
+    /// This [`ClientPskContext`] must outlive the returned [`coap_session_t`].
+    #[cfg(feature = "oscore")]
+    pub(crate) unsafe fn create_raw_oscore_session(
+        &self,
+        ctx: &mut CoapContext<'_>,
+        local_addr: Option<&CoapAddress>,
+        addr: &CoapAddress,
+        proto: coap_proto_t,
+        oscore_conf: OscoreConf,
+    ) -> Result<NonNull<coap_session_t>, SessionCreationError> {
+        let mut inner = (*self.inner).borrow_mut();
+        if let Some(error) = inner.default_key_error {
+            return Err(SessionCreationError::InvalidPsk(error));
+        }
+        let raw_oscore_conf = oscore_conf.into_raw(ctx);
+        // SAFETY: See create_raw_session(), the OSCORE configuration is valid and owned by libcoap
+        // afterwards.
+        NonNull::new(ctx.with_client_block_mode(|raw_context| unsafe {
+            coap_new_client_session_oscore_psk(
+                raw_context,
+                local_addr.map_or(std::ptr::null(), |v| v.as_raw_address()),
+                addr.as_raw_address(),
+                proto,
+                inner.raw_cfg.as_mut(),
+                raw_oscore_conf,
+            )
+        }))
+        .ok_or(SessionCreationError::Unknown)
+    }
 }
 
 impl<'a> ClientPskContext<'a> {
+    /// Returns a value identifying this context and its clones, see
+    /// [ClientCryptoContext::credentials_id()](crate::crypto::ClientCryptoContext::credentials_id).
+    pub(crate) fn credentials_id(&self) -> usize {
+        Rc::as_ptr(&self.inner) as *const () as usize
+    }
+
     /// Restores a [`ClientPskContext`] from a pointer to its inner structure (i.e., from the
     /// user-provided pointer given to DTLS callbacks).
     ///
@@ -257,6 +333,8 @@ struct ClientPskContextInner<'a> {
     provided_keys: Vec<*mut coap_dtls_cpsk_info_t>,
     /// Server Name Indication to send to servers.
     client_sni: Option<Box<[u8]>>,
+    /// Reason why the default key can not be used, if any.
+    default_key_error: Option<PskKeyError>,
 }
 
 impl Drop for ClientPskContextInner<'_> {
@@ -284,6 +362,15 @@ impl Drop for ClientPskContextInner<'_> {
 
 /// Trait for types that can provide the appropriate pre-shared key for a given PSK hint sent by the
 /// server.
+///
+/// Returned keys are owned by the context and kept alive until the context (and therefore every
+/// session using it) is dropped, as libcoap may read them at any time during the handshake and
+/// any renegotiation. Providers may therefore build keys from temporary buffers (e.g., from a
+/// keystore shared with other parts of the application using an `Arc`) without having to keep
+/// the returned values alive themselves.
+///
+/// A single [ClientPskContext] (and its key provider) can be used for any number of sessions by
+/// cloning it.
 pub trait ClientPskHintKeyProvider<'a>: Debug {
     /// Returns the appropriate pre-shared key for a given `identity_hint` and the given `session`,
     /// or `None` if the session should be aborted/no key is available.
@@ -327,9 +414,12 @@ unsafe extern "C" fn dtls_psk_client_ih_callback(
     session: *mut coap_session_t,
     userdata: *mut c_void,
 ) -> *const coap_dtls_cpsk_info_t {
-    let session = CoapClientSession::from_raw(session);
-    let client_context = ClientPskContext::from_raw(userdata as *const RefCell<ClientPskContextInner>);
-    let provided_identity =
-        NonNull::new(hint).map(|h| std::slice::from_raw_parts((*h.as_ptr()).s, (*h.as_ptr()).length));
-    client_context.ih_callback(provided_identity, &session)
+    // A panicking key provider causes the handshake to be aborted.
+    catch_session_callback_panic(session, std::ptr::null(), || {
+        let session = CoapClientSession::from_raw(session);
+        let client_context = ClientPskContext::from_raw(userdata as *const RefCell<ClientPskContextInner>);
+        let provided_identity =
+            NonNull::new(hint).map(|h| std::slice::from_raw_parts((*h.as_ptr()).s, (*h.as_ptr()).length));
+        client_context.ih_callback(provided_identity, &session)
+    })
 }
@@ -12,7 +12,27 @@ use std::borrow::Cow;
 use std::marker::PhantomData;
 use std::ptr::NonNull;
 
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::error::PskKeyError;
+
+/// Maximum length of PSK identities supported by libcoap (`COAP_DTLS_MAX_PSK_IDENTITY`).
+pub const MAX_PSK_IDENTITY_LENGTH: usize = 64;
+
+/// Maximum length of pre-shared keys supported by libcoap (`COAP_DTLS_MAX_PSK`).
+pub const MAX_PSK_KEY_LENGTH: usize = 64;
+
 /// A pre-shared DTLS key.
+///
+/// Keys are always owned: contexts and sessions that use a key (e.g., keys returned by a
+/// [ClientPskHintKeyProvider](crate::crypto::psk::ClientPskHintKeyProvider)) keep their own copy
+/// of the identity and key bytes for as long as libcoap may access them, so key providers never
+/// have to keep the returned values alive themselves.
+///
+/// If the `zeroize` feature is enabled, the key bytes (and identity) are overwritten with zeroes
+/// when the key is dropped. This also applies to the copies held by contexts, which are dropped
+/// together with the context.
 #[derive(Debug, Clone)]
 pub struct PskKey<'a> {
     /// Identity of this key (or None if no identity is known).
@@ -30,8 +50,8 @@ impl<'a> PskKey<'a> {
     /// Creates a new key object with the given `identity` and the actual key bytes given in `data`.
     pub fn new<T: Into<Vec<u8>>, U: Into<Vec<u8>>>(identity: Option<T>, data: U) -> PskKey<'a> {
         PskKey {
-            identity: identity.map(Into::into).map(|v| v.into_boxed_slice()),
-            data: data.into().into_boxed_slice(),
+            identity: identity.map(|v| into_boxed_bytes(v.into())),
+            data: into_boxed_bytes(data.into()),
             _lifetime_marker: Default::default(),
         }
     }
@@ -48,6 +68,25 @@ impl PskKey<'_> {
         self.data.as_ref()
     }
 
+    /// Checks whether this key can be used by libcoap, i.e., whether the key data is non-empty
+    /// and the key data and identity (if any) do not exceed the lengths supported by libcoap
+    /// ([MAX_PSK_KEY_LENGTH] and [MAX_PSK_IDENTITY_LENGTH]).
+    ///
+    /// # Errors
+    /// Returns a [PskKeyError] describing the first problem found.
+    pub fn validate(&self) -> Result<(), PskKeyError> {
+        match self.data.len() {
+            0 => return Err(PskKeyError::EmptyKey),
+            len if len > MAX_PSK_KEY_LENGTH => return Err(PskKeyError::KeyTooLong(len)),
+            _ => {},
+        }
+        match self.identity.as_ref().map(|v| v.len()) {
+            Some(0) => Err(PskKeyError::EmptyIdentity),
+            Some(len) if len > MAX_PSK_IDENTITY_LENGTH => Err(PskKeyError::IdentityTooLong(len)),
+            _ => Ok(()),
+        }
+    }
+
     /// Creates a [`coap_dtls_spsk_info_t`] instance from this [`PskKey`].
     ///
     /// This call converts the identity and data field of this PSK into raw pointers and creates a
@@ -115,9 +154,11 @@ impl PskKey<'_> {
     ///
     /// The pointers given in [`coap_bin_const_t`] have been created by a call to [`Box::into_raw`]
     /// with the `length` field set to the length of the given field.
-    fn into_bin_consts(self) -> (coap_bin_const_t, coap_bin_const_t) {
+    fn into_bin_consts(mut self) -> (coap_bin_const_t, coap_bin_const_t) {
+        // The fields are taken out instead of being moved, as PskKey may implement Drop.
         let identity = self
             .identity
+            .take()
             .map(|v| coap_bin_const_t {
                 length: v.len(),
                 s: Box::into_raw(v) as *const u8,
@@ -126,9 +167,10 @@ impl PskKey<'_> {
                 length: 0,
                 s: std::ptr::null(),
             });
+        let data = std::mem::take(&mut self.data);
         let key = coap_bin_const_t {
-            length: self.data.len(),
-            s: Box::into_raw(self.data) as *const u8,
+            length: data.len(),
+            s: Box::into_raw(data) as *const u8,
         };
         (identity, key)
     }
@@ -159,6 +201,32 @@ impl PskKey<'_> {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for PskKey<'_> {
+    fn drop(&mut self) {
+        self.data.zeroize();
+        if let Some(identity) = self.identity.as_mut() {
+            identity.zeroize();
+        }
+    }
+}
+
+/// Converts the given key bytes into a boxed slice.
+///
+/// [Vec::into_boxed_slice()] reallocates vectors with excess capacity, which would leave a copy of
+/// the bytes in freed memory. If the `zeroize` feature is enabled, the bytes are therefore copied
+/// into a new allocation and the vector is zeroized (including its excess capacity) instead.
+fn into_boxed_bytes(bytes: Vec<u8>) -> Box<[u8]> {
+    #[cfg(feature = "zeroize")]
+    if bytes.len() != bytes.capacity() {
+        let mut bytes = bytes;
+        let boxed = Box::from(bytes.as_slice());
+        bytes.zeroize();
+        return boxed;
+    }
+    bytes.into_boxed_slice()
+}
+
 impl From<Box<[u8]>> for PskKey<'static> {
     fn from(value: Box<[u8]>) -> Self {
         PskKey {
@@ -183,7 +251,7 @@ impl<'a> From<Cow<'a, [u8]>> for PskKey<'static> {
     fn from(value: Cow<'a, [u8]>) -> Self {
         PskKey {
             identity: None,
-            data: value.into(),
+            data: into_boxed_bytes(value.into_owned()),
             _lifetime_marker: Default::default(),
         }
     }
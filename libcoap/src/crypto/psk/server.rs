@@ -10,6 +10,7 @@
 use crate::crypto::psk::key::PskKey;
 use crate::error::ContextConfigurationError;
 use crate::session::CoapServerSession;
+use crate::unwind::catch_session_callback_panic;
 use libcoap_sys::{
     coap_bin_const_t, coap_context_set_psk2, coap_context_t, coap_dtls_spsk_info_t, coap_dtls_spsk_t, coap_session_t,
     COAP_DTLS_SPSK_SETUP_VERSION,
@@ -355,15 +356,18 @@ unsafe extern "C" fn dtls_psk_server_id_callback(
     userdata: *mut c_void,
 ) -> *const coap_bin_const_t {
     let identity = std::slice::from_raw_parts((*identity).s, (*identity).length);
-    // We must not increase the refcount here, as doing so would require locking the global context,
-    // which is not possible during a DTLS callback.
-    // SAFETY: While we are in this callback, libcoap's context is locked by our current thread.
-    //         therefore, it is impossible that the reference counter would be decreased by any
-    //         other means, and constructing the server side session without increasing the refcount
-    //         is fine.
-    let session = CoapServerSession::from_raw_without_refcount(session);
-    let server_context = ServerPskContext::from_raw(userdata as *const RefCell<ServerPskContextInner>);
-    server_context.id_callback(identity, &session)
+    // A panicking key provider causes the handshake to be aborted.
+    catch_session_callback_panic(session, std::ptr::null(), || {
+        // We must not increase the refcount here, as doing so would require locking the global
+        // context, which is not possible during a DTLS callback.
+        // SAFETY: While we are in this callback, libcoap's context is locked by our current thread.
+        //         therefore, it is impossible that the reference counter would be decreased by any
+        //         other means, and constructing the server side session without increasing the
+        //         refcount is fine.
+        let session = CoapServerSession::from_raw_without_refcount(session);
+        let server_context = ServerPskContext::from_raw(userdata as *const RefCell<ServerPskContextInner>);
+        server_context.id_callback(identity, &session)
+    })
 }
 
 /// Raw PSK SNI callback that can be provided to libcoap.
@@ -380,13 +384,16 @@ unsafe extern "C" fn dtls_psk_server_sni_callback(
     userdata: *mut c_void,
 ) -> *const coap_dtls_spsk_info_t {
     let sni = CStr::from_ptr(sni);
-    // We must not increase the refcount here, as doing so would require locking the global context,
-    // which is not possible during a DTLS callback.
-    // SAFETY: While we are in this callback, libcoap's context is locked by our current thread.
-    //         therefore, it is impossible that the reference counter would be decreased by any
-    //         other means, and constructing the server side session without increasing the refcount
-    //         is fine.
-    let session = CoapServerSession::from_raw_without_refcount(session);
-    let server_context = ServerPskContext::from_raw(userdata as *const RefCell<ServerPskContextInner>);
-    server_context.sni_callback(sni, &session)
+    // A panicking key provider causes the handshake to be aborted.
+    catch_session_callback_panic(session, std::ptr::null(), || {
+        // We must not increase the refcount here, as doing so would require locking the global
+        // context, which is not possible during a DTLS callback.
+        // SAFETY: While we are in this callback, libcoap's context is locked by our current thread.
+        //         therefore, it is impossible that the reference counter would be decreased by any
+        //         other means, and constructing the server side session without increasing the
+        //         refcount is fine.
+        let session = CoapServerSession::from_raw_without_refcount(session);
+        let server_context = ServerPskContext::from_raw(userdata as *const RefCell<ServerPskContextInner>);
+        server_context.sni_callback(sni, &session)
+    })
 }
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * echo.rs - Types for verifying the freshness of requests using the Echo option.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for challenging clients using the Echo option
+//! ([RFC 9175, Section 2](https://datatracker.ietf.org/doc/html/rfc9175#section-2)).
+//!
+//! Resources can require requests to contain a fresh Echo value using
+//! [CoapResource::set_echo_policy()](crate::CoapResource::set_echo_policy). Requests to which the
+//! policy applies and that do not contain a valid Echo value are answered with a 4.01
+//! (Unauthorized) response containing a new Echo value, without calling the request handler.
+//! This verifies that the client is able to receive messages at its (claimed) source address and
+//! that the request was sent recently, which mitigates amplification attacks and the delayed
+//! delivery of state-changing requests.
+//!
+//! Echo values are generated and verified by the [CoapEchoValueProvider] of the context (see
+//! [CoapContext::set_echo_value_provider()](crate::CoapContext::set_echo_value_provider)), which
+//! is a [RandomEchoValueProvider] by default.
+//!
+//! On the client side, libcoap automatically repeats requests that were answered with a 4.01
+//! response containing an Echo option once, including the received Echo value (see
+//! `man coap_send`). The retry is transparent to the application, i.e., only the response to the
+//! repeated request is returned by
+//! [CoapSessionCommon::poll_handle()](crate::session::CoapSessionCommon::poll_handle).
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    prng::coap_prng_try_fill,
+    protocol::{CoapRequestCode, Echo},
+    session::{CoapServerSession, CoapSessionCommon, CoapSessionId},
+};
+
+/// Default time span for which Echo values generated by a [RandomEchoValueProvider] are valid.
+const DEFAULT_ECHO_FRESHNESS: Duration = Duration::from_secs(60);
+
+/// Maximum number of Echo values tracked per session by a [RandomEchoValueProvider].
+const MAX_ECHO_VALUES_PER_SESSION: usize = 8;
+
+/// Length of Echo values generated by a [RandomEchoValueProvider] (in bytes).
+const ECHO_VALUE_LENGTH: usize = 8;
+
+/// Policy that determines which requests to a resource need to contain a valid Echo value, see
+/// [CoapResource::set_echo_policy()](crate::CoapResource::set_echo_policy).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CoapEchoPolicy {
+    /// Requests are passed to the request handler regardless of their Echo option (the default).
+    #[default]
+    Disabled,
+    /// Requests using a method that is not safe (i.e., all methods except for GET and FETCH,
+    /// which may change the state of the resource) need to contain a valid Echo value.
+    UnsafeMethods,
+    /// All requests need to contain a valid Echo value.
+    AllMethods,
+}
+
+impl CoapEchoPolicy {
+    /// Returns whether requests using the given method need to contain a valid Echo value
+    /// according to this policy.
+    pub fn applies_to(&self, code: CoapRequestCode) -> bool {
+        match self {
+            CoapEchoPolicy::Disabled => false,
+            CoapEchoPolicy::UnsafeMethods => !matches!(code, CoapRequestCode::Get | CoapRequestCode::Fetch),
+            CoapEchoPolicy::AllMethods => true,
+        }
+    }
+}
+
+/// Trait for types that generate and verify Echo values, see
+/// [CoapContext::set_echo_value_provider()](crate::CoapContext::set_echo_value_provider).
+pub trait CoapEchoValueProvider: Debug {
+    /// Returns a new Echo value that is sent to the peer of the given session in a 4.01
+    /// response.
+    ///
+    /// Returning None causes the request to be answered with a 5.03 (Service Unavailable)
+    /// response instead.
+    fn generate(&mut self, session: &CoapServerSession<'_>) -> Option<Echo>;
+
+    /// Returns whether the given Echo value, which was received from the peer of the given
+    /// session, is valid (i.e., was generated for this peer and is still fresh).
+    fn verify(&mut self, session: &CoapServerSession<'_>, value: &[u8]) -> bool;
+}
+
+/// The default [CoapEchoValueProvider], which generates random Echo values that are valid for
+/// a fixed time span.
+///
+/// Values are only accepted from the session they were generated for and may be used for
+/// multiple requests until they expire. At most eight values are tracked per session, older
+/// values are discarded if more are generated.
+#[derive(Debug)]
+pub struct RandomEchoValueProvider {
+    freshness: Duration,
+    issued: HashMap<CoapSessionId, VecDeque<(Echo, Instant)>>,
+}
+
+impl RandomEchoValueProvider {
+    /// Creates a provider whose Echo values are valid for the given time span.
+    pub fn new(freshness: Duration) -> RandomEchoValueProvider {
+        RandomEchoValueProvider {
+            freshness,
+            issued: HashMap::new(),
+        }
+    }
+
+    /// Returns the time span for which generated Echo values are valid.
+    pub fn freshness(&self) -> Duration {
+        self.freshness
+    }
+
+    /// Removes all expired values (and sessions without unexpired values).
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.issued.retain(|_, values| {
+            values.retain(|(_, expiry)| *expiry > now);
+            !values.is_empty()
+        });
+    }
+}
+
+impl Default for RandomEchoValueProvider {
+    /// Creates a provider whose Echo values are valid for 60 seconds.
+    fn default() -> Self {
+        RandomEchoValueProvider::new(DEFAULT_ECHO_FRESHNESS)
+    }
+}
+
+impl CoapEchoValueProvider for RandomEchoValueProvider {
+    fn generate(&mut self, session: &CoapServerSession<'_>) -> Option<Echo> {
+        self.remove_expired();
+        let mut value = [0; ECHO_VALUE_LENGTH];
+        coap_prng_try_fill(&mut value).ok()?;
+        let expiry = Instant::now().checked_add(self.freshness)?;
+        let values = self.issued.entry(session.id()).or_default();
+        while values.len() >= MAX_ECHO_VALUES_PER_SESSION {
+            values.pop_front();
+        }
+        values.push_back((Echo::from(value), expiry));
+        Some(Echo::from(value))
+    }
+
+    fn verify(&mut self, session: &CoapServerSession<'_>, value: &[u8]) -> bool {
+        self.remove_expired();
+        self.issued
+            .get(&session.id())
+            .is_some_and(|values| values.iter().any(|(issued, _)| issued.as_ref() == value))
+    }
+}
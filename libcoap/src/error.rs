@@ -10,18 +10,76 @@
 //! Error types
 
 use std::ffi::NulError;
+use std::net::{IpAddr, SocketAddr};
 use std::string::FromUtf8Error;
 use std::sync::PoisonError;
 
 use thiserror::Error;
 
-use crate::protocol::{CoapMessageType, CoapOptionType};
+use crate::crypto::{CoapTlsAlert, TlsLibrary};
+use crate::limits::CoapInputLimitExceeded;
+use crate::protocol::{
+    CoapContentFormat, CoapMessageCode, CoapMessageType, CoapOptionNum, CoapOptionType, CoapRequestCode,
+    CoapResponseCode,
+};
+use crate::ResourceFlags;
+use crate::transport::CoapIpv6Mode;
+use crate::types::{CoapProtocol, CoapUriScheme};
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum EndpointCreationError {
     /// Unknown error inside of libcoap
     #[error("CoAP endpoint creation error: unknown error in call to libcoap")]
     Unknown,
+    /// The address to bind to is already in use
+    #[error("CoAP endpoint creation error: address already in use")]
+    AddressInUse,
+    /// Insufficient permissions to bind to the address (e.g., a privileged port)
+    #[error("CoAP endpoint creation error: permission denied")]
+    PermissionDenied,
+    /// libcoap was unable to create the endpoint socket due to another OS error
+    #[error("CoAP endpoint creation error: unable to create socket: {}", .0)]
+    Io(std::io::ErrorKind),
+    /// Attempted to create an encrypted endpoint without configuring server-side credentials
+    #[error("CoAP endpoint creation error: DTLS endpoint has no PSK or PKI/RPK credentials configured")]
+    MissingServerCredentials,
+    /// The provided Unix domain socket path can not be used
+    #[error("CoAP endpoint creation error: invalid Unix socket path")]
+    InvalidUnixPath(#[from] UnixSocketPathError),
+    /// The provided endpoint handle does not refer to an (active) endpoint of this context
+    #[error("CoAP endpoint creation error: unknown endpoint")]
+    UnknownEndpoint,
+    /// The operation is not supported for this type of endpoint
+    #[error("CoAP endpoint creation error: operation not supported for this endpoint type")]
+    UnsupportedEndpoint,
+    /// The libcoap build (or its TLS library) in use does not support the requested transport
+    #[error("CoAP endpoint creation error: transport {} is not supported by the libcoap build in use", .0)]
+    TransportUnsupported(CoapProtocol),
+    /// The address to bind to is an IPv4-mapped IPv6 address, the IPv4 address has to be used instead
+    #[error("CoAP endpoint creation error: unable to bind to IPv4-mapped IPv6 address")]
+    Ipv4MappedAddress,
+    /// The address to bind to is an IPv6 link-local address without a zone (scope) ID
+    #[error("CoAP endpoint creation error: link-local address requires a zone ID")]
+    MissingScopeId,
+    /// The requested IPv6 mode can not be used on this platform (see [CoapIpv6Mode::is_supported()])
+    #[error("CoAP endpoint creation error: IPv6 mode {:?} is not supported on this platform", .0)]
+    Ipv6ModeUnsupported(CoapIpv6Mode),
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UnixSocketPathError {
+    /// The path is too long to be used as a Unix domain socket address by libcoap
+    #[error("Unix socket path error: path exceeds the maximum length of {} bytes", .0)]
+    TooLong(usize),
+    /// The path contains a null byte
+    #[error("Unix socket path error: path contains a null byte")]
+    ContainsNullByte,
+    /// A file that is not a socket already exists at the provided path
+    #[error("Unix socket path error: path is occupied by a file that is not a socket")]
+    NotASocket,
+    /// Unable to remove a stale socket file at the provided path
+    #[error("Unix socket path error: unable to remove stale socket file: {}", .0)]
+    Io(std::io::ErrorKind),
 }
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
@@ -33,6 +91,75 @@ pub enum ContextConfigurationError {
         "CoAP context configuration error: attempted to set encryption context while one has already been configured for this encryption variant"
     )]
     CryptoContextAlreadySet,
+    /// An encrypted endpoint exists, but no server-side credentials have been configured
+    #[error("CoAP context configuration error: {} endpoint has no PSK or PKI/RPK credentials configured", .0)]
+    MissingServerCredentials(CoapProtocol),
+    /// The requested feature is not supported by the libcoap build (or its DTLS library) in use
+    #[error("CoAP context configuration error: {} is not supported by the libcoap build in use", .0)]
+    UnsupportedFeature(&'static str),
+    /// The provided maximum token size is outside of the range supported by libcoap
+    #[error("CoAP context configuration error: maximum token size {} is not supported", .0)]
+    InvalidMaxTokenSize(usize),
+    /// The raw context already has application data (e.g., because it is already wrapped by
+    /// another [CoapContext](crate::CoapContext))
+    #[error("CoAP context configuration error: raw context already has application data")]
+    AppDataInUse,
+    /// The given option number could not be registered, as the maximum number of custom options
+    /// supported by libcoap has been reached
+    #[error("CoAP context configuration error: no space left to register custom option {}", .0)]
+    TooManyCustomOptions(CoapOptionNum),
+    /// The given multicast group could not be joined (e.g., because the address is not a multicast
+    /// address or the interface does not exist)
+    #[error("CoAP context configuration error: unable to join multicast group {}", .0)]
+    MulticastJoinFailed(IpAddr),
+    /// A reverse proxy has already been added to the context (see
+    /// [CoapContext::add_reverse_proxy()](crate::CoapContext::add_reverse_proxy))
+    #[error("CoAP context configuration error: a reverse proxy has already been added to this context")]
+    ReverseProxyAlreadySet,
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ContextBuildError {
+    /// The requested configuration is invalid or could not be applied to the context
+    #[error("CoAP context build error: {}", .0)]
+    Configuration(#[from] ContextConfigurationError),
+    /// One of the requested endpoints could not be created
+    #[error("CoAP context build error: unable to create {} endpoint on {}: {}", .0, .1, .2)]
+    Endpoint(CoapProtocol, SocketAddr, EndpointCreationError),
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ContextHandleError {
+    /// The context the handle refers to has already been dropped
+    #[error("CoAP context handle error: context has been dropped")]
+    ContextDropped,
+    /// The pipe (or, on Windows, the pair of sockets) used to interrupt the IO thread of the
+    /// context could not be created
+    #[error("CoAP context handle error: unable to create waker for the IO thread: {}", .0)]
+    WakerCreationFailed(std::io::ErrorKind),
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResourceCreationError {
+    /// Provided resource flags contradict each other
+    #[error("CoAP resource creation error: resource flags {:?} and {:?} cannot be combined", .0, .1)]
+    ContradictoryFlags(ResourceFlags, ResourceFlags),
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ResourceTreeError {
+    /// Path of a node is empty or contains empty segments
+    #[error("CoAP resource tree error: invalid path {:?}", .0)]
+    InvalidPath(String),
+    /// Multiple nodes of the tree have the same path
+    #[error("CoAP resource tree error: multiple nodes for path {:?}", .0)]
+    DuplicatePath(String),
+    /// Context already has a resource with the path (and host) of a node
+    #[error("CoAP resource tree error: context already has a resource for path {:?}", .0)]
+    PathInUse(String),
+    /// Resource of a node could not be created
+    #[error("CoAP resource tree error: unable to create resource for path {:?}: {}", .0, .1)]
+    ResourceCreation(String, #[source] ResourceCreationError),
 }
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
@@ -47,6 +174,37 @@ pub enum IoProcessError {
     /// Unknown error inside of libcoap
     #[error("CoAP IO error: unknown error in call to libcoap")]
     Unknown,
+    /// Waiting for or processing IO failed with the given OS error number (`errno`)
+    #[error("CoAP IO error: {}", std::io::Error::from_raw_os_error(*.0))]
+    Os(i32),
+    /// The context was not started because its configuration is inconsistent (see
+    /// [CoapContext::validate_configuration()](crate::CoapContext::validate_configuration))
+    #[error("CoAP IO error: invalid context configuration: {}", .0)]
+    InvalidConfiguration(ContextConfigurationError),
+    /// The context still had outstanding IO when the timeout provided to
+    /// [CoapContext::shutdown()](crate::CoapContext::shutdown) had passed
+    #[error("CoAP IO error: timeout while waiting for outstanding IO during shutdown")]
+    ShutdownTimeout,
+    /// IO can not be split into a preparation and a completion phase (see
+    /// [CoapContext::prepare_io()](crate::CoapContext::prepare_io)), as libcoap does not use epoll
+    /// for this context
+    #[error("CoAP IO error: split IO processing requires libcoap to use epoll")]
+    SplitIoUnsupported,
+    /// The budget provided to
+    /// [CoapContext::do_io_budgeted()](crate::CoapContext::do_io_budgeted) was 0, which would
+    /// never allow any received message to be handled
+    #[error("CoAP IO error: the budget for handled messages must not be zero")]
+    InvalidBudget,
+}
+
+impl IoProcessError {
+    /// Returns the kind of the underlying OS error, if any.
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            IoProcessError::Os(errno) => Some(std::io::Error::from_raw_os_error(*errno).kind()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
@@ -56,6 +214,64 @@ pub enum SessionGetAppDataError {
     WrongType,
 }
 
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResourceUserDataError {
+    /// The user data is currently borrowed mutably and can therefore not be borrowed again (e.g.,
+    /// because the function was called from within a handler created using
+    /// [CoapRequestHandler::new()](crate::CoapRequestHandler::new) for the same resource).
+    #[error("CoAP resource user data error: user data is already borrowed mutably")]
+    BorrowedMutably,
+    /// The user data is currently borrowed and can therefore not be borrowed mutably.
+    #[error("CoAP resource user data error: user data is already borrowed")]
+    Borrowed,
+}
+
+/// Error returned by the functions of a [CoapResourceHandle](crate::CoapResourceHandle) if its
+/// resource has been removed from its context or if the context has been dropped.
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+#[error("CoAP resource error: resource is no longer part of its context")]
+pub struct ResourceRemoved;
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ContextGetAppDataError {
+    /// Stored application data type differs from requested type
+    #[error("CoAP context application data retrieval error: wrong type")]
+    WrongType,
+    /// The context is currently in use and its application data can therefore not be accessed
+    /// (e.g., because the function was called from within an event handler).
+    #[error("CoAP context application data retrieval error: context is currently in use")]
+    Unavailable,
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum CacheError {
+    /// The cache key could not be derived because the request could not be converted into a PDU
+    #[error("CoAP cache error: invalid request")]
+    InvalidRequest(#[from] MessageConversionError),
+    /// A cache entry for the request already exists
+    #[error("CoAP cache error: cache entry already exists")]
+    AlreadyExists,
+    /// The cache entry has been removed (e.g., because its idle timeout has elapsed)
+    #[error("CoAP cache error: cache entry has been removed")]
+    EntryRemoved,
+    /// Unknown error inside of libcoap
+    #[error("CoAP cache error: unknown error in call to libcoap")]
+    Unknown,
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PersistError {
+    /// Persistence has already been enabled for this context
+    #[error("CoAP persistence error: persistence has already been enabled")]
+    AlreadyEnabled,
+    /// A file path could not be provided to libcoap
+    #[error("CoAP persistence error: invalid file path")]
+    InvalidPath,
+    /// Unknown error inside of libcoap
+    #[error("CoAP persistence error: unknown error in call to libcoap")]
+    Unknown,
+}
+
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum OptionCreationError {
     /// Unknown error inside of libcoap
@@ -64,10 +280,327 @@ pub enum OptionCreationError {
 }
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PskKeyError {
+    /// The key data is empty
+    #[error("CoAP PSK key error: key is empty")]
+    EmptyKey,
+    /// The key data is longer than supported by libcoap (see
+    /// [MAX_PSK_KEY_LENGTH](crate::crypto::psk::MAX_PSK_KEY_LENGTH))
+    #[error("CoAP PSK key error: key length of {} bytes exceeds the maximum supported by libcoap", .0)]
+    KeyTooLong(usize),
+    /// The key has an identity, but it is empty
+    #[error("CoAP PSK key error: identity is empty")]
+    EmptyIdentity,
+    /// The identity of the key is longer than supported by libcoap (see
+    /// [MAX_PSK_IDENTITY_LENGTH](crate::crypto::psk::MAX_PSK_IDENTITY_LENGTH))
+    #[error("CoAP PSK key error: identity length of {} bytes exceeds the maximum supported by libcoap", .0)]
+    IdentityTooLong(usize),
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
 pub enum SessionCreationError {
     /// Unknown error inside of libcoap
     #[error("CoAP session creation error: unknown error in call to libcoap")]
     Unknown,
+    /// The provided URI could not be parsed
+    #[error("CoAP session creation error: invalid URI")]
+    InvalidUri(#[from] UriParsingError),
+    /// The provided URI does not contain a host to connect to
+    #[error("CoAP session creation error: URI does not contain a host")]
+    MissingHost,
+    /// The scheme of the provided URI is not supported by the used connect function
+    #[error("CoAP session creation error: unsupported URI scheme {}", .0)]
+    UnsupportedScheme(CoapUriScheme),
+    /// The host of the provided URI could not be resolved into any socket address
+    #[error("CoAP session creation error: unable to resolve host {}", .0)]
+    UnresolvableHost(String),
+    /// The name lookup for the host of the provided URI failed
+    #[error("CoAP session creation error: name lookup for host {} failed: {}", .0, .1)]
+    DnsFailure(String, std::io::ErrorKind),
+    /// The provided local and remote addresses are not of the same address family
+    #[error("CoAP session creation error: local and remote address have different address families")]
+    AddressFamilyMismatch,
+    /// The local or remote address is an IPv6 link-local address without a zone (scope) ID
+    #[error("CoAP session creation error: link-local address requires a zone ID")]
+    MissingScopeId,
+    /// Unable to bind to the provided local address (e.g., because it is already in use)
+    #[error("CoAP session creation error: unable to bind to local address {}: {}", .0, .1)]
+    BindFailed(SocketAddr, std::io::ErrorKind),
+    /// The provided Unix domain socket path can not be used
+    #[error("CoAP session creation error: invalid Unix socket path")]
+    InvalidUnixPath(#[from] UnixSocketPathError),
+    /// The provided raw session is not a client-side session belonging to the provided context
+    #[error("CoAP session creation error: raw session is not a client-side session of this context")]
+    InvalidRawSession,
+    /// The raw session already has application data (e.g., because it is already wrapped by
+    /// another [CoapClientSession](crate::session::CoapClientSession))
+    #[error("CoAP session creation error: raw session already has application data")]
+    AppDataInUse,
+    /// The libcoap build (or its TLS library) in use does not support the requested transport
+    #[error("CoAP session creation error: transport {} is not supported by the libcoap build in use", .0)]
+    TransportUnsupported(CoapProtocol),
+    /// An encrypted session was requested, but libcoap was built without a TLS library
+    #[error("CoAP session creation error: libcoap was built without a TLS library")]
+    TlsBackendMissing,
+    /// The default pre-shared key of the provided PSK context can not be used with libcoap
+    #[error("CoAP session creation error: invalid default PSK: {}", .0)]
+    InvalidPsk(#[from] PskKeyError),
+    /// The TLS library was unable to set up the (D)TLS session and start its handshake (e.g.,
+    /// because the provided credentials were rejected)
+    #[error("CoAP session creation error: unable to initialize (D)TLS handshake")]
+    HandshakeInitFailure,
+    /// The session was not established before its handshake deadline (see
+    /// [CoapContext::set_handshake_deadline()](crate::CoapContext::set_handshake_deadline))
+    #[error("CoAP session creation error: session was not established before the handshake deadline")]
+    HandshakeTimeout,
+    /// The (D)TLS handshake failed (e.g., because the peer rejected the provided credentials)
+    ///
+    /// libcoap does not report why a handshake failed, the details are therefore taken from the
+    /// log messages of its TLS library integration. They are only available if the log handler of
+    /// this crate is installed (see [install_log_handler()](crate::logging::install_log_handler))
+    /// and the log level includes the relevant messages (which is the case for the default level
+    /// [Warning](crate::logging::CoapLogLevel::Warning) with most TLS libraries).
+    #[error(
+        "CoAP session creation error: (D)TLS handshake failed{}",
+        handshake_failure_details(.alert, .backend_message)
+    )]
+    HandshakeFailed {
+        /// The alert that was sent or received during the handshake, if known
+        alert: Option<CoapTlsAlert>,
+        /// The last message logged by the TLS library integration of libcoap for the session, if
+        /// any
+        backend_message: Option<String>,
+    },
+    /// The memory used by the sessions of the context has reached its limit of the given number of
+    /// bytes (see [CoapContext::set_memory_limits()](crate::CoapContext::set_memory_limits))
+    #[error("CoAP session creation error: sessions have reached their memory limit of {} bytes", .0)]
+    MemoryLimitExceeded(usize),
+}
+
+/// Formats the details of a failed (D)TLS handshake for [SessionCreationError::HandshakeFailed]
+/// and [SessionEstablishError::HandshakeFailed].
+fn handshake_failure_details(alert: &Option<CoapTlsAlert>, backend_message: &Option<String>) -> String {
+    match (alert, backend_message) {
+        (Some(alert), Some(message)) => format!(": {} alert ({})", alert, message),
+        (Some(alert), None) => format!(": {} alert", alert),
+        (None, Some(message)) => format!(": {}", message),
+        (None, None) => String::new(),
+    }
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum OscoreConfError {
+    /// The line with the given number is not of the form `keyword,type,value`
+    #[error("OSCORE configuration error: line {0} is not of the form keyword,type,value")]
+    MalformedLine(usize),
+    /// The field in the given line uses an unknown value type
+    #[error("OSCORE configuration error: unknown value type for field {1} in line {0}")]
+    UnknownType(usize, String),
+    /// The value of the field in the given line could not be parsed using its value type
+    #[error("OSCORE configuration error: invalid value for field {1} in line {0}")]
+    InvalidValue(usize, String),
+    /// libcoap was unable to parse the provided OSCORE configuration
+    #[error("OSCORE configuration error: invalid configuration")]
+    Invalid,
+    /// The given Group OSCORE parameter is invalid (e.g., a key that does not match the
+    /// countersignature algorithm)
+    #[error("OSCORE configuration error: invalid group parameter {}", .0)]
+    InvalidGroupParameter(&'static str),
+    /// The requested feature (OSCORE itself or group mode, see
+    /// [OscoreConf::group_mode_supported()](crate::oscore::OscoreConf::group_mode_supported)) is
+    /// not supported by the libcoap build in use
+    #[error("OSCORE configuration error: {} is not supported by the libcoap build in use", .0)]
+    FeatureUnavailable(&'static str),
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum SessionEstablishError {
+    /// The session did not become established within the provided timeout
+    #[error("CoAP session establishment error: timeout while waiting for session to become established")]
+    Timeout,
+    /// The session failed to connect (e.g., because the (D)TLS handshake failed)
+    #[error("CoAP session establishment error: session failed to connect")]
+    Failed,
+    /// The session was not established before its handshake deadline (see
+    /// [CoapContext::set_handshake_deadline()](crate::CoapContext::set_handshake_deadline))
+    #[error("CoAP session establishment error: session was not established before the handshake deadline")]
+    HandshakeTimeout,
+    /// The (D)TLS handshake of the session failed, see [SessionCreationError::HandshakeFailed]
+    #[error(
+        "CoAP session establishment error: (D)TLS handshake failed{}",
+        handshake_failure_details(.alert, .backend_message)
+    )]
+    HandshakeFailed {
+        /// The alert that was sent or received during the handshake, if known
+        alert: Option<CoapTlsAlert>,
+        /// The last message logged by the TLS library integration of libcoap for the session, if
+        /// any
+        backend_message: Option<String>,
+    },
+    /// An error occurred while performing IO
+    #[error("CoAP session establishment error: error while performing IO")]
+    Io(#[from] IoProcessError),
+}
+
+/// Errors that can occur when connecting using
+/// [CoapContext::connect_with_fallback()](crate::CoapContext::connect_with_fallback).
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum FallbackConnectError {
+    /// A session could not be created
+    #[error("CoAP fallback connection error: unable to create session")]
+    Creation(#[from] SessionCreationError),
+    /// An error occurred while performing IO during the DTLS handshake
+    #[error("CoAP fallback connection error: error while performing IO")]
+    Io(#[from] IoProcessError),
+}
+
+/// Errors that can occur when sending a request and waiting for its response using
+/// [CoapContext::send_and_wait()](crate::CoapContext::send_and_wait).
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum RequestWaitError {
+    /// Unable to send the request
+    #[error("CoAP request wait error: unable to send request")]
+    Send(#[from] MessageConversionError),
+    /// The request suppresses all responses using the No-Response option, so there is nothing to
+    /// wait for
+    #[error("CoAP request wait error: request does not expect a response")]
+    NoResponseExpected,
+    /// The request failed before a response was received (e.g., because it was rejected by the
+    /// peer or its own timeout elapsed)
+    #[error("CoAP request wait error: request failed")]
+    Request(#[from] RequestPollError),
+    /// No response was received within the provided timeout
+    #[error("CoAP request wait error: timeout while waiting for response")]
+    Timeout,
+    /// An error occurred while performing IO
+    #[error("CoAP request wait error: error while performing IO")]
+    Io(#[from] IoProcessError),
+}
+
+/// Errors that can occur when sending a single request using the functions of the
+/// [client](crate::client) module.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ClientRequestError {
+    /// The provided URI could not be parsed
+    #[error("CoAP client request error: invalid URI")]
+    InvalidUri(#[from] UriParsingError),
+    /// The URI requires an encrypted session (`coaps`), but no credentials were provided
+    #[error("CoAP client request error: no credentials provided for encrypted session")]
+    MissingCredentials,
+    /// Unable to create the context used for the request
+    #[error("CoAP client request error: unable to create context")]
+    Context(#[from] ContextConfigurationError),
+    /// Unable to create a session with the host of the URI
+    #[error("CoAP client request error: unable to create session")]
+    Session(#[from] SessionCreationError),
+    /// The session failed to connect (e.g., because the DTLS handshake failed)
+    #[error("CoAP client request error: session failed to connect")]
+    ConnectionFailed,
+    /// Unable to construct the request from the URI and the provided options
+    #[error("CoAP client request error: unable to build request")]
+    RequestBuild(#[from] RequestBuildError),
+    /// Unable to send the request
+    #[error("CoAP client request error: unable to send request")]
+    Send(#[from] MessageConversionError),
+    /// An error occurred while performing IO
+    #[error("CoAP client request error: error while performing IO")]
+    Io(#[from] IoProcessError),
+    /// The peer rejected the request with a Reset message
+    #[error("CoAP client request error: request was rejected with a Reset message")]
+    Reset,
+    /// No response was received within the timeout (including the time required to establish the
+    /// session)
+    #[error("CoAP client request error: request timed out")]
+    TimedOut,
+}
+
+impl From<SessionEstablishError> for ClientRequestError {
+    fn from(value: SessionEstablishError) -> Self {
+        match value {
+            SessionEstablishError::Timeout => ClientRequestError::TimedOut,
+            SessionEstablishError::Failed => ClientRequestError::ConnectionFailed,
+            SessionEstablishError::HandshakeTimeout => ClientRequestError::TimedOut,
+            SessionEstablishError::HandshakeFailed { alert, backend_message } => {
+                ClientRequestError::Session(SessionCreationError::HandshakeFailed { alert, backend_message })
+            },
+            SessionEstablishError::Io(e) => ClientRequestError::Io(e),
+        }
+    }
+}
+
+impl From<RequestWaitError> for ClientRequestError {
+    fn from(value: RequestWaitError) -> Self {
+        match value {
+            RequestWaitError::Send(e) => ClientRequestError::Send(e),
+            RequestWaitError::NoResponseExpected | RequestWaitError::Timeout => ClientRequestError::TimedOut,
+            RequestWaitError::Request(e) => e.into(),
+            RequestWaitError::Io(e) => ClientRequestError::Io(e),
+        }
+    }
+}
+
+impl From<RequestPollError> for ClientRequestError {
+    fn from(value: RequestPollError) -> Self {
+        match value {
+            RequestPollError::TimedOut => ClientRequestError::TimedOut,
+            RequestPollError::Reset => ClientRequestError::Reset,
+            RequestPollError::SessionFailed
+            | RequestPollError::SessionDisconnected
+            | RequestPollError::SessionClosed
+            | RequestPollError::SendFailed => ClientRequestError::ConnectionFailed,
+            RequestPollError::Aborted => ClientRequestError::TimedOut,
+        }
+    }
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PingError {
+    /// The session is not (yet) established, so no ping can be sent
+    #[error("CoAP ping error: session is not established")]
+    SessionNotEstablished,
+    /// Unknown error inside of libcoap
+    #[error("CoAP ping error: unknown error in call to libcoap")]
+    Unknown,
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RequestPollError {
+    /// No response was received before the timeout of the request elapsed (see
+    /// [CoapRequest::set_timeout()](crate::message::CoapRequest::set_timeout)).
+    #[error("CoAP request error: request timed out")]
+    TimedOut,
+    /// The peer rejected the (confirmable) request by responding with a Reset message.
+    #[error("CoAP request error: request was rejected with a Reset message")]
+    Reset,
+    /// The session the request was sent on was not established before its handshake deadline
+    /// (see [CoapContext::set_handshake_deadline()](crate::CoapContext::set_handshake_deadline)).
+    #[error("CoAP request error: session was not established before the handshake deadline")]
+    SessionFailed,
+    /// The session the request was sent on has been disconnected (see
+    /// [CoapServerSession::disconnect()](crate::session::CoapServerSession::disconnect)).
+    #[error("CoAP request error: session has been disconnected")]
+    SessionDisconnected,
+    /// The request was aborted before it received a response (see
+    /// [CoapContext::abort_pending()](crate::CoapContext::abort_pending)).
+    #[error("CoAP request error: request was aborted")]
+    Aborted,
+    /// The peer (or a router on the path to it) reported that the request could not be delivered
+    /// using an ICMP Destination Unreachable error, e.g., because the peer does not listen on the
+    /// port of the session (see
+    /// [CoapSessionCommon::set_icmp_error_policy()](crate::session::CoapSessionCommon::set_icmp_error_policy)).
+    #[error("CoAP request error: peer is unreachable (ICMP error)")]
+    IcmpUnreachable,
+    /// The connection of the session was closed while the request was queued because of the
+    /// outstanding request limit (see
+    /// [CoapSessionCommon::set_max_outstanding_requests()](crate::session::CoapSessionCommon::set_max_outstanding_requests)).
+    #[error("CoAP request error: session was closed before the request was sent")]
+    SessionClosed,
+    /// The request was queued because of the outstanding request limit, but could not be sent
+    /// once a slot became available, or the request is an observation that could not be
+    /// re-registered after its session was re-established (see
+    /// [ReconnectPolicy::reregister_observations](crate::session::ReconnectPolicy::reregister_observations)).
+    #[error("CoAP request error: request could not be sent again")]
+    SendFailed,
 }
 
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
@@ -125,6 +658,31 @@ pub enum UriParsingError {
     ContainsNullByte(#[from] NulError),
 }
 
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ClientSniError {
+    /// Provided server name is empty.
+    #[error("Server name indication must not be empty")]
+    Empty,
+    /// Provided server name contains a null byte.
+    #[error("Server name indication contains a null byte")]
+    ContainsNullByte(#[from] NulError),
+    /// Provided server name contains non-ASCII characters and must be converted into its ASCII
+    /// form (A-labels, see RFC 5890) first.
+    #[error("Server name indication contains non-ASCII characters, internationalized domain names must be provided in their ASCII form")]
+    NonAscii,
+    /// Provided server name is an IP address literal, which is not permitted in server name
+    /// indications (RFC 6066, Section 3).
+    #[error("Server name indication must not be an IP address literal")]
+    IpAddress,
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TlsSetupHookError {
+    /// The TLS library libcoap was built with does not invoke TLS setup hooks.
+    #[error("CoAP TLS setup hook error: hooks are not supported by the TLS library in use ({0})")]
+    UnsupportedLibrary(TlsLibrary),
+}
+
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
 pub enum MessageConversionError {
     /// Value of an option is invalid.
@@ -149,9 +707,65 @@ pub enum MessageConversionError {
     /// Message has no token.
     #[error("CoAP message conversion error: token missing")]
     MissingToken,
+    /// Message token is longer than supported by libcoap or the peer.
+    #[error("CoAP message conversion error: token of length {} is too long", .0)]
+    TokenTooLong(usize),
+    /// Message token is already used by another request on the same session that is still awaiting
+    /// responses.
+    #[error("CoAP message conversion error: token is already in use by an outstanding request")]
+    TokenInUse,
+    /// The token generator of the context did not generate a token that is not already used by
+    /// an outstanding request on the same session (see
+    /// [CoapContext::set_token_generator()](crate::CoapContext::set_token_generator))
+    #[error("CoAP message conversion error: unable to generate an unused token")]
+    TokenGenerationFailed,
+    /// The session is reconnecting and the maximum number of requests that may be sent while
+    /// reconnecting has been reached (see
+    /// [ReconnectPolicy::max_queued_requests](crate::session::ReconnectPolicy::max_queued_requests)).
+    #[error("CoAP message conversion error: session is reconnecting")]
+    SessionReconnecting,
+    /// The session was not established before its handshake deadline (see
+    /// [CoapContext::set_handshake_deadline()](crate::CoapContext::set_handshake_deadline)).
+    #[error("CoAP message conversion error: session was not established before the handshake deadline")]
+    SessionFailed,
+    /// The session has been disconnected (see
+    /// [CoapServerSession::disconnect()](crate::session::CoapServerSession::disconnect)).
+    #[error("CoAP message conversion error: session has been disconnected")]
+    SessionDisconnected,
+    /// The session already has the maximum number of pending requests (see
+    /// [CoapContext::set_max_pending_requests()](crate::CoapContext::set_max_pending_requests))
+    #[error("CoAP message conversion error: session already has the maximum of {} pending requests", .0)]
+    TooManyPendingRequests(usize),
+    /// The memory used by the pending requests of the context has reached its limit of the given
+    /// number of bytes (see
+    /// [CoapContext::set_memory_limits()](crate::CoapContext::set_memory_limits))
+    #[error("CoAP message conversion error: pending requests have reached their memory limit of {} bytes", .0)]
+    MemoryLimitExceeded(usize),
+    /// The send queue of the session has reached its high-water mark (see
+    /// [CoapContext::set_send_queue_limits()](crate::CoapContext::set_send_queue_limits))
+    #[error("CoAP message conversion error: send queue of the session is full ({} bytes queued)", .0)]
+    SendQueueFull(usize),
+    /// The session already has the maximum number of outstanding requests and its request queue
+    /// is full (see
+    /// [CoapSessionCommon::set_max_outstanding_requests()](crate::session::CoapSessionCommon::set_max_outstanding_requests))
+    #[error("CoAP message conversion error: request queue of the session is full ({} requests queued)", .0)]
+    RequestQueueFull(usize),
+    /// A received request exceeds the input limits of the resource it is addressed to (see the
+    /// [limits](crate::limits) module).
+    #[error("CoAP message conversion error: {}", .0)]
+    InputLimitExceeded(CoapInputLimitExceeded),
+    /// A received datagram of the given size filled the entire receive buffer of libcoap and was
+    /// dropped, as it may have been truncated (see
+    /// [CoapContext::bad_packet_count()](crate::CoapContext::bad_packet_count)).
+    #[error("CoAP message conversion error: datagram of size {} may have been truncated", .0)]
+    TruncatedDatagram(usize),
     /// Message has no ID.
     #[error("CoAP message conversion error: message id missing")]
     MissingMessageId,
+    /// A response of the given message type cannot be sent in reply to the request it belongs
+    /// to (e.g., an ACK in reply to a non-confirmable request).
+    #[error("CoAP message conversion error: response of type {:?} cannot be sent in reply to the request", .0)]
+    InvalidResponseType(CoapMessageType),
     /// Two (or more) options were combined which must not be combined (e.g., Proxy-Scheme and
     /// Proxy-URI).
     #[error("CoAP message conversion error: options {:?} and {:?} cannot be combined", .0, .1)]
@@ -160,6 +774,16 @@ pub enum MessageConversionError {
     /// was not recognized).
     #[error("CoAP option identified as critical but not recognized")]
     CriticalOptionUnrecognized,
+    /// Message (of the given size) exceeds the maximum message size the peer of a reliable session
+    /// announced in its CSM (the second value), see
+    /// [CoapSessionCommon::peer_max_message_size()](crate::session::CoapSessionCommon::peer_max_message_size).
+    #[error("CoAP message conversion error: message of size {} exceeds maximum message size {} of peer", .0, .1)]
+    MessageTooLarge(usize, usize),
+    /// Message (of the given size) was rejected by the socket because it exceeds the (path) MTU,
+    /// e.g., after an ICMP "Fragmentation Needed" or "Packet Too Big" error was received (see
+    /// [IcmpErrorPolicy::reduce_mtu](crate::session::IcmpErrorPolicy::reduce_mtu)).
+    #[error("CoAP message conversion error: message of size {} is too large for the path to the peer", .0)]
+    PacketTooBig(usize),
     /// Unknown error inside of libcoap.
     #[error("unknown CoAP message conversion error")]
     Unknown,
@@ -171,6 +795,209 @@ impl From<UriParsingError> for MessageConversionError {
     }
 }
 
+/// Error returned by [CoapMessage::from_bytes()](crate::message::CoapMessage::from_bytes) if the
+/// provided bytes are not a valid encoding of a CoAP message.
+///
+/// Offsets refer to the position in the provided bytes at which the error was detected.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum MessageParsingError {
+    /// Messages cannot be encoded for the given transport (i.e., [CoapProtocol::None]).
+    #[error("CoAP message parsing error: unsupported protocol {}", .0)]
+    UnsupportedProtocol(CoapProtocol),
+    /// The input ends before the end of the message header.
+    #[error("CoAP message parsing error: truncated header")]
+    TruncatedHeader,
+    /// The version field of a datagram is not 1.
+    #[error("CoAP message parsing error: unsupported version {}", .0)]
+    UnsupportedVersion(u8),
+    /// The length field of a TCP/TLS message does not match the length of the input (the first
+    /// value is the announced length of options and payload, the second one the actual length).
+    #[error("CoAP message parsing error: announced length {} does not match actual length {}", .0, .1)]
+    LengthMismatch(usize, usize),
+    /// The length field of a WebSocket message is not zero (see
+    /// [RFC 8323, Section 4.2](https://datatracker.ietf.org/doc/html/rfc8323#section-4.2)).
+    #[error("CoAP message parsing error: non-zero length field in WebSocket message")]
+    InvalidLengthField,
+    /// The token length field has the reserved value 15.
+    #[error("CoAP message parsing error: reserved token length")]
+    ReservedTokenLength,
+    /// The token is longer than supported by libcoap.
+    #[error("CoAP message parsing error: token of length {} is too long", .0)]
+    TokenTooLong(usize),
+    /// The input ends before the end of the token.
+    #[error("CoAP message parsing error: truncated token")]
+    TruncatedToken,
+    /// The message code is neither the empty code nor a request or response code.
+    #[error("CoAP message parsing error: invalid message code {}.{:02}", .0 >> 5, .0 & 0x1F)]
+    InvalidCode(u8),
+    /// A message with code 0.00 (Empty) has a token, options or a payload.
+    #[error("CoAP message parsing error: empty message with content")]
+    ContentInEmptyMessage,
+    /// The option delta at the given offset has the reserved value 15, or the resulting option
+    /// number exceeds the range of option numbers.
+    #[error("CoAP message parsing error: bad option delta at offset {}", .0)]
+    BadOptionDelta(usize),
+    /// The option length at the given offset has the reserved value 15.
+    #[error("CoAP message parsing error: bad option length at offset {}", .0)]
+    BadOptionLength(usize),
+    /// The input ends before the end of the option at the given offset.
+    #[error("CoAP message parsing error: truncated option at offset {}", .0)]
+    TruncatedOption(usize),
+    /// The payload marker is not followed by a payload (see
+    /// [RFC 7252, Section 3](https://datatracker.ietf.org/doc/html/rfc7252#section-3)).
+    #[error("CoAP message parsing error: payload marker without payload")]
+    PayloadMarkerWithoutPayload,
+    /// The message is well-formed, but could not be converted into a [CoapMessage](crate::message::CoapMessage)
+    /// (e.g., because an option has an invalid value).
+    #[error("CoAP message parsing error: message conversion failed")]
+    Conversion(#[from] MessageConversionError),
+    /// The message was rejected by libcoap for another reason.
+    #[error("CoAP message parsing error: message rejected by libcoap")]
+    Rejected,
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum RequestBuildError {
+    /// Value of an option is shorter than allowed for this option type.
+    #[error("CoAP request build error: value of length {} too short for option {:?}", .1, .0)]
+    OptionValueTooShort(CoapOptionType, usize),
+    /// Value of an option is longer than allowed for this option type.
+    #[error("CoAP request build error: value of length {} too long for option {:?}", .1, .0)]
+    OptionValueTooLong(CoapOptionType, usize),
+    /// Two options were combined which must not be combined (e.g., Proxy-Uri and Uri-Path).
+    #[error("CoAP request build error: options {:?} and {:?} cannot be combined", .0, .1)]
+    InvalidOptionCombination(CoapOptionType, CoapOptionType),
+    /// A payload was provided for a request method that does not define payload semantics, but no
+    /// Content-Format was set.
+    #[error("CoAP request build error: payload for {:?} request without Content-Format", .0)]
+    PayloadWithoutContentFormat(CoapRequestCode),
+    /// The provided token is longer than the maximum token size supported by libcoap.
+    #[error("CoAP request build error: token of length {} is too long", .0)]
+    TokenTooLong(usize),
+    /// The value of an option is outside of the range allowed for its option type (e.g., a
+    /// Hop-Limit of 0).
+    #[error("CoAP request build error: illegal value for option {:?}", .0)]
+    IllegalOptionValue(CoapOptionType),
+    /// An option that is only defined for responses (e.g., Max-Age) was set for a request.
+    #[error("CoAP request build error: option {:?} is only allowed in responses", .0)]
+    OptionNotAllowedInRequest(CoapOptionType),
+    /// The request URI could not be constructed from the provided path, query or proxy URI.
+    #[error("CoAP request build error: invalid request URI")]
+    InvalidUri(#[from] UriParsingError),
+    /// The typed payload could not be encoded (see
+    /// [CoapRequestBuilder::json_payload()](crate::message::CoapRequestBuilder::json_payload)).
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    #[error("CoAP request build error: {}", .0)]
+    PayloadEncoding(#[from] PayloadEncodeError),
+}
+
+/// Error returned by [CoapRequest::preferred_format()](crate::message::CoapRequest::preferred_format)
+/// if none of the supported content formats is acceptable for the client.
+///
+/// Contains the content format requested by the client (if any).
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+#[error("CoAP content negotiation error: requested content format {:?} is not supported", .0)]
+pub struct NotAcceptable(pub Option<CoapContentFormat>);
+
+impl From<NotAcceptable> for CoapResponseCode {
+    fn from(_: NotAcceptable) -> Self {
+        CoapResponseCode::NotAcceptable
+    }
+}
+
+impl From<NotAcceptable> for CoapMessageCode {
+    fn from(_: NotAcceptable) -> Self {
+        CoapMessageCode::Response(CoapResponseCode::NotAcceptable)
+    }
+}
+
+/// Error returned when decoding a typed payload (see
+/// [CoapRequest::parse_payload()](crate::message::CoapRequest::parse_payload)).
+///
+/// Can be converted into the response code that a server should answer the request with.
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum PayloadDecodeError {
+    /// The message has no Content-Format or one that is not supported by the enabled serde
+    /// features (results in 4.15 (Unsupported Content-Format))
+    #[error("CoAP payload decoding error: unsupported content format {:?}", .0)]
+    UnsupportedContentFormat(Option<CoapContentFormat>),
+    /// The payload could not be deserialized using its content format (results in 4.00 (Bad
+    /// Request))
+    #[error("CoAP payload decoding error: invalid {:?} payload: {}", .0, .1)]
+    InvalidPayload(CoapContentFormat, String),
+}
+
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+impl From<PayloadDecodeError> for CoapResponseCode {
+    fn from(error: PayloadDecodeError) -> Self {
+        match error {
+            PayloadDecodeError::UnsupportedContentFormat(_) => CoapResponseCode::UnsupportedContentFormat,
+            PayloadDecodeError::InvalidPayload(..) => CoapResponseCode::BadRequest,
+        }
+    }
+}
+
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+impl From<PayloadDecodeError> for CoapMessageCode {
+    fn from(error: PayloadDecodeError) -> Self {
+        CoapMessageCode::Response(error.into())
+    }
+}
+
+/// Error returned when encoding a typed payload (see
+/// [CoapResponse::set_typed_payload()](crate::message::CoapResponse::set_typed_payload)).
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum PayloadEncodeError {
+    /// The content format is not supported by the enabled serde features
+    #[error("CoAP payload encoding error: unsupported content format {:?}", .0)]
+    UnsupportedContentFormat(CoapContentFormat),
+    /// The value could not be serialized using the content format
+    #[error("CoAP payload encoding error: failed to serialize {:?} payload: {}", .0, .1)]
+    Serialization(CoapContentFormat, String),
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PagedResponseError {
+    /// The requested block starts after the end of the representation.
+    #[error("CoAP paged response error: requested block at offset {} is out of range", .0)]
+    BlockOutOfRange(usize),
+    /// The ETag of the representation changed while a block-wise transfer was in progress
+    /// (only reported in strict mode).
+    #[error("CoAP paged response error: ETag changed during block-wise transfer")]
+    ETagChanged,
+    /// The page provider returned more data than requested (only reported in strict mode).
+    #[error(
+        "CoAP paged response error: page at offset {} has length {}, but at most {} bytes were requested",
+        .0,
+        .1,
+        .2
+    )]
+    PageTooLong(usize, usize, usize),
+    /// The page provider returned no data for an offset it previously indicated to have data for
+    /// (only reported in strict mode).
+    #[error("CoAP paged response error: no page available at offset {} although more data was announced", .0)]
+    InconsistentPageSize(usize),
+}
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AsyncRequestError {
+    /// The request is not the one whose request handler is currently called for the session.
+    #[error("CoAP asynchronous request error: request is not currently handled on this session")]
+    NotHandled,
+    /// libcoap refused to register the request, e.g., because a request with the same token is
+    /// already registered as an asynchronous request.
+    #[error("CoAP asynchronous request error: request could not be registered")]
+    AlreadyRegistered,
+    /// The request has already been answered.
+    #[error("CoAP asynchronous request error: request has already been answered")]
+    AlreadyCompleted,
+    /// The session of the request was closed before the request was answered.
+    #[error("CoAP asynchronous request error: session of the request has been closed")]
+    SessionClosed,
+}
+
 #[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MessageCodeError {
     /// Provided message code for request was not a request code.
@@ -187,3 +1014,33 @@ pub enum MessageTypeError {
     #[error("message type {:?} cannot be used for this message code", .0)]
     InvalidForMessageCode(CoapMessageType),
 }
+
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StartupError {
+    /// libcoap has already been started with a different configuration
+    #[error("libcoap startup error: libcoap has already been started with a different configuration")]
+    AlreadyStarted,
+    /// The requested feature is not supported by the libcoap build in use
+    #[error("libcoap startup error: {} is not supported by the libcoap build in use", .0)]
+    UnsupportedFeature(&'static str),
+    /// libcoap rejected the provided TLS engine configuration
+    #[error("libcoap startup error: unable to apply TLS engine configuration")]
+    TlsEngineConfiguration,
+}
+
+#[cfg(feature = "test-util")]
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum TestPairError {
+    /// Unable to create or configure the server or client context
+    #[error("CoAP test pair error: unable to create context: {}", .0)]
+    Context(#[from] ContextConfigurationError),
+    /// Unable to create the server endpoint
+    #[error("CoAP test pair error: unable to create server endpoint: {}", .0)]
+    Endpoint(#[from] EndpointCreationError),
+    /// Unable to create the client session
+    #[error("CoAP test pair error: unable to create client session: {}", .0)]
+    Session(#[from] SessionCreationError),
+    /// Unable to create the socket of the test link
+    #[error("CoAP test pair error: unable to create link socket: {}", .0)]
+    Link(std::io::ErrorKind),
+}
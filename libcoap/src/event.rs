@@ -10,15 +10,50 @@
 //! Event handling-related code
 
 use std::fmt::Debug;
+use std::net::SocketAddr;
 
-use libcoap_sys::{coap_event_t, coap_session_get_context, coap_session_t};
+use libcoap_sys::{
+    coap_event_t, coap_mid_t, coap_nack_reason_t, coap_pdu_get_token, coap_pdu_t, coap_session_get_context,
+    coap_session_t,
+};
 use libcoap_sys::{coap_session_get_type, coap_session_type_t};
 
 use crate::context::CoapContext;
-use crate::session::CoapSession;
+use crate::error::RequestPollError;
+#[cfg(dtls)]
+use crate::error::SessionEstablishError;
+use crate::limits::CoapInputLimitExceeded;
+use crate::message::coap_pdu_get_raw_code;
+use crate::resource::CoapObserver;
+#[cfg(feature = "async")]
+use crate::session::release_async_requests;
+use crate::session::{
+    dequeue_sent_message, fail_request, is_wrapped_raw_session, record_stats, reregister_observations,
+    CoapRetransmission, CoapSession, CoapSessionCommon,
+};
+use crate::transport::CoapEndpointHandle;
+use crate::types::CoapMessageId;
+use crate::unwind::catch_session_callback_panic;
 
 use crate::session::CoapServerSession;
 
+/// Phases of an endpoint rebinding started using [CoapContext::rebind_endpoint()].
+///
+/// Phases are reported to [CoapEventHandler::handle_endpoint_rebind()] in the order they are listed
+/// here.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CoapEndpointRebindPhase {
+    /// The new endpoint has been created and accepts new sessions.
+    Bound,
+    /// The old endpoint no longer accepts new sessions, existing sessions on the old endpoint are
+    /// served until the grace period ends.
+    Draining,
+    /// The grace period has ended and the old endpoint has been removed alongside all of its
+    /// sessions.
+    Removed,
+}
+
 /// Trait for CoAP event handlers.
 ///
 /// Implementations of this trait can be provided to a [CoapContext] to handle various events relating
@@ -74,7 +109,9 @@ pub trait CoapEventHandler: Debug {
 
     /// Handle a session closed event.
     ///
-    /// This event is triggered by CSM exchanges only when reliable protocols are used.
+    /// This event is triggered by CSM exchanges only when reliable protocols are used, and for
+    /// server-side sessions that were disconnected using
+    /// [CoapServerSession::disconnect()] (for all protocols).
     #[allow(unused_variables)]
     fn handle_session_closed(&mut self, session: &mut CoapSession) {}
 
@@ -84,6 +121,16 @@ pub trait CoapEventHandler: Debug {
     #[allow(unused_variables)]
     fn handle_session_failed(&mut self, session: &mut CoapSession) {}
 
+    /// Handle a reliable session that failed while waiting for the Capabilities and Settings
+    /// Message (CSM) of its peer.
+    ///
+    /// This event is triggered (after [CoapEventHandler::handle_session_failed()]) if the peer did
+    /// not send its CSM within the CSM timeout (see
+    /// [CoapContext::set_csm_timeout()]), or if the connection was closed before the CSM was
+    /// received.
+    #[allow(unused_variables)]
+    fn handle_csm_timeout(&mut self, session: &mut CoapSession) {}
+
     /// Handle a partially received message.
     #[allow(unused_variables)]
     fn handle_partial_block(&mut self, session: &mut CoapSession) {}
@@ -116,13 +163,40 @@ pub trait CoapEventHandler: Debug {
     /// Note that this only refers to packets that can't be parsed by libcoap, i.e. valid packets
     /// that have some semantic issues and therefore can't be parsed into a request or response
     /// object do not trigger this event.
+    ///
+    /// The peer that sent the packet can be determined using
+    /// [CoapSessionCommon::addr_remote()](crate::session::CoapSessionCommon::addr_remote), the
+    /// total number of dropped packets is available using [CoapContext::bad_packet_count()].
     #[allow(unused_variables)]
     fn handle_bad_packet(&mut self, session: &mut CoapSession) {}
 
+    /// Handle a datagram of the given size that was dropped because it was truncated by the
+    /// socket, as it exceeded the maximum datagram size of libcoap (see
+    /// [CoapContext::bad_packet_count()] for the libcoap builds this is detected with).
+    ///
+    /// The request handler (or, for responses, the client session) never sees these datagrams.
+    /// The peer that sent the datagram can be determined using
+    /// [CoapSessionCommon::addr_remote()](crate::session::CoapSessionCommon::addr_remote), the
+    /// datagram is also counted by [CoapContext::bad_packet_count()].
+    #[allow(unused_variables)]
+    fn handle_truncated_datagram(&mut self, session: &mut CoapSession, len: usize) {}
+
     /// Handle a retransmission event.
     #[allow(unused_variables)]
     fn handle_msg_retransmitted(&mut self, session: &mut CoapSession) {}
 
+    /// Handle a retransmission of a confirmable message sent using the given session.
+    ///
+    /// This is called after [CoapEventHandler::handle_msg_retransmitted()] if the retransmitted
+    /// message could be determined, which is the case for messages counted by
+    /// [CoapSessionCommon::queued_pdus()](crate::session::CoapSessionCommon::queued_pdus).
+    /// `retransmission` contains the number of the retransmission and the timeout that elapsed
+    /// before it (see [CoapRetransmission]), which can be used to evaluate congestion control
+    /// schemes. See also
+    /// [CoapSessionCommon::transmission_state()](crate::session::CoapSessionCommon::transmission_state).
+    #[allow(unused_variables)]
+    fn handle_retransmission(&mut self, session: &mut CoapSession, retransmission: &CoapRetransmission) {}
+
     /// Handle an OSCORE decryption failure event.
     #[allow(unused_variables)]
     fn handle_oscore_decryption_failure(&mut self, session: &mut CoapSession) {}
@@ -164,24 +238,244 @@ pub trait CoapEventHandler: Debug {
     /// Handle a failure to perform a keepalive (no response to keepalive packet)
     #[allow(unused_variables)]
     fn handle_keepalive_failure(&mut self, session: &mut CoapSession) {}
+
+    /// Handle the receipt of a pong, i.e., the answer to a ping sent using
+    /// [CoapSessionCommon::send_ping()](crate::session::CoapSessionCommon::send_ping).
+    ///
+    /// For unreliable transports (UDP/DTLS), the pong is the empty ACK (or RST) for the ping and
+    /// `mid` is the message ID returned by `send_ping()`.
+    /// For reliable transports (TCP/TLS), the pong is a 7.03 Pong signaling message, which does not
+    /// have a message ID (`mid` will be set to 0 in this case).
+    #[allow(unused_variables)]
+    fn handle_pong(&mut self, session: &mut CoapSession, mid: CoapMessageId) {}
+
+    /// Handle a ping that was not answered by the peer.
+    ///
+    /// This event is only triggered for unreliable transports (UDP/DTLS), after the ping has been
+    /// retransmitted the maximum number of times (see
+    /// [CoapSessionCommon::max_retransmit()](crate::session::CoapSessionCommon::max_retransmit))
+    /// without receiving a pong.
+    /// `mid` is the message ID returned by
+    /// [CoapSessionCommon::send_ping()](crate::session::CoapSessionCommon::send_ping).
+    #[allow(unused_variables)]
+    fn handle_ping_timeout(&mut self, session: &mut CoapSession, mid: CoapMessageId) {}
+
+    /// Handle a session whose send queue has drained to the low-water mark after having reached
+    /// the high-water mark (see
+    /// [CoapContext::set_send_queue_limits()](crate::CoapContext::set_send_queue_limits)).
+    ///
+    /// After this event, requests and notifications can be sent using the session again.
+    #[allow(unused_variables)]
+    fn handle_send_queue_drained(&mut self, session: &mut CoapSession) {}
+
+    /// Handle the removal of an observer of one of the resources of this context because a
+    /// confirmable notification sent to it was not acknowledged (even after retransmitting it).
+    ///
+    /// libcoap ends the observation at this point, the observer is no longer listed by
+    /// [CoapResource::observers()](crate::CoapResource::observers). How often observers are sent
+    /// confirmable notifications is determined by the
+    /// [NotificationTypePolicy](crate::NotificationTypePolicy) of the resource.
+    #[allow(unused_variables)]
+    fn handle_observer_unreachable(&mut self, session: &mut CoapServerSession, observer: &CoapObserver) {}
+
+    /// Handle a change of the remote address of a session.
+    ///
+    /// This event is triggered if a request is received on an existing session from a different
+    /// address than before, e.g. because a DTLS peer using Connection IDs was rebound to a new
+    /// address by a NAT. The session keeps its [CoapSessionId](crate::session::CoapSessionId),
+    /// so application state should be associated with
+    /// [CoapSessionCommon::id()](crate::session::CoapSessionCommon::id) instead of the address.
+    #[allow(unused_variables)]
+    fn handle_address_changed(&mut self, session: &mut CoapSession, old_addr: SocketAddr, new_addr: SocketAddr) {}
+
+    /// Handle a response that was received from an address other than the one the request was sent
+    /// to.
+    ///
+    /// `accepted` indicates whether the response was passed on to the request or dropped according
+    /// to the session's [CoapResponseAddressPolicy](crate::session::CoapResponseAddressPolicy).
+    /// Frequent mismatches may indicate spoofing attempts.
+    #[allow(unused_variables)]
+    fn handle_response_address_mismatch(
+        &mut self,
+        session: &mut CoapSession,
+        expected_addr: SocketAddr,
+        actual_addr: SocketAddr,
+        accepted: bool,
+    ) {
+    }
+
+    /// Handle a request that was rejected because it exceeded the input limits of the resource it
+    /// was addressed to (see the [limits](crate::limits) module).
+    ///
+    /// The request is answered with [CoapInputLimitExceeded::response_code()] after this function
+    /// returns. As regular clients rarely exceed these limits, rejections may indicate an attack
+    /// (e.g., a fuzzer) and can be used to block offending peers.
+    #[allow(unused_variables)]
+    fn handle_input_limit_exceeded(&mut self, session: &mut CoapServerSession, exceeded: &CoapInputLimitExceeded) {}
+
+    /// Handle a phase change of an endpoint rebinding started using
+    /// [CoapContext::rebind_endpoint()].
+    ///
+    /// `old_endpoint` refers to the endpoint that is being replaced, `new_endpoint` to the
+    /// endpoint replacing it.
+    #[allow(unused_variables)]
+    fn handle_endpoint_rebind(
+        &mut self,
+        old_endpoint: CoapEndpointHandle,
+        new_endpoint: CoapEndpointHandle,
+        phase: CoapEndpointRebindPhase,
+    ) {
+    }
+
+    /// Handle a fallback from DTLS to unencrypted CoAP over UDP performed by
+    /// [CoapContext::connect_with_fallback()].
+    ///
+    /// `session` is the newly created unencrypted session, `dtls_error` the reason why the DTLS
+    /// session could not be established. This can be used to keep an audit log of connections that
+    /// are not protected by DTLS.
+    #[cfg(dtls)]
+    #[allow(unused_variables)]
+    fn handle_dtls_fallback(&mut self, session: &mut CoapSession, dtls_error: &SessionEstablishError) {}
 }
 
 // This should be fine as we don't provide this type to an FFI function, we only read from it.
 #[allow(improper_ctypes_definitions)]
 pub(crate) unsafe extern "C" fn event_handler_callback(raw_session: *mut coap_session_t, event: coap_event_t) -> i32 {
-    let raw_session_type = coap_session_get_type(raw_session);
-
-    let session: CoapSession = if event == coap_event_t::COAP_EVENT_SERVER_SESSION_NEW
-        || (event == coap_event_t::COAP_EVENT_TCP_CONNECTED
-            && raw_session_type == coap_session_type_t::COAP_SESSION_TYPE_SERVER)
-    {
-        CoapServerSession::initialize_raw(raw_session).into()
-    } else {
-        CoapSession::from_raw(raw_session)
-    };
-
-    // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
-    let context = CoapContext::from_raw(coap_session_get_context(raw_session));
-    context.handle_event(session, event);
-    0
+    catch_session_callback_panic(raw_session, 0, || {
+        let raw_session_type = coap_session_get_type(raw_session);
+
+        let session: CoapSession = if event == coap_event_t::COAP_EVENT_SERVER_SESSION_NEW
+            || (event == coap_event_t::COAP_EVENT_TCP_CONNECTED
+                && raw_session_type == coap_session_type_t::COAP_SESSION_TYPE_SERVER)
+        {
+            CoapServerSession::initialize_raw(raw_session).into()
+        } else if is_wrapped_raw_session(raw_session) {
+            CoapSession::from_raw(raw_session)
+        } else {
+            // Events for raw sessions that are not managed by this wrapper are ignored (see
+            // CoapContext::from_raw()).
+            return 0;
+        };
+
+        // Asynchronous requests of server-side sessions that are closed can no longer be answered.
+        #[cfg(feature = "async")]
+        if let CoapSession::Server(server_session) = &session {
+            if matches!(
+                event,
+                coap_event_t::COAP_EVENT_SESSION_CLOSED
+                    | coap_event_t::COAP_EVENT_SESSION_FAILED
+                    | coap_event_t::COAP_EVENT_TCP_CLOSED
+                    | coap_event_t::COAP_EVENT_TCP_FAILED
+                    | coap_event_t::COAP_EVENT_DTLS_CLOSED
+                    | coap_event_t::COAP_EVENT_DTLS_ERROR
+                    | coap_event_t::COAP_EVENT_WS_CLOSED
+                    | coap_event_t::COAP_EVENT_SERVER_SESSION_DEL
+            ) {
+                // SAFETY: libcoap does not process async states while reporting session events.
+                release_async_requests(server_session);
+            }
+        }
+
+        // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+        let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+        // Server-side session wrappers must not be retained here, as they are dropped exclusively
+        // once libcoap deletes their raw session.
+        let client_session = match &session {
+            CoapSession::Client(client_session) => Some(client_session.clone()),
+            _ => None,
+        };
+        context.handle_event(session, event);
+        // Registrations are sent after the event has been handled, as sending them may call
+        // functions of the context.
+        if let Some(client_session) = client_session {
+            reregister_observations(&client_session);
+        }
+        0
+    })
+}
+
+pub(crate) unsafe extern "C" fn pong_handler_callback(
+    raw_session: *mut coap_session_t,
+    _received: *const coap_pdu_t,
+    mid: coap_mid_t,
+) {
+    catch_session_callback_panic(raw_session, (), || {
+        if !is_wrapped_raw_session(raw_session) {
+            return;
+        }
+        let session = CoapSession::from_raw(raw_session);
+        record_stats(&session, |stats| stats.record_received(0));
+        // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+        let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+        context.handle_pong(session, mid);
+    })
+}
+
+pub(crate) unsafe extern "C" fn nack_handler_callback(
+    raw_session: *mut coap_session_t,
+    sent: *const coap_pdu_t,
+    reason: coap_nack_reason_t,
+    mid: coap_mid_t,
+) {
+    catch_session_callback_panic(raw_session, (), || {
+        if !is_wrapped_raw_session(raw_session) {
+            return;
+        }
+        let session = CoapSession::from_raw(raw_session);
+        if reason == coap_nack_reason_t::COAP_NACK_ICMP_ISSUE {
+            // libcoap keeps the message in order to retransmit it, so it has neither failed to be
+            // delivered nor been removed from the send queue yet.
+            if session.icmp_error_policy().fail_requests && !sent.is_null() {
+                let raw_token = coap_pdu_get_token(sent);
+                if raw_token.length > 0 {
+                    let token = std::slice::from_raw_parts(raw_token.s, raw_token.length);
+                    fail_request(&session, token, RequestPollError::IcmpUnreachable);
+                }
+            }
+            return;
+        }
+        record_stats(&session, |stats| stats.failed_deliveries += 1);
+        // libcoap no longer holds messages that were rejected or could not be delivered.
+        if dequeue_sent_message(&session, Some(mid), &[]) {
+            // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+            let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+            context.handle_send_queue_drained(session.clone());
+        }
+        if reason == coap_nack_reason_t::COAP_NACK_RST && !sent.is_null() {
+            // The peer rejected one of our messages, fail the request it belongs to (if any).
+            let raw_token = coap_pdu_get_token(sent);
+            if raw_token.length > 0 {
+                let token = std::slice::from_raw_parts(raw_token.s, raw_token.length);
+                fail_request(&session, token, RequestPollError::Reset);
+            }
+            return;
+        }
+        // libcoap removes observers whose notifications (i.e., responses) could not be delivered.
+        if let CoapSession::Server(server_session) = &session {
+            if reason == coap_nack_reason_t::COAP_NACK_TOO_MANY_RETRIES
+                && !sent.is_null()
+                && coap_pdu_get_raw_code(sent) >= 64
+            {
+                let raw_token = coap_pdu_get_token(sent);
+                let token = match raw_token.length {
+                    0 => &[][..],
+                    len => std::slice::from_raw_parts(raw_token.s, len),
+                };
+                // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+                let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+                context.handle_observer_unreachable(server_session.clone(), token);
+                return;
+            }
+        }
+        // Pings are empty confirmable messages, and the only empty messages that can time out.
+        if reason != coap_nack_reason_t::COAP_NACK_TOO_MANY_RETRIES
+            || sent.is_null()
+            || coap_pdu_get_raw_code(sent) != 0
+        {
+            return;
+        }
+        // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+        let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+        context.handle_ping_timeout(session, mid);
+    })
 }
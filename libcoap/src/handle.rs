@@ -0,0 +1,517 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * handle.rs - Thread-safe handles for controlling a CoAP context from other threads.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+#[cfg(unix)]
+use std::time::Instant;
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt::{Debug, Formatter},
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+#[cfg(windows)]
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    os::windows::io::{AsRawSocket, RawSocket},
+};
+
+#[cfg(unix)]
+use libc::{fcntl, pipe, poll, pollfd, F_GETFL, F_SETFL, O_NONBLOCK, POLLIN};
+#[cfg(unix)]
+use libcoap_sys::{
+    coap_context_get_coap_fd, coap_io_prepare_epoll, coap_io_process, coap_tick_t, coap_ticks, COAP_IO_WAIT,
+};
+use libcoap_sys::{coap_context_t, coap_io_process_with_fds};
+
+use crate::{
+    error::ContextHandleError,
+    session::{CoapSessionCloseReason, CoapSessionId},
+    CoapContext, CoapResource, CoapResourcePath,
+};
+
+/// Timeout value that makes `coap_io_process()` return immediately (`COAP_IO_NO_WAIT`, which
+/// bindgen is unable to generate because it is defined using a cast).
+pub(crate) const COAP_IO_NO_WAIT: u32 = u32::MAX;
+
+/// Operation that is executed by the IO thread of a context.
+type ContextCommand = Box<dyn for<'a> FnOnce(&mut CoapContext<'a>) + Send>;
+
+/// Thread-safe handle that allows other threads to queue operations on a [CoapContext].
+///
+/// [CoapContext] is neither [Send] nor [Sync], as it (and all objects associated with it) may only
+/// be used by the thread that runs its IO loop (i.e., calls [CoapContext::do_io()]).
+/// Handles obtained using [CoapContext::handle()] may be sent to other threads, which can use them
+/// to queue operations that are executed by the IO thread during its next call to
+/// [CoapContext::do_io()] (both before and after waiting for IO).
+/// Queuing an operation interrupts a call to [CoapContext::do_io()] that is currently waiting for
+/// IO, so operations are executed promptly.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use libcoap_rs::CoapContext;
+///
+/// let mut context = CoapContext::new().unwrap();
+/// let handle = context.handle().unwrap();
+/// let worker = std::thread::spawn(move || {
+///     // ...update the state of the resource "sensor"...
+///     handle.notify_observers("sensor").unwrap();
+///     handle.request_stop().unwrap();
+/// });
+/// while !context.stop_requested() {
+///     context.do_io(Some(Duration::from_secs(10))).unwrap();
+/// }
+/// worker.join().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct CoapContextHandle {
+    shared: Arc<HandleShared>,
+}
+
+/// State shared between a context and its handles.
+pub(crate) struct HandleShared {
+    /// Operations that have been queued, but not executed yet.
+    commands: Mutex<VecDeque<ContextCommand>>,
+    /// Whether [CoapContextHandle::request_stop()] (or [StopHandle::stop()]) was called.
+    stop_requested: AtomicBool,
+    /// Whether [StopHandle::request_shutdown()] was called.
+    shutdown_requested: AtomicBool,
+    /// Whether the context has been dropped, in which case no further operations are accepted.
+    closed: AtomicBool,
+    /// Used to interrupt [CoapContext::do_io()] if operations are queued.
+    waker: Waker,
+}
+
+impl Debug for CoapContextHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapContextHandle")
+            .field("stop_requested", &self.shared.stop_requested.load(Ordering::Relaxed))
+            .field("closed", &self.shared.closed.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Debug for HandleShared {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HandleShared")
+            .field("stop_requested", &self.stop_requested)
+            .field("shutdown_requested", &self.shutdown_requested)
+            .field("closed", &self.closed)
+            .field("waker", &self.waker)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CoapContextHandle {
+    /// Creates a handle for the given shared state.
+    pub(crate) fn new(shared: Arc<HandleShared>) -> CoapContextHandle {
+        CoapContextHandle { shared }
+    }
+
+    /// Queues the given operation, which is executed by the IO thread of the context during its
+    /// next call to [CoapContext::do_io()].
+    ///
+    /// As the operation has full access to the context, it can be used for anything that is not
+    /// covered by the other functions of this handle (e.g., configuring the context or adding
+    /// endpoints).
+    /// Operations are executed in the order they were queued in.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn execute<F: for<'a> FnOnce(&mut CoapContext<'a>) + Send + 'static>(
+        &self,
+        operation: F,
+    ) -> Result<(), ContextHandleError> {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return Err(ContextHandleError::ContextDropped);
+        }
+        self.shared
+            .commands
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(Box::new(operation));
+        self.shared.waker.wake();
+        Ok(())
+    }
+
+    /// Notifies the observers of the resource with the given URI path (see [CoapResourcePath]) about
+    /// changes to this resource (see [CoapResource::notify_observers()]).
+    ///
+    /// Nothing happens if the context has no resource with this path.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn notify_observers(&self, uri_path: impl Into<CoapResourcePath>) -> Result<(), ContextHandleError> {
+        let uri_path = uri_path.into();
+        self.execute(move |context| {
+            context.notify_resource_observers(&uri_path);
+        })
+    }
+
+    /// Adds the resource created by `constructor` to the context.
+    ///
+    /// As resources (and their user data) can not be sent between threads, the resource is
+    /// constructed on the IO thread.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn add_resource_with<D, F>(&self, constructor: F) -> Result<(), ContextHandleError>
+    where
+        D: Any + ?Sized + Debug,
+        F: FnOnce() -> CoapResource<D> + Send + 'static,
+    {
+        self.execute(move |context| {
+            context.add_resource(constructor());
+        })
+    }
+
+    /// Removes the resources with the given URI path (see [CoapResourcePath]) from the context,
+    /// including resources for this path that are restricted to specific hosts (see
+    /// [CoapResourceHandle::remove()](crate::CoapResourceHandle::remove)).
+    ///
+    /// Nothing happens if the context has no resource with this path.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn remove_resource(&self, uri_path: impl Into<CoapResourcePath>) -> Result<(), ContextHandleError> {
+        let uri_path = uri_path.into();
+        self.execute(move |context| {
+            context.remove_resources_by_path(&uri_path);
+        })
+    }
+
+    /// Closes the server-side session with the given ID for the given reason (see
+    /// [CoapServerSession::disconnect()](crate::session::CoapServerSession::disconnect)).
+    ///
+    /// Nothing happens if the context has no (connected) server-side session with this ID, e.g.,
+    /// because the session has already been closed in the meantime.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn close_session(
+        &self,
+        session: CoapSessionId,
+        reason: CoapSessionCloseReason,
+    ) -> Result<(), ContextHandleError> {
+        self.execute(move |context| {
+            if let Some(session) = context.server_session_by_id(session) {
+                session.disconnect(reason);
+            }
+        })
+    }
+
+    /// Requests the IO loop of the context to stop, i.e., stops [CoapContext::run()] and causes
+    /// [CoapContext::stop_requested()] to return true (for custom IO loops).
+    ///
+    /// A call to [CoapContext::do_io()] that is currently waiting for IO returns early.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn request_stop(&self) -> Result<(), ContextHandleError> {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return Err(ContextHandleError::ContextDropped);
+        }
+        self.shared.stop_requested.store(true, Ordering::Release);
+        self.shared.waker.wake();
+        Ok(())
+    }
+}
+
+impl HandleShared {
+    /// Creates the shared state for the handles of a context.
+    ///
+    /// Fails if the pipe (or, on Windows, the pair of sockets) used to interrupt
+    /// [CoapContext::do_io()] can not be created (e.g., because the process has run out of file
+    /// descriptors).
+    pub(crate) fn new() -> std::io::Result<HandleShared> {
+        Ok(HandleShared {
+            commands: Mutex::new(VecDeque::new()),
+            stop_requested: AtomicBool::new(false),
+            shutdown_requested: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            waker: Waker::new()?,
+        })
+    }
+
+    /// Returns whether [CoapContextHandle::request_stop()] was called.
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::Acquire)
+    }
+
+    /// Returns whether a stop was requested and resets the request.
+    pub(crate) fn take_stop_request(&self) -> bool {
+        self.stop_requested.swap(false, Ordering::AcqRel)
+    }
+
+    /// Returns whether [StopHandle::request_shutdown()] was called.
+    pub(crate) fn shutdown_requested(&self) -> bool {
+        self.shutdown_requested.load(Ordering::Acquire)
+    }
+
+    /// Resets a request made using [StopHandle::request_shutdown()].
+    pub(crate) fn clear_shutdown_request(&self) {
+        self.shutdown_requested.store(false, Ordering::Release);
+    }
+
+    /// Removes all queued operations, which should then be executed by the IO thread.
+    pub(crate) fn take_commands(&self) -> VecDeque<ContextCommand> {
+        std::mem::take(&mut *self.commands.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Marks the context as dropped and discards all queued operations.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        std::mem::drop(self.take_commands());
+    }
+
+    /// Returns the file descriptor that becomes readable once an operation is queued (or a stop is
+    /// requested), see [CoapContext::prepare_io()].
+    #[cfg(unix)]
+    pub(crate) fn waker_fd(&self) -> c_int {
+        self.waker.read_fd
+    }
+
+    /// Removes all pending wake-ups from the file descriptor returned by
+    /// [HandleShared::waker_fd()].
+    #[cfg(unix)]
+    pub(crate) fn drain_waker(&self) {
+        self.waker.drain();
+    }
+
+    /// Equivalent to `coap_io_process()`, but also returns if an operation was queued (or a stop
+    /// was requested) while waiting for IO.
+    ///
+    /// # Safety
+    /// Same requirements as for `coap_io_process()`, i.e., the raw context must be valid and not
+    /// in use by anyone else.
+    #[cfg(unix)]
+    pub(crate) unsafe fn io_process(&self, raw_context: *mut coap_context_t, timeout: u32) -> c_int {
+        // If epoll is used, libcoap provides the epoll file descriptor, which can be waited on
+        // alongside our own file descriptor (see `coap_io(3)`). Otherwise, our file descriptor can
+        // be added to the set of file descriptors libcoap waits on.
+        let epoll_fd = coap_context_get_coap_fd(raw_context);
+        if epoll_fd < 0 {
+            let mut read_fds: libc::fd_set = std::mem::zeroed();
+            libc::FD_ZERO(&mut read_fds);
+            libc::FD_SET(self.waker.read_fd, &mut read_fds);
+            let result = coap_io_process_with_fds(
+                raw_context,
+                timeout,
+                self.waker.read_fd + 1,
+                &mut read_fds,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            self.waker.drain();
+            return result;
+        }
+
+        let start = Instant::now();
+        let mut now: coap_tick_t = 0;
+        coap_ticks(&mut now);
+        // Sends pending notifications and retransmissions, returns the time until libcoap needs to
+        // be called again (or 0 if there is nothing to do).
+        let next_timer = coap_io_prepare_epoll(raw_context, now);
+        let wait_ms = match (timeout, next_timer) {
+            (COAP_IO_WAIT, 0) => -1,
+            (COAP_IO_WAIT, v) | (v, 0) => c_int::try_from(v).unwrap_or(c_int::MAX),
+            (timeout, next_timer) => c_int::try_from(timeout.min(next_timer)).unwrap_or(c_int::MAX),
+        };
+        let mut fds = [
+            pollfd {
+                fd: epoll_fd,
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                fd: self.waker.read_fd,
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        if poll(fds.as_mut_ptr(), fds.len() as _, wait_ms) < 0
+            && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+        {
+            return -1;
+        }
+        self.waker.drain();
+        if coap_io_process(raw_context, COAP_IO_NO_WAIT) < 0 {
+            return -1;
+        }
+        c_int::try_from(start.elapsed().as_millis()).unwrap_or(c_int::MAX)
+    }
+
+    /// Equivalent to `coap_io_process()`, but also returns if an operation was queued (or a stop
+    /// was requested) while waiting for IO.
+    ///
+    /// # Safety
+    /// Same requirements as for `coap_io_process()`, i.e., the raw context must be valid and not
+    /// in use by anyone else.
+    #[cfg(windows)]
+    pub(crate) unsafe fn io_process(&self, raw_context: *mut coap_context_t, timeout: u32) -> c_int {
+        // libcoap does not support epoll on Windows, so the socket of the waker can always be
+        // added to the set of sockets libcoap waits on. Winsock ignores the number of file
+        // descriptors passed to select(), as its socket sets contain their own length.
+        let mut read_fds: libcoap_sys::fd_set = std::mem::zeroed();
+        read_fds.fd_count = 1;
+        read_fds.fd_array[0] = self.waker.raw_socket() as _;
+        let result = coap_io_process_with_fds(
+            raw_context,
+            timeout,
+            0,
+            &mut read_fds,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        self.waker.drain();
+        result
+    }
+}
+
+/// Handle that can be used to stop [CoapContext::run()] from other threads or signal handlers.
+///
+/// Obtained using [CoapContext::stop_handle()].
+/// On Unix, both functions of this handle are async-signal-safe, i.e., they may be called from
+/// signal handlers (e.g., to stop a server on SIGINT). On Windows, they may be called from console
+/// control handlers (which are run on a separate thread).
+/// Calling them after the context has been dropped has no effect.
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    shared: Arc<HandleShared>,
+}
+
+impl StopHandle {
+    /// Creates a stop handle for the given shared state.
+    pub(crate) fn new(shared: Arc<HandleShared>) -> StopHandle {
+        StopHandle { shared }
+    }
+
+    /// Stops [CoapContext::run()] as soon as possible, interrupting it if it is waiting for IO.
+    ///
+    /// Outstanding IO (e.g., unacknowledged confirmable messages) is not waited for, use
+    /// [StopHandle::request_shutdown()] for that.
+    pub fn stop(&self) {
+        self.shared.stop_requested.store(true, Ordering::Release);
+        self.shared.waker.wake();
+    }
+
+    /// Stops [CoapContext::run()] once the context has no more outstanding IO (i.e.,
+    /// `coap_can_exit()` returns true).
+    ///
+    /// The context continues to serve requests in the meantime.
+    pub fn request_shutdown(&self) {
+        self.shared.shutdown_requested.store(true, Ordering::Release);
+        self.shared.waker.wake();
+    }
+}
+
+/// Self-pipe used to interrupt threads waiting for IO.
+#[cfg(unix)]
+#[derive(Debug)]
+struct Waker {
+    read_fd: c_int,
+    write_fd: c_int,
+}
+
+#[cfg(unix)]
+impl Waker {
+    /// Creates a new non-blocking pipe.
+    fn new() -> std::io::Result<Waker> {
+        let mut fds = [0; 2];
+        // SAFETY: fds has space for the two file descriptors created by pipe().
+        if unsafe { pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let waker = Waker {
+            read_fd: fds[0],
+            write_fd: fds[1],
+        };
+        for fd in fds {
+            // SAFETY: fd was just created and is owned by waker.
+            let result = unsafe { fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK) };
+            if result != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(waker)
+    }
+
+    /// Wakes up the thread waiting for the read end of the pipe to become readable.
+    fn wake(&self) {
+        // Failures can be ignored: If the pipe is full, the waiting thread is already woken up.
+        // SAFETY: write_fd is valid until the waker is dropped, the buffer is valid for one byte.
+        unsafe { libc::write(self.write_fd, [1u8].as_ptr().cast(), 1) };
+    }
+
+    /// Removes all pending wake-ups from the pipe.
+    fn drain(&self) {
+        let mut buffer = [0u8; 64];
+        // SAFETY: read_fd is valid until the waker is dropped, buffer has the provided size and
+        // the read end is non-blocking.
+        while unsafe { libc::read(self.read_fd, buffer.as_mut_ptr().cast(), buffer.len()) } > 0 {}
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Waker {
+    fn drop(&mut self) {
+        // SAFETY: Both file descriptors are owned by this waker.
+        unsafe {
+            libc::close(self.read_fd);
+            libc::close(self.write_fd);
+        }
+    }
+}
+
+/// Pair of connected loopback UDP sockets used to interrupt threads waiting for IO, as Winsock can
+/// only wait for sockets (and not for pipes).
+#[cfg(windows)]
+#[derive(Debug)]
+struct Waker {
+    receiver: UdpSocket,
+    sender: UdpSocket,
+}
+
+#[cfg(windows)]
+impl Waker {
+    /// Creates a new pair of non-blocking sockets.
+    fn new() -> std::io::Result<Waker> {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        // Connecting both sockets ensures that the receiver only accepts datagrams of the sender.
+        sender.connect(receiver.local_addr()?)?;
+        receiver.connect(sender.local_addr()?)?;
+        receiver.set_nonblocking(true)?;
+        sender.set_nonblocking(true)?;
+        Ok(Waker { receiver, sender })
+    }
+
+    /// Returns the socket that becomes readable once [Waker::wake()] is called.
+    fn raw_socket(&self) -> RawSocket {
+        self.receiver.as_raw_socket()
+    }
+
+    /// Wakes up the thread waiting for the receiving socket to become readable.
+    fn wake(&self) {
+        // Failures can be ignored: If the socket buffer is full, the waiting thread is already
+        // woken up.
+        let _ = self.sender.send(&[1u8]);
+    }
+
+    /// Removes all pending wake-ups from the receiving socket.
+    fn drain(&self) {
+        let mut buffer = [0u8; 64];
+        while self.receiver.recv(&mut buffer).is_ok() {}
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * io.rs - Types for integrating CoAP contexts into custom IO schedulers.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Types for driving a [CoapContext](crate::CoapContext) from a custom scheduler.
+//!
+//! [CoapContext::do_io()](crate::CoapContext::do_io) waits for IO itself, which is not possible
+//! in runtimes that have their own event loop (e.g., async runtimes or custom `select()`/`poll()`
+//! loops). These can split IO processing into two phases instead:
+//!
+//! 1. [CoapContext::prepare_io()](crate::CoapContext::prepare_io) sends pending messages and
+//!    returns the sockets to watch (as [CoapSocketRef]s) along with the time after which the
+//!    context has to be called again.
+//! 2. Once any of the sockets is ready (or the timeout has elapsed), the scheduler calls
+//!    [CoapContext::complete_io()](crate::CoapContext::complete_io) with the [CoapSocketReadiness]
+//!    of the ready sockets, which processes all received messages.
+//!
+//! The two phases are based on libcoap's epoll support (see `coap_io(3)`): all sockets of the
+//! context's sessions and endpoints are registered with a single epoll file descriptor, which is
+//! returned as the first socket. Sockets created between the two phases (e.g., sessions created by
+//! request handlers or endpoints added by the application) are therefore watched without the
+//! scheduler having to update its set of sockets. If the context has a
+//! [CoapContextHandle](crate::CoapContextHandle), an additional socket that becomes readable once
+//! an operation is queued on the handle is returned as well.
+//!
+//! ```no_run
+//! use libc::{poll, pollfd, POLLIN, POLLOUT};
+//! use libcoap_rs::io::CoapSocketReadiness;
+//! use libcoap_rs::CoapContext;
+//!
+//! let mut context = CoapContext::new().unwrap();
+//! loop {
+//!     let (sockets, timeout) = context.prepare_io().unwrap();
+//!     let mut fds: Vec<_> = sockets
+//!         .iter()
+//!         .map(|socket| pollfd {
+//!             fd: socket.raw_fd(),
+//!             events: if socket.interest().read { POLLIN } else { 0 }
+//!                 | if socket.interest().write { POLLOUT } else { 0 },
+//!             revents: 0,
+//!         })
+//!         .collect();
+//!     let timeout = timeout.map_or(-1, |v| v.as_millis().try_into().unwrap_or(i32::MAX));
+//!     // SAFETY: fds contains valid pollfd structs and has the provided length.
+//!     unsafe { poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+//!     let ready: Vec<_> = fds
+//!         .iter()
+//!         .filter(|fd| fd.revents != 0)
+//!         .map(|fd| CoapSocketReadiness::new(fd.fd, fd.revents & POLLIN != 0, fd.revents & POLLOUT != 0))
+//!         .collect();
+//!     context.complete_io(&ready).unwrap();
+//! }
+//! ```
+//!
+//! This module is only available on Unix, as libcoap does not support epoll on other platforms.
+
+use std::os::{
+    fd::{AsRawFd, RawFd},
+    raw::c_int,
+};
+
+/// Events a socket returned by [CoapContext::prepare_io()](crate::CoapContext::prepare_io) should
+/// be watched for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CoapSocketInterest {
+    /// Whether the socket should be watched for becoming readable.
+    pub read: bool,
+    /// Whether the socket should be watched for becoming writable.
+    pub write: bool,
+}
+
+impl CoapSocketInterest {
+    /// Interest in the socket becoming readable.
+    pub const READ: CoapSocketInterest = CoapSocketInterest {
+        read: true,
+        write: false,
+    };
+}
+
+/// A socket that should be watched by a custom scheduler, see the [module documentation](self).
+///
+/// The socket is owned by the context, it must neither be closed nor read from by the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoapSocketRef {
+    fd: c_int,
+    interest: CoapSocketInterest,
+}
+
+impl CoapSocketRef {
+    pub(crate) fn new(fd: c_int, interest: CoapSocketInterest) -> CoapSocketRef {
+        CoapSocketRef { fd, interest }
+    }
+
+    /// Returns the raw file descriptor of this socket.
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Returns the events this socket should be watched for.
+    pub fn interest(&self) -> CoapSocketInterest {
+        self.interest
+    }
+}
+
+impl AsRawFd for CoapSocketRef {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+/// Readiness of a socket that was returned by
+/// [CoapContext::prepare_io()](crate::CoapContext::prepare_io), reported to
+/// [CoapContext::complete_io()](crate::CoapContext::complete_io).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoapSocketReadiness {
+    /// Raw file descriptor of the socket (see [CoapSocketRef::raw_fd()]).
+    pub fd: RawFd,
+    /// Whether the socket is readable.
+    pub readable: bool,
+    /// Whether the socket is writable.
+    pub writable: bool,
+}
+
+impl CoapSocketReadiness {
+    /// Creates a readiness report for the socket with the given file descriptor.
+    pub fn new(fd: RawFd, readable: bool, writable: bool) -> CoapSocketReadiness {
+        CoapSocketReadiness { fd, readable, writable }
+    }
+}
@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * job.rs - Background work started by request handlers and completed from other threads.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+};
+
+use libcoap_sys::coap_session_get_context;
+
+#[cfg(feature = "async")]
+use crate::session::CoapAsyncHandle;
+use crate::{
+    error::ContextHandleError,
+    handle::CoapContextHandle,
+    mem::CoapFfiWeakCell,
+    message::{response::CoapResponse, CoapMessageCommon},
+    protocol::{CoapMessageCode, CoapMessageType, CoapResponseCode},
+    resource::{CoapResource, CoapResourceInner},
+    session::{CoapServerSession, CoapSessionCommon},
+    CoapContext,
+};
+
+/// Action that is applied if a [CoapJobHandle] is dropped without completing its job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CoapJobDropAction {
+    /// Answer the request of the job (if any) with 5.03 (Service Unavailable).
+    #[default]
+    ServiceUnavailable,
+    /// Answer the request of the job (if any) with 5.03 (Service Unavailable) and end all
+    /// observations of the resource, whose observers receive a final notification with the code
+    /// 5.03 (see [CoapResource::cancel_observer()]).
+    CancelObservers,
+}
+
+/// Thread-safe handle to background work that was started by a request handler for a resource.
+///
+/// Request handlers are called on the IO thread of their context and should not block it with
+/// long-running work (e.g., verifying a firmware image). Instead, they can start a job using
+/// [CoapJobHandle::start()], move the handle (which is [Send]) to another thread and answer the
+/// request right away (e.g., with 2.01 Created). Using
+/// [CoapJobHandle::start_with_response()], the response to the request can instead be deferred
+/// until the job is completed.
+///
+/// The resource (and the response) are never accessed by the thread performing the job: Updates
+/// and completions are queued using the [CoapContextHandle] of the context and applied on its IO
+/// thread between the IO operations performed by [CoapContext::do_io()], which afterwards notifies
+/// the observers of the resource.
+///
+/// If the handle is dropped without completing the job (e.g., because the thread performing it
+/// panicked), its [CoapJobDropAction] is applied.
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::{message::CoapMessageCommon, protocol::CoapResponseCode, session::CoapSessionCommon};
+/// use libcoap_rs::{CoapContext, CoapJobHandle, CoapResource};
+///
+/// let mut context = CoapContext::new().unwrap();
+/// let resource = CoapResource::builder("firmware", String::from("idle"))
+///     .observable(true)
+///     .post(|resource, session, _request, mut response| {
+///         *resource.user_data_mut() = String::from("verifying");
+///         let job = CoapJobHandle::start(session, resource).unwrap();
+///         std::thread::spawn(move || {
+///             // ...verify the firmware image...
+///             job.complete(|resource, _response| *resource.user_data_mut() = String::from("verified"))
+///                 .unwrap();
+///         });
+///         response.set_code(CoapResponseCode::Created);
+///         session.send(response).unwrap();
+///     })
+///     .build()
+///     .unwrap();
+/// context.add_resource(resource);
+/// ```
+#[derive(Debug)]
+pub struct CoapJobHandle<D: Any + ?Sized + Debug> {
+    id: u64,
+    context: CoapContextHandle,
+    drop_action: CoapJobDropAction,
+    finished: bool,
+    _resource: PhantomData<fn(&D)>,
+}
+
+impl<D: Any + ?Sized + Debug> CoapJobHandle<D> {
+    /// Starts a job for the given resource, which must belong to the context of the given session
+    /// (usually the resource and session passed to a request handler).
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::WakerCreationFailed] if the handle to the context can not be
+    /// created (see [CoapContext::handle()]).
+    pub fn start(
+        session: &CoapServerSession,
+        resource: &CoapResource<D>,
+    ) -> Result<CoapJobHandle<D>, ContextHandleError> {
+        Self::register(session, PendingJob::new(resource))
+    }
+
+    /// Starts a job for the given resource whose completion answers the given asynchronous request
+    /// (see [CoapRequest::into_async()](crate::message::CoapRequest::into_async)).
+    ///
+    /// The request should be registered without a delay, so that it is only answered by the job.
+    ///
+    /// This function is only available if the `async` feature is enabled.
+    ///
+    /// # Errors
+    /// See [CoapJobHandle::start()].
+    #[cfg(feature = "async")]
+    pub fn start_with_response(
+        session: &CoapServerSession,
+        resource: &CoapResource<D>,
+        request: CoapAsyncHandle,
+    ) -> Result<CoapJobHandle<D>, ContextHandleError> {
+        let mut job = PendingJob::new(resource);
+        job.request = Some(request);
+        Self::register(session, job)
+    }
+
+    fn register(session: &CoapServerSession, job: PendingJob) -> Result<CoapJobHandle<D>, ContextHandleError> {
+        // SAFETY: The raw session is valid, and its context is valid for at least as long as the
+        // session.
+        let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+        // Obtain the handle first, so that no job is registered if this fails.
+        let handle = context.handle()?;
+        Ok(CoapJobHandle {
+            id: context.register_job(job),
+            context: handle,
+            drop_action: CoapJobDropAction::default(),
+            finished: false,
+            _resource: PhantomData,
+        })
+    }
+
+    /// Returns the action that is applied if this handle is dropped without completing the job.
+    pub fn drop_action(&self) -> CoapJobDropAction {
+        self.drop_action
+    }
+
+    /// Sets the action that is applied if this handle is dropped without completing the job.
+    pub fn set_drop_action(&mut self, action: CoapJobDropAction) {
+        self.drop_action = action;
+    }
+
+    /// Calls `update` with the resource of this job on the IO thread of the context (e.g., to
+    /// report progress), after which the observers of the resource are notified.
+    ///
+    /// Nothing happens if the resource has been removed from the context in the meantime.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn update<F: FnOnce(&CoapResource<D>) + Send + 'static>(&self, update: F) -> Result<(), ContextHandleError> {
+        let id = self.id;
+        self.context.execute(move |context| {
+            if let Some(resource) = context.job_resource::<D>(id) {
+                update(&resource);
+                resource.notify_observers();
+            }
+        })
+    }
+
+    /// Completes this job by calling `complete` with its resource and the response to its request
+    /// on the IO thread of the context, after which the response is sent and the observers of the
+    /// resource are notified.
+    ///
+    /// The response is initialized with the code 2.05 (Content). It is only sent if the job was
+    /// started using [CoapJobHandle::start_with_response()] (and the session of the request has
+    /// not been closed in the meantime). If the resource has been removed from the context in the
+    /// meantime, `complete` is not called and the request is answered with 4.04 (Not Found).
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn complete<F>(mut self, complete: F) -> Result<(), ContextHandleError>
+    where
+        F: FnOnce(&CoapResource<D>, &mut CoapResponse) + Send + 'static,
+    {
+        self.finished = true;
+        let id = self.id;
+        self.context.execute(move |context| {
+            let Some(job) = context.take_job(id) else {
+                return;
+            };
+            let mut response = new_response(CoapResponseCode::Content);
+            match job.resource::<D>() {
+                Some(resource) => {
+                    complete(&resource, &mut response);
+                    resource.notify_observers();
+                },
+                None => response.set_code(CoapMessageCode::Response(CoapResponseCode::NotFound)),
+            }
+            job.respond(response);
+        })
+    }
+}
+
+impl<D: Any + ?Sized + Debug> Drop for CoapJobHandle<D> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let (id, action) = (self.id, self.drop_action);
+        // If the context has already been dropped, so has the job.
+        let _ = self.context.execute(move |context| {
+            if let Some(job) = context.take_job(id) {
+                if action == CoapJobDropAction::CancelObservers {
+                    (job.cancel_observers)(&job);
+                }
+                job.respond(new_response(CoapResponseCode::ServiceUnavailable));
+            }
+        });
+    }
+}
+
+/// State of a job started using [CoapJobHandle] that is kept by its context until the job is
+/// completed or its handle is dropped.
+pub(crate) struct PendingJob {
+    /// Weak reference to the resource of the job (a `CoapFfiWeakCell<CoapResourceInner<D>>`).
+    resource: Box<dyn Any>,
+    /// Ends the observations of the resource of the given job, see
+    /// [CoapJobDropAction::CancelObservers].
+    cancel_observers: fn(&PendingJob),
+    /// Asynchronous request answered once the job is completed.
+    #[cfg(feature = "async")]
+    request: Option<CoapAsyncHandle>,
+}
+
+impl PendingJob {
+    fn new<D: Any + ?Sized + Debug>(resource: &CoapResource<D>) -> PendingJob {
+        PendingJob {
+            resource: Box::new(resource.downgrade()),
+            cancel_observers: cancel_observers::<D>,
+            #[cfg(feature = "async")]
+            request: None,
+        }
+    }
+
+    /// Returns the resource of this job, or None if it has been removed from its context.
+    pub(crate) fn resource<D: Any + ?Sized + Debug>(&self) -> Option<CoapResource<D>> {
+        let resource = self
+            .resource
+            .downcast_ref::<CoapFfiWeakCell<CoapResourceInner<D>>>()
+            .expect("job resource has unexpected type")
+            .upgrade()
+            .map(CoapResource::from)?;
+        (!resource.is_removed()).then_some(resource)
+    }
+
+    /// Answers the request of this job (if any) with the given response.
+    fn respond(self, response: CoapResponse) {
+        #[cfg(feature = "async")]
+        if let Some(request) = self.request {
+            // Requests that have been abandoned in the meantime can no longer be answered.
+            let _ = request.complete(response);
+        }
+        #[cfg(not(feature = "async"))]
+        std::mem::drop(response);
+    }
+}
+
+impl Debug for PendingJob {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingJob").finish_non_exhaustive()
+    }
+}
+
+/// Ends the observations of the resource of the given job.
+fn cancel_observers<D: Any + ?Sized + Debug>(job: &PendingJob) {
+    if let Some(resource) = job.resource::<D>() {
+        for observer in resource.observers() {
+            resource.cancel_observer(&observer);
+        }
+    }
+}
+
+fn new_response(code: CoapResponseCode) -> CoapResponse {
+    // The message type is set by libcoap when the response is sent.
+    CoapResponse::new(CoapMessageType::Con, code).expect("confirmable responses are valid")
+}
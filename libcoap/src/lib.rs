@@ -20,15 +20,18 @@
 //!     - [x] DTLS
 //!         - [x] DTLS using PSK
 //!         - [x] DTLS using PKI/RPK
-//!     - [ ] TCP
-//!     - [ ] TLS
+//!     - [x] TCP (requires the `tcp` feature)
+//!     - [x] TLS (requires the `tls` feature)
 //!     - [ ] OSCORE
-//!     - [ ] WebSockets
+//!         - [x] OSCORE over DTLS (client sessions, requires the `oscore` feature)
+//!         - [ ] Group OSCORE (not supported by libcoap)
+//!     - [x] WebSockets (requires the `websockets` feature)
 //! - [ ] Blockwise Transfer
 //!     - [x] Receiving large messages
 //!         - Note: Handled in libcoap by setting `COAP_BLOCK_USE_LIBCOAP|COAP_BLOCK_SINGLE_BODY`.
 //!                 Manually constructing and managing blockwise transfers is currently not in scope
 //!                 for this library.
+//!     - [x] Receiving server-side large requests block by block (see [message::upload])
 //!     - [x] sending client-side large messages
 //!     - [ ] sending server-side large messages
 //! - [ ] Resource observation
@@ -47,6 +50,33 @@
 //! Some (but not all) of the available DTLS libraries may also be vendored using the
 //! `dtls_[LIBRARY]_vendored` feature.
 //!
+//! ## Transport features
+//! Support for transports other than UDP is enabled using crate features: `dtls` (together with
+//! at least one of `dtls-psk`, `dtls-pki` and `dtls-rpk`), `tcp`, `tls` and `websockets`, while
+//! `oscore` enables OSCORE.
+//! Functions for transports that are not enabled do not exist (e.g., `connect_tcp()` and
+//! `add_endpoint_tcp()` are only available with the `tcp` feature). Similarly, the `async` feature
+//! is required for asynchronous responses to requests (see
+//! [CoapRequest::into_async()](message::CoapRequest::into_async)); it is not enabled by default and
+//! has to be enabled explicitly. Enabled features are passed on
+//! to libcoap-sys, which fails the build if the libcoap library it is built against lacks one of
+//! them.
+//!
+//! This check is only possible if libcoap provides information on its build options (libcoap
+//! 4.3.5 or newer), and if libcoap is linked dynamically, the library used at runtime may differ
+//! from the one the crate was built against.
+//! In these cases, enabled transports may still turn out to be unsupported at runtime, which can
+//! be checked using [CoapProtocol::is_supported()](types::CoapProtocol::is_supported) and
+//! [tls_backend()](crypto::tls_backend).
+//! Attempting to use them anyway results in the following errors:
+//! - [SessionCreationError::TransportUnsupported](error::SessionCreationError::TransportUnsupported)
+//!   or [EndpointCreationError::TransportUnsupported](error::EndpointCreationError::TransportUnsupported)
+//!   if libcoap was built without support for the transport.
+//! - [SessionCreationError::TlsBackendMissing](error::SessionCreationError::TlsBackendMissing) for
+//!   encrypted sessions if libcoap was built without a TLS library.
+//! - [OscoreConfError::FeatureUnavailable](error::OscoreConfError::FeatureUnavailable) when
+//!   creating OSCORE configurations if libcoap was built without OSCORE support.
+//!
 //! ## Building on the ESP32
 //!
 //! libcoap-rs and libcoap-sys support building for the ESP32.
@@ -202,20 +232,60 @@
 
 extern crate core;
 
-pub use context::CoapContext;
-pub use event::CoapEventHandler;
-pub use resource::{CoapRequestHandler, CoapResource};
+#[cfg(any(unix, windows))]
+pub use context::{BudgetedIoOutcome, RunOptions};
+pub use context::{
+    CoapContext, CoapContextBuilder, CoapContextConfig, CoapEndpointConfig, CoapResourceHandle, CoapWellKnownCoreMode,
+};
+pub use event::{CoapEndpointRebindPhase, CoapEventHandler};
+#[cfg(any(unix, windows))]
+pub use handle::{CoapContextHandle, StopHandle};
+#[cfg(any(unix, windows))]
+pub use job::{CoapJobDropAction, CoapJobHandle};
+pub use resource::{
+    CoapObserver, CoapRequestHandler, CoapResource, CoapResourceBuilder, CoapResourcePath, CoapResourceStats,
+    NotificationConsistency, NotificationPacing, NotificationTypePolicy, ResourceFlags,
+};
+pub use resource_tree::{CoapResourceTree, CoapResourceTreeHandle};
+pub use startup::{startup_with, CoapStartupConfig};
+pub use stats::{CoapMemoryLimits, CoapMemoryReport, CoapServerSessionStats, CoapStats, CoapTransferStats};
 
+pub mod access;
+pub mod cache;
+pub mod client;
+pub mod clock;
 mod context;
-#[cfg(dtls)]
 pub mod crypto;
+pub mod echo;
 pub mod error;
 mod event;
+#[cfg(any(unix, windows))]
+mod handle;
+#[cfg(unix)]
+pub mod io;
+#[cfg(any(unix, windows))]
+mod job;
+pub mod limits;
+pub mod logging;
 mod mem;
 pub mod message;
+#[cfg(feature = "oscore")]
+pub mod oscore;
+pub mod persist;
 pub mod prng;
 pub mod protocol;
+pub mod proxy;
+pub mod rate_limit;
 mod resource;
+mod resource_tree;
 pub mod session;
+mod startup;
+mod stats;
+#[cfg(feature = "test-util")]
+pub mod test_vectors;
+#[cfg(feature = "test-util")]
+pub mod testing;
+pub mod token;
 pub mod transport;
 pub mod types;
+mod unwind;
@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * limits.rs - Limits for the options and payloads of received requests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing limits for the options and payloads of requests a server accepts.
+//!
+//! Each request that is passed to a resource is checked against the [CoapInputLimits] of the
+//! resource (see [CoapResource::set_input_limits()](crate::CoapResource::set_input_limits)), or
+//! the ones of its context if the resource does not have its own (see
+//! [CoapContext::set_input_limits()](crate::CoapContext::set_input_limits)). The check is
+//! performed on the PDU received by libcoap, i.e., before the wrapper copies any of its options
+//! into a [CoapRequest](crate::message::CoapRequest).
+//!
+//! Requests exceeding a limit are not passed to the resource (or its access hook). They are
+//! answered with 4.02 (Bad Option) if they have too many (or too long) options, and with 4.13
+//! (Request Entity Too Large) and a Size1 option indicating the exceeded limit if their payload is
+//! too large. Each rejected request is reported to
+//! [CoapEventHandler::handle_input_limit_exceeded()](crate::CoapEventHandler::handle_input_limit_exceeded),
+//! as it may indicate an attack on the server.
+//!
+//! Payloads are checked against two limits:
+//! - [CoapInputLimits::max_payload_size] limits the payload of each received PDU, i.e., the size
+//!   of the body of requests that are not block-wise transfers and of the individual blocks of
+//!   uploads that are received block by block (see
+//!   [ResourceFlags::STREAM_BLOCK1](crate::ResourceFlags::STREAM_BLOCK1)).
+//! - [CoapInputLimits::max_body_size] limits the size of whole request bodies. For uploads that are
+//!   received block by block, uploads are rejected as soon as a block announces a total size
+//!   (using the Size1 option) exceeding the limit or the received blocks exceed it. For all other
+//!   resources, libcoap reassembles block-wise uploads before the wrapper sees the request
+//!   (bounded only by libcoap's own limits), so the reassembled body is checked instead.
+
+use std::fmt::{Display, Formatter};
+
+use libcoap_sys::coap_pdu_t;
+
+use crate::{
+    message::CoapPduView,
+    protocol::{CoapOptionNum, CoapOptionType, CoapResponseCode},
+    types::{decode_var_len_u32, CoapProtocol},
+};
+
+/// Default maximum number of options of a request, see [CoapInputLimits::max_options].
+pub const DEFAULT_MAX_OPTIONS: usize = 128;
+/// Default maximum length of a single option value, see [CoapInputLimits::max_option_length].
+pub const DEFAULT_MAX_OPTION_LENGTH: usize = 2048;
+/// Default maximum payload size of a single request PDU, see
+/// [CoapInputLimits::max_payload_size].
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+/// Default maximum size of a request body, see [CoapInputLimits::max_body_size].
+pub const DEFAULT_MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Limits applied to the requests that are passed to a resource, see the
+/// [module documentation](self).
+///
+/// The defaults are generous enough for regular clients, but prevent single requests from making
+/// the wrapper allocate arbitrary amounts of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoapInputLimits {
+    /// Maximum number of options of a request ([DEFAULT_MAX_OPTIONS] by default).
+    pub max_options: usize,
+    /// Maximum length of the value of a single option in bytes ([DEFAULT_MAX_OPTION_LENGTH] by
+    /// default, the longest option defined by RFC 7252 (Proxy-Uri) has up to 1034 bytes).
+    pub max_option_length: usize,
+    /// Maximum payload size of a single received PDU in bytes, i.e., before block-wise uploads
+    /// are reassembled ([DEFAULT_MAX_PAYLOAD_SIZE] by default).
+    pub max_payload_size: usize,
+    /// Maximum size of a request body in bytes, i.e., after block-wise uploads have been
+    /// reassembled ([DEFAULT_MAX_BODY_SIZE] by default).
+    pub max_body_size: usize,
+}
+
+impl Default for CoapInputLimits {
+    fn default() -> Self {
+        CoapInputLimits {
+            max_options: DEFAULT_MAX_OPTIONS,
+            max_option_length: DEFAULT_MAX_OPTION_LENGTH,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+/// Limit of [CoapInputLimits] that was exceeded by a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CoapInputLimit {
+    /// [CoapInputLimits::max_options]
+    Options,
+    /// [CoapInputLimits::max_option_length]
+    OptionLength,
+    /// [CoapInputLimits::max_payload_size]
+    PayloadSize,
+    /// [CoapInputLimits::max_body_size]
+    BodySize,
+}
+
+/// A request that was rejected because it exceeded one of its [CoapInputLimits], see
+/// [CoapEventHandler::handle_input_limit_exceeded()](crate::CoapEventHandler::handle_input_limit_exceeded).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoapInputLimitExceeded {
+    /// The exceeded limit.
+    pub limit: CoapInputLimit,
+    /// Value of the limit.
+    pub max: usize,
+    /// Value of the request that exceeds the limit (e.g., the number of options or the announced
+    /// body size), or, for [CoapInputLimit::Options], the number of options received before the
+    /// limit was exceeded.
+    pub actual: usize,
+    /// Number of the option that exceeds the limit for [CoapInputLimit::OptionLength].
+    pub option: Option<CoapOptionNum>,
+}
+
+impl CoapInputLimitExceeded {
+    /// Returns the code of the response the request is answered with.
+    pub fn response_code(&self) -> CoapResponseCode {
+        match self.limit {
+            CoapInputLimit::Options | CoapInputLimit::OptionLength => CoapResponseCode::BadOption,
+            CoapInputLimit::PayloadSize | CoapInputLimit::BodySize => CoapResponseCode::RequestTooLarge,
+        }
+    }
+}
+
+impl Display for CoapInputLimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            CoapInputLimit::Options => write!(f, "request has more than {} options", self.max),
+            CoapInputLimit::OptionLength => write!(
+                f,
+                "option {} of the request has {} bytes (limit: {})",
+                self.option.unwrap_or_default(),
+                self.actual,
+                self.max
+            ),
+            CoapInputLimit::PayloadSize => write!(f, "request payload has {} bytes (limit: {})", self.actual, self.max),
+            CoapInputLimit::BodySize => write!(f, "request body has {} bytes (limit: {})", self.actual, self.max),
+        }
+    }
+}
+
+/// Checks the given raw request against the given limits.
+///
+/// `streams_block1` indicates whether the resource the request is passed to receives block-wise
+/// uploads block by block, i.e., whether the payload of requests with a Block1 option is a single
+/// block instead of the reassembled body.
+///
+/// # Safety
+/// The raw PDU must be valid.
+pub(crate) unsafe fn check_raw_request(
+    limits: &CoapInputLimits,
+    raw_pdu: *const coap_pdu_t,
+    proto: CoapProtocol,
+    streams_block1: bool,
+) -> Result<(), CoapInputLimitExceeded> {
+    let exceeded = |limit, max, actual, option| {
+        Err(CoapInputLimitExceeded {
+            limit,
+            max,
+            actual,
+            option,
+        })
+    };
+    let view = CoapPduView::from_raw(raw_pdu, proto);
+    let mut block1 = None;
+    let mut size1 = None;
+    for (count, (number, value)) in view.options().enumerate() {
+        if count >= limits.max_options {
+            return exceeded(CoapInputLimit::Options, limits.max_options, count, None);
+        }
+        if value.len() > limits.max_option_length {
+            return exceeded(
+                CoapInputLimit::OptionLength,
+                limits.max_option_length,
+                value.len(),
+                Some(number),
+            );
+        }
+        // Values that are too long for these options are rejected once the request is converted.
+        if number == CoapOptionType::Block1 as CoapOptionNum && value.len() <= 4 {
+            block1 = Some(decode_var_len_u32(value));
+        } else if number == CoapOptionType::Size1 as CoapOptionNum && value.len() <= 4 {
+            size1 = Some(decode_var_len_u32(value));
+        }
+    }
+    let payload_len = view.data().map_or(0, <[u8]>::len);
+    // The payload of reassembled uploads is the whole body, not the payload of a single PDU.
+    let reassembled = block1.is_some() && !streams_block1;
+    if !reassembled && payload_len > limits.max_payload_size {
+        return exceeded(CoapInputLimit::PayloadSize, limits.max_payload_size, payload_len, None);
+    }
+    let offset = match block1 {
+        Some(block) if streams_block1 => (block >> 4) as usize * (1 << ((block & 0x7).min(6) + 4)),
+        _ => 0,
+    };
+    let body_size = (offset + payload_len).max(size1.map_or(0, |v| v as usize));
+    if body_size > limits.max_body_size {
+        return exceeded(CoapInputLimit::BodySize, limits.max_body_size, body_size, None);
+    }
+    Ok(())
+}
@@ -0,0 +1,259 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * logging.rs - Integration of libcoap's logging with the log/tracing crates.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing methods for configuring and forwarding the log output of libcoap.
+//!
+//! By default, libcoap prints its log messages to stderr.
+//! After calling [install_log_handler()], all log messages of libcoap are instead forwarded to the
+//! [log] crate using the target `libcoap`, or to the [tracing](https://docs.rs/tracing) crate if
+//! the `tracing` feature is enabled.
+//!
+//! Note that libcoap's log handler and log level are global state, i.e., they apply to all
+//! contexts and sessions in this process.
+//!
+//! While the log handler is installed, recent log messages are also used to provide diagnostics
+//! for failed (D)TLS handshakes, see
+//! [SessionCreationError::HandshakeFailed](crate::error::SessionCreationError::HandshakeFailed).
+//!
+//! Independently of the log handler, the PDUs of sessions whose tracing has been enabled (see
+//! [CoapSessionCommon::set_trace()](crate::session::CoapSessionCommon::set_trace)) are logged at
+//! debug level using the target [TRACE_LOG_TARGET].
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    ffi::{c_char, CStr},
+    panic::catch_unwind,
+    sync::Once,
+};
+
+use libcoap_sys::{coap_get_log_level, coap_log_t, coap_set_log_handler, coap_set_log_level};
+
+/// Log target used for messages forwarded from libcoap.
+pub const LOG_TARGET: &str = "libcoap";
+
+/// Log target used for the PDUs of traced sessions, see
+/// [CoapSessionCommon::set_trace()](crate::session::CoapSessionCommon::set_trace).
+pub const TRACE_LOG_TARGET: &str = "libcoap::trace";
+
+static LOG_HANDLER_ONCE: Once = Once::new();
+
+/// Maximum number of recent log messages kept for handshake diagnostics, see
+/// [take_session_messages()].
+const MAX_RECENT_MESSAGES: usize = 32;
+
+thread_local! {
+    /// Recent log messages of libcoap on this thread (which is the thread that performs IO for the
+    /// sessions the messages refer to).
+    static RECENT_MESSAGES: RefCell<VecDeque<String>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Log levels used by libcoap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CoapLogLevel {
+    /// System is unusable.
+    Emergency,
+    /// Action must be taken immediately.
+    Alert,
+    /// Critical conditions.
+    Critical,
+    /// Error conditions.
+    Error,
+    /// Warning conditions.
+    Warning,
+    /// Normal but significant conditions.
+    Notice,
+    /// Informational messages.
+    Info,
+    /// Debug messages.
+    Debug,
+    /// Debug messages of the OSCORE implementation.
+    Oscore,
+    /// Debug messages of the DTLS library.
+    Dtls,
+}
+
+impl From<coap_log_t> for CoapLogLevel {
+    fn from(raw_level: coap_log_t) -> Self {
+        match raw_level {
+            coap_log_t::COAP_LOG_EMERG => CoapLogLevel::Emergency,
+            coap_log_t::COAP_LOG_ALERT => CoapLogLevel::Alert,
+            coap_log_t::COAP_LOG_CRIT => CoapLogLevel::Critical,
+            coap_log_t::COAP_LOG_ERR => CoapLogLevel::Error,
+            coap_log_t::COAP_LOG_WARN => CoapLogLevel::Warning,
+            coap_log_t::COAP_LOG_NOTICE => CoapLogLevel::Notice,
+            coap_log_t::COAP_LOG_INFO => CoapLogLevel::Info,
+            coap_log_t::COAP_LOG_DEBUG => CoapLogLevel::Debug,
+            coap_log_t::COAP_LOG_OSCORE => CoapLogLevel::Oscore,
+            // Everything above the OSCORE level is DTLS library output.
+            _ => CoapLogLevel::Dtls,
+        }
+    }
+}
+
+impl From<CoapLogLevel> for coap_log_t {
+    fn from(level: CoapLogLevel) -> Self {
+        match level {
+            CoapLogLevel::Emergency => coap_log_t::COAP_LOG_EMERG,
+            CoapLogLevel::Alert => coap_log_t::COAP_LOG_ALERT,
+            CoapLogLevel::Critical => coap_log_t::COAP_LOG_CRIT,
+            CoapLogLevel::Error => coap_log_t::COAP_LOG_ERR,
+            CoapLogLevel::Warning => coap_log_t::COAP_LOG_WARN,
+            CoapLogLevel::Notice => coap_log_t::COAP_LOG_NOTICE,
+            CoapLogLevel::Info => coap_log_t::COAP_LOG_INFO,
+            CoapLogLevel::Debug => coap_log_t::COAP_LOG_DEBUG,
+            CoapLogLevel::Oscore => coap_log_t::COAP_LOG_OSCORE,
+            CoapLogLevel::Dtls => coap_log_t::COAP_LOG_DTLS_BASE,
+        }
+    }
+}
+
+impl From<CoapLogLevel> for log::Level {
+    fn from(level: CoapLogLevel) -> Self {
+        match level {
+            CoapLogLevel::Emergency | CoapLogLevel::Alert | CoapLogLevel::Critical | CoapLogLevel::Error => {
+                log::Level::Error
+            },
+            CoapLogLevel::Warning => log::Level::Warn,
+            CoapLogLevel::Notice | CoapLogLevel::Info => log::Level::Info,
+            CoapLogLevel::Debug => log::Level::Debug,
+            CoapLogLevel::Oscore | CoapLogLevel::Dtls => log::Level::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<CoapLogLevel> for tracing::Level {
+    fn from(level: CoapLogLevel) -> Self {
+        match log::Level::from(level) {
+            log::Level::Error => tracing::Level::ERROR,
+            log::Level::Warn => tracing::Level::WARN,
+            log::Level::Info => tracing::Level::INFO,
+            log::Level::Debug => tracing::Level::DEBUG,
+            log::Level::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+/// Sets the maximum level of log messages that libcoap should output.
+///
+/// This can be changed at any time, e.g., to temporarily increase the verbosity at runtime.
+/// Note that the log level of the [log] crate (or the filter of the
+/// [tracing](https://docs.rs/tracing) subscriber) is applied additionally for messages forwarded
+/// by [install_log_handler()].
+pub fn set_log_level(level: CoapLogLevel) {
+    // SAFETY: coap_set_log_level only sets a global variable.
+    unsafe { coap_set_log_level(level.into()) }
+}
+
+/// Returns the maximum level of log messages that libcoap currently outputs.
+pub fn log_level() -> CoapLogLevel {
+    // SAFETY: coap_get_log_level only reads a global variable.
+    unsafe { coap_get_log_level() }.into()
+}
+
+/// Installs a log handler that forwards all log messages of libcoap to the [log] crate (or to the
+/// [tracing](https://docs.rs/tracing) crate if the `tracing` feature is enabled).
+///
+/// The handler is installed only once per process, calling this function again has no effect.
+/// Log levels are mapped as follows (see also the [From] implementations of [CoapLogLevel]):
+///
+/// | libcoap                                | log/tracing |
+/// |----------------------------------------|-------------|
+/// | Emergency, Alert, Critical, Error      | Error       |
+/// | Warning                                | Warn        |
+/// | Notice, Info                           | Info        |
+/// | Debug                                  | Debug       |
+/// | Oscore, Dtls                           | Trace       |
+pub fn install_log_handler() {
+    // SAFETY: The handler has the signature expected by libcoap and never unwinds into C code.
+    LOG_HANDLER_ONCE.call_once(|| unsafe { coap_set_log_handler(Some(log_handler_callback)) });
+}
+
+/// Log handler trampoline that forwards libcoap log messages to the log or tracing crate.
+unsafe extern "C" fn log_handler_callback(level: coap_log_t, message: *const c_char) {
+    if message.is_null() {
+        return;
+    }
+    // SAFETY: libcoap passes a valid null-terminated string that lives until we return.
+    let message = CStr::from_ptr(message);
+    // Panics must not unwind into libcoap, and there is no sensible way to report them here.
+    let _ = catch_unwind(|| {
+        // Messages may contain arbitrary bytes (e.g., payloads), and libcoap adds a trailing newline.
+        let message = message.to_string_lossy();
+        let level = CoapLogLevel::from(level);
+        if level <= CoapLogLevel::Info {
+            record_recent_message(message.trim_end());
+        }
+        forward_log_message(level, message.trim_end());
+    });
+}
+
+fn record_recent_message(message: &str) {
+    RECENT_MESSAGES.with_borrow_mut(|messages| {
+        if messages.len() == MAX_RECENT_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back(message.to_string());
+    });
+}
+
+/// Removes and returns the recent log messages that refer to the session with the given
+/// description (as returned by libcoap's `coap_session_str()`), from oldest to newest.
+///
+/// The part of these messages up to and including the session description is removed.
+/// Messages are only available if the log handler was installed using [install_log_handler()].
+pub(crate) fn take_session_messages(session_description: &str) -> Vec<String> {
+    if session_description.is_empty() {
+        return Vec::new();
+    }
+    RECENT_MESSAGES.with_borrow_mut(|messages| {
+        let mut taken = Vec::new();
+        messages.retain(|message| match message.split_once(session_description) {
+            Some((_, rest)) => {
+                taken.push(
+                    rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace())
+                        .to_string(),
+                );
+                false
+            },
+            None => true,
+        });
+        taken
+    })
+}
+
+#[cfg(not(feature = "tracing"))]
+fn forward_log_message(level: CoapLogLevel, message: &str) {
+    log::log!(target: LOG_TARGET, log::Level::from(level), "{}", message);
+}
+
+#[cfg(feature = "tracing")]
+fn forward_log_message(level: CoapLogLevel, message: &str) {
+    // tracing requires the level of an event to be known at compile time.
+    match log::Level::from(level) {
+        log::Level::Error => tracing::event!(target: LOG_TARGET, tracing::Level::ERROR, "{}", message),
+        log::Level::Warn => tracing::event!(target: LOG_TARGET, tracing::Level::WARN, "{}", message),
+        log::Level::Info => tracing::event!(target: LOG_TARGET, tracing::Level::INFO, "{}", message),
+        log::Level::Debug => tracing::event!(target: LOG_TARGET, tracing::Level::DEBUG, "{}", message),
+        log::Level::Trace => tracing::event!(target: LOG_TARGET, tracing::Level::TRACE, "{}", message),
+    }
+}
+
+/// Logs a trace message for a PDU of a traced session at debug level.
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn forward_trace_message(message: &str) {
+    log::debug!(target: TRACE_LOG_TARGET, "{}", message);
+}
+
+/// Logs a trace message for a PDU of a traced session at debug level.
+#[cfg(feature = "tracing")]
+pub(crate) fn forward_trace_message(message: &str) {
+    tracing::debug!(target: TRACE_LOG_TARGET, "{}", message);
+}
@@ -172,6 +172,18 @@ impl<D> CoapFfiRcCell<D> {
         Weak::into_raw(Rc::downgrade(&self.0)) as *mut c_void
     }
 
+    /// Creates a weak version of this reference counted cell, which does not keep the contained
+    /// data alive.
+    pub fn downgrade(&self) -> CoapFfiWeakCell<D> {
+        CoapFfiWeakCell(Rc::downgrade(&self.0))
+    }
+
+    /// Returns the number of strong references to the contained data, i.e., the number of
+    /// [CoapFfiRcCell] instances referring to the same value.
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
     /// Creates an immutable reference to the contained data type.
     ///
     /// # Panics
@@ -187,6 +199,18 @@ impl<D> CoapFfiRcCell<D> {
     pub fn borrow_mut(&self) -> RefMut<D> {
         RefCell::borrow_mut(&self.0)
     }
+
+    /// Attempts to create an immutable reference to the contained data type, returning None if it
+    /// is currently borrowed mutably.
+    pub fn try_borrow(&self) -> Option<Ref<D>> {
+        RefCell::try_borrow(&self.0).ok()
+    }
+
+    /// Attempts to create a mutable reference to the contained data type, returning None if it is
+    /// currently borrowed.
+    pub fn try_borrow_mut(&self) -> Option<RefMut<D>> {
+        RefCell::try_borrow_mut(&self.0).ok()
+    }
 }
 
 impl<D: PartialEq> PartialEq for CoapFfiRcCell<D> {
@@ -209,6 +233,29 @@ impl<D: Debug> Debug for CoapFfiRcCell<D> {
     }
 }
 
+/// The weak variant of a [CoapFfiRcCell].
+pub(crate) struct CoapFfiWeakCell<D>(Weak<RefCell<D>>);
+
+impl<D> CoapFfiWeakCell<D> {
+    /// Attempts to upgrade this weak cell into a full [CoapFfiRcCell<D>], returning None if the
+    /// underlying value was already dropped.
+    pub fn upgrade(&self) -> Option<CoapFfiRcCell<D>> {
+        self.0.upgrade().map(CoapFfiRcCell)
+    }
+}
+
+impl<D> Clone for CoapFfiWeakCell<D> {
+    fn clone(&self) -> Self {
+        CoapFfiWeakCell(Weak::clone(&self.0))
+    }
+}
+
+impl<D> Debug for CoapFfiWeakCell<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapFfiWeakCell").finish_non_exhaustive()
+    }
+}
+
 impl<T: Debug> DropInnerExclusively for CoapFfiRcCell<T> {
     fn drop_exclusively(self) {
         std::mem::drop(
@@ -280,6 +327,22 @@ impl<T: Debug> CoapLendableFfiRcCell<T> {
         }
     }
 
+    /// Attempts to immutably borrow from the value in this cell.
+    ///
+    /// Behaves like [borrow()], but returns None instead of panicking if the value is currently
+    /// mutably borrowed.
+    pub fn try_borrow(&self) -> Option<CoapLendableFfiRef<'_, T>> {
+        if let Some(borrowed) = RefCell::borrow_mut(&self.1).take() {
+            // SAFETY: See borrow().
+            Some(CoapLendableFfiRef::Borrowed(
+                unsafe { borrowed.as_mut() }.unwrap(),
+                Rc::clone(&self.1),
+            ))
+        } else {
+            RefCell::try_borrow(&self.0).ok().map(CoapLendableFfiRef::Owned)
+        }
+    }
+
     /// Create a raw pointer to this cell, suitable for storage in a libcoap application data
     /// pointer.
     ///
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/codec.rs - Validation of encoded CoAP messages.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use crate::{
+    error::MessageParsingError,
+    protocol::{CoapMessageCode, MAX_EXTENDED_TOKEN_SIZE},
+    types::CoapProtocol,
+};
+
+/// Payload marker separating the options from the payload of a message.
+const PAYLOAD_MARKER: u8 = 0xFF;
+
+/// Checks whether the given bytes are a well-formed encoding of a message for the given
+/// transport, see [CoapMessage::from_bytes()](crate::message::CoapMessage::from_bytes).
+///
+/// libcoap only reports whether parsing succeeded, this function is used beforehand to determine
+/// why a message is malformed.
+pub(crate) fn validate_encoded(proto: CoapProtocol, bytes: &[u8]) -> Result<(), MessageParsingError> {
+    let (tkl_nibble, raw_code, header_end, announced_len) = match proto {
+        CoapProtocol::None => return Err(MessageParsingError::UnsupportedProtocol(proto)),
+        CoapProtocol::Udp | CoapProtocol::Dtls => {
+            if bytes.len() < 4 {
+                return Err(MessageParsingError::TruncatedHeader);
+            }
+            let version = bytes[0] >> 6;
+            if version != 1 {
+                return Err(MessageParsingError::UnsupportedVersion(version));
+            }
+            (bytes[0] & 0x0F, bytes[1], 4, None)
+        },
+        CoapProtocol::Tcp | CoapProtocol::Tls | CoapProtocol::Ws | CoapProtocol::Wss => {
+            let first = *bytes.first().ok_or(MessageParsingError::TruncatedHeader)?;
+            let len_nibble = first >> 4;
+            let websocket = matches!(proto, CoapProtocol::Ws | CoapProtocol::Wss);
+            if websocket && len_nibble != 0 {
+                return Err(MessageParsingError::InvalidLengthField);
+            }
+            let (announced_len, code_pos) =
+                decode_ext_value(len_nibble, bytes, 1).ok_or(MessageParsingError::TruncatedHeader)?;
+            let raw_code = *bytes.get(code_pos).ok_or(MessageParsingError::TruncatedHeader)?;
+            // WebSocket messages are framed by the WebSocket protocol instead.
+            (
+                first & 0x0F,
+                raw_code,
+                code_pos + 1,
+                (!websocket).then_some(announced_len),
+            )
+        },
+    };
+
+    if tkl_nibble == 15 {
+        return Err(MessageParsingError::ReservedTokenLength);
+    }
+    let (token_len, token_start) =
+        decode_ext_value(tkl_nibble, bytes, header_end).ok_or(MessageParsingError::TruncatedToken)?;
+    if token_len > MAX_EXTENDED_TOKEN_SIZE {
+        return Err(MessageParsingError::TokenTooLong(token_len));
+    }
+    let body_start = token_start + token_len;
+    if bytes.len() < body_start {
+        return Err(MessageParsingError::TruncatedToken);
+    }
+    // The length field of TCP/TLS messages covers the options and payload, but not the token.
+    if let Some(announced_len) = announced_len {
+        if announced_len != bytes.len() - body_start {
+            return Err(MessageParsingError::LengthMismatch(
+                announced_len,
+                bytes.len() - body_start,
+            ));
+        }
+    }
+
+    if CoapMessageCode::from_raw_code(raw_code).is_err() {
+        return Err(MessageParsingError::InvalidCode(raw_code));
+    }
+    if raw_code == 0 && (token_len > 0 || bytes.len() > body_start) {
+        return Err(MessageParsingError::ContentInEmptyMessage);
+    }
+
+    let mut pos = body_start;
+    let mut option_num: usize = 0;
+    while pos < bytes.len() {
+        let option_start = pos;
+        let header = bytes[pos];
+        if header == PAYLOAD_MARKER {
+            if pos + 1 == bytes.len() {
+                return Err(MessageParsingError::PayloadMarkerWithoutPayload);
+            }
+            return Ok(());
+        }
+        let (delta_nibble, len_nibble) = (header >> 4, header & 0x0F);
+        if delta_nibble == 15 {
+            return Err(MessageParsingError::BadOptionDelta(option_start));
+        }
+        if len_nibble == 15 {
+            return Err(MessageParsingError::BadOptionLength(option_start));
+        }
+        let (delta, next) =
+            decode_ext_value(delta_nibble, bytes, pos + 1).ok_or(MessageParsingError::TruncatedOption(option_start))?;
+        let (len, next) =
+            decode_ext_value(len_nibble, bytes, next).ok_or(MessageParsingError::TruncatedOption(option_start))?;
+        option_num += delta;
+        if option_num > u16::MAX as usize {
+            return Err(MessageParsingError::BadOptionDelta(option_start));
+        }
+        pos = next + len;
+        if pos > bytes.len() {
+            return Err(MessageParsingError::TruncatedOption(option_start));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a value (an option delta, option length, token length or message length) from its
+/// 4-bit nibble and the extended bytes starting at `pos`, returning the value and the position
+/// after the extended bytes (or None if the input ends before the extended bytes do).
+///
+/// The nibble must not be 15 unless it is a message length, for which four extended bytes are
+/// used.
+fn decode_ext_value(nibble: u8, bytes: &[u8], pos: usize) -> Option<(usize, usize)> {
+    let (ext_len, offset) = match nibble {
+        0..=12 => return Some((nibble as usize, pos)),
+        13 => (1, 13),
+        14 => (2, 269),
+        _ => (4, 65805),
+    };
+    let ext = bytes.get(pos..pos + ext_len)?;
+    let value = ext.iter().fold(0usize, |acc, v| (acc << 8) | *v as usize);
+    Some((value + offset, pos + ext_len))
+}
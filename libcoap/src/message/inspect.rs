@@ -0,0 +1,334 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/inspect.rs - Read-only views of PDUs for inspection purposes.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Types for inspecting sent and received PDUs, see
+//! [CoapContext::set_pdu_inspector()](crate::CoapContext::set_pdu_inspector).
+
+use std::{
+    fmt::{Display, Formatter},
+    marker::PhantomData,
+    mem::MaybeUninit,
+};
+
+use libcoap_sys::{
+    coap_get_data, coap_opt_iterator_t, coap_opt_length, coap_opt_value, coap_option_iterator_init, coap_option_next,
+    coap_pdu_get_mid, coap_pdu_get_token, coap_pdu_get_type, coap_pdu_t,
+};
+
+use crate::{
+    error::{MessageCodeError, MessageConversionError},
+    message::{coap_pdu_get_raw_code, CoapMessage},
+    protocol::{CoapMessageCode, CoapMessageType, CoapOptionNum, CoapOptionType},
+    session::CoapSession,
+    types::{CoapMessageId, CoapProtocol},
+};
+
+/// Function type of PDU inspectors, see
+/// [CoapContext::set_pdu_inspector()](crate::CoapContext::set_pdu_inspector).
+pub(crate) type CoapPduInspector = dyn FnMut(CoapPduDirection, &CoapSession<'_>, &CoapPduView<'_>);
+
+/// Direction of a PDU passed to a PDU inspector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoapPduDirection {
+    /// The PDU is about to be sent.
+    Sent,
+    /// The PDU was received.
+    Received,
+}
+
+/// A read-only view of a PDU that is sent or received, as provided to PDU inspectors (see
+/// [CoapContext::set_pdu_inspector()](crate::CoapContext::set_pdu_inspector)).
+///
+/// The view (and all values borrowed from it) is only valid for the duration of the inspector
+/// call, use [CoapPduView::to_message()] to obtain an owned copy of the PDU.
+#[derive(Debug)]
+pub struct CoapPduView<'a> {
+    raw_pdu: *const coap_pdu_t,
+    proto: CoapProtocol,
+    _pdu_lifetime_marker: PhantomData<&'a coap_pdu_t>,
+}
+
+impl CoapPduView<'_> {
+    /// Creates a view of the given raw PDU, which is sent or received using the given protocol.
+    ///
+    /// # Safety
+    /// The provided pointer must point to a valid PDU that outlives the created view and is not
+    /// modified while the view exists.
+    pub(crate) unsafe fn from_raw<'a>(raw_pdu: *const coap_pdu_t, proto: CoapProtocol) -> CoapPduView<'a> {
+        CoapPduView {
+            raw_pdu,
+            proto,
+            _pdu_lifetime_marker: PhantomData,
+        }
+    }
+
+    /// Returns the type of this PDU.
+    ///
+    /// For reliable transports, the type is not transmitted and therefore meaningless.
+    pub fn type_(&self) -> CoapMessageType {
+        // SAFETY: The PDU is valid for the lifetime of this view.
+        unsafe { coap_pdu_get_type(self.raw_pdu) }.into()
+    }
+
+    /// Returns the code of this PDU.
+    ///
+    /// # Errors
+    /// Returns a [MessageCodeError] if the code of the PDU is neither the empty code nor a request
+    /// or response code (see [CoapMessageCode::from_raw_code()]), use [CoapPduView::raw_code()] to
+    /// obtain the numeric value of such codes.
+    pub fn code(&self) -> Result<CoapMessageCode, MessageCodeError> {
+        CoapMessageCode::from_raw_code(self.raw_code())
+    }
+
+    /// Returns the numeric value of the code of this PDU (`class << 5 | detail`).
+    pub fn raw_code(&self) -> u8 {
+        // SAFETY: The PDU is valid for the lifetime of this view.
+        unsafe { coap_pdu_get_raw_code(self.raw_pdu) as u8 }
+    }
+
+    /// Returns the message ID of this PDU.
+    ///
+    /// For reliable transports, the message ID is not transmitted and therefore meaningless.
+    pub fn mid(&self) -> CoapMessageId {
+        // SAFETY: The PDU is valid for the lifetime of this view.
+        unsafe { coap_pdu_get_mid(self.raw_pdu) }
+    }
+
+    /// Returns the token of this PDU.
+    pub fn token(&self) -> &[u8] {
+        // SAFETY: The PDU is valid for the lifetime of this view, the token is part of it.
+        unsafe {
+            let raw_token = coap_pdu_get_token(self.raw_pdu);
+            raw_slice(raw_token.s, raw_token.length)
+        }
+    }
+
+    /// Returns an iterator over the numbers and raw values of all options of this PDU (in the
+    /// order in which they are encoded, i.e., ordered by option number).
+    pub fn options(&self) -> CoapPduOptions<'_> {
+        let mut iter = MaybeUninit::zeroed();
+        // SAFETY: The PDU is valid for the lifetime of this view, a null filter selects all
+        // options.
+        let iter = unsafe {
+            coap_option_iterator_init(self.raw_pdu, iter.as_mut_ptr(), std::ptr::null());
+            iter.assume_init()
+        };
+        CoapPduOptions {
+            iter,
+            _pdu_lifetime_marker: PhantomData,
+        }
+    }
+
+    /// Returns the payload of this PDU.
+    pub fn data(&self) -> Option<&[u8]> {
+        let mut len: usize = 0;
+        let mut data = std::ptr::null();
+        // SAFETY: The PDU is valid for the lifetime of this view, the payload is part of it.
+        unsafe {
+            coap_get_data(self.raw_pdu, &mut len, &mut data);
+            (len > 0).then(|| raw_slice(data, len))
+        }
+    }
+
+    /// Returns the encoded representation of this PDU.
+    ///
+    /// For unreliable transports (UDP/DTLS), the PDU is encoded as a datagram as described in
+    /// [RFC 7252, Section 3](https://datatracker.ietf.org/doc/html/rfc7252#section-3), for reliable
+    /// ones (TCP/TLS), the framing described in
+    /// [RFC 8323, Section 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2) is used,
+    /// for WebSockets the one described in
+    /// [RFC 8323, Section 4.2](https://datatracker.ietf.org/doc/html/rfc8323#section-4.2) (i.e.,
+    /// without a length).
+    /// The encoding is not affected by (D)TLS, i.e., the encrypted bytes are not available.
+    ///
+    /// As the representation is constructed from the contents of the PDU, it is only computed if
+    /// this function is called.
+    pub fn encoded(&self) -> Vec<u8> {
+        let body = self.encoded_body();
+        let token = self.token();
+        let (tkl_nibble, tkl_ext) = encode_ext_value(token.len());
+        // SAFETY: The PDU is valid for the lifetime of this view.
+        let raw_type = unsafe { coap_pdu_get_type(self.raw_pdu) };
+        let mut encoded = Vec::with_capacity(body.len() + token.len() + 12);
+        if matches!(self.proto, CoapProtocol::Ws | CoapProtocol::Wss) {
+            encoded.push(tkl_nibble);
+            encoded.push(self.raw_code());
+        } else if self.proto.is_reliable() {
+            let (len_nibble, len_ext) = encode_ext_value(body.len());
+            encoded.push((len_nibble << 4) | tkl_nibble);
+            encoded.extend_from_slice(&len_ext);
+            encoded.push(self.raw_code());
+        } else {
+            encoded.push((1 << 6) | ((raw_type as u8) << 4) | tkl_nibble);
+            encoded.push(self.raw_code());
+            encoded.extend_from_slice(&(self.mid() as u16).to_be_bytes());
+        }
+        encoded.extend_from_slice(&tkl_ext);
+        encoded.extend_from_slice(token);
+        encoded.extend_from_slice(&body);
+        encoded
+    }
+
+    /// Returns the size of this PDU as used by libcoap to enforce maximum PDU sizes, i.e., the
+    /// size of the token (including extended token length bytes), options and payload.
+    pub(crate) fn used_size(&self) -> usize {
+        let token_len = self.token().len();
+        token_len + encode_ext_value(token_len).1.len() + self.encoded_body().len()
+    }
+
+    /// Returns the encoded options and payload (including the payload marker) of this PDU.
+    fn encoded_body(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let mut last_number: CoapOptionNum = 0;
+        for (number, value) in self.options() {
+            let (delta_nibble, delta_ext) = encode_ext_value((number - last_number) as usize);
+            let (len_nibble, len_ext) = encode_ext_value(value.len());
+            body.push((delta_nibble << 4) | len_nibble);
+            body.extend_from_slice(&delta_ext);
+            body.extend_from_slice(&len_ext);
+            body.extend_from_slice(value);
+            last_number = number;
+        }
+        if let Some(data) = self.data() {
+            body.push(0xFF);
+            body.extend_from_slice(data);
+        }
+        body
+    }
+
+    /// Parses this PDU into an owned [CoapMessage].
+    ///
+    /// # Errors
+    /// Returns a [MessageConversionError] if the PDU could not be parsed (e.g., because one of its
+    /// options has an invalid value).
+    pub fn to_message(&self) -> Result<CoapMessage, MessageConversionError> {
+        // SAFETY: The PDU is valid for the lifetime of this view.
+        unsafe { CoapMessage::from_raw_pdu(self.raw_pdu) }
+    }
+}
+
+impl Display for CoapPduView<'_> {
+    /// Formats a decoded description of this PDU for logging purposes, e.g.,
+    /// `CON 0.01 MID=4660 token=0a1b options=[UriPath(11): "value"] payload=5 bytes "hello"`.
+    ///
+    /// The message type and ID are omitted for reliable transports, option values and payloads are
+    /// shown as text if they are printable and as hex otherwise (truncated to 32 bytes).
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.proto.is_reliable() {
+            let type_ = match self.type_() {
+                CoapMessageType::Con => "CON",
+                CoapMessageType::Non => "NON",
+                CoapMessageType::Ack => "ACK",
+                CoapMessageType::Rst => "RST",
+            };
+            write!(f, "{} ", type_)?;
+        }
+        let code = self.raw_code();
+        write!(f, "{}.{:02}", code >> 5, code & 0x1F)?;
+        if !self.proto.is_reliable() {
+            write!(f, " MID={}", self.mid())?;
+        }
+        f.write_str(" token=")?;
+        write_hex(f, self.token())?;
+        f.write_str(" options=[")?;
+        for (i, (number, value)) in self.options().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match CoapOptionType::try_from(number) {
+                Ok(option_type) => write!(f, "{:?}({})", option_type, number)?,
+                Err(_) => write!(f, "{}", number)?,
+            }
+            if !value.is_empty() {
+                f.write_str(": ")?;
+                write_preview(f, value)?;
+            }
+        }
+        f.write_str("]")?;
+        if let Some(data) = self.data() {
+            write!(f, " payload={} bytes ", data.len())?;
+            write_preview(f, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maximum number of bytes of option values and payloads shown by the [Display] implementation
+/// of [CoapPduView].
+const PREVIEW_LEN: usize = 32;
+
+/// Writes the given bytes as lowercase hex digits.
+fn write_hex(f: &mut Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+}
+
+/// Writes the first [PREVIEW_LEN] bytes of the given value, as quoted text if they are printable
+/// UTF-8 and as hex otherwise.
+fn write_preview(f: &mut Formatter<'_>, value: &[u8]) -> std::fmt::Result {
+    let preview = &value[..value.len().min(PREVIEW_LEN)];
+    match std::str::from_utf8(preview) {
+        Ok(text) if !text.chars().any(char::is_control) => write!(f, "{:?}", text)?,
+        _ => {
+            f.write_str("0x")?;
+            write_hex(f, preview)?;
+        },
+    }
+    if preview.len() < value.len() {
+        f.write_str("...")?;
+    }
+    Ok(())
+}
+
+/// Iterator over the options of a PDU, see [CoapPduView::options()].
+#[derive(Debug)]
+pub struct CoapPduOptions<'a> {
+    iter: coap_opt_iterator_t,
+    _pdu_lifetime_marker: PhantomData<&'a coap_pdu_t>,
+}
+
+impl<'a> Iterator for CoapPduOptions<'a> {
+    type Item = (CoapOptionNum, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // SAFETY: The iterator was initialized for a PDU that is valid for 'a, and option values
+        // are part of the PDU.
+        unsafe {
+            let raw_opt = coap_option_next(&mut self.iter);
+            if raw_opt.is_null() {
+                return None;
+            }
+            Some((
+                self.iter.number,
+                raw_slice(coap_opt_value(raw_opt), coap_opt_length(raw_opt) as usize),
+            ))
+        }
+    }
+}
+
+/// Returns the slice with the given pointer and length, which may be null if the length is zero.
+///
+/// # Safety
+/// If `len` is not zero, `data` must point to `len` valid bytes that outlive the returned slice.
+unsafe fn raw_slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(data, len)
+}
+
+/// Encodes the given value (an option delta, option length, token length or message length) as a
+/// 4-bit nibble and its extended bytes.
+fn encode_ext_value(value: usize) -> (u8, Vec<u8>) {
+    match value {
+        0..=12 => (value as u8, Vec::new()),
+        13..=268 => (13, vec![(value - 13) as u8]),
+        269..=65804 => (14, ((value - 269) as u16).to_be_bytes().to_vec()),
+        _ => (15, ((value - 65805) as u32).to_be_bytes().to_vec()),
+    }
+}
@@ -15,39 +15,70 @@
 //! process of creating requests and responses and setting the appropriate options ([CoapRequest]
 //! and [CoapResponse]).
 
-use std::{ffi::c_void, mem::MaybeUninit, slice::Iter};
+use std::{
+    ffi::{c_int, c_uint, c_void},
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    mem::MaybeUninit,
+    ptr::NonNull,
+    slice::Iter,
+    sync::Arc,
+};
 use std::fmt::Write;
 
 use num_traits::FromPrimitive;
 
 use libcoap_sys::{
-    coap_add_data, coap_add_data_large_request, coap_add_optlist_pdu, coap_add_token, coap_delete_optlist,
-    coap_delete_pdu, coap_get_data, coap_insert_optlist, coap_new_optlist, coap_opt_length, coap_opt_t, coap_opt_value,
-    coap_option_iterator_init, coap_option_next, coap_option_num_t, coap_optlist_t, coap_pdu_get_code,
-    coap_pdu_get_mid, coap_pdu_get_token, coap_pdu_get_type, coap_pdu_init, coap_pdu_set_code, coap_pdu_set_type,
-    coap_pdu_t, coap_session_t,
+    coap_add_data, coap_add_data_large_request, coap_add_data_large_response, coap_add_optlist_pdu, coap_add_token,
+    coap_delete_optlist, coap_delete_pdu, coap_get_data, coap_insert_optlist, coap_new_message_id, coap_new_optlist,
+    coap_opt_length, coap_opt_t, coap_opt_value, coap_option_iterator_init, coap_option_next, coap_option_num_t,
+    coap_optlist_t, coap_pdu_code_t, coap_pdu_get_mid, coap_pdu_get_token, coap_pdu_get_type, coap_pdu_init,
+    coap_pdu_parse, coap_pdu_set_type, coap_pdu_t, coap_pdu_type_t, coap_send, coap_session_max_pdu_size,
+    coap_session_t, COAP_MEDIATYPE_TEXT_PLAIN,
 };
-pub use request::CoapRequest;
+pub use inspect::{CoapPduDirection, CoapPduView};
+pub use paged::CoapPagedResponder;
+pub use representations::CoapRepresentations;
+pub use request::{CoapRequest, CoapRequestBuilder};
 pub use response::CoapResponse;
+pub use upload::CoapBlock1Chunk;
 
 use crate::{
-    error::{MessageConversionError, OptionValueError},
+    error::{MessageConversionError, MessageParsingError, OptionValueError},
     protocol::{
         Block, CoapMatch, CoapMessageCode, CoapMessageType, CoapOptionNum, CoapOptionType, ContentFormat, ETag,
         HopLimit, MaxAge, NoResponse, Observe, ProxyScheme, ProxyUri, Size, UriHost, UriPath, UriPort, UriQuery,
     },
-    session::CoapSessionCommon,
-    types::CoapMessageId,
+    session::{CoapSessionCommon, HandledRequest},
+    types::{CoapMessageId, CoapProtocol},
 };
-use crate::context::ensure_coap_started;
-use crate::protocol::{Echo, Oscore, RequestTag};
+use crate::protocol::{Echo, Oscore, RequestTag, MAX_EXTENDED_TOKEN_SIZE};
+use crate::startup::ensure_coap_started;
 use crate::types::{
     decode_var_len_u16, decode_var_len_u32, decode_var_len_u8, encode_var_len_u16, encode_var_len_u32,
     encode_var_len_u8,
 };
 
+mod codec;
+pub mod inspect;
+pub mod paged;
+pub mod representations;
 pub mod request;
 pub mod response;
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+mod typed_payload;
+pub mod upload;
+
+// libcoap-sys represents coap_pdu_code_t as a Rust enum, which must never hold a value that does
+// not correspond to one of its variants (such as an unknown response code received from a peer).
+// PDU codes are therefore read and written as plain integers using these declarations.
+#[allow(clashing_extern_declarations)]
+extern "C" {
+    #[link_name = "coap_pdu_get_code"]
+    pub(crate) fn coap_pdu_get_raw_code(pdu: *const coap_pdu_t) -> c_uint;
+    #[link_name = "coap_pdu_set_code"]
+    pub(crate) fn coap_pdu_set_raw_code(pdu: *mut coap_pdu_t, code: c_uint);
+}
 
 /// Representation of a CoAP option including its value.
 ///
@@ -253,6 +284,247 @@ impl CoapOption {
     }
 }
 
+impl CoapOption {
+    /// Creates a CoAP option from its raw option number and value without losing any information.
+    ///
+    /// If the value can be represented using the typed variant for the given option number, the
+    /// typed variant is returned.
+    /// Otherwise (i.e., for unknown option numbers, but also for values of known options that
+    /// have an invalid length, an invalid string encoding, or a non-minimal integer encoding),
+    /// the value is preserved as-is using [CoapOption::Other].
+    ///
+    /// Converting the returned option back using [CoapOption::into_raw_parts()] will always return
+    /// the original option number and value.
+    pub fn from_raw_parts(number: CoapOptionNum, value: Vec<u8>) -> CoapOption {
+        match Self::from_type_value(number, value.clone()) {
+            Ok(option) if option.clone().into_value_bytes().is_ok_and(|v| *v == *value) => option,
+            _ => CoapOption::Other(number, value.into_boxed_slice()),
+        }
+    }
+
+    /// Converts this option into its raw option number and value.
+    ///
+    /// In contrast to [CoapOption::into_value_bytes()], values of [CoapOption::Other] are returned
+    /// as-is, even if their length is not valid for the option number.
+    ///
+    /// # Errors
+    /// Returns an [OptionValueError] if the value of a typed option is invalid (e.g., too long).
+    pub fn into_raw_parts(self) -> Result<(CoapOptionNum, Vec<u8>), OptionValueError> {
+        match self {
+            CoapOption::Other(number, value) => Ok((number, value.into_vec())),
+            option => Ok((option.number(), option.into_value_bytes()?.into_vec())),
+        }
+    }
+
+    /// Returns whether this option is critical, i.e. whether a recipient that does not recognize
+    /// it must reject the message ([RFC 7252, Section 5.4.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.4.1)).
+    ///
+    /// Critical options that are not known to this crate are represented as [CoapOption::Other],
+    /// request handlers may want to reject requests containing those with 4.02 Bad Option.
+    pub fn is_critical(&self) -> bool {
+        self.number() & 0x01 != 0
+    }
+}
+
+impl From<(CoapOptionNum, Vec<u8>)> for CoapOption {
+    fn from((number, value): (CoapOptionNum, Vec<u8>)) -> Self {
+        CoapOption::from_raw_parts(number, value)
+    }
+}
+
+impl TryFrom<CoapOption> for (CoapOptionNum, Vec<u8>) {
+    type Error = OptionValueError;
+
+    fn try_from(option: CoapOption) -> Result<Self, Self::Error> {
+        option.into_raw_parts()
+    }
+}
+
+/// An ordered collection of CoAP options that can be used with both typed [CoapOption]s and raw
+/// `(CoapOptionNum, Vec<u8>)` pairs.
+///
+/// Options are kept in the order they were inserted in, repeated options are stored as separate
+/// entries.
+/// Raw options are converted using [CoapOption::from_raw_parts()], so no information is lost
+/// when converting back and forth between both representations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CoapOptionSet {
+    options: Vec<CoapOption>,
+}
+
+impl CoapOptionSet {
+    /// Creates a new, empty option set.
+    pub fn new() -> CoapOptionSet {
+        CoapOptionSet::default()
+    }
+
+    /// Adds the given option to this set.
+    pub fn push(&mut self, option: CoapOption) {
+        self.options.push(option);
+    }
+
+    /// Adds the option with the given raw option number and value to this set.
+    pub fn push_raw(&mut self, number: CoapOptionNum, value: Vec<u8>) {
+        self.options.push(CoapOption::from_raw_parts(number, value));
+    }
+
+    /// Returns an iterator over the options in this set.
+    pub fn iter(&self) -> Iter<CoapOption> {
+        self.options.iter()
+    }
+
+    /// Returns an iterator over the options in this set in their raw representation.
+    ///
+    /// Options whose values are invalid are returned as errors (see
+    /// [CoapOption::into_raw_parts()]).
+    pub fn iter_raw(&self) -> impl Iterator<Item = Result<(CoapOptionNum, Vec<u8>), OptionValueError>> + '_ {
+        self.options.iter().map(|v| v.clone().into_raw_parts())
+    }
+
+    /// Returns an iterator over all options in this set with the given option number.
+    pub fn get_all(&self, number: CoapOptionNum) -> impl Iterator<Item = &CoapOption> + '_ {
+        self.options.iter().filter(move |v| v.number() == number)
+    }
+
+    /// Removes all options with the given option number from this set.
+    pub fn remove_all(&mut self, number: CoapOptionNum) {
+        self.options.retain(|v| v.number() != number)
+    }
+
+    /// Returns the number of options in this set.
+    pub fn len(&self) -> usize {
+        self.options.len()
+    }
+
+    /// Returns whether this set does not contain any options.
+    pub fn is_empty(&self) -> bool {
+        self.options.is_empty()
+    }
+
+    /// Converts this set into a list of raw options.
+    ///
+    /// # Errors
+    /// Returns an [OptionValueError] if any of the options has an invalid value (see
+    /// [CoapOption::into_raw_parts()]).
+    pub fn into_raw(self) -> Result<Vec<(CoapOptionNum, Vec<u8>)>, OptionValueError> {
+        self.options.into_iter().map(CoapOption::into_raw_parts).collect()
+    }
+}
+
+impl FromIterator<CoapOption> for CoapOptionSet {
+    fn from_iter<T: IntoIterator<Item = CoapOption>>(iter: T) -> Self {
+        CoapOptionSet {
+            options: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl FromIterator<(CoapOptionNum, Vec<u8>)> for CoapOptionSet {
+    fn from_iter<T: IntoIterator<Item = (CoapOptionNum, Vec<u8>)>>(iter: T) -> Self {
+        iter.into_iter().map(CoapOption::from).collect()
+    }
+}
+
+impl Extend<CoapOption> for CoapOptionSet {
+    fn extend<T: IntoIterator<Item = CoapOption>>(&mut self, iter: T) {
+        self.options.extend(iter)
+    }
+}
+
+impl IntoIterator for CoapOptionSet {
+    type Item = CoapOption;
+    type IntoIter = std::vec::IntoIter<CoapOption>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.options.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CoapOptionSet {
+    type Item = &'a CoapOption;
+    type IntoIter = Iter<'a, CoapOption>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.options.iter()
+    }
+}
+
+/// Options that only apply to a single hop or are set by each sender itself, see
+/// [CoapOptionFilter::proxy_safe()].
+const PROXY_UNSAFE_OPTIONS: [CoapOptionType; 11] = [
+    CoapOptionType::UriHost,
+    CoapOptionType::UriPort,
+    CoapOptionType::ProxyUri,
+    CoapOptionType::ProxyScheme,
+    CoapOptionType::HopLimit,
+    CoapOptionType::Block1,
+    CoapOptionType::Block2,
+    CoapOptionType::QBlock1,
+    CoapOptionType::QBlock2,
+    CoapOptionType::Size1,
+    CoapOptionType::Size2,
+];
+
+/// Filter deciding which options are copied from one message to another, see
+/// [CoapMessageCommon::copy_options_from()].
+///
+/// An option passes the filter if its number is contained in the allow list (or the filter
+/// allows all options) and not contained in the deny list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CoapOptionFilter {
+    allowed: Option<Vec<CoapOptionNum>>,
+    denied: Vec<CoapOptionNum>,
+}
+
+impl CoapOptionFilter {
+    /// Creates a filter that allows all options.
+    pub fn all() -> CoapOptionFilter {
+        CoapOptionFilter::default()
+    }
+
+    /// Creates a filter that only allows options with the given option numbers.
+    pub fn allow<I: IntoIterator<Item = CoapOptionNum>>(numbers: I) -> CoapOptionFilter {
+        CoapOptionFilter {
+            allowed: Some(numbers.into_iter().collect()),
+            denied: Vec::new(),
+        }
+    }
+
+    /// Additionally denies the options with the given option numbers.
+    pub fn deny<I: IntoIterator<Item = CoapOptionNum>>(mut self, numbers: I) -> Self {
+        self.denied.extend(numbers);
+        self
+    }
+
+    /// Creates a filter for options that are forwarded to the next hop (e.g., by a proxy or an
+    /// API gateway), which allows all options except for the ones that only apply to a single hop:
+    /// - Uri-Host, Uri-Port, Proxy-Uri and Proxy-Scheme, which identify the server the message is
+    ///   sent to (see
+    ///   [RFC 7252, Section 5.7.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.2)).
+    /// - Hop-Limit, which is decremented by each proxy
+    ///   ([RFC 8768](https://datatracker.ietf.org/doc/html/rfc8768)).
+    /// - Block1, Block2, Q-Block1, Q-Block2, Size1 and Size2, as block-wise transfers are performed
+    ///   by libcoap for each hop separately
+    ///   ([RFC 7959, Section 2.4](https://datatracker.ietf.org/doc/html/rfc7959#section-2.4)).
+    pub fn proxy_safe() -> CoapOptionFilter {
+        CoapOptionFilter::all().deny(PROXY_UNSAFE_OPTIONS.iter().map(|v| *v as CoapOptionNum))
+    }
+
+    /// Returns whether options with the given option number pass this filter.
+    pub fn allows(&self, number: CoapOptionNum) -> bool {
+        self.allowed.as_ref().map_or(true, |allowed| allowed.contains(&number)) && !self.denied.contains(&number)
+    }
+}
+
+/// Collects the given options into a [CoapOptionSet] that is ordered by option number.
+///
+/// The relative order of options with the same option number is preserved.
+pub(crate) fn sorted_option_set<I: IntoIterator<Item = CoapOption>>(options: I) -> CoapOptionSet {
+    let mut options: Vec<CoapOption> = options.into_iter().collect();
+    options.sort_by_key(CoapOption::number);
+    CoapOptionSet { options }
+}
+
 /// Constructs a path string from a [Vec] of strings containing the separate path components.
 pub(crate) fn construct_path_string(path_components: Vec<String>) -> String {
     path_components.into_iter().fold(String::new(), |mut a: String, v| {
@@ -283,6 +555,37 @@ pub trait CoapMessageCommon {
         self.as_message_mut().options.push(option);
     }
 
+    /// Add the CoAP option with the supplied raw option number and value to this message.
+    ///
+    /// The option is converted using [CoapOption::from_raw_parts()], so options added using this
+    /// function can be used interchangeably with the ones added using
+    /// [add_option()](CoapMessageCommon::add_option()).
+    fn add_raw_option(&mut self, number: CoapOptionNum, value: Vec<u8>) {
+        self.add_option(CoapOption::from_raw_parts(number, value));
+    }
+
+    /// Add all options contained in the supplied option set to this message.
+    fn add_options(&mut self, options: CoapOptionSet) {
+        self.as_message_mut().options.extend(options);
+    }
+
+    /// Adds the given options (e.g., the options of a received message, see
+    /// [CoapRequest::options()] and [CoapResponse::options()]) that pass `filter` to this message,
+    /// preserving their order (including the order of repeated options).
+    ///
+    /// The options are copied in their parsed representation, i.e., they are not encoded or
+    /// validated again. Options represented by typed fields of requests and responses (e.g., the
+    /// Uri-Path of a request) are added in addition to the values of these fields, so they should
+    /// be denied by `filter` if these fields are set.
+    fn copy_options_from<'o, I: IntoIterator<Item = &'o CoapOption>>(&mut self, options: I, filter: &CoapOptionFilter) {
+        self.as_message_mut().options.extend(
+            options
+                .into_iter()
+                .filter(|option| filter.allows(option.number()))
+                .cloned(),
+        );
+    }
+
     /// Clear the list of options that were added to this message using [add_option()](CoapMessageCommon::add_option()).
     fn clear_options(&mut self) {
         self.as_message_mut().options.clear();
@@ -293,6 +596,15 @@ pub trait CoapMessageCommon {
         self.as_message().options.iter()
     }
 
+    /// Returns the options contained in this message in their raw representation.
+    ///
+    /// # Errors
+    /// Returns an [OptionValueError] if any of the options has an invalid value (see
+    /// [CoapOption::into_raw_parts()]).
+    fn raw_options(&self) -> Result<Vec<(CoapOptionNum, Vec<u8>)>, OptionValueError> {
+        self.options_iter().map(|v| v.clone().into_raw_parts()).collect()
+    }
+
     /// Returns the CoAP message type (confirmable, non-confirmable, acknowledgement, rst) of this message.
     fn type_(&self) -> CoapMessageType {
         self.as_message().type_
@@ -327,12 +639,38 @@ pub trait CoapMessageCommon {
 
     /// Returns a reference to the data/body of this message.
     fn data(&self) -> Option<&[u8]> {
-        self.as_message().data.as_ref().map(|v| v.as_ref())
+        self.as_message().data.as_ref().map(CoapMessagePayload::as_slice)
+    }
+
+    /// Returns the payload of this message (which is empty if the message has no body).
+    ///
+    /// For requests passed to a request handler, the payload is not copied out of the received
+    /// PDU, i.e., the returned slice refers to the buffer of libcoap and remains valid for the
+    /// duration of the handler call. Cloning such a request copies the payload.
+    fn payload(&self) -> &[u8] {
+        self.data().unwrap_or_default()
     }
 
     /// Sets the data/body of this message.
     fn set_data<D: Into<Box<[u8]>>>(&mut self, data: Option<D>) {
-        self.as_message_mut().data = data.map(Into::into);
+        self.as_message_mut().data = data.map(|v| CoapMessagePayload::Owned(v.into()));
+    }
+
+    /// Sets the data/body of this message to a shared buffer, which is not copied when sending
+    /// the message.
+    ///
+    /// This is useful for large representations that are sent more than once (e.g., to multiple
+    /// clients). Request payloads and response payloads that do not fit into a single PDU are
+    /// handed to libcoap by reference, which releases the buffer once the (block-wise) transfer
+    /// has finished (see [CoapSessionCommon::send()]).
+    fn set_data_shared<D: Into<Arc<[u8]>>>(&mut self, data: Option<D>) {
+        self.as_message_mut().data = data.map(|v| CoapMessagePayload::Shared(v.into()));
+    }
+
+    /// Sets the data/body of this message to a buffer with a static lifetime, which is never
+    /// copied (see [CoapMessageCommon::set_data_shared()]).
+    fn set_data_static(&mut self, data: Option<&'static [u8]>) {
+        self.as_message_mut().data = data.map(CoapMessagePayload::Static);
     }
 
     /// Returns the message token.
@@ -344,6 +682,12 @@ pub trait CoapMessageCommon {
     ///
     /// Note that [CoapSessionCommon::send_request()] will automatically set the token to a random
     /// value if you don't.
+    ///
+    /// Tokens longer than [DEFAULT_MAX_TOKEN_SIZE](crate::protocol::DEFAULT_MAX_TOKEN_SIZE) bytes
+    /// require support for extended token lengths ([RFC 8974](https://datatracker.ietf.org/doc/html/rfc8974))
+    /// by both the local context (see [CoapContext::set_max_token_size()](crate::CoapContext::set_max_token_size()))
+    /// and the peer, otherwise sending the message will fail with
+    /// [MessageConversionError::TokenTooLong].
     fn set_token<D: Into<Box<[u8]>>>(&mut self, token: Option<D>) {
         self.as_message_mut().token = token.map(Into::into);
     }
@@ -368,7 +712,77 @@ pub struct CoapMessage {
     /// CoAP message token – used for request-response-matching.
     token: Option<Box<[u8]>>,
     /// Message body of this message.
-    data: Option<Box<[u8]>>,
+    data: Option<CoapMessagePayload>,
+}
+
+/// Storage of the body of a [CoapMessage].
+enum CoapMessagePayload {
+    /// Body owned by the message.
+    Owned(Box<[u8]>),
+    /// Body shared with other messages, see [CoapMessageCommon::set_data_shared()].
+    Shared(Arc<[u8]>),
+    /// Body with a static lifetime, see [CoapMessageCommon::set_data_static()].
+    Static(&'static [u8]),
+    /// Body borrowed from a raw PDU, see [CoapMessage::from_raw_pdu_borrowed()].
+    Borrowed(NonNull<u8>, usize),
+}
+
+// SAFETY: Borrowed bodies are never modified and only created for messages that do not outlive
+// the raw PDU they refer to, the other variants are Send and Sync themselves.
+unsafe impl Send for CoapMessagePayload {}
+unsafe impl Sync for CoapMessagePayload {}
+
+impl CoapMessagePayload {
+    /// Returns the bytes of this body.
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            CoapMessagePayload::Owned(data) => data,
+            CoapMessagePayload::Shared(data) => data,
+            CoapMessagePayload::Static(data) => data,
+            // SAFETY: The raw PDU the body was borrowed from outlives this message.
+            CoapMessagePayload::Borrowed(data, len) => unsafe { std::slice::from_raw_parts(data.as_ptr(), *len) },
+        }
+    }
+
+    /// Converts this body into one that does not borrow from a raw PDU (copying it if required),
+    /// which can therefore be handed to libcoap.
+    fn into_detached(self) -> CoapMessagePayload {
+        match self {
+            CoapMessagePayload::Borrowed(..) => CoapMessagePayload::Owned(Box::from(self.as_slice())),
+            data => data,
+        }
+    }
+}
+
+impl Clone for CoapMessagePayload {
+    /// Clones this body, which copies bodies that are owned by or borrowed for the message.
+    fn clone(&self) -> Self {
+        match self {
+            CoapMessagePayload::Shared(data) => CoapMessagePayload::Shared(data.clone()),
+            CoapMessagePayload::Static(data) => CoapMessagePayload::Static(*data),
+            data => CoapMessagePayload::Owned(Box::from(data.as_slice())),
+        }
+    }
+}
+
+impl Debug for CoapMessagePayload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl PartialEq for CoapMessagePayload {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for CoapMessagePayload {}
+
+impl Hash for CoapMessagePayload {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
 }
 
 impl CoapMessage {
@@ -390,6 +804,29 @@ impl CoapMessage {
     /// # Safety
     /// raw_pdu must point to a valid instance of coap_pdu_t.
     pub unsafe fn from_raw_pdu(raw_pdu: *const coap_pdu_t) -> Result<CoapMessage, MessageConversionError> {
+        Self::from_raw_pdu_with_payload(raw_pdu, false)
+    }
+
+    /// Parses the given raw coap_pdu_t into a CoapMessage without copying its body.
+    ///
+    /// # Safety
+    /// raw_pdu must point to a valid instance of coap_pdu_t, which must not be modified or freed
+    /// before the returned message (and anything borrowing its body) is dropped.
+    pub(crate) unsafe fn from_raw_pdu_borrowed(
+        raw_pdu: *const coap_pdu_t,
+    ) -> Result<CoapMessage, MessageConversionError> {
+        Self::from_raw_pdu_with_payload(raw_pdu, true)
+    }
+
+    /// Parses the given raw coap_pdu_t into a CoapMessage, borrowing its body if `borrow_payload`
+    /// is true and copying it otherwise.
+    ///
+    /// # Safety
+    /// See [CoapMessage::from_raw_pdu()] and [CoapMessage::from_raw_pdu_borrowed()].
+    unsafe fn from_raw_pdu_with_payload(
+        raw_pdu: *const coap_pdu_t,
+        borrow_payload: bool,
+    ) -> Result<CoapMessage, MessageConversionError> {
         ensure_coap_started();
         let mut option_iter = MaybeUninit::zeroed();
         coap_option_iterator_init(raw_pdu, option_iter.as_mut_ptr(), std::ptr::null());
@@ -403,15 +840,19 @@ impl CoapMessage {
         let mut len: usize = 0;
         let mut data = std::ptr::null();
         coap_get_data(raw_pdu, &mut len, &mut data);
-        let data = match len {
-            0 => None,
-            len => Some(Vec::from(std::slice::from_raw_parts(data, len)).into_boxed_slice()),
+        let data = match (len, NonNull::new(data.cast_mut())) {
+            (0, _) | (_, None) => None,
+            (len, Some(data)) if borrow_payload => Some(CoapMessagePayload::Borrowed(data, len)),
+            (len, Some(data)) => Some(CoapMessagePayload::Owned(Box::from(std::slice::from_raw_parts(
+                data.as_ptr(),
+                len,
+            )))),
         };
         let raw_token = coap_pdu_get_token(raw_pdu);
         let token = Vec::from(std::slice::from_raw_parts(raw_token.s, raw_token.length));
         Ok(CoapMessage {
             type_: coap_pdu_get_type(raw_pdu).into(),
-            code: coap_pdu_get_code(raw_pdu).try_into().unwrap(),
+            code: CoapMessageCode::from_raw_code(coap_pdu_get_raw_code(raw_pdu) as u8)?,
             mid: Some(coap_pdu_get_mid(raw_pdu)),
             options,
             token: Some(token.into_boxed_slice()),
@@ -419,6 +860,108 @@ impl CoapMessage {
         })
     }
 
+    /// Encodes this message for the given transport without requiring a session, e.g., for storing
+    /// the message or for testing purposes.
+    ///
+    /// For unreliable transports (UDP/DTLS), the message is encoded as a datagram as described in
+    /// [RFC 7252, Section 3](https://datatracker.ietf.org/doc/html/rfc7252#section-3), for TCP/TLS,
+    /// the framing described in [RFC 8323, Section 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2)
+    /// and for WebSockets the one described in
+    /// [RFC 8323, Section 4.2](https://datatracker.ietf.org/doc/html/rfc8323#section-4.2) is used.
+    /// The encoding is not affected by (D)TLS. Options are encoded in the order of their option
+    /// numbers, and the payload is not split into blocks.
+    ///
+    /// Messages for reliable transports do not need a message ID, as neither the message ID nor
+    /// the message type is part of their encoding.
+    ///
+    /// # Errors
+    /// Returns a [MessageConversionError] if the message has no token, has no message ID (for
+    /// unreliable transports), has a payload despite being empty or contains an invalid option
+    /// value.
+    pub fn to_bytes(&self, proto: CoapProtocol) -> Result<Vec<u8>, MessageConversionError> {
+        ensure_coap_started();
+        let mut message = self.clone();
+        let mid = match message.mid {
+            Some(mid) => mid,
+            None if proto.is_reliable() => 0,
+            None => return Err(MessageConversionError::MissingMessageId),
+        };
+        // SAFETY: all values are valid, cannot cause UB.
+        let pdu = unsafe {
+            coap_pdu_init(
+                message.type_.to_raw_pdu_type(),
+                coap_pdu_code_t::COAP_EMPTY_CODE,
+                mid,
+                message.max_encoded_size(),
+            )
+        };
+        if pdu.is_null() {
+            return Err(MessageConversionError::Unknown);
+        }
+        // SAFETY: We just checked that pdu is a valid pointer, it is only deleted after the view
+        // has been dropped.
+        let result = unsafe {
+            message.apply_header_to_raw_pdu(pdu).and_then(|()| {
+                if let Some(data) = message.data.take() {
+                    if message.code == CoapMessageCode::Empty {
+                        return Err(MessageConversionError::DataInEmptyMessage);
+                    }
+                    let data = data.as_slice();
+                    if coap_add_data(pdu, data.len(), data.as_ptr()) == 0 {
+                        return Err(MessageConversionError::Unknown);
+                    }
+                }
+                Ok(CoapPduView::from_raw(pdu, proto).encoded())
+            })
+        };
+        // SAFETY: The PDU was created above and is not referenced anymore.
+        unsafe { coap_delete_pdu(pdu) };
+        result
+    }
+
+    /// Parses the given encoding of a message for the given transport (see
+    /// [CoapMessage::to_bytes()] for the supported encodings) without requiring a session.
+    ///
+    /// Messages for reliable transports have no message ID and the message type
+    /// [CoapMessageType::Con], as neither is part of their encoding.
+    ///
+    /// # Errors
+    /// Returns a [MessageParsingError] describing why the bytes are not a valid encoding of a
+    /// message. Inputs are checked before being passed to libcoap, so arbitrary (e.g., fuzzed)
+    /// inputs can be parsed safely.
+    pub fn from_bytes(proto: CoapProtocol, bytes: &[u8]) -> Result<CoapMessage, MessageParsingError> {
+        ensure_coap_started();
+        codec::validate_encoded(proto, bytes)?;
+        // SAFETY: all values are valid, cannot cause UB.
+        let pdu = unsafe {
+            coap_pdu_init(
+                coap_pdu_type_t::COAP_MESSAGE_CON,
+                coap_pdu_code_t::COAP_EMPTY_CODE,
+                0,
+                bytes.len(),
+            )
+        };
+        if pdu.is_null() {
+            return Err(MessageParsingError::Rejected);
+        }
+        // SAFETY: We just checked that pdu is a valid pointer, the message does not borrow from it.
+        let result = unsafe {
+            if coap_pdu_parse(proto.into(), bytes.as_ptr(), bytes.len(), pdu) == 0 {
+                Err(MessageParsingError::Rejected)
+            } else {
+                CoapMessage::from_raw_pdu(pdu).map_err(MessageParsingError::from)
+            }
+        };
+        // SAFETY: The PDU was created above and is not referenced anymore.
+        unsafe { coap_delete_pdu(pdu) };
+        let mut message = result?;
+        if proto.is_reliable() {
+            message.type_ = CoapMessageType::Con;
+            message.mid = None;
+        }
+        Ok(message)
+    }
+
     /// Converts this message into a raw PDU suitable for sending using the raw [coap_send()](libcoap_sys::coap_send())
     /// function.
     ///
@@ -434,7 +977,8 @@ impl CoapMessage {
         let pdu = unsafe {
             coap_pdu_init(
                 message.type_.to_raw_pdu_type(),
-                message.code.to_raw_pdu_code(),
+                // The actual code is set in apply_to_raw_pdu().
+                coap_pdu_code_t::COAP_EMPTY_CODE,
                 message.mid.ok_or(MessageConversionError::MissingMessageId)?,
                 session.max_pdu_size(),
             )
@@ -472,10 +1016,48 @@ impl CoapMessage {
         session: &S,
     ) -> Result<*mut coap_pdu_t, MessageConversionError> {
         assert!(!raw_pdu.is_null(), "attempted to apply CoapMessage to null pointer");
+        self.apply_header_to_raw_pdu(raw_pdu)?;
+        let message = self.as_message_mut();
+        if let Some(data) = message.data.take() {
+            match message.code {
+                CoapMessageCode::Empty => return Err(MessageConversionError::DataInEmptyMessage),
+                CoapMessageCode::Request(_) => {
+                    let (len, data_ptr, app_ptr) = large_data_parts(data);
+                    coap_add_data_large_request(
+                        session.raw_session_mut(),
+                        raw_pdu,
+                        len,
+                        data_ptr,
+                        Some(large_data_cleanup_handler),
+                        app_ptr,
+                    );
+                },
+                CoapMessageCode::Response(_) => {
+                    // Responses that do not fit into a single PDU are sent using
+                    // apply_to_large_response() instead.
+                    let data = data.as_slice();
+                    if coap_add_data(raw_pdu, data.len(), data.as_ptr()) == 0 {
+                        return Err(MessageConversionError::Unknown);
+                    }
+                },
+            }
+        }
+        Ok(raw_pdu)
+    }
+
+    /// Applies the type, code, token and options of this message to the given raw PDU (removing
+    /// the options from this message), but not its payload.
+    ///
+    /// # Safety
+    /// raw_pdu must point to a valid mutable instance of coap_pdu_t.
+    unsafe fn apply_header_to_raw_pdu(&mut self, raw_pdu: *mut coap_pdu_t) -> Result<(), MessageConversionError> {
         coap_pdu_set_type(raw_pdu, self.type_.to_raw_pdu_type());
-        coap_pdu_set_code(raw_pdu, self.code.to_raw_pdu_code());
+        coap_pdu_set_raw_code(raw_pdu, self.code.to_raw_code() as c_uint);
         let message = self.as_message_mut();
         let token: &[u8] = message.token.as_ref().ok_or(MessageConversionError::MissingToken)?;
+        if token.len() > MAX_EXTENDED_TOKEN_SIZE {
+            return Err(MessageConversionError::TokenTooLong(token.len()));
+        }
         if coap_add_token(raw_pdu, token.len(), token.as_ptr()) == 0 {
             return Err(MessageConversionError::Unknown);
         }
@@ -508,32 +1090,158 @@ impl CoapMessage {
                 return Err(MessageConversionError::Unknown);
             }
         }
-        if let Some(data) = message.data.take() {
-            match message.code {
-                CoapMessageCode::Empty => return Err(MessageConversionError::DataInEmptyMessage),
-                CoapMessageCode::Request(_) => {
-                    let len = data.len();
-                    let box_ptr = Box::into_raw(data);
-                    coap_add_data_large_request(
-                        session.raw_session_mut(),
-                        raw_pdu,
-                        len,
-                        box_ptr as *mut u8,
-                        Some(large_data_cleanup_handler),
-                        box_ptr as *mut c_void,
-                    );
+        Ok(())
+    }
+
+    /// Returns whether this message might not fit into a PDU of the given maximum size (excluding
+    /// the message header, see [CoapSessionCommon::max_pdu_size()]).
+    ///
+    /// The size of options is overestimated, so this may also be true for messages that would just
+    /// fit.
+    pub(crate) fn may_exceed_pdu_size(&self, max_pdu_size: usize) -> bool {
+        self.data.is_some() && self.max_encoded_size() > max_pdu_size
+    }
+
+    /// Returns an upper bound for the size of this message when encoded into a PDU (excluding
+    /// the message header).
+    fn max_encoded_size(&self) -> usize {
+        // Each option header takes up at most five bytes, the token length at most two extended
+        // bytes and the payload is preceded by a marker.
+        let options_size: usize = self
+            .options
+            .iter()
+            .map(|option| option.clone().into_value_bytes().map_or(0, |v| v.len()) + 5)
+            .sum();
+        let data_size = self.data.as_ref().map_or(0, |data| data.as_slice().len() + 1);
+        self.token.as_ref().map_or(0, |v| v.len() + 2) + options_size + data_size
+    }
+
+    /// Applies this response to the response PDU that libcoap provided to the request handler of
+    /// the given request, handing the body to libcoap without copying it.
+    ///
+    /// libcoap sends the response PDU once the request handler returns, splitting the body into
+    /// blocks if required (using the Block2 option,
+    /// [RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959)).
+    /// The Content-Format, Max-Age and ETag options are added by libcoap, the former defaults to
+    /// `text/plain` if this response does not specify one. The ETag is encoded as an integer, i.e.,
+    /// leading zero bytes are removed. As libcoap already added the token of the request to the
+    /// response PDU, the token of this message is not applied.
+    ///
+    /// # Safety
+    /// `raw_session` must be the (valid) session on which the request was received, and the
+    /// handler for the request must currently be called by libcoap.
+    pub(crate) unsafe fn apply_to_large_response(
+        mut self,
+        raw_session: *mut coap_session_t,
+        handled: &HandledRequest,
+    ) -> Result<CoapMessageId, MessageConversionError> {
+        let raw_response = handled.raw_response;
+        coap_pdu_set_type(raw_response, self.type_.to_raw_pdu_type());
+        coap_pdu_set_raw_code(raw_response, self.code.to_raw_code() as c_uint);
+        let mut content_format = COAP_MEDIATYPE_TEXT_PLAIN as u16;
+        let mut max_age = -1;
+        let mut etag = 0;
+        let mut optlist = None;
+        for option in std::mem::take(&mut self.options) {
+            match option {
+                CoapOption::ContentFormat(value) => content_format = value,
+                CoapOption::MaxAge(value) => max_age = c_int::try_from(value).unwrap_or(c_int::MAX),
+                CoapOption::ETag(value) => {
+                    etag = value.iter().fold(0, |etag, byte| (etag << 8) | u64::from(*byte));
                 },
-                CoapMessageCode::Response(_) => {
-                    // TODO blockwise transfer here as well.
-                    // (for some reason libcoap needs the request PDU here?)
-                    let data: &[u8] = data.as_ref();
-                    if coap_add_data(raw_pdu, data.len(), data.as_ptr()) == 0 {
+                option => {
+                    let optnum = option.number();
+                    let entry = option.into_optlist_entry().map_err(|e| {
+                        MessageConversionError::InvalidOptionValue(CoapOptionType::try_from(optnum).ok(), e)
+                    })?;
+                    if entry.is_null() {
+                        if let Some(optlist) = optlist {
+                            coap_delete_optlist(optlist);
+                        }
                         return Err(MessageConversionError::Unknown);
                     }
+                    match optlist {
+                        None => optlist = Some(entry),
+                        Some(mut optlist) => {
+                            coap_insert_optlist(&mut optlist, entry);
+                        },
+                    }
                 },
             }
         }
-        Ok(raw_pdu)
+        if let Some(mut optlist) = optlist {
+            let optlist_add_success = coap_add_optlist_pdu(raw_response, &mut optlist);
+            coap_delete_optlist(optlist);
+            if optlist_add_success == 0 {
+                return Err(MessageConversionError::Unknown);
+            }
+        }
+        let data = self.data.take().unwrap_or(CoapMessagePayload::Static(&[]));
+        let (len, data_ptr, app_ptr) = large_data_parts(data);
+        // libcoap calls the cleanup handler itself if adding the body fails.
+        if coap_add_data_large_response(
+            handled.raw_resource,
+            raw_session,
+            handled.raw_request,
+            raw_response,
+            handled.raw_query,
+            content_format,
+            max_age,
+            etag,
+            len,
+            data_ptr,
+            Some(large_data_cleanup_handler),
+            app_ptr,
+        ) == 0
+        {
+            return Err(MessageConversionError::Unknown);
+        }
+        Ok(coap_pdu_get_mid(raw_response))
+    }
+
+    /// Sends this response as a separate response to the given request, handing the body to
+    /// libcoap without copying it.
+    ///
+    /// In contrast to [CoapMessage::apply_to_large_response()], the response is not applied to
+    /// libcoap's response PDU, but sent in a newly created PDU with the message type and ID of this
+    /// message (or a new message ID if it has none). This way, libcoap can still acknowledge a
+    /// confirmable request using its response PDU, and responses can be sent after the request
+    /// handler has returned.
+    ///
+    /// # Safety
+    /// `raw_session` must be the (valid) session on which the request was received, the raw
+    /// resource and request of `handled` must be valid.
+    pub(crate) unsafe fn send_as_large_separate_response(
+        self,
+        raw_session: *mut coap_session_t,
+        handled: &HandledRequest,
+    ) -> Result<CoapMessageId, MessageConversionError> {
+        let mid = self
+            .mid
+            .unwrap_or_else(|| coap_new_message_id(raw_session) as CoapMessageId);
+        let raw_response = coap_pdu_init(
+            self.type_.to_raw_pdu_type(),
+            coap_pdu_code_t::COAP_EMPTY_CODE,
+            mid,
+            coap_session_max_pdu_size(raw_session),
+        );
+        if raw_response.is_null() {
+            return Err(MessageConversionError::Unknown);
+        }
+        let raw_token = coap_pdu_get_token(handled.raw_request);
+        coap_add_token(raw_response, raw_token.length, raw_token.s);
+        let handled = HandledRequest {
+            raw_response,
+            ..*handled
+        };
+        match self.apply_to_large_response(raw_session, &handled) {
+            // coap_send() takes ownership of the PDU, even if sending fails.
+            Ok(_) => Ok(coap_send(raw_session, raw_response)),
+            Err(e) => {
+                coap_delete_pdu(raw_response);
+                Err(e)
+            },
+        }
     }
 }
 
@@ -559,7 +1267,19 @@ impl From<CoapResponse> for CoapMessage {
     }
 }
 
+/// Splits the given body into the length, data pointer and application pointer that are provided
+/// to libcoap's functions for large message bodies.
+///
+/// The body must be released by calling [large_data_cleanup_handler()] with the returned
+/// application pointer.
+fn large_data_parts(data: CoapMessagePayload) -> (usize, *const u8, *mut c_void) {
+    // Moving the body into a box does not move the bytes it refers to.
+    let data = Box::new(data.into_detached());
+    let bytes = data.as_slice();
+    (bytes.len(), bytes.as_ptr(), Box::into_raw(data) as *mut c_void)
+}
+
 /// Handler provided to libcoap to cleanup large message bodies.
 unsafe extern "C" fn large_data_cleanup_handler(_session: *mut coap_session_t, app_ptr: *mut c_void) {
-    std::mem::drop(Box::from_raw(app_ptr as *mut u8));
+    std::mem::drop(Box::from_raw(app_ptr as *mut CoapMessagePayload));
 }
@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/paged.rs - Helpers for answering requests with block-wise responses.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Helpers for serving large representations block by block.
+//!
+//! By default, libcoap handles block-wise transfers ([RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959))
+//! by itself, which requires the whole representation to be generated for each request.
+//! [CoapPagedResponder] allows request handlers to instead generate only the part of the
+//! representation that is required for the requested block.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::PagedResponseError,
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon, CoapOption},
+    protocol::{Block, CoapMessageCode, CoapResponseCode, ETag},
+    session::{CoapSessionCommon, CoapSessionId},
+};
+
+/// Block size exponent (SZX) for the largest block size allowed by RFC 7959 (1024 bytes).
+const MAX_SZX: u8 = 6;
+
+/// State of a block-wise transfer that is currently in progress.
+#[derive(Debug)]
+struct PagedTransfer {
+    etag: ETag,
+    /// Offset of the next block if the page provider indicated that more data is available.
+    next_offset: Option<usize>,
+}
+
+/// Responder for requests whose responses should be generated page by page.
+///
+/// Each call to [CoapPagedResponder::respond()] answers a single request with the block that
+/// was requested by the client (using the Block2 option), invoking the supplied page provider
+/// only for the range of the representation that is part of this block.
+///
+/// The responder keeps track of block-wise transfers in progress to ensure that the ETag of the
+/// representation does not change during a transfer. Clients that restart a transfer from block
+/// 0 (e.g. after noticing an ETag change) start a new transfer.
+/// As block-wise transfers are identified by their session, request URI and request payload
+/// (i.e. the query of a FETCH request), a single responder can be shared between multiple
+/// clients and requests, e.g. by storing it in the user data of a resource.
+///
+/// In strict mode, inconsistencies in the data provided by the page provider are reported as
+/// errors instead of being corrected, which is useful to catch bugs in page providers in tests.
+#[derive(Debug)]
+pub struct CoapPagedResponder {
+    szx: u8,
+    strict: bool,
+    transfers: HashMap<(CoapSessionId, Vec<u8>), PagedTransfer>,
+}
+
+impl Default for CoapPagedResponder {
+    fn default() -> Self {
+        CoapPagedResponder::new()
+    }
+}
+
+impl CoapPagedResponder {
+    /// Creates a new paged responder using the maximum block size of 1024 bytes.
+    pub fn new() -> CoapPagedResponder {
+        CoapPagedResponder {
+            szx: MAX_SZX,
+            strict: false,
+            transfers: HashMap::new(),
+        }
+    }
+
+    /// Returns the maximum block size used for responses (in bytes).
+    ///
+    /// Clients may request the use of smaller blocks.
+    pub fn max_block_size(&self) -> usize {
+        block_size(self.szx)
+    }
+
+    /// Sets the maximum block size used for responses.
+    ///
+    /// # Panics
+    /// Panics if `block_size` is not a power of two between 16 and 1024.
+    pub fn set_max_block_size(&mut self, block_size: usize) {
+        assert!(
+            block_size.is_power_of_two() && (16..=1024).contains(&block_size),
+            "block size must be a power of two between 16 and 1024"
+        );
+        self.szx = (block_size.trailing_zeros() - 4) as u8;
+    }
+
+    /// Returns whether strict mode is enabled.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Enables or disables strict mode.
+    ///
+    /// If strict mode is enabled, the following situations are reported as errors by
+    /// [CoapPagedResponder::respond()] instead of being handled silently:
+    /// - the ETag changes while a block-wise transfer is in progress (instead of starting a new
+    ///   transfer, which will cause the client to restart the transfer).
+    /// - the page provider returns more data than requested (instead of truncating the data).
+    /// - the page provider returns no data for an offset that it previously indicated to have
+    ///   data for (instead of responding with 4.02 Bad Option).
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Discards the state of all block-wise transfers in progress.
+    ///
+    /// The state of a transfer is discarded automatically once its last block has been served,
+    /// use this function to also discard transfers that were abandoned by clients.
+    pub fn clear(&mut self) {
+        self.transfers.clear()
+    }
+
+    /// Answers the given request with the block it requested.
+    ///
+    /// `etag` should identify the current version of the representation, `pages` is called with
+    /// the offset of the requested block and the maximum number of bytes to return, and should
+    /// return the part of the representation starting at this offset, or None if the offset is
+    /// past the end of the representation.
+    /// The maximum length is one byte larger than the block size, which allows the responder to
+    /// determine whether further blocks follow without calling `pages` again. Page providers
+    /// should therefore only return fewer bytes than requested for the end of the
+    /// representation.
+    ///
+    /// The block, the Block2 option and the ETag are set on the provided response, which can
+    /// then be sent using [CoapSessionCommon::send()]. The response code is only changed if the
+    /// block cannot be served.
+    ///
+    /// # Errors
+    /// Returns [PagedResponseError::BlockOutOfRange] (and sets the response code to 4.02 Bad
+    /// Option) if the requested block is past the end of the representation.
+    ///
+    /// In strict mode, also returns the respective [PagedResponseError] variants for the
+    /// situations listed in [CoapPagedResponder::set_strict()] and sets the response code to
+    /// 5.00 Internal Server Error.
+    pub fn respond<'a, S: CoapSessionCommon<'a>, F: FnMut(usize, usize) -> Option<Vec<u8>>>(
+        &mut self,
+        session: &S,
+        request: &CoapRequest,
+        response: &mut CoapResponse,
+        etag: ETag,
+        mut pages: F,
+    ) -> Result<(), PagedResponseError> {
+        let key = (session.id(), transfer_key(request));
+        let (offset, szx) = match request.block2() {
+            Some(block) => {
+                let (num, requested_szx) = (block >> 4, (block & 0x7) as u8);
+                // SZX 7 is reserved (and used for BERT on reliable transports), we treat it like
+                // the largest regular block size.
+                let requested_szx = requested_szx.min(MAX_SZX);
+                (num as usize * block_size(requested_szx), requested_szx.min(self.szx))
+            },
+            None => (0, self.szx),
+        };
+        let size = block_size(szx);
+
+        if offset == 0 {
+            // Clients restarting from block 0 always start a new transfer.
+            self.transfers.remove(&key);
+        }
+        let transfer = self.transfers.entry(key.clone()).or_insert_with(|| PagedTransfer {
+            etag: etag.clone(),
+            next_offset: None,
+        });
+        if transfer.etag != etag {
+            if self.strict {
+                self.transfers.remove(&key);
+                return fail(
+                    response,
+                    CoapResponseCode::InternalError,
+                    PagedResponseError::ETagChanged,
+                );
+            }
+            *transfer = PagedTransfer {
+                etag: etag.clone(),
+                next_offset: None,
+            };
+        }
+
+        let mut data = match pages(offset, size + 1) {
+            Some(data) => data,
+            None if offset == 0 => Vec::new(),
+            None => {
+                let announced = transfer.next_offset == Some(offset);
+                self.transfers.remove(&key);
+                return if announced && self.strict {
+                    fail(
+                        response,
+                        CoapResponseCode::InternalError,
+                        PagedResponseError::InconsistentPageSize(offset),
+                    )
+                } else {
+                    fail(
+                        response,
+                        CoapResponseCode::BadOption,
+                        PagedResponseError::BlockOutOfRange(offset),
+                    )
+                };
+            },
+        };
+        if data.len() > size + 1 {
+            if self.strict {
+                self.transfers.remove(&key);
+                return fail(
+                    response,
+                    CoapResponseCode::InternalError,
+                    PagedResponseError::PageTooLong(offset, data.len(), size + 1),
+                );
+            }
+            data.truncate(size + 1);
+        }
+        let more = data.len() > size;
+        data.truncate(size);
+
+        if more {
+            transfer.next_offset = Some(offset + size);
+        } else {
+            self.transfers.remove(&key);
+        }
+
+        let num = (offset / size) as Block;
+        response.add_option(CoapOption::Block2((num << 4) | (u32::from(more) << 3) | u32::from(szx)));
+        response.set_etag(Some(etag));
+        response.set_data(Some(data));
+        Ok(())
+    }
+}
+
+/// Returns the block size for the given block size exponent.
+fn block_size(szx: u8) -> usize {
+    1 << (szx + 4)
+}
+
+/// Returns the key identifying the block-wise transfer the given request belongs to (apart from
+/// the session).
+fn transfer_key(request: &CoapRequest) -> Vec<u8> {
+    let mut key = Vec::new();
+    for part in [
+        request.uri().path(),
+        request.uri().query(),
+        request.data().map(|v| v.as_ref()),
+    ] {
+        let part = part.unwrap_or(&[]);
+        key.extend_from_slice(&part.len().to_be_bytes());
+        key.extend_from_slice(part);
+    }
+    key
+}
+
+/// Sets the given error code on the response and returns the given error.
+fn fail(
+    response: &mut CoapResponse,
+    code: CoapResponseCode,
+    err: PagedResponseError,
+) -> Result<(), PagedResponseError> {
+    response.set_code(CoapMessageCode::Response(code));
+    Err(err)
+}
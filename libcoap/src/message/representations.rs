@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/representations.rs - Content negotiation between multiple representations of a resource.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Helpers for serving a resource in multiple content formats.
+//!
+//! [CoapRepresentations] holds the representations of a resource in different content formats
+//! (e.g., JSON and CBOR) and answers GET requests with the representation selected by the Accept
+//! option of the request ([RFC 7252, Section 5.10.4](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.4)).
+
+use std::{
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use crate::{
+    error::NotAcceptable,
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon},
+    protocol::{CoapContentFormat, CoapMessageCode, CoapResponseCode, ETag},
+};
+
+/// Source of the payload of a representation.
+enum RepresentationSource {
+    /// Payload that is shared with every response (and libcoap) without being copied.
+    Data(Arc<[u8]>),
+    /// Function that generates the payload whenever the representation is selected.
+    Producer(Box<dyn FnMut() -> Vec<u8>>),
+}
+
+/// A single representation of a resource, see [CoapRepresentations].
+struct Representation {
+    format: CoapContentFormat,
+    source: RepresentationSource,
+    etag: Option<ETag>,
+}
+
+/// Representations of a resource in different content formats.
+///
+/// Representations are registered in order of preference, the first registered representation is
+/// used for requests without an Accept option. [CoapRepresentations::respond()] selects the
+/// representation for a request (see [CoapRequest::preferred_format()]), only calling the
+/// producer of the selected representation, and answers requests for unavailable content formats
+/// with 4.06 (Not Acceptable).
+///
+/// Payloads are sent like any other response payload, i.e., representations that do not fit into
+/// a single PDU are sent block-wise by libcoap (see
+/// [CoapSessionCommon::send()](crate::session::CoapSessionCommon::send)).
+///
+/// Each representation may have its own ETag, which is set on the responses for that
+/// representation. Requests that contain the ETag of the selected representation are answered with
+/// 2.03 (Valid) without producing the payload. As the ETag of a resource (see
+/// [CoapResource::set_etag()](crate::CoapResource::set_etag)) is validated for all
+/// representations, it should not be set for resources served using this type.
+///
+/// Representations can either be used in a GET handler or added to a resource using
+/// [CoapResourceBuilder::representations()](crate::CoapResourceBuilder::representations).
+#[derive(Default)]
+pub struct CoapRepresentations {
+    representations: Vec<Representation>,
+}
+
+impl CoapRepresentations {
+    /// Creates an empty set of representations.
+    pub fn new() -> CoapRepresentations {
+        CoapRepresentations::default()
+    }
+
+    /// Adds a representation with the given content format and payload, replacing any
+    /// representation previously added for this content format (keeping its position, but not its
+    /// ETag).
+    ///
+    /// The payload is shared with all responses instead of being copied.
+    pub fn add_data<P: Into<Arc<[u8]>>>(&mut self, format: CoapContentFormat, data: P) {
+        self.add(format, RepresentationSource::Data(data.into()));
+    }
+
+    /// Adds a representation with the given content format whose payload is generated by
+    /// `producer` whenever the representation is selected for a request, replacing any
+    /// representation previously added for this content format (keeping its position, but not its
+    /// ETag).
+    pub fn add_producer<F: 'static + FnMut() -> Vec<u8>>(&mut self, format: CoapContentFormat, producer: F) {
+        self.add(format, RepresentationSource::Producer(Box::new(producer)));
+    }
+
+    /// Adds a representation with the given content format and payload, see
+    /// [CoapRepresentations::add_data()].
+    pub fn with_data<P: Into<Arc<[u8]>>>(mut self, format: CoapContentFormat, data: P) -> Self {
+        self.add_data(format, data);
+        self
+    }
+
+    /// Adds a representation with the given content format and payload producer, see
+    /// [CoapRepresentations::add_producer()].
+    pub fn with_producer<F: 'static + FnMut() -> Vec<u8>>(mut self, format: CoapContentFormat, producer: F) -> Self {
+        self.add_producer(format, producer);
+        self
+    }
+
+    /// Removes the representation with the given content format, returning whether it existed.
+    pub fn remove(&mut self, format: CoapContentFormat) -> bool {
+        let len = self.representations.len();
+        self.representations.retain(|v| v.format != format);
+        self.representations.len() != len
+    }
+
+    /// Returns the content formats of the registered representations, in order of preference.
+    pub fn formats(&self) -> Vec<CoapContentFormat> {
+        self.representations.iter().map(|v| v.format).collect()
+    }
+
+    /// Returns the ETag of the representation with the given content format (if any).
+    pub fn etag(&self, format: CoapContentFormat) -> Option<&ETag> {
+        self.get(format).and_then(|v| v.etag.as_ref())
+    }
+
+    /// Sets the ETag of the representation with the given content format.
+    ///
+    /// Returns false (and does nothing) if there is no representation with this content format.
+    /// The ETag should be updated whenever the representation changes.
+    pub fn set_etag(&mut self, format: CoapContentFormat, etag: Option<ETag>) -> bool {
+        match self.representations.iter_mut().find(|v| v.format == format) {
+            Some(representation) => {
+                representation.etag = etag;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Prepares the response to the given request using the representation selected by its Accept
+    /// option, returning the content format of the selected representation.
+    ///
+    /// The response code (2.05 Content, or 2.03 Valid if the request contains the ETag of the
+    /// selected representation), Content-Format, ETag and payload are set on the provided
+    /// response, which can then be sent using
+    /// [CoapSessionCommon::send()](crate::session::CoapSessionCommon::send).
+    ///
+    /// # Errors
+    /// Returns [NotAcceptable] (and sets the response code to 4.06 Not Acceptable, removing any
+    /// payload) if no representation is available in the content format requested by the client
+    /// (or if there are no representations at all).
+    pub fn respond(
+        &mut self,
+        request: &CoapRequest,
+        response: &mut CoapResponse,
+    ) -> Result<CoapContentFormat, NotAcceptable> {
+        let format = match request.preferred_format(&self.formats()) {
+            Ok(format) => format,
+            Err(e) => {
+                response.set_code(CoapMessageCode::from(e));
+                response.set_data(None::<Vec<u8>>);
+                return Err(e);
+            },
+        };
+        let representation = self
+            .representations
+            .iter_mut()
+            .find(|v| v.format == format)
+            .expect("selected content format has no representation");
+        response.set_content_format(Some(format.into()));
+        response.set_etag(representation.etag.clone());
+        let valid = representation
+            .etag
+            .as_ref()
+            .is_some_and(|etag| request.etag().is_some_and(|etags| etags.contains(etag)));
+        if valid {
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::Valid));
+            response.set_data(None::<Vec<u8>>);
+            return Ok(format);
+        }
+        response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+        match &mut representation.source {
+            RepresentationSource::Data(data) => response.set_data_shared(Some(data.clone())),
+            RepresentationSource::Producer(producer) => response.set_data(Some(producer())),
+        }
+        Ok(format)
+    }
+
+    fn add(&mut self, format: CoapContentFormat, source: RepresentationSource) {
+        match self.representations.iter_mut().find(|v| v.format == format) {
+            Some(representation) => {
+                representation.source = source;
+                representation.etag = None;
+            },
+            None => self.representations.push(Representation {
+                format,
+                source,
+                etag: None,
+            }),
+        }
+    }
+
+    fn get(&self, format: CoapContentFormat) -> Option<&Representation> {
+        self.representations.iter().find(|v| v.format == format)
+    }
+}
+
+impl Debug for CoapRepresentations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapRepresentations")
+            .field("formats", &self.formats())
+            .finish_non_exhaustive()
+    }
+}
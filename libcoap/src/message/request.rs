@@ -7,20 +7,29 @@
  * See the README as well as the LICENSE file for more information.
  */
 
-use std::str::FromStr;
+use std::{borrow::Cow, str::FromStr, time::Duration};
 
 use crate::{
-    error::{MessageConversionError, MessageTypeError},
-    message::{CoapMessage, CoapMessageCommon, CoapOption},
+    error::{MessageConversionError, MessageTypeError, NotAcceptable, RequestBuildError},
+    message::{sorted_option_set, CoapMessage, CoapMessageCommon, CoapOption, CoapOptionSet},
     protocol::{
-        CoapMatch, CoapMessageCode, CoapMessageType, CoapOptionType, CoapRequestCode, ContentFormat, ETag, HopLimit,
-        NoResponse, Observe,
+        Block, CoapContentFormat, CoapMatch, CoapMessageCode, CoapMessageType, CoapNoResponse, CoapOptionType,
+        CoapRequestCode, CoapToken, ContentFormat, ETag, Echo, HopLimit, MaxAge, NoResponse, Observe, RequestTag, Size,
+        UriQuery, HOP_LIMIT_RANGE, MAX_EXTENDED_TOKEN_SIZE,
     },
-    types::{CoapUri, CoapUriScheme},
+    types::{percent_decode, utf8_lossy, CoapUri, CoapUriScheme},
 };
 use crate::error::OptionValueError;
+#[cfg(feature = "async")]
+use crate::error::AsyncRequestError;
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+use crate::error::{PayloadDecodeError, PayloadEncodeError};
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+use crate::message::typed_payload;
 use crate::message::{construct_path_string, construct_query_string};
-use crate::session::CoapSessionCommon;
+#[cfg(feature = "async")]
+use crate::session::{CoapAsyncHandle, CoapServerSession};
+use crate::session::{CoapSessionCommon, CoapSessionId};
 
 /// Representation of a CoAP request message.
 ///
@@ -30,6 +39,7 @@ use crate::session::CoapSessionCommon;
 pub struct CoapRequest {
     pdu: CoapMessage,
     uri: CoapUri,
+    query: Vec<UriQuery>,
     accept: Option<ContentFormat>,
     etag: Option<Vec<ETag>>,
     if_match: Option<Vec<CoapMatch>>,
@@ -38,6 +48,20 @@ pub struct CoapRequest {
     hop_limit: Option<HopLimit>,
     no_response: Option<NoResponse>,
     observe: Option<Observe>,
+    block1: Option<Block>,
+    block2: Option<Block>,
+    size1: Option<Size>,
+    size2: Option<Size>,
+    echo: Option<Echo>,
+    request_tags: Vec<RequestTag>,
+    session_id: Option<CoapSessionId>,
+    retransmission: bool,
+    async_replay: bool,
+    timeout: Option<Duration>,
+    bypass_cache: bool,
+    /// Whether the message type of this request should be replaced by the default message type
+    /// of the session it is sent on (set for builders without an explicit message type).
+    default_type: bool,
 }
 
 impl CoapRequest {
@@ -51,9 +75,19 @@ impl CoapRequest {
             CoapMessageType::Con | CoapMessageType::Non => {},
             v => return Err(MessageTypeError::InvalidForMessageCode(v)),
         }
+        let query = uri
+            .clone()
+            .into_options()
+            .into_iter()
+            .filter_map(|option| match option {
+                CoapOption::UriQuery(query) => Some(query),
+                _ => None,
+            })
+            .collect();
         Ok(CoapRequest {
             pdu: CoapMessage::new(type_, code.into()),
             uri,
+            query,
             accept: None,
             etag: None,
             if_match: None,
@@ -62,6 +96,18 @@ impl CoapRequest {
             hop_limit: None,
             no_response: None,
             observe: None,
+            block1: None,
+            block2: None,
+            size1: None,
+            size2: None,
+            echo: None,
+            request_tags: Vec::new(),
+            session_id: None,
+            retransmission: false,
+            async_replay: false,
+            timeout: None,
+            bypass_cache: false,
+            default_type: false,
         })
     }
 
@@ -80,6 +126,30 @@ impl CoapRequest {
         self.accept = accept
     }
 
+    /// Returns the "Accept" option value for this request as a [CoapContentFormat].
+    pub fn accept_format(&self) -> Option<CoapContentFormat> {
+        self.accept.map(CoapContentFormat::from)
+    }
+
+    /// Selects the content format that should be used for the response to this request.
+    ///
+    /// `supported` should list the content formats the handler is able to provide, in order of
+    /// preference.
+    /// If the request has an "Accept" option, its value is returned if it is part of `supported`.
+    /// Otherwise, the first supported content format is returned.
+    ///
+    /// # Errors
+    /// Returns [NotAcceptable] if the content format requested by the client is not supported
+    /// (or if `supported` is empty). The error can be converted into a [CoapResponseCode](crate::protocol::CoapResponseCode)
+    /// (4.06 Not Acceptable) that can be used as the response code.
+    pub fn preferred_format(&self, supported: &[CoapContentFormat]) -> Result<CoapContentFormat, NotAcceptable> {
+        match self.accept_format() {
+            Some(accept) if supported.contains(&accept) => Ok(accept),
+            Some(accept) => Err(NotAcceptable(Some(accept))),
+            None => supported.first().copied().ok_or(NotAcceptable(None)),
+        }
+    }
+
     /// Returns the "ETag" option value for this request.
     pub fn etag(&self) -> Option<&Vec<ETag>> {
         self.etag.as_ref()
@@ -131,6 +201,27 @@ impl CoapRequest {
         self.content_format = content_format;
     }
 
+    /// Returns the "Content-Format" option value for this request as a [CoapContentFormat].
+    pub fn payload_format(&self) -> Option<CoapContentFormat> {
+        self.content_format.map(CoapContentFormat::from)
+    }
+
+    /// Decodes the payload of this request into a value of type `T` according to its
+    /// Content-Format.
+    ///
+    /// JSON payloads are supported if the `serde-json` feature is enabled, CBOR payloads if the
+    /// `serde-cbor` feature is enabled.
+    ///
+    /// # Errors
+    /// Returns [PayloadDecodeError::UnsupportedContentFormat] if the request has no
+    /// Content-Format or one that is not supported, and [PayloadDecodeError::InvalidPayload] if
+    /// the payload can not be deserialized. Both can be converted into the response code to
+    /// answer the request with (4.15 (Unsupported Content-Format) and 4.00 (Bad Request)).
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    pub fn parse_payload<T: serde::de::DeserializeOwned>(&self) -> Result<T, PayloadDecodeError> {
+        typed_payload::decode(self.payload_format(), self.payload())
+    }
+
     /// Returns the "If-None-Match" option value of this request.
     pub fn if_none_match(&self) -> bool {
         self.if_none_match
@@ -161,8 +252,16 @@ impl CoapRequest {
     ///
     /// This option is defined in [RFC 8768](https://datatracker.ietf.org/doc/html/rfc8768) and is
     /// not part of the main CoAP spec. Some peers may therefore not support this option.
-    pub fn set_hop_limit(&mut self, hop_limit: Option<HopLimit>) {
+    ///
+    /// # Errors
+    /// Returns [OptionValueError::IllegalValue] if the value is outside of [HOP_LIMIT_RANGE], in
+    /// which case the option is left unchanged.
+    pub fn set_hop_limit(&mut self, hop_limit: Option<HopLimit>) -> Result<(), OptionValueError> {
+        if hop_limit.is_some_and(|v| !HOP_LIMIT_RANGE.contains(&v)) {
+            return Err(OptionValueError::IllegalValue);
+        }
         self.hop_limit = hop_limit;
+        Ok(())
     }
 
     /// Returns the "No-Response" option value for this request.
@@ -170,6 +269,17 @@ impl CoapRequest {
         self.no_response
     }
 
+    /// Returns the classes of responses the client is not interested in, as indicated by the
+    /// "No-Response" option of this request (empty if the option is not set).
+    ///
+    /// Responses sent using [CoapSessionCommon::send()] from within a request handler are dropped
+    /// automatically if their class is suppressed (in which case libcoap acknowledges confirmable
+    /// requests using an empty ACK).
+    pub fn suppressed_responses(&self) -> CoapNoResponse {
+        self.no_response
+            .map_or(CoapNoResponse::empty(), CoapNoResponse::from_bits_retain)
+    }
+
     /// Sets the "No-Response" option value for this request.
     ///
     /// This option indicates that the client performing this request does not wish to receive a
@@ -197,11 +307,295 @@ impl CoapRequest {
         self.observe = observe;
     }
 
+    /// Returns the "Echo" option value for this request.
+    ///
+    /// Clients include the Echo value received in a 4.01 (Unauthorized) response in the repeated
+    /// request to prove that the request is fresh, see
+    /// [RFC 9175, Section 2](https://datatracker.ietf.org/doc/html/rfc9175#section-2) and the
+    /// [echo](crate::echo) module.
+    pub fn echo(&self) -> Option<&Echo> {
+        self.echo.as_ref()
+    }
+
+    /// Sets the "Echo" option value for this request.
+    ///
+    /// Usually, this is not required, as libcoap automatically repeats requests that were
+    /// answered with a 4.01 response containing an Echo option (see `man coap_send`).
+    pub fn set_echo(&mut self, echo: Option<Echo>) {
+        self.echo = echo;
+    }
+
+    /// Returns the "Request-Tag" option values for this request.
+    ///
+    /// Request-Tag options distinguish concurrent block-wise transfers of request bodies
+    /// ([RFC 9175, Section 3](https://datatracker.ietf.org/doc/html/rfc9175#section-3)), libcoap
+    /// adds them to block-wise requests on its own.
+    pub fn request_tags(&self) -> &[RequestTag] {
+        &self.request_tags
+    }
+
+    /// Sets the "Request-Tag" option values for this request.
+    pub fn set_request_tags(&mut self, request_tags: Vec<RequestTag>) {
+        self.request_tags = request_tags;
+    }
+
+    /// Returns the raw value of the "Block1" option for this request.
+    ///
+    /// The Block1 option is used by clients to upload a request body block by block
+    /// ([RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959)).
+    /// As libcoap usually reassembles such request bodies by itself, this will only be set for
+    /// resources created with [ResourceFlags::STREAM_BLOCK1](crate::ResourceFlags::STREAM_BLOCK1),
+    /// see [CoapBlock1Chunk](crate::message::upload::CoapBlock1Chunk) for a more convenient way
+    /// to access the block-wise transfer state.
+    pub fn block1(&self) -> Option<Block> {
+        self.block1
+    }
+
+    /// Sets the raw value of the "Block1" option for this request.
+    ///
+    /// libcoap adds this option automatically when sending large requests, so there is usually
+    /// no need to set it.
+    pub fn set_block1(&mut self, block1: Option<Block>) {
+        self.block1 = block1;
+    }
+
+    /// Returns the raw value of the "Block2" option for this request.
+    ///
+    /// The Block2 option is used by clients to request a specific block of a response
+    /// ([RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959)).
+    /// As libcoap usually handles block-wise transfers by itself, this will usually only be set if
+    /// the server application answers with individual blocks, e.g. using
+    /// [CoapPagedResponder](crate::message::paged::CoapPagedResponder).
+    pub fn block2(&self) -> Option<Block> {
+        self.block2
+    }
+
+    /// Sets the raw value of the "Block2" option for this request.
+    pub fn set_block2(&mut self, block2: Option<Block>) {
+        self.block2 = block2;
+    }
+
+    /// Returns the "Size1" option value for this request.
+    ///
+    /// This option indicates the size of the request payload of a block-wise transfer
+    /// ([RFC 7959, Section 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4)).
+    /// libcoap adds this option automatically when sending large requests, so there is usually
+    /// no need to set it.
+    pub fn size1(&self) -> Option<Size> {
+        self.size1
+    }
+
+    /// Sets the "Size1" option value for this request.
+    pub fn set_size1(&mut self, size1: Option<Size>) {
+        self.size1 = size1;
+    }
+
+    /// Returns the "Size2" option value for this request.
+    ///
+    /// A Size2 option with value 0 asks the server to indicate the size of the response
+    /// representation ([RFC 7959, Section 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4)).
+    pub fn size2(&self) -> Option<Size> {
+        self.size2
+    }
+
+    /// Sets the "Size2" option value for this request.
+    pub fn set_size2(&mut self, size2: Option<Size>) {
+        self.size2 = size2;
+    }
+
+    /// Returns the query components of the request URI as key-value pairs.
+    ///
+    /// Each Uri-Query option is split at the first `=`, query components without a `=` are
+    /// returned with a value of None.
+    pub fn uri_query_pairs(&self) -> Vec<(String, Option<String>)> {
+        self.uri
+            .clone()
+            .into_options()
+            .into_iter()
+            .filter_map(|option| match option {
+                CoapOption::UriQuery(query) => Some(match query.split_once('=') {
+                    Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                    None => (query, None),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the query components of this request as percent-decoded key-value pairs.
+    ///
+    /// Each Uri-Query option is split at the first `=` (i.e., values may contain further `=`
+    /// characters) before key and value are percent-decoded. Query components without a `=` are
+    /// returned with a value of None, repeated keys are returned in the order of their options.
+    ///
+    /// Invalid UTF-8 sequences in the decoded values are replaced with U+FFFD, use
+    /// [CoapRequest::query_raw()] to obtain the decoded bytes instead.
+    pub fn query(&self) -> impl Iterator<Item = (Cow<'_, str>, Option<Cow<'_, str>>)> + '_ {
+        self.query_raw()
+            .map(|(key, value)| (utf8_lossy(key), value.map(utf8_lossy)))
+    }
+
+    /// Returns the query components of this request as percent-decoded key-value pairs of raw
+    /// bytes.
+    ///
+    /// See [CoapRequest::query()] for more information.
+    pub fn query_raw(&self) -> impl Iterator<Item = (Cow<'_, [u8]>, Option<Cow<'_, [u8]>>)> + '_ {
+        self.query.iter().map(|component| match component.split_once('=') {
+            Some((key, value)) => (percent_decode(key.as_bytes()), Some(percent_decode(value.as_bytes()))),
+            None => (percent_decode(component.as_bytes()), None),
+        })
+    }
+
+    /// Returns the decoded value of the first query component with the given key (see
+    /// [CoapRequest::query()]).
+    ///
+    /// Query components without a value result in an empty string, None is returned if there is
+    /// no query component with the given key.
+    pub fn query_value(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.query()
+            .find(|(component_key, _)| component_key == key)
+            .map(|(_, value)| value.unwrap_or_default())
+    }
+
+    /// Returns all options of this request, ordered by their option number.
+    ///
+    /// In contrast to [CoapMessageCommon::options_iter()], which only returns the options that
+    /// are not represented by one of the typed fields of this request, the returned set contains
+    /// all options, including unknown ones (see [CoapOption::from_raw_parts()]).
+    /// Use [CoapOptionSet::iter_raw()] to obtain the raw option numbers and values, and
+    /// [CoapOption::is_critical()] to determine whether an unknown option must cause the request
+    /// to be rejected.
+    pub fn options(&self) -> CoapOptionSet {
+        sorted_option_set(self.clone().into_message().options)
+    }
+
     /// Returns the CoAP URI that is requested.
     pub fn uri(&self) -> &CoapUri {
         &self.uri
     }
 
+    /// Returns the time after which the client stops waiting for a response to this request (see
+    /// [CoapRequest::set_timeout()]).
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the time after which the client stops waiting for a response to this request.
+    ///
+    /// The timeout is independent of the retransmission parameters of the session and is
+    /// especially useful for non-confirmable requests, as libcoap never reports a failure for
+    /// these. If no response was received once the timeout (measured from the moment the request
+    /// was sent using [CoapSessionCommon::send_request()]) has elapsed, the request is failed, i.e.,
+    /// [CoapSessionCommon::try_poll_handle()] returns
+    /// [RequestPollError::TimedOut](crate::error::RequestPollError::TimedOut) and responses that
+    /// arrive afterwards are rejected.
+    /// [CoapContext::do_io()](crate::CoapContext::do_io) returns early in order to allow handling
+    /// the timeout in time.
+    ///
+    /// Requests without a timeout (the default) are awaited until their handle is removed.
+    /// The timeout is local to the client and not transmitted to the server.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns whether this request bypasses the response cache of the client session it is sent
+    /// on (see [CoapRequest::set_bypass_cache()]).
+    pub fn bypasses_cache(&self) -> bool {
+        self.bypass_cache
+    }
+
+    /// Sets whether this request bypasses the response cache of the client session it is sent on
+    /// (see [CoapClientSession::set_response_cache_capacity()](crate::session::CoapClientSession::set_response_cache_capacity)).
+    ///
+    /// Requests that bypass the cache are always sent to the server, and their responses are not
+    /// stored in the cache.
+    /// This setting is local to the client and not transmitted to the server.
+    pub fn set_bypass_cache(&mut self, bypass_cache: bool) {
+        self.bypass_cache = bypass_cache;
+    }
+
+    /// Returns whether this request should be sent using the default message type of the session
+    /// (see [CoapSessionCommon::set_default_message_type()](crate::session::CoapSessionCommon::set_default_message_type)).
+    pub(crate) fn uses_default_message_type(&self) -> bool {
+        self.default_type
+    }
+
+    /// Returns the stable identifier of the session this request was received on.
+    ///
+    /// Returns None for requests that were not parsed from a received message.
+    ///
+    /// See [CoapSessionId] for more information.
+    pub fn session_id(&self) -> Option<CoapSessionId> {
+        self.session_id
+    }
+
+    /// Returns whether this request is a retransmission of a request that was received earlier on
+    /// the same session (i.e., has the same message ID).
+    ///
+    /// Handlers of non-idempotent requests may use this to avoid performing an action twice.
+    /// Applications that implement their own deduplication (e.g., using idempotency keys that
+    /// outlive the tracking window) can use the [session ID](CoapRequest::session_id()) together
+    /// with the message ID, type and token of the request (see [CoapMessageCommon]) instead.
+    /// Message IDs are tracked for a limited time and number of requests (see
+    /// [CoapSessionCommon::exchange_lifetime()] and
+    /// [CoapContext::set_dedup_capacity()](crate::CoapContext::set_dedup_capacity)), older
+    /// retransmissions are therefore not detected.
+    /// Retransmissions that are answered by libcoap itself (e.g., for block-wise transfers that are
+    /// handled by libcoap) never reach the request handler.
+    ///
+    /// Always returns false for requests that were not parsed from a received message or were
+    /// received using a reliable transport (which does not use message IDs for deduplication).
+    pub fn is_retransmission(&self) -> bool {
+        self.retransmission
+    }
+
+    /// Marks this request as a retransmission (see [CoapRequest::is_retransmission()]).
+    pub(crate) fn set_retransmission(&mut self, retransmission: bool) {
+        self.retransmission = retransmission;
+    }
+
+    /// Registers this request, which is currently handled by a request handler of the given
+    /// session, as an asynchronous request whose response is sent later on (see
+    /// [CoapAsyncHandle]).
+    ///
+    /// If the handler does not send a response itself, libcoap acknowledges confirmable requests
+    /// with an empty ACK once the handler returns.
+    /// If a `delay` is provided, libcoap passes the request to the request handler again once the
+    /// delay has expired (unless it was answered before), otherwise the request is only answered
+    /// once [CoapAsyncHandle::complete()] or [CoapAsyncHandle::trigger()] is called.
+    ///
+    /// Returns [AsyncRequestError::NotHandled] if the request does not belong to the request
+    /// handler that is currently called for the session, and
+    /// [AsyncRequestError::AlreadyRegistered] if libcoap fails to register the request (e.g.,
+    /// because it is already an asynchronous request, see [CoapRequest::is_async_replay()]).
+    ///
+    /// This function is only available if the `async` feature is enabled.
+    #[cfg(feature = "async")]
+    pub fn into_async(
+        self,
+        session: &CoapServerSession,
+        delay: Option<Duration>,
+    ) -> Result<CoapAsyncHandle, AsyncRequestError> {
+        CoapAsyncHandle::register(session, self, delay)
+    }
+
+    /// Returns whether this request is an asynchronous request (see [CoapRequest::into_async()])
+    /// that libcoap passes to the request handler again, either because its delay has expired or
+    /// because it was triggered using [CoapAsyncHandle::trigger()](crate::session::CoapAsyncHandle::trigger).
+    ///
+    /// Handlers are expected to answer such requests, as libcoap removes the asynchronous request
+    /// once the handler returns.
+    pub fn is_async_replay(&self) -> bool {
+        self.async_replay
+    }
+
+    /// Marks this request as an asynchronous request that is passed to the request handler again
+    /// (see [CoapRequest::is_async_replay()]).
+    #[cfg(feature = "async")]
+    pub(crate) fn set_async_replay(&mut self, async_replay: bool) {
+        self.async_replay = async_replay;
+    }
+
     /// Parses the given [CoapMessage] into a CoapRequest.
     ///
     /// Returns a [MessageConversionError] if the provided PDU cannot be parsed into a request.
@@ -223,6 +617,12 @@ impl CoapRequest {
         let mut hop_limit = None;
         let mut no_response = None;
         let mut observe = None;
+        let mut block1 = None;
+        let mut block2 = None;
+        let mut size1 = None;
+        let mut size2 = None;
+        let mut echo = None;
+        let mut request_tags = Vec::new();
         let mut additional_opts = Vec::new();
         for option in pdu.options_iter() {
             match option {
@@ -310,19 +710,41 @@ impl CoapRequest {
                     }
                     accept = Some(*value);
                 },
-                // libcoap handles blockwise transfer for us (for now).
-                CoapOption::Size1(_) => {},
-                CoapOption::Size2(_) => {
-                    return Err(MessageConversionError::InvalidOptionForMessageType(
-                        CoapOptionType::Size2,
-                    ));
+                CoapOption::Size1(value) => {
+                    if size1.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Size1,
+                        ));
+                    }
+                    size1 = Some(*value);
                 },
-                // libcoap handles blockwise transfer for us (for now).
-                CoapOption::Block1(_) => {},
-                CoapOption::Block2(_) => {
-                    return Err(MessageConversionError::InvalidOptionForMessageType(
-                        CoapOptionType::Block2,
-                    ));
+                CoapOption::Size2(value) => {
+                    if size2.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Size2,
+                        ));
+                    }
+                    size2 = Some(*value);
+                },
+                // Block1 options in requests are only passed on to us for resources that
+                // receive uploads block by block (see ResourceFlags::STREAM_BLOCK1).
+                CoapOption::Block1(value) => {
+                    if block1.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Block1,
+                        ));
+                    }
+                    block1 = Some(*value);
+                },
+                // Block2 options in requests are only passed on to us if the application handles
+                // the block-wise transfer itself (e.g. using CoapPagedResponder).
+                CoapOption::Block2(value) => {
+                    if block2.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Block2,
+                        ));
+                    }
+                    block2 = Some(*value);
                 },
                 // libcoap handles blockwise transfer for us (for now).
                 CoapOption::QBlock1(_) => {},
@@ -333,6 +755,12 @@ impl CoapRequest {
                             CoapOptionType::HopLimit,
                         ));
                     }
+                    if !HOP_LIMIT_RANGE.contains(value) {
+                        return Err(MessageConversionError::InvalidOptionValue(
+                            Some(CoapOptionType::HopLimit),
+                            OptionValueError::IllegalValue,
+                        ));
+                    }
                     hop_limit = Some(*value);
                 },
                 CoapOption::NoResponse(value) => {
@@ -362,11 +790,15 @@ impl CoapRequest {
                     }
                     observe = Some(*value);
                 },
-                // Handling of echo options is automatically done by libcoap (see man coap_send)
-                CoapOption::Echo(_) => {},
-                // Handling of request tag options is automatically done by libcoap (see man
-                // coap_send)
-                CoapOption::RTag(_) => {},
+                CoapOption::Echo(value) => {
+                    if echo.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Echo,
+                        ));
+                    }
+                    echo = Some(value.clone());
+                },
+                CoapOption::RTag(value) => request_tags.push(value.clone()),
                 // OSCORE is currently not supported, and even if it should probably be handled by
                 // libcoap, so I'm unsure whether we have to expose this.
                 CoapOption::Oscore(_v) => {},
@@ -386,10 +818,14 @@ impl CoapRequest {
                 CoapOptionType::ProxyUri,
             ));
         }
+        let query_components = query.clone().unwrap_or_default();
         let uri = if let Some(v) = proxy_uri {
             CoapUri::try_from_str_proxy(v.as_str())
         } else {
-            let path_str = path.map(construct_path_string);
+            // Segments are percent-encoded, so that segments containing `/` can be told apart from
+            // multiple segments (see CoapUri::path_segments()).
+            let path_str =
+                path.map(|path| construct_path_string(path.iter().map(|v| percent_encode(v, b":@")).collect()));
             let query_str = query.map(construct_query_string);
 
             match proxy_scheme {
@@ -414,6 +850,7 @@ impl CoapRequest {
         Ok(CoapRequest {
             pdu,
             uri,
+            query: query_components,
             accept,
             etag,
             if_match,
@@ -422,6 +859,18 @@ impl CoapRequest {
             hop_limit,
             no_response,
             observe,
+            block1,
+            block2,
+            size1,
+            size2,
+            echo,
+            request_tags,
+            session_id: Some(session.id()),
+            retransmission: false,
+            async_replay: false,
+            timeout: None,
+            bypass_cache: false,
+            default_type: false,
         })
     }
 
@@ -461,6 +910,24 @@ impl CoapRequest {
         if let Some(observe) = self.observe {
             self.pdu.add_option(CoapOption::Observe(observe));
         }
+        if let Some(block1) = self.block1 {
+            self.pdu.add_option(CoapOption::Block1(block1));
+        }
+        if let Some(block2) = self.block2 {
+            self.pdu.add_option(CoapOption::Block2(block2));
+        }
+        if let Some(size1) = self.size1 {
+            self.pdu.add_option(CoapOption::Size1(size1));
+        }
+        if let Some(size2) = self.size2 {
+            self.pdu.add_option(CoapOption::Size2(size2));
+        }
+        if let Some(echo) = self.echo {
+            self.pdu.add_option(CoapOption::Echo(echo));
+        }
+        for request_tag in self.request_tags {
+            self.pdu.add_option(CoapOption::RTag(request_tag));
+        }
         self.pdu
     }
 }
@@ -479,6 +946,16 @@ impl CoapMessageCommon for CoapRequest {
         }
     }
 
+    /// Sets the CoAP message type of this request.
+    ///
+    /// Requests built using a [CoapRequestBuilder] without an explicit message type use the default
+    /// message type of the session they are sent on, setting a message type using this function
+    /// overrides this default.
+    fn set_type_(&mut self, type_: CoapMessageType) {
+        self.pdu.set_type_(type_);
+        self.default_type = false;
+    }
+
     fn as_message(&self) -> &CoapMessage {
         &self.pdu
     }
@@ -487,3 +964,461 @@ impl CoapMessageCommon for CoapRequest {
         &mut self.pdu
     }
 }
+
+/// Builder for [CoapRequest]s.
+///
+/// In contrast to constructing a [CoapRequest] using [CoapRequest::new()] and its setters, the
+/// builder validates option lengths and option combinations when calling
+/// [CoapRequestBuilder::build()].
+/// Option values are encoded as required by [RFC 7252, Section 3.2](https://datatracker.ietf.org/doc/html/rfc7252#section-3.2)
+/// once the request is sent, repeatable options are sent in the order they were added.
+///
+/// # Examples
+/// ```
+/// use libcoap_rs::error::RequestBuildError;
+/// use libcoap_rs::message::{CoapMessageCommon, CoapRequestBuilder};
+/// use libcoap_rs::protocol::{CoapContentFormat, CoapMessageType, CoapRequestCode};
+///
+/// let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+///     .uri_path(["sensors", "temperature"])
+///     .uri_query([("unit", "celsius")])
+///     .content_format(CoapContentFormat::TextPlain)
+///     .payload("21.5".as_bytes().to_vec())
+///     .confirmable(false)
+///     .build()?;
+///
+/// assert_eq!(request.uri().path(), Some("sensors/temperature".as_bytes()));
+/// assert_eq!(request.uri().query(), Some("unit=celsius".as_bytes()));
+/// assert_eq!(request.type_(), CoapMessageType::Non);
+///
+/// # Result::<(), RequestBuildError>::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CoapRequestBuilder {
+    code: CoapRequestCode,
+    confirmable: Option<bool>,
+    path: Vec<String>,
+    query: Vec<String>,
+    proxy_uri: Option<String>,
+    accept: Option<ContentFormat>,
+    content_format: Option<ContentFormat>,
+    etag: Vec<ETag>,
+    if_match: Vec<CoapMatch>,
+    if_none_match: bool,
+    no_response: Option<NoResponse>,
+    observe: Option<Observe>,
+    hop_limit: Option<HopLimit>,
+    max_age: Option<MaxAge>,
+    size1: Option<Size>,
+    size2: Option<Size>,
+    payload: Option<Vec<u8>>,
+    token: Option<CoapToken>,
+    timeout: Option<Duration>,
+    bypass_cache: bool,
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    payload_error: Option<PayloadEncodeError>,
+}
+
+impl CoapRequestBuilder {
+    /// Creates a new builder for a request with the given request code.
+    ///
+    /// Unless set using [CoapRequestBuilder::confirmable()], the request is sent using the default
+    /// message type of the session it is sent on (see
+    /// [CoapSessionCommon::set_default_message_type()](crate::session::CoapSessionCommon::set_default_message_type)),
+    /// which is confirmable by default.
+    pub fn new(code: CoapRequestCode) -> CoapRequestBuilder {
+        CoapRequestBuilder {
+            code,
+            confirmable: None,
+            path: Vec::new(),
+            query: Vec::new(),
+            proxy_uri: None,
+            accept: None,
+            content_format: None,
+            etag: Vec::new(),
+            if_match: Vec::new(),
+            if_none_match: false,
+            no_response: None,
+            observe: None,
+            hop_limit: None,
+            max_age: None,
+            size1: None,
+            size2: None,
+            payload: None,
+            token: None,
+            timeout: None,
+            bypass_cache: false,
+            #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+            payload_error: None,
+        }
+    }
+
+    /// Sets whether the request should be sent as a confirmable or non-confirmable message,
+    /// overriding the default message type of the session.
+    ///
+    /// Non-confirmable messages are required for multicast requests, and are useful for requests
+    /// that do not need to be delivered reliably (e.g. periodic telemetry).
+    pub fn confirmable(mut self, confirmable: bool) -> Self {
+        self.confirmable = Some(confirmable);
+        self
+    }
+
+    /// Appends the given segments to the request path.
+    ///
+    /// Each segment is sent as a separate Uri-Path option, i.e. segments may contain slashes.
+    pub fn uri_path<I: IntoIterator<Item = S>, S: Into<String>>(mut self, segments: I) -> Self {
+        self.path.extend(segments.into_iter().map(Into::into));
+        self
+    }
+
+    /// Appends the given key-value pairs to the request query.
+    ///
+    /// Each pair is sent as a separate Uri-Query option of the form `key=value` (or just `key`
+    /// if the value is empty).
+    pub fn uri_query<I: IntoIterator<Item = (K, V)>, K: Into<String>, V: Into<String>>(mut self, pairs: I) -> Self {
+        self.query.extend(pairs.into_iter().map(|(key, value)| {
+            let (key, value) = (key.into(), value.into());
+            if value.is_empty() {
+                key
+            } else {
+                format!("{}={}", key, value)
+            }
+        }));
+        self
+    }
+
+    /// Appends the path segments and query components of the given URI to the request path and
+    /// query.
+    ///
+    /// As for Uri-Path and Uri-Query options created from URIs by libcoap, path segments and query
+    /// components are percent-decoded ([RFC 7252, Section 6.4](https://datatracker.ietf.org/doc/html/rfc7252#section-6.4)).
+    /// The scheme, host and port of absolute URIs are ignored, as they are determined by the
+    /// session the request is sent on. If `uri` is a proxy URI, it is set as the Proxy-Uri of this
+    /// request instead (see [CoapRequestBuilder::proxy_uri()]).
+    pub fn uri(mut self, uri: &CoapUri) -> Self {
+        if uri.is_proxy() {
+            self.proxy_uri = Some(uri.to_string());
+            return self;
+        }
+        self.path.extend(uri.path_segments().map(Cow::into_owned));
+        self.query.extend(
+            uri.query_components()
+                .map(|component| utf8_lossy(percent_decode(component)).into_owned()),
+        );
+        self
+    }
+
+    /// Appends a single key-value pair to the request query.
+    ///
+    /// In contrast to [CoapRequestBuilder::uri_query()], key and value are percent-encoded, so
+    /// that they may contain any character (including `=`, `&` and `%`) and are returned unchanged
+    /// by [CoapRequest::query()] on the receiving side.
+    /// The pair is sent as a Uri-Query option of the form `key=value`, or just `key` if `value`
+    /// is None (i.e., `Some("")` results in `key=`).
+    ///
+    /// As the Uri-Query option contains the encoded pair, [CoapRequestBuilder::build()] fails if
+    /// the encoded pair exceeds the maximum option length of 255 bytes.
+    pub fn query_pair(mut self, key: &str, value: Option<&str>) -> Self {
+        let key = percent_encode(key, b":@/?");
+        self.query.push(match value {
+            Some(value) => format!("{}={}", key, percent_encode(value, b":@/?")),
+            None => key,
+        });
+        self
+    }
+
+    /// Sets the Proxy-Uri of this request, i.e. the absolute URI that a forward proxy should
+    /// request.
+    ///
+    /// Cannot be combined with [CoapRequestBuilder::uri_path()] or
+    /// [CoapRequestBuilder::uri_query()].
+    pub fn proxy_uri<S: Into<String>>(mut self, proxy_uri: S) -> Self {
+        self.proxy_uri = Some(proxy_uri.into());
+        self
+    }
+
+    /// Sets the content format that the client wishes to receive.
+    ///
+    /// Content formats not known to this crate can be set using [CoapContentFormat::Other].
+    pub fn accept(mut self, accept: CoapContentFormat) -> Self {
+        self.accept = Some(accept.into());
+        self
+    }
+
+    /// Sets the content format of the request payload.
+    pub fn content_format(mut self, content_format: CoapContentFormat) -> Self {
+        self.content_format = Some(content_format.into());
+        self
+    }
+
+    /// Adds an entity tag to the request.
+    pub fn etag<T: Into<ETag>>(mut self, etag: T) -> Self {
+        self.etag.push(etag.into());
+        self
+    }
+
+    /// Adds a precondition that the target resource must match.
+    pub fn if_match(mut self, if_match: CoapMatch) -> Self {
+        self.if_match.push(if_match);
+        self
+    }
+
+    /// Makes the request conditional on the target resource not existing.
+    pub fn if_none_match(mut self) -> Self {
+        self.if_none_match = true;
+        self
+    }
+
+    /// Sets the classes of responses the client is not interested in (see
+    /// [CoapRequest::set_no_response()]).
+    ///
+    /// If all responses are suppressed ([CoapNoResponse::ALL]), the client will not wait for a
+    /// response to this request (see
+    /// [CoapRequestHandle::expects_response()](crate::session::CoapRequestHandle::expects_response)).
+    pub fn no_response(mut self, no_response: CoapNoResponse) -> Self {
+        self.no_response = Some(no_response.bits());
+        self
+    }
+
+    /// Sets the Observe option of this request (see [CoapRequest::set_observe()]).
+    pub fn observe(mut self, observe: Observe) -> Self {
+        self.observe = Some(observe);
+        self
+    }
+
+    /// Sets the Hop-Limit option of this request (see [CoapRequest::set_hop_limit()]).
+    pub fn hop_limit(mut self, hop_limit: HopLimit) -> Self {
+        self.hop_limit = Some(hop_limit);
+        self
+    }
+
+    /// Sets the Max-Age option of this request.
+    ///
+    /// Max-Age indicates how long a response may be cached and is only defined for responses
+    /// ([RFC 7252, Section 5.10.5](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.5)),
+    /// which is why [CoapRequest] has no Max-Age option and received requests containing one are
+    /// rejected. Setting it therefore always makes [CoapRequestBuilder::build()] fail with
+    /// [RequestBuildError::OptionNotAllowedInRequest], instead of silently dropping the option.
+    pub fn max_age(mut self, max_age: MaxAge) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets the Size1 option of this request (see [CoapRequest::set_size1()]).
+    ///
+    /// This is usually not necessary: if the payload of a request might not fit into a single
+    /// PDU, [CoapSessionCommon::send_request()] indicates the length of the payload using the
+    /// Size1 option automatically, so that servers can reject oversized uploads before all
+    /// blocks have been sent.
+    pub fn size1(mut self, size1: Size) -> Self {
+        self.size1 = Some(size1);
+        self
+    }
+
+    /// Sets the Size2 option of this request (see [CoapRequest::set_size2()]).
+    ///
+    /// A value of 0 asks the server to indicate the size of the response representation.
+    pub fn size2(mut self, size2: Size) -> Self {
+        self.size2 = Some(size2);
+        self
+    }
+
+    /// Sets the payload of this request to the JSON encoding of the given value and its
+    /// Content-Format to [CoapContentFormat::Json].
+    ///
+    /// If the value can not be serialized, [CoapRequestBuilder::build()] returns
+    /// [RequestBuildError::PayloadEncoding].
+    ///
+    /// This method is only available if the `serde-json` feature is enabled.
+    #[cfg(feature = "serde-json")]
+    pub fn json_payload<T: serde::Serialize + ?Sized>(self, value: &T) -> Self {
+        self.typed_payload(value, CoapContentFormat::Json)
+    }
+
+    /// Sets the payload of this request to the CBOR encoding of the given value and its
+    /// Content-Format to [CoapContentFormat::Cbor].
+    ///
+    /// If the value can not be serialized, [CoapRequestBuilder::build()] returns
+    /// [RequestBuildError::PayloadEncoding].
+    ///
+    /// This method is only available if the `serde-cbor` feature is enabled.
+    #[cfg(feature = "serde-cbor")]
+    pub fn cbor_payload<T: serde::Serialize + ?Sized>(self, value: &T) -> Self {
+        self.typed_payload(value, CoapContentFormat::Cbor)
+    }
+
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    fn typed_payload<T: serde::Serialize + ?Sized>(mut self, value: &T, format: CoapContentFormat) -> Self {
+        match typed_payload::encode(value, format) {
+            Ok(payload) => {
+                self.payload = Some(payload);
+                self.content_format = Some(format.into());
+                self.payload_error = None;
+            },
+            Err(e) => self.payload_error = Some(e),
+        }
+        self
+    }
+
+    /// Sets the payload of this request.
+    ///
+    /// Payloads that might not fit into a single PDU are sent using block-wise transfer, see
+    /// [CoapRequestBuilder::size1()].
+    pub fn payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Sets the token of this request.
+    ///
+    /// If no token is set, [CoapSessionCommon::send_request()] generates one using
+    /// [CoapSessionCommon::new_token()].
+    /// Tokens longer than 8 bytes require support for extended token lengths by both peers (see
+    /// [CoapMessageCommon::set_token()]).
+    pub fn token<T: Into<CoapToken>>(mut self, token: T) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Sets the time after which the client stops waiting for a response to this request (see
+    /// [CoapRequest::set_timeout()]).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the request bypass the response cache of the client session it is sent on (see
+    /// [CoapRequest::set_bypass_cache()]).
+    pub fn bypass_cache(mut self) -> Self {
+        self.bypass_cache = true;
+        self
+    }
+
+    /// Validates the provided options and constructs the resulting [CoapRequest].
+    ///
+    /// # Errors
+    /// Returns a [RequestBuildError] if
+    /// - an option value is too short or too long for its option type
+    /// - a Proxy-Uri is combined with a request path or query
+    /// - a Max-Age is set (see [CoapRequestBuilder::max_age()])
+    /// - a payload is set for a GET or DELETE request without setting a Content-Format
+    /// - the token is longer than the maximum token size supported by libcoap
+    /// - the request URI could not be constructed from its parts
+    /// - a typed payload could not be encoded.
+    pub fn build(self) -> Result<CoapRequest, RequestBuildError> {
+        #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+        if let Some(e) = self.payload_error {
+            return Err(e.into());
+        }
+        for segment in &self.path {
+            check_option_len(CoapOptionType::UriPath, segment.len())?;
+        }
+        for query in &self.query {
+            check_option_len(CoapOptionType::UriQuery, query.len())?;
+        }
+        for etag in &self.etag {
+            check_option_len(CoapOptionType::ETag, etag.len())?;
+        }
+        for if_match in &self.if_match {
+            if let CoapMatch::ETag(etag) = if_match {
+                check_option_len(CoapOptionType::IfMatch, etag.len())?;
+            }
+        }
+        if self.payload.is_some()
+            && self.content_format.is_none()
+            && matches!(self.code, CoapRequestCode::Get | CoapRequestCode::Delete)
+        {
+            return Err(RequestBuildError::PayloadWithoutContentFormat(self.code));
+        }
+        if let Some(token) = self.token.as_ref().filter(|v| v.len() > MAX_EXTENDED_TOKEN_SIZE) {
+            return Err(RequestBuildError::TokenTooLong(token.len()));
+        }
+        if self.max_age.is_some() {
+            return Err(RequestBuildError::OptionNotAllowedInRequest(CoapOptionType::MaxAge));
+        }
+        if self.hop_limit.is_some_and(|v| !HOP_LIMIT_RANGE.contains(&v)) {
+            return Err(RequestBuildError::IllegalOptionValue(CoapOptionType::HopLimit));
+        }
+
+        let uri = match self.proxy_uri {
+            Some(proxy_uri) => {
+                if !self.path.is_empty() {
+                    return Err(RequestBuildError::InvalidOptionCombination(
+                        CoapOptionType::ProxyUri,
+                        CoapOptionType::UriPath,
+                    ));
+                }
+                if !self.query.is_empty() {
+                    return Err(RequestBuildError::InvalidOptionCombination(
+                        CoapOptionType::ProxyUri,
+                        CoapOptionType::UriQuery,
+                    ));
+                }
+                check_option_len(CoapOptionType::ProxyUri, proxy_uri.len())?;
+                CoapUri::try_from_str_proxy(proxy_uri.as_str())?
+            },
+            None => {
+                let path_str = (!self.path.is_empty())
+                    .then(|| construct_path_string(self.path.iter().map(|v| percent_encode(v, b":@")).collect()));
+                let query_str = (!self.query.is_empty())
+                    .then(|| construct_query_string(self.query.iter().map(|v| percent_encode(v, b":@/?=")).collect()));
+                CoapUri::new_relative(
+                    path_str.as_ref().map(|v| v.as_bytes()),
+                    query_str.as_ref().map(|v| v.as_bytes()),
+                )?
+            },
+        };
+
+        let type_ = if self.confirmable.unwrap_or(true) {
+            CoapMessageType::Con
+        } else {
+            CoapMessageType::Non
+        };
+        let mut request =
+            CoapRequest::new(type_, self.code, uri).expect("Con and Non are valid message types for requests");
+        request.default_type = self.confirmable.is_none();
+        request.set_accept(self.accept);
+        request.set_content_format(self.content_format);
+        request.set_etag((!self.etag.is_empty()).then_some(self.etag));
+        request.set_if_match((!self.if_match.is_empty()).then_some(self.if_match));
+        request.set_if_none_match(self.if_none_match);
+        request.set_no_response(self.no_response);
+        request.set_observe(self.observe);
+        request.hop_limit = self.hop_limit;
+        request.set_size1(self.size1);
+        request.set_size2(self.size2);
+        request.set_data(self.payload);
+        request.set_token(self.token);
+        request.set_timeout(self.timeout);
+        request.set_bypass_cache(self.bypass_cache);
+        Ok(request)
+    }
+}
+
+/// Checks whether an option value of the given length is allowed for the given option type.
+fn check_option_len(option: CoapOptionType, len: usize) -> Result<(), RequestBuildError> {
+    if len < option.min_len() {
+        Err(RequestBuildError::OptionValueTooShort(option, len))
+    } else if len > option.max_len() {
+        Err(RequestBuildError::OptionValueTooLong(option, len))
+    } else {
+        Ok(())
+    }
+}
+
+/// Percent-encodes all characters of `value` that are neither unreserved characters nor
+/// sub-delimiters (see [RFC 3986, Section 2](https://datatracker.ietf.org/doc/html/rfc3986#section-2))
+/// nor contained in `allowed`.
+///
+/// The `&` sub-delimiter is always encoded, as it separates query components.
+pub(crate) fn percent_encode(value: &str, allowed: &[u8]) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$'()*+,;".contains(&byte) || allowed.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
@@ -7,14 +7,25 @@
  * See the README as well as the LICENSE file for more information.
  */
 
+use std::time::Duration;
+
 use crate::error::{MessageConversionError, MessageTypeError, OptionValueError};
-use crate::message::{CoapMessage, CoapMessageCommon, CoapOption, construct_path_string, construct_query_string};
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+use crate::error::{PayloadDecodeError, PayloadEncodeError};
+#[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+use crate::message::typed_payload;
+use crate::message::{
+    construct_path_string, construct_query_string, sorted_option_set, CoapMessage, CoapMessageCommon, CoapOption,
+    CoapOptionSet,
+};
 use crate::protocol::{
-    CoapMessageCode, CoapMessageType, CoapOptionType, CoapResponseCode, ContentFormat, Echo, ETag, MaxAge, Observe,
+    CoapContentFormat, CoapMessageCode, CoapMessageType, CoapOptionType, CoapResponseCode, ContentFormat, ETag, Echo,
+    MaxAge, Observe, Size, DEFAULT_MAX_AGE,
 };
+use crate::stats::CoapTransferStats;
 use crate::types::CoapUri;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct CoapResponse {
     pdu: CoapMessage,
     content_format: Option<ContentFormat>,
@@ -23,6 +34,9 @@ pub struct CoapResponse {
     echo: Option<Echo>,
     location: Option<CoapUri>,
     observe: Option<Observe>,
+    size1: Option<Size>,
+    size2: Option<Size>,
+    transfer_stats: Option<CoapTransferStats>,
 }
 
 impl CoapResponse {
@@ -43,9 +57,181 @@ impl CoapResponse {
             echo: None,
             location: None,
             observe: None,
+            size1: None,
+            size2: None,
+            transfer_stats: None,
         })
     }
 
+    /// Creates a new piggybacked (i.e., acknowledgement) response with the given code and
+    /// payload.
+    ///
+    /// The created response has neither a message ID nor a token, use
+    /// [CoapResponse::in_reply_to()] to send it in response to a request (e.g., from within a
+    /// request handler).
+    fn with_payload(code: CoapResponseCode, payload: Option<Vec<u8>>) -> CoapResponse {
+        let mut response =
+            CoapResponse::new(CoapMessageType::Ack, code).expect("Ack is a valid message type for responses");
+        response.set_data(payload);
+        response
+    }
+
+    /// Creates a new error response with the given code and diagnostic payload.
+    ///
+    /// The diagnostic payload is a human-readable description of the error, which is sent without
+    /// a Content-Format option as described in [RFC 7252, Section 5.5.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.5.2).
+    /// An empty diagnostic results in a response without payload.
+    ///
+    /// Just like for [CoapResponse::content()], the created response has to be sent using
+    /// [CoapResponse::in_reply_to()].
+    pub fn error<D: Into<String>>(code: CoapResponseCode, diagnostic: D) -> CoapResponse {
+        let diagnostic = diagnostic.into();
+        CoapResponse::with_payload(code, (!diagnostic.is_empty()).then(|| diagnostic.into_bytes()))
+    }
+
+    /// Creates a new 2.05 (Content) response with the given content format and payload.
+    ///
+    /// The created response has neither a message ID nor a token, use
+    /// [CoapResponse::in_reply_to()] to send it in response to a request (e.g., from within a
+    /// request handler).
+    pub fn content<P: Into<Vec<u8>>>(content_format: CoapContentFormat, payload: P) -> CoapResponse {
+        let mut response = CoapResponse::with_payload(CoapResponseCode::Content, Some(payload.into()));
+        response.set_content_format(Some(content_format.into()));
+        response
+    }
+
+    /// Creates a new 2.04 (Changed) response without payload, see [CoapResponse::content()].
+    pub fn changed() -> CoapResponse {
+        CoapResponse::with_payload(CoapResponseCode::Changed, None)
+    }
+
+    /// Creates a new 2.01 (Created) response without payload, see [CoapResponse::content()].
+    pub fn created() -> CoapResponse {
+        CoapResponse::with_payload(CoapResponseCode::Created, None)
+    }
+
+    /// Creates a new 2.02 (Deleted) response without payload, see [CoapResponse::content()].
+    pub fn deleted() -> CoapResponse {
+        CoapResponse::with_payload(CoapResponseCode::Deleted, None)
+    }
+
+    /// Creates a new 4.00 (Bad Request) response with the given diagnostic payload, see
+    /// [CoapResponse::error()].
+    pub fn bad_request<D: Into<String>>(diagnostic: D) -> CoapResponse {
+        CoapResponse::error(CoapResponseCode::BadRequest, diagnostic)
+    }
+
+    /// Creates a new 4.04 (Not Found) response without payload, see [CoapResponse::error()].
+    pub fn not_found() -> CoapResponse {
+        CoapResponse::error(CoapResponseCode::NotFound, "")
+    }
+
+    /// Creates a new 4.05 (Method Not Allowed) response without payload, see
+    /// [CoapResponse::error()].
+    pub fn method_not_allowed() -> CoapResponse {
+        CoapResponse::error(CoapResponseCode::NotAllowed, "")
+    }
+
+    /// Creates a new 5.00 (Internal Server Error) response with the given diagnostic payload, see
+    /// [CoapResponse::error()].
+    pub fn internal_error<D: Into<String>>(diagnostic: D) -> CoapResponse {
+        CoapResponse::error(CoapResponseCode::InternalError, diagnostic)
+    }
+
+    /// Turns this response into a reply to the request that `prepared` was created for.
+    ///
+    /// `prepared` should be the response provided to a request handler, whose message type,
+    /// message ID and token are copied into this response. Code, options and payload of this
+    /// response are kept.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use libcoap_rs::message::{CoapRequest, CoapResponse};
+    /// use libcoap_rs::session::{CoapServerSession, CoapSessionCommon};
+    ///
+    /// fn handle_get(_: &mut (), session: &mut CoapServerSession, request: &CoapRequest, prepared: CoapResponse) {
+    ///     let response = match request.query_value("name") {
+    ///         Some(name) => CoapResponse::content(
+    ///             libcoap_rs::protocol::CoapContentFormat::TextPlain,
+    ///             format!("Hello, {}!", name),
+    ///         ),
+    ///         None => CoapResponse::bad_request("missing query parameter: name"),
+    ///     };
+    ///     session.send(response.in_reply_to(&prepared)).unwrap();
+    /// }
+    /// ```
+    pub fn in_reply_to(mut self, prepared: &CoapResponse) -> CoapResponse {
+        self.pdu.set_type_(prepared.type_());
+        self.pdu.set_mid(prepared.mid());
+        self.pdu.set_token(prepared.token().map(Box::<[u8]>::from));
+        self
+    }
+
+    /// Turns this response into a separate response (see
+    /// [RFC 7252, Section 5.2.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.2.2)),
+    /// sent as a confirmable message if `confirmable` is true or as a non-confirmable one
+    /// otherwise.
+    ///
+    /// By default, the response provided to a request handler for a confirmable request is
+    /// piggybacked onto the ACK of the request. If the message type is changed to CON or NON (using
+    /// this function or [CoapMessageCommon::set_type_()]), sending the response using
+    /// [CoapSessionCommon::send()](crate::session::CoapSessionCommon::send) assigns it a new
+    /// message ID, and libcoap acknowledges the request with an empty ACK once the handler returns.
+    /// Responses to non-confirmable requests can not be sent as ACKs. Responses sent after the
+    /// handler has returned (e.g., deferred responses) are always separate responses.
+    pub fn set_separate(&mut self, confirmable: bool) {
+        self.pdu.set_type_(match confirmable {
+            true => CoapMessageType::Con,
+            false => CoapMessageType::Non,
+        });
+    }
+
+    /// Returns whether this response was (or will be) piggybacked onto the ACK of its request,
+    /// i.e., whether its message type is ACK.
+    pub fn is_piggybacked(&self) -> bool {
+        self.pdu.type_() == CoapMessageType::Ack
+    }
+
+    /// Returns the response code of this response.
+    ///
+    /// Returns None if this response does not have a response code (yet), e.g. for the
+    /// responses provided to request handlers before their code has been set.
+    pub fn response_code(&self) -> Option<CoapResponseCode> {
+        match self.pdu.code() {
+            CoapMessageCode::Response(code) => Some(code),
+            CoapMessageCode::Empty | CoapMessageCode::Request(_) => None,
+        }
+    }
+
+    /// Returns whether this response indicates success (2.xx).
+    pub fn is_success(&self) -> bool {
+        self.response_code().is_some_and(CoapResponseCode::is_success)
+    }
+
+    /// Returns whether this response indicates a client error (4.xx).
+    pub fn is_client_error(&self) -> bool {
+        self.response_code().is_some_and(CoapResponseCode::is_client_error)
+    }
+
+    /// Returns whether this response indicates a server error (5.xx).
+    pub fn is_server_error(&self) -> bool {
+        self.response_code().is_some_and(CoapResponseCode::is_server_error)
+    }
+
+    /// Returns the diagnostic payload of this response.
+    ///
+    /// As described in [RFC 7252, Section 5.5.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.5.2),
+    /// the payload of an error response (4.xx or 5.xx) without a Content-Format option is a
+    /// human-readable diagnostic message encoded as UTF-8.
+    /// Returns None for other responses, responses without payload, or payloads that are not
+    /// valid UTF-8.
+    pub fn diagnostic(&self) -> Option<&str> {
+        if !self.response_code().is_some_and(CoapResponseCode::is_error) || self.content_format.is_some() {
+            return None;
+        }
+        self.pdu.data().and_then(|data| std::str::from_utf8(data).ok())
+    }
+
     /// Returns the "Max-Age" option value for this response.
     pub fn max_age(&self) -> Option<MaxAge> {
         self.max_age
@@ -61,6 +247,27 @@ impl CoapResponse {
         self.max_age = max_age
     }
 
+    /// Sets the "Max-Age" option value for this response to the given duration (see
+    /// [CoapResponse::set_max_age()]) and returns the response.
+    ///
+    /// As the option value is specified in seconds, fractions of seconds are discarded (i.e., a
+    /// duration of less than one second results in a Max-Age of zero, which indicates that the
+    /// response must not be reused without revalidation). Durations exceeding the maximum option
+    /// value (`u32::MAX` seconds, roughly 136 years) are capped to it.
+    pub fn with_max_age(mut self, max_age: Duration) -> CoapResponse {
+        self.max_age = Some(MaxAge::try_from(max_age.as_secs()).unwrap_or(MaxAge::MAX));
+        self
+    }
+
+    /// Returns the time span for which this response is fresh, i.e., may be reused by a cache
+    /// without revalidation.
+    ///
+    /// This is the value of the "Max-Age" option, or 60 seconds ([DEFAULT_MAX_AGE]) for responses
+    /// without this option.
+    pub fn freshness_lifetime(&self) -> Duration {
+        Duration::from_secs(self.max_age.unwrap_or(DEFAULT_MAX_AGE).into())
+    }
+
     /// Returns the "Content-Format" option value for this request.
     pub fn content_format(&self) -> Option<ContentFormat> {
         self.content_format
@@ -76,6 +283,40 @@ impl CoapResponse {
         self.content_format = content_format;
     }
 
+    /// Returns the "Content-Format" option value for this response as a [CoapContentFormat].
+    pub fn payload_format(&self) -> Option<CoapContentFormat> {
+        self.content_format.map(CoapContentFormat::from)
+    }
+
+    /// Decodes the payload of this response into a value of type `T` according to its
+    /// Content-Format (see [CoapRequest::parse_payload()](crate::message::CoapRequest::parse_payload)).
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    pub fn parse_payload<T: serde::de::DeserializeOwned>(&self) -> Result<T, PayloadDecodeError> {
+        typed_payload::decode(self.payload_format(), self.payload())
+    }
+
+    /// Sets the payload of this response to the encoding of the given value in the given content
+    /// format and sets the Content-Format option accordingly.
+    ///
+    /// JSON ([CoapContentFormat::Json]) is supported if the `serde-json` feature is enabled, CBOR
+    /// ([CoapContentFormat::Cbor]) if the `serde-cbor` feature is enabled.
+    ///
+    /// # Errors
+    /// Returns [PayloadEncodeError::UnsupportedContentFormat] for all other content formats and
+    /// [PayloadEncodeError::Serialization] if the value can not be serialized. In both cases, the
+    /// response is left unchanged.
+    #[cfg(any(feature = "serde-json", feature = "serde-cbor"))]
+    pub fn set_typed_payload<T: serde::Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+        content_format: CoapContentFormat,
+    ) -> Result<(), PayloadEncodeError> {
+        let payload = typed_payload::encode(value, content_format)?;
+        self.set_data(Some(payload));
+        self.set_content_format(Some(content_format.into()));
+        Ok(())
+    }
+
     /// Returns the "ETag" option value for this request.
     pub fn etag(&self) -> Option<&ETag> {
         self.etag.as_ref()
@@ -106,8 +347,9 @@ impl CoapResponse {
     ///
     /// The client should include the provided option value in its next request.
     ///
-    /// As handling echo options on the client side is done automatically by libcoap, this option
-    /// is not accessible in [CoapRequest], see `man coap_send` for more information.
+    /// Handling echo options on the client side is done automatically by libcoap (see
+    /// `man coap_send`), servers can challenge clients automatically using
+    /// [CoapResource::set_echo_policy()](crate::CoapResource::set_echo_policy).
     ///
     /// See [RFC 9175, Section 2.2](https://datatracker.ietf.org/doc/html/rfc9175#section-2.2)
     /// for more information.
@@ -115,6 +357,48 @@ impl CoapResponse {
         self.echo = echo
     }
 
+    /// Returns the "Size1" option value for this response.
+    ///
+    /// In 4.13 (Request Entity Too Large) responses, this option indicates the maximum request
+    /// body size accepted by the server
+    /// ([RFC 7959, Section 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4)).
+    pub fn size1(&self) -> Option<Size> {
+        self.size1
+    }
+
+    /// Sets the "Size1" option value for this response.
+    pub fn set_size1(&mut self, size1: Option<Size>) {
+        self.size1 = size1;
+    }
+
+    /// Returns the "Size2" option value for this response.
+    ///
+    /// This option indicates the size of the full representation of a block-wise transfer
+    /// ([RFC 7959, Section 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4)).
+    pub fn size2(&self) -> Option<Size> {
+        self.size2
+    }
+
+    /// Sets the "Size2" option value for this response.
+    pub fn set_size2(&mut self, size2: Option<Size>) {
+        self.size2 = size2;
+    }
+
+    /// Returns metadata on how this response was received (e.g., whether its body was transferred
+    /// block-wise, see [CoapTransferStats]).
+    ///
+    /// Returns None for responses that were not received using a session (e.g., responses created
+    /// by the application or served from the response cache of a session without contacting the
+    /// server).
+    pub fn transfer_stats(&self) -> Option<CoapTransferStats> {
+        self.transfer_stats
+    }
+
+    /// Sets the metadata on how this response was received, see [CoapResponse::transfer_stats()].
+    pub(crate) fn set_transfer_stats(&mut self, transfer_stats: Option<CoapTransferStats>) {
+        self.transfer_stats = transfer_stats;
+    }
+
     /// Returns the "Observe" option value for this request.
     pub fn observe(&self) -> Option<Observe> {
         self.observe
@@ -173,13 +457,30 @@ impl CoapResponse {
         if let Some(observe) = self.observe {
             self.pdu.add_option(CoapOption::Observe(observe));
         }
+        if let Some(size1) = self.size1 {
+            self.pdu.add_option(CoapOption::Size1(size1));
+        }
+        if let Some(size2) = self.size2 {
+            self.pdu.add_option(CoapOption::Size2(size2));
+        }
         self.pdu
     }
 
+    /// Returns all options of this response, ordered by their option number.
+    ///
+    /// In contrast to [CoapMessageCommon::options_iter()], which only returns the options that
+    /// are not represented by one of the typed fields of this response, the returned set contains
+    /// all options, including unknown ones (see [CoapOption::from_raw_parts()]).
+    /// Use [CoapOptionSet::iter_raw()] to obtain the raw option numbers and values.
+    pub fn options(&self) -> CoapOptionSet {
+        let echo = self.echo.clone().map(CoapOption::Echo);
+        sorted_option_set(self.clone().into_message().options.into_iter().chain(echo))
+    }
+
     /// Parses the given [CoapMessage] into a CoapResponse.
     ///
     /// Returns a [MessageConversionError] if the provided PDU cannot be parsed into a response.
-    pub fn from_message(pdu: CoapMessage) -> Result<CoapResponse, MessageConversionError> {
+    pub fn from_message(mut pdu: CoapMessage) -> Result<CoapResponse, MessageConversionError> {
         let mut location_path = None;
         let mut location_query = None;
         let mut max_age = None;
@@ -187,6 +488,8 @@ impl CoapResponse {
         let mut echo = None;
         let mut observe = None;
         let mut content_format = None;
+        let mut size1 = None;
+        let mut size2 = None;
         let mut additional_opts = Vec::new();
         for option in pdu.options_iter() {
             match option {
@@ -279,17 +582,25 @@ impl CoapResponse {
                         CoapOptionType::Accept,
                     ));
                 },
-                CoapOption::Size1(_) => {
-                    return Err(MessageConversionError::InvalidOptionForMessageType(
-                        CoapOptionType::Size1,
-                    ));
+                CoapOption::Size1(value) => {
+                    if size1.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Size1,
+                        ));
+                    }
+                    size1 = Some(*value)
                 },
-                CoapOption::Size2(_) => {},
-                CoapOption::Block1(_) => {
-                    return Err(MessageConversionError::InvalidOptionForMessageType(
-                        CoapOptionType::Block1,
-                    ));
+                CoapOption::Size2(value) => {
+                    if size2.is_some() {
+                        return Err(MessageConversionError::NonRepeatableOptionRepeated(
+                            CoapOptionType::Size2,
+                        ));
+                    }
+                    size2 = Some(*value)
                 },
+                // Block1 options in responses acknowledge blocks of a block-wise upload, which
+                // libcoap handles for us.
+                CoapOption::Block1(_) => {},
                 CoapOption::Block2(_) => {},
                 CoapOption::QBlock1(_) => {},
                 CoapOption::QBlock2(_) => {},
@@ -322,6 +633,10 @@ impl CoapResponse {
                 CoapOption::Other(n, v) => additional_opts.push(CoapOption::Other(*n, v.clone())),
             }
         }
+        pdu.clear_options();
+        for opt in additional_opts {
+            pdu.add_option(opt);
+        }
         let location = if location_path.is_some() || location_query.is_some() {
             let path_str = location_path.map(construct_path_string);
             let query_str = location_query.map(construct_query_string);
@@ -340,10 +655,31 @@ impl CoapResponse {
             echo,
             location,
             observe,
+            size1,
+            size2,
+            transfer_stats: None,
         })
     }
 }
 
+// The transfer metadata describes how a response was received rather than the response itself,
+// so it is not compared.
+impl PartialEq for CoapResponse {
+    fn eq(&self, other: &Self) -> bool {
+        self.pdu == other.pdu
+            && self.content_format == other.content_format
+            && self.max_age == other.max_age
+            && self.etag == other.etag
+            && self.echo == other.echo
+            && self.location == other.location
+            && self.observe == other.observe
+            && self.size1 == other.size1
+            && self.size2 == other.size2
+    }
+}
+
+impl Eq for CoapResponse {}
+
 impl CoapMessageCommon for CoapResponse {
     /// Sets the message code of this response.
     ///
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/typed_payload.rs - Encoding and decoding of payloads using serde.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Conversion between payloads and typed values for the content formats supported by the enabled
+//! serde features (`serde-json` for [CoapContentFormat::Json], `serde-cbor` for
+//! [CoapContentFormat::Cbor]).
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::{PayloadDecodeError, PayloadEncodeError},
+    protocol::CoapContentFormat,
+};
+
+/// Decodes a payload with the given content format into a value of type `T`.
+pub(crate) fn decode<T: DeserializeOwned>(
+    format: Option<CoapContentFormat>,
+    data: &[u8],
+) -> Result<T, PayloadDecodeError> {
+    match format {
+        #[cfg(feature = "serde-json")]
+        Some(format @ CoapContentFormat::Json) => {
+            serde_json::from_slice(data).map_err(|e| PayloadDecodeError::InvalidPayload(format, e.to_string()))
+        },
+        #[cfg(feature = "serde-cbor")]
+        Some(format @ CoapContentFormat::Cbor) => {
+            ciborium::from_reader(data).map_err(|e| PayloadDecodeError::InvalidPayload(format, e.to_string()))
+        },
+        format => Err(PayloadDecodeError::UnsupportedContentFormat(format)),
+    }
+}
+
+/// Encodes the given value into a payload with the given content format.
+pub(crate) fn encode<T: Serialize + ?Sized>(
+    value: &T,
+    format: CoapContentFormat,
+) -> Result<Vec<u8>, PayloadEncodeError> {
+    match format {
+        #[cfg(feature = "serde-json")]
+        CoapContentFormat::Json => {
+            serde_json::to_vec(value).map_err(|e| PayloadEncodeError::Serialization(format, e.to_string()))
+        },
+        #[cfg(feature = "serde-cbor")]
+        CoapContentFormat::Cbor => {
+            let mut payload = Vec::new();
+            ciborium::into_writer(value, &mut payload)
+                .map_err(|e| PayloadEncodeError::Serialization(format, e.to_string()))?;
+            Ok(payload)
+        },
+        format => Err(PayloadEncodeError::UnsupportedContentFormat(format)),
+    }
+}
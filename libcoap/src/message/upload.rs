@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message/upload.rs - Helpers for receiving block-wise uploads block by block.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Helpers for processing large request bodies block by block.
+//!
+//! By default, libcoap reassembles request bodies that are uploaded block-wise (using the Block1
+//! option of [RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959)) and only calls the
+//! request handler once the whole body has been received.
+//! Resources created with [ResourceFlags::STREAM_BLOCK1](crate::ResourceFlags::STREAM_BLOCK1)
+//! instead receive each block as a separate request, which allows handlers to process uploads
+//! (e.g., firmware images) as they arrive and to reject them early.
+//! [CoapBlock1Chunk] provides access to the block-wise transfer state of such a request and
+//! generates the appropriate responses.
+//!
+//! # Example
+//! ```no_run
+//! use libcoap_rs::{
+//!     message::{upload::CoapBlock1Chunk, CoapRequest, CoapResponse},
+//!     protocol::{CoapRequestCode, CoapResponseCode},
+//!     session::{CoapServerSession, CoapSessionCommon},
+//!     CoapRequestHandler, CoapResource, ResourceFlags,
+//! };
+//!
+//! const MAX_IMAGE_SIZE: usize = 64 * 1024;
+//!
+//! let resource = CoapResource::new_with_flags(
+//!     "firmware",
+//!     Vec::<u8>::new(),
+//!     ResourceFlags::NOTIFY_NON | ResourceFlags::STREAM_BLOCK1,
+//! )
+//! .unwrap();
+//! resource.set_method_handler(
+//!     CoapRequestCode::Put,
+//!     Some(CoapRequestHandler::new(
+//!         |image: &mut Vec<u8>, session: &mut CoapServerSession, request: &CoapRequest, mut response: CoapResponse| {
+//!             let chunk = CoapBlock1Chunk::from_request(request);
+//!             let too_large = chunk.total_size().unwrap_or(0) > MAX_IMAGE_SIZE
+//!                 || chunk.offset() + chunk.data().len() > MAX_IMAGE_SIZE;
+//!             if too_large {
+//!                 image.clear();
+//!                 chunk.abort_too_large(&mut response, MAX_IMAGE_SIZE);
+//!             } else {
+//!                 if chunk.offset() == 0 {
+//!                     image.clear();
+//!                 }
+//!                 image.extend_from_slice(chunk.data());
+//!                 response.set_code(CoapResponseCode::Changed);
+//!                 chunk.acknowledge(&mut response);
+//!             }
+//!             session.send(response).unwrap();
+//!         },
+//!     )),
+//! );
+//! ```
+
+use crate::{
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon, CoapOption},
+    protocol::{Block, CoapResponseCode, Size},
+};
+
+/// Block size exponent (SZX) for the largest block size allowed by RFC 7959 (1024 bytes).
+const MAX_SZX: u8 = 6;
+
+/// A single block of a request body that is uploaded block-wise.
+///
+/// Requests without a Block1 option (i.e., bodies that fit into a single message) are treated as
+/// a transfer consisting of only one (final) block, so handlers can process both kinds of
+/// requests in the same way.
+///
+/// Note that libcoap still checks that blocks arrive in order before passing them to the request
+/// handler, but it does not keep track of data that was already received. Handlers are therefore
+/// responsible for storing the blocks (e.g., by writing them to flash) and can identify the start
+/// of a new transfer by its offset of 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoapBlock1Chunk<'a> {
+    block: Option<Block>,
+    total_size: Option<Size>,
+    data: &'a [u8],
+}
+
+impl<'a> CoapBlock1Chunk<'a> {
+    /// Returns the block of the upload that is contained in the given request.
+    pub fn from_request(request: &'a CoapRequest) -> CoapBlock1Chunk<'a> {
+        CoapBlock1Chunk {
+            block: request.block1(),
+            total_size: request.size1(),
+            data: request.data().unwrap_or_default(),
+        }
+    }
+
+    /// Returns whether the request is part of a block-wise transfer (i.e., contains a Block1
+    /// option).
+    pub fn is_blockwise(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Returns the number of this block (starting at 0).
+    pub fn num(&self) -> u32 {
+        self.block.map_or(0, |block| block >> 4)
+    }
+
+    /// Returns whether the client indicated that more blocks follow this one.
+    pub fn more(&self) -> bool {
+        self.block.is_some_and(|block| block & 0x8 != 0)
+    }
+
+    /// Returns the block size used by the client (in bytes).
+    ///
+    /// For requests that are not part of a block-wise transfer, this is the length of the request
+    /// body.
+    pub fn block_size(&self) -> usize {
+        match self.block {
+            Some(_) => block_size(self.szx()),
+            None => self.data.len(),
+        }
+    }
+
+    /// Returns the offset of this block within the request body (in bytes).
+    pub fn offset(&self) -> usize {
+        self.num() as usize * self.block_size()
+    }
+
+    /// Returns the size of the whole request body as indicated by the client using the Size1
+    /// option, if present.
+    ///
+    /// Clients usually only include this option in the first block of a transfer. For requests
+    /// that are not part of a block-wise transfer, the length of the request body is returned.
+    pub fn total_size(&self) -> Option<usize> {
+        match self.block {
+            Some(_) => self.total_size.map(|size| size as usize),
+            None => Some(self.data.len()),
+        }
+    }
+
+    /// Returns the data contained in this block.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Prepares the given response to acknowledge this block.
+    ///
+    /// For blocks that are followed by more blocks, the response code is set to 2.31 (Continue).
+    /// For the last block, the response code set by the request handler (e.g., 2.04 (Changed)) is
+    /// kept, as this response concludes the transfer.
+    /// In both cases, the Block1 option of the request is echoed as required by RFC 7959.
+    ///
+    /// The response code and Block1 option are set explicitly instead of relying on libcoap's
+    /// automatic generation of 2.31 (Continue) responses, so that the response does not depend on
+    /// the libcoap version in use.
+    pub fn acknowledge(&self, response: &mut CoapResponse) {
+        let Some(block) = self.block else {
+            return;
+        };
+        if self.more() {
+            response.set_code(CoapResponseCode::Continue);
+        }
+        response.add_option(CoapOption::Block1(block));
+    }
+
+    /// Prepares the given response to abort the transfer because the request body is too large.
+    ///
+    /// The response code is set to 4.13 (Request Entity Too Large), and the Size1 option of the
+    /// response indicates the maximum body size (in bytes) accepted by the server, as recommended in
+    /// [RFC 7959, Section 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4).
+    /// As the response does not contain a Block1 option, it ends the transfer and the client does
+    /// not send the remaining blocks.
+    pub fn abort_too_large(&self, response: &mut CoapResponse, max_size: usize) {
+        response.set_code(CoapResponseCode::RequestTooLarge);
+        response.set_size1(Some(Size::try_from(max_size).unwrap_or(Size::MAX)));
+        response.set_data(None::<Vec<u8>>);
+    }
+
+    /// Returns the block size exponent of this block.
+    fn szx(&self) -> u8 {
+        self.block.map_or(0, |block| (block & 0x7) as u8)
+    }
+}
+
+/// Returns the block size for the given block size exponent.
+///
+/// SZX 7 is reserved (and used for BERT on reliable transports), we treat it like the largest
+/// regular block size.
+fn block_size(szx: u8) -> usize {
+    1 << (szx.min(MAX_SZX) + 4)
+}
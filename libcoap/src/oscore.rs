@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * oscore.rs - Types for Object Security for Constrained RESTful Environments (OSCORE).
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for Object Security for Constrained RESTful Environments (OSCORE,
+//! [RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613)).
+//!
+//! OSCORE protects requests and responses on the application layer and may be combined with
+//! (D)TLS for defense in depth (see
+//! [RFC 8613, Section 1](https://datatracker.ietf.org/doc/html/rfc8613#section-1)), e.g. using
+//! [CoapClientSession::connect_oscore_psk()](crate::session::CoapClientSession::connect_oscore_psk).
+//!
+//! See the [libcoap documentation](https://libcoap.net/doc/reference/4.3.5/group__oscore.html)
+//! for more information.
+//!
+//! Group OSCORE ([draft-ietf-core-oscore-groupcomm](https://datatracker.ietf.org/doc/draft-ietf-core-oscore-groupcomm/))
+//! configurations can be described using [OscoreGroupConfBuilder], but can only be created if
+//! the libcoap build in use supports group mode (see [OscoreConf::group_mode_supported()]).
+
+use std::{
+    ffi::{c_int, c_void},
+    fmt::{Debug, Formatter},
+    ptr::NonNull,
+};
+
+use libcoap_sys::{coap_delete_oscore_conf, coap_new_oscore_conf, coap_oscore_conf_t, coap_str_const_t};
+
+use crate::{crypto::tls_backend, error::OscoreConfError, unwind::catch_callback_panic, CoapContext};
+
+/// Function type of sequence number save callbacks, see [OscoreConf::from_config_bytes()].
+///
+/// The callback is called with the sender sequence number that should be persisted and returns
+/// whether it was saved successfully.
+pub type OscoreSeqNumSaver = dyn FnMut(u64) -> bool;
+
+/// An OSCORE configuration (i.e., a security context and its parameters).
+///
+/// libcoap takes ownership of the configuration when a session is created using it, so
+/// functions using a configuration take it by value (and a configuration can not be cloned),
+/// which ensures that a configuration is not used for more than one session.
+pub struct OscoreConf {
+    raw_conf: NonNull<coap_oscore_conf_t>,
+    storage: OscoreConfStorage,
+}
+
+/// Values that have to be kept alive for as long as libcoap may use an OSCORE configuration.
+///
+/// Once the configuration is passed to libcoap, the storage is moved into the context the
+/// configuration is used with (see [CoapContext::retain_oscore_storage()]).
+pub(crate) struct OscoreConfStorage {
+    /// The textual configuration the raw configuration was parsed from.
+    config: Box<[u8]>,
+    /// Sequence number save callback (boxed twice, as the address of the inner box is provided to
+    /// libcoap).
+    save_seq_num: Option<Box<Box<OscoreSeqNumSaver>>>,
+}
+
+impl OscoreConf {
+    /// Parses the given OSCORE configuration, using `start_seq_num` as the initial sender
+    /// sequence number.
+    ///
+    /// Equivalent to calling [OscoreConf::from_config_str()] without a sequence number save
+    /// callback.
+    ///
+    /// # Errors
+    /// See [OscoreConf::from_config_bytes()].
+    pub fn new(config: &str, start_seq_num: u64) -> Result<OscoreConf, OscoreConfError> {
+        OscoreConf::from_config_str(config, start_seq_num, None)
+    }
+
+    /// Parses the given OSCORE configuration, see [OscoreConf::from_config_bytes()].
+    pub fn from_config_str(
+        config: &str,
+        start_seq_num: u64,
+        save_seq_num: Option<Box<OscoreSeqNumSaver>>,
+    ) -> Result<OscoreConf, OscoreConfError> {
+        OscoreConf::from_config_bytes(config.as_bytes(), start_seq_num, save_seq_num)
+    }
+
+    /// Parses the given OSCORE configuration, using `start_seq_num` as the initial sender
+    /// sequence number.
+    ///
+    /// The configuration uses the textual format described in the
+    /// [libcoap documentation](https://libcoap.net/doc/reference/4.3.5/man_coap-oscore-conf.html),
+    /// i.e., one `keyword,type,value` entry per line (e.g.,
+    /// `master_secret,hex,"0102030405060708090a0b0c0d0e0f10"` and `sender_id,ascii,"client"`),
+    /// empty lines and lines starting with `#` are ignored.
+    ///
+    /// As sequence numbers must never be reused with the same security context, applications
+    /// that restart should persist the sequence number and provide it here. If `save_seq_num` is
+    /// set, libcoap calls it whenever the sequence number should be persisted (see the
+    /// `ssn_freq` configuration keyword).
+    ///
+    /// # Errors
+    /// Returns [OscoreConfError::MalformedLine], [OscoreConfError::UnknownType] or
+    /// [OscoreConfError::InvalidValue] (including the line number and the affected field) if an
+    /// entry of the configuration could not be parsed, [OscoreConfError::FeatureUnavailable] if
+    /// libcoap was built without OSCORE support, or [OscoreConfError::Invalid] if libcoap rejected
+    /// the configuration for a different reason (e.g., because of an unknown or missing field).
+    pub fn from_config_bytes(
+        config: &[u8],
+        start_seq_num: u64,
+        save_seq_num: Option<Box<OscoreSeqNumSaver>>,
+    ) -> Result<OscoreConf, OscoreConfError> {
+        validate_config(config)?;
+        if !tls_backend().oscore_supported() {
+            return Err(OscoreConfError::FeatureUnavailable("OSCORE"));
+        }
+        let storage = OscoreConfStorage {
+            config: config.into(),
+            save_seq_num: save_seq_num.map(Box::new),
+        };
+        let raw_config = coap_str_const_t {
+            length: storage.config.len(),
+            s: storage.config.as_ptr(),
+        };
+        let (save_func, save_param) = match &storage.save_seq_num {
+            Some(save_seq_num) => (
+                Some(save_seq_num_callback as unsafe extern "C" fn(u64, *mut c_void) -> c_int),
+                save_seq_num.as_ref() as *const Box<OscoreSeqNumSaver> as *mut c_void,
+            ),
+            None => (None, std::ptr::null_mut()),
+        };
+        // SAFETY: The configuration string and the save callback are owned by the storage, which
+        // is kept alive for as long as libcoap may use the configuration.
+        let raw_conf = unsafe { coap_new_oscore_conf(raw_config, save_func, save_param, start_seq_num) };
+        NonNull::new(raw_conf)
+            .map(|raw_conf| OscoreConf { raw_conf, storage })
+            .ok_or(OscoreConfError::Invalid)
+    }
+
+    /// Returns whether the libcoap build in use supports Group OSCORE, i.e., whether
+    /// [OscoreGroupConfBuilder::build()] is able to create group mode configurations.
+    ///
+    /// libcoap (as of version 4.3.5) only implements the pairwise mode of RFC 8613, so this
+    /// currently always returns false.
+    pub fn group_mode_supported() -> bool {
+        false
+    }
+
+    /// Returns the raw configuration, transferring its ownership to libcoap.
+    ///
+    /// The values the configuration refers to are moved into the given context, which must be
+    /// the context the configuration is used with.
+    pub(crate) fn into_raw(self, ctx: &CoapContext<'_>) -> *mut coap_oscore_conf_t {
+        let raw_conf = self.raw_conf.as_ptr();
+        // SAFETY: self is forgotten below, so the storage is not dropped twice.
+        let storage = unsafe { std::ptr::read(&self.storage) };
+        std::mem::forget(self);
+        ctx.retain_oscore_storage(storage);
+        raw_conf
+    }
+}
+
+impl Debug for OscoreConf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OscoreConf")
+            .field("raw_conf", &self.raw_conf)
+            .field("save_seq_num", &self.storage.save_seq_num.is_some())
+            .finish()
+    }
+}
+
+impl Debug for OscoreConfStorage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OscoreConfStorage").finish_non_exhaustive()
+    }
+}
+
+impl Drop for OscoreConf {
+    fn drop(&mut self) {
+        // SAFETY: The configuration was created by coap_new_oscore_conf() and has not been passed
+        // to libcoap (otherwise, into_raw() would have been called).
+        unsafe {
+            coap_delete_oscore_conf(self.raw_conf.as_ptr());
+        }
+    }
+}
+
+/// COSE algorithm used for countersignatures in Group OSCORE (see
+/// [RFC 9053, Section 2](https://datatracker.ietf.org/doc/html/rfc9053#section-2)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OscoreSignatureAlgorithm {
+    /// EdDSA using Ed25519 keys.
+    EdDsa,
+    /// ECDSA using the P-256 curve and SHA-256.
+    Es256,
+    /// ECDSA using the P-384 curve and SHA-384.
+    Es384,
+    /// ECDSA using the P-521 curve and SHA-512.
+    Es512,
+}
+
+impl OscoreSignatureAlgorithm {
+    /// Returns the COSE algorithm identifier of this algorithm.
+    pub fn cose_alg(&self) -> i32 {
+        match self {
+            OscoreSignatureAlgorithm::EdDsa => -8,
+            OscoreSignatureAlgorithm::Es256 => -7,
+            OscoreSignatureAlgorithm::Es384 => -35,
+            OscoreSignatureAlgorithm::Es512 => -36,
+        }
+    }
+
+    /// Returns the length of private keys for this algorithm (in bytes).
+    fn private_key_len(&self) -> usize {
+        match self {
+            OscoreSignatureAlgorithm::EdDsa | OscoreSignatureAlgorithm::Es256 => 32,
+            OscoreSignatureAlgorithm::Es384 => 48,
+            OscoreSignatureAlgorithm::Es512 => 66,
+        }
+    }
+
+    /// Returns whether the given public key has a valid length for this algorithm.
+    ///
+    /// ECDSA public keys may be provided in compressed or uncompressed SEC 1 encoding.
+    fn is_valid_public_key(&self, key: &[u8]) -> bool {
+        match self {
+            OscoreSignatureAlgorithm::EdDsa => key.len() == 32,
+            _ => {
+                let len = self.private_key_len();
+                key.len() == len + 1 || key.len() == 2 * len + 1
+            },
+        }
+    }
+}
+
+/// Builder for Group OSCORE configurations, i.e., security contexts shared by all members of an
+/// OSCORE group that can be used to protect multicast requests.
+///
+/// In addition to the common context parameters of the (pairwise) configuration (see
+/// [OscoreConf::from_config_bytes()]), group mode requires the group identifier, the
+/// countersignature algorithm, the private key of the sender and the public keys of all other
+/// group members.
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::oscore::{OscoreConf, OscoreGroupConfBuilder, OscoreSignatureAlgorithm};
+///
+/// if OscoreConf::group_mode_supported() {
+///     let conf = OscoreGroupConfBuilder::new(
+///         "master_secret,hex,\"0102030405060708090a0b0c0d0e0f10\"\nsender_id,hex,\"25\"\n",
+///         [0xfe, 0xed],
+///         OscoreSignatureAlgorithm::EdDsa,
+///         [0u8; 32],
+///     )
+///     .peer_public_key([0x52], [0u8; 32])
+///     .build()
+///     .unwrap();
+/// }
+/// ```
+pub struct OscoreGroupConfBuilder {
+    config: Box<[u8]>,
+    group_id: Box<[u8]>,
+    sign_alg: OscoreSignatureAlgorithm,
+    sender_private_key: Box<[u8]>,
+    peer_public_keys: Vec<(Box<[u8]>, Box<[u8]>)>,
+    start_seq_num: u64,
+    save_seq_num: Option<Box<OscoreSeqNumSaver>>,
+}
+
+impl OscoreGroupConfBuilder {
+    /// Creates a builder for a group mode configuration with the given common context parameters
+    /// (in the textual format of [OscoreConf::from_config_bytes()]), group identifier,
+    /// countersignature algorithm and private key of the sender.
+    pub fn new(
+        config: &str,
+        group_id: impl Into<Box<[u8]>>,
+        sign_alg: OscoreSignatureAlgorithm,
+        sender_private_key: impl Into<Box<[u8]>>,
+    ) -> OscoreGroupConfBuilder {
+        OscoreGroupConfBuilder {
+            config: config.as_bytes().into(),
+            group_id: group_id.into(),
+            sign_alg,
+            sender_private_key: sender_private_key.into(),
+            peer_public_keys: Vec::new(),
+            start_seq_num: 0,
+            save_seq_num: None,
+        }
+    }
+
+    /// Adds the public key of the group member with the given sender ID.
+    pub fn peer_public_key(mut self, recipient_id: impl Into<Box<[u8]>>, public_key: impl Into<Box<[u8]>>) -> Self {
+        self.peer_public_keys.push((recipient_id.into(), public_key.into()));
+        self
+    }
+
+    /// Sets the initial sender sequence number and the callback used to persist it, see
+    /// [OscoreConf::from_config_bytes()].
+    pub fn sequence_numbers(mut self, start_seq_num: u64, save_seq_num: Option<Box<OscoreSeqNumSaver>>) -> Self {
+        self.start_seq_num = start_seq_num;
+        self.save_seq_num = save_seq_num;
+        self
+    }
+
+    /// Creates the group mode configuration.
+    ///
+    /// As libcoap does not provide an API for group mode configurations yet (see
+    /// [OscoreConf::group_mode_supported()]), this only validates the parameters and then always
+    /// fails with [OscoreConfError::FeatureUnavailable]. No (pairwise) configuration is created in
+    /// place of the group mode configuration.
+    ///
+    /// # Errors
+    /// Returns [OscoreConfError::InvalidGroupParameter] if the group identifier is empty, a key
+    /// does not match the countersignature algorithm or a recipient ID is used more than once,
+    /// the errors of [OscoreConf::from_config_bytes()] if the common context parameters could not
+    /// be parsed, and [OscoreConfError::FeatureUnavailable] otherwise.
+    pub fn build(self) -> Result<OscoreConf, OscoreConfError> {
+        if self.group_id.is_empty() {
+            return Err(OscoreConfError::InvalidGroupParameter("group_id"));
+        }
+        if self.sender_private_key.len() != self.sign_alg.private_key_len() {
+            return Err(OscoreConfError::InvalidGroupParameter("sender_private_key"));
+        }
+        for (idx, (recipient_id, public_key)) in self.peer_public_keys.iter().enumerate() {
+            if self.peer_public_keys[..idx].iter().any(|(id, _)| id == recipient_id) {
+                return Err(OscoreConfError::InvalidGroupParameter("recipient_id"));
+            }
+            if !self.sign_alg.is_valid_public_key(public_key) {
+                return Err(OscoreConfError::InvalidGroupParameter("peer_public_key"));
+            }
+        }
+        validate_config(&self.config)?;
+        Err(OscoreConfError::FeatureUnavailable("group OSCORE"))
+    }
+}
+
+impl Debug for OscoreGroupConfBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OscoreGroupConfBuilder")
+            .field("group_id", &self.group_id)
+            .field("sign_alg", &self.sign_alg)
+            .field("peers", &self.peer_public_keys.len())
+            .field("start_seq_num", &self.start_seq_num)
+            .field("save_seq_num", &self.save_seq_num.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Checks the syntax of the entries of the given configuration, as libcoap only reports the
+/// location of errors in its log.
+fn validate_config(config: &[u8]) -> Result<(), OscoreConfError> {
+    for (idx, line) in config.split(|b| *b == b'\n').enumerate() {
+        let line_num = idx + 1;
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let mut parts = line.splitn(3, |b| *b == b',').map(<[u8]>::trim_ascii);
+        let (Some(keyword), Some(type_), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(OscoreConfError::MalformedLine(line_num));
+        };
+        if keyword.is_empty() {
+            return Err(OscoreConfError::MalformedLine(line_num));
+        }
+        let field = String::from_utf8_lossy(keyword).into_owned();
+        let valid = match type_ {
+            b"ascii" | b"text" => !value.is_empty(),
+            b"hex" => {
+                let digits = unquote(value);
+                digits.len() % 2 == 0 && digits.iter().all(u8::is_ascii_hexdigit)
+            },
+            b"integer" => std::str::from_utf8(value).is_ok_and(|v| v.parse::<i64>().is_ok()),
+            b"bool" => value == b"true" || value == b"false",
+            _ => return Err(OscoreConfError::UnknownType(line_num, field)),
+        };
+        if !valid {
+            return Err(OscoreConfError::InvalidValue(line_num, field));
+        }
+    }
+    Ok(())
+}
+
+/// Removes the surrounding double quotes of the given value (if present).
+fn unquote(value: &[u8]) -> &[u8] {
+    value
+        .strip_prefix(b"\"")
+        .and_then(|v| v.strip_suffix(b"\""))
+        .unwrap_or(value)
+}
+
+/// Sequence number save callback provided to libcoap.
+///
+/// # Safety
+/// `param` must point to the `Box<OscoreSeqNumSaver>` stored in an [OscoreConfStorage].
+unsafe extern "C" fn save_seq_num_callback(sender_seq_num: u64, param: *mut c_void) -> c_int {
+    let save_seq_num = &mut *(param as *mut Box<OscoreSeqNumSaver>);
+    catch_callback_panic(std::ptr::null_mut(), 0, || save_seq_num(sender_seq_num) as c_int)
+}
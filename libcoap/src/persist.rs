@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * persist.rs - Types for persisting server state across restarts.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for persisting server state across restarts.
+//!
+//! libcoap is able to track observe relationships, the observe numbers of resources and
+//! dynamically created resources, so that this state can be restored after the server has been
+//! restarted (and clients do not need to re-register their observations).
+//! Persistence is enabled using [CoapContext::enable_persistence()](crate::CoapContext::enable_persistence),
+//! either by letting libcoap store the state in files ([PersistConfig::Files]) or by storing the
+//! tracked state in an application-defined way ([PersistConfig::Callbacks]).
+//!
+//! See the [libcoap documentation](https://libcoap.net/doc/reference/4.3.5/group__persist.html)
+//! for more information.
+
+use std::{
+    ffi::{c_int, c_void},
+    fmt::{Debug, Formatter},
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+use libcoap_sys::{
+    coap_addr_tuple_t, coap_address_t, coap_bin_const_t, coap_context_t, coap_proto_t, coap_session_t,
+    coap_str_const_t, coap_subscription_t,
+};
+
+use crate::{
+    types::{CoapAddress, CoapProtocol},
+    unwind::{catch_callback_panic, catch_session_callback_panic},
+};
+
+/// Configuration for persisting server state, see
+/// [CoapContext::enable_persistence()](crate::CoapContext::enable_persistence).
+pub enum PersistConfig {
+    /// Let libcoap store the tracked state in the given files, restoring the state stored in
+    /// these files when persistence is enabled.
+    ///
+    /// State that should not be tracked can be excluded by setting the respective path to None.
+    Files {
+        /// File storing dynamically created resources.
+        dyn_resource_file: Option<PathBuf>,
+        /// File storing observe relationships.
+        observe_file: Option<PathBuf>,
+        /// File storing the observe numbers of resources.
+        observe_number_file: Option<PathBuf>,
+        /// Number of notifications for a resource after which its observe number is saved.
+        save_frequency: u32,
+    },
+    /// Report all changes of the tracked state to the given handler, which is responsible for
+    /// storing them.
+    ///
+    /// The stored state has to be restored by the application using
+    /// [CoapContext::restore_observer()](crate::CoapContext::restore_observer) and
+    /// [CoapResource::set_observe_number()](crate::CoapResource::set_observe_number).
+    Callbacks {
+        /// Handler that is called whenever the tracked state changes.
+        handler: Box<dyn CoapPersistHandler>,
+        /// Number of notifications for a resource after which
+        /// [CoapPersistHandler::observe_number_changed()] is called.
+        save_frequency: u32,
+    },
+}
+
+impl Debug for PersistConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistConfig::Files {
+                dyn_resource_file,
+                observe_file,
+                observe_number_file,
+                save_frequency,
+            } => f
+                .debug_struct("Files")
+                .field("dyn_resource_file", dyn_resource_file)
+                .field("observe_file", observe_file)
+                .field("observe_number_file", observe_number_file)
+                .field("save_frequency", save_frequency)
+                .finish(),
+            PersistConfig::Callbacks {
+                handler,
+                save_frequency,
+            } => f
+                .debug_struct("Callbacks")
+                .field("handler", handler)
+                .field("save_frequency", save_frequency)
+                .finish(),
+        }
+    }
+}
+
+/// Identifier of an observe relationship tracked by libcoap.
+///
+/// The key of an observe relationship is only valid while the server is running, i.e., a
+/// restored relationship has a different key than the original one (see
+/// [CoapContext::restore_observer()](crate::CoapContext::restore_observer)).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CoapObserveKey(usize);
+
+impl CoapObserveKey {
+    pub(crate) fn from_raw(raw_subscription: *const coap_subscription_t) -> CoapObserveKey {
+        CoapObserveKey(raw_subscription as usize)
+    }
+}
+
+/// Information on an observe relationship that is required in order to restore it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CoapObserveRecord {
+    /// Transport protocol of the endpoint the relationship was established on.
+    pub proto: CoapProtocol,
+    /// Address of the endpoint the relationship was established on.
+    pub endpoint_addr: SocketAddr,
+    /// Local address of the session the relationship was established on.
+    pub local_addr: SocketAddr,
+    /// Remote address of the session the relationship was established on.
+    pub remote_addr: SocketAddr,
+    /// Raw packet of the request that established the relationship.
+    pub raw_packet: Vec<u8>,
+    /// OSCORE information of the relationship, if OSCORE was used.
+    pub oscore_info: Option<Vec<u8>>,
+}
+
+/// Trait for handlers that store the server state tracked by libcoap, see
+/// [PersistConfig::Callbacks].
+///
+/// All functions have a default implementation that ignores the change.
+pub trait CoapPersistHandler: Debug {
+    /// Called when a new observe relationship was established.
+    #[allow(unused_variables)]
+    fn observe_added(&mut self, key: CoapObserveKey, record: &CoapObserveRecord) {}
+
+    /// Called when the observe relationship with the given key was removed.
+    ///
+    /// This is not called for relationships that still exist when the context is dropped, as
+    /// these should be restored after a restart.
+    #[allow(unused_variables)]
+    fn observe_deleted(&mut self, key: CoapObserveKey) {}
+
+    /// Called when the observe number of the resource with the given URI path should be saved.
+    #[allow(unused_variables)]
+    fn observe_number_changed(&mut self, resource_path: &str, observe_number: u32) {}
+
+    /// Called when a resource was created dynamically by a request with the given raw packet.
+    #[allow(unused_variables)]
+    fn dyn_resource_added(&mut self, resource_path: &str, raw_packet: &[u8]) {}
+
+    /// Called when the resource with the given URI path was deleted.
+    #[allow(unused_variables)]
+    fn resource_deleted(&mut self, resource_path: &str) {}
+}
+
+/// Handler for persistence callbacks as stored in the context.
+///
+/// A pointer to this type is passed to libcoap as the application data of the tracking functions,
+/// so it has to be boxed in order to keep its address stable.
+pub(crate) type PersistHandlerCell = std::cell::RefCell<Box<dyn CoapPersistHandler>>;
+
+/// Converts the given raw observe relationship information into a [CoapObserveRecord].
+///
+/// Returns None if any of the addresses is not a socket address (e.g., for Unix domain socket
+/// endpoints).
+///
+/// # Safety
+/// All provided pointers must be valid, except for `oscore_info`, which may also be null.
+unsafe fn observe_record_from_raw(
+    e_proto: coap_proto_t,
+    e_listen_addr: *const coap_address_t,
+    s_addr_info: *const coap_addr_tuple_t,
+    raw_packet: *const coap_bin_const_t,
+    oscore_info: *const coap_bin_const_t,
+) -> Option<CoapObserveRecord> {
+    let socket_addr = |addr: &coap_address_t| CoapAddress::from(addr).to_socket_addrs().ok()?.next();
+    Some(CoapObserveRecord {
+        proto: e_proto.into(),
+        endpoint_addr: socket_addr(&*e_listen_addr)?,
+        local_addr: socket_addr(&(*s_addr_info).local)?,
+        remote_addr: socket_addr(&(*s_addr_info).remote)?,
+        raw_packet: bin_const_to_slice(raw_packet).to_vec(),
+        oscore_info: (!oscore_info.is_null()).then(|| bin_const_to_slice(oscore_info).to_vec()),
+    })
+}
+
+/// Returns the bytes referred to by the given raw binary string.
+///
+/// # Safety
+/// `value` must point to a valid binary string that outlives the returned slice.
+unsafe fn bin_const_to_slice<'a>(value: *const coap_bin_const_t) -> &'a [u8] {
+    if (*value).length == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts((*value).s, (*value).length)
+}
+
+/// Returns the URI path referred to by the given raw resource name.
+///
+/// # Safety
+/// `value` must point to a valid string.
+unsafe fn resource_path(value: *const coap_str_const_t) -> String {
+    if (*value).length == 0 {
+        return String::new();
+    }
+    String::from_utf8_lossy(std::slice::from_raw_parts((*value).s, (*value).length)).into_owned()
+}
+
+/// Returns the handler stored at the given application data pointer.
+///
+/// # Safety
+/// `user_data` must point to a valid [PersistHandlerCell].
+unsafe fn persist_handler<'a>(user_data: *mut c_void) -> std::cell::RefMut<'a, Box<dyn CoapPersistHandler>> {
+    (*(user_data as *const PersistHandlerCell))
+        .try_borrow_mut()
+        .expect("persistence handler called while it is already in use")
+}
+
+pub(crate) unsafe extern "C" fn persist_observe_added_callback(
+    session: *mut coap_session_t,
+    observe_key: *mut coap_subscription_t,
+    e_proto: coap_proto_t,
+    e_listen_addr: *mut coap_address_t,
+    s_addr_info: *mut coap_addr_tuple_t,
+    raw_packet: *mut coap_bin_const_t,
+    oscore_info: *mut coap_bin_const_t,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_session_callback_panic(session, 0, || {
+        if let Some(record) = observe_record_from_raw(e_proto, e_listen_addr, s_addr_info, raw_packet, oscore_info) {
+            persist_handler(user_data).observe_added(CoapObserveKey::from_raw(observe_key), &record);
+        }
+        1
+    })
+}
+
+pub(crate) unsafe extern "C" fn persist_observe_deleted_callback(
+    session: *mut coap_session_t,
+    observe_key: *mut coap_subscription_t,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_session_callback_panic(session, 0, || {
+        persist_handler(user_data).observe_deleted(CoapObserveKey::from_raw(observe_key));
+        1
+    })
+}
+
+pub(crate) unsafe extern "C" fn persist_observe_value_callback(
+    context: *mut coap_context_t,
+    resource_name: *mut coap_str_const_t,
+    observe_num: u32,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_callback_panic(context, 0, || {
+        persist_handler(user_data).observe_number_changed(&resource_path(resource_name), observe_num);
+        1
+    })
+}
+
+pub(crate) unsafe extern "C" fn persist_dyn_resource_added_callback(
+    session: *mut coap_session_t,
+    resource_name: *mut coap_str_const_t,
+    raw_packet: *mut coap_bin_const_t,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_session_callback_panic(session, 0, || {
+        persist_handler(user_data).dyn_resource_added(&resource_path(resource_name), bin_const_to_slice(raw_packet));
+        1
+    })
+}
+
+pub(crate) unsafe extern "C" fn persist_resource_deleted_callback(
+    context: *mut coap_context_t,
+    resource_name: *mut coap_str_const_t,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_callback_panic(context, 0, || {
+        persist_handler(user_data).resource_deleted(&resource_path(resource_name));
+        1
+    })
+}
@@ -29,8 +29,10 @@ use libcoap_sys::{coap_prng, coap_prng_init};
 #[cfg(feature = "rand")]
 use libcoap_sys::coap_set_prng;
 
-use crate::context::ensure_coap_started;
 use crate::error::RngError;
+use crate::startup::ensure_coap_started;
+#[cfg(feature = "rand")]
+use crate::unwind::catch_callback_panic;
 
 // TODO If we can assert that libcoap's own thread-safety features are enabled at some point, we
 //      don't need these mutexes.
@@ -188,12 +190,12 @@ pub fn set_coap_prng<RNG: RngCore + CryptoRng + Send + Sync + 'static>(rng: RNG)
 #[cfg(feature = "rand")]
 unsafe extern "C" fn prng_callback(out: *mut c_void, len: size_t) -> c_int {
     let out_slice = std::slice::from_raw_parts_mut(out as *mut u8, len);
-    match COAP_RNG_FN_MUTEX.lock() {
+    catch_callback_panic(std::ptr::null_mut(), 0, || match COAP_RNG_FN_MUTEX.lock() {
         Ok(mut rng_fn) => rng_fn
             .as_mut()
             .expect("rng_callback has been set, but no RNG was set")
             .try_fill_bytes(out_slice)
             .map_or(0, |_| 1),
         Err(_e) => 0,
-    }
+    })
 }
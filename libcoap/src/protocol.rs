@@ -12,8 +12,11 @@
 use std::{
     ffi::CStr,
     fmt::{Display, Formatter},
+    ops::RangeInclusive,
+    time::Duration,
 };
 
+use bitflags::bitflags;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
@@ -38,6 +41,7 @@ use libcoap_sys::{
     COAP_OPTION_Q_BLOCK2, COAP_OPTION_RTAG, COAP_OPTION_SIZE1, COAP_OPTION_SIZE2, COAP_OPTION_URI_HOST,
     COAP_OPTION_URI_PATH, COAP_OPTION_URI_PORT, COAP_OPTION_URI_QUERY, coap_pdu_code_t, coap_pdu_type_t,
     coap_pdu_type_t::{COAP_MESSAGE_ACK, COAP_MESSAGE_CON, COAP_MESSAGE_NON, COAP_MESSAGE_RST}, coap_request_t, coap_response_phrase,
+    COAP_TOKEN_DEFAULT_MAX, COAP_TOKEN_EXT_MAX,
 };
 
 use crate::error::{MessageCodeError, UnknownOptionError};
@@ -66,6 +70,61 @@ pub type RequestTag = Box<[u8]>;
 pub type CoapOptionNum = coap_option_num_t;
 pub type CoapToken = Box<[u8]>;
 
+/// Maximum token length defined in [RFC 7252](https://datatracker.ietf.org/doc/html/rfc7252#section-3),
+/// i.e., for peers that do not support extended token lengths.
+pub const DEFAULT_MAX_TOKEN_SIZE: usize = COAP_TOKEN_DEFAULT_MAX as usize;
+/// Maximum token length supported by libcoap if extended token lengths
+/// ([RFC 8974](https://datatracker.ietf.org/doc/html/rfc8974)) are enabled.
+pub const MAX_EXTENDED_TOKEN_SIZE: usize = COAP_TOKEN_EXT_MAX as usize;
+/// Max-Age assumed for responses without a Max-Age option (in seconds), see
+/// [RFC 7252, Section 5.10.5](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.5).
+pub const DEFAULT_MAX_AGE: MaxAge = 60;
+/// Hop-Limit inserted into forwarded requests that do not have one, see
+/// [RFC 8768, Section 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3).
+pub const DEFAULT_HOP_LIMIT: HopLimit = 16;
+/// Range of valid Hop-Limit option values, see
+/// [RFC 8768, Section 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3).
+pub const HOP_LIMIT_RANGE: RangeInclusive<HopLimit> = 1..=255;
+/// DEFAULT_LEISURE as defined in [RFC 7252, Section 4.8](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8),
+/// used by libcoap unless configured otherwise.
+pub const DEFAULT_LEISURE: Duration = Duration::from_secs(5);
+/// PROBING_RATE (in bytes per second) as defined in
+/// [RFC 7252, Section 4.8](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8), used by
+/// libcoap unless configured otherwise.
+pub const DEFAULT_PROBING_RATE: u32 = 1;
+
+bitflags! {
+    /// Classes of responses a client is not interested in, as indicated using the No-Response
+    /// option (see [RFC 7967](https://datatracker.ietf.org/doc/html/rfc7967)).
+    ///
+    /// An empty set indicates that the client is interested in all responses.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct CoapNoResponse: NoResponse {
+        /// Suppress 2.xx (success) responses.
+        const SUPPRESS_SUCCESS = 0x02;
+        /// Suppress 4.xx (client error) responses.
+        const SUPPRESS_CLIENT_ERROR = 0x08;
+        /// Suppress 5.xx (server error) responses.
+        const SUPPRESS_SERVER_ERROR = 0x10;
+        /// Suppress all responses.
+        const ALL = Self::SUPPRESS_SUCCESS.bits()
+            | Self::SUPPRESS_CLIENT_ERROR.bits()
+            | Self::SUPPRESS_SERVER_ERROR.bits();
+    }
+}
+
+impl CoapNoResponse {
+    /// Returns whether responses with the given response code should be suppressed.
+    pub fn suppresses(&self, code: CoapResponseCode) -> bool {
+        match code.class() {
+            2 => self.contains(CoapNoResponse::SUPPRESS_SUCCESS),
+            4 => self.contains(CoapNoResponse::SUPPRESS_CLIENT_ERROR),
+            5 => self.contains(CoapNoResponse::SUPPRESS_SERVER_ERROR),
+            _ => false,
+        }
+    }
+}
+
 /// Representation of a CoAP match expression supplied in the If-Match option, see
 /// [RFC 7252, Section 5.10.8.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.8.1).
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -221,50 +280,150 @@ impl TryFrom<coap_option_num_t> for CoapOptionType {
 
 /// Various content formats that can be used for CoAP requests.
 ///
-/// To get the corresponding numeric value, use `[value] as u16`.
+/// Content formats can be converted from and to their numeric value using [`From<u16>`] and
+/// [`Into<u16>`]. Values that are not known to this crate are represented as
+/// [CoapContentFormat::Other], so these conversions are lossless.
 ///
 /// See <https://www.iana.org/assignments/core-parameters/core-parameters.xhtml#content-formats> for
 /// values that are currently registered with the IANA.
-#[repr(u16)]
-#[derive(Copy, Clone, FromPrimitive, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 #[non_exhaustive]
 pub enum CoapContentFormat {
-    Cbor = COAP_MEDIATYPE_APPLICATION_CBOR as u16,
-    DotsCbor = COAP_MEDIATYPE_APPLICATION_DOTS_CBOR as u16,
-    SenMlCbor = COAP_MEDIATYPE_APPLICATION_SENML_CBOR as u16,
-    SenMlExi = COAP_MEDIATYPE_APPLICATION_SENML_EXI as u16,
-    CoseEncrypt = COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT as u16,
-    CoseEncrypt0 = COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT0 as u16,
-    CoseKey = COAP_MEDIATYPE_APPLICATION_COSE_KEY as u16,
-    CoseKeySet = COAP_MEDIATYPE_APPLICATION_COSE_KEY_SET as u16,
-    CoseMac = COAP_MEDIATYPE_APPLICATION_COSE_MAC as u16,
-    CoseMac0 = COAP_MEDIATYPE_APPLICATION_COSE_MAC0 as u16,
-    CoseSign = COAP_MEDIATYPE_APPLICATION_COSE_SIGN as u16,
-    CoseSign1 = COAP_MEDIATYPE_APPLICATION_COSE_SIGN1 as u16,
-    Cwt = COAP_MEDIATYPE_APPLICATION_CWT as u16,
-    Exi = COAP_MEDIATYPE_APPLICATION_EXI as u16,
-    Json = COAP_MEDIATYPE_APPLICATION_JSON as u16,
-    LinkFormat = COAP_MEDIATYPE_APPLICATION_LINK_FORMAT as u16,
-    OctetStream = COAP_MEDIATYPE_APPLICATION_OCTET_STREAM as u16,
-    RdfXml = COAP_MEDIATYPE_APPLICATION_RDF_XML as u16,
-    SenMlJson = COAP_MEDIATYPE_APPLICATION_SENML_JSON as u16,
-    SenMlXml = COAP_MEDIATYPE_APPLICATION_SENML_XML as u16,
-    SensMlCbor = COAP_MEDIATYPE_APPLICATION_SENSML_CBOR as u16,
-    SensMlExi = COAP_MEDIATYPE_APPLICATION_SENSML_EXI as u16,
-    SensMlJson = COAP_MEDIATYPE_APPLICATION_SENSML_JSON as u16,
-    SensMlXml = COAP_MEDIATYPE_APPLICATION_SENSML_XML as u16,
-    ApplicationXml = COAP_MEDIATYPE_APPLICATION_XML as u16,
-    TextPlain = COAP_MEDIATYPE_TEXT_PLAIN as u16,
-    AceCbor = COAP_MEDIATYPE_APPLICATION_ACE_CBOR as u16,
-    CoapGroupJson = COAP_MEDIATYPE_APPLICATION_COAP_GROUP_JSON as u16,
-    MbCborSeq = COAP_MEDIATYPE_APPLICATION_MB_CBOR_SEQ as u16,
-    Oscore = COAP_MEDIATYPE_APPLICATION_OSCORE as u16,
-    Other,
+    Cbor,
+    DotsCbor,
+    SenMlCbor,
+    SenMlExi,
+    CoseEncrypt,
+    CoseEncrypt0,
+    CoseKey,
+    CoseKeySet,
+    CoseMac,
+    CoseMac0,
+    CoseSign,
+    CoseSign1,
+    Cwt,
+    Exi,
+    Json,
+    LinkFormat,
+    OctetStream,
+    RdfXml,
+    SenMlJson,
+    SenMlXml,
+    SensMlCbor,
+    SensMlExi,
+    SensMlJson,
+    SensMlXml,
+    ApplicationXml,
+    TextPlain,
+    AceCbor,
+    CoapGroupJson,
+    MbCborSeq,
+    Oscore,
+    /// `image/gif`
+    ImageGif,
+    /// `image/jpeg`
+    ImageJpeg,
+    /// `image/png`
+    ImagePng,
+    /// `application/json-patch+json`
+    JsonPatchJson,
+    /// `application/merge-patch+json`
+    MergePatchJson,
+    /// `application/multipart-core`
+    MultipartCore,
+    /// `application/cbor-seq`
+    CborSeq,
+    /// A content format that is not known to this crate.
+    Other(ContentFormat),
 }
 
 impl From<ContentFormat> for CoapContentFormat {
-    fn from(value: u16) -> Self {
-        <CoapContentFormat as FromPrimitive>::from_u16(value).unwrap_or(CoapContentFormat::Other)
+    fn from(value: ContentFormat) -> Self {
+        match value as u32 {
+            COAP_MEDIATYPE_APPLICATION_CBOR => CoapContentFormat::Cbor,
+            COAP_MEDIATYPE_APPLICATION_DOTS_CBOR => CoapContentFormat::DotsCbor,
+            COAP_MEDIATYPE_APPLICATION_SENML_CBOR => CoapContentFormat::SenMlCbor,
+            COAP_MEDIATYPE_APPLICATION_SENML_EXI => CoapContentFormat::SenMlExi,
+            COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT => CoapContentFormat::CoseEncrypt,
+            COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT0 => CoapContentFormat::CoseEncrypt0,
+            COAP_MEDIATYPE_APPLICATION_COSE_KEY => CoapContentFormat::CoseKey,
+            COAP_MEDIATYPE_APPLICATION_COSE_KEY_SET => CoapContentFormat::CoseKeySet,
+            COAP_MEDIATYPE_APPLICATION_COSE_MAC => CoapContentFormat::CoseMac,
+            COAP_MEDIATYPE_APPLICATION_COSE_MAC0 => CoapContentFormat::CoseMac0,
+            COAP_MEDIATYPE_APPLICATION_COSE_SIGN => CoapContentFormat::CoseSign,
+            COAP_MEDIATYPE_APPLICATION_COSE_SIGN1 => CoapContentFormat::CoseSign1,
+            COAP_MEDIATYPE_APPLICATION_CWT => CoapContentFormat::Cwt,
+            COAP_MEDIATYPE_APPLICATION_EXI => CoapContentFormat::Exi,
+            COAP_MEDIATYPE_APPLICATION_JSON => CoapContentFormat::Json,
+            COAP_MEDIATYPE_APPLICATION_LINK_FORMAT => CoapContentFormat::LinkFormat,
+            COAP_MEDIATYPE_APPLICATION_OCTET_STREAM => CoapContentFormat::OctetStream,
+            COAP_MEDIATYPE_APPLICATION_RDF_XML => CoapContentFormat::RdfXml,
+            COAP_MEDIATYPE_APPLICATION_SENML_JSON => CoapContentFormat::SenMlJson,
+            COAP_MEDIATYPE_APPLICATION_SENML_XML => CoapContentFormat::SenMlXml,
+            COAP_MEDIATYPE_APPLICATION_SENSML_CBOR => CoapContentFormat::SensMlCbor,
+            COAP_MEDIATYPE_APPLICATION_SENSML_EXI => CoapContentFormat::SensMlExi,
+            COAP_MEDIATYPE_APPLICATION_SENSML_JSON => CoapContentFormat::SensMlJson,
+            COAP_MEDIATYPE_APPLICATION_SENSML_XML => CoapContentFormat::SensMlXml,
+            COAP_MEDIATYPE_APPLICATION_XML => CoapContentFormat::ApplicationXml,
+            COAP_MEDIATYPE_TEXT_PLAIN => CoapContentFormat::TextPlain,
+            COAP_MEDIATYPE_APPLICATION_ACE_CBOR => CoapContentFormat::AceCbor,
+            COAP_MEDIATYPE_APPLICATION_COAP_GROUP_JSON => CoapContentFormat::CoapGroupJson,
+            COAP_MEDIATYPE_APPLICATION_MB_CBOR_SEQ => CoapContentFormat::MbCborSeq,
+            COAP_MEDIATYPE_APPLICATION_OSCORE => CoapContentFormat::Oscore,
+            21 => CoapContentFormat::ImageGif,
+            22 => CoapContentFormat::ImageJpeg,
+            23 => CoapContentFormat::ImagePng,
+            51 => CoapContentFormat::JsonPatchJson,
+            52 => CoapContentFormat::MergePatchJson,
+            62 => CoapContentFormat::MultipartCore,
+            63 => CoapContentFormat::CborSeq,
+            _ => CoapContentFormat::Other(value),
+        }
+    }
+}
+
+impl From<CoapContentFormat> for ContentFormat {
+    fn from(value: CoapContentFormat) -> Self {
+        match value {
+            CoapContentFormat::Cbor => COAP_MEDIATYPE_APPLICATION_CBOR as ContentFormat,
+            CoapContentFormat::DotsCbor => COAP_MEDIATYPE_APPLICATION_DOTS_CBOR as ContentFormat,
+            CoapContentFormat::SenMlCbor => COAP_MEDIATYPE_APPLICATION_SENML_CBOR as ContentFormat,
+            CoapContentFormat::SenMlExi => COAP_MEDIATYPE_APPLICATION_SENML_EXI as ContentFormat,
+            CoapContentFormat::CoseEncrypt => COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT as ContentFormat,
+            CoapContentFormat::CoseEncrypt0 => COAP_MEDIATYPE_APPLICATION_COSE_ENCRYPT0 as ContentFormat,
+            CoapContentFormat::CoseKey => COAP_MEDIATYPE_APPLICATION_COSE_KEY as ContentFormat,
+            CoapContentFormat::CoseKeySet => COAP_MEDIATYPE_APPLICATION_COSE_KEY_SET as ContentFormat,
+            CoapContentFormat::CoseMac => COAP_MEDIATYPE_APPLICATION_COSE_MAC as ContentFormat,
+            CoapContentFormat::CoseMac0 => COAP_MEDIATYPE_APPLICATION_COSE_MAC0 as ContentFormat,
+            CoapContentFormat::CoseSign => COAP_MEDIATYPE_APPLICATION_COSE_SIGN as ContentFormat,
+            CoapContentFormat::CoseSign1 => COAP_MEDIATYPE_APPLICATION_COSE_SIGN1 as ContentFormat,
+            CoapContentFormat::Cwt => COAP_MEDIATYPE_APPLICATION_CWT as ContentFormat,
+            CoapContentFormat::Exi => COAP_MEDIATYPE_APPLICATION_EXI as ContentFormat,
+            CoapContentFormat::Json => COAP_MEDIATYPE_APPLICATION_JSON as ContentFormat,
+            CoapContentFormat::LinkFormat => COAP_MEDIATYPE_APPLICATION_LINK_FORMAT as ContentFormat,
+            CoapContentFormat::OctetStream => COAP_MEDIATYPE_APPLICATION_OCTET_STREAM as ContentFormat,
+            CoapContentFormat::RdfXml => COAP_MEDIATYPE_APPLICATION_RDF_XML as ContentFormat,
+            CoapContentFormat::SenMlJson => COAP_MEDIATYPE_APPLICATION_SENML_JSON as ContentFormat,
+            CoapContentFormat::SenMlXml => COAP_MEDIATYPE_APPLICATION_SENML_XML as ContentFormat,
+            CoapContentFormat::SensMlCbor => COAP_MEDIATYPE_APPLICATION_SENSML_CBOR as ContentFormat,
+            CoapContentFormat::SensMlExi => COAP_MEDIATYPE_APPLICATION_SENSML_EXI as ContentFormat,
+            CoapContentFormat::SensMlJson => COAP_MEDIATYPE_APPLICATION_SENSML_JSON as ContentFormat,
+            CoapContentFormat::SensMlXml => COAP_MEDIATYPE_APPLICATION_SENSML_XML as ContentFormat,
+            CoapContentFormat::ApplicationXml => COAP_MEDIATYPE_APPLICATION_XML as ContentFormat,
+            CoapContentFormat::TextPlain => COAP_MEDIATYPE_TEXT_PLAIN as ContentFormat,
+            CoapContentFormat::AceCbor => COAP_MEDIATYPE_APPLICATION_ACE_CBOR as ContentFormat,
+            CoapContentFormat::CoapGroupJson => COAP_MEDIATYPE_APPLICATION_COAP_GROUP_JSON as ContentFormat,
+            CoapContentFormat::MbCborSeq => COAP_MEDIATYPE_APPLICATION_MB_CBOR_SEQ as ContentFormat,
+            CoapContentFormat::Oscore => COAP_MEDIATYPE_APPLICATION_OSCORE as ContentFormat,
+            CoapContentFormat::ImageGif => 21,
+            CoapContentFormat::ImageJpeg => 22,
+            CoapContentFormat::ImagePng => 23,
+            CoapContentFormat::JsonPatchJson => 51,
+            CoapContentFormat::MergePatchJson => 52,
+            CoapContentFormat::MultipartCore => 62,
+            CoapContentFormat::CborSeq => 63,
+            CoapContentFormat::Other(value) => value,
+        }
     }
 }
 
@@ -284,6 +443,9 @@ pub enum CoapMessageCode {
 impl CoapMessageCode {
     /// Returns the corresponding raw code for this message code, which can be added to a raw
     /// [coap_pdu_t](libcoap_sys::coap_pdu_t).
+    ///
+    /// # Panics
+    /// Panics for unknown response codes (see [CoapResponseCode::to_raw_pdu_code()]).
     pub fn to_raw_pdu_code(self) -> coap_pdu_code_t {
         match self {
             CoapMessageCode::Empty => coap_pdu_code_t::COAP_EMPTY_CODE,
@@ -291,6 +453,30 @@ impl CoapMessageCode {
             CoapMessageCode::Response(rsp) => rsp.to_raw_pdu_code(),
         }
     }
+
+    /// Returns the message code for the given numeric value (`class << 5 | detail`).
+    ///
+    /// # Errors
+    /// Returns a [MessageCodeError] if the value is neither the empty code nor a request or
+    /// response code (see [CoapResponseCode::from_raw_code()]).
+    pub fn from_raw_code(code: u8) -> Result<CoapMessageCode, MessageCodeError> {
+        match code {
+            0 => Ok(CoapMessageCode::Empty),
+            code => <CoapRequestCode as FromPrimitive>::from_u8(code)
+                .map(CoapMessageCode::Request)
+                .or_else(|| CoapResponseCode::from_raw_code(code).map(CoapMessageCode::Response))
+                .ok_or(MessageCodeError::NotAResponseCode),
+        }
+    }
+
+    /// Returns the numeric value of this message code (`class << 5 | detail`).
+    pub fn to_raw_code(self) -> u8 {
+        match self {
+            CoapMessageCode::Empty => 0,
+            CoapMessageCode::Request(req) => req.to_raw_pdu_code() as u8,
+            CoapMessageCode::Response(rsp) => rsp.to_raw_code(),
+        }
+    }
 }
 
 impl From<CoapRequestCode> for CoapMessageCode {
@@ -309,12 +495,7 @@ impl TryFrom<coap_pdu_code_t> for CoapMessageCode {
     type Error = MessageCodeError;
 
     fn try_from(code: coap_pdu_code_t) -> Result<Self, Self::Error> {
-        match code {
-            coap_pdu_code_t::COAP_EMPTY_CODE => Ok(CoapMessageCode::Empty),
-            code => CoapRequestCode::try_from(code)
-                .map(CoapMessageCode::Request)
-                .or_else(|_| CoapResponseCode::try_from(code).map(CoapMessageCode::Response)),
-        }
+        CoapMessageCode::from_raw_code(code as u8)
     }
 }
 
@@ -345,7 +526,7 @@ impl CoapRequestCode {
         match self {
             CoapRequestCode::Get => coap_request_t::COAP_REQUEST_GET,
             CoapRequestCode::Put => coap_request_t::COAP_REQUEST_PUT,
-            CoapRequestCode::Delete => coap_request_t::COAP_REQUEST_FETCH,
+            CoapRequestCode::Delete => coap_request_t::COAP_REQUEST_DELETE,
             CoapRequestCode::Post => coap_request_t::COAP_REQUEST_POST,
             CoapRequestCode::Fetch => coap_request_t::COAP_REQUEST_FETCH,
             CoapRequestCode::IPatch => coap_request_t::COAP_REQUEST_IPATCH,
@@ -359,7 +540,7 @@ impl CoapRequestCode {
         match self {
             CoapRequestCode::Get => coap_pdu_code_t::COAP_REQUEST_CODE_GET,
             CoapRequestCode::Put => coap_pdu_code_t::COAP_REQUEST_CODE_PUT,
-            CoapRequestCode::Delete => coap_pdu_code_t::COAP_REQUEST_CODE_FETCH,
+            CoapRequestCode::Delete => coap_pdu_code_t::COAP_REQUEST_CODE_DELETE,
             CoapRequestCode::Post => coap_pdu_code_t::COAP_REQUEST_CODE_POST,
             CoapRequestCode::Fetch => coap_pdu_code_t::COAP_REQUEST_CODE_FETCH,
             CoapRequestCode::IPatch => coap_pdu_code_t::COAP_REQUEST_CODE_IPATCH,
@@ -395,42 +576,138 @@ impl TryFrom<coap_pdu_code_t> for CoapRequestCode {
 ///
 /// See <https://www.iana.org/assignments/core-parameters/core-parameters.xhtml#response-codes> for
 /// the possible values currently registered with the IANA.
-#[repr(u8)]
 #[non_exhaustive]
-#[derive(Clone, Copy, FromPrimitive, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum CoapResponseCode {
-    Content = coap_pdu_code_t::COAP_RESPONSE_CODE_CONTENT as u8,
-    BadGateway = coap_pdu_code_t::COAP_RESPONSE_CODE_BAD_GATEWAY as u8,
-    Continue = coap_pdu_code_t::COAP_RESPONSE_CODE_CONTINUE as u8,
-    Conflict = coap_pdu_code_t::COAP_RESPONSE_CODE_CONFLICT as u8,
-    BadRequest = coap_pdu_code_t::COAP_RESPONSE_CODE_BAD_REQUEST as u8,
-    BadOption = coap_pdu_code_t::COAP_RESPONSE_CODE_BAD_OPTION as u8,
-    Changed = coap_pdu_code_t::COAP_RESPONSE_CODE_CHANGED as u8,
-    Created = coap_pdu_code_t::COAP_RESPONSE_CODE_CREATED as u8,
-    Deleted = coap_pdu_code_t::COAP_RESPONSE_CODE_DELETED as u8,
-    Forbidden = coap_pdu_code_t::COAP_RESPONSE_CODE_FORBIDDEN as u8,
-    GatewayTimeout = coap_pdu_code_t::COAP_RESPONSE_CODE_GATEWAY_TIMEOUT as u8,
-    HopLimitReached = coap_pdu_code_t::COAP_RESPONSE_CODE_HOP_LIMIT_REACHED as u8,
-    Incomplete = coap_pdu_code_t::COAP_RESPONSE_CODE_INCOMPLETE as u8,
-    InternalError = coap_pdu_code_t::COAP_RESPONSE_CODE_INTERNAL_ERROR as u8,
-    NotAcceptable = coap_pdu_code_t::COAP_RESPONSE_CODE_NOT_ACCEPTABLE as u8,
-    NotAllowed = coap_pdu_code_t::COAP_RESPONSE_CODE_NOT_ALLOWED as u8,
-    NotFound = coap_pdu_code_t::COAP_RESPONSE_CODE_NOT_FOUND as u8,
-    NotImplemented = coap_pdu_code_t::COAP_RESPONSE_CODE_NOT_IMPLEMENTED as u8,
-    PreconditionFailed = coap_pdu_code_t::COAP_RESPONSE_CODE_PRECONDITION_FAILED as u8,
-    ProxyingNotSupported = coap_pdu_code_t::COAP_RESPONSE_CODE_PROXYING_NOT_SUPPORTED as u8,
-    RequestTooLarge = coap_pdu_code_t::COAP_RESPONSE_CODE_REQUEST_TOO_LARGE as u8,
-    ServiceUnavailable = coap_pdu_code_t::COAP_RESPONSE_CODE_SERVICE_UNAVAILABLE as u8,
-    TooManyRequests = coap_pdu_code_t::COAP_RESPONSE_CODE_TOO_MANY_REQUESTS as u8,
-    Unauthorized = coap_pdu_code_t::COAP_RESPONSE_CODE_UNAUTHORIZED as u8,
-    Unprocessable = coap_pdu_code_t::COAP_RESPONSE_CODE_UNPROCESSABLE as u8,
-    UnsupportedContentFormat = coap_pdu_code_t::COAP_RESPONSE_CODE_UNSUPPORTED_CONTENT_FORMAT as u8,
-    Valid = coap_pdu_code_t::COAP_RESPONSE_CODE_VALID as u8,
+    Content,
+    BadGateway,
+    Continue,
+    Conflict,
+    BadRequest,
+    BadOption,
+    Changed,
+    Created,
+    Deleted,
+    Forbidden,
+    GatewayTimeout,
+    HopLimitReached,
+    Incomplete,
+    InternalError,
+    NotAcceptable,
+    NotAllowed,
+    NotFound,
+    NotImplemented,
+    PreconditionFailed,
+    ProxyingNotSupported,
+    RequestTooLarge,
+    ServiceUnavailable,
+    TooManyRequests,
+    Unauthorized,
+    Unprocessable,
+    UnsupportedContentFormat,
+    Valid,
+    /// A response code that is not known to this library, represented by its numeric value
+    /// (`class << 5 | detail`, e.g., `0x80` for 4.00).
+    ///
+    /// This variant is only used for values that do not correspond to one of the other variants,
+    /// use [CoapResponseCode::from_raw_code()] to obtain the response code for a numeric value.
+    Other(u8),
 }
 
+/// All response codes that are known to this library, i.e., all variants of [CoapResponseCode]
+/// except for [CoapResponseCode::Other].
+const KNOWN_RESPONSE_CODES: [CoapResponseCode; 27] = [
+    CoapResponseCode::Content,
+    CoapResponseCode::BadGateway,
+    CoapResponseCode::Continue,
+    CoapResponseCode::Conflict,
+    CoapResponseCode::BadRequest,
+    CoapResponseCode::BadOption,
+    CoapResponseCode::Changed,
+    CoapResponseCode::Created,
+    CoapResponseCode::Deleted,
+    CoapResponseCode::Forbidden,
+    CoapResponseCode::GatewayTimeout,
+    CoapResponseCode::HopLimitReached,
+    CoapResponseCode::Incomplete,
+    CoapResponseCode::InternalError,
+    CoapResponseCode::NotAcceptable,
+    CoapResponseCode::NotAllowed,
+    CoapResponseCode::NotFound,
+    CoapResponseCode::NotImplemented,
+    CoapResponseCode::PreconditionFailed,
+    CoapResponseCode::ProxyingNotSupported,
+    CoapResponseCode::RequestTooLarge,
+    CoapResponseCode::ServiceUnavailable,
+    CoapResponseCode::TooManyRequests,
+    CoapResponseCode::Unauthorized,
+    CoapResponseCode::Unprocessable,
+    CoapResponseCode::UnsupportedContentFormat,
+    CoapResponseCode::Valid,
+];
+
 impl CoapResponseCode {
+    /// Returns the response code for the given numeric value (`class << 5 | detail`).
+    ///
+    /// Values in one of the response classes (2.xx to 5.xx) that are not known to this library
+    /// are represented as [CoapResponseCode::Other], None is returned for values of other classes
+    /// (i.e., request codes, the empty code and signaling codes).
+    pub fn from_raw_code(code: u8) -> Option<CoapResponseCode> {
+        if !(2..=5).contains(&(code >> 5)) {
+            return None;
+        }
+        Some(
+            KNOWN_RESPONSE_CODES
+                .into_iter()
+                .find(|known| known.to_raw_code() == code)
+                .unwrap_or(CoapResponseCode::Other(code)),
+        )
+    }
+
+    /// Returns the numeric value of this response code (`class << 5 | detail`).
+    pub fn to_raw_code(self) -> u8 {
+        match self {
+            CoapResponseCode::Other(code) => code,
+            known => known.to_raw_pdu_code() as u8,
+        }
+    }
+
+    /// Returns the class of this response code, e.g. `4` for 4.04 (Not Found).
+    pub fn class(self) -> u8 {
+        self.to_raw_code() >> 5
+    }
+
+    /// Returns the detail of this response code, e.g. `4` for 4.04 (Not Found).
+    pub fn detail(self) -> u8 {
+        self.to_raw_code() & 0x1F
+    }
+
+    /// Returns whether this response code indicates success (2.xx).
+    pub fn is_success(self) -> bool {
+        self.class() == 2
+    }
+
+    /// Returns whether this response code indicates a client error (4.xx).
+    pub fn is_client_error(self) -> bool {
+        self.class() == 4
+    }
+
+    /// Returns whether this response code indicates a server error (5.xx).
+    pub fn is_server_error(self) -> bool {
+        self.class() == 5
+    }
+
+    /// Returns whether this response code indicates a client or server error (4.xx or 5.xx).
+    pub fn is_error(self) -> bool {
+        self.is_client_error() || self.is_server_error()
+    }
+
     /// Returns the raw [coap_pdu_code_t](coap_pdu_code_t) corresponding to this
     /// request code.
+    ///
+    /// # Panics
+    /// Panics for [CoapResponseCode::Other], as libcoap only defines values for known response
+    /// codes. Use [CoapResponseCode::to_raw_code()] to obtain the numeric value instead.
     pub fn to_raw_pdu_code(self) -> coap_pdu_code_t {
         match self {
             CoapResponseCode::Content => coap_pdu_code_t::COAP_RESPONSE_CODE_CONTENT,
@@ -462,6 +739,7 @@ impl CoapResponseCode {
                 coap_pdu_code_t::COAP_RESPONSE_CODE_UNSUPPORTED_CONTENT_FORMAT
             },
             CoapResponseCode::Valid => coap_pdu_code_t::COAP_RESPONSE_CODE_VALID,
+            CoapResponseCode::Other(code) => panic!("response code {:#04x} is not known to libcoap", code),
         }
     }
 }
@@ -469,7 +747,7 @@ impl CoapResponseCode {
 impl Display for CoapResponseCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let response_phrase = unsafe {
-            let raw_phrase = coap_response_phrase(*self as u8);
+            let raw_phrase = coap_response_phrase(self.to_raw_code());
             if raw_phrase.is_null() {
                 "unknown response code"
             } else {
@@ -487,7 +765,7 @@ impl TryFrom<coap_pdu_code_t> for CoapResponseCode {
     type Error = MessageCodeError;
 
     fn try_from(value: coap_pdu_code_t) -> Result<Self, Self::Error> {
-        <CoapResponseCode as FromPrimitive>::from_u32(value as u32).ok_or(MessageCodeError::NotAResponseCode)
+        CoapResponseCode::from_raw_code(value as u8).ok_or(MessageCodeError::NotAResponseCode)
     }
 }
 
@@ -0,0 +1,570 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * proxy.rs - Reverse proxy forwarding requests to upstream CoAP servers.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing a reverse proxy that maps a local resource tree onto upstream CoAP servers.
+//!
+//! A [CoapReverseProxyResource] is added to a context using
+//! [CoapContext::add_reverse_proxy()](crate::CoapContext::add_reverse_proxy). It receives all
+//! requests whose path starts with its prefix (e.g., `devices`), and uses the path segment
+//! following the prefix to look up the upstream server (e.g., the device ID in
+//! `/devices/{id}/...`). The request is then forwarded to the upstream server, with the prefix
+//! and the ID being replaced by the path of the upstream URI.
+//! For each upstream server (identified by the scheme, host and port of its URI), a
+//! [CoapClientSession] is created when the first request is forwarded to it, which is then reused
+//! for subsequent requests.
+//!
+//! Requests are forwarded without blocking the context: confirmable requests are acknowledged
+//! right away, and the response of the upstream server is sent as a separate response once it
+//! has been received during [CoapContext::do_io()](crate::CoapContext::do_io). Upstream requests
+//! that are not answered within the timeout of the proxy (see
+//! [CoapReverseProxyResource::timeout()]) are answered with 5.04 (Gateway Timeout), other
+//! failures (e.g., failed DTLS handshakes or sessions that could not be created) with 5.02 (Bad
+//! Gateway). Both responses contain a diagnostic payload describing the failure.
+//!
+//! The Hop-Limit option ([RFC 8768](https://datatracker.ietf.org/doc/html/rfc8768)) of forwarded
+//! requests is decremented (or set to [DEFAULT_HOP_LIMIT] if it is missing), and requests that
+//! have reached their hop limit are answered with 5.08 (Hop Limit Reached), so that forwarding
+//! loops between proxies terminate.
+//!
+//! Block-wise transfers are handled by libcoap on both sides of the proxy: as contexts deliver
+//! complete bodies to request handlers and clients, bodies are reassembled before they are
+//! forwarded, but they are handed to libcoap without being copied again, which then transfers
+//! them block-wise if required.
+//!
+//! # Limitations
+//! - The proxy uses libcoap's handler for unknown resources, i.e., it only receives requests for
+//!   paths that no other resource of the context is responsible for, and there can only be one
+//!   reverse proxy per context.
+//! - Observe options are removed from forwarded requests (libcoap does not support observing the
+//!   handler for unknown resources).
+//! - Upstream hosts are resolved while handling the first request for them, which blocks if the
+//!   host is not an IP address.
+//!
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use libcoap_rs::{proxy::CoapReverseProxyResource, CoapContext};
+//!
+//! let mut context = CoapContext::new().unwrap();
+//! context.add_endpoint_udp("[::]:5683".parse().unwrap()).unwrap();
+//! // Forward requests for /devices/{id}/... to coap://[fd00::{id}]/api/...
+//! let proxy = CoapReverseProxyResource::new("devices", |id| format!("coap://[fd00::{id}]/api").parse().ok())
+//!     .timeout(Duration::from_secs(5));
+//! context.add_reverse_proxy(proxy).unwrap();
+//! loop {
+//!     context.do_io(None).unwrap();
+//! }
+//! ```
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    rc::Rc,
+    time::Duration,
+};
+
+use libcoap_sys::{
+    coap_delete_pdu, coap_resource_t, coap_session_get_context, coap_session_reference, coap_session_release,
+    coap_session_t,
+};
+
+#[cfg(dtls)]
+use crate::crypto::ClientCryptoContext;
+use crate::{
+    context::CoapContext,
+    error::{MessageConversionError, RequestPollError},
+    message::{request::CoapRequest, response::CoapResponse, CoapMessage, CoapMessageCommon, CoapOption},
+    protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode, UriPort, DEFAULT_HOP_LIMIT},
+    resource::{CoapRequestHandler, CoapResource, UntypedCoapResource},
+    session::{
+        is_wrapped_raw_session, CoapClientSession, CoapRequestHandle, CoapServerSession, CoapSessionCommon,
+        HandledRequest,
+    },
+    types::{CoapUri, CoapUriScheme},
+};
+
+/// Default timeout for requests forwarded to upstream servers.
+const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request methods that are forwarded by a reverse proxy.
+const FORWARDED_METHODS: [CoapRequestCode; 7] = [
+    CoapRequestCode::Get,
+    CoapRequestCode::Put,
+    CoapRequestCode::Delete,
+    CoapRequestCode::Post,
+    CoapRequestCode::Fetch,
+    CoapRequestCode::IPatch,
+    CoapRequestCode::Patch,
+];
+
+/// Function that returns the URI of the upstream server for the given path segment.
+type UpstreamResolverFn = dyn FnMut(&str) -> Option<CoapUri>;
+
+/// Identifies an upstream server (by its scheme, host and port).
+type UpstreamKey = (CoapUriScheme, String, UriPort);
+
+/// Resource that forwards requests for a local resource tree to upstream CoAP servers, see the
+/// [module-level documentation](crate::proxy).
+pub struct CoapReverseProxyResource {
+    prefix: Vec<String>,
+    resolver: Box<UpstreamResolverFn>,
+    timeout: Duration,
+    #[cfg(dtls)]
+    crypto_ctx: Option<ClientCryptoContext<'static>>,
+}
+
+impl CoapReverseProxyResource {
+    /// Creates a reverse proxy for the given path prefix (e.g., `devices` or `api/devices`).
+    ///
+    /// For each request whose path starts with the prefix, `resolver` is called with the path
+    /// segment following the prefix and returns the URI of the upstream server the request should
+    /// be forwarded to (or None if the request should be answered with 4.04 (Not Found)).
+    /// The remaining path segments of the request are appended to the path of the returned URI,
+    /// e.g., if `resolver` returns `coap://[fd00::1]/api` for `/devices/1/temp`, the request is
+    /// forwarded to `coap://[fd00::1]/api/temp`. The query of the request is forwarded as-is.
+    pub fn new<F: 'static + FnMut(&str) -> Option<CoapUri>>(prefix: &str, resolver: F) -> CoapReverseProxyResource {
+        CoapReverseProxyResource {
+            prefix: prefix
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string)
+                .collect(),
+            resolver: Box::new(resolver),
+            timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            #[cfg(dtls)]
+            crypto_ctx: None,
+        }
+    }
+
+    /// Sets the time after which requests to an upstream server fail if no response has been
+    /// received (ten seconds by default).
+    ///
+    /// The timeout also applies to establishing the session with an upstream server (including
+    /// the DTLS handshake), so that unreachable servers do not hold up requests indefinitely.
+    pub fn timeout(mut self, timeout: Duration) -> CoapReverseProxyResource {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the crypto context used for sessions with upstream servers with `coaps` URIs.
+    ///
+    /// The same credentials are used for all upstream servers. Requests for `coaps` upstream
+    /// servers are answered with 5.02 (Bad Gateway) if no crypto context is set.
+    #[cfg(dtls)]
+    pub fn crypto_context(mut self, crypto_ctx: impl Into<ClientCryptoContext<'static>>) -> CoapReverseProxyResource {
+        self.crypto_ctx = Some(crypto_ctx.into());
+        self
+    }
+
+    /// Creates the resource for libcoap's handler for unknown resources that forwards requests
+    /// using the returned state.
+    pub(crate) fn into_resource(
+        self,
+    ) -> (
+        CoapResource<Rc<RefCell<ReverseProxyState>>>,
+        Rc<RefCell<ReverseProxyState>>,
+    ) {
+        let state = Rc::new(RefCell::new(ReverseProxyState {
+            proxy: self,
+            raw_resource: std::ptr::null_mut(),
+            upstreams: HashMap::new(),
+            forwarded: Vec::new(),
+        }));
+        let mut resource = CoapResource::new_unknown(Rc::clone(&state));
+        for code in FORWARDED_METHODS {
+            resource.set_method_handler(
+                code,
+                Some(CoapRequestHandler::new_resource_ref(
+                    |resource: &CoapResource<Rc<RefCell<ReverseProxyState>>>, session, request, response| {
+                        let state = Rc::clone(&resource.user_data());
+                        state.borrow_mut().forward_request(session, request, response);
+                    },
+                )),
+            );
+        }
+        // SAFETY: The raw resource is only used to send responses that do not fit into a single
+        // PDU, it is valid for as long as the state is used by the context.
+        state.borrow_mut().raw_resource = unsafe { resource.raw_resource() };
+        (resource, state)
+    }
+}
+
+impl Debug for CoapReverseProxyResource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapReverseProxyResource")
+            .field("prefix", &self.prefix)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// State of a reverse proxy that has been added to a context.
+///
+/// This state is shared with the [CoapContext], which sends the responses of upstream servers
+/// while performing IO (see [ReverseProxyState::forward_responses()]).
+pub(crate) struct ReverseProxyState {
+    proxy: CoapReverseProxyResource,
+    raw_resource: *mut coap_resource_t,
+    /// Sessions with upstream servers, which are created when the first request is forwarded to
+    /// them.
+    upstreams: HashMap<UpstreamKey, CoapClientSession<'static>>,
+    /// Requests that were forwarded and are waiting for the response of their upstream server.
+    forwarded: Vec<ForwardedRequest>,
+}
+
+impl ReverseProxyState {
+    /// Forwards the given request received by the proxy resource to its upstream server.
+    fn forward_request(&mut self, session: &mut CoapServerSession, request: &CoapRequest, mut response: CoapResponse) {
+        let segments: Vec<Cow<str>> = request.uri().path_segments().collect();
+        let (local_base, path) = match segments.get(self.proxy.prefix.len()..) {
+            Some([_, path @ ..]) if segments.iter().zip(&self.proxy.prefix).all(|(a, b)| a == b) => {
+                (&segments[..=self.proxy.prefix.len()], path)
+            },
+            _ => {
+                respond_with_error(session, response, CoapResponseCode::NotFound, None);
+                return;
+            },
+        };
+        // Each proxy decrements the Hop-Limit, so that forwarding loops terminate (RFC 8768,
+        // Section 3).
+        let hop_limit = match request.hop_limit() {
+            Some(hop_limit) if hop_limit <= 1 => {
+                let diagnostic = Some("hop limit reached".to_string());
+                respond_with_error(session, response, CoapResponseCode::HopLimitReached, diagnostic);
+                return;
+            },
+            Some(hop_limit) => hop_limit - 1,
+            None => DEFAULT_HOP_LIMIT,
+        };
+        let Some(upstream_uri) = (self.proxy.resolver)(&local_base[self.proxy.prefix.len()]) else {
+            respond_with_error(session, response, CoapResponseCode::NotFound, None);
+            return;
+        };
+        let (key, upstream) = match self.upstream_session(session, &upstream_uri) {
+            Ok(upstream) => upstream,
+            Err(diagnostic) => {
+                respond_with_error(session, response, CoapResponseCode::BadGateway, Some(diagnostic));
+                return;
+            },
+        };
+        let upstream_base: Vec<String> = upstream_uri.path_segments().map(Cow::into_owned).collect();
+        let handle = match self.upstream_request(request, &upstream_base, path, &upstream) {
+            Ok(mut upstream_request) => {
+                upstream_request
+                    .set_hop_limit(Some(hop_limit))
+                    .expect("decremented hop limit is within the allowed range");
+                upstream.send_request(upstream_request)
+            },
+            Err(e) => Err(e),
+        };
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => {
+                respond_with_error(session, response, CoapResponseCode::BadGateway, Some(e.to_string()));
+                return;
+            },
+        };
+        // The body is not required to send the response.
+        let mut request = request.clone();
+        request.set_data(None::<Box<[u8]>>);
+        // SAFETY: The session pointer is valid, the reference is released once the forwarded
+        // request is dropped.
+        let raw_session = unsafe { coap_session_reference(session.raw_session_mut()) };
+        self.forwarded.push(ForwardedRequest {
+            upstream_key: key,
+            upstream,
+            handle,
+            raw_session,
+            request,
+            local_base: local_base.iter().map(|v| v.to_string()).collect(),
+            upstream_base,
+        });
+        // Not sending a response here causes libcoap to send an empty ACK, the response is sent
+        // separately once it has been received from the upstream server.
+    }
+
+    /// Returns the session for the upstream server referred to by the given URI, creating it if
+    /// required.
+    ///
+    /// Returns a diagnostic message if no session could be created.
+    fn upstream_session(
+        &mut self,
+        session: &CoapServerSession,
+        uri: &CoapUri,
+    ) -> Result<(UpstreamKey, CoapClientSession<'static>), String> {
+        let (Some(scheme), Some(host), Some(port)) = (uri.scheme(), uri.decoded_host(), uri.port_or_default()) else {
+            return Err("upstream URI does not contain a host".to_string());
+        };
+        let key = (scheme, host.into_owned(), port);
+        if let Some(upstream) = self.upstreams.get(&key) {
+            return Ok((key, upstream.clone()));
+        }
+        // SAFETY: The session belongs to a context that is currently performing IO, so the raw
+        // context is valid.
+        let mut context: CoapContext<'static> =
+            unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+        let upstream = match scheme {
+            #[cfg(dtls)]
+            CoapUriScheme::Coaps => {
+                let Some(crypto_ctx) = self.proxy.crypto_ctx.clone() else {
+                    return Err("no credentials configured for coaps upstream servers".to_string());
+                };
+                CoapClientSession::connect_dtls_uri(&mut context, uri, crypto_ctx)
+            },
+            _ => CoapClientSession::connect_uri(&mut context, uri),
+        }
+        .map_err(|e| e.to_string())?;
+        context.set_handshake_deadline(&upstream, self.proxy.timeout);
+        self.upstreams.insert(key.clone(), upstream.clone());
+        Ok((key, upstream))
+    }
+
+    /// Creates the request that is sent to the upstream server for the given request, replacing
+    /// the path of the request with the given upstream base path and remaining path segments.
+    fn upstream_request(
+        &self,
+        request: &CoapRequest,
+        upstream_base: &[String],
+        path: &[Cow<str>],
+        upstream: &CoapClientSession<'static>,
+    ) -> Result<CoapRequest, MessageConversionError> {
+        let mut message = request.clone().into_message();
+        let options: Vec<CoapOption> = message
+            .options_iter()
+            .filter(|option| {
+                !matches!(
+                    option,
+                    CoapOption::UriHost(_)
+                        | CoapOption::UriPort(_)
+                        | CoapOption::UriPath(_)
+                        | CoapOption::ProxyUri(_)
+                        | CoapOption::ProxyScheme(_)
+                        | CoapOption::Observe(_)
+                ) && !is_hop_by_hop_option(option)
+            })
+            .cloned()
+            .collect();
+        message.clear_options();
+        for segment in upstream_base {
+            message.add_option(CoapOption::UriPath(segment.clone()));
+        }
+        for segment in path {
+            message.add_option(CoapOption::UriPath(segment.to_string()));
+        }
+        message.add_options(options.into_iter().collect());
+        // Requests are sent reliably to the upstream server, as the proxy has acknowledged them.
+        message.set_type_(CoapMessageType::Con);
+        message.set_mid(None);
+        message.set_token(None::<Box<[u8]>>);
+        let mut upstream_request = CoapRequest::from_message(message, upstream)?;
+        upstream_request.set_timeout(Some(self.proxy.timeout));
+        Ok(upstream_request)
+    }
+
+    /// Sends the responses of upstream servers that have been received since the last call to
+    /// this function, and answers forwarded requests whose upstream request has failed.
+    ///
+    /// This function is called by the [CoapContext] after processing IO.
+    pub(crate) fn forward_responses(&mut self) {
+        let mut forwarded = std::mem::take(&mut self.forwarded);
+        forwarded.retain(|forwarded| {
+            let result = match forwarded.upstream.try_poll_handle(&forwarded.handle) {
+                Ok(mut responses) => match responses.next() {
+                    Some(response) => Ok(response),
+                    None => return true,
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(response) => {
+                    let message = forwarded.downstream_message(response);
+                    // SAFETY: No handler of the downstream session is currently called and we hold
+                    // a reference to the raw session.
+                    unsafe { self.send_downstream(forwarded, message) };
+                },
+                Err(e) => {
+                    if e == RequestPollError::SessionFailed {
+                        // Create a new session for the next request to the upstream server.
+                        self.upstreams.remove(&forwarded.upstream_key);
+                    }
+                    let code = match e {
+                        RequestPollError::TimedOut => CoapResponseCode::GatewayTimeout,
+                        _ => CoapResponseCode::BadGateway,
+                    };
+                    let mut message = CoapMessage::new(forwarded.response_type(), CoapMessageCode::Response(code));
+                    message.set_token(forwarded.request.token().map(Box::<[u8]>::from));
+                    message.set_data(Some(e.to_string().into_bytes()));
+                    // SAFETY: See above.
+                    unsafe { self.send_downstream(forwarded, message) };
+                },
+            }
+            forwarded.upstream.remove_handle(forwarded.handle.clone());
+            false
+        });
+        // The upstream sessions do not call the proxy while being polled, so no requests can have
+        // been forwarded in the meantime.
+        self.forwarded = forwarded;
+    }
+
+    /// Sends the given response to the client of the given forwarded request.
+    ///
+    /// Responses that do not fit into a single PDU are handed to libcoap as a large response to a
+    /// copy of the original request, so that libcoap transfers them block-wise.
+    ///
+    /// # Safety
+    /// No request handler of the downstream session may currently be called, the raw resource of
+    /// this proxy must still be valid.
+    unsafe fn send_downstream(&self, forwarded: &ForwardedRequest, message: CoapMessage) {
+        // SAFETY: We hold a reference to the raw session, so it is still valid.
+        if !is_wrapped_raw_session(forwarded.raw_session) {
+            // The session has been disconnected in the meantime.
+            return;
+        }
+        let session = CoapServerSession::from_raw(forwarded.raw_session);
+        if !message.may_exceed_pdu_size(session.max_pdu_size()) {
+            // If sending fails, the client will retransmit the request, which is then forwarded
+            // again.
+            let _ = session.send(message);
+            return;
+        }
+        let Ok(raw_request) = forwarded.request.clone().into_message().into_raw_pdu(&session) else {
+            return;
+        };
+        let handled = HandledRequest {
+            raw_resource: self.raw_resource,
+            raw_request,
+            raw_query: std::ptr::null(),
+            // Replaced by the PDU the response is sent in.
+            raw_response: std::ptr::null_mut(),
+        };
+        let _ = message.send_as_large_separate_response(forwarded.raw_session, &handled);
+        coap_delete_pdu(raw_request);
+    }
+
+    /// Drops all forwarded requests and sessions with upstream servers.
+    ///
+    /// This function is called by the [CoapContext] before it is dropped, so that the sessions can
+    /// be released while the raw context still exists.
+    pub(crate) fn clear(&mut self) {
+        self.forwarded.clear();
+        self.upstreams.clear();
+    }
+}
+
+impl Debug for ReverseProxyState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReverseProxyState")
+            .field("proxy", &self.proxy)
+            .field("upstreams", &self.upstreams.keys())
+            .field("forwarded", &self.forwarded)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A request that was forwarded to an upstream server and waits for its response.
+#[derive(Debug)]
+struct ForwardedRequest {
+    upstream_key: UpstreamKey,
+    upstream: CoapClientSession<'static>,
+    handle: CoapRequestHandle,
+    /// The server-side session the request was received on.
+    raw_session: *mut coap_session_t,
+    /// The request received from the client (without its body).
+    request: CoapRequest,
+    /// Path prefix of the request that refers to the upstream server (including the ID).
+    local_base: Vec<String>,
+    /// Path of the URI of the upstream server.
+    upstream_base: Vec<String>,
+}
+
+impl ForwardedRequest {
+    /// Returns the message type of the (separate) response to the client.
+    fn response_type(&self) -> CoapMessageType {
+        match self.request.type_() {
+            CoapMessageType::Con => CoapMessageType::Con,
+            _ => CoapMessageType::Non,
+        }
+    }
+
+    /// Converts the given response of the upstream server into the response to the client.
+    ///
+    /// Location-Path options referring to the upstream server are mapped to the local resource
+    /// tree of the proxy.
+    fn downstream_message(&self, response: CoapResponse) -> CoapMessage {
+        let mut message = response.into_message();
+        let mut location_path = Vec::new();
+        let mut options = Vec::new();
+        for option in message.options_iter() {
+            match option {
+                CoapOption::LocationPath(segment) => location_path.push(segment.clone()),
+                CoapOption::Observe(_) => {},
+                option if is_hop_by_hop_option(option) => {},
+                option => options.push(option.clone()),
+            }
+        }
+        if !location_path.is_empty() {
+            let relative_path = location_path
+                .strip_prefix(self.upstream_base.as_slice())
+                .unwrap_or(&location_path);
+            location_path = self.local_base.iter().chain(relative_path).cloned().collect();
+        }
+        message.clear_options();
+        for segment in location_path {
+            message.add_option(CoapOption::LocationPath(segment));
+        }
+        message.add_options(options.into_iter().collect());
+        message.set_type_(self.response_type());
+        message.set_mid(None);
+        message.set_token(self.request.token().map(Box::<[u8]>::from));
+        message
+    }
+}
+
+impl Drop for ForwardedRequest {
+    fn drop(&mut self) {
+        // SAFETY: We increased the reference count of the session when forwarding the request.
+        unsafe { coap_session_release(self.raw_session) }
+    }
+}
+
+/// Returns whether the given option only applies to a single hop and must therefore not be
+/// forwarded by the proxy (block-wise transfers and security are handled by libcoap for each
+/// hop, the Hop-Limit is set by the proxy itself).
+fn is_hop_by_hop_option(option: &CoapOption) -> bool {
+    matches!(
+        option,
+        CoapOption::Block1(_)
+            | CoapOption::Block2(_)
+            | CoapOption::QBlock1(_)
+            | CoapOption::QBlock2(_)
+            | CoapOption::Size1(_)
+            | CoapOption::Size2(_)
+            | CoapOption::Echo(_)
+            | CoapOption::RTag(_)
+            | CoapOption::Oscore(_)
+            | CoapOption::NoResponse(_)
+            | CoapOption::HopLimit(_)
+    )
+}
+
+/// Answers the given request with an error response, with the given diagnostic payload (if any).
+fn respond_with_error(
+    session: &mut CoapServerSession,
+    mut response: CoapResponse,
+    code: CoapResponseCode,
+    diagnostic: Option<String>,
+) {
+    response.set_code(CoapMessageCode::Response(code));
+    response.set_data(diagnostic.map(String::into_bytes));
+    // If sending fails, libcoap will answer the request with an empty ACK instead.
+    let _ = session.send(response);
+}
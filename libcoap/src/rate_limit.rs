@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * rate_limit.rs - Per-peer rate limiting of received requests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing types for limiting the rate of requests a server accepts from each peer.
+//!
+//! If a rate limit is set for a context (see
+//! [CoapContext::set_rate_limit()](crate::CoapContext::set_rate_limit)), each peer (identified by
+//! its IP address) is assigned a token bucket that holds up to [CoapRateLimit::burst] tokens and
+//! is refilled at [CoapRateLimit::requests_per_second]. Every request that is dispatched to a
+//! resource consumes one token. Requests that arrive while the bucket of their peer is empty are
+//! throttled: they are not passed to the resource (or its access hook) and are handled according
+//! to the [CoapRateLimitAction] for their message type instead.
+//!
+//! Only requests received from peers are subject to the rate limit. Responses to requests sent by
+//! the context itself never pass through the request dispatch, and notifications generated for
+//! existing observations (see [CoapResource::notify_observers()](crate::CoapResource::notify_observers))
+//! do not consume tokens.
+//!
+//! Throttled requests are counted in [CoapStats::requests_throttled](crate::CoapStats::requests_throttled)
+//! and reported to the hook set using
+//! [CoapContext::set_rate_limit_hook()](crate::CoapContext::set_rate_limit_hook).
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::protocol::{CoapMessageType, CoapRequestCode};
+
+/// Default maximum number of peers whose token buckets are tracked, see
+/// [CoapRateLimit::max_peers].
+const DEFAULT_MAX_PEERS: usize = 1024;
+
+/// Rate limit applied to the requests of each peer, see
+/// [CoapContext::set_rate_limit()](crate::CoapContext::set_rate_limit).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoapRateLimit {
+    /// Number of requests per second a peer may send on average (the rate at which its token
+    /// bucket is refilled).
+    ///
+    /// Non-positive (or non-finite) values never refill the bucket.
+    pub requests_per_second: f64,
+    /// Number of requests a peer may send in a burst (the capacity of its token bucket).
+    ///
+    /// Buckets always hold at least one token, so a value of 0 is treated like 1.
+    pub burst: u32,
+    /// Maximum number of peers whose token buckets are tracked (1024 by default).
+    ///
+    /// If a request is received from a new peer while this many peers are tracked, the bucket of
+    /// the least recently seen peer is discarded, which allows that peer to send a full burst
+    /// again.
+    pub max_peers: usize,
+    /// Handling of throttled confirmable requests ([CoapRateLimitAction::Reject] by default).
+    pub confirmable_action: CoapRateLimitAction,
+    /// Handling of throttled non-confirmable requests ([CoapRateLimitAction::Drop] by default).
+    pub non_confirmable_action: CoapRateLimitAction,
+}
+
+impl CoapRateLimit {
+    /// Creates a rate limit with the given average rate and burst size, using the defaults for
+    /// all other settings.
+    pub fn new(requests_per_second: f64, burst: u32) -> CoapRateLimit {
+        CoapRateLimit {
+            requests_per_second,
+            burst,
+            max_peers: DEFAULT_MAX_PEERS,
+            confirmable_action: CoapRateLimitAction::Reject,
+            non_confirmable_action: CoapRateLimitAction::Drop,
+        }
+    }
+
+    /// Returns the action for throttled requests of the given message type.
+    fn action_for(&self, type_: CoapMessageType) -> CoapRateLimitAction {
+        match type_ {
+            CoapMessageType::Con => self.confirmable_action,
+            _ => self.non_confirmable_action,
+        }
+    }
+
+    /// Returns the rate at which buckets are refilled (in tokens per second), or None if they are
+    /// never refilled.
+    fn refill_rate(&self) -> Option<f64> {
+        (self.requests_per_second.is_finite() && self.requests_per_second > 0.0).then_some(self.requests_per_second)
+    }
+
+    /// Returns the capacity of the token buckets.
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+}
+
+/// Handling of requests that exceed the rate limit of their peer, see [CoapRateLimit].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CoapRateLimitAction {
+    /// The request is not answered.
+    ///
+    /// Confirmable requests are still acknowledged with an empty ACK by libcoap, so the client
+    /// stops retransmitting the request (but keeps waiting for a separate response until its
+    /// request times out).
+    Drop,
+    /// The request is answered with 4.29 (Too Many Requests), with a Max-Age option indicating
+    /// the number of seconds after which the peer may send its next request
+    /// ([RFC 8516](https://datatracker.ietf.org/doc/html/rfc8516)).
+    Reject,
+}
+
+/// Information on a throttled request, passed to the hook set using
+/// [CoapContext::set_rate_limit_hook()](crate::CoapContext::set_rate_limit_hook).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoapThrottledRequest {
+    /// IP address of the peer that sent the request.
+    pub peer: IpAddr,
+    /// Method of the request.
+    pub code: CoapRequestCode,
+    /// Message type of the request.
+    pub type_: CoapMessageType,
+    /// How the request is handled.
+    pub action: CoapRateLimitAction,
+    /// Time after which the peer may send its next request.
+    pub retry_after: Duration,
+}
+
+/// Token bucket of a single peer.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    /// Time at which `tokens` was last updated.
+    updated: Instant,
+    /// Value of [RateLimiter::use_counter] when the peer was last seen, used to find the least
+    /// recently seen peer.
+    last_used: u64,
+}
+
+/// Token buckets of the peers of a context.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: CoapRateLimit,
+    buckets: HashMap<IpAddr, TokenBucket>,
+    use_counter: u64,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: CoapRateLimit) -> RateLimiter {
+        RateLimiter {
+            limit,
+            buckets: HashMap::new(),
+            use_counter: 0,
+        }
+    }
+
+    /// Returns the rate limit applied by this limiter.
+    pub(crate) fn limit(&self) -> CoapRateLimit {
+        self.limit
+    }
+
+    /// Takes a token from the bucket of the given peer at the current time `now`.
+    ///
+    /// Returns None if a token was available, or the action for the request (of the given message
+    /// type) and the time until the next token is available if the request is throttled.
+    pub(crate) fn take_token(
+        &mut self,
+        peer: IpAddr,
+        type_: CoapMessageType,
+        now: Instant,
+    ) -> Option<(CoapRateLimitAction, Duration)> {
+        self.use_counter += 1;
+        if !self.buckets.contains_key(&peer) && self.buckets.len() >= self.limit.max_peers.max(1) {
+            let least_recent = self
+                .buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.last_used)
+                .map(|(addr, _)| *addr);
+            if let Some(addr) = least_recent {
+                self.buckets.remove(&addr);
+            }
+        }
+        let capacity = self.limit.capacity();
+        let rate = self.limit.refill_rate();
+        let bucket = self.buckets.entry(peer).or_insert(TokenBucket {
+            tokens: capacity,
+            updated: now,
+            last_used: 0,
+        });
+        bucket.last_used = self.use_counter;
+        if let Some(rate) = rate {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        }
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        let retry_after = rate
+            .and_then(|rate| Duration::try_from_secs_f64((1.0 - bucket.tokens) / rate).ok())
+            .unwrap_or(Duration::MAX);
+        Some((self.limit.action_for(type_), retry_after))
+    }
+}
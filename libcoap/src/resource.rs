@@ -11,31 +11,79 @@
 
 use std::{
     any::Any,
-    cell::Ref,
-    cell::RefMut,
+    cell::{Ref, RefCell, RefMut},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Debug, Formatter},
     marker::PhantomData,
+    net::SocketAddr,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use libc::c_int;
+use bitflags::bitflags;
+use libc::{c_int, c_uint};
 
 use libcoap_sys::{
-    coap_delete_resource, coap_new_str_const, coap_pdu_t, coap_register_request_handler, COAP_RESOURCE_FLAGS_NOTIFY_CON,
-    COAP_RESOURCE_FLAGS_NOTIFY_NON, COAP_RESOURCE_FLAGS_RELEASE_URI, coap_resource_get_uri_path, coap_resource_get_userdata,
-    coap_resource_init, coap_resource_notify_observers, coap_resource_set_get_observable, coap_resource_set_mode, coap_resource_set_userdata, coap_resource_t,
-    coap_send_rst, coap_session_t, coap_string_t,
+    coap_add_attr, coap_add_option, coap_add_token, coap_delete_pdu, coap_delete_resource, coap_new_message_id,
+    coap_new_str_const, coap_pdu_code_t, coap_pdu_init, coap_pdu_t, coap_persist_set_observe_num,
+    coap_register_request_handler, coap_resource_get_uri_path, coap_resource_get_userdata, coap_resource_init,
+    coap_resource_notify_observers, coap_resource_set_get_observable, coap_resource_set_mode,
+    coap_resource_set_userdata, coap_resource_t, coap_resource_unknown_init2, coap_send_rst, coap_session_get_context,
+    coap_session_get_proto, coap_session_max_pdu_size, coap_session_reference, coap_session_release, coap_session_t,
+    coap_string_t, COAP_ATTR_FLAGS_RELEASE_NAME, COAP_ATTR_FLAGS_RELEASE_VALUE, COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY,
+    COAP_RESOURCE_FLAGS_HAS_MCAST_SUPPORT, COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_DELAYS,
+    COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_SUPPRESS_4_XX, COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_SUPPRESS_5_XX,
+    COAP_RESOURCE_FLAGS_LIB_ENA_MCAST_SUPPRESS_2_05, COAP_RESOURCE_FLAGS_LIB_ENA_MCAST_SUPPRESS_2_XX,
+    COAP_RESOURCE_FLAGS_NOTIFY_CON, COAP_RESOURCE_FLAGS_NOTIFY_NON, COAP_RESOURCE_FLAGS_NOTIFY_NON_ALWAYS,
+    COAP_RESOURCE_FLAGS_RELEASE_URI,
 };
 
-use crate::{error::MessageConversionError, message::CoapMessage, protocol::CoapRequestCode};
-use crate::context::ensure_coap_started;
-use crate::mem::{CoapFfiRcCell, DropInnerExclusively};
+use crate::{
+    access::{AccessHookHandle, CoapAccessDecision},
+    crypto::CoapCryptoSessionInfo,
+    echo::CoapEchoPolicy,
+    rate_limit::CoapRateLimitAction,
+    error::{MessageConversionError, ResourceCreationError, ResourceUserDataError},
+    limits::{check_raw_request, CoapInputLimits},
+    message::{CoapMessage, CoapPduView},
+    protocol::{CoapOptionNum, CoapOptionType, CoapRequestCode},
+    transport::CoapEndpointHandle,
+    types::{encode_var_len_u32, percent_decode, utf8_lossy, CoapMessageId},
+};
+use crate::context::{CoapContext, CoapContextShared};
+use crate::startup::ensure_coap_started;
+use crate::mem::{CoapFfiRcCell, CoapFfiWeakCell, DropInnerExclusively};
+use crate::message::coap_pdu_set_raw_code;
 use crate::message::CoapMessageCommon;
-use crate::message::request::CoapRequest;
+use crate::message::CoapPduDirection;
+use crate::message::representations::CoapRepresentations;
+use crate::message::request::{percent_encode, CoapRequest};
+use crate::message::upload::CoapBlock1Chunk;
 use crate::message::response::CoapResponse;
+use crate::protocol::CoapMatch;
+use crate::protocol::CoapNoResponse;
 use crate::protocol::CoapMessageCode;
 use crate::protocol::CoapMessageType;
+use crate::protocol::CoapResponseCode;
+use crate::protocol::ContentFormat;
+use crate::protocol::ETag;
+use crate::protocol::MaxAge;
+use crate::protocol::Observe;
+use crate::stats::{message_memory, CoapMemoryReport};
+use crate::session::{
+    count_dispatched_pdu, handled_response_observe, inspect_pdu, is_replaying_request, is_wrapped_raw_session,
+    raw_addr_remote, record_stats, refuses_requests, set_handled_request, set_replaying_request, set_response_stats,
+    set_suppressed_responses, truncated_datagram_len, update_addr_remote, HandledRequest,
+};
+#[cfg(feature = "async")]
+use crate::session::{finish_async_replay, is_async_replay};
 use crate::session::CoapServerSession;
+use crate::session::CoapSession;
 use crate::session::CoapSessionCommon;
+use crate::session::CoapSessionId;
+use crate::unwind::{catch_session_callback_panic, record_panic};
 
 // Trait aliases are experimental
 //trait CoapMethodHandlerFn<D> = FnMut(&D, &mut CoapSession, &CoapRequestMessage, &mut CoapResponseMessage);
@@ -57,11 +105,20 @@ macro_rules! resource_handler {
             query: *const coap_string_t,
             response_pdu: *mut coap_pdu_t,
         ) {
-            let handler_data =
-                prepare_resource_handler_data::<$t>(resource, session, incoming_pdu, query, response_pdu);
-            if let Ok((mut resource, mut session, incoming_pdu, outgoing_pdu)) = handler_data {
-                ($f::<D>)(&mut resource, &mut session, &incoming_pdu, outgoing_pdu)
-            }
+            // Panics of the handler itself are already handled by dispatch_request().
+            catch_session_callback_panic(session, (), || {
+                let handler_data =
+                    prepare_resource_handler_data::<$t>(resource, session, incoming_pdu, query, response_pdu);
+                if let Ok((mut resource, mut session, incoming_pdu, outgoing_pdu)) = handler_data {
+                    CoapResource::dispatch_request(
+                        &mut resource,
+                        &mut session,
+                        &incoming_pdu,
+                        outgoing_pdu,
+                        |resource, session, request, response| ($f::<D>)(resource, session, request, response),
+                    )
+                }
+            })
         }
         unsafe { CoapRequestHandler::<$t>::from_raw_handler(_coap_method_handler_wrapper::<$t>) }
     }};
@@ -75,24 +132,88 @@ macro_rules! resource_handler {
 /// This function is not intended for public use, the only reason it is public is that the
 /// [resource_handler!] macro requires this function.
 ///
+/// The payload of the returned request is borrowed from `raw_incoming_pdu` instead of being
+/// copied.
+///
 /// # Safety
 /// The provided pointers must all be valid and point to the appropriate data structures.
+/// The returned values must be passed to [CoapResource::dispatch_request()] while the raw request
+/// handler is called, and the returned request must be dropped before it returns.
 #[inline]
 #[doc(hidden)]
 pub unsafe fn prepare_resource_handler_data<'a, D: Any + ?Sized + Debug>(
     raw_resource: *mut coap_resource_t,
     raw_session: *mut coap_session_t,
     raw_incoming_pdu: *const coap_pdu_t,
-    _raw_query: *const coap_string_t,
+    raw_query: *const coap_string_t,
     raw_response_pdu: *mut coap_pdu_t,
 ) -> Result<(CoapResource<D>, CoapServerSession<'a>, CoapRequest, CoapResponse), MessageConversionError> {
+    if !is_wrapped_raw_session(raw_session) {
+        // The session has been disconnected and released, see CoapServerSession::disconnect().
+        // libcoap sends the error response and ends observations of the peer once a notification
+        // with an error code is generated.
+        let code = CoapResponseCode::ServiceUnavailable.to_raw_code();
+        coap_pdu_set_raw_code(raw_response_pdu, c_uint::from(code));
+        return Err(MessageConversionError::SessionDisconnected);
+    }
     let resource_tmp = CoapFfiRcCell::clone_raw_weak(coap_resource_get_userdata(raw_resource));
     let resource = CoapResource::from(resource_tmp);
     let session = CoapServerSession::from_raw(raw_session);
-    let request = CoapMessage::from_raw_pdu(raw_incoming_pdu).and_then(|v| CoapRequest::from_message(v, &session));
+    // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+    let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+    if let Some((old_addr, new_addr)) = update_addr_remote(&session) {
+        context.handle_address_changed(session.clone().into(), old_addr, new_addr);
+    }
+    inspect_pdu(&session, CoapPduDirection::Received, raw_incoming_pdu);
+    count_dispatched_pdu(&session);
+    // Limits are checked before the options of the request are copied.
+    let limits = resource.input_limits().unwrap_or_else(|| context.input_limits());
+    let proto = coap_session_get_proto(raw_session).into();
+    if let Some(len) = truncated_datagram_len(proto, raw_incoming_pdu) {
+        // No response is set, so libcoap acknowledges confirmable requests with an empty ACK.
+        context.handle_truncated_datagram(session.into(), len);
+        return Err(MessageConversionError::TruncatedDatagram(len));
+    }
+    if let Err(exceeded) = check_raw_request(&limits, raw_incoming_pdu, proto, resource.streams_block1()) {
+        let code = exceeded.response_code();
+        coap_pdu_set_raw_code(raw_response_pdu, c_uint::from(code.to_raw_code()));
+        if code == CoapResponseCode::RequestTooLarge {
+            // Size1 indicates the maximum size the server is able to handle (RFC 7959, Section 4).
+            let size1 = encode_var_len_u32(u32::try_from(exceeded.max).unwrap_or(u32::MAX));
+            coap_add_option(
+                raw_response_pdu,
+                CoapOptionType::Size1 as CoapOptionNum,
+                size1.len(),
+                size1.as_ptr(),
+            );
+        }
+        context.handle_input_limit_exceeded(session, &exceeded);
+        return Err(MessageConversionError::InputLimitExceeded(exceeded));
+    }
+    let request =
+        CoapMessage::from_raw_pdu_borrowed(raw_incoming_pdu).and_then(|v| CoapRequest::from_message(v, &session));
     let response = CoapMessage::from_raw_pdu(raw_response_pdu).and_then(CoapResponse::from_message);
     match (request, response) {
-        (Ok(request), Ok(response)) => Ok((resource, session, request, response)),
+        (Ok(mut request), Ok(response)) => {
+            #[cfg(feature = "async")]
+            request.set_async_replay(is_async_replay(&session, raw_incoming_pdu));
+            // Asynchronous requests that are passed to the handler again were not received again.
+            if !request.is_async_replay() {
+                let payload_len = request.data().map_or(0, |v| v.len());
+                record_stats(&session, |stats| stats.record_received(payload_len));
+                request.set_retransmission(context.track_request(&session, &request));
+            }
+            set_handled_request(
+                &session,
+                Some(HandledRequest {
+                    raw_resource,
+                    raw_request: raw_incoming_pdu,
+                    raw_query,
+                    raw_response: raw_response_pdu,
+                }),
+            );
+            Ok((resource, session, request, response))
+        },
         (v1, v2) => {
             coap_send_rst(raw_session, raw_incoming_pdu);
             Err(v1.and(v2).err().unwrap())
@@ -100,10 +221,982 @@ pub unsafe fn prepare_resource_handler_data<'a, D: Any + ?Sized + Debug>(
     }
 }
 
+bitflags! {
+    /// Flags that influence the behavior of a [CoapResource].
+    ///
+    /// Some of these flags cannot be changed after the resource has been created, which is why
+    /// they have to be provided to [CoapResource::new_with_flags()].
+    ///
+    /// See the [libcoap documentation](https://libcoap.net/doc/reference/4.3.5/group__resource.html)
+    /// on `coap_resource_init()` for more information on the individual flags.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct ResourceFlags: u32 {
+        /// Send observe notifications as confirmable messages.
+        const NOTIFY_CON = 1 << 0;
+        /// Send observe notifications as non-confirmable messages (libcoap's default).
+        ///
+        /// Note that libcoap will still send a confirmable notification every 24 hours as
+        /// required by RFC 7641, unless [ResourceFlags::NOTIFY_NON_ALWAYS] is set instead.
+        const NOTIFY_NON = 1 << 1;
+        /// Always send observe notifications as non-confirmable messages, even if RFC 7641
+        /// requires a confirmable message to be sent.
+        const NOTIFY_NON_ALWAYS = 1 << 2;
+        /// This resource accepts requests sent to a multicast address.
+        const HAS_MCAST_SUPPORT = 1 << 3;
+        /// Disable the random delay before responding to multicast requests.
+        const LIB_DIS_MCAST_DELAYS = 1 << 4;
+        /// Suppress 2.05 responses to multicast requests.
+        const LIB_ENA_MCAST_SUPPRESS_2_05 = 1 << 5;
+        /// Suppress all 2.xx responses to multicast requests.
+        const LIB_ENA_MCAST_SUPPRESS_2_XX = 1 << 6;
+        /// Do not suppress 4.xx responses to multicast requests.
+        const LIB_DIS_MCAST_SUPPRESS_4_XX = 1 << 7;
+        /// Do not suppress 5.xx responses to multicast requests.
+        const LIB_DIS_MCAST_SUPPRESS_5_XX = 1 << 8;
+        /// Pass each block of block-wise uploads (Block1) to the request handler as it arrives,
+        /// instead of reassembling the whole request body first (see the
+        /// [upload](crate::message::upload) module).
+        ///
+        /// Adding a resource with this flag to a context changes the block-wise transfer mode of
+        /// the context, which only applies to sessions created afterwards. Resources with this
+        /// flag should therefore be added before the context starts serving requests.
+        const STREAM_BLOCK1 = 1 << 9;
+    }
+}
+
+impl ResourceFlags {
+    /// Validates this set of flags and converts it into the format expected by libcoap's
+    /// `coap_resource_init()`.
+    ///
+    /// `COAP_RESOURCE_FLAGS_RELEASE_URI` is always set, as this library always allocates the URI
+    /// path string for the resource it creates. `COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY` is set
+    /// unless [ResourceFlags::STREAM_BLOCK1] is set, as the block-wise transfer mode of the
+    /// context may not reassemble request bodies (see [CoapContext::add_resource()](crate::CoapContext::add_resource)).
+    ///
+    /// # Errors
+    /// Returns [ResourceCreationError::ContradictoryFlags] if this set of flags contains flags that
+    /// cannot be combined (e.g., only one of the `NOTIFY_*` flags may be set).
+    pub(crate) fn to_raw_flags(self) -> Result<c_int, ResourceCreationError> {
+        let notify_flags = [
+            ResourceFlags::NOTIFY_CON,
+            ResourceFlags::NOTIFY_NON,
+            ResourceFlags::NOTIFY_NON_ALWAYS,
+        ];
+        let mut set_notify_flags = notify_flags.iter().filter(|f| self.contains(**f));
+        if let (Some(first), Some(second)) = (set_notify_flags.next(), set_notify_flags.next()) {
+            return Err(ResourceCreationError::ContradictoryFlags(*first, *second));
+        }
+        if self.contains(ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_05)
+            && self.contains(ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_XX)
+        {
+            // Suppressing all 2.xx responses already includes suppressing 2.05 responses.
+            return Err(ResourceCreationError::ContradictoryFlags(
+                ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_05,
+                ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_XX,
+            ));
+        }
+
+        let mut raw_flags = COAP_RESOURCE_FLAGS_RELEASE_URI;
+        if !self.contains(ResourceFlags::STREAM_BLOCK1) {
+            raw_flags |= COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY;
+        }
+        for (flag, raw_flag) in [
+            (ResourceFlags::NOTIFY_CON, COAP_RESOURCE_FLAGS_NOTIFY_CON),
+            (ResourceFlags::NOTIFY_NON, COAP_RESOURCE_FLAGS_NOTIFY_NON),
+            (ResourceFlags::NOTIFY_NON_ALWAYS, COAP_RESOURCE_FLAGS_NOTIFY_NON_ALWAYS),
+            (ResourceFlags::HAS_MCAST_SUPPORT, COAP_RESOURCE_FLAGS_HAS_MCAST_SUPPORT),
+            (
+                ResourceFlags::LIB_DIS_MCAST_DELAYS,
+                COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_DELAYS,
+            ),
+            (
+                ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_05,
+                COAP_RESOURCE_FLAGS_LIB_ENA_MCAST_SUPPRESS_2_05,
+            ),
+            (
+                ResourceFlags::LIB_ENA_MCAST_SUPPRESS_2_XX,
+                COAP_RESOURCE_FLAGS_LIB_ENA_MCAST_SUPPRESS_2_XX,
+            ),
+            (
+                ResourceFlags::LIB_DIS_MCAST_SUPPRESS_4_XX,
+                COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_SUPPRESS_4_XX,
+            ),
+            (
+                ResourceFlags::LIB_DIS_MCAST_SUPPRESS_5_XX,
+                COAP_RESOURCE_FLAGS_LIB_DIS_MCAST_SUPPRESS_5_XX,
+            ),
+        ] {
+            if self.contains(flag) {
+                raw_flags |= raw_flag;
+            }
+        }
+        // The raw flag constants are all small enough to fit into a c_int.
+        Ok(raw_flags as c_int)
+    }
+}
+
+/// URI path of a [CoapResource], consisting of a sequence of (percent-decoded) path segments.
+///
+/// Requests are matched to resources by comparing the values of their Uri-Path options to the
+/// segments of the path one by one, so segments may contain any character, including `/`.
+///
+/// Paths can be created from a sequence of segments (e.g., `&["config", "wifi/main"]` for a path
+/// whose second segment contains a slash) or parsed from their textual representation, in which
+/// segments are separated by `/` and percent-encoded (e.g., `"config/wifi%2Fmain"` for the same
+/// path). Converting a string slice into a path parses it (see [CoapResourcePath::parse()]), and
+/// the [Display](std::fmt::Display) implementation produces the textual representation used
+/// for links to the resource (e.g., in `/.well-known/core`).
+///
+/// The root resource has an empty path (i.e., no segments), which is represented by `""`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CoapResourcePath {
+    segments: Vec<String>,
+}
+
+impl CoapResourcePath {
+    /// Creates a path consisting of the given (decoded) segments.
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(segments: I) -> CoapResourcePath {
+        CoapResourcePath {
+            segments: segments.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Parses the textual representation of a path, i.e., `/`-separated segments that are
+    /// percent-encoded.
+    ///
+    /// An empty string refers to the root resource, a leading `/` is ignored. Invalid
+    /// percent-encoded sequences are kept as-is, invalid UTF-8 sequences in decoded segments are
+    /// replaced with U+FFFD.
+    pub fn parse(path: &str) -> CoapResourcePath {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        if path.is_empty() {
+            return CoapResourcePath::default();
+        }
+        CoapResourcePath::new(
+            path.split('/')
+                .map(|segment| utf8_lossy(percent_decode(segment.as_bytes())).into_owned()),
+        )
+    }
+
+    /// Returns the (decoded) segments of this path.
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// Returns whether this is the path of the root resource (i.e., has no segments).
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Returns whether the Uri-Path options of the given request match this path segment by
+    /// segment.
+    pub(crate) fn matches_request(&self, request: &CoapRequest) -> bool {
+        self.segments
+            .iter()
+            .map(String::as_str)
+            .eq(request.uri().path_segments())
+    }
+
+    /// Returns the path under which libcoap looks up the resource, i.e., the segments joined by
+    /// `/` without percent-encoding them (as libcoap does for the Uri-Path options of requests).
+    ///
+    /// Different paths may have the same raw path (e.g., `["a/b"]` and `["a", "b"]`), in which case
+    /// requests are passed to the matching resource by the context.
+    pub(crate) fn to_raw(&self) -> String {
+        self.segments.join("/")
+    }
+
+    /// Returns whether the textual representation of this path differs from its raw path (see
+    /// [CoapResourcePath::to_raw()]).
+    pub(crate) fn needs_encoding(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| percent_encode(segment, b":@").len() != segment.len())
+    }
+}
+
+impl std::fmt::Display for CoapResourcePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, segment) in self.segments.iter().enumerate() {
+            if index > 0 {
+                f.write_str("/")?;
+            }
+            // Characters that are allowed in path segments are not encoded (RFC 3986, Section 3.3).
+            f.write_str(&percent_encode(segment, b":@"))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for CoapResourcePath {
+    fn from(value: &str) -> Self {
+        CoapResourcePath::parse(value)
+    }
+}
+
+impl From<&String> for CoapResourcePath {
+    fn from(value: &String) -> Self {
+        CoapResourcePath::parse(value)
+    }
+}
+
+impl From<String> for CoapResourcePath {
+    fn from(value: String) -> Self {
+        CoapResourcePath::parse(&value)
+    }
+}
+
+impl From<&[&str]> for CoapResourcePath {
+    fn from(value: &[&str]) -> Self {
+        CoapResourcePath::new(value.iter().copied())
+    }
+}
+
+impl<const N: usize> From<&[&str; N]> for CoapResourcePath {
+    fn from(value: &[&str; N]) -> Self {
+        CoapResourcePath::new(value.iter().copied())
+    }
+}
+
+impl<const N: usize> From<[&str; N]> for CoapResourcePath {
+    fn from(value: [&str; N]) -> Self {
+        CoapResourcePath::new(value)
+    }
+}
+
+impl From<Vec<String>> for CoapResourcePath {
+    fn from(value: Vec<String>) -> Self {
+        CoapResourcePath { segments: value }
+    }
+}
+
+/// Request statistics collected for a [CoapResource].
+///
+/// Statistics are updated automatically whenever a request for the resource is dispatched to one
+/// of its handlers, see [CoapResource::stats()].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoapResourceStats {
+    /// Number of requests received for each request method.
+    pub requests: HashMap<CoapRequestCode, u64>,
+    /// Number of responses sent for each response code class (e.g. `2` for 2.xx responses).
+    ///
+    /// Only responses sent from within a request handler are counted.
+    pub responses: HashMap<u8, u64>,
+    /// Total time spent inside of request handlers.
+    pub total_handler_duration: Duration,
+    /// Total number of payload bytes sent in responses.
+    pub bytes_served: u64,
+    /// Number of clients that were observing the resource when this snapshot was taken (see
+    /// [CoapResource::observer_count()]).
+    ///
+    /// Unlike the other statistics, this value is not reset by [CoapResource::reset_stats()].
+    pub active_observers: usize,
+}
+
+impl CoapResourceStats {
+    /// Returns the total number of requests received for this resource.
+    pub fn total_requests(&self) -> u64 {
+        self.requests.values().sum()
+    }
+
+    /// Returns the average time spent inside of request handlers, or None if no requests have
+    /// been handled yet.
+    pub fn average_handler_duration(&self) -> Option<Duration> {
+        let total_requests = u32::try_from(self.total_requests()).unwrap_or(u32::MAX);
+        (total_requests != 0).then(|| self.total_handler_duration / total_requests)
+    }
+
+    /// Records a response with the given code and payload length.
+    pub(crate) fn record_response(&mut self, code: CoapResponseCode, payload_len: usize) {
+        *self.responses.entry(code.class()).or_default() += 1;
+        self.bytes_served += payload_len as u64;
+    }
+}
+
+/// Behavior of a [CoapResource] for requests that arrive while observe notifications for the
+/// resource are still pending.
+///
+/// After calling [CoapResource::notify_observers()], notifications are not sent immediately, but
+/// only during the next call to [CoapContext::do_io()](crate::context::CoapContext::do_io).
+/// Requests that arrive in the meantime may therefore be answered with a resource state that is
+/// newer than the one observers have been notified about so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum NotificationConsistency {
+    /// Serve requests immediately, regardless of any pending notifications (the default).
+    ///
+    /// Handlers may still use [CoapResource::is_notification_pending()] to decide on how to
+    /// handle requests while notifications are pending.
+    #[default]
+    ServeCurrent,
+    /// Delay the response to plain (i.e., non-observe) GET requests until all notifications that
+    /// were pending at the time of the request have been sent.
+    ///
+    /// The request is acknowledged with an empty ACK, and the request handler is called (and
+    /// the response sent as a separate response) once the notifications have been sent.
+    ServeAfterPendingNotify,
+}
+
+/// Pacing of the observe notifications of a [CoapResource], see
+/// [CoapContext::set_notification_pacing()](crate::context::CoapContext::set_notification_pacing).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum NotificationPacing {
+    /// Notify all observers at once (the default).
+    #[default]
+    Unlimited,
+    /// Send at most the given number of notifications of the resource per call to
+    /// [CoapContext::do_io()](crate::context::CoapContext::do_io).
+    ///
+    /// Values of 0 are treated like 1.
+    PdusPerIteration(usize),
+    /// Send notifications of the resource with at most the given number of payload bytes per
+    /// second on average.
+    ///
+    /// Up to one second's worth of bytes may be sent in a burst, and a notification is sent as
+    /// long as the allowance has not been used up, so the rate may briefly be exceeded by the
+    /// size of one notification. Values of 0 are treated like 1.
+    BytesPerSecond(u64),
+}
+
+/// Message types used for the observe notifications of a [CoapResource], see
+/// [CoapResource::set_notification_type_policy()].
+///
+/// [RFC 7641, Section 4.5](https://datatracker.ietf.org/doc/html/rfc7641#section-4.5) recommends
+/// sending a confirmable notification occasionally, as the server only learns that an observer is
+/// no longer reachable if a confirmable notification is not acknowledged (see
+/// [CoapEventHandler::handle_observer_unreachable()](crate::CoapEventHandler::handle_observer_unreachable)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationTypePolicy {
+    /// Send all notifications as non-confirmable messages.
+    AlwaysNon,
+    /// Send all notifications as confirmable messages.
+    AlwaysCon,
+    /// Send notifications as non-confirmable messages, except for every `con_every`th notification
+    /// to an observer and the first notification to an observer after `con_interval` has elapsed
+    /// since its last confirmable notification (or its registration), which are sent as
+    /// confirmable messages.
+    ///
+    /// Values of 0 for `con_every` are treated like 1.
+    Mixed {
+        /// Number of notifications after which a confirmable notification is sent.
+        con_every: u32,
+        /// Time after which a confirmable notification is sent, if any.
+        con_interval: Option<Duration>,
+    },
+}
+
+/// Notifications an observer has received since its last confirmable notification, see
+/// [NotificationTypePolicy::Mixed].
+#[derive(Debug, Clone, Copy)]
+struct NotificationTypeTracking {
+    /// Number of non-confirmable notifications sent since the last confirmable one.
+    since_con: u32,
+    /// Time at which the last confirmable notification was sent (or the observer registered).
+    last_con: Instant,
+}
+
+/// Description of a client observing a [CoapResource], see [CoapResource::observers()].
+///
+/// Observers are identified by their session and the token of their registration request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoapObserver {
+    session_id: CoapSessionId,
+    addr_remote: Option<SocketAddr>,
+    token: Box<[u8]>,
+    uri_path: String,
+    query: Option<String>,
+    established: SystemTime,
+    last_observe: Option<Observe>,
+}
+
+impl CoapObserver {
+    /// Returns the identifier of the session the observer registered on.
+    pub fn session_id(&self) -> CoapSessionId {
+        self.session_id
+    }
+
+    /// Returns the address of the peer at the time of the registration, or None if it can not be
+    /// represented as a [SocketAddr] (i.e., for Unix domain socket sessions).
+    pub fn addr_remote(&self) -> Option<SocketAddr> {
+        self.addr_remote
+    }
+
+    /// Returns the token of the registration request, which is used for all notifications.
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+
+    /// Returns the URI path of the observed resource.
+    pub fn uri_path(&self) -> &str {
+        &self.uri_path
+    }
+
+    /// Returns the query of the registration request (the values of its Uri-Query options,
+    /// separated by `&`), if any.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// Returns the time at which the observer registered.
+    pub fn established(&self) -> SystemTime {
+        self.established
+    }
+
+    /// Returns the value of the Observe option of the last notification (or of the response to
+    /// the registration) sent to this observer, if any.
+    ///
+    /// This can be used to relate gaps in the notifications reported by clients to the
+    /// notifications sent by the server.
+    pub fn last_observe(&self) -> Option<Observe> {
+        self.last_observe
+    }
+
+    /// Returns the key identifying this observer in the notification state of its resource.
+    fn key(&self) -> (CoapSessionId, Box<[u8]>) {
+        (self.session_id, self.token.clone())
+    }
+}
+
+/// A request that was deferred until the pending notifications of its resource are sent.
+#[derive(Debug)]
+struct DeferredRequest {
+    raw_resource: *mut coap_resource_t,
+    raw_session: *mut coap_session_t,
+    raw_handler: unsafe extern "C" fn(
+        resource: *mut coap_resource_t,
+        session: *mut coap_session_t,
+        incoming_pdu: *const coap_pdu_t,
+        query: *const coap_string_t,
+        response_pdu: *mut coap_pdu_t,
+    ),
+    request: CoapRequest,
+}
+
+impl DeferredRequest {
+    /// Calls the request handler for the deferred request with a newly created response PDU.
+    ///
+    /// # Safety
+    /// The raw resource must still be valid, i.e. the resource must not have been dropped.
+    unsafe fn replay(&self) {
+        let response_type = match self.request.type_() {
+            CoapMessageType::Con => CoapMessageType::Con,
+            _ => CoapMessageType::Non,
+        };
+        replay_request(
+            self.raw_resource,
+            self.raw_session,
+            self.raw_handler,
+            &self.request,
+            response_type,
+            None,
+        );
+    }
+}
+
+/// Calls the given raw request handler for a request that is not currently handled by libcoap,
+/// with a newly created response PDU of the given message type (and with an Observe option of
+/// the given value, if any).
+///
+/// Responses sent by the handler are sent as separate responses.
+///
+/// # Safety
+/// The raw resource and raw session must be valid.
+unsafe fn replay_request(
+    raw_resource: *mut coap_resource_t,
+    raw_session: *mut coap_session_t,
+    raw_handler: RawRequestHandler,
+    request: &CoapRequest,
+    response_type: CoapMessageType,
+    observe: Option<Observe>,
+) {
+    let session = CoapSession::from_raw(raw_session);
+    let raw_request = match request.clone().into_message().into_raw_pdu(&session) {
+        Ok(raw_request) => raw_request,
+        Err(_) => return,
+    };
+    let raw_response = coap_pdu_init(
+        response_type.to_raw_pdu_type(),
+        coap_pdu_code_t::COAP_EMPTY_CODE,
+        coap_new_message_id(raw_session) as CoapMessageId,
+        coap_session_max_pdu_size(raw_session),
+    );
+    if raw_response.is_null() {
+        coap_delete_pdu(raw_request);
+        return;
+    }
+    let token = request.token().unwrap_or(&[]);
+    coap_add_token(raw_response, token.len(), token.as_ptr());
+    if let Some(observe) = observe {
+        let value = encode_var_len_u32(observe);
+        coap_add_option(
+            raw_response,
+            CoapOptionType::Observe as CoapOptionNum,
+            value.len(),
+            value.as_ptr(),
+        );
+    }
+    // The response PDU created here is never sent, so responses must not be added to it.
+    set_replaying_request(&session, true);
+    raw_handler(raw_resource, raw_session, raw_request, std::ptr::null(), raw_response);
+    set_replaying_request(&session, false);
+    coap_delete_pdu(raw_request);
+    coap_delete_pdu(raw_response);
+}
+
+impl Drop for DeferredRequest {
+    fn drop(&mut self) {
+        // SAFETY: We increased the reference count of the session when deferring the request.
+        unsafe { coap_session_release(self.raw_session) }
+    }
+}
+
+/// State of a [CoapResource] relating to pending observe notifications.
+///
+/// This state is shared with the [CoapContext](crate::context::CoapContext) the resource is
+/// added to, which tracks when pending notifications have been sent.
+#[derive(Debug, Default)]
+pub(crate) struct CoapResourceNotifyState {
+    consistency: NotificationConsistency,
+    /// Incremented every time observers are notified about a change of the resource.
+    notify_seq: u64,
+    /// Value of `notify_seq` up to which notifications have been sent.
+    flushed_seq: u64,
+    /// Requests that are waiting for pending notifications to be sent.
+    deferred: Vec<DeferredRequest>,
+    /// Message type for the pending notifications set using
+    /// [CoapResource::notify_observers_with()].
+    notify_type: Option<CoapMessageType>,
+    /// Message types for the notifications of individual observers (identified by their session
+    /// and the token of their registration).
+    observer_types: HashMap<(CoapSessionId, Box<[u8]>), CoapMessageType>,
+    /// Policy for the message types of notifications, overriding the default of the resource (see
+    /// [CoapResource::set_notification_type_policy()]).
+    type_policy: Option<NotificationTypePolicy>,
+    /// Notifications the observers have received since their last confirmable notification.
+    type_tracking: HashMap<(CoapSessionId, Box<[u8]>), NotificationTypeTracking>,
+    /// Observers whose registration has been answered with a success response, in the order of
+    /// their registration (see [CoapResource::observers()]).
+    observers: Vec<CoapObserver>,
+    /// Observers that receive a final error notification instead of calling the request handler
+    /// during the next notification (see [CoapResource::cancel_observer()]).
+    cancelled_observers: Vec<(CoapSessionId, Box<[u8]>)>,
+    /// Representation that is sent in the pending notifications instead of calling the GET
+    /// handler (see [CoapResource::notify_observers_with_snapshot()]).
+    snapshot: Option<NotificationSnapshot>,
+    /// Minimum time between two notifications (see [CoapResource::set_coalesce_interval()]).
+    coalesce_interval: Option<Duration>,
+    /// Time at which libcoap was last asked to notify the observers.
+    last_notified: Option<Instant>,
+    /// Notification that is postponed until the coalesce interval has expired, along with the
+    /// snapshot that should be sent in it (if any).
+    coalesced: Option<Option<NotificationSnapshot>>,
+    /// Pacing of the notifications of this resource, overriding the default of the context (see
+    /// [CoapResource::set_notification_pacing()]).
+    pacing: Option<NotificationPacing>,
+    /// Registration requests of the observers, which are replayed to generate paced
+    /// notifications.
+    registrations: HashMap<(CoapSessionId, Box<[u8]>), CoapRequest>,
+    /// Observers that are still to be sent a paced notification, in the order in which the
+    /// notifications are sent.
+    ///
+    /// Each observer is queued at most once, with the pending snapshot (if any) being sent to all
+    /// of them.
+    paced_queue: VecDeque<(CoapSessionId, Box<[u8]>)>,
+    /// Handler and resource used for sending the paced notifications of the queue.
+    paced_target: Option<PacedTarget>,
+    /// Number of payload bytes that may still be sent in paced notifications if the pacing is
+    /// [NotificationPacing::BytesPerSecond] (negative if the allowance has been exceeded).
+    pacing_allowance: f64,
+    /// Time at which `pacing_allowance` was last updated.
+    pacing_updated: Option<Instant>,
+}
+
+/// Raw handler and resource that paced notifications are generated with, see
+/// [send_paced_notifications()].
+#[derive(Debug, Clone)]
+struct PacedTarget {
+    raw_resource: *mut coap_resource_t,
+    raw_handler: RawRequestHandler,
+    /// Whether notifications are sent as confirmable messages by default.
+    notify_con: bool,
+    stats: Rc<RefCell<CoapResourceStats>>,
+}
+
+impl CoapResourceNotifyState {
+    /// Returns whether notifications for the resource are currently pending.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.notify_seq != self.flushed_seq || !self.paced_queue.is_empty()
+    }
+
+    /// Returns the number of paced notifications that have not been sent yet.
+    pub(crate) fn queued_notifications(&self) -> usize {
+        self.paced_queue.len()
+    }
+
+    /// Returns the memory used by this state, with the records of the observers being reported as
+    /// [CoapMemoryReport::resources] and the pending snapshots as
+    /// [CoapMemoryReport::cached_representations].
+    pub(crate) fn memory_usage(&self) -> CoapMemoryReport {
+        let key_size = |key: &(CoapSessionId, Box<[u8]>)| std::mem::size_of_val(key) + key.1.len();
+        let snapshot_size = |snapshot: &NotificationSnapshot| std::mem::size_of_val(snapshot) + snapshot.payload.len();
+        let observers: usize = self
+            .observers
+            .iter()
+            .map(|observer| {
+                std::mem::size_of_val(observer)
+                    + observer.token.len()
+                    + observer.uri_path.len()
+                    + observer.query.as_ref().map_or(0, String::len)
+            })
+            .sum();
+        let registrations: usize = self
+            .registrations
+            .iter()
+            .map(|(key, request)| key_size(key) + message_memory(request))
+            .sum();
+        let records = self.observer_types.keys().map(key_size).sum::<usize>()
+            + self
+                .type_tracking
+                .iter()
+                .map(|(key, value)| key_size(key) + std::mem::size_of_val(value))
+                .sum::<usize>()
+            + self.cancelled_observers.iter().map(key_size).sum::<usize>()
+            + self.deferred.len() * std::mem::size_of::<DeferredRequest>();
+        CoapMemoryReport {
+            resources: std::mem::size_of::<Self>() + observers + registrations + records,
+            cached_representations: self
+                .snapshot
+                .iter()
+                .chain(self.coalesced.iter().flatten())
+                .map(snapshot_size)
+                .sum(),
+            queued_notifications: self.paced_queue.iter().map(key_size).sum(),
+            ..CoapMemoryReport::default()
+        }
+    }
+
+    /// Returns the time at which further paced notifications may be sent (if any are queued),
+    /// given the pacing of the context and the current time `now`.
+    pub(crate) fn pacing_deadline(&self, default_pacing: NotificationPacing, now: Instant) -> Option<Instant> {
+        if self.paced_queue.is_empty() {
+            return None;
+        }
+        match self.pacing.unwrap_or(default_pacing) {
+            NotificationPacing::BytesPerSecond(rate) if self.pacing_allowance <= 0.0 => {
+                // Wait until at least one byte may be sent, durations that can't be represented
+                // are never over.
+                let wait = Duration::try_from_secs_f64((1.0 - self.pacing_allowance) / rate.max(1) as f64).ok()?;
+                self.pacing_updated.unwrap_or(now).checked_add(wait)
+            },
+            _ => Some(now),
+        }
+    }
+
+    /// Queues a paced notification for each of the observers with the given keys that does not
+    /// have one queued already, returning whether any notifications are queued.
+    ///
+    /// Notifications that are already queued keep their position, they are superseded by the new
+    /// notification as the pending snapshot (if any) is only taken once they are sent.
+    fn queue_paced_notifications<I: IntoIterator<Item = (CoapSessionId, Box<[u8]>)>>(
+        &mut self,
+        keys: I,
+        target: PacedTarget,
+    ) -> bool {
+        let queued: HashSet<_> = self.paced_queue.iter().cloned().collect();
+        for key in keys {
+            if self.registrations.contains_key(&key) && !queued.contains(&key) {
+                self.paced_queue.push_back(key);
+            }
+        }
+        self.paced_target = Some(target);
+        !self.paced_queue.is_empty()
+    }
+
+    /// Adds the allowance for the time since paced notifications were last sent, given the
+    /// pacing of the context and the current time `now`.
+    fn refill_pacing_allowance(&mut self, default_pacing: NotificationPacing, now: Instant) {
+        let NotificationPacing::BytesPerSecond(rate) = self.pacing.unwrap_or(default_pacing) else {
+            return;
+        };
+        let rate = rate.max(1) as f64;
+        self.pacing_allowance = match self.pacing_updated {
+            Some(updated) => {
+                (self.pacing_allowance + now.saturating_duration_since(updated).as_secs_f64() * rate).min(rate)
+            },
+            None => rate,
+        };
+        self.pacing_updated = Some(now);
+    }
+
+    /// Returns the message type of the next notification for the observer with the given key and
+    /// records it for the notification type policy, given whether notifications of the resource
+    /// are confirmable by default and the current time `now`.
+    ///
+    /// See [CoapResource::notify_observers_with()] for the order in which the settings apply.
+    fn next_notification_type(
+        &mut self,
+        key: &(CoapSessionId, Box<[u8]>),
+        notify_con: bool,
+        now: Instant,
+    ) -> CoapMessageType {
+        let confirmable = match (
+            self.notify_type.or_else(|| self.observer_types.get(key).copied()),
+            self.type_policy,
+        ) {
+            (Some(type_), _) => type_ == CoapMessageType::Con,
+            (None, Some(NotificationTypePolicy::AlwaysNon)) => false,
+            (None, Some(NotificationTypePolicy::AlwaysCon)) => true,
+            (
+                None,
+                Some(NotificationTypePolicy::Mixed {
+                    con_every,
+                    con_interval,
+                }),
+            ) => self.type_tracking.get(key).is_some_and(|tracking| {
+                tracking.since_con.saturating_add(1) >= con_every.max(1)
+                    || con_interval.is_some_and(|interval| now.saturating_duration_since(tracking.last_con) >= interval)
+            }),
+            (None, None) => notify_con,
+        };
+        if let Some(tracking) = self.type_tracking.get_mut(key) {
+            if confirmable {
+                tracking.since_con = 0;
+                tracking.last_con = now;
+            } else {
+                tracking.since_con = tracking.since_con.saturating_add(1);
+            }
+        }
+        if confirmable {
+            CoapMessageType::Con
+        } else {
+            CoapMessageType::Non
+        }
+    }
+
+    /// Removes the observer with the given session and token because one of its notifications
+    /// could not be delivered, returning the observer if it was registered.
+    pub(crate) fn remove_unreachable_observer(
+        &mut self,
+        session_id: CoapSessionId,
+        token: &[u8],
+    ) -> Option<CoapObserver> {
+        let key = (session_id, Box::from(token));
+        let observer = self.observers.iter().find(|v| v.key() == key).cloned()?;
+        self.observer_types.remove(&key);
+        self.remove_observer(&key);
+        Some(observer)
+    }
+
+    /// Returns the current notification sequence number.
+    pub(crate) fn notify_seq(&self) -> u64 {
+        self.notify_seq
+    }
+
+    /// Returns the time at which the postponed notification for the resource should be sent (if
+    /// there is one, see [CoapResource::set_coalesce_interval()]), given the current time `now`.
+    pub(crate) fn coalesce_deadline(&self, now: Instant) -> Option<Instant> {
+        self.coalesced.as_ref()?;
+        match (self.last_notified, self.coalesce_interval) {
+            // Intervals that can't be represented are never over.
+            (Some(last_notified), Some(interval)) => last_notified.checked_add(interval),
+            // The interval was removed, so the notification is due immediately.
+            _ => Some(now),
+        }
+    }
+
+    /// Drops all deferred requests and paced notifications without calling their request
+    /// handlers.
+    pub(crate) fn clear_deferred(&mut self) {
+        self.deferred.clear();
+        self.paced_queue.clear();
+        self.paced_target = None;
+    }
+
+    /// Drops the deferred requests and notification type overrides of the given session, which is
+    /// about to be released (see [CoapServerSession::disconnect()]).
+    pub(crate) fn remove_session(&mut self, session: &CoapServerSession) {
+        let id = session.id();
+        // SAFETY: Pointers are only compared, never accessed.
+        let raw_session = unsafe { session.raw_session() };
+        self.deferred.retain(|v| v.raw_session.cast_const() != raw_session);
+        self.observer_types.retain(|(session_id, _), _| *session_id != id);
+        self.type_tracking.retain(|(session_id, _), _| *session_id != id);
+        self.observers.retain(|observer| observer.session_id != id);
+        self.cancelled_observers.retain(|(session_id, _)| *session_id != id);
+        self.registrations.retain(|(session_id, _), _| *session_id != id);
+        self.paced_queue.retain(|(session_id, _)| *session_id != id);
+    }
+
+    /// Removes the observer with the given key, returning whether it was registered.
+    fn remove_observer(&mut self, key: &(CoapSessionId, Box<[u8]>)) -> bool {
+        self.cancelled_observers.retain(|v| v != key);
+        self.registrations.remove(key);
+        self.type_tracking.remove(key);
+        self.paced_queue.retain(|v| v != key);
+        let len = self.observers.len();
+        self.observers
+            .retain(|observer| observer.session_id != key.0 || observer.token != key.1);
+        self.observers.len() != len
+    }
+}
+
+/// Marks the notifications that were pending up to (and including) sequence number `seq` as
+/// sent, and calls the request handlers of any requests that were deferred until then.
+///
+/// If observers have been notified again since `seq`, notifications are still considered pending
+/// and deferred requests are kept waiting.
+///
+/// # Safety
+/// The resource the state belongs to must still be valid.
+pub(crate) unsafe fn complete_pending_notifications(state: &Rc<RefCell<CoapResourceNotifyState>>, seq: u64) {
+    let deferred = {
+        let mut state = state.borrow_mut();
+        if state.notify_seq != seq || !state.paced_queue.is_empty() {
+            return;
+        }
+        state.flushed_seq = seq;
+        state.notify_type = None;
+        // libcoap keeps its own reference to snapshots that are sent block-wise.
+        state.snapshot = None;
+        std::mem::take(&mut state.deferred)
+    };
+    for request in deferred {
+        request.replay();
+    }
+}
+
+/// Sends the queued paced notifications of a resource as far as its pacing allows (see
+/// [CoapResource::set_notification_pacing()]), given the pacing of the context and the current
+/// time `now`.
+///
+/// Notifications are generated by replaying the registration request of each observer, and are
+/// dropped if the session of the observer is not among the given `sessions`.
+///
+/// # Safety
+/// The resource the state belongs to must still be valid, and the given raw sessions must be
+/// valid server-side sessions managed by this wrapper.
+pub(crate) unsafe fn send_paced_notifications(
+    state: &Rc<RefCell<CoapResourceNotifyState>>,
+    default_pacing: NotificationPacing,
+    now: Instant,
+    sessions: &HashMap<CoapSessionId, *mut coap_session_t>,
+) {
+    state.borrow_mut().refill_pacing_allowance(default_pacing, now);
+    let mut sent = 0;
+    loop {
+        let (target, raw_session, request, type_, observe) = {
+            let mut state = state.borrow_mut();
+            let Some(target) = state.paced_target.clone() else {
+                return;
+            };
+            match state.pacing.unwrap_or(default_pacing) {
+                NotificationPacing::PdusPerIteration(max) if sent >= max.max(1) => return,
+                NotificationPacing::BytesPerSecond(_) if state.pacing_allowance <= 0.0 => return,
+                _ => {},
+            }
+            let Some(key) = state.paced_queue.pop_front() else {
+                return;
+            };
+            let (Some(raw_session), Some(request)) = (sessions.get(&key.0), state.registrations.get(&key)) else {
+                continue;
+            };
+            let request = request.clone();
+            let type_ = state.next_notification_type(&key, target.notify_con, now);
+            // Observe values are 24 bits long.
+            let observe = (state.notify_seq & 0xff_ffff) as Observe;
+            (target, *raw_session, request, type_, observe)
+        };
+        let bytes_before = target.stats.borrow().bytes_served;
+        replay_request(
+            target.raw_resource,
+            raw_session,
+            target.raw_handler,
+            &request,
+            type_,
+            Some(observe),
+        );
+        let bytes = target.stats.borrow().bytes_served.saturating_sub(bytes_before);
+        state.borrow_mut().pacing_allowance -= bytes as f64;
+        sent += 1;
+    }
+}
+
+/// Representation of a resource that is sent to all observers of a notification, see
+/// [CoapResource::notify_observers_with_snapshot()].
+#[derive(Debug, Clone)]
+struct NotificationSnapshot {
+    payload: Arc<[u8]>,
+    content_format: Option<ContentFormat>,
+    max_age: Option<MaxAge>,
+}
+
+impl NotificationSnapshot {
+    /// Answers a notification request using this snapshot, with the given value for the Observe
+    /// option.
+    ///
+    /// The payload is shared with libcoap, so large snapshots are sent block-wise without being
+    /// copied (and all blocks of a notification are taken from the same snapshot).
+    fn respond(&self, session: &mut CoapServerSession, observe: Observe, mut response: CoapResponse) {
+        response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+        response.set_observe(Some(observe));
+        response.set_content_format(self.content_format);
+        response.set_max_age(self.max_age);
+        // The ETag of the resource does not necessarily match the snapshot.
+        response.set_etag(None);
+        response.set_data_shared(Some(self.payload.clone()));
+        // If sending fails, libcoap will answer the request with an empty ACK instead.
+        let _ = session.send(response);
+    }
+}
+
+/// Returns the raw resource mode (as used by `coap_resource_set_mode()`) for sending
+/// notifications as confirmable or non-confirmable messages.
+fn raw_notify_mode(confirmable: bool) -> c_int {
+    // The raw flag constants are all small enough to fit into a c_int.
+    if confirmable {
+        COAP_RESOURCE_FLAGS_NOTIFY_CON as c_int
+    } else {
+        COAP_RESOURCE_FLAGS_NOTIFY_NON as c_int
+    }
+}
+
+/// Asserts that the given message type can be used for observe notifications.
+fn assert_notify_type(type_: CoapMessageType) {
+    assert!(
+        matches!(type_, CoapMessageType::Con | CoapMessageType::Non),
+        "notifications must be sent as confirmable or non-confirmable messages"
+    );
+}
+
+/// Generates an 8 byte ETag for the given representation using the 64-bit FNV-1a hash function.
+fn generate_etag(content: &[u8]) -> ETag {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    let hash = content.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    });
+    Box::from(hash.to_be_bytes())
+}
+
 /// Trait with functions relating to [CoapResource]s with an unknown data type.
 pub trait UntypedCoapResource: Any + Debug {
-    /// Returns the uri_path this resource responds to.
+    /// Returns the uri_path this resource responds to, i.e., its segments joined by `/` without
+    /// percent-encoding them (see [CoapResourcePath] for a representation that keeps the segments
+    /// apart).
     fn uri_path(&self) -> &str;
+    /// Returns the URI path this resource responds to as a sequence of segments (see
+    /// [CoapResource::path()]).
+    fn path(&self) -> Option<CoapResourcePath>;
+    /// Notify any observers about changes to this resource (see
+    /// [CoapResource::notify_observers()]).
+    fn notify_observers(&self) -> bool;
+    /// Sends the postponed notification of this resource if its coalesce interval has expired
+    /// (see [CoapResource::set_coalesce_interval()]).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) while performing
+    /// IO. *You should not use this function*.
+    #[doc(hidden)]
+    fn flush_coalesced_notification(&self);
     /// Provides a reference to this resource as an [Any] trait object.
     ///
     /// You can use the resulting [Any] reference to downcast the resource to its appropriate
@@ -121,6 +1214,13 @@ pub trait UntypedCoapResource: Any + Debug {
     /// dropped, i.e. because the underlying [Rc] is used elsewhere.
     #[doc(hidden)]
     fn drop_inner_exclusive(self: Box<Self>);
+    /// Drops all request handlers of this resource (and therefore all values captured by them).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) on cleanup, as
+    /// handlers may hold handles to other resources that would prevent those from being dropped
+    /// exclusively. *You should not use this function*.
+    #[doc(hidden)]
+    fn drop_handlers(&self);
     /// Returns the raw resource associated with this CoapResource.
     ///
     /// # Safety
@@ -131,13 +1231,67 @@ pub trait UntypedCoapResource: Any + Debug {
     ///   will the resource.
     /// - ...modify the application-specific data.
     unsafe fn raw_resource(&mut self) -> *mut coap_resource_t;
-}
-
-/// Representation of a CoapResource that can be requested from a server.
-#[derive(Debug)]
-pub struct CoapResource<D: Any + ?Sized + Debug> {
-    inner: CoapFfiRcCell<CoapResourceInner<D>>,
-}
+    /// Returns the host this resource is restricted to (see [CoapResourceBuilder::host()]).
+    fn host(&self) -> Option<String>;
+    /// Returns the raw handler that libcoap would call for requests with the given method.
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) to pass requests
+    /// to the resource responsible for the requested host. *You should not use this function*.
+    #[doc(hidden)]
+    fn raw_method_handler(&self, code: CoapRequestCode) -> Option<RawRequestHandler>;
+    /// Lets the raw resource pass all requests to the resource responsible for the requested host
+    /// instead of calling the handlers of this resource directly (see
+    /// [CoapResourceBuilder::host()]).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) once resources for
+    /// the path of this resource are restricted to hosts. *You should not use this function*.
+    #[doc(hidden)]
+    fn route_virtual_hosts(&self);
+    /// Returns whether this resource can be observed (see [CoapResource::set_get_observable()]).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) when another
+    /// resource starts routing requests to this one. *You should not use this function*.
+    #[doc(hidden)]
+    fn is_observable(&self) -> bool;
+    /// Replaces the raw resource of this resource with a new one that is not associated with any
+    /// context, and frees the previous raw resource (removing it from its context).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) to release
+    /// resources that have been removed from it while they may still be referenced by the
+    /// application. *You should not use this function*.
+    #[doc(hidden)]
+    fn detach_raw_resource(&self);
+    /// Marks this resource as removed from its context, so that requests that libcoap still
+    /// passes to it are answered with 4.04 (Not Found).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) when removing
+    /// resources. *You should not use this function*.
+    #[doc(hidden)]
+    fn mark_removed(&self);
+    /// Returns the number of bytes used by this resource, excluding its observers and request
+    /// handlers (see [CoapMemoryReport::resources]).
+    ///
+    /// This function is used by the [CoapContext](crate::context::CoapContext) to report its
+    /// memory usage (see [CoapContext::memory_usage()](crate::CoapContext::memory_usage)). *You
+    /// should not use this function*.
+    #[doc(hidden)]
+    fn memory_usage(&self) -> usize;
+}
+
+/// Raw request handler function as registered with libcoap.
+pub(crate) type RawRequestHandler = unsafe extern "C" fn(
+    resource: *mut coap_resource_t,
+    session: *mut coap_session_t,
+    incoming_pdu: *const coap_pdu_t,
+    query: *const coap_string_t,
+    response_pdu: *mut coap_pdu_t,
+);
+
+/// Representation of a CoapResource that can be requested from a server.
+#[derive(Debug)]
+pub struct CoapResource<D: Any + ?Sized + Debug> {
+    inner: CoapFfiRcCell<CoapResourceInner<D>>,
+}
 
 /// Container for resource handlers for various CoAP methods.
 #[derive(Debug)]
@@ -163,135 +1317,1457 @@ impl<D: Any + ?Sized + Debug> Default for CoapResourceHandlers<D> {
             patch: None,
         }
     }
-}
+}
+
+impl<D: Any + ?Sized + Debug> CoapResourceHandlers<D> {
+    #[inline]
+    fn handler(&self, code: CoapRequestCode) -> Option<&CoapRequestHandler<D>> {
+        match code {
+            CoapRequestCode::Get => self.get.as_ref(),
+            CoapRequestCode::Put => self.put.as_ref(),
+            CoapRequestCode::Delete => self.delete.as_ref(),
+            CoapRequestCode::Post => self.post.as_ref(),
+            CoapRequestCode::Fetch => self.fetch.as_ref(),
+            CoapRequestCode::IPatch => self.ipatch.as_ref(),
+            CoapRequestCode::Patch => self.patch.as_ref(),
+        }
+    }
+
+    #[inline]
+    // Clippy complains about this being unused, but I'd like to keep it for consistency.
+    #[allow(unused)]
+    fn handler_mut(&mut self, code: CoapRequestCode) -> Option<&mut CoapRequestHandler<D>> {
+        match code {
+            CoapRequestCode::Get => self.get.as_mut(),
+            CoapRequestCode::Put => self.put.as_mut(),
+            CoapRequestCode::Delete => self.delete.as_mut(),
+            CoapRequestCode::Post => self.post.as_mut(),
+            CoapRequestCode::Fetch => self.fetch.as_mut(),
+            CoapRequestCode::IPatch => self.ipatch.as_mut(),
+            CoapRequestCode::Patch => self.patch.as_mut(),
+        }
+    }
+
+    // Kept for consistency
+    #[allow(unused)]
+    #[inline]
+    fn handler_ref(&self, code: CoapRequestCode) -> &Option<CoapRequestHandler<D>> {
+        match code {
+            CoapRequestCode::Get => &self.get,
+            CoapRequestCode::Put => &self.put,
+            CoapRequestCode::Delete => &self.delete,
+            CoapRequestCode::Post => &self.post,
+            CoapRequestCode::Fetch => &self.fetch,
+            CoapRequestCode::IPatch => &self.ipatch,
+            CoapRequestCode::Patch => &self.patch,
+        }
+    }
+
+    #[inline]
+    fn handler_ref_mut(&mut self, code: CoapRequestCode) -> &mut Option<CoapRequestHandler<D>> {
+        match code {
+            CoapRequestCode::Get => &mut self.get,
+            CoapRequestCode::Put => &mut self.put,
+            CoapRequestCode::Delete => &mut self.delete,
+            CoapRequestCode::Post => &mut self.post,
+            CoapRequestCode::Fetch => &mut self.fetch,
+            CoapRequestCode::IPatch => &mut self.ipatch,
+            CoapRequestCode::Patch => &mut self.patch,
+        }
+    }
+}
+
+/// Inner part of a [CoapResource], which is referenced inside the raw resource and might be
+/// referenced multiple times, e.g. outside and inside of a resource handler.
+#[derive(Debug)]
+pub(crate) struct CoapResourceInner<D: Any + ?Sized + Debug> {
+    raw_resource: *mut coap_resource_t,
+    user_data: Box<D>,
+    handlers: CoapResourceHandlers<D>,
+    stats: Rc<RefCell<CoapResourceStats>>,
+    notify_state: Rc<RefCell<CoapResourceNotifyState>>,
+    /// ETag of the current representation of this resource.
+    etag: Option<ETag>,
+    /// Whether requests are validated against `etag` before calling the request handler.
+    etag_validation: bool,
+    /// Whether observe notifications are sent as confirmable messages by default.
+    notify_con: bool,
+    /// Whether blocks of block-wise uploads are passed to the request handler individually (see
+    /// [ResourceFlags::STREAM_BLOCK1]).
+    stream_block1: bool,
+    /// Whether this resource can be observed (libcoap does not provide a getter for this).
+    observable: bool,
+    /// Policy that determines which requests need to contain a valid Echo value.
+    echo_policy: CoapEchoPolicy,
+    /// Maximum size of request bodies in bytes, see [CoapResource::set_max_request_size()].
+    max_request_size: Option<usize>,
+    /// Limits for the options and payloads of requests, see [CoapResource::set_input_limits()].
+    input_limits: Option<CoapInputLimits>,
+    /// Endpoints this resource is restricted to, see [CoapResource::set_endpoints()].
+    endpoints: Option<Vec<CoapEndpointHandle>>,
+    /// Hook that decides whether requests are passed to the request handler, see
+    /// [CoapResource::set_access_hook()].
+    access_hook: Option<AccessHookHandle>,
+    /// Shared state of the context this resource has been added to, which keeps the raw context
+    /// (that the raw resource is attached to) alive until this resource is dropped.
+    context_shared: Option<Rc<CoapContextShared>>,
+    /// URI path of this resource, or None for resources that receive requests for all unknown
+    /// paths (see [CoapResource::new_unknown()]).
+    path: Option<CoapResourcePath>,
+    /// Host (in lowercase) this resource is restricted to, see [CoapResourceBuilder::host()].
+    host: Option<String>,
+    /// Whether the raw resource passes requests to the resource responsible for the requested
+    /// host instead of calling the handlers of this resource directly, see
+    /// [UntypedCoapResource::route_virtual_hosts()].
+    routes_virtual_hosts: bool,
+    /// Whether this resource has been removed from its context, see
+    /// [CoapResourceHandle::remove()](crate::CoapResourceHandle::remove).
+    removed: bool,
+}
+
+impl<D: Any + ?Sized + Debug> CoapResource<D> {
+    /// Creates a new CoapResource for the given `uri_path`.
+    ///
+    /// The path can be provided as a sequence of segments or as a string whose segments are
+    /// percent-encoded, see [CoapResourcePath].
+    ///
+    /// Handlers that are associated with this resource have to be able to take a reference to the
+    /// provided `user_data` value as their first value.
+    ///
+    /// The `notify_con` parameter specifies whether observe notifications originating from this
+    /// resource are sent as confirmable or non-confirmable.
+    pub fn new<P: Into<CoapResourcePath>, C: Into<Box<D>>>(
+        uri_path: P,
+        user_data: C,
+        notify_con: bool,
+    ) -> CoapResource<D> {
+        let flags = if notify_con {
+            ResourceFlags::NOTIFY_CON
+        } else {
+            ResourceFlags::NOTIFY_NON
+        };
+        // Flags consisting of only one notify flag are always valid.
+        Self::new_with_flags(uri_path, user_data, flags).unwrap()
+    }
+
+    /// Creates a new CoapResource for the given `uri_path` (see [CoapResourcePath]) using the
+    /// provided resource flags.
+    ///
+    /// Handlers that are associated with this resource have to be able to take a reference to the
+    /// provided `user_data` value as their first value.
+    ///
+    /// The provided `flags` are applied on creation of the underlying raw resource, as some of them
+    /// cannot be changed afterwards.
+    ///
+    /// # Errors
+    /// Returns [ResourceCreationError::ContradictoryFlags] if the provided flags cannot be combined
+    /// (see [ResourceFlags]).
+    pub fn new_with_flags<P: Into<CoapResourcePath>, C: Into<Box<D>>>(
+        uri_path: P,
+        user_data: C,
+        flags: ResourceFlags,
+    ) -> Result<CoapResource<D>, ResourceCreationError> {
+        let raw_flags = flags.to_raw_flags()?;
+        let path = uri_path.into();
+        ensure_coap_started();
+        // SAFETY: The raw resource was just created and has no user data yet.
+        unsafe {
+            let raw_path = path.to_raw();
+            let uri_path = coap_new_str_const(raw_path.as_ptr(), raw_path.len());
+            let raw_resource = coap_resource_init(uri_path, raw_flags);
+            Ok(Self::wrap_raw_resource(
+                raw_resource,
+                Some(path),
+                user_data.into(),
+                flags.contains(ResourceFlags::NOTIFY_CON),
+                flags.contains(ResourceFlags::STREAM_BLOCK1),
+            ))
+        }
+    }
+
+    /// Creates a new CoapResource that receives requests for all URI paths that no other resource
+    /// of the context is responsible for, using libcoap's handler for unknown resources.
+    ///
+    /// libcoap only supports one such resource per context, adding another one frees the
+    /// previous one. Requests for methods without a handler are answered by libcoap itself (e.g.,
+    /// with 4.04 (Not Found)).
+    pub(crate) fn new_unknown<C: Into<Box<D>>>(user_data: C) -> CoapResource<D> {
+        ensure_coap_started();
+        // SAFETY: The raw resource was just created and has no user data yet.
+        // The flag constant is small enough to fit into a c_int.
+        unsafe {
+            Self::wrap_raw_resource(
+                coap_resource_unknown_init2(None, COAP_RESOURCE_FLAGS_FORCE_SINGLE_BODY as c_int),
+                None,
+                user_data.into(),
+                false,
+                false,
+            )
+        }
+    }
+
+    /// Wraps the given newly created raw resource.
+    ///
+    /// # Safety
+    /// `raw_resource` must be a valid raw resource that is not wrapped by another CoapResource yet
+    /// and not associated with a context.
+    unsafe fn wrap_raw_resource(
+        raw_resource: *mut coap_resource_t,
+        path: Option<CoapResourcePath>,
+        user_data: Box<D>,
+        notify_con: bool,
+        stream_block1: bool,
+    ) -> CoapResource<D> {
+        let inner = CoapFfiRcCell::new(CoapResourceInner {
+            raw_resource,
+            user_data,
+            handlers: CoapResourceHandlers::default(),
+            stats: Rc::new(RefCell::new(CoapResourceStats::default())),
+            notify_state: Rc::new(RefCell::new(CoapResourceNotifyState::default())),
+            etag: None,
+            etag_validation: true,
+            notify_con,
+            stream_block1,
+            observable: false,
+            echo_policy: CoapEchoPolicy::default(),
+            max_request_size: None,
+            input_limits: None,
+            endpoints: None,
+            access_hook: None,
+            context_shared: None,
+            path,
+            host: None,
+            routes_virtual_hosts: false,
+            removed: false,
+        });
+        coap_resource_set_userdata(raw_resource, inner.create_raw_weak());
+        Self::from(inner)
+    }
+
+    /// Returns a builder for a new CoapResource for the given `uri_path` (see [CoapResourcePath]),
+    /// which allows setting request handlers for individual methods (see [CoapResourceBuilder]).
+    ///
+    /// Handlers that are associated with this resource have to be able to take a reference to the
+    /// provided `user_data` value as their first value.
+    ///
+    /// ```
+    /// use libcoap_rs::CoapResource;
+    ///
+    /// // A resource with two segments, the second of which contains a slash.
+    /// let builder = CoapResource::builder(&["config", "wifi/main"], ());
+    /// ```
+    pub fn builder<P: Into<CoapResourcePath>, C: Into<Box<D>>>(uri_path: P, user_data: C) -> CoapResourceBuilder<D> {
+        CoapResourceBuilder {
+            uri_path: uri_path.into(),
+            user_data: user_data.into(),
+            flags: ResourceFlags::NOTIFY_NON,
+            observable: None,
+            coalesce_interval: None,
+            type_policy: None,
+            attributes: Vec::new(),
+            handlers: CoapResourceHandlers::default(),
+            fallback: None,
+            access_hook: None,
+            host: None,
+            max_request_size: None,
+            input_limits: None,
+            endpoints: None,
+        }
+    }
+
+    /// Notify any observers about changes to this resource.
+    ///
+    /// Notifications are sent during the next call to
+    /// [CoapContext::do_io()](crate::context::CoapContext::do_io), until then,
+    /// [CoapResource::is_notification_pending()] will return true.
+    /// The GET handler of the resource is called to generate the notification for each observer.
+    ///
+    /// If a coalesce interval is set (see [CoapResource::set_coalesce_interval()]), the
+    /// notifications may be postponed, in which case this function always returns true.
+    /// Otherwise, returns false if the resource is not observable or has no observers.
+    pub fn notify_observers(&self) -> bool {
+        self.request_notification(None)
+    }
+
+    /// Notify any observers about changes to this resource, unless the send queue of any
+    /// observer's session is congested.
+    ///
+    /// Returns [MessageConversionError::SendQueueFull] (with the number of bytes queued on the
+    /// first congested session) if the send queue of a session used by an observer has reached the
+    /// high-water mark set using
+    /// [CoapContext::set_send_queue_limits()](crate::context::CoapContext::set_send_queue_limits),
+    /// in which case no notifications are requested. The application may then retry once
+    /// [CoapEventHandler::handle_send_queue_drained()](crate::CoapEventHandler::handle_send_queue_drained)
+    /// has been called.
+    /// Otherwise, this function behaves like [CoapResource::notify_observers()].
+    pub fn try_notify_observers(&self) -> Result<bool, MessageConversionError> {
+        let congested_session = {
+            let inner = self.inner.borrow();
+            let notify_state = inner.notify_state.borrow();
+            inner.context_shared.as_ref().and_then(|shared| {
+                let congested = shared.congested_sessions.borrow();
+                notify_state
+                    .observers
+                    .iter()
+                    .find_map(|observer| congested.get(&observer.session_id).copied())
+            })
+        };
+        if let Some(raw_session) = congested_session {
+            // SAFETY: Sessions are removed from the map of congested sessions before they are
+            // dropped, so the raw session is still valid and managed by this wrapper.
+            let session = unsafe { CoapSession::from_raw(raw_session) };
+            return Err(MessageConversionError::SendQueueFull(session.queued_bytes()));
+        }
+        Ok(self.request_notification(None))
+    }
+
+    /// Notify any observers about changes to this resource, sending them the given
+    /// representation instead of calling the GET handler.
+    ///
+    /// The representation is captured when calling this function, and all observers that are
+    /// notified receive this exact representation (as a 2.05 (Content) response with the given
+    /// Content-Format and Max-Age options), even if the state of the resource changes before the
+    /// notifications are sent.
+    /// If observers are notified again before the notifications have been sent (or while they
+    /// are postponed, see [CoapResource::set_coalesce_interval()]), only the latest
+    /// representation is sent.
+    /// Observers that register while notifications are being sent also receive the snapshot.
+    ///
+    /// Large representations are sent block-wise, with libcoap keeping a reference to the
+    /// snapshot until all blocks have been requested, so that observers never receive blocks of
+    /// different representations. Use an [Arc] in order to share the payload between multiple
+    /// resources without copying it.
+    ///
+    /// The return value is the same as for [CoapResource::notify_observers()].
+    pub fn notify_observers_with_snapshot<P: Into<Arc<[u8]>>>(
+        &self,
+        payload: P,
+        content_format: Option<ContentFormat>,
+        max_age: Option<MaxAge>,
+    ) -> bool {
+        self.request_notification(Some(NotificationSnapshot {
+            payload: payload.into(),
+            content_format,
+            max_age,
+        }))
+    }
+
+    /// Returns the minimum time between two notifications for this resource (see
+    /// [CoapResource::set_coalesce_interval()]).
+    pub fn coalesce_interval(&self) -> Option<Duration> {
+        self.inner.borrow().notify_state.borrow().coalesce_interval
+    }
+
+    /// Sets the minimum time between two notifications for this resource, or None to send
+    /// notifications as soon as possible (the default).
+    ///
+    /// If observers are notified within the interval after the previous notification was sent,
+    /// the new notification is postponed until the interval has expired. Multiple notifications
+    /// that are postponed this way are coalesced into a single notification, using the snapshot
+    /// of the latest call to [CoapResource::notify_observers_with_snapshot()] (or the GET handler
+    /// if the latest notification was requested using [CoapResource::notify_observers()]).
+    ///
+    /// Postponed notifications are sent by
+    /// [CoapContext::do_io()](crate::context::CoapContext::do_io) once the interval has expired
+    /// (returning early if required). If the interval is removed, postponed notifications are
+    /// sent during the next call to `do_io()`.
+    pub fn set_coalesce_interval(&self, interval: Option<Duration>) {
+        self.inner.borrow().notify_state.borrow_mut().coalesce_interval = interval;
+        self.flush_coalesced_notification();
+    }
+
+    /// Asks libcoap to notify the observers of this resource, unless the notification has to be
+    /// postponed due to the coalesce interval.
+    fn request_notification(&self, snapshot: Option<NotificationSnapshot>) -> bool {
+        {
+            let now = self.now();
+            let inner = self.inner.borrow();
+            let mut notify_state = inner.notify_state.borrow_mut();
+            let within_interval = match (notify_state.last_notified, notify_state.coalesce_interval) {
+                (Some(last_notified), Some(interval)) => now.saturating_duration_since(last_notified) < interval,
+                _ => false,
+            };
+            if notify_state.coalesced.is_some() || within_interval {
+                notify_state.coalesced = Some(snapshot);
+                return true;
+            }
+        }
+        self.notify_raw(snapshot)
+    }
+
+    /// Sends the postponed notification of this resource if the coalesce interval has expired.
+    pub(crate) fn flush_coalesced_notification(&self) {
+        let snapshot = {
+            let now = self.now();
+            let inner = self.inner.borrow();
+            let mut notify_state = inner.notify_state.borrow_mut();
+            match notify_state.coalesce_deadline(now) {
+                Some(deadline) if deadline <= now => notify_state.coalesced.take().flatten(),
+                _ => return,
+            }
+        };
+        self.notify_raw(snapshot);
+    }
+
+    /// Marks this resource as dirty, causing libcoap to notify its observers during the next call
+    /// to [CoapContext::do_io()](crate::context::CoapContext::do_io) (using the given snapshot,
+    /// if any), or queues paced notifications for its observers if notifications are paced.
+    fn notify_raw(&self, snapshot: Option<NotificationSnapshot>) -> bool {
+        let now = self.now();
+        // Observers of resources sharing their path with resources for other hosts are registered
+        // with the resource that routes requests for the path.
+        let raw_resource = self.routing_raw_resource();
+        let paced_target = self.paced_target();
+        let inner = self.inner.borrow_mut();
+        let notified = match paced_target {
+            Some(target) => {
+                let mut notify_state = inner.notify_state.borrow_mut();
+                let keys: Vec<_> = notify_state.observers.iter().map(CoapObserver::key).collect();
+                notify_state.queue_paced_notifications(keys, target)
+            },
+            // SAFETY: Resource is valid as long as CoapResourceInner exists (or, for routing
+            // resources, while it is listed in the shared context state), query is currently
+            // unused.
+            None => unsafe { coap_resource_notify_observers(raw_resource, std::ptr::null_mut()) != 0 },
+        };
+        if notified {
+            let mut notify_state = inner.notify_state.borrow_mut();
+            notify_state.notify_seq += 1;
+            notify_state.snapshot = snapshot;
+            notify_state.last_notified = Some(now);
+        }
+        notified
+    }
+
+    /// Returns the handler and resource that paced notifications of this resource are generated
+    /// with, or None if its notifications are not paced (or it has no GET handler).
+    fn paced_target(&self) -> Option<PacedTarget> {
+        let inner = self.inner.borrow();
+        let pacing = inner.notify_state.borrow().pacing.or_else(|| {
+            inner
+                .context_shared
+                .as_ref()
+                .map(|shared| shared.notification_pacing.get())
+        })?;
+        if pacing == NotificationPacing::Unlimited {
+            return None;
+        }
+        let handler = inner.handlers.handler(CoapRequestCode::Get)?;
+        Some(PacedTarget {
+            raw_resource: inner.raw_resource,
+            raw_handler: handler.raw_handler,
+            notify_con: inner.notify_con,
+            stats: inner.stats.clone(),
+        })
+    }
+
+    /// Returns the pacing of the notifications of this resource, or None if the pacing of the
+    /// context is used (see [CoapResource::set_notification_pacing()]).
+    pub fn notification_pacing(&self) -> Option<NotificationPacing> {
+        self.inner.borrow().notify_state.borrow().pacing
+    }
+
+    /// Sets the pacing of the notifications of this resource, overriding the pacing of the
+    /// context (see
+    /// [CoapContext::set_notification_pacing()](crate::context::CoapContext::set_notification_pacing)),
+    /// or None to use the pacing of the context (the default).
+    ///
+    /// Use [NotificationPacing::Unlimited] to notify all observers of this resource at once even
+    /// if the context paces notifications. Notifications that are already queued are still sent
+    /// according to the new pacing.
+    pub fn set_notification_pacing(&self, pacing: Option<NotificationPacing>) {
+        self.inner.borrow().notify_state.borrow_mut().pacing = pacing;
+    }
+
+    /// Returns the number of paced notifications of this resource that have not been sent yet
+    /// (see
+    /// [CoapContext::set_notification_pacing()](crate::context::CoapContext::set_notification_pacing)).
+    pub fn queued_notifications(&self) -> usize {
+        self.inner.borrow().notify_state.borrow().queued_notifications()
+    }
+
+    /// Returns the snapshot that should be sent in response to the given request instead of
+    /// calling the request handler, i.e., if the request is an observe request (rather than a
+    /// deregistration) and notifications with a snapshot are currently pending.
+    fn notification_snapshot(&self, request: &CoapRequest) -> Option<(NotificationSnapshot, Observe)> {
+        if request.code() != CoapMessageCode::Request(CoapRequestCode::Get)
+            || request.observe().map_or(true, |v| v == 1)
+        {
+            return None;
+        }
+        let inner = self.inner.borrow();
+        let notify_state = inner.notify_state.borrow();
+        if !notify_state.is_pending() {
+            return None;
+        }
+        // Observe values are 24 bits long.
+        let observe = (notify_state.notify_seq & 0xff_ffff) as Observe;
+        notify_state.snapshot.clone().map(|v| (v, observe))
+    }
+
+    /// Notify any observers about changes to this resource, sending the notifications using the
+    /// given message type.
+    ///
+    /// This can be used to send important notifications (e.g., alarms) as confirmable messages,
+    /// even if notifications for this resource are usually sent as non-confirmable messages.
+    /// The message type applies to all notifications sent during the next call to
+    /// [CoapContext::do_io()](crate::context::CoapContext::do_io), including those caused by
+    /// other calls to [CoapResource::notify_observers()] in the meantime.
+    ///
+    /// The message type of a notification is determined as follows:
+    /// 1. The type provided to this function, if any.
+    /// 2. The type set for the observer using [CoapResource::set_observer_notify_type()], if any.
+    /// 3. The type selected by the notification type policy of the resource, if any (see
+    ///    [CoapResource::set_notification_type_policy()]).
+    /// 4. The default of the resource (see [CoapResource::set_observe_notify_confirmable()]).
+    ///
+    /// Explicitly requested confirmable notifications are always sent as confirmable messages.
+    /// For non-confirmable notifications, libcoap still sends a confirmable notification
+    /// periodically in order to check whether the observer is still interested (as required by
+    /// [RFC 7641, Section 4.5](https://datatracker.ietf.org/doc/html/rfc7641#section-4.5)), unless
+    /// the resource was created using [ResourceFlags::NOTIFY_NON_ALWAYS].
+    ///
+    /// Returns false if the resource is not observable or has no observers.
+    ///
+    /// # Panics
+    /// Panics if `type_` is neither [CoapMessageType::Con] nor [CoapMessageType::Non].
+    pub fn notify_observers_with(&self, type_: CoapMessageType) -> bool {
+        assert_notify_type(type_);
+        let notified = self.notify_observers();
+        if notified {
+            self.inner.borrow().notify_state.borrow_mut().notify_type = Some(type_);
+        }
+        notified
+    }
+
+    /// Sets the message type that should be used for notifications to the observer that
+    /// registered using the given request, overriding the default of the resource.
+    ///
+    /// This function is intended to be called from the GET handler of this resource when an
+    /// observer registers, e.g. to let clients request confirmable notifications using a query
+    /// parameter. If `type_` is None, the override for the observer is removed.
+    /// Overrides are also removed automatically if the observer cancels its registration using a
+    /// GET request with an Observe value of 1.
+    ///
+    /// See [CoapResource::notify_observers_with()] for how this override interacts with other
+    /// settings.
+    ///
+    /// # Panics
+    /// Panics if `type_` is neither [CoapMessageType::Con] nor [CoapMessageType::Non].
+    pub fn set_observer_notify_type<'a, S: CoapSessionCommon<'a>>(
+        &self,
+        session: &S,
+        request: &CoapRequest,
+        type_: Option<CoapMessageType>,
+    ) {
+        let key = (session.id(), Box::from(request.token().unwrap_or(&[])));
+        let inner = self.inner.borrow();
+        let mut notify_state = inner.notify_state.borrow_mut();
+        match type_ {
+            Some(type_) => {
+                assert_notify_type(type_);
+                notify_state.observer_types.insert(key, type_);
+            },
+            None => {
+                notify_state.observer_types.remove(&key);
+            },
+        }
+    }
+
+    /// Returns the notification type policy of this resource, or None if the default set using
+    /// [CoapResource::set_observe_notify_confirmable()] is used (see
+    /// [CoapResource::set_notification_type_policy()]).
+    pub fn notification_type_policy(&self) -> Option<NotificationTypePolicy> {
+        self.inner.borrow().notify_state.borrow().type_policy
+    }
+
+    /// Sets the policy that determines the message types of the notifications of this resource for
+    /// each observer (see [NotificationTypePolicy]), or None to use the default set using
+    /// [CoapResource::set_observe_notify_confirmable()] (the default).
+    ///
+    /// The policy can be overridden for individual notifications or observers, see
+    /// [CoapResource::notify_observers_with()].
+    ///
+    /// libcoap sends additional confirmable notifications on its own unless the resource was
+    /// created using [ResourceFlags::NOTIFY_NON_ALWAYS], so non-confirmable notifications are only
+    /// sent exactly as specified by the policy for resources created with this flag (which
+    /// [CoapResourceBuilder::notification_type_policy()] sets automatically).
+    pub fn set_notification_type_policy(&self, policy: Option<NotificationTypePolicy>) {
+        self.inner.borrow().notify_state.borrow_mut().type_policy = policy;
+    }
+
+    /// Returns the clients that are currently observing this resource, in the order of their
+    /// registration.
+    ///
+    /// Observers are tracked by this wrapper: they are added once their registration request
+    /// (a GET request with an Observe value of 0) has been answered with a success response, and
+    /// removed once they cancel their registration, a notification is answered with an error
+    /// response, a confirmable notification is not acknowledged (see
+    /// [CoapEventHandler::handle_observer_unreachable()](crate::CoapEventHandler::handle_observer_unreachable)),
+    /// their session is disconnected (see
+    /// [CoapServerSession::disconnect()](crate::session::CoapServerSession::disconnect)) or they
+    /// are cancelled using [CoapResource::cancel_observer()].
+    /// Observations that libcoap ends by itself (e.g., because the client rejected a notification
+    /// using a Reset message) are still listed until the session is disconnected.
+    pub fn observers(&self) -> impl Iterator<Item = CoapObserver> {
+        let observers = self.inner.borrow().notify_state.borrow().observers.clone();
+        observers.into_iter()
+    }
+
+    /// Returns the number of clients that are currently observing this resource (see
+    /// [CoapResource::observers()]).
+    ///
+    /// This can be used to skip building expensive representations for notifications if nobody
+    /// is observing the resource.
+    pub fn observer_count(&self) -> usize {
+        self.inner.borrow().notify_state.borrow().observers.len()
+    }
+
+    /// Ends the observation of the given observer.
+    ///
+    /// During the next call to [CoapContext::do_io()](crate::context::CoapContext::do_io), the
+    /// observer receives a final notification with the code 5.03 (Service Unavailable) instead
+    /// of a notification generated by the GET handler, upon which libcoap removes the
+    /// registration. Unless notifications are pending anyway, the other observers of this
+    /// resource receive a regular notification at the same time, as libcoap can only notify all
+    /// observers of a resource at once. If the notifications of this resource are paced (see
+    /// [CoapResource::set_notification_pacing()]), only the cancelled observer is notified.
+    ///
+    /// Returns false if the observer is not (or no longer) registered.
+    pub fn cancel_observer(&self, observer: &CoapObserver) -> bool {
+        let key = observer.key();
+        let paced_target = self.paced_target();
+        let pending = {
+            let inner = self.inner.borrow();
+            let mut notify_state = inner.notify_state.borrow_mut();
+            if !notify_state.observers.iter().any(|v| v.key() == key) {
+                return false;
+            }
+            if !notify_state.cancelled_observers.contains(&key) {
+                notify_state.cancelled_observers.push(key.clone());
+            }
+            // Only the cancelled observer is sent a (final) paced notification.
+            if let Some(target) = paced_target {
+                notify_state.queue_paced_notifications([key.clone()], target);
+            }
+            notify_state.is_pending()
+        };
+        // The postponing of notifications due to the coalesce interval is bypassed, as the
+        // cancellation should take effect immediately.
+        if pending || self.notify_raw(None) {
+            return true;
+        }
+        // libcoap has no observers for this resource, so the observation has already ended.
+        self.inner.borrow().notify_state.borrow_mut().remove_observer(&key);
+        false
+    }
+
+    /// Checks whether the given request is a notification request for an observer that was
+    /// cancelled using [CoapResource::cancel_observer()], removing the observer if it is.
+    fn take_cancelled_observer(&self, session: &CoapServerSession, request: &CoapRequest) -> bool {
+        if request.code() != CoapMessageCode::Request(CoapRequestCode::Get) || request.observe() != Some(0) {
+            return false;
+        }
+        let key = (session.id(), Box::from(request.token().unwrap_or(&[])));
+        let inner = self.inner.borrow();
+        let mut notify_state = inner.notify_state.borrow_mut();
+        notify_state.cancelled_observers.contains(&key) && notify_state.remove_observer(&key)
+    }
+
+    /// Returns whether the given request is the registration request of an existing observer of
+    /// this resource, which libcoap also uses to generate notifications.
+    fn is_observer_request(&self, session: &CoapServerSession, request: &CoapRequest) -> bool {
+        if request.code() != CoapMessageCode::Request(CoapRequestCode::Get) || request.observe() != Some(0) {
+            return false;
+        }
+        let key = (session.id(), Box::from(request.token().unwrap_or(&[])));
+        let inner = self.inner.borrow();
+        let notify_state = inner.notify_state.borrow();
+        notify_state.observers.iter().any(|v| v.key() == key)
+    }
+
+    /// Updates the list of observers of this resource after the request handler has been called
+    /// for the given observe request (see [CoapResource::observers()]).
+    ///
+    /// `succeeded` indicates whether the request was answered with a success response, `observe`
+    /// is the value of the Observe option of the response.
+    fn track_observer(
+        &self,
+        session: &mut CoapServerSession,
+        request: &CoapRequest,
+        succeeded: bool,
+        observe: Option<Observe>,
+    ) {
+        if request.code() != CoapMessageCode::Request(CoapRequestCode::Get) || request.observe().is_none() {
+            return;
+        }
+        let now = self.now();
+        let inner = self.inner.borrow();
+        let mut notify_state = inner.notify_state.borrow_mut();
+        let key = (session.id(), Box::from(request.token().unwrap_or(&[])));
+        if request.observe() != Some(0) || !succeeded || !inner.observable {
+            notify_state.remove_observer(&key);
+            return;
+        }
+        // Notifications are generated using the registration request, so they are handled like
+        // repeated registrations, which keep the original registration time.
+        if let Some(observer) = notify_state.observers.iter_mut().find(|v| v.key() == key) {
+            observer.last_observe = observe.or(observer.last_observe);
+            return;
+        }
+        let query = request
+            .uri()
+            .query()
+            .map(|query| String::from_utf8_lossy(query).into_owned());
+        notify_state.registrations.insert(key.clone(), request.clone());
+        notify_state.type_tracking.insert(
+            key.clone(),
+            NotificationTypeTracking {
+                since_con: 0,
+                last_con: now,
+            },
+        );
+        notify_state.observers.push(CoapObserver {
+            session_id: key.0,
+            // SAFETY: The session pointer is valid while its request handler is called.
+            addr_remote: unsafe { raw_addr_remote(session.raw_session_mut()) },
+            token: key.1,
+            uri_path: self.uri_path().to_string(),
+            query,
+            established: SystemTime::now(),
+            last_observe: observe,
+        });
+    }
+
+    /// Sets the raw mode of this resource to the message type that should be used for the
+    /// notification answering the given request.
+    ///
+    /// libcoap determines the message type of a notification based on the resource mode after
+    /// the request handler for the notification returns, so this function must be called after
+    /// the request handler has been called.
+    ///
+    /// Paced notifications already have their message type when the request handler is called
+    /// (see [send_paced_notifications()]), so the mode is not changed for them.
+    fn apply_notify_type(&self, session: &CoapServerSession, request: &CoapRequest) {
+        if is_replaying_request(session) {
+            return;
+        }
+        let now = self.now();
+        let inner = self.inner.borrow();
+        let mut notify_state = inner.notify_state.borrow_mut();
+        let key = (session.id(), Box::from(request.token().unwrap_or(&[])));
+        if request.observe() == Some(1) {
+            notify_state.observer_types.remove(&key);
+        }
+        let confirmable = notify_state.next_notification_type(&key, inner.notify_con, now) == CoapMessageType::Con;
+        // SAFETY: Resource is valid as long as CoapResourceInner exists.
+        unsafe { coap_resource_set_mode(inner.raw_resource, raw_notify_mode(confirmable)) }
+    }
+
+    /// Returns whether observers have been notified about a change to this resource (using
+    /// [CoapResource::notify_observers()]) that has not been sent to them yet.
+    ///
+    /// This includes notifications that are postponed due to the coalesce interval (see
+    /// [CoapResource::set_coalesce_interval()]) and paced notifications that are still queued (see
+    /// [CoapResource::queued_notifications()]).
+    pub fn is_notification_pending(&self) -> bool {
+        let inner = self.inner.borrow();
+        let notify_state = inner.notify_state.borrow();
+        notify_state.is_pending() || notify_state.coalesced.is_some()
+    }
+
+    /// Returns how requests for this resource are handled while notifications are pending.
+    pub fn notification_consistency(&self) -> NotificationConsistency {
+        self.inner.borrow().notify_state.borrow().consistency
+    }
+
+    /// Sets how requests for this resource are handled while notifications are pending (see
+    /// [NotificationConsistency]).
+    pub fn set_notification_consistency(&self, consistency: NotificationConsistency) {
+        self.inner.borrow().notify_state.borrow_mut().consistency = consistency;
+    }
+
+    /// Returns whether blocks of block-wise uploads are passed to the request handlers of this
+    /// resource individually (see [ResourceFlags::STREAM_BLOCK1]).
+    pub fn streams_block1(&self) -> bool {
+        self.inner.borrow().stream_block1
+    }
+
+    /// Returns the current time of the clock of the context this resource has been added to (or
+    /// of the system clock if it has not been added to a context yet).
+    fn now(&self) -> Instant {
+        self.inner
+            .borrow()
+            .context_shared
+            .as_ref()
+            .map_or_else(Instant::now, |shared| shared.now())
+    }
+
+    /// Returns the raw resource that libcoap uses for requests to the path of this resource, i.e.,
+    /// the raw resource that routes requests to the resources for different hosts (see
+    /// [CoapResourceBuilder::host()]) or the raw resource of this resource.
+    fn routing_raw_resource(&self) -> *mut coap_resource_t {
+        let inner = self.inner.borrow();
+        inner
+            .context_shared
+            .as_ref()
+            .and_then(|shared| shared.virtual_host_paths.borrow().get(self.uri_path()).copied())
+            .unwrap_or(inner.raw_resource)
+    }
+
+    /// Retains the shared state of the context this resource is added to, see
+    /// [CoapResourceInner::context_shared].
+    pub(crate) fn retain_context_shared(&self, shared: Rc<CoapContextShared>) {
+        self.inner.borrow_mut().context_shared = Some(shared);
+    }
+
+    /// Returns the notification state of this resource, which is shared with the context.
+    pub(crate) fn notify_state(&self) -> Rc<RefCell<CoapResourceNotifyState>> {
+        self.inner.borrow().notify_state.clone()
+    }
+
+    /// Returns a weak reference to the inner resource, see
+    /// [CoapResourceHandle](crate::CoapResourceHandle).
+    pub(crate) fn downgrade(&self) -> CoapFfiWeakCell<CoapResourceInner<D>> {
+        self.inner.downgrade()
+    }
+
+    /// Returns whether this resource has been removed from its context (see
+    /// [CoapResourceHandle::remove()](crate::CoapResourceHandle::remove)).
+    pub(crate) fn is_removed(&self) -> bool {
+        self.inner.borrow().removed
+    }
+
+    /// Returns whether the given request is meant for this resource, i.e., whether its Uri-Path
+    /// options match the path of this resource (see [CoapResourcePath]).
+    ///
+    /// Resources receiving requests for unknown paths match all requests.
+    fn matches_request_path(&self, request: &CoapRequest) -> bool {
+        self.inner
+            .borrow()
+            .path
+            .as_ref()
+            .map_or(true, |path| path.matches_request(request))
+    }
+
+    /// Marks this resource as removed from its context, so that requests that libcoap still passes
+    /// to it are answered with 4.04 (Not Found).
+    pub(crate) fn mark_removed(&self) {
+        self.inner.borrow_mut().removed = true;
+    }
+
+    /// Sets whether this resource can be observed by clients according to
+    /// [RFC 7641](https://datatracker.ietf.org/doc/html/rfc7641).
+    pub fn set_get_observable(&self, observable: bool) {
+        let mut inner = self.inner.borrow_mut();
+        inner.observable = observable;
+        // SAFETY: Resource is valid as long as CoapResourceInner exists, query is currently unused.
+        unsafe { coap_resource_set_get_observable(inner.raw_resource, observable as c_int) }
+        std::mem::drop(inner);
+        let routing_resource = self.routing_raw_resource();
+        if observable && routing_resource != self.inner.borrow().raw_resource {
+            // SAFETY: Routing resources are valid while they are listed in the shared context
+            // state.
+            unsafe { coap_resource_set_get_observable(routing_resource, 1) }
+        }
+    }
+
+    /// Returns whether this resource can be observed (see [CoapResource::set_get_observable()]).
+    pub(crate) fn is_observable(&self) -> bool {
+        self.inner.borrow().observable
+    }
+
+    /// Returns the URI path of this resource, or None if the resource receives requests for all
+    /// paths that no other resource is responsible for (e.g., the resource of a reverse proxy, see
+    /// [CoapContext::add_reverse_proxy()]).
+    pub fn path(&self) -> Option<CoapResourcePath> {
+        self.inner.borrow().path.clone()
+    }
+
+    /// Returns the host this resource is restricted to (in lowercase), if any (see
+    /// [CoapResourceBuilder::host()]).
+    pub fn host(&self) -> Option<String> {
+        self.inner.borrow().host.clone()
+    }
+
+    /// Adds a link attribute (e.g., `rt` or `if`) to the description of this resource in the
+    /// `/.well-known/core` resource (see [RFC 6690](https://datatracker.ietf.org/doc/html/rfc6690)).
+    ///
+    /// The value is added to the link format as-is, so string values have to be enclosed in
+    /// quotes (e.g., `"\"temperature-c\""`). Attributes without a value (e.g. `obs`) can be added
+    /// by providing None as the value.
+    pub fn add_attribute(&self, name: &str, value: Option<&str>) {
+        // SAFETY: Resource is valid as long as CoapResourceInner exists, name and value are
+        // released by libcoap together with the attribute.
+        unsafe {
+            let raw_name = coap_new_str_const(name.as_ptr(), name.len());
+            let raw_value = value.map_or(std::ptr::null_mut(), |v| coap_new_str_const(v.as_ptr(), v.len()));
+            coap_add_attr(
+                self.inner.borrow().raw_resource,
+                raw_name,
+                raw_value,
+                (COAP_ATTR_FLAGS_RELEASE_NAME | COAP_ATTR_FLAGS_RELEASE_VALUE) as c_int,
+            );
+        }
+    }
+
+    /// Sets whether observe notifications for this resource should be sent as confirmable or
+    /// non-confirmable CoAP messages.
+    ///
+    /// This default can be overridden for individual notifications or observers or using a
+    /// notification type policy, see [CoapResource::notify_observers_with()].
+    pub fn set_observe_notify_confirmable(&self, confirmable: bool) {
+        let mut inner = self.inner.borrow_mut();
+        inner.notify_con = confirmable;
+        // SAFETY: Resource is valid as long as CoapResourceInner exists.
+        unsafe { coap_resource_set_mode(inner.raw_resource, raw_notify_mode(confirmable)) }
+    }
+
+    /// Sets the observe number that is used for the next notification of this resource.
+    ///
+    /// This is used to restore the observe number of a resource after a restart of the server
+    /// (see [CoapPersistHandler::observe_number_changed()](crate::persist::CoapPersistHandler::observe_number_changed)),
+    /// so that clients do not discard notifications as outdated.
+    pub fn set_observe_number(&self, observe_number: u32) {
+        // SAFETY: Resource is valid as long as CoapResourceInner exists.
+        unsafe { coap_persist_set_observe_num(self.inner.borrow().raw_resource, observe_number) }
+    }
+
+    /// Returns the user data associated with this resource.
+    ///
+    /// The user data can be accessed from within request handlers (including handlers of other
+    /// resources), except for handlers created using [CoapRequestHandler::new()], which hold a
+    /// mutable borrow of the user data of their resource while they are called.
+    ///
+    /// # Panics
+    /// Panics if the user data is currently borrowed mutably, use [CoapResource::try_user_data()]
+    /// to handle this case.
+    pub fn user_data(&self) -> Ref<D> {
+        self.try_user_data()
+            .unwrap_or_else(|e| panic!("unable to borrow user data of resource: {}", e))
+    }
+
+    /// Mutably returns the user data associated with this resource.
+    ///
+    /// See [CoapResource::user_data()] for restrictions when accessing the user data from within
+    /// request handlers.
+    ///
+    /// # Panics
+    /// Panics if the user data is currently borrowed, use [CoapResource::try_user_data_mut()] to
+    /// handle this case.
+    ///
+    /// # Examples
+    /// A counter resource whose GET handler increments its (typed) user data:
+    /// ```no_run
+    /// use libcoap_rs::{CoapContext, CoapRequestHandler, CoapResource};
+    /// use libcoap_rs::message::{CoapMessageCommon, CoapRequest, CoapResponse};
+    /// use libcoap_rs::protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode};
+    /// use libcoap_rs::session::{CoapServerSession, CoapSessionCommon};
+    ///
+    /// let mut context = CoapContext::new().unwrap();
+    /// let counter = CoapResource::new("counter", 0u64, false);
+    /// counter.set_method_handler(
+    ///     CoapRequestCode::Get,
+    ///     Some(CoapRequestHandler::new_resource_ref(
+    ///         |resource: &CoapResource<u64>, session: &mut CoapServerSession, _: &CoapRequest, mut response: CoapResponse| {
+    ///             let mut count = resource.user_data_mut();
+    ///             *count += 1;
+    ///             response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+    ///             response.set_data(Some(count.to_string().into_bytes()));
+    ///             session.send(response).unwrap();
+    ///         },
+    ///     )),
+    /// );
+    /// context.add_resource(counter);
+    ///
+    /// // The user data can also be accessed outside of handlers.
+    /// let counter = context.typed_resource_by_uri_path::<u64>("counter").unwrap();
+    /// assert_eq!(*counter.user_data(), 0);
+    /// ```
+    pub fn user_data_mut(&self) -> RefMut<D> {
+        self.try_user_data_mut()
+            .unwrap_or_else(|e| panic!("unable to mutably borrow user data of resource: {}", e))
+    }
+
+    /// Returns the user data associated with this resource, failing if it is currently borrowed
+    /// mutably.
+    ///
+    /// # Errors
+    /// Returns [ResourceUserDataError::BorrowedMutably] if the user data is currently borrowed
+    /// mutably (see [CoapResource::user_data()]).
+    pub fn try_user_data(&self) -> Result<Ref<D>, ResourceUserDataError> {
+        self.inner
+            .try_borrow()
+            .map(|v| Ref::map(v, |v| v.user_data.as_ref()))
+            .ok_or(ResourceUserDataError::BorrowedMutably)
+    }
+
+    /// Mutably returns the user data associated with this resource, failing if it is currently
+    /// borrowed.
+    ///
+    /// # Errors
+    /// Returns [ResourceUserDataError::Borrowed] if the user data is currently borrowed (see
+    /// [CoapResource::user_data()]).
+    pub fn try_user_data_mut(&self) -> Result<RefMut<D>, ResourceUserDataError> {
+        self.inner
+            .try_borrow_mut()
+            .map(|v| RefMut::map(v, |v| v.user_data.as_mut()))
+            .ok_or(ResourceUserDataError::Borrowed)
+    }
+
+    /// Creates a new handle to this resource.
+    pub(crate) fn clone_handle(&self) -> CoapResource<D> {
+        CoapResource::from(self.inner.clone())
+    }
+
+    /// Returns the ETag of the current representation of this resource, if one has been set.
+    pub fn etag(&self) -> Option<ETag> {
+        self.inner.borrow().etag.clone()
+    }
+
+    /// Sets the ETag of the current representation of this resource.
+    ///
+    /// The ETag should be updated whenever the representation changes, e.g. from within the PUT
+    /// or POST handler of this resource (using a handler created with
+    /// [CoapRequestHandler::new_resource_ref()]).
+    ///
+    /// If an ETag is set and ETag validation is enabled (see [CoapResource::set_etag_validation()]),
+    /// requests are validated against it before the request handler is called:
+    /// - GET and FETCH requests whose ETag options contain the current ETag are answered with
+    ///   2.03 Valid (and an empty payload) without calling the handler. For all other GET and
+    ///   FETCH requests, the response provided to the handler already contains the current ETag,
+    ///   which is therefore also used for all blocks of block-wise transfers.
+    /// - For all other methods, the If-Match and If-None-Match options are evaluated according to
+    ///   [RFC 7252, Section 5.10.8](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.8),
+    ///   and the request is answered with 4.12 Precondition Failed if the preconditions are not
+    ///   met.
+    ///
+    /// `etag` must not be longer than 8 bytes.
+    ///
+    /// # Panics
+    /// Panics if `etag` is empty or longer than 8 bytes.
+    pub fn set_etag(&self, etag: Option<ETag>) {
+        if let Some(etag) = &etag {
+            assert!(
+                (1..=8).contains(&etag.len()),
+                "ETag must have a length between 1 and 8 bytes"
+            );
+        }
+        self.inner.borrow_mut().etag = etag;
+    }
+
+    /// Sets the ETag of the current representation of this resource to one generated from the
+    /// given representation (see [CoapResource::set_etag()]).
+    ///
+    /// The generated ETag is an 8 byte hash of `content`, i.e. it changes whenever the
+    /// representation changes (barring collisions) and is stable across server restarts.
+    pub fn set_etag_from_content(&self, content: &[u8]) {
+        self.set_etag(Some(generate_etag(content)))
+    }
+
+    /// Returns whether requests are validated against the ETag of this resource automatically.
+    pub fn etag_validation(&self) -> bool {
+        self.inner.borrow().etag_validation
+    }
+
+    /// Enables or disables automatic validation of requests against the ETag of this resource
+    /// (enabled by default, see [CoapResource::set_etag()] for the checks that are performed).
+    ///
+    /// If validation is disabled, all requests are passed to the request handler, which is then
+    /// responsible for handling the ETag, If-Match and If-None-Match options itself.
+    pub fn set_etag_validation(&self, enabled: bool) {
+        self.inner.borrow_mut().etag_validation = enabled;
+    }
+
+    /// Returns the policy that determines which requests to this resource need to contain a valid
+    /// Echo value.
+    pub fn echo_policy(&self) -> CoapEchoPolicy {
+        self.inner.borrow().echo_policy
+    }
+
+    /// Sets the policy that determines which requests to this resource need to contain a valid
+    /// Echo value ([RFC 9175, Section 2](https://datatracker.ietf.org/doc/html/rfc9175#section-2)),
+    /// [CoapEchoPolicy::Disabled] by default.
+    ///
+    /// Requests to which the policy applies are answered with a 4.01 (Unauthorized) response
+    /// containing a fresh Echo value unless they contain a valid Echo value, the request handler
+    /// is only called for the repeated request. Echo values are generated and verified by the
+    /// provider set using
+    /// [CoapContext::set_echo_value_provider()](crate::CoapContext::set_echo_value_provider).
+    pub fn set_echo_policy(&self, policy: CoapEchoPolicy) {
+        self.inner.borrow_mut().echo_policy = policy;
+    }
+
+    /// Returns the maximum size of request bodies (in bytes) accepted by this resource, if
+    /// limited.
+    pub fn max_request_size(&self) -> Option<usize> {
+        self.inner.borrow().max_request_size
+    }
+
+    /// Limits the size of request bodies (in bytes) accepted by this resource, or removes the
+    /// limit if `max_size` is None (the default).
+    ///
+    /// Requests exceeding the limit are answered with 4.13 (Request Entity Too Large), with a
+    /// Size1 option indicating the limit (see [CoapBlock1Chunk::abort_too_large()]), instead of
+    /// calling the request handler.
+    ///
+    /// For resources that receive block-wise uploads block by block (see
+    /// [ResourceFlags::STREAM_BLOCK1]), uploads are rejected early: as soon as a block announces
+    /// a total size (using the Size1 option) exceeding the limit, or, if the client does not
+    /// announce the size, as soon as the received blocks exceed the limit. For all other
+    /// resources, libcoap reassembles the body before the wrapper sees the request, so oversized
+    /// uploads are only rejected once they have been received completely.
+    pub fn set_max_request_size(&self, max_size: Option<usize>) {
+        self.inner.borrow_mut().max_request_size = max_size;
+    }
 
-impl<D: Any + ?Sized + Debug> CoapResourceHandlers<D> {
-    #[inline]
-    fn handler(&self, code: CoapRequestCode) -> Option<&CoapRequestHandler<D>> {
-        match code {
-            CoapRequestCode::Get => self.get.as_ref(),
-            CoapRequestCode::Put => self.put.as_ref(),
-            CoapRequestCode::Delete => self.delete.as_ref(),
-            CoapRequestCode::Post => self.post.as_ref(),
-            CoapRequestCode::Fetch => self.fetch.as_ref(),
-            CoapRequestCode::IPatch => self.ipatch.as_ref(),
-            CoapRequestCode::Patch => self.patch.as_ref(),
-        }
+    /// Returns the limits for the options and payloads of requests to this resource, if it does
+    /// not use the ones of its context (see [CoapResource::set_input_limits()]).
+    pub fn input_limits(&self) -> Option<CoapInputLimits> {
+        self.inner.borrow().input_limits
     }
 
-    #[inline]
-    // Clippy complains about this being unused, but I'd like to keep it for consistency.
-    #[allow(unused)]
-    fn handler_mut(&mut self, code: CoapRequestCode) -> Option<&mut CoapRequestHandler<D>> {
-        match code {
-            CoapRequestCode::Get => self.get.as_mut(),
-            CoapRequestCode::Put => self.put.as_mut(),
-            CoapRequestCode::Delete => self.delete.as_mut(),
-            CoapRequestCode::Post => self.post.as_mut(),
-            CoapRequestCode::Fetch => self.fetch.as_mut(),
-            CoapRequestCode::IPatch => self.ipatch.as_mut(),
-            CoapRequestCode::Patch => self.patch.as_mut(),
-        }
+    /// Sets the limits for the options and payloads of requests to this resource, or uses the
+    /// ones of its context if `limits` is None (the default, see
+    /// [CoapContext::set_input_limits()]).
+    ///
+    /// Requests exceeding the limits are rejected before the request handler (or the limit set
+    /// using [CoapResource::set_max_request_size()]) is applied, see the [limits](crate::limits)
+    /// module for details.
+    pub fn set_input_limits(&self, limits: Option<CoapInputLimits>) {
+        self.inner.borrow_mut().input_limits = limits;
     }
 
-    // Kept for consistency
-    #[allow(unused)]
-    #[inline]
-    fn handler_ref(&self, code: CoapRequestCode) -> &Option<CoapRequestHandler<D>> {
-        match code {
-            CoapRequestCode::Get => &self.get,
-            CoapRequestCode::Put => &self.put,
-            CoapRequestCode::Delete => &self.delete,
-            CoapRequestCode::Post => &self.post,
-            CoapRequestCode::Fetch => &self.fetch,
-            CoapRequestCode::IPatch => &self.ipatch,
-            CoapRequestCode::Patch => &self.patch,
-        }
+    /// Returns the endpoints this resource is restricted to, if any (see
+    /// [CoapResource::set_endpoints()]).
+    pub fn endpoints(&self) -> Option<Vec<CoapEndpointHandle>> {
+        self.inner.borrow().endpoints.clone()
     }
 
-    #[inline]
-    fn handler_ref_mut(&mut self, code: CoapRequestCode) -> &mut Option<CoapRequestHandler<D>> {
-        match code {
-            CoapRequestCode::Get => &mut self.get,
-            CoapRequestCode::Put => &mut self.put,
-            CoapRequestCode::Delete => &mut self.delete,
-            CoapRequestCode::Post => &mut self.post,
-            CoapRequestCode::Fetch => &mut self.fetch,
-            CoapRequestCode::IPatch => &mut self.ipatch,
-            CoapRequestCode::Patch => &mut self.patch,
-        }
+    /// Restricts this resource to requests received on the given endpoints, or allows requests
+    /// received on all endpoints if `endpoints` is None (the default).
+    ///
+    /// Requests received on other endpoints (see
+    /// [CoapServerSession::endpoint()](crate::session::CoapServerSession::endpoint)) are answered
+    /// with 4.04 (Not Found) before the access hook or the request handler is called, so the
+    /// resource appears not to exist on these endpoints. This allows serving different resources
+    /// on, e.g., an endpoint bound to a management interface and one bound to a public interface.
+    ///
+    /// Note that endpoints are referred to by their handles, so resources have to be restricted to
+    /// the replacement of an endpoint in addition to the endpoint itself if it is rebound (see
+    /// [CoapContext::rebind_endpoint()]). The resource is still listed in `/.well-known/core` on
+    /// all endpoints, unless a custom handler filters it (see
+    /// [CoapContext::set_wellknown_core_mode()](crate::CoapContext::set_wellknown_core_mode)).
+    pub fn set_endpoints(&self, endpoints: Option<Vec<CoapEndpointHandle>>) {
+        self.inner.borrow_mut().endpoints = endpoints;
     }
-}
 
-/// Inner part of a [CoapResource], which is referenced inside the raw resource and might be
-/// referenced multiple times, e.g. outside and inside of a resource handler.
-#[derive(Debug)]
-pub(crate) struct CoapResourceInner<D: Any + ?Sized + Debug> {
-    raw_resource: *mut coap_resource_t,
-    user_data: Box<D>,
-    handlers: CoapResourceHandlers<D>,
-}
+    /// Returns whether requests received on the endpoint of the given session may be passed to
+    /// this resource (see [CoapResource::set_endpoints()]).
+    fn serves_endpoint_of(&self, session: &CoapServerSession) -> bool {
+        let inner = self.inner.borrow();
+        let Some(endpoints) = &inner.endpoints else {
+            return true;
+        };
+        session.endpoint().is_some_and(|endpoint| endpoints.contains(&endpoint))
+    }
 
-impl<D: Any + ?Sized + Debug> CoapResource<D> {
-    /// Creates a new CoapResource for the given `uri_path`.
+    /// Sets the hook that decides whether requests to this resource are passed to the request
+    /// handler, replacing any previously set hook (see the [access](crate::access) module).
     ///
-    /// Handlers that are associated with this resource have to be able to take a reference to the
-    /// provided `user_data` value as their first value.
+    /// The hook is called with the security parameters of the session, the method and the request
+    /// before the request handler (and before the Echo policy is applied, see
+    /// [CoapResource::set_echo_policy()]). If no hook is set for this resource, the default hook
+    /// of its context is used (see
+    /// [CoapContext::set_default_access_hook()](crate::CoapContext::set_default_access_hook)).
+    pub fn set_access_hook<
+        F: Fn(Option<&CoapCryptoSessionInfo>, CoapRequestCode, &CoapRequest) -> CoapAccessDecision + 'static,
+    >(
+        &self,
+        hook: F,
+    ) {
+        self.inner.borrow_mut().access_hook = Some(AccessHookHandle::new(hook));
+    }
+
+    /// Removes the access hook of this resource (if any), see [CoapResource::set_access_hook()].
     ///
-    /// The `notify_con` parameter specifies whether observe notifications originating from this
-    /// resource are sent as confirmable or non-confirmable.
-    pub fn new<C: Into<Box<D>>>(uri_path: &str, user_data: C, notify_con: bool) -> CoapResource<D> {
-        ensure_coap_started();
-        let inner = unsafe {
-            let uri_path = coap_new_str_const(uri_path.as_ptr(), uri_path.len());
-            let raw_resource = coap_resource_init(
-                uri_path,
-                (COAP_RESOURCE_FLAGS_RELEASE_URI
-                    | if notify_con {
-                        COAP_RESOURCE_FLAGS_NOTIFY_CON
-                    } else {
-                        COAP_RESOURCE_FLAGS_NOTIFY_NON
-                    }) as i32,
-            );
-            let inner = CoapFfiRcCell::new(CoapResourceInner {
-                raw_resource,
-                user_data: user_data.into(),
-                handlers: CoapResourceHandlers::default(),
-            });
-            coap_resource_set_userdata(raw_resource, inner.create_raw_weak());
+    /// Requests to this resource are then checked using the default hook of the context (if any).
+    pub fn clear_access_hook(&self) {
+        self.inner.borrow_mut().access_hook = None;
+    }
+
+    /// Returns the access hook that applies to this resource, i.e., either its own hook or the
+    /// default hook of the context it has been added to.
+    fn effective_access_hook(&self) -> Option<AccessHookHandle> {
+        let inner = self.inner.borrow();
+        inner.access_hook.clone().or_else(|| {
             inner
-        };
-        Self::from(inner)
+                .context_shared
+                .as_ref()
+                .and_then(|shared| shared.default_access_hook.borrow().clone())
+        })
     }
 
-    /// Notify any observers about changes to this resource.
-    pub fn notify_observers(&self) -> bool {
-        // SAFETY: Resource is valid as long as CoapResourceInner exists, query is currently unused.
-        unsafe { coap_resource_notify_observers(self.inner.borrow_mut().raw_resource, std::ptr::null_mut()) != 0 }
+    /// Validates the given request against the ETag of this resource, returning the response
+    /// code the request should be answered with if the request handler should not be called.
+    fn evaluate_etag(&self, request: &CoapRequest) -> Option<CoapResponseCode> {
+        let inner = self.inner.borrow();
+        let etag = match (&inner.etag, inner.etag_validation) {
+            (Some(etag), true) => etag,
+            _ => return None,
+        };
+        match request.code() {
+            CoapMessageCode::Request(CoapRequestCode::Get | CoapRequestCode::Fetch) => request
+                .etag()
+                .is_some_and(|etags| etags.contains(etag))
+                .then_some(CoapResponseCode::Valid),
+            _ => {
+                let if_match_failed = request.if_match().is_some_and(|matches| {
+                    !matches.iter().any(|v| match v {
+                        CoapMatch::ETag(value) => value == etag,
+                        // The resource has a current representation, as it has an ETag.
+                        CoapMatch::Empty => true,
+                    })
+                });
+                (if_match_failed || request.if_none_match()).then_some(CoapResponseCode::PreconditionFailed)
+            },
+        }
     }
 
-    /// Sets whether this resource can be observed by clients according to
-    /// [RFC 7641](https://datatracker.ietf.org/doc/html/rfc7641).
-    pub fn set_get_observable(&self, observable: bool) {
-        // SAFETY: Resource is valid as long as CoapResourceInner exists, query is currently unused.
-        unsafe { coap_resource_set_get_observable(self.inner.borrow_mut().raw_resource, observable as c_int) }
+    /// Returns a snapshot of the request statistics collected for this resource.
+    pub fn stats(&self) -> CoapResourceStats {
+        let mut stats = self.inner.borrow().stats.borrow().clone();
+        stats.active_observers = self.observer_count();
+        stats
     }
 
-    /// Sets whether observe notifications for this resource should be sent as confirmable or
-    /// non-confirmable CoAP messages.
-    pub fn set_observe_notify_confirmable(&self, confirmable: bool) {
-        // SAFETY: Resource is valid as long as CoapResourceInner exists, query is currently unused.
-        unsafe { coap_resource_set_mode(self.inner.borrow_mut().raw_resource, confirmable as c_int) }
+    /// Resets the request statistics collected for this resource.
+    pub fn reset_stats(&self) {
+        *self.inner.borrow().stats.borrow_mut() = CoapResourceStats::default();
     }
 
-    /// Returns the user data associated with this resource.
-    pub fn user_data(&self) -> Ref<D> {
-        Ref::map(self.inner.borrow(), |v| v.user_data.as_ref())
+    /// Dispatches a request to the provided handler function, updating the request statistics
+    /// of this resource.
+    ///
+    /// This function is not intended for public use, the only reason it is public is that the
+    /// [resource_handler!] macro requires this function.
+    #[doc(hidden)]
+    pub fn dispatch_request<F: FnOnce(&mut CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        resource: &mut CoapResource<D>,
+        session: &mut CoapServerSession,
+        request: &CoapRequest,
+        response: CoapResponse,
+        handler: F,
+    ) {
+        // Responses of suppressed classes are dropped by the session while the request is handled
+        // (RFC 7967).
+        set_suppressed_responses(session, request.suppressed_responses());
+        Self::dispatch_request_with_suppression(resource, session, request, response, handler);
+        set_suppressed_responses(session, CoapNoResponse::empty());
+        set_handled_request(session, None);
     }
 
-    /// Mutably returns the user data associated with this resource.
-    pub fn user_data_mut(&self) -> RefMut<D> {
-        RefMut::map(self.inner.borrow_mut(), |v| v.user_data.as_mut())
+    fn dispatch_request_with_suppression<
+        F: FnOnce(&mut CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse),
+    >(
+        resource: &mut CoapResource<D>,
+        session: &mut CoapServerSession,
+        request: &CoapRequest,
+        mut response: CoapResponse,
+        handler: F,
+    ) {
+        #[cfg(feature = "async")]
+        if request.is_async_replay() {
+            if let Some(async_response) = finish_async_replay(session, request) {
+                // The response was provided using CoapAsyncHandle::complete(), libcoap determines
+                // the message type of the separate response.
+                let _ = session.send(async_response.in_reply_to(&response));
+                return;
+            }
+        }
+        if resource.is_removed() || !resource.matches_request_path(request) || !resource.serves_endpoint_of(session) {
+            // libcoap passes requests to removed resources until the context has released them, and
+            // only compares the Uri-Path options of requests joined by `/` to the path of resources
+            // (see CoapResourcePath::to_raw()). Resources restricted to other endpoints are hidden
+            // in the same way.
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::NotFound));
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+            return;
+        }
+        if refuses_requests(session) {
+            // The session was created on a draining endpoint (see CoapContext::rebind_endpoint())
+            // or while sessions exceeded their memory limit (see CoapContext::set_memory_limits()).
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable));
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+            return;
+        }
+        // Notifications (and repeated registrations) of existing observers as well as asynchronous
+        // requests that are passed to the handler again are not rate limited.
+        if !request.is_async_replay() && !resource.is_observer_request(session, request) {
+            // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+            let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+            if let Some((action, retry_after)) = context.apply_rate_limit(session, request) {
+                if action == CoapRateLimitAction::Reject {
+                    // Max-Age indicates the number of seconds until the next request is accepted
+                    // (RFC 8516, Section 3), rounded up.
+                    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    response.set_code(CoapMessageCode::Response(CoapResponseCode::TooManyRequests));
+                    response.set_max_age(Some(MaxAge::try_from(seconds.max(1)).unwrap_or(MaxAge::MAX)));
+                    response.set_data(None::<Vec<u8>>);
+                    // If sending fails, libcoap will answer the request with an empty ACK instead.
+                    let _ = session.send(response);
+                }
+                return;
+            }
+        }
+        if let CoapMessageCode::Request(code) = request.code() {
+            // The hook is cloned before calling it, so that it can access the resource.
+            let decision = resource
+                .effective_access_hook()
+                .map_or(CoapAccessDecision::Allow, |hook| {
+                    hook.decide(session.crypto_info().as_ref(), code, request)
+                });
+            let challenge = match decision {
+                CoapAccessDecision::Allow => false,
+                CoapAccessDecision::Deny {
+                    code: response_code,
+                    diagnostic,
+                } => {
+                    response.set_code(CoapMessageCode::Response(response_code));
+                    response.set_data(diagnostic.map(String::into_bytes));
+                    // If sending fails, libcoap will answer the request with an empty ACK instead.
+                    let _ = session.send(response);
+                    // libcoap ends observations whose notifications are answered with errors.
+                    resource.track_observer(session, request, false, None);
+                    return;
+                },
+                CoapAccessDecision::Challenge => true,
+            };
+            if challenge || resource.echo_policy().applies_to(code) {
+                // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+                let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+                if let Err(echo) = context.verify_echo(session, request) {
+                    let code = match echo {
+                        Some(_) => CoapResponseCode::Unauthorized,
+                        None => CoapResponseCode::ServiceUnavailable,
+                    };
+                    response.set_code(CoapMessageCode::Response(code));
+                    response.set_echo(echo);
+                    response.set_data(None::<Vec<u8>>);
+                    // If sending fails, libcoap will answer the request with an empty ACK instead.
+                    let _ = session.send(response);
+                    resource.track_observer(session, request, false, None);
+                    return;
+                }
+            }
+        }
+        if let Some(max_size) = resource.max_request_size() {
+            let chunk = CoapBlock1Chunk::from_request(request);
+            if chunk.total_size().is_some_and(|size| size > max_size) || chunk.offset() + chunk.data().len() > max_size
+            {
+                chunk.abort_too_large(&mut response, max_size);
+                // If sending fails, libcoap will answer the request with an empty ACK instead.
+                let _ = session.send(response);
+                return;
+            }
+        }
+        if resource.take_cancelled_observer(session, request) {
+            // libcoap ends the observation once a notification with an error code is sent.
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable));
+            response.set_data(None::<Vec<u8>>);
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+            return;
+        }
+        if request.code() == CoapMessageCode::Request(CoapRequestCode::Get) && request.observe().is_none() {
+            let inner = resource.inner.borrow();
+            let mut notify_state = inner.notify_state.borrow_mut();
+            if notify_state.consistency == NotificationConsistency::ServeAfterPendingNotify && notify_state.is_pending()
+            {
+                if let Some(handler) = inner.handlers.handler(CoapRequestCode::Get) {
+                    // SAFETY: The session pointer is valid, the reference is released once the
+                    // deferred request is dropped.
+                    let raw_session = unsafe { coap_session_reference(session.raw_session_mut()) };
+                    notify_state.deferred.push(DeferredRequest {
+                        raw_resource: inner.raw_resource,
+                        raw_session,
+                        raw_handler: handler.raw_handler,
+                        request: request.clone(),
+                    });
+                    // Not sending a response here causes libcoap to send an empty ACK.
+                    return;
+                }
+            }
+        }
+        let stats = resource.inner.borrow().stats.clone();
+        if let CoapMessageCode::Request(code) = request.code() {
+            *stats.borrow_mut().requests.entry(code).or_default() += 1;
+        }
+        set_response_stats(session, Some(stats.clone()));
+        let start = Instant::now();
+        match resource.evaluate_etag(request) {
+            Some(code) => {
+                if code == CoapResponseCode::Valid {
+                    response.set_etag(resource.etag());
+                }
+                response.set_code(CoapMessageCode::Response(code));
+                response.set_data(None::<Vec<u8>>);
+                // If sending fails, libcoap will answer the request with an empty ACK instead.
+                let _ = session.send(response);
+            },
+            None => {
+                if matches!(
+                    request.code(),
+                    CoapMessageCode::Request(CoapRequestCode::Get | CoapRequestCode::Fetch)
+                ) && resource.etag_validation()
+                {
+                    response.set_etag(resource.etag());
+                }
+                let mut fallback_response = response.clone();
+                let responses_sent = || stats.borrow().responses.values().sum::<u64>();
+                let success_responses_sent = || stats.borrow().responses.get(&2).copied().unwrap_or(0);
+                let responses_before = responses_sent();
+                let success_responses_before = success_responses_sent();
+                let snapshot = resource.notification_snapshot(request);
+                // Replayed notification requests (see send_paced_notifications()) are answered using
+                // a separate response with the Observe value set here.
+                let replayed_observe = response.observe();
+                match catch_unwind(AssertUnwindSafe(|| match snapshot {
+                    Some((snapshot, observe)) => snapshot.respond(session, observe, response),
+                    None => handler(resource, session, request, response),
+                })) {
+                    Ok(()) => {
+                        if request.code() == CoapMessageCode::Request(CoapRequestCode::Get)
+                            && request.observe().is_some()
+                        {
+                            resource.apply_notify_type(session, request);
+                            let succeeded = success_responses_sent() != success_responses_before;
+                            // libcoap adds the Observe option while the response is sent.
+                            let observe = handled_response_observe(session).or(replayed_observe);
+                            resource.track_observer(session, request, succeeded, observe);
+                        }
+                    },
+                    Err(payload) => {
+                        // SAFETY: The raw session of a wrapped session is always valid.
+                        unsafe { record_panic(coap_session_get_context(session.raw_session()), payload) };
+                        // Answer the request with an error, unless the handler already sent a
+                        // response before panicking.
+                        if responses_sent() == responses_before {
+                            fallback_response.set_code(CoapMessageCode::Response(CoapResponseCode::InternalError));
+                            fallback_response.set_etag(None);
+                            fallback_response.set_data(None::<Vec<u8>>);
+                            // If sending fails, libcoap will answer the request with an empty ACK
+                            // instead.
+                            let _ = session.send(fallback_response);
+                        }
+                    },
+                }
+            },
+        }
+        stats.borrow_mut().total_handler_duration += start.elapsed();
+        set_response_stats(session, None);
     }
 
     /// Restores a resource from its raw [coap_resource_t](libcoap_sys::coap_resource_t).
@@ -308,6 +2784,10 @@ impl<D: Any + ?Sized + Debug> CoapResource<D> {
     pub fn set_method_handler<H: Into<CoapRequestHandler<D>>>(&self, code: CoapRequestCode, handler: Option<H>) {
         let mut inner = self.inner.borrow_mut();
         *inner.handlers.handler_ref_mut(code) = handler.map(|v| v.into());
+        if inner.routes_virtual_hosts {
+            // Requests are passed to the handlers by virtual_host_handler().
+            return;
+        }
         unsafe {
             coap_register_request_handler(
                 inner.raw_resource,
@@ -343,22 +2823,28 @@ impl<D: Any + ?Sized + Debug> CoapResource<D> {
             .expect("attempted to call dynamic handler for method that has no handler set");
         std::mem::drop(inner);
 
-        (handler_fn
-            .dynamic_handler_function
-            .as_mut()
-            .expect("attempted to call dynamic handler for method that has no dynamic handler set"))(
-            self,
-            session,
-            req_message,
-            rsp_message,
-        );
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            (handler_fn
+                .dynamic_handler_function
+                .as_mut()
+                .expect("attempted to call dynamic handler for method that has no dynamic handler set"))(
+                self,
+                session,
+                req_message,
+                rsp_message,
+            )
+        }));
 
-        // Put the handler function back into the resource, unless the handler was replaced.
+        // Put the handler function back into the resource, unless the handler was replaced (this
+        // has to happen even if the handler panicked, as it would be lost otherwise).
         self.inner
             .borrow_mut()
             .handlers
             .handler_ref_mut(req_code)
             .get_or_insert(handler_fn);
+        if let Err(payload) = result {
+            resume_unwind(payload);
+        }
     }
 }
 
@@ -370,6 +2856,18 @@ impl<D: Any + ?Sized + Debug> UntypedCoapResource for CoapResource<D> {
         }
     }
 
+    fn path(&self) -> Option<CoapResourcePath> {
+        CoapResource::path(self)
+    }
+
+    fn notify_observers(&self) -> bool {
+        CoapResource::notify_observers(self)
+    }
+
+    fn flush_coalesced_notification(&self) {
+        CoapResource::flush_coalesced_notification(self)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self as &(dyn Any)
     }
@@ -378,9 +2876,131 @@ impl<D: Any + ?Sized + Debug> UntypedCoapResource for CoapResource<D> {
         self.inner.drop_exclusively();
     }
 
+    fn drop_handlers(&self) {
+        let handlers = std::mem::take(&mut self.inner.borrow_mut().handlers);
+        // Drop the handlers after releasing the borrow, as they may refer to this resource.
+        std::mem::drop(handlers);
+    }
+
     unsafe fn raw_resource(&mut self) -> *mut coap_resource_t {
         self.inner.borrow_mut().raw_resource
     }
+
+    fn host(&self) -> Option<String> {
+        CoapResource::host(self)
+    }
+
+    fn raw_method_handler(&self, code: CoapRequestCode) -> Option<RawRequestHandler> {
+        self.inner.borrow().handlers.handler(code).map(|h| h.raw_handler)
+    }
+
+    fn route_virtual_hosts(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.routes_virtual_hosts = true;
+        for code in [
+            CoapRequestCode::Get,
+            CoapRequestCode::Put,
+            CoapRequestCode::Delete,
+            CoapRequestCode::Post,
+            CoapRequestCode::Fetch,
+            CoapRequestCode::IPatch,
+            CoapRequestCode::Patch,
+        ] {
+            // SAFETY: Resource is valid as long as CoapResourceInner exists.
+            unsafe {
+                coap_register_request_handler(inner.raw_resource, code.to_raw_request(), Some(virtual_host_handler))
+            };
+        }
+    }
+
+    fn is_observable(&self) -> bool {
+        CoapResource::is_observable(self)
+    }
+
+    fn mark_removed(&self) {
+        CoapResource::mark_removed(self)
+    }
+
+    fn memory_usage(&self) -> usize {
+        let inner = self.inner.borrow();
+        std::mem::size_of::<CoapResourceInner<D>>()
+            + std::mem::size_of_val(&*inner.user_data)
+            + inner.etag.as_ref().map_or(0, |etag| etag.len())
+            + inner.host.as_ref().map_or(0, String::len)
+            + inner
+                .endpoints
+                .as_ref()
+                .map_or(0, |v| v.len() * std::mem::size_of::<CoapEndpointHandle>())
+            + self.uri_path().len()
+    }
+
+    fn detach_raw_resource(&self) {
+        let mut inner = self.inner.borrow_mut();
+        let previous = inner.raw_resource;
+        // SAFETY: The previous raw resource is valid as long as CoapResourceInner exists, and its
+        // user data is moved to the new raw resource before it is freed (the first argument of
+        // coap_delete_resource() is ignored).
+        unsafe {
+            let raw_path = coap_resource_get_uri_path(previous);
+            let uri_path = coap_new_str_const((*raw_path).s, (*raw_path).length);
+            let detached = coap_resource_init(uri_path, COAP_RESOURCE_FLAGS_RELEASE_URI);
+            coap_resource_set_userdata(detached, coap_resource_get_userdata(previous));
+            coap_resource_set_userdata(previous, std::ptr::null_mut());
+            inner.raw_resource = detached;
+            coap_delete_resource(std::ptr::null_mut(), previous);
+        }
+    }
+}
+
+/// Raw request handler of resources that route requests to the resource responsible for the
+/// requested host (see [CoapResourceBuilder::host()]) or to the resource whose path matches the
+/// Uri-Path options of the request if multiple resources have the same raw path (see
+/// [CoapResourcePath::to_raw()]).
+///
+/// Requests for hosts without a responsible resource are answered with 4.04 (Not Found),
+/// requests using methods the responsible resource has no handler for with 4.05 (Method Not
+/// Allowed).
+unsafe extern "C" fn virtual_host_handler(
+    _raw_resource: *mut coap_resource_t,
+    raw_session: *mut coap_session_t,
+    raw_incoming_pdu: *const coap_pdu_t,
+    raw_query: *const coap_string_t,
+    raw_response_pdu: *mut coap_pdu_t,
+) {
+    catch_session_callback_panic(raw_session, (), || {
+        let request = CoapPduView::from_raw(raw_incoming_pdu, coap_session_get_proto(raw_session).into());
+        let host = request
+            .options()
+            .find(|(number, _)| *number == CoapOptionType::UriHost as CoapOptionNum)
+            .map(|(_, value)| String::from_utf8_lossy(value).to_ascii_lowercase());
+        let path = CoapResourcePath::new(
+            request
+                .options()
+                .filter(|(number, _)| *number == CoapOptionType::UriPath as CoapOptionNum)
+                .map(|(_, value)| String::from_utf8_lossy(value).into_owned()),
+        );
+        let Ok(CoapMessageCode::Request(code)) = request.code() else {
+            return;
+        };
+        // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+        let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+        let response_code = match context.route_virtual_host(&path, host.as_deref(), code) {
+            Some((target, Some(handler))) => {
+                handler(target, raw_session, raw_incoming_pdu, raw_query, raw_response_pdu);
+                return;
+            },
+            Some((_, None)) => CoapResponseCode::NotAllowed,
+            None => CoapResponseCode::NotFound,
+        };
+        // Requests passed to a resource handler are checked by prepare_resource_handler_data().
+        if let Some(len) = truncated_datagram_len(coap_session_get_proto(raw_session).into(), raw_incoming_pdu) {
+            if is_wrapped_raw_session(raw_session) {
+                context.handle_truncated_datagram(CoapServerSession::from_raw(raw_session).into(), len);
+            }
+            return;
+        }
+        coap_pdu_set_raw_code(raw_response_pdu, c_uint::from(response_code.to_raw_code()));
+    })
 }
 
 #[doc(hidden)]
@@ -402,6 +3022,311 @@ impl<D: Any + ?Sized + Debug> Drop for CoapResourceInner<D> {
     }
 }
 
+/// Request handler function that is shared by multiple methods, see
+/// [CoapResourceBuilder::fallback()].
+type CoapFallbackHandlerFn<D> = dyn FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse);
+
+/// Builder for a [CoapResource] with request handlers for individual methods, created using
+/// [CoapResource::builder()].
+///
+/// Requests using methods for which neither a method-specific nor a fallback handler was set are
+/// answered with 4.05 (Method Not Allowed) by libcoap.
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::{
+///     message::CoapMessageCommon,
+///     protocol::{CoapMessageCode, CoapResponseCode},
+///     session::CoapSessionCommon,
+///     CoapResource,
+/// };
+///
+/// let resource = CoapResource::builder("temperature", 21u32)
+///     .get(|resource, session, _request, mut response| {
+///         let temperature = *resource.user_data();
+///         response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+///         response.set_data(Some(temperature.to_string().into_bytes()));
+///         session.send(response).unwrap();
+///     })
+///     .fallback(|_resource, session, _request, mut response| {
+///         response.set_code(CoapMessageCode::Response(CoapResponseCode::NotImplemented));
+///         session.send(response).unwrap();
+///     })
+///     .observable(true)
+///     .attribute("rt", Some("\"temperature-c\""))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct CoapResourceBuilder<D: Any + ?Sized + Debug> {
+    uri_path: CoapResourcePath,
+    user_data: Box<D>,
+    flags: ResourceFlags,
+    observable: Option<bool>,
+    coalesce_interval: Option<Duration>,
+    type_policy: Option<NotificationTypePolicy>,
+    attributes: Vec<(String, Option<String>)>,
+    handlers: CoapResourceHandlers<D>,
+    fallback: Option<Box<CoapFallbackHandlerFn<D>>>,
+    access_hook: Option<AccessHookHandle>,
+    host: Option<String>,
+    max_request_size: Option<usize>,
+    input_limits: Option<CoapInputLimits>,
+    endpoints: Option<Vec<CoapEndpointHandle>>,
+}
+
+impl<D: 'static + ?Sized + Debug> CoapResourceBuilder<D> {
+    /// Returns the URI path of the resource that will be built.
+    pub(crate) fn uri_path(&self) -> &CoapResourcePath {
+        &self.uri_path
+    }
+
+    /// Replaces the URI path of the resource that will be built (used by
+    /// [CoapResourceTree](crate::CoapResourceTree) to resolve relative paths).
+    pub(crate) fn set_uri_path(&mut self, uri_path: CoapResourcePath) {
+        self.uri_path = uri_path;
+    }
+
+    /// Sets the handler for requests with the given method code, replacing any handler previously
+    /// set for this method.
+    pub fn method<H: Into<CoapRequestHandler<D>>>(mut self, code: CoapRequestCode, handler: H) -> Self {
+        *self.handlers.handler_ref_mut(code) = Some(handler.into());
+        self
+    }
+
+    /// Sets the handler for GET requests.
+    pub fn get<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        self,
+        handler: F,
+    ) -> Self {
+        self.method(CoapRequestCode::Get, CoapRequestHandler::new_resource_ref(handler))
+    }
+
+    /// Sets a handler for GET requests that answers them using the given representations,
+    /// selected according to the Accept option of each request (see
+    /// [CoapRepresentations::respond()]).
+    pub fn representations(self, mut representations: CoapRepresentations) -> Self {
+        self.get(move |_resource, session, request, mut response| {
+            // The response code is set to 4.06 (Not Acceptable) if no representation matches.
+            let _ = representations.respond(request, &mut response);
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+        })
+    }
+
+    /// Sets the handler for PUT requests.
+    pub fn put<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        self,
+        handler: F,
+    ) -> Self {
+        self.method(CoapRequestCode::Put, CoapRequestHandler::new_resource_ref(handler))
+    }
+
+    /// Sets the handler for POST requests.
+    pub fn post<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        self,
+        handler: F,
+    ) -> Self {
+        self.method(CoapRequestCode::Post, CoapRequestHandler::new_resource_ref(handler))
+    }
+
+    /// Sets the handler for DELETE requests.
+    pub fn delete<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        self,
+        handler: F,
+    ) -> Self {
+        self.method(CoapRequestCode::Delete, CoapRequestHandler::new_resource_ref(handler))
+    }
+
+    /// Sets the handler for FETCH requests.
+    pub fn fetch<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        self,
+        handler: F,
+    ) -> Self {
+        self.method(CoapRequestCode::Fetch, CoapRequestHandler::new_resource_ref(handler))
+    }
+
+    /// Sets a handler that is called for requests with any method that has no method-specific
+    /// handler.
+    ///
+    /// The method of the request can be determined using
+    /// [CoapRequest::code()](crate::message::CoapMessageCommon::code).
+    pub fn fallback<F: 'static + FnMut(&CoapResource<D>, &mut CoapServerSession, &CoapRequest, CoapResponse)>(
+        mut self,
+        handler: F,
+    ) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets the hook that decides whether requests are passed to the request handlers (see
+    /// [CoapResource::set_access_hook()]).
+    pub fn access_hook<
+        F: Fn(Option<&CoapCryptoSessionInfo>, CoapRequestCode, &CoapRequest) -> CoapAccessDecision + 'static,
+    >(
+        mut self,
+        hook: F,
+    ) -> Self {
+        self.access_hook = Some(AccessHookHandle::new(hook));
+        self
+    }
+
+    /// Sets the flags the resource is created with (see [CoapResource::new_with_flags()]).
+    ///
+    /// By default, only [ResourceFlags::NOTIFY_NON] is set.
+    pub fn flags(mut self, flags: ResourceFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets whether the resource can be observed by clients (see
+    /// [CoapResource::set_get_observable()]).
+    pub fn observable(mut self, observable: bool) -> Self {
+        self.observable = Some(observable);
+        self
+    }
+
+    /// Sets the minimum time between two notifications for the resource (see
+    /// [CoapResource::set_coalesce_interval()]).
+    pub fn coalesce_interval(mut self, interval: Duration) -> Self {
+        self.coalesce_interval = Some(interval);
+        self
+    }
+
+    /// Sets the notification type policy of the resource (see
+    /// [CoapResource::set_notification_type_policy()]).
+    ///
+    /// This replaces the `NOTIFY_*` flags of the resource (see [CoapResourceBuilder::flags()]):
+    /// [NotificationTypePolicy::AlwaysCon] sets [ResourceFlags::NOTIFY_CON], the other policies set
+    /// [ResourceFlags::NOTIFY_NON_ALWAYS], so that libcoap does not send additional confirmable
+    /// notifications.
+    pub fn notification_type_policy(mut self, policy: NotificationTypePolicy) -> Self {
+        self.flags
+            .remove(ResourceFlags::NOTIFY_CON | ResourceFlags::NOTIFY_NON | ResourceFlags::NOTIFY_NON_ALWAYS);
+        self.flags |= match policy {
+            NotificationTypePolicy::AlwaysCon => ResourceFlags::NOTIFY_CON,
+            NotificationTypePolicy::AlwaysNon | NotificationTypePolicy::Mixed { .. } => {
+                ResourceFlags::NOTIFY_NON_ALWAYS
+            },
+        };
+        self.type_policy = Some(policy);
+        self
+    }
+
+    /// Adds a link attribute to the description of the resource in the `/.well-known/core`
+    /// resource (see [CoapResource::add_attribute()]).
+    pub fn attribute(mut self, name: &str, value: Option<&str>) -> Self {
+        self.attributes.push((name.to_string(), value.map(str::to_string)));
+        self
+    }
+
+    /// Restricts the resource to requests for the given host, which allows serving multiple
+    /// logical servers (virtual hosts) using the same endpoints.
+    ///
+    /// Requests are passed to the resource that matches both the URI path and the host named in
+    /// the Uri-Host option of the request (or the default host of the context if the request has
+    /// no Uri-Host option, see
+    /// [CoapContext::set_default_host()](crate::CoapContext::set_default_host)). If no resource is
+    /// restricted to the requested host, the resource with the same path that is not restricted to
+    /// any host is used instead. Host names are compared case-insensitively.
+    ///
+    /// Note that libcoap only manages a single resource per URI path, so the resources sharing a
+    /// path are served (and observed) through the one added to the context first:
+    /// - The path is observable if any of the resources sharing it is observable.
+    /// - Notifying the observers of one of these resources also notifies the observers of the
+    ///   others (with their current representation).
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Limits the size of request bodies (in bytes) accepted by the resource (see
+    /// [CoapResource::set_max_request_size()]).
+    pub fn max_request_size(mut self, max_size: usize) -> Self {
+        self.max_request_size = Some(max_size);
+        self
+    }
+
+    /// Sets the limits for the options and payloads of requests to the resource instead of using
+    /// the ones of its context (see [CoapResource::set_input_limits()]).
+    pub fn input_limits(mut self, limits: CoapInputLimits) -> Self {
+        self.input_limits = Some(limits);
+        self
+    }
+
+    /// Restricts the resource to requests received on the given endpoints (see
+    /// [CoapResource::set_endpoints()]).
+    pub fn endpoints<I: IntoIterator<Item = CoapEndpointHandle>>(mut self, endpoints: I) -> Self {
+        self.endpoints = Some(endpoints.into_iter().collect());
+        self
+    }
+
+    /// Creates the resource and registers the configured handlers.
+    ///
+    /// # Errors
+    /// Returns [ResourceCreationError::ContradictoryFlags] if the provided flags cannot be combined
+    /// (see [ResourceFlags]).
+    pub fn build(mut self) -> Result<CoapResource<D>, ResourceCreationError> {
+        let resource = CoapResource::new_with_flags(self.uri_path, self.user_data, self.flags)?;
+        if let Some(observable) = self.observable {
+            resource.set_get_observable(observable);
+        }
+        if let Some(interval) = self.coalesce_interval {
+            resource.set_coalesce_interval(Some(interval));
+        }
+        resource.set_notification_type_policy(self.type_policy);
+        for (name, value) in &self.attributes {
+            resource.add_attribute(name, value.as_deref());
+        }
+        resource.inner.borrow_mut().access_hook = self.access_hook;
+        resource.inner.borrow_mut().host = self.host;
+        resource.set_max_request_size(self.max_request_size);
+        resource.set_input_limits(self.input_limits);
+        resource.set_endpoints(self.endpoints);
+        // The fallback handler is shared between all methods that don't have their own handler.
+        let fallback = self.fallback.map(|handler| Rc::new(RefCell::new(handler)));
+        for code in [
+            CoapRequestCode::Get,
+            CoapRequestCode::Put,
+            CoapRequestCode::Delete,
+            CoapRequestCode::Post,
+            CoapRequestCode::Fetch,
+            CoapRequestCode::IPatch,
+            CoapRequestCode::Patch,
+        ] {
+            let handler = self.handlers.handler_ref_mut(code).take().or_else(|| {
+                let fallback = Rc::clone(fallback.as_ref()?);
+                Some(CoapRequestHandler::new_resource_ref(
+                    move |resource, session, request, response| {
+                        let mut fallback = fallback.borrow_mut();
+                        (*fallback)(resource, session, request, response)
+                    },
+                ))
+            });
+            if handler.is_some() {
+                resource.set_method_handler(code, handler);
+            }
+        }
+        Ok(resource)
+    }
+}
+
+impl<D: Any + ?Sized + Debug> Debug for CoapResourceBuilder<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapResourceBuilder")
+            .field("uri_path", &self.uri_path)
+            .field("flags", &self.flags)
+            .field("observable", &self.observable)
+            .field("coalesce_interval", &self.coalesce_interval)
+            .field("type_policy", &self.type_policy)
+            .field("attributes", &self.attributes)
+            .field("host", &self.host)
+            .field("max_request_size", &self.max_request_size)
+            .field("input_limits", &self.input_limits)
+            .field("endpoints", &self.endpoints)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A handler for CoAP requests on a resource.
 ///
 /// This handler can be associated with a [CoapResource] in order to be called when a request for
@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * resource_tree.rs - Registration of hierarchies of CoAP resources.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt::{Debug, Formatter},
+};
+
+use libcoap_sys::coap_session_get_context;
+
+use crate::{
+    context::{CoapContext, CoapResourceHandle},
+    error::ResourceTreeError,
+    message::CoapMessageCommon,
+    protocol::{CoapContentFormat, CoapMessageCode, CoapResponseCode},
+    resource::{CoapResource, CoapResourceBuilder, CoapResourcePath},
+    session::CoapSessionCommon,
+};
+
+/// Declarative description of a hierarchy of resources that are added to a context at once using
+/// [CoapContext::add_resource_tree()].
+///
+/// Each node of the tree is described by a [CoapResourceBuilder] whose URI path is relative to the
+/// root path of the tree. Trees can be nested using [CoapResourceTree::subtree()], in which case
+/// the root path of the subtree is relative to the root path of the tree it is added to.
+///
+/// For parent paths of nodes that neither the tree nor the context has a resource for (including
+/// the root path of the tree itself), a resource is created automatically, which answers GET
+/// requests with links to the resources of its direct children in the CoRE link format
+/// ([RFC 6690](https://datatracker.ietf.org/doc/html/rfc6690)).
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::{message::CoapMessageCommon, protocol::CoapResponseCode, session::CoapSessionCommon};
+/// use libcoap_rs::{CoapContext, CoapResource, CoapResourceTree};
+///
+/// let mut context = CoapContext::new().unwrap();
+/// let tree = CoapResourceTree::new("dev")
+///     .node(
+///         CoapResource::builder("cfg/net", ())
+///             .attribute("rt", Some("\"net-config\""))
+///             .get(|_, session, _request, mut response| {
+///                 response.set_code(CoapResponseCode::Content);
+///                 session.send(response).unwrap();
+///             }),
+///     )
+///     .node(CoapResource::builder("status", ()).observable(true));
+/// // Adds "dev/cfg/net" and "dev/status" as well as "dev" and "dev/cfg" listing their children.
+/// let handle = context.add_resource_tree(tree).unwrap();
+/// assert!(handle.get("dev/status").is_some());
+/// ```
+pub struct CoapResourceTree<D: Any + ?Sized + Debug> {
+    root: String,
+    nodes: Vec<CoapResourceBuilder<D>>,
+}
+
+impl<D: 'static + ?Sized + Debug> CoapResourceTree<D> {
+    /// Creates an empty tree whose nodes are located below the given root path.
+    ///
+    /// The root path is given in its textual representation, i.e., with percent-encoded segments
+    /// (see [CoapResourcePath]). It may be empty, in which case the paths of nodes are used as-is.
+    pub fn new(root: &str) -> CoapResourceTree<D> {
+        CoapResourceTree {
+            root: root.to_string(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Adds a node whose resource is built from the given builder, replacing its URI path with the
+    /// path relative to the root path of this tree.
+    ///
+    /// An empty URI path refers to the root path of the tree itself.
+    pub fn node(mut self, mut builder: CoapResourceBuilder<D>) -> Self {
+        let path = join_path(&self.root, &builder.uri_path().to_string());
+        builder.set_uri_path(CoapResourcePath::parse(&path));
+        self.nodes.push(builder);
+        self
+    }
+
+    /// Adds all nodes of the given tree, whose root path is relative to the root path of this
+    /// tree.
+    pub fn subtree(mut self, tree: CoapResourceTree<D>) -> Self {
+        for mut builder in tree.nodes {
+            let path = join_path(&self.root, &builder.uri_path().to_string());
+            builder.set_uri_path(CoapResourcePath::parse(&path));
+            self.nodes.push(builder);
+        }
+        self
+    }
+
+    /// Builds the resources of this tree and adds them to the given context, see
+    /// [CoapContext::add_resource_tree()].
+    pub(crate) fn register<'a>(
+        self,
+        context: &mut CoapContext<'a>,
+    ) -> Result<CoapResourceTreeHandle<'a, D>, ResourceTreeError> {
+        // All resources are built before the first one is added, so that errors do not leave the
+        // context with only some of them.
+        let mut nodes = BTreeMap::new();
+        for builder in self.nodes {
+            let uri_path = builder.uri_path().clone();
+            let path = uri_path.to_string();
+            if path.is_empty() || path.split('/').any(str::is_empty) {
+                return Err(ResourceTreeError::InvalidPath(path));
+            }
+            if nodes.contains_key(&path) {
+                return Err(ResourceTreeError::DuplicatePath(path));
+            }
+            let resource = builder
+                .build()
+                .map_err(|e| ResourceTreeError::ResourceCreation(path.clone(), e))?;
+            if context.resource_hosts(&uri_path).contains(&resource.host()) {
+                return Err(ResourceTreeError::PathInUse(path));
+            }
+            nodes.insert(path, TreeResource::Node(resource));
+        }
+        let paths: Vec<String> = nodes.keys().cloned().collect();
+        for path in paths {
+            let mut parent = path.as_str();
+            while let Some((parent_path, _)) = parent.rsplit_once('/') {
+                parent = parent_path;
+                if !nodes.contains_key(parent) && context.resource_hosts(&CoapResourcePath::parse(parent)).is_empty() {
+                    nodes.insert(parent.to_string(), TreeResource::Parent(new_parent_resource(parent)));
+                }
+            }
+        }
+
+        // Ordering by path adds parents before their children.
+        let mut handle = CoapResourceTreeHandle {
+            resources: BTreeMap::new(),
+            parents: BTreeMap::new(),
+        };
+        for (path, resource) in nodes {
+            match resource {
+                TreeResource::Node(resource) => {
+                    handle.resources.insert(path, context.add_resource(resource));
+                },
+                TreeResource::Parent(resource) => {
+                    handle.parents.insert(path, context.add_resource(resource));
+                },
+            }
+        }
+        Ok(handle)
+    }
+}
+
+impl<D: Any + ?Sized + Debug> Debug for CoapResourceTree<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoapResourceTree")
+            .field("root", &self.root)
+            .field("nodes", &self.nodes)
+            .finish()
+    }
+}
+
+/// Resource of a [CoapResourceTree] that is about to be added to a context.
+enum TreeResource<D: Any + ?Sized + Debug> {
+    /// Resource of a node of the tree.
+    Node(CoapResource<D>),
+    /// Automatically created resource for a parent path.
+    Parent(CoapResource<()>),
+}
+
+/// Handle to the resources of a [CoapResourceTree] that has been added to a context, returned by
+/// [CoapContext::add_resource_tree()].
+#[derive(Debug)]
+pub struct CoapResourceTreeHandle<'a, D: Any + ?Sized + Debug> {
+    resources: BTreeMap<String, CoapResourceHandle<'a, D>>,
+    parents: BTreeMap<String, CoapResourceHandle<'a, ()>>,
+}
+
+impl<'a, D: Any + ?Sized + Debug> CoapResourceTreeHandle<'a, D> {
+    /// Returns the handles to the resources of the nodes of the tree by their URI paths (in their
+    /// textual representation, see [CoapResourcePath]).
+    ///
+    /// Resources that have been removed using [CoapResourceTreeHandle::remove_subtree()] are not
+    /// included.
+    pub fn resources(&self) -> &BTreeMap<String, CoapResourceHandle<'a, D>> {
+        &self.resources
+    }
+
+    /// Returns the handle to the resource of the node with the given URI path.
+    pub fn get(&self, uri_path: &str) -> Option<&CoapResourceHandle<'a, D>> {
+        self.resources.get(uri_path)
+    }
+
+    /// Returns the handles to the resources that have been created for parent paths by their URI
+    /// paths.
+    pub fn parents(&self) -> &BTreeMap<String, CoapResourceHandle<'a, ()>> {
+        &self.parents
+    }
+
+    /// Removes the resource with the given URI path and all resources of the tree below it from
+    /// their context, returning the number of removed resources.
+    ///
+    /// Resources are removed children-first, see [CoapResourceHandle::remove()]. Once the
+    /// resources are released at the end of the current (or next) call to
+    /// [CoapContext::do_io()], libcoap sends a final notification with the code 4.04 (Not Found)
+    /// to their observers (unless observe persistence is enabled, see the
+    /// [persist](crate::persist) module).
+    ///
+    /// Resources that have already been removed by other means are skipped.
+    pub fn remove_subtree(&mut self, uri_path: &str) -> usize {
+        let in_subtree = |path: &String| {
+            path == uri_path
+                || uri_path.is_empty()
+                || path.strip_prefix(uri_path).is_some_and(|rest| rest.starts_with('/'))
+        };
+        let mut removed: Vec<_> = self.resources.keys().filter(|v| in_subtree(v)).cloned().collect();
+        removed.extend(self.parents.keys().filter(|v| in_subtree(v)).cloned());
+        // Children have paths that are greater than the ones of their parents.
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        let mut count = 0;
+        for path in removed {
+            let result = match self.resources.remove(&path) {
+                Some(handle) => handle.remove(),
+                None => self.parents.remove(&path).map_or(Ok(()), CoapResourceHandle::remove),
+            };
+            if result.is_ok() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Removes all resources of the tree from their context, see
+    /// [CoapResourceTreeHandle::remove_subtree()].
+    pub fn remove(mut self) -> usize {
+        self.remove_subtree("")
+    }
+}
+
+/// Joins the given (possibly empty) paths.
+fn join_path(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.to_string(),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
+
+/// Creates the resource for a parent path of a [CoapResourceTree], which answers GET requests with
+/// links to its children.
+fn new_parent_resource(uri_path: &str) -> CoapResource<()> {
+    let parent_path = CoapResourcePath::parse(uri_path);
+    CoapResource::builder(uri_path, ())
+        .attribute("ct", Some("40"))
+        .get(move |_, session, request, mut response| {
+            // SAFETY: The session belongs to a context that is currently performing IO, so the raw
+            // context is valid.
+            let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(session.raw_session())) };
+            let host = request
+                .uri()
+                .host()
+                .map(|host| String::from_utf8_lossy(host).into_owned());
+            response.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            response.set_content_format(Some(CoapContentFormat::LinkFormat.into()));
+            response.set_data(Some(context.child_links_for_host(&parent_path, host.as_deref())));
+            // If sending fails, libcoap will answer the request with an empty ACK instead.
+            let _ = session.send(response);
+        })
+        .build()
+        .expect("default resource flags are valid")
+}
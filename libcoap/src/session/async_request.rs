@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/async_request.rs - Asynchronous (separate) responses to requests using libcoap's async state.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{
+    cell::RefCell,
+    ffi::c_void,
+    fmt::{Debug, Formatter},
+    rc::Rc,
+    time::Duration,
+};
+
+use libcoap_sys::{
+    coap_async_get_app_data, coap_async_set_app_data, coap_async_t, coap_async_trigger, coap_bin_const_t,
+    coap_find_async, coap_free_async, coap_pdu_get_token, coap_pdu_t, coap_register_async, coap_session_t, coap_tick_t,
+};
+
+use super::{sealed::CoapSessionInnerProvider, CoapServerSession, CoapSessionCommon, CoapSessionId};
+use crate::{
+    error::AsyncRequestError,
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon},
+    protocol::CoapToken,
+};
+
+/// State of an asynchronous request, see [CoapAsyncHandle::state()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoapAsyncState {
+    /// The request has not been answered yet.
+    Pending,
+    /// The request has been answered, either using [CoapAsyncHandle::complete()] or by the request
+    /// handler after libcoap passed the request to it again.
+    Completed,
+    /// The session of the request was closed before the request was answered.
+    Abandoned,
+}
+
+/// Wrapper-side state of a request registered with libcoap as an asynchronous request.
+///
+/// A pointer to this state is stored as the app data of the `coap_async_t` instance of libcoap,
+/// while the state itself is kept alive by the session (until the request is answered or
+/// abandoned) and by the [CoapAsyncHandle] of the request.
+pub(crate) struct AsyncRequest {
+    raw_session: *mut coap_session_t,
+    token: CoapToken,
+    state: CoapAsyncState,
+    /// Response provided using [CoapAsyncHandle::complete()], which is sent once libcoap passes
+    /// the request to the request handler again.
+    response: Option<CoapResponse>,
+    abandon_callback: Option<Box<dyn FnOnce()>>,
+}
+
+impl AsyncRequest {
+    /// Returns the async state of libcoap for this request, or a null pointer if libcoap no longer
+    /// knows the request.
+    ///
+    /// # Safety
+    /// The raw session of this request must still be valid, which is the case until
+    /// [release_async_requests()] has been called for it.
+    unsafe fn raw_async(&self) -> *mut coap_async_t {
+        coap_find_async(
+            self.raw_session,
+            coap_bin_const_t {
+                length: self.token.len(),
+                s: self.token.as_ptr(),
+            },
+        )
+    }
+
+    /// Marks this request as abandoned without calling the abandon callback, used once the session
+    /// wrapper is dropped.
+    pub(crate) fn invalidate(&mut self) {
+        if self.state == CoapAsyncState::Pending {
+            self.state = CoapAsyncState::Abandoned;
+        }
+        self.response = None;
+        self.abandon_callback = None;
+    }
+}
+
+impl Debug for AsyncRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncRequest")
+            .field("token", &self.token)
+            .field("state", &self.state)
+            .field("response", &self.response)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to a request whose response is sent asynchronously, see [CoapRequest::into_async()].
+///
+/// libcoap keeps track of the request until it is answered: confirmable requests are acknowledged
+/// with an empty ACK once the request handler returns, retransmissions of the request by the
+/// client are acknowledged again instead of being passed to the request handler, and the response
+/// is sent as a separate response (a confirmable one for confirmable requests, which libcoap
+/// retransmits until it is acknowledged).
+///
+/// The request is answered in one of the following ways:
+/// - Using [CoapAsyncHandle::complete()], which sends the provided response during the next call
+///   to [CoapContext::do_io()](crate::CoapContext::do_io).
+/// - By the request handler of the resource, which is called again with the request (see
+///   [CoapRequest::is_async_replay()]) once the delay provided to [CoapRequest::into_async()] has
+///   expired or [CoapAsyncHandle::trigger()] is called.
+///
+/// If the session of the request is closed or disconnected before the request is answered, the
+/// request is abandoned and the callback set using [CoapAsyncHandle::set_abandon_callback()] is
+/// called, allowing the application to stop working on the response.
+///
+/// Dropping the handle does not remove the request: if a delay was provided, the request handler
+/// is still called once it expires, otherwise the request is never answered.
+#[derive(Debug)]
+pub struct CoapAsyncHandle {
+    state: Rc<RefCell<AsyncRequest>>,
+    request: CoapRequest,
+    session_id: CoapSessionId,
+}
+
+impl CoapAsyncHandle {
+    /// Registers the given request (which must currently be handled by a request handler of the
+    /// given session) as an asynchronous request, see [CoapRequest::into_async()].
+    pub(crate) fn register(
+        session: &CoapServerSession,
+        request: CoapRequest,
+        delay: Option<Duration>,
+    ) -> Result<CoapAsyncHandle, AsyncRequestError> {
+        let (raw_session, handled) = {
+            let inner = session.inner_ref();
+            (inner.raw_session, inner.handled_request)
+        };
+        let handled = handled
+            .filter(|handled| {
+                // SAFETY: The request PDU is valid while its handler is called.
+                let raw_token = unsafe { coap_pdu_get_token(handled.raw_request) };
+                let request_token = match raw_token.length {
+                    0 => &[],
+                    length => unsafe { std::slice::from_raw_parts(raw_token.s, length) },
+                };
+                request.token().unwrap_or(&[]) == request_token
+            })
+            .ok_or(AsyncRequestError::NotHandled)?;
+        // libcoap uses a delay of zero to indicate that the request is only answered once it is
+        // triggered, its ticks are milliseconds.
+        let delay = delay.map_or(0, |delay| {
+            coap_tick_t::try_from(delay.as_millis())
+                .unwrap_or(coap_tick_t::MAX)
+                .max(1)
+        });
+        // SAFETY: Session and request are valid, libcoap copies the request.
+        let raw_async = unsafe { coap_register_async(raw_session, handled.raw_request, delay) };
+        if raw_async.is_null() {
+            return Err(AsyncRequestError::AlreadyRegistered);
+        }
+        let state = Rc::new(RefCell::new(AsyncRequest {
+            raw_session,
+            token: Box::from(request.token().unwrap_or(&[])),
+            state: CoapAsyncState::Pending,
+            response: None,
+            abandon_callback: None,
+        }));
+        // SAFETY: The state is kept alive by the session until libcoap no longer knows the
+        // request, see release_async_requests().
+        unsafe { coap_async_set_app_data(raw_async, Rc::as_ptr(&state).cast_mut().cast::<c_void>()) };
+        session.inner_mut().async_requests.push(Rc::clone(&state));
+        Ok(CoapAsyncHandle {
+            state,
+            request,
+            session_id: session.id(),
+        })
+    }
+
+    /// Returns the request this handle belongs to.
+    pub fn request(&self) -> &CoapRequest {
+        &self.request
+    }
+
+    /// Returns the ID of the session the request was received on.
+    pub fn session_id(&self) -> CoapSessionId {
+        self.session_id
+    }
+
+    /// Returns the current state of the request.
+    pub fn state(&self) -> CoapAsyncState {
+        self.state.borrow().state
+    }
+
+    /// Answers the request with the given response, which is sent during the next call to
+    /// [CoapContext::do_io()](crate::CoapContext::do_io).
+    ///
+    /// The message type, message ID and token of the response are set by libcoap. The request
+    /// handler of the resource is not called for the request again.
+    pub fn complete(self, response: CoapResponse) -> Result<(), AsyncRequestError> {
+        complete_pending(&mut self.state.borrow_mut(), response)
+    }
+
+    /// Passes the request to the request handler of the resource again during the next call to
+    /// [CoapContext::do_io()](crate::CoapContext::do_io), ignoring the remaining delay.
+    pub fn trigger(&self) -> Result<(), AsyncRequestError> {
+        let raw_async = pending_raw_async(&self.state.borrow())?;
+        // SAFETY: The async state was just looked up, the session is valid as the request is
+        // still pending.
+        unsafe { coap_async_trigger(raw_async) };
+        Ok(())
+    }
+
+    /// Sets a callback that is called if the session of the request is closed or disconnected
+    /// before the request was answered, replacing any previously set callback.
+    ///
+    /// If the request has already been abandoned, the callback is called immediately.
+    /// The callback is not called if the context is dropped while the request is pending.
+    pub fn set_abandon_callback<F: FnOnce() + 'static>(&self, callback: F) {
+        let mut state = self.state.borrow_mut();
+        match state.state {
+            CoapAsyncState::Pending => state.abandon_callback = Some(Box::new(callback)),
+            CoapAsyncState::Abandoned => {
+                std::mem::drop(state);
+                callback()
+            },
+            CoapAsyncState::Completed => {},
+        }
+    }
+}
+
+/// Returns the libcoap async state of the given request if it is still pending.
+fn pending_raw_async(state: &AsyncRequest) -> Result<*mut coap_async_t, AsyncRequestError> {
+    match state.state {
+        CoapAsyncState::Pending => {},
+        CoapAsyncState::Completed => return Err(AsyncRequestError::AlreadyCompleted),
+        CoapAsyncState::Abandoned => return Err(AsyncRequestError::SessionClosed),
+    }
+    if state.response.is_some() {
+        return Err(AsyncRequestError::AlreadyCompleted);
+    }
+    // SAFETY: The session is valid as the request is still pending.
+    let raw_async = unsafe { state.raw_async() };
+    match raw_async.is_null() {
+        true => Err(AsyncRequestError::AlreadyCompleted),
+        false => Ok(raw_async),
+    }
+}
+
+/// Stores the response for the given request and makes libcoap pass the request to the request
+/// handler again, which sends the response.
+fn complete_pending(state: &mut AsyncRequest, response: CoapResponse) -> Result<(), AsyncRequestError> {
+    let raw_async = pending_raw_async(state)?;
+    state.response = Some(response);
+    // SAFETY: The async state was just looked up, the session is valid as the request is still
+    // pending.
+    unsafe { coap_async_trigger(raw_async) };
+    Ok(())
+}
+
+/// Answers the pending asynchronous request with the given token of the given session with the
+/// given response, see [SessionGuard::send_deferred_response()](super::SessionGuard::send_deferred_response).
+pub(crate) fn complete_async_request(
+    session: &CoapServerSession,
+    token: &[u8],
+    response: CoapResponse,
+) -> Result<(), AsyncRequestError> {
+    let state = session
+        .inner_ref()
+        .async_requests
+        .iter()
+        .find(|state| *state.borrow().token == *token)
+        .cloned()
+        // Answered and abandoned requests are removed from the session.
+        .ok_or(AsyncRequestError::AlreadyCompleted)?;
+    let mut state = state.borrow_mut();
+    complete_pending(&mut state, response)
+}
+
+/// Returns whether the given raw request, which libcoap passed to a request handler of the given
+/// session, is an asynchronous request registered using [CoapRequest::into_async()] that libcoap
+/// passes to the handler again.
+///
+/// libcoap acknowledges retransmissions of pending asynchronous requests itself, so requests
+/// with the token of a registered asynchronous request are always replays.
+///
+/// # Safety
+/// The raw request must be valid.
+pub(crate) unsafe fn is_async_replay(session: &CoapServerSession, raw_request: *const coap_pdu_t) -> bool {
+    let inner = session.inner_ref();
+    if inner.async_requests.is_empty() {
+        return false;
+    }
+    let raw_async = coap_find_async(inner.raw_session, coap_pdu_get_token(raw_request));
+    if raw_async.is_null() {
+        return false;
+    }
+    let app_data = coap_async_get_app_data(raw_async).cast_const();
+    inner
+        .async_requests
+        .iter()
+        .any(|request| Rc::as_ptr(request).cast::<c_void>() == app_data)
+}
+
+/// Marks the asynchronous request that is currently replayed to the request handler (see
+/// [is_async_replay()]) as answered, returning the response provided using
+/// [CoapAsyncHandle::complete()] (if any).
+///
+/// libcoap frees its async state once the request handler returns.
+pub(crate) fn finish_async_replay(session: &CoapServerSession, request: &CoapRequest) -> Option<CoapResponse> {
+    let token = request.token().unwrap_or(&[]);
+    let state = {
+        let mut inner = session.inner_mut();
+        let position = inner
+            .async_requests
+            .iter()
+            .position(|state| *state.borrow().token == *token)?;
+        inner.async_requests.remove(position)
+    };
+    let mut state = state.borrow_mut();
+    state.state = CoapAsyncState::Completed;
+    state.abandon_callback = None;
+    state.response.take()
+}
+
+/// Abandons the pending asynchronous requests of the given session (e.g., because it is
+/// disconnected), calling their abandon callbacks.
+///
+/// The async states of libcoap are kept until [release_async_requests()] is called, as this
+/// function may be called while libcoap processes them.
+pub(crate) fn abandon_async_requests(session: &CoapServerSession) {
+    let requests: Vec<_> = session.inner_ref().async_requests.clone();
+    for request in requests {
+        let callback = {
+            let mut request = request.borrow_mut();
+            if request.state != CoapAsyncState::Pending {
+                continue;
+            }
+            request.state = CoapAsyncState::Abandoned;
+            request.response = None;
+            request.abandon_callback.take()
+        };
+        if let Some(callback) = callback {
+            callback()
+        }
+    }
+}
+
+/// Abandons the pending asynchronous requests of the given session and frees their async states
+/// in libcoap, which must happen before the raw session is released.
+///
+/// # Safety
+/// libcoap must currently not process the async states of the given session.
+pub(crate) unsafe fn release_async_requests(session: &CoapServerSession) {
+    abandon_async_requests(session);
+    let requests = std::mem::take(&mut session.inner_mut().async_requests);
+    for request in requests {
+        let request = request.borrow();
+        let raw_async = request.raw_async();
+        if !raw_async.is_null() {
+            coap_free_async(request.raw_session, raw_async);
+        }
+    }
+}
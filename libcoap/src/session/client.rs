@@ -8,22 +8,42 @@
  */
 
 use std::cell::{Ref, RefMut};
-use std::net::SocketAddr;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(all(feature = "af-unix", unix))]
+use std::path::{Path, PathBuf};
+#[cfg(dtls)]
+use std::ptr::NonNull;
+use std::time::Duration;
 
 use libcoap_sys::{
-    coap_new_client_session, coap_proto_t, coap_register_event_handler, coap_session_get_app_data,
-    coap_session_get_context, coap_session_get_type, coap_session_init_token, coap_session_release,
-    coap_session_set_app_data, coap_session_t, coap_session_type_t, COAP_TOKEN_DEFAULT_MAX,
+    coap_get_app_data, coap_new_client_session, coap_proto_t, coap_register_event_handler, coap_session_get_app_data,
+    coap_session_get_context, coap_session_get_type, coap_session_init_token, coap_session_reference,
+    coap_session_release, coap_session_set_app_data, coap_session_t, coap_session_type_t, COAP_TOKEN_DEFAULT_MAX,
 };
 
-use super::{CoapSessionCommon, CoapSessionInner, CoapSessionInnerProvider};
+use super::{
+    add_known_peer_addrs, handshake_timed_out, icmp::clear_last_error, response_cache::ResponseCache,
+    CoapSessionCommon, CoapSessionInner, CoapSessionInnerProvider,
+};
 use crate::event::event_handler_callback;
-use crate::mem::{CoapFfiRcCell, DropInnerExclusively};
+use crate::mem::{CoapFfiRcCell, CoapFfiWeakCell, DropInnerExclusively};
 use crate::prng::coap_prng_try_fill;
-use crate::{context::CoapContext, error::SessionCreationError, types::CoapAddress};
+use crate::{
+    context::CoapContext,
+    crypto::{tls_backend, transport_supported, TlsLibrary},
+    error::{SessionCreationError, SessionEstablishError, UriParsingError},
+    protocol::{CoapRequestCode, CoapResponseCode, CoapToken},
+    types::{is_unscoped_link_local, CoapAddress, CoapProtocol, CoapUri, CoapUriScheme, Ownership},
+};
 
+#[cfg(all(feature = "oscore", any(feature = "dtls-pki", feature = "dtls-rpk")))]
+use crate::crypto::pki_rpk::{KeyType, PkiRpkContext};
+#[cfg(all(feature = "oscore", feature = "dtls-psk"))]
+use crate::crypto::psk::ClientPskContext;
 #[cfg(dtls)]
 use crate::crypto::ClientCryptoContext;
+#[cfg(all(feature = "oscore", dtls))]
+use crate::oscore::OscoreConf;
 
 #[derive(Debug)]
 struct CoapClientSessionInner<'a> {
@@ -32,6 +52,17 @@ struct CoapClientSessionInner<'a> {
     // This field is actually referred to be libcoap, so it isn't actually unused.
     #[allow(unused)]
     crypto_ctx: Option<ClientCryptoContext<'a>>,
+    /// Path of the local socket file for Unix domain socket sessions, removed when the session is
+    /// dropped.
+    #[cfg(all(feature = "af-unix", unix))]
+    unix_path: Option<PathBuf>,
+    /// Whether the raw session was created by this wrapper or transferred to it ([Ownership::Owned])
+    /// or is still owned by someone else ([Ownership::Borrowed]), see
+    /// [CoapClientSession::from_raw_external()].
+    ownership: Ownership,
+    /// Whether the raw session should be handed back to the caller instead of being released when
+    /// this session is dropped (set by [CoapClientSession::into_raw()]).
+    keep_raw_session: bool,
 }
 
 impl<'a> CoapClientSessionInner<'a> {
@@ -55,6 +86,36 @@ impl<'a> CoapClientSessionInner<'a> {
             inner: CoapSessionInner::new(raw_session),
             #[cfg(dtls)]
             crypto_ctx: None,
+            #[cfg(all(feature = "af-unix", unix))]
+            unix_path: None,
+            ownership: Ownership::Owned,
+            keep_raw_session: false,
+        });
+
+        // SAFETY: raw session is valid, inner session pointer must be valid as it was just created
+        // from one of Rust's smart pointers.
+        coap_session_set_app_data(raw_session, inner_session.create_raw_weak());
+
+        inner_session
+    }
+
+    /// Initializes a new [`CoapClientSessionInner`] for a raw session that was created outside of
+    /// this wrapper, keeping its current token state.
+    ///
+    /// # Safety
+    /// The provided pointer for `raw_session` must be valid and must not have any app data.
+    unsafe fn new_external(
+        raw_session: *mut coap_session_t,
+        ownership: Ownership,
+    ) -> CoapFfiRcCell<CoapClientSessionInner<'a>> {
+        let inner_session = CoapFfiRcCell::new(CoapClientSessionInner {
+            inner: CoapSessionInner::new(raw_session),
+            #[cfg(dtls)]
+            crypto_ctx: None,
+            #[cfg(all(feature = "af-unix", unix))]
+            unix_path: None,
+            ownership,
+            keep_raw_session: false,
         });
 
         // SAFETY: raw session is valid, inner session pointer must be valid as it was just created
@@ -78,6 +139,10 @@ impl<'a> CoapClientSessionInner<'a> {
         let inner_session = CoapFfiRcCell::new(CoapClientSessionInner {
             inner: CoapSessionInner::new(raw_session),
             crypto_ctx: Some(crypto_ctx),
+            #[cfg(all(feature = "af-unix", unix))]
+            unix_path: None,
+            ownership: Ownership::Owned,
+            keep_raw_session: false,
         });
 
         // SAFETY: raw session is valid, inner session pointer must be valid as it was just created
@@ -88,7 +153,231 @@ impl<'a> CoapClientSessionInner<'a> {
     }
 }
 
+/// Policy for reconnecting client sessions whose connection was lost, see
+/// [CoapContext::set_reconnect_policy()].
+///
+/// Reconnection attempts are performed by libcoap, which also re-registers the observations of a
+/// session once it has been re-established. Delays are rounded up to full seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ReconnectPolicy {
+    /// Delay between the loss of the connection and the first reconnection attempt.
+    pub initial_delay: Duration,
+    /// Maximum delay between two reconnection attempts.
+    pub max_delay: Duration,
+    /// Factor by which the delay is multiplied after each failed reconnection attempt.
+    pub backoff_factor: u32,
+    /// Maximum number of requests that may be sent using
+    /// [CoapSessionCommon::send_request()] while a session is reconnecting. These requests are
+    /// sent once the session has been re-established, while further ones fail with
+    /// [MessageConversionError::SessionReconnecting](crate::error::MessageConversionError::SessionReconnecting).
+    ///
+    /// A limit of zero makes all requests sent while reconnecting fail fast.
+    pub max_queued_requests: usize,
+    /// Whether the observations of a session are registered again by the wrapper once the
+    /// session has been re-established (false by default).
+    ///
+    /// This is necessary if the server has lost its observations, e.g., because it was
+    /// restarted. Registrations are re-sent using the tokens of the original requests, so
+    /// notifications keep being delivered to the existing request handles. A
+    /// [CoapObservationEvent::Reregistered](super::CoapObservationEvent::Reregistered) event is
+    /// reported for each re-registered observation (see
+    /// [CoapSessionCommon::poll_observation_events()]), and observations the server no longer
+    /// accepts are reported as
+    /// [CoapObservationEvent::Terminated](super::CoapObservationEvent::Terminated).
+    pub reregister_observations: bool,
+}
+
+impl ReconnectPolicy {
+    /// Creates a policy that reconnects after the given delay (doubling it after each failed
+    /// attempt up to a maximum of one minute), does not queue requests while reconnecting and does
+    /// not re-register observations.
+    pub fn new(initial_delay: Duration) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay,
+            max_delay: Duration::from_secs(60).max(initial_delay),
+            backoff_factor: 2,
+            max_queued_requests: 0,
+            reregister_observations: false,
+        }
+    }
+
+    /// Returns the delay to use after a reconnection attempt with the given delay has failed.
+    pub(crate) fn next_delay(&self, delay: Duration) -> Duration {
+        delay.saturating_mul(self.backoff_factor).min(self.max_delay)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy::new(Duration::from_secs(1))
+    }
+}
+
+/// Policy for re-sending requests that were answered with 5.03 (Service Unavailable), see
+/// [CoapClientSession::set_retry_policy()].
+///
+/// Servers use 5.03 responses to indicate that they are temporarily unable to handle requests
+/// (e.g., during maintenance), with the Max-Age option of the response indicating after how many
+/// seconds the request may be repeated
+/// ([RFC 7252, Section 5.9.3.4](https://datatracker.ietf.org/doc/html/rfc7252#section-5.9.3.4)).
+/// Requests answered with such a response are re-sent once this delay has elapsed, instead of
+/// providing the response to the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// Maximum number of times a request is re-sent, the response to the last attempt is
+    /// provided to the application regardless of its code.
+    pub max_retries: u32,
+    /// Maximum delay before a request is re-sent. Responses that ask the client to wait longer
+    /// are provided to the application instead.
+    pub max_delay: Duration,
+    /// Whether requests answered with 4.29 (Too Many Requests,
+    /// [RFC 8516](https://datatracker.ietf.org/doc/html/rfc8516)) are re-sent as well (false by
+    /// default).
+    pub retry_too_many_requests: bool,
+    /// Whether requests with methods that are not idempotent (POST and PATCH) are re-sent as well
+    /// (false by default).
+    ///
+    /// Re-sending such requests may cause their action to be performed more than once, e.g., if a
+    /// proxy answered with 5.03 even though the server has already handled the request.
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that re-sends requests with idempotent methods (GET, PUT, DELETE, FETCH
+    /// and iPATCH) up to `max_retries` times if they are answered with 5.03, waiting for at most
+    /// one minute (the default Max-Age) before each attempt.
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            max_delay: Duration::from_secs(60),
+            retry_too_many_requests: false,
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Returns whether requests with the given method may be re-sent according to this policy.
+    pub(crate) fn applies_to(&self, code: CoapRequestCode) -> bool {
+        match code {
+            CoapRequestCode::Get
+            | CoapRequestCode::Put
+            | CoapRequestCode::Delete
+            | CoapRequestCode::Fetch
+            | CoapRequestCode::IPatch => true,
+            CoapRequestCode::Post | CoapRequestCode::Patch => self.retry_non_idempotent,
+        }
+    }
+
+    /// Returns whether requests answered with the given code are re-sent according to this
+    /// policy.
+    pub(crate) fn retries_code(&self, code: CoapResponseCode) -> bool {
+        code == CoapResponseCode::ServiceUnavailable
+            || (self.retry_too_many_requests && code == CoapResponseCode::TooManyRequests)
+    }
+}
+
+/// A request that is re-sent because of its response, see
+/// [CoapClientSession::set_retry_hook()].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CoapRetryAttempt {
+    /// Token of the request, which is also used when re-sending it.
+    pub token: CoapToken,
+    /// Number of the upcoming attempt to re-send the request (1 for the first one).
+    pub attempt: u32,
+    /// Code of the response the request was answered with.
+    pub code: CoapResponseCode,
+    /// Time after which the request is re-sent.
+    pub delay: Duration,
+}
+
+/// Hook called for requests that are re-sent, see [CoapClientSession::set_retry_hook()].
+pub(crate) struct RetryHook(pub(crate) Box<dyn FnMut(&CoapRetryAttempt)>);
+
+impl std::fmt::Debug for RetryHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryHook").finish_non_exhaustive()
+    }
+}
+
+/// Policy for falling back to unencrypted CoAP over UDP if a DTLS session can not be
+/// established, see [CoapContext::connect_with_fallback()].
+#[cfg(dtls)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DtlsFallbackPolicy {
+    /// Only DTLS is used, the session is returned without waiting for its handshake (like
+    /// [CoapClientSession::connect_dtls()]).
+    RequireDtls,
+    /// DTLS is attempted first, falling back to plain UDP if the DTLS session is not established
+    /// within the given timeout or its handshake fails.
+    PreferDtlsFallbackPlain {
+        /// Maximum time to wait for the DTLS session to become established.
+        handshake_timeout: Duration,
+    },
+    /// Only plain UDP is used, the provided crypto context is ignored.
+    PlainOnly,
+}
+
+/// Client session created using [CoapContext::connect_with_fallback()], which records the
+/// transport that was actually used.
+///
+/// Applications should check [FallbackSession::is_secure()] before sending sensitive payloads,
+/// as the session may use unencrypted CoAP if the fallback policy allowed it.
+#[cfg(dtls)]
+#[derive(Debug, Clone)]
+pub struct FallbackSession<'a> {
+    session: CoapClientSession<'a>,
+    dtls_error: Option<SessionEstablishError>,
+}
+
+#[cfg(dtls)]
+impl<'a> FallbackSession<'a> {
+    pub(crate) fn new(
+        session: CoapClientSession<'a>,
+        dtls_error: Option<SessionEstablishError>,
+    ) -> FallbackSession<'a> {
+        FallbackSession { session, dtls_error }
+    }
+
+    /// Returns the created session.
+    pub fn session(&self) -> &CoapClientSession<'a> {
+        &self.session
+    }
+
+    /// Consumes this value and returns the created session.
+    pub fn into_session(self) -> CoapClientSession<'a> {
+        self.session
+    }
+
+    /// Returns the transport protocol that is used by the session ([CoapProtocol::Dtls] or
+    /// [CoapProtocol::Udp]).
+    pub fn protocol(&self) -> CoapProtocol {
+        self.session.proto()
+    }
+
+    /// Returns whether the session is encrypted using DTLS.
+    pub fn is_secure(&self) -> bool {
+        self.protocol() == CoapProtocol::Dtls
+    }
+
+    /// Returns the error that caused the fallback to plain UDP, or None if no fallback occurred
+    /// (including if the policy was [DtlsFallbackPolicy::PlainOnly]).
+    pub fn dtls_error(&self) -> Option<&SessionEstablishError> {
+        self.dtls_error.as_ref()
+    }
+}
+
 /// Representation of a client-side CoAP session.
+///
+/// Session handles are cheap to clone, and all clones refer to the same session. Requests can be
+/// sent using any of them: the pending requests of the session are shared between all clones, so
+/// responses can be polled using any clone, regardless of which clone sent the request.
+///
+/// The underlying raw session is released once the last handle to it is dropped (this includes
+/// [CoapSession](super::CoapSession)s and [SessionGuard](super::SessionGuard)s referring to the
+/// session), or once [disconnect()](CoapClientSession::disconnect) is called on the last one.
+/// Handles may outlive the [CoapContext] they were created for: dropping the context does not
+/// invalidate them, but no messages are sent or received using them afterwards, and the raw
+/// context is only freed once the last handle is dropped.
 #[derive(Debug, Clone)]
 pub struct CoapClientSession<'a> {
     inner: CoapFfiRcCell<CoapClientSessionInner<'a>>,
@@ -106,26 +395,41 @@ impl CoapClientSession<'_> {
         addr: SocketAddr,
         crypto_ctx: impl Into<ClientCryptoContext<'a>>,
     ) -> Result<CoapClientSession<'a>, SessionCreationError> {
-        let crypto_ctx = crypto_ctx.into();
-        // SAFETY: The returned raw session lives for as long as the constructed
-        // CoapClientSessionInner does, which is limited to the lifetime of crypto_ctx.
-        // When the CoapClientSessionInner instance is dropped, the session is dropped before the
-        // crypto context is.
+        Self::connect_dtls_from_optional(ctx, None, addr, crypto_ctx.into())
+    }
+
+    /// Create a new DTLS encrypted session with the given peer `addr` using the given
+    /// `crypto_ctx`, sending from the given `local_addr`.
+    ///
+    /// Binding to a specific local address allows selecting the source address on hosts with
+    /// multiple interfaces.
+    /// If the port of `local_addr` is 0, an ephemeral port is chosen by the operating system.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::AddressFamilyMismatch] if `local_addr` and `addr` are not
+    /// of the same address family, [SessionCreationError::BindFailed] if it was not possible to
+    /// bind to `local_addr`, or another [SessionCreationError] if libcoap was unable to create
+    /// a session.
+    #[cfg(dtls)]
+    pub fn connect_dtls_from<'a>(
+        ctx: &mut CoapContext<'a>,
+        local_addr: SocketAddr,
+        addr: SocketAddr,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        Self::connect_dtls_from_optional(ctx, Some(local_addr), addr, crypto_ctx.into())
+    }
+
+    #[cfg(dtls)]
+    fn connect_dtls_from_optional<'a>(
+        ctx: &mut CoapContext<'a>,
+        local_addr: Option<SocketAddr>,
+        addr: SocketAddr,
+        crypto_ctx: ClientCryptoContext<'a>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        // SAFETY: See create_raw_secure_session().
         let raw_session = unsafe {
-            match &crypto_ctx {
-                #[cfg(feature = "dtls-psk")]
-                ClientCryptoContext::Psk(psk_ctx) => {
-                    psk_ctx.create_raw_session(ctx, &addr.into(), coap_proto_t::COAP_PROTO_DTLS)?
-                },
-                #[cfg(feature = "dtls-pki")]
-                ClientCryptoContext::Pki(pki_ctx) => {
-                    pki_ctx.create_raw_session(ctx, &addr.into(), coap_proto_t::COAP_PROTO_DTLS)?
-                },
-                #[cfg(feature = "dtls-rpk")]
-                ClientCryptoContext::Rpk(rpk_ctx) => {
-                    rpk_ctx.create_raw_session(ctx, &addr.into(), coap_proto_t::COAP_PROTO_DTLS)?
-                },
-            }
+            Self::create_raw_secure_session(ctx, local_addr, addr, coap_proto_t::COAP_PROTO_DTLS, &crypto_ctx)?
         };
 
         // SAFETY: raw_session was just checked to be valid pointer.
@@ -134,6 +438,244 @@ impl CoapClientSession<'_> {
         })
     }
 
+    /// Create a new DTLS encrypted session with the peer referred to by the given `coaps://` URI
+    /// using the given `crypto_ctx`.
+    ///
+    /// The URI may be provided as a string or as an already parsed [CoapUri].
+    /// The host part of the URI is resolved into a list of socket addresses, which are tried in
+    /// order until a session could be created.
+    /// If the URI does not contain a port, the default CoAPS port (5684) is used.
+    /// The address that was finally used can be retrieved using
+    /// [CoapSessionCommon::addr_remote()](super::CoapSessionCommon::addr_remote), the other
+    /// resolved addresses are considered to belong to the same peer for
+    /// [CoapResponseAddressPolicy::AcceptKnownAlternatives](super::CoapResponseAddressPolicy::AcceptKnownAlternatives).
+    ///
+    /// # Errors
+    /// Will return a [SessionCreationError] if the URI could not be parsed, has a scheme other than
+    /// `coaps`, or its host could not be resolved, or if libcoap was unable to create a session for
+    /// all resolved addresses.
+    #[cfg(dtls)]
+    pub fn connect_dtls_uri<'a, U>(
+        ctx: &mut CoapContext<'a>,
+        uri: U,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError>
+    where
+        U: TryInto<CoapUri>,
+        UriParsingError: From<U::Error>,
+    {
+        let uri = uri.try_into().map_err(UriParsingError::from)?;
+        let (_, addrs) = resolve_uri(&uri, &[CoapUriScheme::Coaps])?;
+        Self::connect_dtls_resolved(ctx, &addrs, crypto_ctx.into())
+    }
+
+    /// Creates a new DTLS encrypted session with the first of the given (resolved) addresses of a
+    /// peer that a session could be created for, see [CoapClientSession::connect_dtls_uri()].
+    #[cfg(dtls)]
+    pub(crate) fn connect_dtls_resolved<'a>(
+        ctx: &mut CoapContext<'a>,
+        addrs: &[SocketAddr],
+        crypto_ctx: ClientCryptoContext<'a>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        let mut last_error = SessionCreationError::Unknown;
+        for addr in addrs.iter().copied() {
+            // SAFETY: See create_raw_secure_session().
+            match unsafe {
+                Self::create_raw_secure_session(ctx, None, addr, coap_proto_t::COAP_PROTO_DTLS, &crypto_ctx)
+            } {
+                // SAFETY: raw_session was just checked to be valid pointer.
+                Ok(raw_session) => {
+                    let session = CoapClientSession {
+                        inner: unsafe { CoapClientSessionInner::new_with_crypto_ctx(raw_session.as_ptr(), crypto_ctx) },
+                    };
+                    add_known_peer_addrs(&session, addrs);
+                    return Ok(session);
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Create a new DTLS encrypted session with the given peer `addr` using the given PSK
+    /// `crypto_ctx`, additionally protecting messages using OSCORE
+    /// ([RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613)) with the given configuration.
+    ///
+    /// The OSCORE configuration is consumed by libcoap, even if no session could be created.
+    ///
+    /// # Errors
+    /// Will return a [SessionCreationError] if libcoap was unable to create a session (e.g.,
+    /// because libcoap was built without OSCORE support).
+    #[cfg(all(feature = "oscore", feature = "dtls-psk"))]
+    pub fn connect_oscore_psk<'a>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+        crypto_ctx: ClientPskContext<'a>,
+        oscore_conf: OscoreConf,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        check_transport_supported(coap_proto_t::COAP_PROTO_DTLS)?;
+        ctx.check_session_memory()?;
+        // SAFETY: The crypto context is owned by the created session, see
+        // create_raw_secure_session().
+        let raw_session = unsafe {
+            crypto_ctx.create_raw_oscore_session(ctx, None, &addr.into(), coap_proto_t::COAP_PROTO_DTLS, oscore_conf)?
+        };
+
+        // SAFETY: raw_session was just checked to be valid pointer.
+        let session = CoapClientSession {
+            inner: unsafe { CoapClientSessionInner::new_with_crypto_ctx(raw_session.as_ptr(), crypto_ctx.into()) },
+        };
+        session.inner.borrow_mut().inner.oscore = true;
+        Ok(session)
+    }
+
+    /// Create a new DTLS encrypted session with the given peer `addr` using the given PKI or RPK
+    /// `crypto_ctx`, additionally protecting messages using OSCORE
+    /// ([RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613)) with the given configuration.
+    ///
+    /// The OSCORE configuration is consumed by libcoap, even if no session could be created.
+    ///
+    /// # Errors
+    /// Will return a [SessionCreationError] if libcoap was unable to create a session (e.g.,
+    /// because libcoap was built without OSCORE support).
+    #[cfg(all(feature = "oscore", any(feature = "dtls-pki", feature = "dtls-rpk")))]
+    pub fn connect_oscore_pki<'a, KTY: KeyType>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+        crypto_ctx: PkiRpkContext<'a, KTY>,
+        oscore_conf: OscoreConf,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError>
+    where
+        PkiRpkContext<'a, KTY>: Into<ClientCryptoContext<'a>>,
+    {
+        check_transport_supported(coap_proto_t::COAP_PROTO_DTLS)?;
+        ctx.check_session_memory()?;
+        // SAFETY: The crypto context is owned by the created session, see
+        // create_raw_secure_session().
+        let raw_session = unsafe {
+            crypto_ctx.create_raw_oscore_session(ctx, None, &addr.into(), coap_proto_t::COAP_PROTO_DTLS, oscore_conf)?
+        };
+
+        // SAFETY: raw_session was just checked to be valid pointer.
+        let session = CoapClientSession {
+            inner: unsafe { CoapClientSessionInner::new_with_crypto_ctx(raw_session.as_ptr(), crypto_ctx.into()) },
+        };
+        session.inner.borrow_mut().inner.oscore = true;
+        Ok(session)
+    }
+
+    /// Creates a raw encrypted session (using DTLS or a TLS-based transport, depending on `proto`)
+    /// with the given peer `addr` using the given `crypto_ctx`.
+    ///
+    /// # Safety
+    /// The returned raw session must not outlive the provided crypto context, i.e., the returned
+    /// session should be wrapped in a [CoapClientSessionInner] that owns the crypto context.
+    /// When the CoapClientSessionInner instance is dropped, the session is dropped before the
+    /// crypto context is.
+    #[cfg(dtls)]
+    unsafe fn create_raw_secure_session(
+        ctx: &mut CoapContext<'_>,
+        local_addr: Option<SocketAddr>,
+        addr: SocketAddr,
+        proto: coap_proto_t,
+        crypto_ctx: &ClientCryptoContext<'_>,
+    ) -> Result<NonNull<coap_session_t>, SessionCreationError> {
+        check_transport_supported(proto)?;
+        ctx.check_session_memory()?;
+        check_address_families(local_addr, addr)?;
+        let raw_local_addr = local_addr.map(CoapAddress::from);
+        let error_cleared = clear_last_error();
+        let raw_session = match crypto_ctx {
+            #[cfg(feature = "dtls-psk")]
+            ClientCryptoContext::Psk(psk_ctx) => {
+                psk_ctx.create_raw_session(ctx, raw_local_addr.as_ref(), &addr.into(), proto)
+            },
+            #[cfg(feature = "dtls-pki")]
+            ClientCryptoContext::Pki(pki_ctx) => {
+                pki_ctx.create_raw_session(ctx, raw_local_addr.as_ref(), &addr.into(), proto)
+            },
+            #[cfg(feature = "dtls-rpk")]
+            ClientCryptoContext::Rpk(rpk_ctx) => {
+                rpk_ctx.create_raw_session(ctx, raw_local_addr.as_ref(), &addr.into(), proto)
+            },
+        };
+        // The crypto contexts report libcoap failing to create the session as an unknown error,
+        // other errors (e.g., invalid keys) are reported as is.
+        raw_session.map_err(|e| match e {
+            SessionCreationError::Unknown => handshake_init_failure(local_addr, error_cleared),
+            e => e,
+        })
+    }
+
+    /// Create a new unencrypted session with the peer referred to by the given URI.
+    ///
+    /// The transport protocol is selected based on the URI scheme, `coap://` URIs will use UDP,
+    /// while `coap+tcp://` URIs will use TCP (if the `tcp` feature is enabled) and `coap+ws://`
+    /// URIs will use WebSockets (if the `websockets` feature is enabled). For encrypted sessions, use
+    /// [connect_dtls_uri()](CoapClientSession::connect_dtls_uri) instead.
+    /// The URI may be provided as a string or as an already parsed [CoapUri]. Peers listening on
+    /// Unix domain sockets can not be addressed using URIs, as a local socket path is required as
+    /// well (see `connect_unix()`).
+    ///
+    /// The host part of the URI is resolved into a list of socket addresses, which are tried in
+    /// order until a session could be created.
+    /// If the URI does not contain a port, the default CoAP port (5683) is used.
+    /// The address that was finally used can be retrieved using
+    /// [CoapSessionCommon::addr_remote()](super::CoapSessionCommon::addr_remote), the other
+    /// resolved addresses are considered to belong to the same peer for
+    /// [CoapResponseAddressPolicy::AcceptKnownAlternatives](super::CoapResponseAddressPolicy::AcceptKnownAlternatives).
+    ///
+    /// # Errors
+    /// Will return a [SessionCreationError] if the URI could not be parsed, has an unsupported
+    /// scheme, or its host could not be resolved, or if libcoap was unable to create a session for
+    /// all resolved addresses.
+    pub fn connect_uri<'a, U>(ctx: &mut CoapContext<'a>, uri: U) -> Result<CoapClientSession<'a>, SessionCreationError>
+    where
+        U: TryInto<CoapUri>,
+        UriParsingError: From<U::Error>,
+    {
+        let uri = uri.try_into().map_err(UriParsingError::from)?;
+        let (scheme, addrs) = resolve_uri(
+            &uri,
+            &[
+                CoapUriScheme::Coap,
+                #[cfg(feature = "tcp")]
+                CoapUriScheme::CoapTcp,
+                #[cfg(feature = "websockets")]
+                CoapUriScheme::CoapWs,
+            ],
+        )?;
+        Self::connect_resolved(ctx, scheme, &addrs)
+    }
+
+    /// Creates a new unencrypted session using the transport of the given URI scheme with the
+    /// first of the given (resolved) addresses of a peer that a session could be created for, see
+    /// [CoapClientSession::connect_uri()].
+    pub(crate) fn connect_resolved<'a>(
+        ctx: &mut CoapContext<'a>,
+        scheme: CoapUriScheme,
+        addrs: &[SocketAddr],
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        let mut last_error = SessionCreationError::Unknown;
+        for addr in addrs.iter().copied() {
+            let session = match scheme {
+                #[cfg(feature = "tcp")]
+                CoapUriScheme::CoapTcp => Self::connect_tcp(ctx, addr),
+                #[cfg(feature = "websockets")]
+                CoapUriScheme::CoapWs => Self::connect_ws(ctx, addr),
+                _ => Self::connect_udp(ctx, addr),
+            };
+            match session {
+                Ok(session) => {
+                    add_known_peer_addrs(&session, addrs);
+                    return Ok(session);
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
     /// Create a new unencrypted session with the given peer over UDP.
     ///
     /// # Errors
@@ -143,44 +685,209 @@ impl CoapClientSession<'_> {
         ctx: &mut CoapContext<'a>,
         addr: SocketAddr,
     ) -> Result<CoapClientSession<'a>, SessionCreationError> {
-        // SAFETY: self.raw_context is guaranteed to be valid, local_if can be null.
-        let session = unsafe {
+        Self::connect_unencrypted(ctx, None, addr, coap_proto_t::COAP_PROTO_UDP)
+    }
+
+    /// Create a new unencrypted session with the given peer over UDP, sending from the given
+    /// `local_addr`.
+    ///
+    /// Binding to a specific local address allows selecting the source address on hosts with
+    /// multiple interfaces.
+    /// If the port of `local_addr` is 0, an ephemeral port is chosen by the operating system.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::AddressFamilyMismatch] if `local_addr` and `addr` are not
+    /// of the same address family, [SessionCreationError::BindFailed] if it was not possible to
+    /// bind to `local_addr`, or another [SessionCreationError] if libcoap was unable to create
+    /// a session.
+    pub fn connect_udp_from<'a>(
+        ctx: &mut CoapContext<'a>,
+        local_addr: SocketAddr,
+        addr: SocketAddr,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        Self::connect_unencrypted(ctx, Some(local_addr), addr, coap_proto_t::COAP_PROTO_UDP)
+    }
+
+    /// Create a new unencrypted session with the given peer over TCP.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::TransportUnsupported] if libcoap was built without TCP
+    /// support, or another [SessionCreationError] if libcoap was unable to create a session (most
+    /// likely because it was not possible to bind to a port).
+    #[cfg(feature = "tcp")]
+    pub fn connect_tcp<'a>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        Self::connect_unencrypted(ctx, None, addr, coap_proto_t::COAP_PROTO_TCP)
+    }
+
+    /// Create a new unencrypted session with the given peer over TCP, connecting from the given
+    /// `local_addr`.
+    ///
+    /// See [connect_udp_from()](CoapClientSession::connect_udp_from) for more information.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::AddressFamilyMismatch] if `local_addr` and `addr` are not
+    /// of the same address family, [SessionCreationError::BindFailed] if it was not possible to
+    /// bind to `local_addr`, or another [SessionCreationError] if libcoap was unable to create
+    /// a session.
+    #[cfg(feature = "tcp")]
+    pub fn connect_tcp_from<'a>(
+        ctx: &mut CoapContext<'a>,
+        local_addr: SocketAddr,
+        addr: SocketAddr,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        Self::connect_unencrypted(ctx, Some(local_addr), addr, coap_proto_t::COAP_PROTO_TCP)
+    }
+
+    /// Create a new TLS encrypted session (i.e., CoAP over TCP secured using TLS) with the given
+    /// peer using the given `crypto_ctx`.
+    ///
+    /// The crypto context is used in the same way as for
+    /// [connect_dtls()](CoapClientSession::connect_dtls).
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::TransportUnsupported] if libcoap (or its TLS library)
+    /// does not support TLS, or another [SessionCreationError] if libcoap was unable to create a
+    /// session.
+    #[cfg(tls)]
+    pub fn connect_tls<'a>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        let crypto_ctx = crypto_ctx.into();
+        // SAFETY: See create_raw_secure_session().
+        let raw_session =
+            unsafe { Self::create_raw_secure_session(ctx, None, addr, coap_proto_t::COAP_PROTO_TLS, &crypto_ctx)? };
+
+        // SAFETY: raw_session was just checked to be valid pointer.
+        Ok(CoapClientSession {
+            inner: unsafe { CoapClientSessionInner::new_with_crypto_ctx(raw_session.as_ptr(), crypto_ctx) },
+        })
+    }
+
+    /// Create a new unencrypted session with the given peer over WebSockets.
+    ///
+    /// The WebSocket connection is established using an HTTP upgrade request for the
+    /// `/.well-known/coap` path (see
+    /// [RFC 8323, Section 4](https://datatracker.ietf.org/doc/html/rfc8323#section-4)), after
+    /// which the session behaves like a TCP session (i.e., uses CSM signaling messages).
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::TransportUnsupported] if libcoap was built without
+    /// WebSocket support, or another [SessionCreationError] if libcoap was unable to create a
+    /// session.
+    #[cfg(feature = "websockets")]
+    pub fn connect_ws<'a>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        Self::connect_unencrypted(ctx, None, addr, coap_proto_t::COAP_PROTO_WS)
+    }
+
+    /// Create a new secure WebSocket session (i.e., using WebSockets over TLS) with the given peer
+    /// using the given `crypto_ctx`.
+    ///
+    /// See [connect_ws()](CoapClientSession::connect_ws) and
+    /// [connect_dtls()](CoapClientSession::connect_dtls) for more information, the crypto context
+    /// is used in the same way as for DTLS.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::TransportUnsupported] if libcoap (or its TLS library)
+    /// does not support secure WebSockets, or another [SessionCreationError] if libcoap was unable
+    /// to create a session.
+    #[cfg(all(feature = "websockets", dtls))]
+    pub fn connect_wss<'a>(
+        ctx: &mut CoapContext<'a>,
+        addr: SocketAddr,
+        crypto_ctx: impl Into<ClientCryptoContext<'a>>,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        let crypto_ctx = crypto_ctx.into();
+        // SAFETY: See create_raw_secure_session().
+        let raw_session =
+            unsafe { Self::create_raw_secure_session(ctx, None, addr, coap_proto_t::COAP_PROTO_WSS, &crypto_ctx)? };
+
+        // SAFETY: raw_session was just checked to be valid pointer.
+        Ok(CoapClientSession {
+            inner: unsafe { CoapClientSessionInner::new_with_crypto_ctx(raw_session.as_ptr(), crypto_ctx) },
+        })
+    }
+
+    /// Create a new unencrypted session with the peer listening on the Unix domain socket at
+    /// `path` (see [CoapContext::add_endpoint_unix()]).
+    ///
+    /// In contrast to network sockets, Unix domain datagram sockets need to be bound to a local
+    /// path in order to receive responses, which is why a `local_path` has to be provided as well.
+    /// If a stale socket file exists at `local_path`, it is removed before connecting. The socket
+    /// file is removed again once the session is dropped.
+    ///
+    /// Note that [CoapSessionCommon::addr_local()](super::CoapSessionCommon::addr_local) and
+    /// [CoapSessionCommon::addr_remote()](super::CoapSessionCommon::addr_remote) can not be used
+    /// for Unix domain socket sessions, use
+    /// [CoapSessionCommon::local_address()](super::CoapSessionCommon::local_address) and
+    /// [CoapSessionCommon::remote_address()](super::CoapSessionCommon::remote_address) instead.
+    ///
+    /// # Errors
+    /// Will return [SessionCreationError::InvalidUnixPath] if one of the paths is too long, or
+    /// `local_path` refers to an existing file that is not a socket, or
+    /// [SessionCreationError::Unknown] if libcoap was unable to create a session.
+    #[cfg(all(feature = "af-unix", unix))]
+    pub fn connect_unix<'a, L: AsRef<Path>, P: AsRef<Path>>(
+        ctx: &mut CoapContext<'a>,
+        local_path: L,
+        path: P,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        ctx.check_session_memory()?;
+        let remote_addr = CoapAddress::unix(path.as_ref())?;
+        let local_addr = crate::transport::prepare_unix_socket_path(local_path.as_ref())?;
+        // SAFETY: self.raw_context is guaranteed to be valid, addresses are valid.
+        let session = ctx.with_client_block_mode(|raw_context| unsafe {
             coap_new_client_session(
-                ctx.as_mut_raw_context(),
-                std::ptr::null(),
-                CoapAddress::from(addr).as_raw_address(),
+                raw_context,
+                local_addr.as_raw_address(),
+                remote_addr.as_raw_address(),
                 coap_proto_t::COAP_PROTO_UDP,
             )
-        };
+        });
         if session.is_null() {
             return Err(SessionCreationError::Unknown);
         }
         // SAFETY: Session was just checked for validity.
-        Ok(CoapClientSession {
+        let session = CoapClientSession {
             inner: unsafe { CoapClientSessionInner::new(session) },
-        })
+        };
+        session.inner.borrow_mut().unix_path = Some(local_path.as_ref().to_path_buf());
+        Ok(session)
     }
 
-    /// Create a new unencrypted session with the given peer over TCP.
-    ///
-    /// # Errors
-    /// Will return a [SessionCreationError] if libcoap was unable to create a session (most likely
-    /// because it was not possible to bind to a port).
-    pub fn connect_tcp<'a>(
+    fn connect_unencrypted<'a>(
         ctx: &mut CoapContext<'a>,
+        local_addr: Option<SocketAddr>,
         addr: SocketAddr,
+        proto: coap_proto_t,
     ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        check_transport_supported(proto)?;
+        ctx.check_session_memory()?;
+        let local_addr = match proto {
+            coap_proto_t::COAP_PROTO_UDP => local_addr.or_else(|| ctx.shared_source_addr(addr)),
+            _ => local_addr,
+        };
+        check_address_families(local_addr, addr)?;
+        let raw_local_addr = local_addr.map(CoapAddress::from);
+        let error_cleared = clear_last_error();
         // SAFETY: self.raw_context is guaranteed to be valid, local_if can be null.
-        let session = unsafe {
+        let session = ctx.with_client_block_mode(|raw_context| unsafe {
             coap_new_client_session(
-                ctx.as_mut_raw_context(),
-                std::ptr::null(),
+                raw_context,
+                raw_local_addr.as_ref().map_or(std::ptr::null(), |v| v.as_raw_address()),
                 CoapAddress::from(addr).as_raw_address(),
-                coap_proto_t::COAP_PROTO_TCP,
+                proto,
             )
-        };
+        });
         if session.is_null() {
-            return Err(SessionCreationError::Unknown);
+            return Err(session_creation_failure(local_addr, error_cleared));
         }
         // SAFETY: Session was just checked for validity.
         Ok(CoapClientSession {
@@ -224,6 +931,322 @@ impl CoapClientSession<'_> {
     }
 }
 
+/// Checks that the libcoap build in use supports the transport protocol of a new session.
+fn check_transport_supported(proto: coap_proto_t) -> Result<(), SessionCreationError> {
+    if transport_supported(proto) {
+        return Ok(());
+    }
+    let proto = CoapProtocol::from(proto);
+    if proto.is_secure() && tls_backend().library() == TlsLibrary::NoTls {
+        Err(SessionCreationError::TlsBackendMissing)
+    } else {
+        Err(SessionCreationError::TransportUnsupported(proto))
+    }
+}
+
+/// Checks that the local and remote address of a new session are of the same address family, and
+/// that link-local addresses specify the interface to use.
+fn check_address_families(local_addr: Option<SocketAddr>, addr: SocketAddr) -> Result<(), SessionCreationError> {
+    if is_unscoped_link_local(&addr) || local_addr.as_ref().is_some_and(is_unscoped_link_local) {
+        return Err(SessionCreationError::MissingScopeId);
+    }
+    match local_addr {
+        Some(local_addr) if local_addr.is_ipv4() != addr.is_ipv4() => Err(SessionCreationError::AddressFamilyMismatch),
+        _ => Ok(()),
+    }
+}
+
+/// Returns [SessionCreationError::BindFailed] if libcoap was unable to create a session because
+/// binding to the provided local address failed.
+///
+/// libcoap does not report why session creation failed, so this relies on `errno`, which is only
+/// meaningful if it was cleared before calling into libcoap (`error_cleared`, see
+/// [clear_last_error()]). As libcoap may also fail (and set `errno`) for other reasons, only the
+/// errors caused by binding to an unusable address (`EADDRINUSE`, `EADDRNOTAVAIL` and `EACCES`)
+/// are considered.
+fn bind_failure(local_addr: Option<SocketAddr>, error_cleared: bool) -> Option<SessionCreationError> {
+    let local_addr = local_addr.filter(|_| error_cleared)?;
+    let kind = std::io::Error::last_os_error().kind();
+    matches!(
+        kind,
+        std::io::ErrorKind::AddrInUse | std::io::ErrorKind::AddrNotAvailable | std::io::ErrorKind::PermissionDenied
+    )
+    .then_some(SessionCreationError::BindFailed(local_addr, kind))
+}
+
+/// Returns the error that should be reported if libcoap was unable to create a session.
+fn session_creation_failure(local_addr: Option<SocketAddr>, error_cleared: bool) -> SessionCreationError {
+    bind_failure(local_addr, error_cleared).unwrap_or(SessionCreationError::Unknown)
+}
+
+/// Returns the error that should be reported if libcoap was unable to create a DTLS session.
+///
+/// Apart from binding to the local address, creating a DTLS session may fail if the TLS library is
+/// unable to set up the session (e.g., because it rejects the provided credentials).
+#[cfg(dtls)]
+fn handshake_init_failure(local_addr: Option<SocketAddr>, error_cleared: bool) -> SessionCreationError {
+    bind_failure(local_addr, error_cleared).unwrap_or(SessionCreationError::HandshakeInitFailure)
+}
+
+/// Resolves the host part of the given URI into a list of socket addresses.
+///
+/// Returns an error if the URI scheme is not contained in `allowed_schemes`.
+/// If the URI does not contain a port, the default port for the URI scheme is used.
+/// IPv6 zone identifiers (e.g., `coap://[fe80::1%25eth0]`) are resolved by the system resolver.
+pub(crate) fn resolve_uri(
+    uri: &CoapUri,
+    allowed_schemes: &[CoapUriScheme],
+) -> Result<(CoapUriScheme, Vec<SocketAddr>), SessionCreationError> {
+    let (scheme, host) = match (uri.scheme(), uri.decoded_host()) {
+        (Some(scheme), Some(host)) => (scheme, host),
+        _ => return Err(SessionCreationError::MissingHost),
+    };
+    if !allowed_schemes.contains(&scheme) {
+        return Err(SessionCreationError::UnsupportedScheme(scheme));
+    }
+    let port = uri.port().unwrap_or(scheme.default_port());
+    let host = host.as_ref();
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| SessionCreationError::DnsFailure(host.to_string(), e.kind()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(SessionCreationError::UnresolvableHost(host.to_string()));
+    }
+    Ok((scheme, addrs))
+}
+
+impl<'a> CoapClientSession<'a> {
+    /// Disconnects this session from its peer and releases the underlying raw session.
+    ///
+    /// Releasing the raw session causes libcoap to shut down the session in a way appropriate for
+    /// the transport protocol (e.g., by sending a DTLS close_notify alert or closing the TCP
+    /// connection). Responses to outstanding requests will no longer be received afterwards.
+    ///
+    /// As the raw session may only be released once it is no longer used, this function only
+    /// succeeds if this is the last remaining handle to the session, i.e., there are no other
+    /// clones of this session (including [CoapSession](super::CoapSession)s and
+    /// [SessionGuard](super::SessionGuard)s referring to it).
+    ///
+    /// Connecting to the same peer again afterwards (e.g., using
+    /// [connect_udp()](CoapClientSession::connect_udp)) will create a new session.
+    ///
+    /// # Errors
+    /// Returns this session back to the caller if other handles to the session still exist.
+    pub fn disconnect(self) -> Result<(), CoapClientSession<'a>> {
+        if self.inner.strong_count() > 1 {
+            return Err(self);
+        }
+        self.drop_exclusively();
+        Ok(())
+    }
+
+    /// Returns whether this session has lost its connection and is waiting to be re-established
+    /// by libcoap (see [CoapContext::set_reconnect_policy()]).
+    ///
+    /// Always returns false if no reconnect policy is set for the context of this session.
+    pub fn is_reconnecting(&self) -> bool {
+        self.inner_ref().reconnecting
+    }
+
+    /// Returns the error this session has been failed with after it was created, i.e.,
+    /// [SessionCreationError::HandshakeTimeout] if it was not established before its handshake
+    /// deadline (see [CoapContext::set_handshake_deadline()]), or
+    /// [SessionCreationError::HandshakeFailed] if its initial (D)TLS handshake failed.
+    ///
+    /// Failed sessions do not recover, use a new session to connect to the peer again.
+    /// Handshake failures of sessions that have been established before (e.g., while being
+    /// reconnected) are reported by [CoapSessionCommon::last_error()] instead.
+    pub fn connect_error(&self) -> Option<SessionCreationError> {
+        if handshake_timed_out(self) {
+            return Some(SessionCreationError::HandshakeTimeout);
+        }
+        if self.inner_ref().has_been_established {
+            return None;
+        }
+        self.last_error()
+    }
+
+    /// Enables a client-side cache holding responses to up to `capacity` GET requests sent on
+    /// this session, or disables the cache if `capacity` is None (the default).
+    ///
+    /// While the cache is enabled, 2.05 (Content) responses to GET requests are stored alongside
+    /// the URI and Accept option of the request. Repeated requests for the same URI are answered
+    /// from the cache without contacting the server for as long as the cached response is fresh
+    /// (see [CoapResponse::freshness_lifetime()](crate::message::CoapResponse::freshness_lifetime)),
+    /// i.e., [CoapSessionCommon::poll_handle()] returns the response immediately.
+    /// Stale responses that have an ETag are revalidated by sending the request with this ETag:
+    /// if the server answers with 2.03 (Valid), the cached response is refreshed and returned
+    /// instead.
+    /// If the cache is full, the least recently used response is evicted.
+    ///
+    /// Requests that use Observe, ETag or No-Response options or have a payload are never
+    /// answered from the cache, and individual requests can bypass it using
+    /// [CoapRequestBuilder::bypass_cache()](crate::message::CoapRequestBuilder::bypass_cache).
+    ///
+    /// Reducing the capacity of an enabled cache evicts responses if required, disabling the cache
+    /// discards all cached responses.
+    pub fn set_response_cache_capacity(&self, capacity: Option<usize>) {
+        let mut inner = self.inner_mut();
+        match (inner.response_cache.as_mut(), capacity) {
+            (Some(cache), Some(capacity)) => cache.set_capacity(capacity),
+            (_, capacity) => inner.response_cache = capacity.map(ResponseCache::new),
+        }
+        inner.account_memory();
+    }
+
+    /// Returns the capacity of the response cache of this session, or None if the cache is
+    /// disabled (see [CoapClientSession::set_response_cache_capacity()]).
+    pub fn response_cache_capacity(&self) -> Option<usize> {
+        self.inner_ref().response_cache.as_ref().map(ResponseCache::capacity)
+    }
+
+    /// Removes all responses from the response cache of this session (see
+    /// [CoapClientSession::set_response_cache_capacity()]).
+    pub fn clear_response_cache(&self) {
+        let mut inner = self.inner_mut();
+        if let Some(cache) = inner.response_cache.as_mut() {
+            cache.clear();
+        }
+        inner.account_memory();
+    }
+
+    /// Sets the policy for re-sending requests that are answered with 5.03 (Service Unavailable),
+    /// or disables re-sending them if `policy` is None (the default).
+    ///
+    /// If a policy is set, requests sent afterwards whose method is covered by the policy are
+    /// re-sent once the delay indicated by the Max-Age option of such a response (60 seconds if
+    /// it has none) has elapsed, instead of providing the response to the application. Requests
+    /// are re-sent using their original token, so the final response is delivered to the handle
+    /// returned by [CoapSessionCommon::send_request()] as usual: either the response to the first
+    /// attempt that is not re-sent, or the response to the last attempt if the maximum number of
+    /// retries has been reached. Each attempt to re-send a request is reported to the hook set
+    /// using [CoapClientSession::set_retry_hook()].
+    ///
+    /// Delays are measured using the clock of the context (see
+    /// [CoapContext::set_clock()](crate::CoapContext::set_clock)), and requests are re-sent while
+    /// polling for their responses (e.g., in [CoapSessionCommon::poll_handle()]), so the context
+    /// has to keep performing IO in the meantime. A timeout set on the request (see
+    /// [CoapRequest::set_timeout()](crate::message::CoapRequest::set_timeout)) covers all attempts.
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        self.inner_mut().retry_policy = policy;
+    }
+
+    /// Returns the policy for re-sending requests answered with 5.03 (Service Unavailable), see
+    /// [CoapClientSession::set_retry_policy()].
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.inner_ref().retry_policy
+    }
+
+    /// Sets a hook that is called whenever a request is scheduled to be re-sent according to the
+    /// retry policy of this session (see [CoapClientSession::set_retry_policy()]), replacing any
+    /// previously set hook.
+    ///
+    /// The hook may use this session (e.g., to cancel the request using
+    /// [CoapSessionCommon::remove_handle()]), but must not replace or clear itself.
+    pub fn set_retry_hook<F: FnMut(&CoapRetryAttempt) + 'static>(&self, hook: F) {
+        self.inner_mut().retry_hook = Some(RetryHook(Box::new(hook)));
+    }
+
+    /// Removes the hook set using [CoapClientSession::set_retry_hook()].
+    pub fn clear_retry_hook(&self) {
+        self.inner_mut().retry_hook = None;
+    }
+
+    /// Creates a weak reference to this session, which does not keep the session alive.
+    pub(crate) fn downgrade(&self) -> WeakCoapClientSession<'a> {
+        WeakCoapClientSession(self.inner.downgrade())
+    }
+
+    /// Wraps an existing raw client session that was created using libcoap-sys (e.g., using
+    /// `coap_new_client_session()`) on the raw context of `ctx`.
+    ///
+    /// The wrapper takes over the application data pointer of the raw session, which allows
+    /// sending requests and receiving responses using this wrapper (see also
+    /// [CoapContext::from_raw()]).
+    /// The token state of the raw session is preserved.
+    ///
+    /// If `ownership` is [Ownership::Borrowed], the wrapper increases the reference counter of the
+    /// raw session and decreases it again once it is dropped, i.e., the caller keeps its own
+    /// reference and remains responsible for releasing it.
+    /// If `ownership` is [Ownership::Owned], the caller's reference is transferred to the wrapper,
+    /// which releases it once it is dropped.
+    /// In both cases, the application data of the raw session is reset once the wrapper is dropped.
+    ///
+    /// # Errors
+    /// Returns [SessionCreationError::InvalidRawSession] if the raw session is not a client-side
+    /// session of `ctx`, or [SessionCreationError::AppDataInUse] if it already has application
+    /// data (e.g., because it is already wrapped by another [CoapClientSession]).
+    ///
+    /// # Safety
+    /// The provided pointer must point to a valid raw session, which must not be released by the
+    /// caller while the wrapper exists.
+    /// If `ownership` is [Ownership::Owned], the caller must no longer use the raw session after
+    /// the wrapper has been dropped.
+    pub unsafe fn from_raw_external(
+        ctx: &CoapContext<'a>,
+        raw_session: *mut coap_session_t,
+        ownership: Ownership,
+    ) -> Result<CoapClientSession<'a>, SessionCreationError> {
+        assert!(!raw_session.is_null(), "provided raw session was null");
+        if coap_session_get_type(raw_session) != coap_session_type_t::COAP_SESSION_TYPE_CLIENT
+            || !std::ptr::eq(coap_session_get_context(raw_session), ctx.as_raw_context())
+        {
+            return Err(SessionCreationError::InvalidRawSession);
+        }
+        if !coap_session_get_app_data(raw_session).is_null() {
+            return Err(SessionCreationError::AppDataInUse);
+        }
+        if ownership == Ownership::Borrowed {
+            coap_session_reference(raw_session);
+        }
+        Ok(CoapClientSession {
+            inner: CoapClientSessionInner::new_external(raw_session, ownership),
+        })
+    }
+
+    /// Releases the raw session from this wrapper, returning it to the caller.
+    ///
+    /// The application data of the raw session is reset, and any responses that were received but
+    /// not yet polled are discarded.
+    /// The caller owns one reference to the returned raw session and is responsible for releasing
+    /// it using `coap_session_release()` (for sessions wrapped using [Ownership::Borrowed], this
+    /// is the reference the caller already held).
+    ///
+    /// # Errors
+    /// Returns this session back to the caller if other handles to the session still exist (see
+    /// [disconnect()](CoapClientSession::disconnect)), or if the session uses a crypto context
+    /// provided to this wrapper, as libcoap would otherwise refer to the crypto context after it
+    /// was dropped.
+    pub fn into_raw(self) -> Result<*mut coap_session_t, CoapClientSession<'a>> {
+        if self.inner.strong_count() > 1 {
+            return Err(self);
+        }
+        #[cfg(dtls)]
+        if self.inner.borrow().crypto_ctx.is_some() {
+            return Err(self);
+        }
+        let raw_session = {
+            let mut inner = self.inner.borrow_mut();
+            inner.keep_raw_session = true;
+            inner.inner.raw_session
+        };
+        self.drop_exclusively();
+        Ok(raw_session)
+    }
+}
+
+/// A weak reference to a [CoapClientSession], see [CoapClientSession::downgrade()].
+#[derive(Debug, Clone)]
+pub(crate) struct WeakCoapClientSession<'a>(CoapFfiWeakCell<CoapClientSessionInner<'a>>);
+
+impl<'a> WeakCoapClientSession<'a> {
+    /// Attempts to upgrade this weak reference, returning None if the session was already dropped.
+    pub(crate) fn upgrade(&self) -> Option<CoapClientSession<'a>> {
+        self.0.upgrade().map(|inner| CoapClientSession { inner })
+    }
+}
+
 impl DropInnerExclusively for CoapClientSession<'_> {
     fn drop_exclusively(self) {
         self.inner.drop_exclusively();
@@ -243,6 +1266,12 @@ impl Drop for CoapClientSessionInner<'_> {
             // Recreate weak pointer instance so that it can be dropped (which in turn reduces the
             // weak reference count, avoiding memory leaks).
             CoapFfiRcCell::<CoapClientSessionInner>::raw_ptr_to_weak(app_data);
+            coap_session_set_app_data(self.inner.raw_session, std::ptr::null_mut());
+            // If the raw session is handed back to an owner that holds its own reference, there is
+            // nothing left to do.
+            if self.keep_raw_session && self.ownership == Ownership::Owned {
+                return;
+            }
             // We need to temporarily disable event handling so that our own event handler does not
             // access this already partially invalid session (and recursively also calls this Drop
             // implementation), causing a SIGABRT.
@@ -257,8 +1286,16 @@ impl Drop for CoapClientSessionInner<'_> {
             coap_register_event_handler(raw_context, None);
             // Let libcoap do its cleanup of the raw session and free the associated memory.
             coap_session_release(self.inner.raw_session);
-            // Restore event handler.
-            coap_register_event_handler(raw_context, Some(event_handler_callback));
+            // Restore event handler, unless the context has already been dropped (and only waits
+            // for its sessions to be dropped before freeing the raw context).
+            if !coap_get_app_data(raw_context).is_null() {
+                coap_register_event_handler(raw_context, Some(event_handler_callback));
+            }
+        }
+        #[cfg(all(feature = "af-unix", unix))]
+        if let (Some(path), false) = (&self.unix_path, self.keep_raw_session) {
+            // If the file was already removed by someone else, there is nothing left to do.
+            let _ = std::fs::remove_file(path);
         }
     }
 }
@@ -275,10 +1312,9 @@ impl<'a> CoapSessionInnerProvider<'a> for CoapClientSession<'a> {
 impl<'a, T: CoapSessionCommon<'a>> PartialEq<T> for CoapClientSession<'_> {
     fn eq(&self, other: &T) -> bool {
         // SAFETY: Pointers are only compared, never accessed.
-        self.if_index() == other.if_index()
-            && unsafe { self.raw_session() == other.raw_session() }
-            && self.addr_local() == other.addr_local()
-            && self.addr_remote() == other.addr_remote()
+        // The addresses are not compared, as they are the same for identical raw sessions (and
+        // can't be represented as a SocketAddr for Unix domain socket sessions).
+        self.if_index() == other.if_index() && unsafe { self.raw_session() == other.raw_session() }
     }
 }
 
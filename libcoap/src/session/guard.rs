@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/guard.rs - Thread-safe guards that keep sessions alive.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::sync::Arc;
+
+use libcoap_sys::{coap_session_get_context, coap_session_reference, coap_session_release};
+
+#[cfg(feature = "async")]
+use super::async_request::complete_async_request;
+use super::{sealed::CoapSessionInnerProvider, CoapSession, CoapSessionCommon, CoapSessionId};
+use crate::{error::ContextHandleError, handle::CoapContextHandle, CoapContext};
+#[cfg(feature = "async")]
+use crate::{
+    message::response::CoapResponse,
+    protocol::{CoapMessageType, CoapResponseCode, CoapToken},
+};
+
+/// Thread-safe handle to a session that keeps the underlying libcoap session alive.
+///
+/// Sessions (like all other objects associated with a [CoapContext]) may only be used by the IO
+/// thread of their context. Guards obtained using [CoapSessionCommon::guard()] (e.g., for the
+/// session passed to a request handler or an event handler) are [Send] and may therefore be moved
+/// to other threads, which can use them to queue operations on the session (see
+/// [SessionGuard::execute()]) or to answer deferred requests (see
+/// [SessionGuard::send_deferred_response()]).
+/// Like the operations queued using a [CoapContextHandle], these are executed by the IO thread
+/// during its next call to [CoapContext::do_io()].
+///
+/// As long as a guard (or one of its clones) exists, its context keeps the session alive and has
+/// the libcoap-internal reference counter of the session incremented (`coap_session_reference()`),
+/// so libcoap does not clean up the session in the meantime (e.g., because it has been idle for
+/// too long, see [CoapContext::set_session_timeout()]).
+///
+/// Guards may outlive the context of their session: once the context is dropped, the session is
+/// released alongside it and queuing operations using the guard fails with
+/// [ContextHandleError::ContextDropped].
+///
+/// This type is only available on Unix and Windows.
+///
+/// # Examples
+/// ```no_run
+/// use libcoap_rs::{message::CoapMessageCommon, protocol::CoapResponseCode, session::CoapSessionCommon};
+/// use libcoap_rs::{CoapContext, CoapResource};
+///
+/// let mut context = CoapContext::new().unwrap();
+/// let resource = CoapResource::builder("ping", ())
+///     .post(|_resource, session, _request, mut response| {
+///         let guard = session.guard().unwrap();
+///         std::thread::spawn(move || {
+///             // ...wait for the peer to become relevant again...
+///             guard
+///                 .execute(|session| {
+///                     let _ = session.send_ping();
+///                 })
+///                 .unwrap();
+///         });
+///         response.set_code(CoapResponseCode::Changed);
+///         session.send(response).unwrap();
+///     })
+///     .build()
+///     .unwrap();
+/// context.add_resource(resource);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionGuard {
+    session_id: CoapSessionId,
+    registration: Arc<GuardRegistration>,
+}
+
+/// Registration of a guarded session with its context, which is removed once the last clone of
+/// the corresponding [SessionGuard] is dropped.
+#[derive(Debug)]
+struct GuardRegistration {
+    id: u64,
+    context: CoapContextHandle,
+}
+
+impl SessionGuard {
+    /// Creates a new guard for the provided session.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::WakerCreationFailed] if the handle to the context of the
+    /// session can not be created (see [CoapContext::handle()]).
+    pub fn new<'a, S: CoapSessionCommon<'a> + ?Sized>(session: &S) -> Result<SessionGuard, ContextHandleError> {
+        let raw_session = session.inner_ref().raw_session;
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, and
+        // its context is valid for at least as long as the session.
+        let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(raw_session)) };
+        // Obtain the handle first, so that the session is not kept alive if this fails.
+        let handle = context.handle()?;
+        // SAFETY: The raw session is valid and managed by this wrapper, as it belongs to a session
+        // wrapper.
+        let session = GuardedSession::new(unsafe { CoapSession::from_raw(raw_session) });
+        Ok(SessionGuard {
+            session_id: session.session.id(),
+            registration: Arc::new(GuardRegistration {
+                id: context.register_session_guard(session),
+                context: handle,
+            }),
+        })
+    }
+
+    /// Returns the ID of the guarded session.
+    pub fn session_id(&self) -> CoapSessionId {
+        self.session_id
+    }
+
+    /// Calls `operation` with the guarded session on the IO thread of its context.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    pub fn execute<F: for<'a> FnOnce(&mut CoapSession<'a>) + Send + 'static>(
+        &self,
+        operation: F,
+    ) -> Result<(), ContextHandleError> {
+        let id = self.registration.id;
+        self.registration.context.execute(move |context| {
+            if let Some(mut session) = context.guarded_session(id) {
+                operation(&mut session);
+            }
+        })
+    }
+
+    /// Answers the asynchronous request with the given token (see
+    /// [CoapRequest::into_async()](crate::message::CoapRequest::into_async)) of the guarded
+    /// session by calling `respond` with its response on the IO thread of the context, after
+    /// which the response is sent.
+    ///
+    /// The response is initialized with the code 2.05 (Content). It is not sent if the guarded
+    /// session is not a server-side session or if the request has already been answered or
+    /// abandoned (see [CoapAsyncHandle](super::CoapAsyncHandle)) in the meantime.
+    ///
+    /// This function is only available if the `async` feature is enabled.
+    ///
+    /// # Errors
+    /// Returns [ContextHandleError::ContextDropped] if the context has already been dropped.
+    #[cfg(feature = "async")]
+    pub fn send_deferred_response<F: FnOnce(&mut CoapResponse) + Send + 'static>(
+        &self,
+        token: &[u8],
+        respond: F,
+    ) -> Result<(), ContextHandleError> {
+        let token = CoapToken::from(token);
+        self.execute(move |session| {
+            let CoapSession::Server(session) = session else {
+                return;
+            };
+            // The message type is set by libcoap when the response is sent.
+            let mut response = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content)
+                .expect("confirmable responses are valid");
+            respond(&mut response);
+            // Requests that have been answered or abandoned in the meantime can no longer be
+            // answered.
+            let _ = complete_async_request(session, &token, response);
+        })
+    }
+}
+
+impl Drop for GuardRegistration {
+    fn drop(&mut self) {
+        let id = self.id;
+        // If the context has already been dropped, it has released the session alongside it.
+        let _ = self.context.execute(move |context| context.release_session_guard(id));
+    }
+}
+
+/// Session that is kept alive by its context on behalf of a [SessionGuard].
+#[derive(Debug)]
+pub(crate) struct GuardedSession<'a> {
+    session: CoapSession<'a>,
+}
+
+impl<'a> GuardedSession<'a> {
+    fn new(session: CoapSession<'a>) -> GuardedSession<'a> {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        unsafe { coap_session_reference(session.inner_ref().raw_session) };
+        GuardedSession { session }
+    }
+
+    /// Returns the guarded session.
+    pub(crate) fn session(&self) -> &CoapSession<'a> {
+        &self.session
+    }
+}
+
+impl Drop for GuardedSession<'_> {
+    fn drop(&mut self) {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, and we
+        // incremented the reference counter on creation.
+        unsafe { coap_session_release(self.session.inner_ref().raw_session) };
+    }
+}
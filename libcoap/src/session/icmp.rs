@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/icmp.rs - Handling of ICMP errors reported for sessions.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+use libc::__errno as errno_location;
+#[cfg(any(target_os = "linux", target_os = "emscripten"))]
+use libc::__errno_location as errno_location;
+#[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "dragonfly"))]
+use libc::__error as errno_location;
+
+/// Smallest MTU the wrapper reduces the MTU of a session to after a request was too large to be
+/// sent (see [IcmpErrorPolicy::reduce_mtu]).
+///
+/// Like libcoap's MTU, this refers to the maximum size of a CoAP PDU. It still fits blocks of 128
+/// bytes alongside typical options.
+pub const MIN_REDUCED_MTU: u32 = 256;
+
+/// Policy for handling ICMP errors of (unreliable) client sessions, see
+/// [CoapSessionCommon::set_icmp_error_policy()](crate::session::CoapSessionCommon::set_icmp_error_policy).
+///
+/// libcoap reports ICMP Destination Unreachable errors (e.g., Port Unreachable because the peer
+/// does not listen on the port the session is connected to) for each confirmable message it has
+/// not received an acknowledgement for yet. Such messages are kept by libcoap and retransmitted
+/// regardless of this policy, as the peer may become reachable again before the maximum number of
+/// retransmissions is reached. Non-confirmable requests are not tracked by libcoap, so ICMP errors
+/// caused by them are not reported.
+///
+/// libcoap does not report "Fragmentation Needed" (IPv4) or "Packet Too Big" (IPv6) errors as
+/// such. Operating systems typically update the path MTU once they receive one of these errors and
+/// reject subsequent datagrams exceeding it, which is what [IcmpErrorPolicy::reduce_mtu] reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IcmpErrorPolicy {
+    /// Whether requests whose confirmable messages caused an ICMP Destination Unreachable error
+    /// fail immediately with
+    /// [RequestPollError::IcmpUnreachable](crate::error::RequestPollError::IcmpUnreachable)
+    /// (true by default).
+    ///
+    /// If false, such requests keep waiting for a response until they time out or libcoap gives
+    /// up retransmitting them.
+    ///
+    /// Failing a request does not cancel the retransmissions of its confirmable message: libcoap
+    /// provides no public API for removing messages from its retransmission queue (see also
+    /// [CoapContext::abort_pending()](crate::CoapContext::abort_pending)), so the message is still
+    /// retransmitted until it is acknowledged or the maximum number of retransmissions of the
+    /// session is reached. Responses received for a failed request afterwards are discarded.
+    /// In order to limit the traffic sent to unreachable peers, reduce the maximum number of
+    /// retransmissions of the session (see
+    /// [CoapSessionCommon::set_max_retransmit()](crate::session::CoapSessionCommon::set_max_retransmit)).
+    pub fail_requests: bool,
+    /// Whether requests that could not be sent because they were too large for the path to the
+    /// peer are sent again after halving the MTU of the session (at most down to
+    /// [MIN_REDUCED_MTU]), which makes libcoap split their bodies into smaller blocks (true by
+    /// default).
+    ///
+    /// Each reduction is counted in [CoapStats::mtu_reductions](crate::CoapStats::mtu_reductions).
+    /// Requests that are still too large once the MTU cannot be reduced any further (or if this
+    /// is false) fail with
+    /// [MessageConversionError::PacketTooBig](crate::error::MessageConversionError::PacketTooBig).
+    pub reduce_mtu: bool,
+}
+
+impl Default for IcmpErrorPolicy {
+    fn default() -> Self {
+        IcmpErrorPolicy {
+            fail_requests: true,
+            reduce_mtu: true,
+        }
+    }
+}
+
+/// Returns the MTU to use after a PDU could not be sent with the given current MTU because it was
+/// too large, or None if the MTU cannot be reduced any further.
+pub(crate) fn reduced_mtu(current: u32) -> Option<u32> {
+    (current > MIN_REDUCED_MTU).then(|| (current / 2).max(MIN_REDUCED_MTU))
+}
+
+#[cfg(windows)]
+extern "system" {
+    // Part of kernel32, which is always linked on Windows. Winsock reports its errors using the
+    // same per-thread value.
+    fn SetLastError(code: u32);
+}
+
+/// Clears the most recent OS error of the current thread, so that checking it afterwards (e.g.,
+/// using [last_error_is_packet_too_big()]) only reports errors that occur in the meantime.
+///
+/// libcoap does not guarantee that `errno` is set if it fails (e.g., to send a message or to
+/// create a session), so it has to be cleared before calling into libcoap.
+///
+/// Returns false if the error can not be cleared on this platform, in which case it must not be
+/// relied on.
+pub(crate) fn clear_last_error() -> bool {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))]
+    {
+        // SAFETY: The returned location of errno is valid for the current thread.
+        unsafe { *errno_location() = 0 };
+        true
+    }
+    #[cfg(windows)]
+    {
+        // SAFETY: SetLastError() has no preconditions.
+        unsafe { SetLastError(0) };
+        true
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        windows
+    )))]
+    false
+}
+
+/// Returns whether the most recent OS error indicates that a datagram could not be sent because it
+/// exceeds the (path) MTU.
+///
+/// Must only be called after clearing the error using [clear_last_error()] before the call that
+/// may have failed, as libcoap may report failures without setting the error.
+pub(crate) fn last_error_is_packet_too_big() -> bool {
+    #[cfg(unix)]
+    let code = Some(libc::EMSGSIZE);
+    // Socket functions report WSAEMSGSIZE instead of errno values on Windows.
+    #[cfg(windows)]
+    let code = Some(10040);
+    #[cfg(not(any(unix, windows)))]
+    let code = None;
+    code.is_some_and(|code| std::io::Error::last_os_error().raw_os_error() == Some(code))
+}
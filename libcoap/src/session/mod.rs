@@ -10,45 +10,166 @@
 use std::{
     any::Any,
     borrow::BorrowMut,
-    cell::{Ref, RefMut},
-    collections::{HashMap, VecDeque},
+    cell::{Ref, RefCell, RefMut},
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::CStr,
     marker::PhantomData,
     net::{SocketAddr, ToSocketAddrs},
     rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
 };
 
 use libcoap_sys::{
-    coap_context_t, coap_fixed_point_t, coap_mid_t, coap_new_message_id, coap_pdu_get_token, coap_pdu_t,
-    coap_response_t, coap_send, coap_session_get_ack_random_factor, coap_session_get_ack_timeout,
-    coap_session_get_addr_local, coap_session_get_addr_remote, coap_session_get_ifindex,
-    coap_session_get_max_retransmit, coap_session_get_proto, coap_session_get_state, coap_session_get_type,
-    coap_session_init_token, coap_session_max_pdu_size, coap_session_new_token, coap_session_send_ping,
-    coap_session_set_ack_random_factor, coap_session_set_ack_timeout, coap_session_set_max_retransmit,
-    coap_session_set_mtu, coap_session_state_t, coap_session_t, coap_session_type_t,
+    coap_context_t, coap_delete_pdu, coap_event_t, coap_fixed_point_t, coap_mid_t, coap_new_message_id,
+    coap_pdu_get_mid, coap_pdu_get_token, coap_pdu_get_type, coap_pdu_t, coap_resource_t, coap_response_t, coap_send,
+    coap_session_get_ack_random_factor, coap_session_get_ack_timeout, coap_session_get_addr_local,
+    coap_session_get_addr_remote, coap_session_get_app_data, coap_session_get_context,
+    coap_session_get_default_leisure, coap_session_get_ifindex, coap_session_get_max_retransmit,
+    coap_session_get_probing_rate, coap_session_get_proto, coap_session_get_state, coap_session_get_tls,
+    coap_session_get_type, coap_session_init_token, coap_session_max_pdu_size, coap_session_new_token,
+    coap_session_send_ping, coap_session_set_ack_random_factor, coap_session_set_ack_timeout,
+    coap_session_set_default_leisure, coap_session_set_max_retransmit, coap_session_set_mtu,
+    coap_session_set_probing_rate, coap_session_state_t, coap_session_str, coap_session_t, coap_session_type_t,
+    coap_string_t, coap_tls_library_t, COAP_INVALID_MID,
 };
+#[cfg(coap_rxbuffer_headroom)]
+use libcoap_sys::COAP_RXBUFFER_SIZE;
 #[cfg(feature = "dtls-psk")]
 use libcoap_sys::{coap_session_get_psk_hint, coap_session_get_psk_identity, coap_session_get_psk_key};
 
-use self::sealed::{CoapSessionCommonInternal, CoapSessionInnerProvider};
-pub use self::{client::CoapClientSession, server::CoapServerSession};
+#[cfg(feature = "async")]
+pub(crate) use self::async_request::{
+    abandon_async_requests, finish_async_replay, is_async_replay, release_async_requests, AsyncRequest,
+};
+#[cfg(feature = "async")]
+pub use self::async_request::{CoapAsyncHandle, CoapAsyncState};
+#[cfg(dtls)]
+pub use self::client::{DtlsFallbackPolicy, FallbackSession};
+#[cfg(any(unix, windows))]
+pub(crate) use self::guard::GuardedSession;
+#[cfg(any(unix, windows))]
+pub use self::guard::SessionGuard;
+use self::{
+    client::RetryHook,
+    icmp::{clear_last_error, last_error_is_packet_too_big, reduced_mtu},
+    notification_order::CoapNotificationOrder,
+    request_queue::QueuedRequest,
+    response_cache::{ResponseCache, ResponseCacheKey, ResponseCacheLookup},
+    sealed::{CoapSessionCommonInternal, CoapSessionInnerProvider},
+    send_queue::SendQueue,
+    transmission::RtoEstimator,
+};
+pub use self::{
+    client::{CoapClientSession, CoapRetryAttempt, ReconnectPolicy, RetryPolicy},
+    icmp::{IcmpErrorPolicy, MIN_REDUCED_MTU},
+    notification_order::{is_fresher_notification, CoapNotificationOrder, NOTIFICATION_FRESHNESS_TIMEOUT},
+    request_queue::OutstandingRequestLimit,
+    send_queue::SendQueueLimits,
+    server::CoapServerSession,
+    transmission::{
+        transmission_capabilities, CoapRetransmission, CoapRtoEstimate, CoapTransmissionCapabilities,
+        CoapTransmissionState,
+    },
+};
+#[cfg(any(unix, windows))]
+use crate::error::ContextHandleError;
 use crate::{
-    error::{MessageConversionError, SessionGetAppDataError},
-    message::{request::CoapRequest, response::CoapResponse, CoapMessage, CoapMessageCommon},
-    protocol::CoapToken,
-    types::{CoapAddress, CoapMessageId, CoapProtocol, IfIndex, MaxRetransmit},
+    context::{CoapContext, CoapContextShared},
+    crypto::{tls_backend, CoapCryptoSessionInfo, CoapTlsAlert, TlsLibrary},
+    error::{
+        ContextGetAppDataError, MessageConversionError, MessageTypeError, PingError, RequestPollError,
+        SessionCreationError, SessionGetAppDataError,
+    },
+    logging::{forward_trace_message, take_session_messages},
+    message::{
+        request::CoapRequest, response::CoapResponse, CoapMessage, CoapMessageCommon, CoapOption, CoapPduDirection,
+        CoapPduView,
+    },
+    protocol::{
+        CoapMessageCode, CoapMessageType, CoapNoResponse, CoapOptionNum, CoapOptionType, CoapToken, Observe, Size,
+        DEFAULT_MAX_TOKEN_SIZE,
+    },
+    resource::CoapResourceStats,
+    stats::{message_memory, CoapStats, CoapTransferStats, SessionMemoryUsage},
+    types::{decode_var_len_u32, CoapAddress, CoapMessageId, CoapProtocol, IfIndex, MaxRetransmit},
+    unwind::catch_session_callback_panic,
 };
 
+#[cfg(feature = "async")]
+mod async_request;
+
 pub mod client;
 
+#[cfg(any(unix, windows))]
+mod guard;
+
+mod icmp;
+
+mod notification_order;
+
+pub(crate) mod pool;
+
+mod request_queue;
+
+mod response_cache;
+
+mod send_queue;
+
 pub mod server;
 
+mod transmission;
+
+/// MAX_LATENCY as defined in [RFC 7252, Section 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2).
+const MAX_LATENCY: Duration = Duration::from_secs(100);
+
+/// Counter used to assign session IDs, see [CoapSessionId].
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Stable identifier of a logical CoAP session.
+///
+/// Session IDs are unique for all sessions created in this process and stay the same for the
+/// entire lifetime of the session, even if the remote address of the session changes (e.g. if a
+/// DTLS peer using Connection IDs is rebound to a different address by a NAT).
+/// Applications that need to associate state with a peer should therefore use this ID instead of
+/// [CoapSessionCommon::addr_remote()].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CoapSessionId(u64);
+
+impl CoapSessionId {
+    fn next() -> CoapSessionId {
+        CoapSessionId(NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the numeric value of this session ID.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for CoapSessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Representation of the states that a session can be in.
+///
+/// Note that libcoap does not have a separate state for sessions that are being closed, a session
+/// that has been closed (or that failed to connect, e.g., because of a DTLS handshake failure) is
+/// in the [CoapSessionState::None] state.
 #[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum CoapSessionState {
+    /// The session is closed or has not been connected yet.
     None = coap_session_state_t::COAP_SESSION_STATE_NONE as u32,
+    /// The underlying connection is being established (for reliable transports).
     Connecting = coap_session_state_t::COAP_SESSION_STATE_CONNECTING as u32,
+    /// The (D)TLS handshake is in progress.
     Handshake = coap_session_state_t::COAP_SESSION_STATE_HANDSHAKE as u32,
+    /// Capabilities and Settings Messages are being exchanged (for reliable transports).
     Csm = coap_session_state_t::COAP_SESSION_STATE_CSM as u32,
+    /// The session is established and can be used to exchange messages.
     Established = coap_session_state_t::COAP_SESSION_STATE_ESTABLISHED as u32,
 }
 
@@ -65,6 +186,65 @@ impl From<coap_session_state_t> for CoapSessionState {
     }
 }
 
+/// Reasons for disconnecting a server-side session using [CoapServerSession::disconnect()].
+///
+/// For reliable transports, the reason determines the signaling message that is sent to the peer
+/// before closing the connection (see
+/// [RFC 8323, Section 5.5 and 5.6](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5)).
+/// For unreliable transports, both reasons behave the same.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CoapSessionCloseReason {
+    /// The session is closed in an orderly fashion, the peer may connect again (Release message).
+    Release,
+    /// The session is closed because of a problem with the peer, e.g. repeated authentication
+    /// failures (Abort message).
+    Abort,
+}
+
+/// Policy that determines how responses received from an address other than the one the request
+/// was sent to are handled on unreliable transports (UDP/DTLS).
+///
+/// Note that libcoap itself only delivers such responses for sessions that do not use a connected
+/// socket (e.g., multicast requests), for all other sessions the operating system already filters
+/// datagrams from other addresses.
+///
+/// Regardless of the policy, each mismatch is counted (see
+/// [CoapSessionCommon::response_address_mismatch_count()]) and reported to
+/// [CoapEventHandler::handle_response_address_mismatch()](crate::CoapEventHandler::handle_response_address_mismatch).
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum CoapResponseAddressPolicy {
+    /// Drop all responses received from another address.
+    Strict,
+    /// Accept responses from addresses that belong to the same resolved host set as the
+    /// address the session was created for (i.e., all addresses the URI given to
+    /// [CoapClientSession::connect_uri()] resolved to), drop all others.
+    AcceptKnownAlternatives,
+    /// Accept responses from any address.
+    #[default]
+    Permissive,
+}
+
+/// Event concerning an observation (i.e., a request with an Observe option sent using
+/// [CoapSessionCommon::send_request()]), see [CoapSessionCommon::poll_observation_events()].
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CoapObservationEvent {
+    /// The observation has been registered again after its session was re-established (see
+    /// [ReconnectPolicy::reregister_observations]).
+    ///
+    /// As the server may have lost the observation while the session was disconnected,
+    /// notifications may have been missed.
+    Reregistered,
+    /// The server answered the re-registration of the observation without an Observe option
+    /// (e.g., with a 4.04 response because the resource no longer exists), i.e., the observation
+    /// has ended.
+    ///
+    /// The response itself is delivered to the request handle as usual.
+    Terminated(CoapMessageCode),
+}
+
 mod sealed {
     use super::*;
 
@@ -83,18 +263,105 @@ mod sealed {
     /// This trait does not have any mandatory functions and will be automatically implemented for
     /// all types that implement [CoapSessionInnerProvider].
     pub trait CoapSessionCommonInternal<'a>: CoapSessionInnerProvider<'a> {
-        fn add_response(&self, pdu: CoapResponse) {
-            let token = pdu.token();
+        fn add_response(&self, mut pdu: CoapResponse) {
+            let token = pdu.token().map(CoapToken::from);
             if let Some(token) = token {
-                if self.inner_ref().received_responses.contains_key(token) {
-                    self.inner_mut()
-                        .received_responses
-                        .get_mut(token)
-                        .unwrap()
-                        .push_back(pdu);
+                if self.inner_ref().received_responses.contains_key(&token) {
+                    let retry = self.inner_mut().schedule_retry(&token, &pdu);
+                    if let Some(attempt) = retry {
+                        // The hook is taken out of the session while it is called, so that it may
+                        // use the session.
+                        let Some(mut hook) = self.inner_mut().retry_hook.take() else {
+                            return;
+                        };
+                        (hook.0)(&attempt);
+                        self.inner_mut().retry_hook.get_or_insert(hook);
+                        return;
+                    }
+                    let inner = &mut *self.inner_mut();
+                    if let Some(observe) = pdu.observe() {
+                        let now = inner.context_shared.now();
+                        if inner
+                            .notification_orders
+                            .get_mut(&token)
+                            .is_some_and(|order| !order.check(observe, now))
+                        {
+                            // Stale notifications are dropped silently.
+                            return;
+                        }
+                    }
+                    inner.retryable_requests.remove(&token);
+                    if pdu.observe().is_some() {
+                        inner.reregistered_observations.remove(&token);
+                    } else if inner.observations.remove(&token).is_some()
+                        && inner.reregistered_observations.remove(&token)
+                    {
+                        inner
+                            .observation_events
+                            .entry(token.clone())
+                            .or_default()
+                            .push_back(CoapObservationEvent::Terminated(pdu.code()));
+                    }
+                    // The request has been answered, so its timeout no longer applies.
+                    inner.request_deadlines.remove(&token);
+                    inner.forget_unanswered_request(&token);
+                    if let Some((key, revalidating)) = inner.cache_requests.remove(&token) {
+                        let now = inner.context_shared.now();
+                        if let Some(cache) = inner.response_cache.as_mut() {
+                            pdu = cache.update(key, revalidating, pdu, now);
+                        }
+                    }
+                    inner.received_responses.get_mut(&token).unwrap().push_back(pdu);
+                    inner.account_memory();
                 }
             }
         }
+
+        /// Fails all requests whose timeout has elapsed without receiving a response, i.e., stops
+        /// waiting for their responses (see [CoapRequest::set_timeout()]).
+        fn expire_timed_out_requests(&self) {
+            let inner = &mut *self.inner_mut();
+            let now = inner.context_shared.now();
+            if inner.request_deadlines.values().all(|deadline| *deadline > now) {
+                return;
+            }
+            let expired: Vec<CoapToken> = inner
+                .request_deadlines
+                .iter()
+                .filter(|(_, deadline)| **deadline <= now)
+                .map(|(token, _)| token.clone())
+                .collect();
+            for token in expired {
+                inner.request_deadlines.remove(&token);
+                inner.received_responses.remove(&token);
+                inner.forget_unanswered_request(&token);
+                inner.failed_requests.insert(token, RequestPollError::TimedOut);
+            }
+            inner.account_memory();
+        }
+
+        /// Fails all requests that had not received a response yet when pending transmissions were
+        /// aborted (see [CoapContext::abort_pending()]).
+        fn fail_aborted_requests(&self) {
+            let inner = &mut *self.inner_mut();
+            let abort_count = inner.context_shared.abort_count.get();
+            let aborted: Vec<CoapToken> = inner
+                .unanswered_requests
+                .iter()
+                .filter(|(_, sent_abort_count)| **sent_abort_count != abort_count)
+                .map(|(token, _)| token.clone())
+                .collect();
+            for token in aborted {
+                // Aborted requests are no longer counted by the context, see
+                // CoapSessionInner::forget_unanswered_request().
+                inner.unanswered_requests.remove(&token);
+                inner.request_sent_at.remove(&token);
+                inner.request_deadlines.remove(&token);
+                inner.received_responses.remove(&token);
+                inner.failed_requests.insert(token, RequestPollError::Aborted);
+            }
+            inner.account_memory();
+        }
     }
 
     impl<'a, T: CoapSessionInnerProvider<'a>> CoapSessionCommonInternal<'a> for T {}
@@ -103,6 +370,12 @@ mod sealed {
 /// Trait for functions that are common between client and server sessions.
 pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
     /// Returns the application specific data stored alongside this session.
+    ///
+    /// The data is stored in the Rust representation of the session and is therefore independent
+    /// of the `app_data` pointer of the raw libcoap session (which libcoap-rs uses internally).
+    ///
+    /// Returns `Ok(None)` if no data is set, and [SessionGetAppDataError::WrongType] if the stored
+    /// data is not of type `T`.
     fn app_data<T: Any>(&self) -> Result<Option<Rc<T>>, SessionGetAppDataError> {
         self.inner_ref()
             .app_data
@@ -111,17 +384,64 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
             .transpose()
     }
 
-    /// Sets the application-specific data stored alongside this session.
+    /// Sets the application-specific data stored alongside this session, replacing (and
+    /// dropping) any previously stored value.
+    ///
+    /// The data is dropped once the session is freed, i.e., when the last reference to the session
+    /// is dropped or, for server-side sessions, when libcoap closes the session or the
+    /// [CoapContext] is dropped (unless references obtained using [CoapSessionCommon::app_data()]
+    /// are still held).
     fn set_app_data<T: 'static+Any>(&self, value: Option<T>) {
         let mut inner = self.inner_mut();
         let new_box: Option<Rc<dyn Any>> = value.map(|v| Rc::new(v) as Rc<dyn Any>);
         inner.app_data = new_box;
+        inner.account_memory();
     }
 
     /// Clears the application-specific data stored alongside this session.
     fn clear_app_data(&self) {
         let mut inner = self.inner_mut();
         inner.app_data = None;
+        inner.account_memory();
+    }
+
+    /// Returns the application-specific data stored alongside the context this session belongs
+    /// to (see [CoapContext::set_app_data()]).
+    ///
+    /// This allows request handlers to access state shared by the entire context.
+    ///
+    /// # Errors
+    /// Returns [ContextGetAppDataError::WrongType] if the stored data is not of type `T` and
+    /// [ContextGetAppDataError::Unavailable] if the context is currently in use, which is the case
+    /// while [CoapEventHandler](crate::CoapEventHandler) functions are called.
+    fn context_app_data<T: Any>(&self) -> Result<Option<Rc<T>>, ContextGetAppDataError> {
+        // SAFETY: The raw session is valid, and its context is valid for at least as long as the
+        // session.
+        let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(self.inner_ref().raw_session)) };
+        context.try_app_data()
+    }
+
+    /// Returns the stable identifier of this session.
+    ///
+    /// In contrast to the remote address, this identifier does not change if the peer migrates
+    /// to a different address (see [CoapSessionId]).
+    fn id(&self) -> CoapSessionId {
+        self.inner_ref().id
+    }
+
+    /// Creates a [SessionGuard] for this session, which keeps the session alive and may be sent to
+    /// other threads in order to continue working with the session there.
+    ///
+    /// This is usually called for the session passed to a request handler or an event handler
+    /// (see [CoapEventHandler](crate::CoapEventHandler)).
+    ///
+    /// This function is only available on Unix and Windows.
+    ///
+    /// # Errors
+    /// See [SessionGuard::new()].
+    #[cfg(any(unix, windows))]
+    fn guard(&self) -> Result<SessionGuard, ContextHandleError> {
+        SessionGuard::new(self)
     }
 
     /// Returns the Ack-Random-Factor used by libcoap.
@@ -172,7 +492,85 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         };
     }
 
+    /// Sets the initial retransmission timeout of confirmable messages sent using this session to
+    /// a fixed value (if supported, see
+    /// [CoapTransmissionCapabilities::initial_rto_supported()]).
+    ///
+    /// libcoap chooses the initial timeout randomly between the Acknowledgement Timeout and the
+    /// Acknowledgement Timeout multiplied by the Ack-Random-Factor, so this sets the
+    /// Acknowledgement Timeout to `rto` (rounded down to whole milliseconds and capped at
+    /// [u16::MAX] seconds) and the Ack-Random-Factor to 1. The timeout is still doubled after each
+    /// retransmission.
+    fn set_initial_rto(&self, rto: Duration) {
+        let rto = duration_fixed_point(rto);
+        self.set_ack_timeout(rto.integer_part, rto.fractional_part);
+        self.set_ack_random_factor(1, 0);
+    }
+
+    /// Returns the retransmission state of this session, including an estimate of the round-trip
+    /// time to the peer.
+    ///
+    /// libcoap does not provide access to its retransmission timeouts (see
+    /// [CoapTransmissionCapabilities::rto_state_supported()]), so the state is derived from the
+    /// messages tracked by the wrapper (see [CoapSessionCommon::queued_pdus()]). Round-trip times
+    /// are only sampled for confirmable messages on unreliable transports that are acknowledged
+    /// without having been retransmitted.
+    fn transmission_state(&self) -> CoapTransmissionState {
+        prune_send_queue(self);
+        let inner = self.inner_ref();
+        CoapTransmissionState {
+            rto_estimate: inner.rto_estimator.estimate(),
+            outstanding: inner.send_queue.len(),
+            backoff: inner.send_queue.max_retransmissions(),
+        }
+    }
+
+    /// Returns the DEFAULT_LEISURE used by libcoap for this session, i.e., the time span over which
+    /// responses to multicast requests are spread in order to avoid congestion (see
+    /// [RFC 7252, Section 8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-8.2)).
+    fn default_leisure(&self) -> Duration {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        let leisure = unsafe { coap_session_get_default_leisure(self.inner_ref().raw_session) };
+        fixed_point_duration((leisure.integer_part, leisure.fractional_part))
+    }
+
+    /// Sets the DEFAULT_LEISURE used by libcoap for this session.
+    ///
+    /// libcoap delays responses to multicast requests received on a server-side session by a
+    /// random time span between zero and this value (unless the resource sets
+    /// [ResourceFlags::LIB_DIS_MCAST_DELAYS](crate::ResourceFlags::LIB_DIS_MCAST_DELAYS)).
+    /// The value is rounded down to whole milliseconds and capped at [u16::MAX] seconds.
+    fn set_default_leisure(&self, leisure: Duration) {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        unsafe { coap_session_set_default_leisure(self.inner_ref().raw_session, duration_fixed_point(leisure)) };
+    }
+
+    /// Returns the PROBING_RATE used by libcoap for this session (in bytes per second), i.e., the
+    /// average data rate that is not exceeded when sending to a peer that does not respond (see
+    /// [RFC 7252, Section 4.7](https://datatracker.ietf.org/doc/html/rfc7252#section-4.7)).
+    fn probing_rate(&self) -> u32 {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        unsafe { coap_session_get_probing_rate(self.inner_ref().raw_session) }
+    }
+
+    /// Sets the PROBING_RATE used by libcoap for this session (in bytes per second).
+    fn set_probing_rate(&self, rate: u32) {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        unsafe { coap_session_set_probing_rate(self.inner_ref().raw_session, rate) };
+    }
+
     /// Returns the local address for this session.
+    ///
+    /// For client sessions, this contains the port that was chosen by the operating system if no
+    /// local address was provided (or its port was 0). For server sessions, this is the address of
+    /// the endpoint the session belongs to.
+    ///
+    /// IPv6 addresses include the flow information and scope ID of the underlying socket address.
+    ///
+    /// # Panics
+    /// Panics if the address can not be represented as a [SocketAddr], i.e., for Unix domain socket
+    /// sessions. Use [CoapSessionCommon::local_address()] for sessions that may use Unix domain
+    /// sockets.
     fn addr_local(&self) -> SocketAddr {
         CoapAddress::from(unsafe {
             // This is infallible as long as the raw session is valid (which it always should be
@@ -187,7 +585,14 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         .unwrap()
     }
 
-    /// Returns the remote address for this session.
+    /// Returns the remote address for this session, i.e., the address of the peer.
+    ///
+    /// IPv6 addresses include the flow information and scope ID of the underlying socket address.
+    ///
+    /// # Panics
+    /// Panics if the address can not be represented as a [SocketAddr], i.e., for Unix domain socket
+    /// sessions. Use [CoapSessionCommon::remote_address()] for sessions that may use Unix domain
+    /// sockets.
     fn addr_remote(&self) -> SocketAddr {
         CoapAddress::from(unsafe {
             // This is infallible as long as the raw session is valid (which it always should be
@@ -202,7 +607,32 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         .unwrap()
     }
 
-    /// Returns the interface index for this session.
+    /// Returns the local address for this session (see [CoapSessionCommon::addr_local()]), which
+    /// may also be the address of a Unix domain socket.
+    fn local_address(&self) -> CoapAddress {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, in
+        // which case libcoap always returns a valid address.
+        CoapAddress::from(unsafe {
+            coap_session_get_addr_local(self.inner_ref().raw_session)
+                .as_ref()
+                .unwrap()
+        })
+    }
+
+    /// Returns the remote address for this session (see [CoapSessionCommon::addr_remote()]),
+    /// which may also be the address of a Unix domain socket.
+    fn remote_address(&self) -> CoapAddress {
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, in
+        // which case libcoap always returns a valid address.
+        CoapAddress::from(unsafe {
+            coap_session_get_addr_remote(self.inner_ref().raw_session)
+                .as_ref()
+                .unwrap()
+        })
+    }
+
+    /// Returns the index of the network interface this session communicates over, as determined by
+    /// libcoap.
     fn if_index(&self) -> IfIndex {
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
         unsafe { coap_session_get_ifindex(self.inner_ref().raw_session) }
@@ -220,6 +650,27 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         unsafe { coap_session_set_max_retransmit(self.inner_ref().raw_session, value) }
     }
 
+    /// Returns the EXCHANGE_LIFETIME derived from the transmission parameters of this session (see
+    /// [RFC 7252, Section 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2)).
+    ///
+    /// This is the time span in which retransmissions of a confirmable message may be received,
+    /// and therefore the time span for which message IDs of received confirmable requests are
+    /// tracked in order to detect duplicates (see [CoapRequest::is_retransmission()]).
+    fn exchange_lifetime(&self) -> Duration {
+        max_transmit_span(self)
+            .saturating_add(MAX_LATENCY * 2)
+            .saturating_add(fixed_point_duration(self.ack_timeout()))
+    }
+
+    /// Returns the NON_LIFETIME derived from the transmission parameters of this session (see
+    /// [RFC 7252, Section 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2)).
+    ///
+    /// This is the time span for which message IDs of received non-confirmable requests are tracked
+    /// in order to detect duplicates (see [CoapRequest::is_retransmission()]).
+    fn non_lifetime(&self) -> Duration {
+        max_transmit_span(self).saturating_add(MAX_LATENCY)
+    }
+
     /// Returns the underlying transport protocol used for this session.
     fn proto(&self) -> CoapProtocol {
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
@@ -259,6 +710,59 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         }
     }
 
+    /// Returns the security parameters of this session, or None if the session is neither
+    /// encrypted using (D)TLS nor protected using OSCORE (e.g., for plain UDP sessions).
+    ///
+    /// Some of the parameters (e.g., the PSK identity or the certificate of the peer) are only
+    /// known once the session is established (see [CoapSessionCommon::state()]).
+    fn crypto_info(&self) -> Option<CoapCryptoSessionInfo> {
+        let proto = self.proto();
+        let inner = self.inner_ref();
+        if !proto.is_secure() && !inner.oscore {
+            return None;
+        }
+        let mut raw_library = coap_tls_library_t::COAP_TLS_LIBRARY_NOTLS;
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+        let raw_tls_session = unsafe { coap_session_get_tls(inner.raw_session, &mut raw_library) };
+        let backend = tls_backend();
+        let tls_library = if raw_tls_session.is_null() {
+            backend.library()
+        } else {
+            TlsLibrary::from(raw_library)
+        };
+        Some(CoapCryptoSessionInfo {
+            proto,
+            tls_library,
+            tls_library_version: backend.version(),
+            raw_tls_session,
+            #[cfg(feature = "dtls-psk")]
+            psk_identity: self.psk_identity(),
+            #[cfg(feature = "dtls-psk")]
+            psk_hint: self.psk_hint(),
+            #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+            peer_certificate: inner.peer_certificate.clone(),
+            oscore: inner.oscore,
+        })
+    }
+
+    /// Returns the error that caused the (D)TLS handshake of this session to fail most recently,
+    /// or [SessionCreationError::HandshakeTimeout] if the session was not established before its
+    /// handshake deadline (see [CoapContext::set_handshake_deadline()]).
+    ///
+    /// For sessions that are reconnected by libcoap (see [CoapContext::set_reconnect_policy()]),
+    /// this also includes failures of reconnection attempts. The error is kept after a later
+    /// handshake succeeds.
+    fn last_error(&self) -> Option<SessionCreationError> {
+        let inner = self.inner_ref();
+        if inner.handshake_timed_out {
+            return Some(SessionCreationError::HandshakeTimeout);
+        }
+        inner
+            .handshake_failure
+            .clone()
+            .map(|(alert, backend_message)| SessionCreationError::HandshakeFailed { alert, backend_message })
+    }
+
     /// Returns the current state of this session.
     #[must_use = "getting the current session state without using it is a no-op"]
     fn state(&self) -> CoapSessionState {
@@ -268,8 +772,10 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
 
     /// Initializes the initial token value used by libcoap for this session.
     ///
-    /// This value will be used as the token and incremented for each message sent through this
-    /// session that does not already have a token set.
+    /// This value will be used as the token and incremented for each message that libcoap sends
+    /// through this session on its own (e.g., the subsequent blocks of block-wise transfers).
+    /// Tokens of requests sent using [CoapSessionCommon::send_request()] are generated by the
+    /// token generator of the context instead (see [CoapContext::set_token_generator()]).
     fn init_token(&self, token: &[u8; 8]) {
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
         unsafe { coap_session_init_token(self.inner_mut().raw_session, token.len(), token.as_ptr()) }
@@ -281,84 +787,631 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         unsafe { coap_session_max_pdu_size(self.inner_ref().raw_session) }
     }
 
+    /// Returns the maximum message size the peer announced in its Capabilities and Settings
+    /// Message (CSM), i.e., the maximum size of a PDU that may be sent to it.
+    ///
+    /// The returned value excludes the message header (as in [CoapSessionCommon::max_pdu_size()])
+    /// and is already limited by the MTU of this session.
+    ///
+    /// CSMs are only exchanged for reliable transports (TCP/TLS) as specified in
+    /// [RFC 8323, Section 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3), so None
+    /// is returned for unreliable sessions and for sessions that are not yet established (i.e.,
+    /// whose peer has not sent its CSM yet).
+    ///
+    /// Requests with larger payloads are sent using block-wise transfers automatically, while
+    /// [CoapSessionCommon::send()] refuses to send other messages that exceed this size.
+    fn peer_max_message_size(&self) -> Option<usize> {
+        (self.proto().is_reliable() && self.state() == CoapSessionState::Established).then(|| self.max_pdu_size())
+    }
+
     /// Sets the maximum size of a PDU for this session.
     fn set_mtu(&self, mtu: u32) {
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
         unsafe { coap_session_set_mtu(self.inner_mut().raw_session, mtu) }
     }
 
+    /// Sets the policy for handling ICMP errors of this session (see [IcmpErrorPolicy]).
+    ///
+    /// ICMP errors are only reported for sessions whose socket is connected to the peer, i.e.,
+    /// for client sessions using unreliable transports (UDP/DTLS).
+    fn set_icmp_error_policy(&self, policy: IcmpErrorPolicy) {
+        self.inner_mut().icmp_error_policy = policy;
+    }
+
+    /// Returns the policy for handling ICMP errors of this session (see
+    /// [CoapSessionCommon::set_icmp_error_policy()]).
+    fn icmp_error_policy(&self) -> IcmpErrorPolicy {
+        self.inner_ref().icmp_error_policy
+    }
+
     /// Returns the next message ID that should be used for this session.
     fn next_message_id(&self) -> CoapMessageId {
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
         unsafe { coap_new_message_id(self.inner_mut().raw_session) as CoapMessageId }
     }
 
-    /// Returns the next token that should be used for requests.
-    fn new_token(&mut self, token: &mut [u8; 8]) -> usize {
-        let mut length = 8;
-        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+    /// Generates a new token for a request on this session.
+    ///
+    /// Tokens are generated using the token generator of the context (see
+    /// [CoapContext::set_token_generator()]) and are not used by any outstanding request of this
+    /// session. If the generator fails to generate such a token, a token generated by libcoap
+    /// (which increments the initial token of the session, see [CoapSessionCommon::init_token()])
+    /// of at most [DEFAULT_MAX_TOKEN_SIZE] bytes is returned instead.
+    /// Generated tokens can be used to set the token of a request explicitly (e.g., in order to
+    /// correlate it with external state before sending it), requests without a token are
+    /// assigned a token by [CoapSessionCommon::send_request()] in the same way.
+    fn new_token(&self) -> CoapToken {
+        if let Some(token) = generate_token(self) {
+            return token;
+        }
+        let mut token = [0; DEFAULT_MAX_TOKEN_SIZE];
+        let mut length = token.len();
+        // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner,
+        // provided buffer is large enough for the default maximum token length.
         unsafe { coap_session_new_token(self.inner_mut().raw_session, &mut length, token.as_mut_ptr()) }
-        length
+        Box::from(&token[..length])
     }
 
     /// Send a ping message to the remote peer.
-    fn send_ping(&mut self) -> CoapMessageId {
+    ///
+    /// Returns the message ID of the sent ping (pings are empty messages and therefore do not have
+    /// a token).
+    /// The corresponding pong (or the lack thereof) is reported to the
+    /// [CoapEventHandler](crate::CoapEventHandler) of the context this session belongs to using
+    /// [handle_pong()](crate::CoapEventHandler::handle_pong) and
+    /// [handle_ping_timeout()](crate::CoapEventHandler::handle_ping_timeout).
+    ///
+    /// # Errors
+    /// Returns [PingError::SessionNotEstablished] if the session is not in the
+    /// [CoapSessionState::Established] state, or [PingError::Unknown] if libcoap was unable to
+    /// send the ping.
+    fn send_ping(&mut self) -> Result<CoapMessageId, PingError> {
+        if self.state() != CoapSessionState::Established {
+            return Err(PingError::SessionNotEstablished);
+        }
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
-        unsafe { coap_session_send_ping(self.inner_mut().raw_session) }
+        match unsafe { coap_session_send_ping(self.inner_mut().raw_session) } {
+            COAP_INVALID_MID => Err(PingError::Unknown),
+            mid => {
+                record_stats(self, |stats| stats.record_sent(0));
+                Ok(mid)
+            },
+        }
     }
 
     /// Send the given message-like object to the peer.
     ///
     /// # Errors
     /// Returns a [MessageConversionError] if the supplied object cannot be converted to a message.
+    /// If the message has an extended token ([RFC 8974](https://datatracker.ietf.org/doc/html/rfc8974))
+    /// that exceeds the maximum token size of this session (e.g., because the peer did not signal
+    /// support for extended tokens in its CSM), [MessageConversionError::TokenTooLong] is returned
+    /// and the message is not sent. Tokens are never truncated.
+    /// If the session has been disconnected using [CoapServerSession::disconnect()],
+    /// [MessageConversionError::SessionDisconnected] is returned.
+    ///
+    /// On reliable sessions, messages that are larger than the maximum message size announced by
+    /// the peer (see [CoapSessionCommon::peer_max_message_size()]) are not sent and
+    /// [MessageConversionError::MessageTooLarge] is returned. Request payloads are split into
+    /// blocks automatically, so this only affects other messages (e.g., responses with large
+    /// payloads).
+    ///
+    /// If the socket rejects the message because it exceeds the (path) MTU,
+    /// [MessageConversionError::PacketTooBig] is returned. Requests sent using
+    /// [CoapSessionCommon::send_request()] are sent again using smaller blocks instead (see
+    /// [IcmpErrorPolicy::reduce_mtu]).
+    ///
+    /// If a response to the request whose handler is currently called might not fit into a
+    /// single PDU, its payload is handed to libcoap without copying it (see
+    /// [CoapMessageCommon::set_data_shared()]) and sent using block-wise transfer
+    /// ([RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959)) once the handler returns.
+    /// In that case, the Content-Format, Max-Age and ETag options are added by libcoap (using
+    /// `text/plain` if the response has no Content-Format and encoding the ETag as an integer),
+    /// only one such response can be sent per request, and the response is not passed to the PDU
+    /// inspector (see [CoapContext::set_pdu_inspector()]).
+    ///
+    /// Requests and responses whose payload might not fit into a single PDU are given a Size1 or
+    /// Size2 option (respectively) indicating the length of the payload, unless they already
+    /// contain one.
     fn send<P: Into<CoapMessage>>(&self, pdu: P) -> Result<CoapMessageId, MessageConversionError> {
-        let raw_pdu = pdu.into().into_raw_pdu(self)?;
+        if self.inner_ref().close_reason.is_some() {
+            return Err(MessageConversionError::SessionDisconnected);
+        }
+        let mut message = pdu.into();
+        if let CoapMessageCode::Response(code) = message.code() {
+            if self.inner_ref().suppressed_responses.suppresses(code) {
+                // The client indicated that it is not interested in this response (RFC 7967).
+                return Ok(COAP_INVALID_MID);
+            }
+        }
+        if let (CoapMessageCode::Response(code), Some(stats)) = (message.code(), &self.inner_ref().response_stats) {
+            stats
+                .borrow_mut()
+                .record_response(code, message.data().map_or(0, |v| v.len()));
+        }
+        let token_len = message.token().map_or(0, |v| v.len());
+        let payload_len = message.data().map_or(0, |v| v.len());
+        let handled_request = self.inner_ref().handled_request.filter(|handled| {
+            // SAFETY: The request PDU is valid while its handler is called.
+            let raw_token = unsafe { coap_pdu_get_token(handled.raw_request) };
+            let request_token = unsafe { std::slice::from_raw_parts(raw_token.s, raw_token.length) };
+            matches!(message.code(), CoapMessageCode::Response(_)) && message.token() == Some(request_token)
+        });
+        if let Some(handled) = handled_request {
+            // SAFETY: The request PDU is valid while its handler is called.
+            let (request_type, request_mid) = unsafe {
+                (
+                    CoapMessageType::from(coap_pdu_get_type(handled.raw_request)),
+                    coap_pdu_get_mid(handled.raw_request),
+                )
+            };
+            match message.type_() {
+                // Piggybacked responses are only possible for confirmable requests (RFC 7252,
+                // Section 5.2.1).
+                CoapMessageType::Ack if request_type != CoapMessageType::Con => {
+                    return Err(MessageConversionError::InvalidResponseType(CoapMessageType::Ack));
+                },
+                CoapMessageType::Rst => return Err(MessageConversionError::InvalidResponseType(CoapMessageType::Rst)),
+                // Separate responses (RFC 7252, Section 5.2.2) require a message ID of their own,
+                // libcoap acknowledges the request with an empty ACK once the handler returns.
+                CoapMessageType::Con | CoapMessageType::Non if message.mid() == Some(request_mid) => {
+                    message.set_mid(Some(self.next_message_id()));
+                },
+                _ => {},
+            }
+        }
+        let may_be_blockwise = message.may_exceed_pdu_size(self.max_pdu_size());
+        // Indicate the size of bodies sent using block-wise transfer (RFC 7959, Section 4), so
+        // that servers can reject oversized uploads early and clients can preallocate memory for
+        // large responses. This does not rely on libcoap adding these options, as libcoap
+        // versions differ in whether they do.
+        if may_be_blockwise {
+            let size = Size::try_from(payload_len).unwrap_or(Size::MAX);
+            let size_option = match message.code() {
+                CoapMessageCode::Request(_) => Some(CoapOption::Size1(size)),
+                CoapMessageCode::Response(_) if handled_request.is_some() => Some(CoapOption::Size2(size)),
+                _ => None,
+            };
+            if let Some(size_option) = size_option {
+                let present = message
+                    .options_iter()
+                    .any(|option| std::mem::discriminant(option) == std::mem::discriminant(&size_option));
+                if !present {
+                    message.add_option(size_option);
+                }
+            }
+        }
+        let large_response = handled_request.filter(|_| may_be_blockwise);
+        if let Some(handled) = large_response {
+            // libcoap's response PDU can only be used for a single response.
+            self.inner_mut().handled_request = None;
+            let raw_session = self.inner_ref().raw_session;
+            // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, the
+            // handler for the request is currently called.
+            let mid = unsafe {
+                match message.type_() {
+                    CoapMessageType::Ack => message.apply_to_large_response(raw_session, &handled)?,
+                    _ => message.send_as_large_separate_response(raw_session, &handled)?,
+                }
+            };
+            record_stats(self, |stats| stats.record_sent(payload_len));
+            self.inner_ref().context_shared.pending_aborted.set(false);
+            return Ok(mid);
+        }
+        // Confirmable messages on unreliable transports are kept by libcoap until they are
+        // acknowledged (or delayed due to NSTART), so they count towards the send queue.
+        let queued_token = (message.type_() == CoapMessageType::Con && !self.proto().is_reliable())
+            .then(|| message.token().map(Box::<[u8]>::from).unwrap_or_default());
+        let raw_pdu = message.into_raw_pdu(self)?;
+        // SAFETY: raw pdu should be valid as we got it from `into_raw_pdu()`.
+        let size = unsafe { CoapPduView::from_raw(raw_pdu, self.proto()).used_size() };
+        if let Some(max_size) = self.peer_max_message_size() {
+            // SAFETY: raw pdu should be valid as we got it from `into_raw_pdu()`, and it is not
+            // referenced by libcoap if it is deleted here.
+            unsafe {
+                if size > max_size {
+                    coap_delete_pdu(raw_pdu);
+                    return Err(MessageConversionError::MessageTooLarge(size, max_size));
+                }
+            }
+        }
         // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner,
         // raw pdu should be valid as we got it from `into_raw_pdu()`.
-        let mid = unsafe { coap_send(self.inner_mut().raw_session, raw_pdu) };
+        let (mid, error_cleared) = unsafe {
+            inspect_pdu(self, CoapPduDirection::Sent, raw_pdu);
+            // Only errors reported by the socket while sending are relevant, not stale ones.
+            let error_cleared = clear_last_error();
+            (coap_send(self.inner_mut().raw_session, raw_pdu), error_cleared)
+        };
+        if mid == COAP_INVALID_MID && token_len > DEFAULT_MAX_TOKEN_SIZE {
+            // libcoap drops messages whose token is longer than the maximum token size of the
+            // session (i.e., if the peer did not indicate support for extended token lengths).
+            return Err(MessageConversionError::TokenTooLong(token_len));
+        }
+        if mid == COAP_INVALID_MID && error_cleared && last_error_is_packet_too_big() {
+            return Err(MessageConversionError::PacketTooBig(size));
+        }
+        if mid != COAP_INVALID_MID {
+            record_stats(self, |stats| stats.record_sent(payload_len));
+            self.inner_ref().context_shared.pending_aborted.set(false);
+            if let Some(token) = queued_token {
+                let max_transmit_wait = max_transmit_wait(self);
+                let inner = &mut *self.inner_mut();
+                let shared = &inner.context_shared;
+                let now = shared.now();
+                let (epoch, limits) = (shared.send_queue_epoch.get(), shared.send_queue_limits.get());
+                inner
+                    .send_queue
+                    .push(mid, &token, size, now, now.checked_add(max_transmit_wait), epoch);
+                if inner.send_queue.update_congestion(limits) {
+                    let mut congested = shared.congested_sessions.borrow_mut();
+                    congested.insert(inner.id, inner.raw_session);
+                }
+            }
+        }
         Ok(mid)
     }
 
+    /// Returns the number of messages sent using this session that libcoap may still have to
+    /// (re-)transmit.
+    ///
+    /// libcoap does not provide access to its retransmission queue, so the wrapper tracks
+    /// confirmable messages sent on unreliable transports (UDP, DTLS) itself, including
+    /// notifications and messages delayed by libcoap due to NSTART (see
+    /// [RFC 7252, Section 4.7](https://datatracker.ietf.org/doc/html/rfc7252#section-4.7)).
+    /// Messages are considered dequeued once a response to them is received, libcoap reports that
+    /// they were rejected or could not be delivered, no transmissions of the context are
+    /// outstanding, or their last retransmission would have timed out (MAX_TRANSMIT_WAIT).
+    /// As libcoap does not report empty acknowledgements (e.g., for confirmable notifications),
+    /// acknowledged messages may be counted for longer than they are actually queued.
+    ///
+    /// Non-confirmable messages and messages on reliable transports are handed to the operating
+    /// system right away and are not counted.
+    fn queued_pdus(&self) -> usize {
+        prune_send_queue(self);
+        self.inner_ref().send_queue.len()
+    }
+
+    /// Returns the total size (in bytes) of the messages counted by
+    /// [CoapSessionCommon::queued_pdus()].
+    fn queued_bytes(&self) -> usize {
+        prune_send_queue(self);
+        self.inner_ref().send_queue.bytes()
+    }
+
+    /// Returns whether this session has reached the high-water mark of its send queue and has not
+    /// drained to the low-water mark since (see
+    /// [CoapContext::set_send_queue_limits()](crate::CoapContext::set_send_queue_limits)).
+    fn is_send_queue_congested(&self) -> bool {
+        prune_send_queue(self);
+        self.inner_ref().send_queue.is_congested()
+    }
+
+    /// Limits the number of requests sent using [CoapSessionCommon::send_request()] that await
+    /// their first response at the same time, or removes the limit if `limit` is None (the
+    /// default).
+    ///
+    /// Requests that would exceed the limit are queued and sent in order once other requests have
+    /// received their first response, have failed or have had their handles removed (see
+    /// [OutstandingRequestLimit]). Queued requests are sent while a response is processed or when
+    /// the handle of a request is polled or removed, and their handles can be used like the ones of
+    /// requests that have been sent right away.
+    /// Queued requests fail with [RequestPollError::SessionClosed] if the connection of the session
+    /// is closed (and not reconnected by libcoap) before they are sent, and with
+    /// [RequestPollError::SendFailed] if they can not be sent once a slot is available.
+    ///
+    /// Raising or removing the limit sends queued requests right away.
+    fn set_max_outstanding_requests(&self, limit: Option<OutstandingRequestLimit>) {
+        self.inner_mut().outstanding_limit = limit;
+        send_queued_requests(self);
+    }
+
+    /// Returns the limit for the number of outstanding requests of this session, see
+    /// [CoapSessionCommon::set_max_outstanding_requests()].
+    fn max_outstanding_requests(&self) -> Option<OutstandingRequestLimit> {
+        self.inner_ref().outstanding_limit
+    }
+
+    /// Returns the number of requests sent using this session that await their first response (see
+    /// [CoapSessionCommon::set_max_outstanding_requests()]).
+    fn outstanding_requests(&self) -> usize {
+        self.inner_ref().unanswered_requests.len()
+    }
+
+    /// Returns the number of requests that are queued because this session has the maximum number
+    /// of outstanding requests (see [CoapSessionCommon::set_max_outstanding_requests()]).
+    fn queued_requests(&self) -> usize {
+        let inner = &mut *self.inner_mut();
+        inner.prune_queued_requests();
+        inner.queued_requests.len()
+    }
+
     /// Sends the given CoapRequest, returning a CoapRequestHandle that can be used to poll the
     /// request for completion.
     ///
+    /// If the request suppresses all responses using the No-Response option, no response is
+    /// awaited (see [CoapRequestHandle::expects_response()]).
+    ///
+    /// If the request already has a token (e.g., one set using
+    /// [CoapRequestBuilder::token()](crate::message::CoapRequestBuilder::token)), it is used as-is,
+    /// otherwise a new token is generated using the token generator of the context (see
+    /// [CoapContext::set_token_generator()]).
+    /// Responses are matched to the request using the token that was sent.
+    ///
+    /// Requests built without an explicit message type are sent using the default message type
+    /// of this session (see [CoapSessionCommon::set_default_message_type()]).
+    ///
+    /// If the response cache of a client session is enabled (see
+    /// [CoapClientSession::set_response_cache_capacity()]), GET requests may be answered from the
+    /// cache without contacting the server, in which case the response is available immediately.
+    ///
+    /// If the session already has the maximum number of outstanding requests (see
+    /// [CoapSessionCommon::set_max_outstanding_requests()]), the request is queued and sent later.
+    ///
     /// # Errors
     /// Returns a [MessageConversionError] if the given Request could not be converted into a raw
     /// message.
+    /// Returns [MessageConversionError::TokenInUse] if the request has a token that is already
+    /// used by another request on this session that is still awaiting responses (i.e., whose
+    /// handle has not been removed using [CoapSessionCommon::remove_handle()]).
+    /// Returns [MessageConversionError::TokenGenerationFailed] if the request has no token and the
+    /// token generator of the context did not generate an unused one.
+    /// Returns [MessageConversionError::SessionFailed] if the session was not established before
+    /// its handshake deadline (see [CoapContext::set_handshake_deadline()]).
+    /// Returns [MessageConversionError::SessionDisconnected] if the session has been disconnected
+    /// using [CoapServerSession::disconnect()].
+    /// Returns [MessageConversionError::TooManyPendingRequests] if this session already has the
+    /// maximum number of pending requests (see [CoapContext::set_max_pending_requests()]).
+    /// Returns [MessageConversionError::MemoryLimitExceeded] if the memory used by pending
+    /// requests exceeds its limit (see [CoapContext::set_memory_limits()]).
+    /// Returns [MessageConversionError::SendQueueFull] if the send queue of this session is
+    /// congested (see [CoapSessionCommon::is_send_queue_congested()]).
+    /// Returns [MessageConversionError::RequestQueueFull] if the request would have to be queued
+    /// because of the outstanding request limit, but the queue is full (see
+    /// [OutstandingRequestLimit::max_queued]).
+    /// Returns [MessageConversionError::PacketTooBig] if the request is too large for the path to
+    /// the peer even after reducing the MTU of this session (see [IcmpErrorPolicy::reduce_mtu]).
     fn send_request(&self, mut req: CoapRequest) -> Result<CoapRequestHandle, MessageConversionError> {
-        if req.token().is_none() {
-            let mut token_len = libcoap_sys::COAP_TOKEN_DEFAULT_MAX as usize;
-            let mut token_tmp: Vec<u8> = vec![0; token_len];
-            // SAFETY: Provided pointer is valid, length matches.
-            unsafe {
-                coap_session_new_token(self.inner_mut().raw_session, &mut token_len, token_tmp.as_mut_ptr());
+        if self.inner_ref().handshake_timed_out {
+            return Err(MessageConversionError::SessionFailed);
+        }
+        if self.inner_ref().close_reason.is_some() {
+            return Err(MessageConversionError::SessionDisconnected);
+        }
+        if let Some(limit) = self.inner_ref().context_shared.max_pending_requests.get() {
+            if self.pending_requests() >= limit {
+                return Err(MessageConversionError::TooManyPendingRequests(limit));
             }
-            req.set_token(Some(Vec::from(&token_tmp[0..token_len])))
         }
-        let token: Box<[u8]> = Box::from(req.token().unwrap());
-        if req.mid().is_none() {
-            req.set_mid(Some(self.next_message_id()))
+        if let Err(limit) = self.inner_ref().context_shared.memory.check_request() {
+            return Err(MessageConversionError::MemoryLimitExceeded(limit));
         }
-        self.inner_mut()
-            .received_responses
-            .insert(token.clone(), VecDeque::new());
-        self.send(req.into_message()).map(|v| CoapRequestHandle::new(v, token))
+        if self.is_send_queue_congested() {
+            let queued_bytes = self.inner_ref().send_queue.bytes();
+            return Err(MessageConversionError::SendQueueFull(queued_bytes));
+        }
+        let token: CoapToken = match req.token() {
+            Some(token)
+                if self.inner_ref().received_responses.contains_key(token)
+                    || self.inner_ref().failed_requests.contains_key(token) =>
+            {
+                return Err(MessageConversionError::TokenInUse);
+            },
+            Some(token) => Box::from(token),
+            None => {
+                let token = generate_token(self).ok_or(MessageConversionError::TokenGenerationFailed)?;
+                req.set_token(Some(token.clone()));
+                token
+            },
+        };
+        let cache_key = self
+            .inner_ref()
+            .response_cache
+            .as_ref()
+            .and_then(|_| ResponseCacheKey::for_request(&req));
+        let mut revalidating = false;
+        if let Some(key) = &cache_key {
+            let lookup = {
+                let inner = &mut *self.inner_mut();
+                let now = inner.context_shared.now();
+                inner.response_cache.as_mut().map(|cache| cache.lookup(key, now))
+            };
+            match lookup {
+                Some(ResponseCacheLookup::Fresh(mut response)) => {
+                    // Fresh responses are provided without contacting the server.
+                    response.set_token(Some(token.clone()));
+                    let inner = &mut *self.inner_mut();
+                    inner
+                        .received_responses
+                        .insert(token.clone(), VecDeque::from([response]));
+                    inner.account_memory();
+                    let mid = req.mid().unwrap_or_else(|| self.next_message_id());
+                    return Ok(CoapRequestHandle::new(mid, token, true));
+                },
+                Some(ResponseCacheLookup::Stale(etag)) => {
+                    req.set_etag(Some(vec![etag]));
+                    revalidating = true;
+                },
+                Some(ResponseCacheLookup::Miss) | None => {},
+            }
+        }
+        // Responses to requests that suppress all responses will never arrive, so there is no
+        // need to wait for them (and they do not count as outstanding).
+        let expects_response = req.suppressed_responses() != CoapNoResponse::ALL;
+        let queue = expects_response && self.inner_mut().must_queue_request()?;
+        if self.inner_ref().reconnecting {
+            let inner = &mut *self.inner_mut();
+            let limit = inner
+                .context_shared
+                .reconnect_policy
+                .get()
+                .map_or(0, |policy| policy.max_queued_requests);
+            if inner.requests_while_reconnecting >= limit {
+                return Err(MessageConversionError::SessionReconnecting);
+            }
+            inner.requests_while_reconnecting += 1;
+        }
+        if req.uses_default_message_type() {
+            req.set_type_(self.default_message_type());
+        }
+        let mid = match req.mid() {
+            Some(mid) => mid,
+            None => {
+                let mid = self.next_message_id();
+                req.set_mid(Some(mid));
+                mid
+            },
+        };
+        if expects_response {
+            let inner = &mut *self.inner_mut();
+            inner.received_responses.insert(token.clone(), VecDeque::new());
+            // Registrations of observations are kept so they can be re-sent after reconnecting.
+            if req.observe() == Some(0) {
+                inner.observations.insert(token.clone(), req.clone());
+            }
+            // Requests are kept so they can be re-sent if the server is temporarily unavailable.
+            if let (Some(policy), CoapMessageCode::Request(code)) = (inner.retry_policy, req.code()) {
+                if policy.applies_to(code) {
+                    inner.retryable_requests.insert(token.clone(), (req.clone(), 0));
+                }
+            }
+        }
+        let timeout = req.timeout().filter(|_| expects_response);
+        let cache_key = cache_key.map(|key| (key, revalidating));
+        if queue {
+            let inner = &mut *self.inner_mut();
+            let includes_queueing = inner
+                .outstanding_limit
+                .is_some_and(|limit| limit.timeout_includes_queueing);
+            let timeout = match timeout {
+                Some(timeout) if includes_queueing => {
+                    inner.start_request_timeout(&token, timeout);
+                    None
+                },
+                timeout => timeout,
+            };
+            inner.queued_requests.push_back(QueuedRequest {
+                token: token.clone(),
+                request: req,
+                cache_key,
+                timeout,
+            });
+            inner.account_memory();
+            return Ok(CoapRequestHandle::new(mid, token, true));
+        }
+        let result = transmit_request(self, req, token, cache_key, timeout, expects_response);
+        self.inner_mut().account_memory();
+        result
     }
 
     /// Polls whether the request for the given handle already has pending responses.
     ///
     /// Returns an iterator over all responses associated with the request.
     ///
+    /// Returns an empty iterator for requests that have timed out (see
+    /// [CoapSessionCommon::try_poll_handle()] to distinguish these from requests that are still
+    /// awaiting responses).
+    ///
     /// # Panics
     ///
     /// Panics if the provided handle does not refer to a valid token, i.e., because it belongs to
     /// a different session.
     fn poll_handle(&self, handle: &CoapRequestHandle) -> std::collections::vec_deque::IntoIter<CoapResponse> {
+        self.try_poll_handle(handle)
+            .unwrap_or_else(|_| VecDeque::new().into_iter())
+    }
+
+    /// Returns an iterator over the events of the observation with the given handle that occurred
+    /// since this function was last called for it (see [CoapObservationEvent]).
+    ///
+    /// Returns an empty iterator for handles of requests that are not observations.
+    fn poll_observation_events(
+        &self,
+        handle: &CoapRequestHandle,
+    ) -> std::collections::vec_deque::IntoIter<CoapObservationEvent> {
         self.inner_mut()
+            .observation_events
+            .remove(&handle.token)
+            .unwrap_or_default()
+            .into_iter()
+    }
+
+    /// Enables or disables ordered delivery of the notifications of the observation with the
+    /// given handle.
+    ///
+    /// By default, notifications (i.e., responses with an Observe option, whose value is returned
+    /// by [CoapResponse::observe()]) are returned by [CoapSessionCommon::poll_handle()] in the
+    /// order they were received. If ordered delivery is enabled, notifications that are not
+    /// fresher than the freshest notification received so far (as determined by
+    /// [is_fresher_notification()]) are dropped instead, i.e., reordered notifications never
+    /// replace newer state. Responses without an Observe option are always delivered.
+    ///
+    /// Disabling ordered delivery resets the number of stale notifications (see
+    /// [CoapSessionCommon::stale_notifications()]).
+    fn set_ordered_notifications(&self, handle: &CoapRequestHandle, ordered: bool) {
+        let inner = &mut *self.inner_mut();
+        if ordered {
+            inner.notification_orders.entry(handle.token.clone()).or_default();
+        } else {
+            inner.notification_orders.remove(&handle.token);
+        }
+        inner.account_memory();
+    }
+
+    /// Returns the number of notifications of the observation with the given handle that were
+    /// dropped because they were stale (see [CoapSessionCommon::set_ordered_notifications()]).
+    fn stale_notifications(&self, handle: &CoapRequestHandle) -> u64 {
+        self.inner_ref()
+            .notification_orders
+            .get(&handle.token)
+            .map_or(0, CoapNotificationOrder::stale_count)
+    }
+
+    /// Polls whether the request for the given handle already has pending responses, failing if
+    /// the request has timed out.
+    ///
+    /// Returns an iterator over all responses associated with the request.
+    ///
+    /// # Errors
+    /// Returns [RequestPollError::TimedOut] if no response was received before the timeout of the
+    /// request elapsed (see [CoapRequest::set_timeout()]), [RequestPollError::Reset] if the peer
+    /// rejected the request with a Reset message (which libcoap only reports for confirmable
+    /// requests), [RequestPollError::SessionFailed] if the session was not established before its
+    /// handshake deadline (see [CoapContext::set_handshake_deadline()]),
+    /// [RequestPollError::SessionDisconnected] if the session was disconnected using
+    /// [CoapServerSession::disconnect()], [RequestPollError::Aborted] if the request was aborted
+    /// using [CoapContext::abort_pending()], [RequestPollError::IcmpUnreachable] if the peer was
+    /// reported to be unreachable (see [CoapSessionCommon::set_icmp_error_policy()]), or
+    /// [RequestPollError::SessionClosed] or [RequestPollError::SendFailed] if the request was
+    /// queued and could not be sent (see [CoapSessionCommon::set_max_outstanding_requests()]).
+    /// These errors are returned until the handle is removed using
+    /// [CoapSessionCommon::remove_handle()].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided handle does not refer to a valid token, i.e., because it belongs to
+    /// a different session.
+    fn try_poll_handle(
+        &self,
+        handle: &CoapRequestHandle,
+    ) -> Result<std::collections::vec_deque::IntoIter<CoapResponse>, RequestPollError> {
+        if !handle.expects_response {
+            return Ok(VecDeque::new().into_iter());
+        }
+        self.expire_timed_out_requests();
+        self.fail_aborted_requests();
+        send_due_retries(self);
+        send_queued_requests(self);
+        let mut inner = self.inner_mut();
+        if let Some(error) = inner.failed_requests.get(&handle.token) {
+            return Err(*error);
+        }
+        let responses = inner
             .received_responses
             .insert(handle.token.clone(), VecDeque::new())
-            .expect("Attempted to poll handle that does not refer to a valid token")
-            .into_iter()
+            .expect("Attempted to poll handle that does not refer to a valid token");
+        inner.account_memory();
+        Ok(responses.into_iter())
     }
 
     /// Returns whether this session waits for the provided token.
@@ -366,12 +1419,174 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
         self.inner_ref().received_responses.contains_key(token)
     }
 
+    /// Sends the given CoapRequest like [CoapSessionCommon::send_request()], storing `value`
+    /// alongside the request until its handle is removed.
+    ///
+    /// This allows attaching application-specific context to each exchange. The value can be
+    /// accessed using [CoapSessionCommon::with_request_value()] and is handed back by
+    /// [CoapSessionCommon::remove_handle_with_value()] once the exchange is complete, regardless of
+    /// whether a response was received or the request failed (e.g., because it timed out or was
+    /// rejected by the peer, see [CoapSessionCommon::try_poll_handle()]).
+    /// Values of requests whose handles are never removed are dropped alongside the session.
+    ///
+    /// # Errors
+    /// See [CoapSessionCommon::send_request()], `value` is dropped if the request could not be
+    /// sent.
+    fn send_request_with_value<T: Any>(
+        &self,
+        req: CoapRequest,
+        value: T,
+    ) -> Result<CoapRequestHandle, MessageConversionError> {
+        let handle = self.send_request(req)?;
+        let inner = &mut *self.inner_mut();
+        inner.request_values.insert(handle.token.clone(), Box::new(value));
+        inner.account_memory();
+        Ok(handle)
+    }
+
+    /// Calls `f` with the value stored alongside the request of the given handle (see
+    /// [CoapSessionCommon::send_request_with_value()]) and returns its result.
+    ///
+    /// Returns `Ok(None)` if no value is stored for the request, and
+    /// [SessionGetAppDataError::WrongType] if the stored value is not of type `T`.
+    fn with_request_value<T: Any, R, F: FnOnce(&mut T) -> R>(
+        &self,
+        handle: &CoapRequestHandle,
+        f: F,
+    ) -> Result<Option<R>, SessionGetAppDataError> {
+        // The value is taken out of the session while f is called, so that f may use the session.
+        let Some(mut value) = self.inner_mut().request_values.remove(&handle.token) else {
+            return Ok(None);
+        };
+        let result = value.downcast_mut().map(f);
+        self.inner_mut().request_values.insert(handle.token.clone(), value);
+        result.map(Some).ok_or(SessionGetAppDataError::WrongType)
+    }
+
+    /// Stops listening for responses to this request handle like
+    /// [CoapSessionCommon::remove_handle()], returning the value stored alongside the request (see
+    /// [CoapSessionCommon::send_request_with_value()]).
+    fn remove_handle_with_value(&self, handle: CoapRequestHandle) -> Option<Box<dyn Any>> {
+        let value = self.inner_mut().request_values.remove(&handle.token);
+        self.remove_handle(handle);
+        value
+    }
+
+    /// Returns the number of requests of this session whose handles have not been removed yet,
+    /// i.e., that are still awaiting responses or have failed (see
+    /// [CoapContext::set_max_pending_requests()]).
+    fn pending_requests(&self) -> usize {
+        let inner = self.inner_ref();
+        inner.received_responses.len() + inner.failed_requests.len()
+    }
+
     /// Stops listening for responses to this request handle.
     ///
     /// Any future responses to the request associated with this handle will be responded to with an
     /// RST message.
     fn remove_handle(&self, handle: CoapRequestHandle) {
-        self.inner_mut().received_responses.remove(&handle.token);
+        {
+            let inner = &mut *self.inner_mut();
+            inner.received_responses.remove(&handle.token);
+            inner.request_deadlines.remove(&handle.token);
+            inner.failed_requests.remove(&handle.token);
+            inner.request_values.remove(&handle.token);
+            inner.cache_requests.remove(&handle.token);
+            inner.retryable_requests.remove(&handle.token);
+            inner.pending_retries.remove(&handle.token);
+            inner.observations.remove(&handle.token);
+            inner.reregistered_observations.remove(&handle.token);
+            inner.observation_events.remove(&handle.token);
+            inner.notification_orders.remove(&handle.token);
+            inner.forget_unanswered_request(&handle.token);
+            inner.account_memory();
+        }
+        // The request may have occupied a slot for outstanding requests.
+        send_queued_requests(self);
+    }
+
+    /// Returns the message type used for requests sent on this session that do not explicitly
+    /// specify whether they are confirmable (see
+    /// [CoapSessionCommon::set_default_message_type()]).
+    fn default_message_type(&self) -> CoapMessageType {
+        self.inner_ref().default_message_type
+    }
+
+    /// Sets the message type used for requests sent on this session that do not explicitly
+    /// specify whether they are confirmable, i.e., requests built using a
+    /// [CoapRequestBuilder](crate::message::CoapRequestBuilder) without calling
+    /// [confirmable()](crate::message::CoapRequestBuilder::confirmable).
+    ///
+    /// The default is [CoapMessageType::Con]. For reliable transports (TCP/TLS), the message type
+    /// is not transmitted and therefore has no effect.
+    ///
+    /// # Errors
+    /// Returns [MessageTypeError::InvalidForMessageCode] if the message type is neither
+    /// [CoapMessageType::Con] nor [CoapMessageType::Non].
+    fn set_default_message_type(&self, type_: CoapMessageType) -> Result<(), MessageTypeError> {
+        match type_ {
+            CoapMessageType::Con | CoapMessageType::Non => {
+                self.inner_mut().default_message_type = type_;
+                Ok(())
+            },
+            v => Err(MessageTypeError::InvalidForMessageCode(v)),
+        }
+    }
+
+    /// Returns the policy used for responses received from an unexpected address.
+    ///
+    /// See [CoapResponseAddressPolicy] for more information.
+    fn response_address_policy(&self) -> CoapResponseAddressPolicy {
+        self.inner_ref().response_addr_policy
+    }
+
+    /// Sets the policy used for responses received from an unexpected address.
+    ///
+    /// The default policy is [CoapResponseAddressPolicy::Permissive].
+    fn set_response_address_policy(&self, policy: CoapResponseAddressPolicy) {
+        self.inner_mut().response_addr_policy = policy;
+    }
+
+    /// Enables or disables tracing of the PDUs of this session.
+    ///
+    /// While tracing is enabled, every PDU that would be passed to the PDU inspector of the context
+    /// (see [CoapContext::set_pdu_inspector()]) is also logged at debug level using the target
+    /// [TRACE_LOG_TARGET](crate::logging::TRACE_LOG_TARGET), tagged with the address of the peer
+    /// and decoded as described in the [Display](std::fmt::Display) implementation of
+    /// [CoapPduView].
+    /// Tracing is independent of the log level of libcoap and of whether its log handler is
+    /// installed.
+    ///
+    /// Changes take effect immediately, i.e., tracing can also be enabled from within event or
+    /// request handlers. See [CoapContext::trace_next_sessions()] for enabling tracing of sessions
+    /// that have not been created yet.
+    fn set_trace(&self, enabled: bool) {
+        self.inner_mut().trace = enabled;
+    }
+
+    /// Returns whether tracing of the PDUs of this session is enabled, see
+    /// [CoapSessionCommon::set_trace()].
+    fn is_traced(&self) -> bool {
+        self.inner_ref().trace
+    }
+
+    /// Returns the number of responses that were received on this session from an address other
+    /// than the one the request was sent to (regardless of whether they were accepted or not).
+    fn response_address_mismatch_count(&self) -> u64 {
+        self.inner_ref().response_addr_mismatches
+    }
+
+    /// Returns the traffic statistics of this session.
+    ///
+    /// See [CoapContext::stats()] for the statistics of all sessions of a context.
+    fn stats(&self) -> CoapStats {
+        self.inner_ref().stats
+    }
+
+    /// Resets the traffic statistics of this session (without affecting the statistics of its
+    /// context).
+    fn reset_stats(&self) {
+        self.inner_mut().stats = CoapStats::default();
     }
 
     /// Returns a mutable reference to the underlying raw session.
@@ -395,7 +1610,7 @@ pub trait CoapSessionCommon<'a>: CoapSessionCommonInternal<'a> {
 
 impl<'a, T: CoapSessionCommonInternal<'a>> CoapSessionCommon<'a> for T {}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// The representation of a CoAP session.
 ///
 /// This enum only provides functionality that is common between clients and servers (by
@@ -492,8 +1707,142 @@ impl Eq for CoapSession<'_> {}
 #[doc(hidden)]
 pub struct CoapSessionInner<'a> {
     raw_session: *mut coap_session_t,
+    id: CoapSessionId,
+    /// Last known remote address of this session, used to detect address changes.
+    last_addr_remote: Option<SocketAddr>,
     app_data: Option<Rc<dyn Any>>,
     received_responses: HashMap<CoapToken, VecDeque<CoapResponse>>,
+    /// Deadlines of requests with a timeout that have not received a response yet.
+    request_deadlines: HashMap<CoapToken, Instant>,
+    /// Requests that have not received a response yet, alongside the abort counter of the context
+    /// at the time they were sent (see [CoapContext::abort_pending()]).
+    unanswered_requests: HashMap<CoapToken, u64>,
+    /// Times at which requests that have not received a response yet were sent, used for
+    /// [CoapTransferStats::duration].
+    request_sent_at: HashMap<CoapToken, Instant>,
+    /// Tokens of requests that have failed (i.e., timed out or were rejected by the peer), but
+    /// whose handles have not been removed yet.
+    failed_requests: HashMap<CoapToken, RequestPollError>,
+    /// Application-specific values stored alongside requests, see
+    /// [CoapSessionCommon::send_request_with_value()].
+    request_values: HashMap<CoapToken, Box<dyn Any>>,
+    /// Client-side cache for responses to GET requests (if enabled, see
+    /// [CoapClientSession::set_response_cache_capacity()]).
+    response_cache: Option<ResponseCache>,
+    /// Cache keys of sent requests whose responses are handled by the response cache, alongside
+    /// whether the request revalidates a stale cached response.
+    cache_requests: HashMap<CoapToken, (ResponseCacheKey, bool)>,
+    /// Policy for re-sending requests answered with 5.03 (Service Unavailable), see
+    /// [CoapClientSession::set_retry_policy()].
+    retry_policy: Option<RetryPolicy>,
+    /// Hook called for requests that are re-sent, see [CoapClientSession::set_retry_hook()].
+    retry_hook: Option<RetryHook>,
+    /// Requests that are re-sent if the server is temporarily unavailable (see
+    /// [CoapClientSession::set_retry_policy()]), alongside the number of times they have been
+    /// re-sent.
+    retryable_requests: HashMap<CoapToken, (CoapRequest, u32)>,
+    /// Times at which requests that were answered with 5.03 (Service Unavailable) are re-sent.
+    pending_retries: HashMap<CoapToken, Instant>,
+    /// Message type used for requests that do not specify one explicitly.
+    default_message_type: CoapMessageType,
+    /// Statistics of the resource whose request handler is currently being called for this
+    /// session (if any), used to record sent responses.
+    response_stats: Option<Rc<RefCell<CoapResourceStats>>>,
+    /// Whether requests received on this session should be refused (set for server-side sessions
+    /// that were created on a draining endpoint, see
+    /// [CoapContext::rebind_endpoint()](crate::CoapContext::rebind_endpoint)).
+    refuse_requests: bool,
+    /// Message IDs of recently received requests alongside the time until which they are tracked
+    /// (None if they never expire), used to detect retransmissions (oldest first).
+    recent_request_mids: VecDeque<(CoapMessageId, Option<Instant>)>,
+    /// Classes of responses that should not be sent on this session (set while the request handler
+    /// for a request with a No-Response option is called).
+    suppressed_responses: CoapNoResponse,
+    /// Raw parameters of the request whose handler is currently called by libcoap for this
+    /// session (if any), used to send large responses.
+    handled_request: Option<HandledRequest>,
+    /// Whether a deferred request is currently replayed for this session, whose response PDU is
+    /// not sent by libcoap (see [set_replaying_request()]).
+    replaying_request: bool,
+    /// Asynchronous requests registered with libcoap for this session that have not been answered
+    /// yet (see [CoapRequest::into_async()]).
+    #[cfg(feature = "async")]
+    async_requests: Vec<Rc<RefCell<AsyncRequest>>>,
+    /// Policy for responses received from an address other than the expected one.
+    response_addr_policy: CoapResponseAddressPolicy,
+    /// Addresses that belong to the peer of this session, the first one being the address the
+    /// session was created for.
+    known_peer_addrs: Vec<SocketAddr>,
+    /// Number of responses received from an address other than the expected one.
+    response_addr_mismatches: u64,
+    /// Whether this session has been established at least once (i.e., a connected event has been
+    /// reported for it).
+    has_been_established: bool,
+    /// Whether this session has lost its connection and is waiting to be reconnected by libcoap.
+    reconnecting: bool,
+    /// Whether this session was not established before its handshake deadline (see
+    /// [CoapContext::set_handshake_deadline()]) and has therefore been failed.
+    handshake_timed_out: bool,
+    /// Alert and last log message of the TLS library integration for the most recent (D)TLS
+    /// handshake failure of this session (see [CoapSessionCommon::last_error()]).
+    handshake_failure: Option<(Option<CoapTlsAlert>, Option<String>)>,
+    /// Reason this (server-side) session has been disconnected for (see
+    /// [CoapServerSession::disconnect()]).
+    close_reason: Option<CoapSessionCloseReason>,
+    /// Whether the connection of this (reliable) session has been established, but the CSM of the
+    /// peer has not been received yet.
+    awaiting_csm: bool,
+    /// Whether messages of this session are protected using OSCORE.
+    oscore: bool,
+    /// DER-encoded certificate (or raw public key) of the peer, as validated during the handshake.
+    #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+    peer_certificate: Option<Box<[u8]>>,
+    /// Number of requests sent using this session while it is reconnecting.
+    requests_while_reconnecting: usize,
+    /// Confirmable messages sent using this session that libcoap may still have to transmit (see
+    /// [CoapSessionCommon::queued_pdus()]).
+    send_queue: SendQueue,
+    /// Estimator for the round-trip time of this session (see
+    /// [CoapSessionCommon::transmission_state()]).
+    rto_estimator: RtoEstimator,
+    /// Policy for handling ICMP errors (see [CoapSessionCommon::set_icmp_error_policy()]).
+    icmp_error_policy: IcmpErrorPolicy,
+    /// Limit for the number of outstanding requests (see
+    /// [CoapSessionCommon::set_max_outstanding_requests()]).
+    outstanding_limit: Option<OutstandingRequestLimit>,
+    /// Requests that wait for a free slot before they are sent (oldest first).
+    queued_requests: VecDeque<QueuedRequest>,
+    /// Registration requests of the active observations of this session, which are sent again
+    /// once the session has been re-established (see [ReconnectPolicy::reregister_observations]).
+    observations: HashMap<CoapToken, CoapRequest>,
+    /// Observations that have been re-registered, but have not received a response to their
+    /// re-registration yet.
+    reregistered_observations: HashSet<CoapToken>,
+    /// Events of observations that have not been polled yet (see
+    /// [CoapSessionCommon::poll_observation_events()]).
+    observation_events: HashMap<CoapToken, VecDeque<CoapObservationEvent>>,
+    /// Freshest notifications of observations with ordered delivery (see
+    /// [CoapSessionCommon::set_ordered_notifications()]).
+    notification_orders: HashMap<CoapToken, CoapNotificationOrder>,
+    /// Whether this session has been reconnected and its observations have to be re-registered
+    /// once it is established.
+    reregister_pending: bool,
+    /// Traffic statistics of this session.
+    stats: CoapStats,
+    /// Time at which traffic was last recorded for this session (according to the clock of the
+    /// context), see [CoapContext::set_idle_session_hook()].
+    last_activity: Instant,
+    /// Whether the idle session hook has declined to keep this session alive since its last
+    /// activity.
+    idle_hook_declined: bool,
+    /// Whether the PDUs of this session are logged, see [CoapSessionCommon::set_trace()].
+    trace: bool,
+    /// Memory usage of this session as currently accounted for by its context, see
+    /// [CoapContext::memory_usage()].
+    accounted_memory: SessionMemoryUsage,
+    /// State shared with the context this session belongs to (whose traffic statistics are updated
+    /// alongside the ones of this session).
+    context_shared: Rc<CoapContextShared>,
     _context_lifetime_marker: PhantomData<&'a coap_context_t>,
 }
 
@@ -513,13 +1862,1078 @@ impl CoapSessionInner<'_> {
     /// never provide values of this session with a lifetime that exceeds the one of the
     /// [CoapContext] this session is bound to.
     pub(crate) unsafe fn new<'a>(raw_session: *mut coap_session_t) -> CoapSessionInner<'a> {
-        CoapSessionInner {
+        let addr_remote = raw_addr_remote(raw_session);
+        let context_shared = CoapContext::shared_state_of_raw(coap_session_get_context(raw_session));
+        if let Some(leisure) = context_shared.default_leisure.get() {
+            coap_session_set_default_leisure(raw_session, duration_fixed_point(leisure));
+        }
+        if let Some(rate) = context_shared.probing_rate.get() {
+            coap_session_set_probing_rate(raw_session, rate);
+        }
+        let last_activity = context_shared.now();
+        let trace = context_shared.take_session_trace(addr_remote);
+        let mut inner = CoapSessionInner {
             raw_session,
+            id: CoapSessionId::next(),
+            last_addr_remote: addr_remote,
             app_data: None,
             received_responses: HashMap::new(),
+            request_deadlines: HashMap::new(),
+            unanswered_requests: HashMap::new(),
+            request_sent_at: HashMap::new(),
+            failed_requests: HashMap::new(),
+            request_values: HashMap::new(),
+            response_cache: None,
+            cache_requests: HashMap::new(),
+            retry_policy: None,
+            retry_hook: None,
+            retryable_requests: HashMap::new(),
+            pending_retries: HashMap::new(),
+            default_message_type: CoapMessageType::Con,
+            response_stats: None,
+            refuse_requests: false,
+            recent_request_mids: VecDeque::new(),
+            suppressed_responses: CoapNoResponse::empty(),
+            handled_request: None,
+            replaying_request: false,
+            #[cfg(feature = "async")]
+            async_requests: Vec::new(),
+            response_addr_policy: CoapResponseAddressPolicy::default(),
+            known_peer_addrs: addr_remote.into_iter().collect(),
+            response_addr_mismatches: 0,
+            has_been_established: false,
+            reconnecting: false,
+            handshake_timed_out: false,
+            handshake_failure: None,
+            close_reason: None,
+            awaiting_csm: false,
+            oscore: false,
+            #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+            peer_certificate: None,
+            requests_while_reconnecting: 0,
+            send_queue: SendQueue::default(),
+            rto_estimator: RtoEstimator::default(),
+            icmp_error_policy: IcmpErrorPolicy::default(),
+            outstanding_limit: None,
+            queued_requests: VecDeque::new(),
+            observations: HashMap::new(),
+            reregistered_observations: HashSet::new(),
+            observation_events: HashMap::new(),
+            notification_orders: HashMap::new(),
+            reregister_pending: false,
+            stats: CoapStats::default(),
+            last_activity,
+            idle_hook_declined: false,
+            trace,
+            accounted_memory: SessionMemoryUsage::default(),
+            context_shared,
             _context_lifetime_marker: Default::default(),
+        };
+        inner.account_memory();
+        inner
+    }
+
+    /// Returns the memory currently used by this session (see
+    /// [CoapMemoryReport](crate::CoapMemoryReport)).
+    fn memory_usage(&self) -> SessionMemoryUsage {
+        let entry = |token: &CoapToken, value_size: usize| std::mem::size_of_val(token) + token.len() + value_size;
+        #[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+        let certificate = self
+            .peer_certificate
+            .as_ref()
+            .map_or(0, |certificate| certificate.len());
+        #[cfg(not(any(feature = "dtls-pki", feature = "dtls-rpk")))]
+        let certificate = 0;
+        let session = std::mem::size_of::<Self>()
+            + self.app_data.as_ref().map_or(0, |data| std::mem::size_of_val(&**data))
+            + self.known_peer_addrs.len() * std::mem::size_of::<SocketAddr>()
+            + self.recent_request_mids.len() * std::mem::size_of::<(CoapMessageId, Option<Instant>)>()
+            + self
+                .handshake_failure
+                .as_ref()
+                .and_then(|(_, message)| message.as_ref())
+                .map_or(0, String::len)
+            + certificate;
+        let responses: usize = self
+            .received_responses
+            .iter()
+            .map(|(token, responses)| {
+                entry(
+                    token,
+                    std::mem::size_of_val(responses) + responses.iter().map(message_memory).sum::<usize>(),
+                )
+            })
+            .sum();
+        let values: usize = self
+            .request_values
+            .iter()
+            .map(|(token, value)| entry(token, std::mem::size_of_val(value) + std::mem::size_of_val(&**value)))
+            .sum();
+        let queued: usize = self
+            .queued_requests
+            .iter()
+            .map(|queued| {
+                std::mem::size_of_val(queued) + queued.token.len() + queued.request.data().map_or(0, <[u8]>::len)
+            })
+            .sum();
+        let observations: usize = self
+            .observations
+            .iter()
+            .map(|(token, request)| entry(token, message_memory(request)))
+            .sum::<usize>()
+            + self
+                .observation_events
+                .iter()
+                .map(|(token, events)| entry(token, events.len() * std::mem::size_of::<CoapObservationEvent>()))
+                .sum::<usize>()
+            + self
+                .reregistered_observations
+                .iter()
+                .map(|token| entry(token, 0))
+                .sum::<usize>()
+            + self
+                .notification_orders
+                .keys()
+                .map(|token| entry(token, std::mem::size_of::<CoapNotificationOrder>()))
+                .sum::<usize>();
+        let retries: usize = self
+            .retryable_requests
+            .iter()
+            .map(|(token, (request, _))| entry(token, message_memory(request) + std::mem::size_of::<u32>()))
+            .sum();
+        let records = token_map_memory(&self.request_deadlines)
+            + token_map_memory(&self.unanswered_requests)
+            + token_map_memory(&self.request_sent_at)
+            + token_map_memory(&self.failed_requests)
+            + token_map_memory(&self.cache_requests)
+            + token_map_memory(&self.pending_retries);
+        SessionMemoryUsage {
+            session,
+            pending_requests: responses + values + queued + observations + retries + records,
+            cached_representations: self.response_cache.as_ref().map_or(0, ResponseCache::memory_usage),
         }
     }
+
+    /// Updates the memory usage of this session that is accounted for by its context (see
+    /// [CoapContext::memory_usage()]), which has to be done whenever its bookkeeping changes.
+    fn account_memory(&mut self) {
+        let usage = self.memory_usage();
+        self.context_shared.memory.update(self.accounted_memory, usage);
+        self.accounted_memory = usage;
+    }
+
+    /// Stops counting the request with the given token as awaiting its first response (see
+    /// [CoapContext::pending_transmissions()]).
+    ///
+    /// Requests sent before pending transmissions were last aborted are no longer counted by the
+    /// context, so only the own bookkeeping is updated for them.
+    fn forget_unanswered_request(&mut self, token: &[u8]) {
+        self.request_sent_at.remove(token);
+        let shared = &self.context_shared;
+        if self.unanswered_requests.remove(token) == Some(shared.abort_count.get()) {
+            shared
+                .unanswered_requests
+                .set(shared.unanswered_requests.get().saturating_sub(1));
+        }
+    }
+
+    /// Fails the request with the given token if it has not received a response once the given
+    /// timeout has elapsed (see [CoapRequest::set_timeout()]).
+    fn start_request_timeout(&mut self, token: &CoapToken, timeout: Duration) {
+        let deadline = self.context_shared.now() + timeout;
+        self.request_deadlines.insert(token.clone(), deadline);
+        self.context_shared.request_deadlines.borrow_mut().push(deadline);
+    }
+
+    /// Drops queued requests whose handles have been removed or that have failed (e.g., because
+    /// they timed out while queued).
+    fn prune_queued_requests(&mut self) {
+        let received_responses = &self.received_responses;
+        self.queued_requests
+            .retain(|queued| received_responses.contains_key(&queued.token));
+    }
+
+    /// Returns whether a request sent now has to be queued because of the outstanding request
+    /// limit of this session.
+    ///
+    /// # Errors
+    /// Returns [MessageConversionError::RequestQueueFull] if the request would have to be queued,
+    /// but the queue is full.
+    fn must_queue_request(&mut self) -> Result<bool, MessageConversionError> {
+        let Some(limit) = self.outstanding_limit else {
+            return Ok(false);
+        };
+        self.prune_queued_requests();
+        // Requests are sent in order, so new ones also have to wait if others are still queued.
+        if self.queued_requests.is_empty() && self.unanswered_requests.len() < limit.max_outstanding.max(1) {
+            return Ok(false);
+        }
+        if self.queued_requests.len() >= limit.max_queued {
+            return Err(MessageConversionError::RequestQueueFull(self.queued_requests.len()));
+        }
+        Ok(true)
+    }
+
+    /// Schedules the request with the given token to be re-sent if `response` asks the client to
+    /// repeat it later according to the retry policy of this session (see
+    /// [CoapClientSession::set_retry_policy()]), returning the scheduled attempt.
+    fn schedule_retry(&mut self, token: &CoapToken, response: &CoapResponse) -> Option<CoapRetryAttempt> {
+        let policy = self.retry_policy?;
+        let CoapMessageCode::Response(code) = response.code() else {
+            return None;
+        };
+        let (_, retries) = self.retryable_requests.get_mut(token)?;
+        if !policy.retries_code(code) || *retries >= policy.max_retries {
+            return None;
+        }
+        // Responses without a Max-Age option have a Max-Age of 60 seconds, see RFC 7252,
+        // Section 5.10.5.
+        let delay = Duration::from_secs(response.max_age().unwrap_or(60).into());
+        if delay > policy.max_delay {
+            return None;
+        }
+        *retries += 1;
+        let attempt = *retries;
+        let deadline = self.context_shared.now() + delay;
+        self.pending_retries.insert(token.clone(), deadline);
+        // Wakes up the context in time to re-send the request.
+        self.context_shared.request_deadlines.borrow_mut().push(deadline);
+        self.forget_unanswered_request(token);
+        self.account_memory();
+        Some(CoapRetryAttempt {
+            token: token.clone(),
+            attempt,
+            code,
+            delay,
+        })
+    }
+
+    /// Fails all requests that are still awaiting responses with the given error.
+    fn fail_requests(&mut self, error: RequestPollError) {
+        self.request_deadlines.clear();
+        self.queued_requests.clear();
+        self.retryable_requests.clear();
+        self.pending_retries.clear();
+        self.observations.clear();
+        self.reregistered_observations.clear();
+        for (token, _) in std::mem::take(&mut self.received_responses) {
+            self.forget_unanswered_request(&token);
+            self.failed_requests.insert(token, error);
+        }
+        self.account_memory();
+    }
+}
+
+impl Drop for CoapSessionInner<'_> {
+    fn drop(&mut self) {
+        let shared = &self.context_shared;
+        shared.congested_sessions.borrow_mut().remove(&self.id);
+        shared
+            .memory
+            .update(self.accounted_memory, SessionMemoryUsage::default());
+        let abort_count = shared.abort_count.get();
+        let counted = self
+            .unanswered_requests
+            .values()
+            .filter(|sent_abort_count| **sent_abort_count == abort_count)
+            .count();
+        shared
+            .unanswered_requests
+            .set(shared.unanswered_requests.get().saturating_sub(counted));
+        // Handles of asynchronous requests must no longer access the raw session.
+        #[cfg(feature = "async")]
+        for request in &self.async_requests {
+            request.borrow_mut().invalidate();
+        }
+    }
+}
+
+/// Returns the number of bytes used by the entries of the given map of requests, excluding values
+/// that are stored outside of the map.
+fn token_map_memory<V>(map: &HashMap<CoapToken, V>) -> usize {
+    map.keys()
+        .map(|token| std::mem::size_of::<(CoapToken, V)>() + token.len())
+        .sum()
+}
+
+/// Sends the given request (which already has its token, message ID and message type) and keeps
+/// track of it until it has been answered, see [CoapSessionCommon::send_request()].
+///
+/// If `expects_response` is true, the request must already be listed in the received responses of
+/// the session.
+fn transmit_request<'a, S: CoapSessionCommon<'a> + ?Sized>(
+    session: &S,
+    mut req: CoapRequest,
+    token: CoapToken,
+    cache_key: Option<(ResponseCacheKey, bool)>,
+    timeout: Option<Duration>,
+    expects_response: bool,
+) -> Result<CoapRequestHandle, MessageConversionError> {
+    let result = loop {
+        // Requests that fit into the smallest MTU the wrapper reduces to cannot be sent using
+        // smaller blocks, so there is no need to keep a copy of them.
+        let retry = (session.icmp_error_policy().reduce_mtu
+            && req.as_message().may_exceed_pdu_size(MIN_REDUCED_MTU as usize))
+        .then(|| req.clone());
+        match (session.send(req.into_message()), retry) {
+            (Err(MessageConversionError::PacketTooBig(size)), Some(retry)) => {
+                let Some(mtu) = reduced_mtu(session.max_pdu_size() as u32) else {
+                    break Err(MessageConversionError::PacketTooBig(size));
+                };
+                session.set_mtu(mtu);
+                record_stats(session, |stats| stats.mtu_reductions += 1);
+                req = retry;
+            },
+            (result, _) => break result,
+        }
+    };
+    match result {
+        Ok(mid) => {
+            let inner = &mut *session.inner_mut();
+            if let Some(timeout) = timeout {
+                inner.start_request_timeout(&token, timeout);
+            }
+            if let Some(cache_key) = cache_key {
+                inner.cache_requests.insert(token.clone(), cache_key);
+            }
+            if expects_response {
+                let shared = &inner.context_shared;
+                inner
+                    .unanswered_requests
+                    .insert(token.clone(), shared.abort_count.get());
+                shared.unanswered_requests.set(shared.unanswered_requests.get() + 1);
+                inner.request_sent_at.insert(token.clone(), shared.now());
+            }
+            Ok(CoapRequestHandle::new(mid, token, expects_response))
+        },
+        Err(e) => {
+            let inner = &mut *session.inner_mut();
+            inner.received_responses.remove(&token);
+            inner.observations.remove(&token);
+            Err(e)
+        },
+    }
+}
+
+/// Re-sends the requests of the given session whose retry delay has elapsed (see
+/// [CoapClientSession::set_retry_policy()]).
+///
+/// Requests are re-sent using their original token, so responses are delivered to the same handle.
+pub(crate) fn send_due_retries<'a, S: CoapSessionCommon<'a> + ?Sized>(session: &S) {
+    let due: Vec<(CoapToken, CoapRequest)> = {
+        let inner = &mut *session.inner_mut();
+        let now = inner.context_shared.now();
+        if inner.pending_retries.values().all(|time| *time > now) {
+            return;
+        }
+        let tokens: Vec<CoapToken> = inner
+            .pending_retries
+            .iter()
+            .filter(|(_, time)| **time <= now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        tokens
+            .into_iter()
+            .filter_map(|token| {
+                inner.pending_retries.remove(&token);
+                // Requests that have failed in the meantime (e.g., because they timed out) are
+                // not re-sent.
+                if !inner.received_responses.contains_key(&token) {
+                    inner.retryable_requests.remove(&token);
+                    return None;
+                }
+                let request = inner.retryable_requests.get(&token)?.0.clone();
+                Some((token, request))
+            })
+            .collect()
+    };
+    for (token, mut request) in due {
+        request.set_mid(Some(session.next_message_id()));
+        if transmit_request(session, request, token.clone(), None, None, true).is_err() {
+            let inner = &mut *session.inner_mut();
+            inner.request_deadlines.remove(&token);
+            inner.retryable_requests.remove(&token);
+            inner.failed_requests.insert(token, RequestPollError::SendFailed);
+        }
+    }
+    session.inner_mut().account_memory();
+}
+
+/// Sends the requests that are queued because of the outstanding request limit of the given
+/// session for as long as it has free slots (see
+/// [CoapSessionCommon::set_max_outstanding_requests()]).
+pub(crate) fn send_queued_requests<'a, S: CoapSessionCommon<'a> + ?Sized>(session: &S) {
+    loop {
+        let queued = {
+            let inner = &mut *session.inner_mut();
+            inner.prune_queued_requests();
+            let outstanding = inner.unanswered_requests.len();
+            if inner
+                .outstanding_limit
+                .is_some_and(|limit| outstanding >= limit.max_outstanding.max(1))
+            {
+                return;
+            }
+            match inner.queued_requests.pop_front() {
+                Some(queued) => queued,
+                None => return,
+            }
+        };
+        let QueuedRequest {
+            token,
+            request,
+            cache_key,
+            timeout,
+        } = queued;
+        if transmit_request(session, request, token.clone(), cache_key, timeout, true).is_err() {
+            let inner = &mut *session.inner_mut();
+            inner.request_deadlines.remove(&token);
+            inner.failed_requests.insert(token, RequestPollError::SendFailed);
+            inner.account_memory();
+        }
+    }
+}
+
+/// Fails all requests that are queued because of the outstanding request limit of the given
+/// session with the given error (e.g., because the connection of the session has been closed).
+pub(crate) fn fail_queued_requests<'a, S: CoapSessionInnerProvider<'a>>(session: &S, error: RequestPollError) {
+    let inner = &mut *session.inner_mut();
+    for queued in std::mem::take(&mut inner.queued_requests) {
+        if inner.received_responses.remove(&queued.token).is_some() {
+            inner.request_deadlines.remove(&queued.token);
+            inner.failed_requests.insert(queued.token, error);
+        }
+    }
+    inner.account_memory();
+}
+
+/// Removes messages that are no longer queued from the send queue of the given session (see
+/// [CoapSessionCommon::queued_pdus()]).
+pub(crate) fn prune_send_queue<'a, S: CoapSessionInnerProvider<'a>>(session: &S) {
+    let inner = &mut *session.inner_mut();
+    let shared = &inner.context_shared;
+    inner.send_queue.prune(shared.now(), shared.send_queue_epoch.get());
+}
+
+/// Removes the message with the given message ID and/or token from the send queue of the given
+/// session (e.g., because it was acknowledged) and returns whether the send queue has drained to
+/// its low-water mark as a result (see [CoapSessionCommon::is_send_queue_congested()]).
+pub(crate) fn dequeue_sent_message<'a, S: CoapSessionInnerProvider<'a>>(
+    session: &S,
+    mid: Option<CoapMessageId>,
+    token: &[u8],
+) -> bool {
+    if let Some(mid) = mid {
+        session.inner_mut().send_queue.remove_mid(mid);
+    }
+    session.inner_mut().send_queue.remove_token(token);
+    take_send_queue_drained(session)
+}
+
+/// Records that libcoap has retransmitted a message of the given session, returning the
+/// retransmission if the message could be determined (see
+/// [CoapEventHandler::handle_retransmission()](crate::CoapEventHandler::handle_retransmission)).
+pub(crate) fn record_retransmission<'a, S: CoapSessionCommon<'a>>(session: &S) -> Option<CoapRetransmission> {
+    prune_send_queue(session);
+    let ack_timeout = fixed_point_duration(session.ack_timeout());
+    let inner = &mut *session.inner_mut();
+    let now = inner.context_shared.now();
+    inner.send_queue.record_retransmission(now, ack_timeout)
+}
+
+/// Updates the round-trip time estimate of the given session using the message with the given
+/// message ID, which has just been acknowledged (see [CoapSessionCommon::transmission_state()]).
+pub(crate) fn sample_round_trip_time<'a, S: CoapSessionInnerProvider<'a>>(session: &S, mid: CoapMessageId) {
+    let inner = &mut *session.inner_mut();
+    if let Some(rtt) = inner.send_queue.round_trip_time(mid, inner.context_shared.now()) {
+        inner.rto_estimator.add_sample(rtt);
+    }
+}
+
+/// Returns whether the congested send queue of the given session has drained to its low-water
+/// mark, in which case it is no longer considered congested.
+pub(crate) fn take_send_queue_drained<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> bool {
+    prune_send_queue(session);
+    let inner = &mut *session.inner_mut();
+    let shared = &inner.context_shared;
+    let drained = inner.send_queue.take_drained(shared.send_queue_limits.get());
+    if drained {
+        shared.congested_sessions.borrow_mut().remove(&inner.id);
+    }
+    drained
+}
+
+/// Generates a token for a new request of the given session that is not used by any of its
+/// outstanding requests, see [CoapContext::set_token_generator()].
+fn generate_token<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<CoapToken> {
+    let inner = session.inner_ref();
+    let in_use =
+        |token: &[u8]| inner.received_responses.contains_key(token) || inner.failed_requests.contains_key(token);
+    inner.context_shared.generate_token(inner.id, &in_use)
+}
+
+/// Updates the traffic statistics of the given session and of the context it belongs to using `f`.
+pub(crate) fn record_stats<'a, S: CoapSessionInnerProvider<'a>>(session: &S, f: impl Fn(&mut CoapStats)) {
+    let inner = &mut *session.inner_mut();
+    f(&mut inner.stats);
+    f(&mut inner.context_shared.stats.borrow_mut());
+    inner.last_activity = inner.context_shared.now();
+    inner.idle_hook_declined = false;
+}
+
+/// Determines how the body of the given received response was transferred (see
+/// [CoapTransferStats]), given the time at which the corresponding request was sent (if this is
+/// its first response).
+fn transfer_stats<'a, S: CoapSessionInnerProvider<'a>>(
+    session: &S,
+    response: &CoapMessage,
+    sent_at: Option<Instant>,
+) -> CoapTransferStats {
+    let inner = session.inner_ref();
+    let bytes = response.data().map_or(0, |v| v.len());
+    let block2 = response.options_iter().find_map(|option| match option {
+        CoapOption::Block2(block) => Some(*block),
+        _ => None,
+    });
+    let (blockwise, block_szx, blocks) = match block2 {
+        Some(block) => (true, Some((block & 0x7) as u8), Some((block >> 4) + 1)),
+        None => {
+            // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+            let max_pdu_size = unsafe { coap_session_max_pdu_size(inner.raw_session) };
+            let blockwise = bytes >= max_pdu_size;
+            (blockwise, None, (!blockwise).then_some(1))
+        },
+    };
+    CoapTransferStats {
+        blockwise,
+        block_szx,
+        blocks,
+        bytes,
+        duration: sent_at.map(|sent_at| inner.context_shared.now().saturating_duration_since(sent_at)),
+    }
+}
+
+/// Returns the time of the last activity of the given session, or None if the idle session hook
+/// has already declined to keep the session alive since then (see
+/// [CoapContext::set_idle_session_hook()]).
+pub(crate) fn idle_since<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<Instant> {
+    let inner = session.inner_ref();
+    (!inner.idle_hook_declined).then_some(inner.last_activity)
+}
+
+/// Records that the idle session hook has declined to keep the given session alive, so that it is
+/// not called again for the session until its next activity.
+pub(crate) fn decline_idle_session<'a, S: CoapSessionInnerProvider<'a>>(session: &S) {
+    session.inner_mut().idle_hook_declined = true;
+}
+
+/// Fails the request with the given token on the given session (if it is still awaiting
+/// responses), i.e., stops waiting for its responses and reports `error` when its handle is polled.
+pub(crate) fn fail_request<'a, S: CoapSessionInnerProvider<'a>>(session: &S, token: &[u8], error: RequestPollError) {
+    let inner = &mut *session.inner_mut();
+    if inner.received_responses.remove(token).is_some() {
+        inner.request_deadlines.remove(token);
+        inner.forget_unanswered_request(token);
+        inner.failed_requests.insert(Box::from(token), error);
+        inner.account_memory();
+    }
+}
+
+/// Records the DER-encoded certificate (or raw public key) the peer of the given session presented
+/// during the handshake, see [CoapCryptoSessionInfo::peer_certificate()].
+#[cfg(any(feature = "dtls-pki", feature = "dtls-rpk"))]
+pub(crate) fn record_peer_certificate<'a, S: CoapSessionInnerProvider<'a>>(session: &S, certificate: &[u8]) {
+    let inner = &mut *session.inner_mut();
+    inner.peer_certificate = Some(Box::from(certificate));
+    inner.account_memory();
+}
+
+/// Returns whether the given session has been failed because it was not established before its
+/// handshake deadline (see [CoapContext::set_handshake_deadline()]).
+pub(crate) fn handshake_timed_out<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> bool {
+    session.inner_ref().handshake_timed_out
+}
+
+/// Fails the given session because it was not established before its handshake deadline, i.e.,
+/// fails all requests that are still awaiting responses and refuses new ones.
+pub(crate) fn fail_handshake<'a, S: CoapSessionInnerProvider<'a>>(session: &S) {
+    let inner = &mut *session.inner_mut();
+    inner.handshake_timed_out = true;
+    inner.fail_requests(RequestPollError::SessionFailed);
+}
+
+/// Records a (D)TLS handshake failure of the given session, taking the alert and backend message
+/// from the log messages that libcoap has output for the session (see
+/// [SessionCreationError::HandshakeFailed]).
+pub(crate) fn record_handshake_failure<'a, S: CoapSessionInnerProvider<'a>>(session: &S) {
+    let inner = &mut *session.inner_mut();
+    // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner, and
+    // coap_session_str() returns a null-terminated string (or a null pointer if logging is
+    // disabled in libcoap).
+    let description = unsafe {
+        let raw_description = coap_session_str(inner.raw_session);
+        (!raw_description.is_null()).then(|| CStr::from_ptr(raw_description).to_string_lossy().into_owned())
+    };
+    let messages = description.map_or_else(Vec::new, |description| take_session_messages(&description));
+    let alert = messages
+        .iter()
+        .rev()
+        .find_map(|message| CoapTlsAlert::from_log_message(message));
+    inner.handshake_failure = Some((alert, messages.last().cloned()));
+}
+
+/// Counts a received request or response of the given session that is passed to a resource or to
+/// the session, see [CoapContext::do_io_budgeted()].
+pub(crate) fn count_dispatched_pdu<'a, S: CoapSessionInnerProvider<'a>>(session: &S) {
+    let inner = session.inner_ref();
+    let dispatched_pdus = &inner.context_shared.dispatched_pdus;
+    dispatched_pdus.set(dispatched_pdus.get().wrapping_add(1));
+}
+
+/// Returns the size of the given raw PDU received using a session with the given protocol if it
+/// has been received as an oversized datagram that was truncated by the socket.
+///
+/// libcoap does not report whether datagrams have been truncated (`MSG_TRUNC`). The vendored
+/// libcoap build therefore reserves one byte more in its receive buffer (of `COAP_RXBUFFER_SIZE`
+/// bytes) than the size of the largest datagram it accepts, so a datagram that fills the entire
+/// buffer is known to be oversized, while datagrams of the maximum size are handled normally.
+/// With other libcoap builds, the size of the largest accepted datagram is unknown, so truncation
+/// can not be detected and this function always returns None.
+///
+/// Datagrams of DTLS sessions are not checked, as truncated records can not be decrypted (and are
+/// reported as bad packets instead).
+///
+/// # Safety
+/// The provided pointer must point to a valid PDU.
+#[cfg_attr(not(coap_rxbuffer_headroom), allow(unused_variables))]
+pub(crate) unsafe fn truncated_datagram_len(proto: CoapProtocol, raw_pdu: *const coap_pdu_t) -> Option<usize> {
+    #[cfg(coap_rxbuffer_headroom)]
+    if proto == CoapProtocol::Udp {
+        // The header of a datagram always has a size of 4 bytes.
+        let len = 4 + CoapPduView::from_raw(raw_pdu, proto).used_size();
+        return (len >= COAP_RXBUFFER_SIZE as usize).then_some(len);
+    }
+    None
+}
+
+/// Calls the PDU inspector of the context of the given session (if any) for the given raw PDU,
+/// and logs the PDU if the session is traced (see [CoapSessionCommon::set_trace()]).
+///
+/// Does not call the inspector if it is currently being called, i.e., if the PDU is sent by the
+/// inspector itself.
+///
+/// # Safety
+/// The provided pointer must point to a valid PDU that is not modified during this call.
+pub(crate) unsafe fn inspect_pdu<'a, S: CoapSessionInnerProvider<'a>>(
+    session: &S,
+    direction: CoapPduDirection,
+    raw_pdu: *const coap_pdu_t,
+) {
+    let (shared, raw_session) = {
+        let inner = session.inner_ref();
+        if inner.trace {
+            let view = CoapPduView::from_raw(raw_pdu, coap_session_get_proto(inner.raw_session).into());
+            let direction = match direction {
+                CoapPduDirection::Sent => "sent to",
+                CoapPduDirection::Received => "received from",
+            };
+            let peer = inner
+                .last_addr_remote
+                .map_or_else(|| "unknown peer".to_string(), |addr| addr.to_string());
+            forward_trace_message(&format!("{} {}: {}", direction, peer, view));
+        }
+        if inner
+            .context_shared
+            .pdu_inspector
+            .try_borrow()
+            .map_or(true, |inspector| inspector.is_none())
+        {
+            return;
+        }
+        (Rc::clone(&inner.context_shared), inner.raw_session)
+    };
+    let Ok(mut inspector) = shared.pdu_inspector.try_borrow_mut() else {
+        return;
+    };
+    if let Some(inspector) = inspector.as_mut() {
+        let session = CoapSession::from_raw(raw_session);
+        let view = CoapPduView::from_raw(raw_pdu, session.proto());
+        inspector(direction, &session, &view);
+    }
+}
+
+/// Sets the statistics of the resource whose request handler is currently being called for the
+/// given session, so that responses sent using this session are recorded in these statistics.
+pub(crate) fn set_response_stats<'a, S: CoapSessionInnerProvider<'a>>(
+    session: &S,
+    stats: Option<Rc<RefCell<CoapResourceStats>>>,
+) {
+    session.inner_mut().response_stats = stats;
+}
+
+/// Sets the classes of responses that should be dropped instead of being sent using the given
+/// session, used to honor the No-Response option of the request that is currently handled.
+pub(crate) fn set_suppressed_responses<'a, S: CoapSessionInnerProvider<'a>>(session: &S, suppressed: CoapNoResponse) {
+    session.inner_mut().suppressed_responses = suppressed;
+}
+
+/// Raw parameters that libcoap provided to the request handler that is currently called for a
+/// server-side session, see [set_handled_request()].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HandledRequest {
+    pub(crate) raw_resource: *mut coap_resource_t,
+    pub(crate) raw_request: *const coap_pdu_t,
+    pub(crate) raw_query: *const coap_string_t,
+    pub(crate) raw_response: *mut coap_pdu_t,
+}
+
+/// Sets the raw parameters of the request whose handler is currently called for the given
+/// session, which allows responses that do not fit into a single PDU to be added to the response
+/// PDU of libcoap (see [CoapSessionCommon::send()]).
+///
+/// The parameters are ignored while a deferred request is replayed, as its response PDU is not
+/// sent by libcoap.
+pub(crate) fn set_handled_request<'a, S: CoapSessionInnerProvider<'a>>(session: &S, handled: Option<HandledRequest>) {
+    let mut inner = session.inner_mut();
+    if !inner.replaying_request {
+        inner.handled_request = handled;
+    }
+}
+
+/// Sets whether a deferred request is currently replayed for the given session, see
+/// [set_handled_request()].
+pub(crate) fn set_replaying_request<'a, S: CoapSessionInnerProvider<'a>>(session: &S, replaying: bool) {
+    session.inner_mut().replaying_request = replaying;
+}
+
+/// Returns the value of the Observe option of libcoap's response PDU for the request whose
+/// handler is currently called for the given session (see [set_handled_request()]), if any.
+pub(crate) fn handled_response_observe<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<Observe> {
+    let inner = session.inner_ref();
+    let handled = inner.handled_request.as_ref()?;
+    // SAFETY: The response PDU is valid while the request handler is called.
+    let view = unsafe { CoapPduView::from_raw(handled.raw_response, coap_session_get_proto(inner.raw_session).into()) };
+    view.options()
+        .find(|(number, _)| *number == CoapOptionType::Observe as CoapOptionNum)
+        .map(|(_, value)| decode_var_len_u32(value))
+}
+
+/// Returns whether a request that is not currently handled by libcoap is replayed for the given
+/// session, see [set_replaying_request()].
+pub(crate) fn is_replaying_request<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> bool {
+    session.inner_ref().replaying_request
+}
+
+/// Change of the reconnection state of a client session, see [update_reconnect_state()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReconnectUpdate {
+    /// The reconnection state did not change.
+    Unchanged,
+    /// The session has been (re-)established.
+    Established,
+    /// A reconnection attempt for the session has failed.
+    AttemptFailed,
+}
+
+/// Updates the reconnection state of the given client session based on the given event.
+///
+/// If no reconnect `policy` is set, established sessions that lose their connection are not
+/// marked as reconnecting.
+pub(crate) fn update_reconnect_state<'a, S: CoapSessionInnerProvider<'a>>(
+    session: &S,
+    event: coap_event_t,
+    policy: Option<ReconnectPolicy>,
+) -> ReconnectUpdate {
+    let inner = &mut *session.inner_mut();
+    match event {
+        coap_event_t::COAP_EVENT_DTLS_CONNECTED
+        | coap_event_t::COAP_EVENT_TCP_CONNECTED
+        | coap_event_t::COAP_EVENT_SESSION_CONNECTED => {
+            if inner.reconnecting && policy.is_some_and(|policy| policy.reregister_observations) {
+                inner.reregister_pending = true;
+            }
+            inner.has_been_established = true;
+            inner.reconnecting = false;
+            inner.requests_while_reconnecting = 0;
+            ReconnectUpdate::Established
+        },
+        coap_event_t::COAP_EVENT_DTLS_ERROR
+        | coap_event_t::COAP_EVENT_TCP_FAILED
+        | coap_event_t::COAP_EVENT_SESSION_FAILED
+            if inner.reconnecting =>
+        {
+            ReconnectUpdate::AttemptFailed
+        },
+        coap_event_t::COAP_EVENT_DTLS_CLOSED
+        | coap_event_t::COAP_EVENT_TCP_CLOSED
+        | coap_event_t::COAP_EVENT_SESSION_CLOSED
+        | coap_event_t::COAP_EVENT_DTLS_ERROR
+        | coap_event_t::COAP_EVENT_TCP_FAILED
+        | coap_event_t::COAP_EVENT_SESSION_FAILED
+        | coap_event_t::COAP_EVENT_KEEPALIVE_FAILURE => {
+            inner.reconnecting = policy.is_some() && inner.has_been_established;
+            ReconnectUpdate::Unchanged
+        },
+        _ => ReconnectUpdate::Unchanged,
+    }
+}
+
+/// Re-sends the registrations of the observations of the given session if it has been
+/// re-established after reconnecting (see [ReconnectPolicy::reregister_observations]).
+///
+/// The registrations are sent using the tokens of the original requests, so notifications are
+/// delivered to the existing request handles.
+pub(crate) fn reregister_observations<'a, S: CoapSessionCommon<'a>>(session: &S) {
+    if !session.inner_ref().reregister_pending || session.state() != CoapSessionState::Established {
+        return;
+    }
+    let registrations: Vec<(CoapToken, CoapRequest)> = {
+        let inner = &mut *session.inner_mut();
+        inner.reregister_pending = false;
+        let received_responses = &inner.received_responses;
+        inner
+            .observations
+            .retain(|token, _| received_responses.contains_key(token));
+        // Queued registrations have not been sent yet in the first place.
+        let queued: HashSet<&CoapToken> = inner.queued_requests.iter().map(|queued| &queued.token).collect();
+        inner
+            .observations
+            .iter()
+            .filter(|(token, _)| !queued.contains(token))
+            .map(|(token, request)| (token.clone(), request.clone()))
+            .collect()
+    };
+    for (token, mut request) in registrations {
+        request.set_mid(Some(session.next_message_id()));
+        let sent = session.send(request.into_message()).is_ok();
+        let inner = &mut *session.inner_mut();
+        if sent {
+            inner.reregistered_observations.insert(token.clone());
+            // The server may have lost the observation and start a new sequence of Observe values.
+            if let Some(order) = inner.notification_orders.get_mut(&token) {
+                order.restart();
+            }
+            inner
+                .observation_events
+                .entry(token)
+                .or_default()
+                .push_back(CoapObservationEvent::Reregistered);
+        } else {
+            inner.observations.remove(&token);
+            inner.received_responses.remove(&token);
+            inner.failed_requests.insert(token, RequestPollError::SendFailed);
+        }
+        inner.account_memory();
+    }
+}
+
+/// Updates the CSM exchange state of the given session based on the given event.
+///
+/// Returns true if the session failed while waiting for the CSM of the peer (which most commonly
+/// happens if the peer did not send its CSM before the CSM timeout elapsed).
+pub(crate) fn update_csm_state<'a, S: CoapSessionCommon<'a>>(session: &S, event: coap_event_t) -> bool {
+    if !session.proto().is_reliable() {
+        return false;
+    }
+    let inner = &mut *session.inner_mut();
+    match event {
+        // For TLS, the CSM exchange starts once the TLS handshake has completed (for which libcoap
+        // uses the DTLS event).
+        coap_event_t::COAP_EVENT_TCP_CONNECTED if session.proto() == CoapProtocol::Tcp => {
+            inner.awaiting_csm = true;
+        },
+        coap_event_t::COAP_EVENT_DTLS_CONNECTED => inner.awaiting_csm = true,
+        // For WebSockets, the CSM exchange starts once the WebSocket handshake has completed.
+        coap_event_t::COAP_EVENT_WS_CONNECTED => inner.awaiting_csm = true,
+        coap_event_t::COAP_EVENT_SESSION_CONNECTED | coap_event_t::COAP_EVENT_SESSION_CLOSED => {
+            inner.awaiting_csm = false
+        },
+        // libcoap reports the TCP connection as closed before reporting the session as failed.
+        coap_event_t::COAP_EVENT_SESSION_FAILED => return std::mem::replace(&mut inner.awaiting_csm, false),
+        _ => {},
+    }
+    false
+}
+
+/// Sets whether requests received on the given session should be refused with 5.03 Service
+/// Unavailable instead of being passed to the request handler.
+pub(crate) fn set_refuse_requests<'a, S: CoapSessionInnerProvider<'a>>(session: &S, refuse: bool) {
+    session.inner_mut().refuse_requests = refuse;
+}
+
+/// Returns whether requests received on the given session should be refused (see
+/// [set_refuse_requests()]).
+pub(crate) fn refuses_requests<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> bool {
+    session.inner_ref().refuse_requests
+}
+
+/// Converts a fixed point number of seconds as returned by libcoap into a [Duration].
+fn fixed_point_duration((integer_part, fractional_part): (u16, u16)) -> Duration {
+    Duration::from_secs(integer_part.into()) + Duration::from_millis(fractional_part.into())
+}
+
+/// Converts a [Duration] into a fixed point number of seconds as expected by libcoap, rounding
+/// down to whole milliseconds and saturating at the largest representable value.
+fn duration_fixed_point(duration: Duration) -> coap_fixed_point_t {
+    match u16::try_from(duration.as_secs()) {
+        Ok(integer_part) => coap_fixed_point_t {
+            integer_part,
+            fractional_part: duration.subsec_millis() as u16,
+        },
+        Err(_) => coap_fixed_point_t {
+            integer_part: u16::MAX,
+            fractional_part: 999,
+        },
+    }
+}
+
+/// Returns the MAX_TRANSMIT_SPAN derived from the transmission parameters of the given session (see
+/// [RFC 7252, Section 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2)).
+fn max_transmit_span<'a, S: CoapSessionCommon<'a> + ?Sized>(session: &S) -> Duration {
+    let ack_timeout = fixed_point_duration(session.ack_timeout()).as_secs_f64();
+    let ack_random_factor = fixed_point_duration(session.ack_random_factor()).as_secs_f64();
+    let retransmissions = 2f64.powi(session.max_retransmit().into()) - 1.0;
+    Duration::try_from_secs_f64(ack_timeout * retransmissions * ack_random_factor).unwrap_or(Duration::MAX)
+}
+
+/// Returns the MAX_TRANSMIT_WAIT derived from the transmission parameters of the given session
+/// (see [RFC 7252, Section 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2)),
+/// i.e., the maximum time from the first transmission of a confirmable message to the time when
+/// the sender gives up on receiving an acknowledgement.
+fn max_transmit_wait<'a, S: CoapSessionCommon<'a> + ?Sized>(session: &S) -> Duration {
+    let ack_timeout = fixed_point_duration(session.ack_timeout()).as_secs_f64();
+    let ack_random_factor = fixed_point_duration(session.ack_random_factor()).as_secs_f64();
+    let transmissions = 2f64.powi(i32::from(session.max_retransmit()) + 1) - 1.0;
+    Duration::try_from_secs_f64(ack_timeout * transmissions * ack_random_factor).unwrap_or(Duration::MAX)
+}
+
+/// Records the receipt of a request with the given message ID on the given session and returns
+/// whether the request is a retransmission of a request that was received earlier.
+///
+/// Message IDs of confirmable requests are tracked for [CoapSessionCommon::exchange_lifetime()],
+/// those of non-confirmable requests for [CoapSessionCommon::non_lifetime()].
+/// At most `capacity` message IDs are tracked at the same time, the oldest ones are evicted first.
+pub(crate) fn record_request_mid<'a, S: CoapSessionCommon<'a>>(
+    session: &S,
+    mid: CoapMessageId,
+    confirmable: bool,
+    capacity: usize,
+) -> bool {
+    let lifetime = if confirmable {
+        session.exchange_lifetime()
+    } else {
+        session.non_lifetime()
+    };
+    let now = Instant::now();
+    let mut inner = session.inner_mut();
+    inner
+        .recent_request_mids
+        .retain(|(_, expiry)| expiry.map_or(true, |expiry| expiry > now));
+    if inner
+        .recent_request_mids
+        .iter()
+        .any(|(recent_mid, _)| *recent_mid == mid)
+    {
+        return true;
+    }
+    if capacity == 0 {
+        return false;
+    }
+    while inner.recent_request_mids.len() >= capacity {
+        inner.recent_request_mids.pop_front();
+    }
+    inner.recent_request_mids.push_back((mid, now.checked_add(lifetime)));
+    false
+}
+
+/// Returns the local address of the given session, or None if it cannot be represented as a
+/// [SocketAddr] (e.g., for Unix domain socket sessions).
+pub(crate) fn local_socket_addr<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<SocketAddr> {
+    // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+    unsafe {
+        coap_session_get_addr_local(session.inner_ref().raw_session)
+            .as_ref()
+            .and_then(|addr| CoapAddress::from(addr).to_socket_addrs().ok())
+            .and_then(|mut addrs| addrs.next())
+    }
+}
+
+/// Returns the remote address of the given session, or None if it cannot be represented as a
+/// [SocketAddr] (e.g., for Unix domain socket sessions).
+pub(crate) fn remote_socket_addr<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<SocketAddr> {
+    // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+    unsafe { raw_addr_remote(session.inner_ref().raw_session) }
+}
+
+/// Returns the current remote address of the given raw session, or None if it cannot be
+/// represented as a [SocketAddr].
+///
+/// # Safety
+/// The provided pointer must point to a valid raw session.
+pub(crate) unsafe fn raw_addr_remote(raw_session: *mut coap_session_t) -> Option<SocketAddr> {
+    coap_session_get_addr_remote(raw_session)
+        .as_ref()
+        .and_then(|addr| CoapAddress::from(addr).to_socket_addrs().ok())
+        .and_then(|mut addrs| addrs.next())
+}
+
+/// Returns whether the given raw session is managed by this wrapper, i.e., has app data that
+/// refers to a [CoapClientSession] or [CoapServerSession].
+///
+/// Raw sessions that are not managed by this wrapper may exist on contexts created using
+/// [CoapContext::from_raw()].
+///
+/// # Safety
+/// The provided pointer must point to a valid raw session.
+pub(crate) unsafe fn is_wrapped_raw_session(raw_session: *mut coap_session_t) -> bool {
+    !coap_session_get_app_data(raw_session).is_null()
+}
+
+/// Checks whether the remote address of the given session has changed since the last call to this
+/// function (or since the creation of the session).
+///
+/// Returns the old and new remote address if the address has changed.
+pub(crate) fn update_addr_remote<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<(SocketAddr, SocketAddr)> {
+    let mut inner = session.inner_mut();
+    // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+    let current = unsafe { raw_addr_remote(inner.raw_session) }?;
+    match inner.last_addr_remote.replace(current) {
+        Some(old) if old != current => Some((old, current)),
+        _ => None,
+    }
+}
+
+/// Adds the given addresses to the set of addresses known to belong to the peer of the given
+/// session (see [CoapResponseAddressPolicy::AcceptKnownAlternatives]).
+pub(crate) fn add_known_peer_addrs<'a, S: CoapSessionInnerProvider<'a>>(session: &S, addrs: &[SocketAddr]) {
+    let mut inner = session.inner_mut();
+    for addr in addrs {
+        if !inner.known_peer_addrs.contains(addr) {
+            inner.known_peer_addrs.push(*addr);
+        }
+    }
+}
+
+/// Checks whether a response received on the given session from its current remote address should
+/// be accepted according to the session's [CoapResponseAddressPolicy].
+///
+/// Returns the expected and actual address alongside the decision if the address differs from the
+/// expected one, None otherwise.
+fn check_response_addr<'a, S: CoapSessionInnerProvider<'a>>(session: &S) -> Option<(SocketAddr, SocketAddr, bool)> {
+    let mut inner = session.inner_mut();
+    // SAFETY: Provided session pointer being valid is an invariant of CoapSessionInner
+    let actual = unsafe { raw_addr_remote(inner.raw_session) }?;
+    let expected = *inner.known_peer_addrs.first()?;
+    if actual == expected {
+        return None;
+    }
+    inner.response_addr_mismatches += 1;
+    let accepted = match inner.response_addr_policy {
+        CoapResponseAddressPolicy::Strict => false,
+        CoapResponseAddressPolicy::AcceptKnownAlternatives => inner.known_peer_addrs.contains(&actual),
+        CoapResponseAddressPolicy::Permissive => true,
+    };
+    Some((expected, actual, accepted))
 }
 
 /// A handle returned by CoAP sessions upon sending a request.
@@ -530,15 +2944,26 @@ impl CoapSessionInner<'_> {
 pub struct CoapRequestHandle {
     _mid: CoapMessageId,
     token: CoapToken,
+    expects_response: bool,
 }
 
 impl CoapRequestHandle {
-    fn new<T: Into<Box<[u8]>>>(mid: CoapMessageId, token: T) -> CoapRequestHandle {
+    fn new<T: Into<Box<[u8]>>>(mid: CoapMessageId, token: T, expects_response: bool) -> CoapRequestHandle {
         CoapRequestHandle {
             _mid: mid,
             token: token.into(),
+            expects_response,
         }
     }
+
+    /// Returns whether a response to the request is expected.
+    ///
+    /// This is false if the request suppressed all responses using the No-Response option
+    /// ([CoapNoResponse::ALL]), in which case the request is complete as soon as it has been sent
+    /// and [CoapSessionCommon::poll_handle()] never returns any responses.
+    pub fn expects_response(&self) -> bool {
+        self.expects_response
+    }
 }
 
 // This is fine, we don't read the C-type struct, we return it.
@@ -549,18 +2974,66 @@ pub(crate) unsafe extern "C" fn session_response_handler(
     received: *const coap_pdu_t,
     _id: coap_mid_t,
 ) -> coap_response_t {
-    let mut session = CoapSession::from_raw(session);
-    let client = session.borrow_mut();
-    // First check if the token is actually one we are currently waiting for.
-    let raw_token = coap_pdu_get_token(received);
-    let token: CoapToken = CoapToken::from(std::slice::from_raw_parts(raw_token.s, raw_token.length));
-    if !client.is_waiting_for_token(&token) {
-        return coap_response_t::COAP_RESPONSE_FAIL;
-    }
-    if let Ok(message) = CoapMessage::from_raw_pdu(received).and_then(CoapResponse::from_message) {
-        client.add_response(message);
-        coap_response_t::COAP_RESPONSE_OK
-    } else {
-        coap_response_t::COAP_RESPONSE_FAIL
-    }
+    catch_session_callback_panic(session, coap_response_t::COAP_RESPONSE_FAIL, || {
+        let raw_session = session;
+        if !is_wrapped_raw_session(raw_session) {
+            // Responses for raw sessions that are not managed by this wrapper are ignored (see
+            // CoapContext::from_raw()).
+            return coap_response_t::COAP_RESPONSE_OK;
+        }
+        let mut session = CoapSession::from_raw(raw_session);
+        if let Some((expected, actual, accepted)) = check_response_addr(&session) {
+            // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+            let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+            context.handle_response_address_mismatch(session.clone(), expected, actual, accepted);
+            if !accepted {
+                return coap_response_t::COAP_RESPONSE_FAIL;
+            }
+        }
+        inspect_pdu(&session, CoapPduDirection::Received, received);
+        count_dispatched_pdu(&session);
+        if let Some(len) = truncated_datagram_len(session.proto(), received) {
+            // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+            let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+            context.handle_truncated_datagram(session, len);
+            return coap_response_t::COAP_RESPONSE_FAIL;
+        }
+        // Piggybacked and separate responses imply that the request has been acknowledged.
+        let raw_token = coap_pdu_get_token(received);
+        let received_token = std::slice::from_raw_parts(raw_token.s, raw_token.length);
+        let acked_mid = (CoapMessageType::from(coap_pdu_get_type(received)) == CoapMessageType::Ack)
+            .then(|| coap_pdu_get_mid(received));
+        if let Some(mid) = acked_mid {
+            sample_round_trip_time(&session, mid);
+        }
+        if dequeue_sent_message(&session, acked_mid, received_token) {
+            // SAFETY: Pointer is always valid as long as there is no bug in libcoap.
+            let context = CoapContext::restore_from_raw(coap_session_get_context(raw_session));
+            context.handle_send_queue_drained(session.clone());
+        }
+        let client = session.borrow_mut();
+        // Responses to requests that have timed out are rejected.
+        client.expire_timed_out_requests();
+        client.fail_aborted_requests();
+        // First check if the token is actually one we are currently waiting for.
+        let token: CoapToken = CoapToken::from(received_token);
+        if !client.is_waiting_for_token(&token) {
+            return coap_response_t::COAP_RESPONSE_FAIL;
+        }
+        let Ok(message) = CoapMessage::from_raw_pdu(received) else {
+            return coap_response_t::COAP_RESPONSE_FAIL;
+        };
+        let sent_at = client.inner_mut().request_sent_at.remove(&token);
+        let transfer = transfer_stats(&*client, &message, sent_at);
+        if let Ok(mut message) = CoapResponse::from_message(message) {
+            message.set_transfer_stats(Some(transfer));
+            record_stats(&*client, |stats| stats.record_received_response(&transfer));
+            client.add_response(message);
+            // The response may have freed a slot for a queued request.
+            send_queued_requests(&*client);
+            coap_response_t::COAP_RESPONSE_OK
+        } else {
+            coap_response_t::COAP_RESPONSE_FAIL
+        }
+    })
 }
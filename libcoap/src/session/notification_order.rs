@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/notification_order.rs - Detection of reordered notifications of observations.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::protocol::Observe;
+
+/// Number of distinct values of the Observe option (which has up to 24 bits).
+const OBSERVE_MODULUS: u32 = 1 << 24;
+/// Half of [OBSERVE_MODULUS], i.e., the largest distance by which a newer Observe value may be ahead
+/// of an older one.
+const OBSERVE_WINDOW: u32 = 1 << 23;
+/// Time after which a notification is always considered to be fresher than the freshest one
+/// received so far, regardless of their Observe values
+/// ([RFC 7641, Section 3.4](https://datatracker.ietf.org/doc/html/rfc7641#section-3.4)).
+pub const NOTIFICATION_FRESHNESS_TIMEOUT: Duration = Duration::from_secs(128);
+
+/// Returns whether a notification with the Observe value `new` received at `new_time` is fresher
+/// than one with the Observe value `old` received at `old_time`, following the rule of
+/// [RFC 7641, Section 3.4](https://datatracker.ietf.org/doc/html/rfc7641#section-3.4).
+///
+/// Observe values are compared modulo 2^24, i.e., bits above the lowest 24 are ignored and values
+/// that wrapped around are considered to be newer than the ones shortly before the wraparound.
+/// Notifications that are received more than [NOTIFICATION_FRESHNESS_TIMEOUT] after the older one
+/// are always fresher, as the sequence of the server may have been restarted in the meantime.
+pub fn is_fresher_notification(old: Observe, old_time: Instant, new: Observe, new_time: Instant) -> bool {
+    let (old, new) = (old % OBSERVE_MODULUS, new % OBSERVE_MODULUS);
+    (old < new && new - old < OBSERVE_WINDOW)
+        || (old > new && old - new > OBSERVE_WINDOW)
+        || new_time.saturating_duration_since(old_time) > NOTIFICATION_FRESHNESS_TIMEOUT
+}
+
+/// Tracks the freshest notification of an observation in order to detect stale (i.e., reordered
+/// or duplicated) notifications, see
+/// [CoapSessionCommon::set_ordered_notifications()](crate::session::CoapSessionCommon::set_ordered_notifications).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoapNotificationOrder {
+    freshest: Option<(Observe, Instant)>,
+    stale: u64,
+}
+
+impl CoapNotificationOrder {
+    /// Creates a tracker that has not seen any notifications yet.
+    pub fn new() -> CoapNotificationOrder {
+        CoapNotificationOrder::default()
+    }
+
+    /// Checks a notification with the given Observe value that was received at `now`.
+    ///
+    /// Returns true (and records the notification as the freshest one) if it is fresher than all
+    /// notifications checked before (see [is_fresher_notification()]), or false (and increments
+    /// [CoapNotificationOrder::stale_count()]) if it is stale.
+    pub fn check(&mut self, observe: Observe, now: Instant) -> bool {
+        let fresh = self.freshest.map_or(true, |(old, old_time)| {
+            is_fresher_notification(old, old_time, observe, now)
+        });
+        if fresh {
+            self.freshest = Some((observe % OBSERVE_MODULUS, now));
+        } else {
+            self.stale += 1;
+        }
+        fresh
+    }
+
+    /// Returns the Observe value (modulo 2^24) of the freshest notification, if any.
+    pub fn freshest(&self) -> Option<Observe> {
+        self.freshest.map(|(observe, _)| observe)
+    }
+
+    /// Returns the number of notifications that were considered to be stale.
+    pub fn stale_count(&self) -> u64 {
+        self.stale
+    }
+
+    /// Forgets the freshest notification, e.g., because the observation was registered again and
+    /// the server may use a new sequence of Observe values.
+    ///
+    /// The number of stale notifications is kept.
+    pub(crate) fn restart(&mut self) {
+        self.freshest = None;
+    }
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/pool.rs - Pool of client-side sessions that are reused for requests to the same peer.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    session::{CoapClientSession, CoapSessionCommon},
+    types::CoapProtocol,
+};
+
+/// Default maximum number of sessions held by a [SessionPool].
+pub(crate) const DEFAULT_SESSION_POOL_CAPACITY: usize = 64;
+
+/// Identifies the peer, transport and credentials of a pooled session.
+///
+/// Sessions are only reused for requests whose key is equal, i.e., sessions using different
+/// transports (e.g., UDP and DTLS) or credentials for the same address are kept separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SessionPoolKey {
+    pub(crate) addr: SocketAddr,
+    pub(crate) proto: CoapProtocol,
+    /// Identifier of the client-side crypto context of the session (see
+    /// [ClientCryptoContext::credentials_id()](crate::crypto::ClientCryptoContext::credentials_id)),
+    /// or None for unencrypted sessions.
+    pub(crate) credentials: Option<usize>,
+}
+
+/// A session stored in a [SessionPool].
+#[derive(Debug)]
+struct PooledSession<'a> {
+    key: SessionPoolKey,
+    session: CoapClientSession<'a>,
+    /// Time at which the session was last handed out by the pool.
+    last_used: Instant,
+    /// Whether the session was reported as closed by libcoap and should be removed from the pool.
+    closed: bool,
+}
+
+impl PooledSession<'_> {
+    /// Returns whether the session can be handed out for further requests.
+    fn is_usable(&self) -> bool {
+        !self.closed && self.session.connect_error().is_none()
+    }
+}
+
+/// Pool of client-side sessions of a context, see
+/// [CoapContext::get_or_connect()](crate::CoapContext::get_or_connect).
+///
+/// Sessions are evicted in least recently used order once the capacity is exceeded. Evicted
+/// sessions are only dropped by the pool, i.e., they remain usable for as long as the application
+/// holds clones of them.
+#[derive(Debug)]
+pub(crate) struct SessionPool<'a> {
+    capacity: usize,
+    idle_timeout: Option<Duration>,
+    /// Pooled sessions, least recently used first.
+    sessions: VecDeque<PooledSession<'a>>,
+}
+
+impl Default for SessionPool<'_> {
+    fn default() -> Self {
+        SessionPool {
+            capacity: DEFAULT_SESSION_POOL_CAPACITY,
+            idle_timeout: None,
+            sessions: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a> SessionPool<'a> {
+    /// Returns the maximum number of sessions held by this pool.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of sessions held by this pool, evicting sessions if required.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the time after which unused sessions are removed from this pool.
+    pub(crate) fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Sets the time after which unused sessions are removed from this pool (None to keep them
+    /// until they are evicted or closed).
+    pub(crate) fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Returns the number of sessions currently held by this pool.
+    pub(crate) fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns a usable session for any of the given keys (if one exists), marking it as most
+    /// recently used.
+    pub(crate) fn get(&mut self, keys: &[SessionPoolKey]) -> Option<CoapClientSession<'a>> {
+        let index = self
+            .sessions
+            .iter()
+            .position(|entry| entry.is_usable() && keys.contains(&entry.key))?;
+        let mut entry = self.sessions.remove(index)?;
+        entry.last_used = Instant::now();
+        let session = entry.session.clone();
+        self.sessions.push_back(entry);
+        Some(session)
+    }
+
+    /// Adds the given newly created session as the most recently used session, evicting sessions
+    /// if the capacity is exceeded.
+    pub(crate) fn insert(&mut self, key: SessionPoolKey, session: CoapClientSession<'a>) {
+        self.sessions.push_back(PooledSession {
+            key,
+            session,
+            last_used: Instant::now(),
+            closed: false,
+        });
+        self.evict();
+    }
+
+    /// Marks the given session as closed, so that it is no longer handed out and removed from the
+    /// pool by the next call to [SessionPool::remove_stale()].
+    ///
+    /// The session is not dropped right away, as this function is called while libcoap processes
+    /// the session.
+    pub(crate) fn mark_closed(&mut self, session: &CoapClientSession<'a>) {
+        for entry in self.sessions.iter_mut().filter(|entry| entry.session == *session) {
+            entry.closed = true;
+        }
+    }
+
+    /// Removes sessions that are closed, have failed, or have neither been used nor had pending
+    /// requests for longer than the idle timeout.
+    pub(crate) fn remove_stale(&mut self) {
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout;
+        self.sessions.retain(|entry| {
+            let idle = idle_timeout.is_some_and(|timeout| now.duration_since(entry.last_used) >= timeout)
+                && entry.session.pending_requests() == 0;
+            entry.is_usable() && !idle
+        });
+    }
+
+    /// Removes all sessions from this pool.
+    pub(crate) fn clear(&mut self) {
+        self.sessions.clear();
+    }
+
+    /// Evicts the least recently used sessions until the capacity is no longer exceeded.
+    fn evict(&mut self) {
+        while self.sessions.len() > self.capacity {
+            self.sessions.pop_front();
+        }
+    }
+}
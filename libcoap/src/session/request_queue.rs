@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/request_queue.rs - Queueing of requests that exceed the outstanding request limit.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::Duration;
+
+use crate::{message::request::CoapRequest, protocol::CoapToken, session::response_cache::ResponseCacheKey};
+
+/// Limit for the number of requests a session has outstanding at the same time, see
+/// [CoapSessionCommon::set_max_outstanding_requests()](crate::session::CoapSessionCommon::set_max_outstanding_requests).
+///
+/// Requests that would exceed the limit are queued by the wrapper and sent in the order they were
+/// sent using [CoapSessionCommon::send_request()](crate::session::CoapSessionCommon::send_request)
+/// once outstanding requests have received their (first) response, have failed or have had their
+/// handles removed.
+///
+/// For unreliable transports (UDP/DTLS), libcoap additionally delays confirmable requests according
+/// to NSTART ([RFC 7252, Section 4.7](https://datatracker.ietf.org/doc/html/rfc7252#section-4.7)),
+/// which only considers unacknowledged messages. Reliable transports (TCP/TLS/WebSockets) have no
+/// such mechanism, so this limit is the only way to restrict the number of requests a peer has to
+/// handle concurrently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutstandingRequestLimit {
+    /// Maximum number of requests that await their first response at the same time.
+    ///
+    /// Values of 0 are treated like 1.
+    pub max_outstanding: usize,
+    /// Maximum number of requests that may be queued, further requests fail with
+    /// [MessageConversionError::RequestQueueFull](crate::error::MessageConversionError::RequestQueueFull)
+    /// (unlimited by default).
+    pub max_queued: usize,
+    /// Whether the time a request spends queued counts towards its timeout (see
+    /// [CoapRequest::set_timeout()]), true by default.
+    ///
+    /// If false, the timeout only starts once the request has been sent.
+    pub timeout_includes_queueing: bool,
+}
+
+impl OutstandingRequestLimit {
+    /// Creates a limit of the given number of outstanding requests, using the defaults for all
+    /// other settings.
+    pub fn new(max_outstanding: usize) -> OutstandingRequestLimit {
+        OutstandingRequestLimit {
+            max_outstanding,
+            max_queued: usize::MAX,
+            timeout_includes_queueing: true,
+        }
+    }
+}
+
+/// A request that waits for a free slot before it is sent, see [OutstandingRequestLimit].
+///
+/// The request already has its token, message ID and message type.
+#[derive(Debug)]
+pub(crate) struct QueuedRequest {
+    pub(crate) token: CoapToken,
+    pub(crate) request: CoapRequest,
+    /// Key and revalidation state of the request in the response cache of the session (if any).
+    pub(crate) cache_key: Option<(ResponseCacheKey, bool)>,
+    /// Timeout that starts once the request is sent, if the time spent queued does not count
+    /// towards it (see [OutstandingRequestLimit::timeout_includes_queueing]).
+    pub(crate) timeout: Option<Duration>,
+}
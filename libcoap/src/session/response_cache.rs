@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/response_cache.rs - Client-side cache for responses to GET requests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{collections::VecDeque, time::Instant};
+
+use crate::{
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode, ContentFormat, ETag, MaxAge},
+};
+
+/// Identifies the requests a cached response can be reused for.
+///
+/// Only requests that have no options affecting the representation other than their URI and
+/// Accept option are cached (see [ResponseCacheKey::for_request()]), so these fully determine the
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResponseCacheKey {
+    uri: String,
+    accept: Option<ContentFormat>,
+}
+
+impl ResponseCacheKey {
+    /// Returns the cache key for the given request, or None if responses to this request must not
+    /// be cached.
+    ///
+    /// Requests are cacheable if they are GET requests without payload that neither bypass the
+    /// cache nor use Observe, ETag or No-Response options (which are handled by the application
+    /// itself).
+    pub(crate) fn for_request(request: &CoapRequest) -> Option<ResponseCacheKey> {
+        if request.code() != CoapMessageCode::Request(CoapRequestCode::Get)
+            || request.bypasses_cache()
+            || request.observe().is_some()
+            || request.etag().is_some()
+            || request.no_response().is_some()
+            || request.data().is_some()
+        {
+            return None;
+        }
+        Some(ResponseCacheKey {
+            uri: request.uri().to_string(),
+            accept: request.accept(),
+        })
+    }
+}
+
+/// Result of looking up a request in a [ResponseCache].
+#[derive(Debug)]
+pub(crate) enum ResponseCacheLookup {
+    /// A fresh response is cached, which can be returned without contacting the server.
+    Fresh(CoapResponse),
+    /// A stale response with the given ETag is cached, which can be revalidated.
+    Stale(ETag),
+    /// No usable response is cached.
+    Miss,
+}
+
+/// A response stored in a [ResponseCache].
+#[derive(Debug)]
+struct CachedResponse {
+    response: CoapResponse,
+    /// Time after which the response is stale, i.e., needs to be revalidated (None if this time
+    /// can't be represented).
+    expiry: Option<Instant>,
+}
+
+/// Client-side cache for responses to GET requests, see
+/// [CoapClientSession::set_response_cache_capacity()](crate::session::CoapClientSession::set_response_cache_capacity).
+///
+/// Entries are evicted in least recently used order once the capacity is exceeded. As the cache
+/// is meant to hold a small number of entries, they are looked up linearly.
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    capacity: usize,
+    /// Cached responses, least recently used first.
+    entries: VecDeque<(ResponseCacheKey, CachedResponse)>,
+}
+
+impl ResponseCache {
+    /// Creates an empty cache holding at most `capacity` responses.
+    pub(crate) fn new(capacity: usize) -> ResponseCache {
+        ResponseCache {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Returns the maximum number of responses held by this cache.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of responses held by this cache, evicting entries if required.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Removes all responses from this cache.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the number of bytes used by the cached responses (see
+    /// [CoapMemoryReport::cached_representations](crate::CoapMemoryReport::cached_representations)).
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(key, entry)| {
+                std::mem::size_of::<(ResponseCacheKey, CachedResponse)>()
+                    + key.uri.len()
+                    + entry.response.data().map_or(0, <[u8]>::len)
+            })
+            .sum()
+    }
+
+    /// Looks up the response for the given key at the current time `now`, marking it as most
+    /// recently used.
+    ///
+    /// The Max-Age option of returned fresh responses is set to their remaining freshness
+    /// lifetime.
+    pub(crate) fn lookup(&mut self, key: &ResponseCacheKey, now: Instant) -> ResponseCacheLookup {
+        let Some(entry) = self.touch(key) else {
+            return ResponseCacheLookup::Miss;
+        };
+        if entry.expiry.map_or(true, |expiry| expiry > now) {
+            let mut response = entry.response.clone();
+            let remaining = entry.expiry.map_or(MaxAge::MAX, |expiry| {
+                MaxAge::try_from(expiry.duration_since(now).as_secs()).unwrap_or(MaxAge::MAX)
+            });
+            response.set_max_age(Some(remaining));
+            // The response was not received for this request.
+            response.set_transfer_stats(None);
+            return ResponseCacheLookup::Fresh(response);
+        }
+        match entry.response.etag() {
+            Some(etag) => ResponseCacheLookup::Stale(etag.clone()),
+            None => ResponseCacheLookup::Miss,
+        }
+    }
+
+    /// Updates the cache using a response received for a request with the given key and returns
+    /// the response that should be provided to the application.
+    ///
+    /// If `revalidating` is true, the request contained the ETag of the cached (stale) response.
+    /// In that case, a 2.03 (Valid) response with the same (or no) ETag refreshes the cached
+    /// response, which is returned instead (with the message type, message ID, token, Max-Age and
+    /// transfer metadata of the received response).
+    ///
+    /// `now` is the current time, from which the freshness lifetime of the response is measured.
+    pub(crate) fn update(
+        &mut self,
+        key: ResponseCacheKey,
+        revalidating: bool,
+        response: CoapResponse,
+        now: Instant,
+    ) -> CoapResponse {
+        match response.response_code() {
+            Some(CoapResponseCode::Content) => {
+                let expiry = now.checked_add(response.freshness_lifetime());
+                // Responses that are stale right away are only worth keeping if they can be
+                // revalidated.
+                if response.max_age() != Some(0) || response.etag().is_some() {
+                    self.insert(key, response.clone(), expiry);
+                } else {
+                    self.remove(&key);
+                }
+                response
+            },
+            Some(CoapResponseCode::Valid) if revalidating => {
+                let Some(entry) = self
+                    .touch(&key)
+                    .filter(|entry| response.etag().is_none() || entry.response.etag() == response.etag())
+                else {
+                    return response;
+                };
+                entry.expiry = now.checked_add(response.freshness_lifetime());
+                let mut cached = entry.response.clone();
+                cached.set_type_(response.type_());
+                cached.set_mid(response.mid());
+                cached.set_token(response.token().map(Box::<[u8]>::from));
+                cached.set_max_age(response.max_age());
+                cached.set_transfer_stats(response.transfer_stats());
+                cached
+            },
+            _ => {
+                // The representation has changed (or the resource no longer exists).
+                self.remove(&key);
+                response
+            },
+        }
+    }
+
+    /// Returns the entry for the given key (if any) after marking it as most recently used.
+    fn touch(&mut self, key: &ResponseCacheKey) -> Option<&mut CachedResponse> {
+        let index = self.entries.iter().position(|(entry_key, _)| entry_key == key)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry);
+        self.entries.back_mut().map(|(_, entry)| entry)
+    }
+
+    /// Stores the given response as the most recently used entry, replacing any previous
+    /// response for the same key.
+    fn insert(&mut self, key: ResponseCacheKey, response: CoapResponse, expiry: Option<Instant>) {
+        self.remove(&key);
+        self.entries.push_back((key, CachedResponse { response, expiry }));
+        self.evict();
+    }
+
+    /// Removes the response for the given key (if any).
+    fn remove(&mut self, key: &ResponseCacheKey) {
+        self.entries.retain(|(entry_key, _)| entry_key != key);
+    }
+
+    /// Evicts the least recently used entries until the capacity is no longer exceeded.
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
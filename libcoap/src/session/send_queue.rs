@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/send_queue.rs - Tracking of messages queued for transmission on a session.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{session::CoapRetransmission, types::CoapMessageId};
+
+/// Limits for the amount of data queued for transmission on a session, see
+/// [CoapContext::set_send_queue_limits()](crate::CoapContext::set_send_queue_limits).
+///
+/// Once the number of queued bytes of a session reaches the high-water mark, the session is
+/// considered congested: further requests and notifications are refused until the number of
+/// queued bytes has dropped to the low-water mark, at which point
+/// [CoapEventHandler::handle_send_queue_drained()](crate::CoapEventHandler::handle_send_queue_drained)
+/// is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SendQueueLimits {
+    /// Number of queued bytes at which a session becomes congested.
+    pub high_water_mark: usize,
+    /// Number of queued bytes at which a congested session is considered drained again.
+    ///
+    /// Values larger than the high-water mark are treated like the high-water mark.
+    pub low_water_mark: usize,
+}
+
+impl SendQueueLimits {
+    /// Creates limits with the given high-water and low-water marks (in bytes).
+    pub fn new(high_water_mark: usize, low_water_mark: usize) -> SendQueueLimits {
+        SendQueueLimits {
+            high_water_mark,
+            low_water_mark,
+        }
+    }
+}
+
+/// A message that libcoap may still have to (re-)transmit.
+#[derive(Debug)]
+struct QueuedMessage {
+    mid: CoapMessageId,
+    token: Box<[u8]>,
+    /// Encoded size of the message.
+    size: usize,
+    /// Time of the most recent (re-)transmission of the message.
+    sent_at: Instant,
+    /// Number of times the message has been retransmitted (see
+    /// [SendQueue::record_retransmission()]).
+    retransmissions: u32,
+    /// Time after which libcoap has stopped retransmitting the message in any case (None if it
+    /// can not be represented).
+    expiry: Option<Instant>,
+    /// Value of the idle epoch of the context when the message was sent, see
+    /// [SendQueue::prune()].
+    epoch: u64,
+}
+
+/// Confirmable messages sent on a session using an unreliable transport that have not been
+/// acknowledged yet.
+///
+/// libcoap does not provide access to its retransmission queue (or the queue of messages delayed
+/// due to NSTART), so messages are tracked as they are passed to libcoap and removed once the
+/// wrapper learns that libcoap no longer holds them: when a piggybacked or separate response is
+/// received, when libcoap reports that the message was rejected or could not be delivered, once
+/// the context has no outstanding transmissions at all, or once the message would no longer be
+/// retransmitted.
+#[derive(Debug, Default)]
+pub(crate) struct SendQueue {
+    messages: VecDeque<QueuedMessage>,
+    bytes: usize,
+    /// Whether the high-water mark has been reached and the low-water mark has not been reached
+    /// since.
+    congested: bool,
+}
+
+impl SendQueue {
+    /// Returns the number of queued messages.
+    pub(crate) fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Returns the total size of the queued messages.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns whether the queue is congested, see [SendQueueLimits].
+    pub(crate) fn is_congested(&self) -> bool {
+        self.congested
+    }
+
+    /// Adds a message that has been sent at `sent_at` to the queue.
+    pub(crate) fn push(
+        &mut self,
+        mid: CoapMessageId,
+        token: &[u8],
+        size: usize,
+        sent_at: Instant,
+        expiry: Option<Instant>,
+        epoch: u64,
+    ) {
+        self.messages.push_back(QueuedMessage {
+            mid,
+            token: Box::from(token),
+            size,
+            sent_at,
+            retransmissions: 0,
+            expiry,
+            epoch,
+        });
+        self.bytes += size;
+    }
+
+    /// Marks the queue as congested if it has reached the high-water mark of the given limits,
+    /// returning whether the queue is congested.
+    pub(crate) fn update_congestion(&mut self, limits: Option<SendQueueLimits>) -> bool {
+        if limits.is_some_and(|limits| self.bytes >= limits.high_water_mark) {
+            self.congested = true;
+        }
+        self.congested
+    }
+
+    /// Returns the largest number of retransmissions of any queued message.
+    pub(crate) fn max_retransmissions(&self) -> u32 {
+        self.messages.iter().map(|v| v.retransmissions).max().unwrap_or(0)
+    }
+
+    /// Returns the round-trip time of the message with the given message ID, which has been
+    /// acknowledged at `now`, if it has not been retransmitted (as the acknowledgement of a
+    /// retransmitted message can not be attributed to one of its transmissions).
+    pub(crate) fn round_trip_time(&self, mid: CoapMessageId, now: Instant) -> Option<Duration> {
+        self.messages
+            .iter()
+            .find(|v| v.mid == mid && v.retransmissions == 0)
+            .map(|v| now.saturating_duration_since(v.sent_at))
+    }
+
+    /// Records that libcoap has retransmitted one of the queued messages at `now`, returning the
+    /// retransmission.
+    ///
+    /// libcoap does not report which message it retransmitted, so the message whose timeout
+    /// expires first is assumed: As timeouts are chosen between `ack_timeout` and
+    /// `ack_timeout * ack_random_factor` and doubled after each retransmission, this is exact as
+    /// long as only one message is outstanding at a time (NSTART = 1) and a best-effort guess
+    /// otherwise.
+    pub(crate) fn record_retransmission(&mut self, now: Instant, ack_timeout: Duration) -> Option<CoapRetransmission> {
+        let message = self.messages.iter_mut().min_by_key(|v| {
+            let timeout = ack_timeout.saturating_mul(1u32.checked_shl(v.retransmissions).unwrap_or(u32::MAX));
+            v.sent_at.checked_add(timeout)
+        })?;
+        message.retransmissions += 1;
+        let timeout = now.saturating_duration_since(message.sent_at);
+        message.sent_at = now;
+        Some(CoapRetransmission {
+            mid: message.mid,
+            token: message.token.clone(),
+            attempt: message.retransmissions,
+            timeout,
+        })
+    }
+
+    /// Removes the message with the given message ID (e.g., because it was acknowledged).
+    pub(crate) fn remove_mid(&mut self, mid: CoapMessageId) {
+        self.remove_where(|message| message.mid == mid);
+    }
+
+    /// Removes the messages with the given token (e.g., because a separate response to the
+    /// request was received, which implies that the request was acknowledged).
+    pub(crate) fn remove_token(&mut self, token: &[u8]) {
+        if !token.is_empty() {
+            self.remove_where(|message| *message.token == *token);
+        }
+    }
+
+    /// Removes messages that are no longer retransmitted at the current time `now`, as well as
+    /// messages that were sent before the context last had no outstanding transmissions (i.e.,
+    /// before the given idle epoch).
+    pub(crate) fn prune(&mut self, now: Instant, epoch: u64) {
+        self.remove_where(|message| message.epoch < epoch || message.expiry.is_some_and(|expiry| expiry <= now));
+    }
+
+    /// Returns whether the queue was congested and has now drained to the low-water mark of the
+    /// given limits, in which case it is no longer considered congested.
+    ///
+    /// If no limits are set, congested queues are always considered drained.
+    pub(crate) fn take_drained(&mut self, limits: Option<SendQueueLimits>) -> bool {
+        let drained = self.congested
+            && limits.map_or(true, |limits| {
+                self.bytes <= limits.low_water_mark.min(limits.high_water_mark)
+            });
+        if drained {
+            self.congested = false;
+        }
+        drained
+    }
+
+    fn remove_where(&mut self, mut f: impl FnMut(&QueuedMessage) -> bool) {
+        let mut removed = 0;
+        self.messages.retain(|message| {
+            let remove = f(message);
+            if remove {
+                removed += message.size;
+            }
+            !remove
+        });
+        self.bytes -= removed;
+    }
+}
@@ -10,12 +10,21 @@
 use std::cell::{Ref, RefMut};
 
 use libcoap_sys::{
-    coap_session_get_app_data, coap_session_get_type, coap_session_reference, coap_session_release,
-    coap_session_set_app_data, coap_session_t, coap_session_type_t,
+    coap_pdu_code_t, coap_pdu_init, coap_send, coap_session_get_app_data, coap_session_get_context,
+    coap_session_get_type, coap_session_max_pdu_size, coap_session_reference, coap_session_release,
+    coap_session_set_app_data, coap_session_set_type_client, coap_session_t, coap_session_type_t,
 };
 
-use super::{CoapSessionCommon, CoapSessionInner, CoapSessionInnerProvider};
-use crate::mem::{CoapFfiRcCell, DropInnerExclusively};
+#[cfg(feature = "async")]
+use super::{abandon_async_requests, release_async_requests};
+use super::{CoapSessionCloseReason, CoapSessionCommon, CoapSessionInner, CoapSessionInnerProvider};
+use crate::{
+    error::RequestPollError,
+    mem::{CoapFfiRcCell, DropInnerExclusively},
+    protocol::CoapMessageType,
+    transport::CoapEndpointHandle,
+    CoapContext,
+};
 
 impl DropInnerExclusively for CoapServerSession<'_> {
     fn drop_exclusively(self) {
@@ -26,7 +35,7 @@ impl DropInnerExclusively for CoapServerSession<'_> {
 }
 
 /// Representation of a server-side CoAP session.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CoapServerSession<'a> {
     /// Inner part of this server-side session
     /// A weak version of this reference is stored inside of the user/app data pointer in the
@@ -157,6 +166,134 @@ impl CoapServerSession<'_> {
     }
 }
 
+impl<'a> CoapServerSession<'a> {
+    /// Forcibly terminates this session, e.g., to get rid of a misbehaving client.
+    ///
+    /// The session is shut down in a way appropriate for its transport protocol:
+    /// - For reliable transports (TCP/TLS/WebSockets), a Release (for
+    ///   [CoapSessionCloseReason::Release]) or Abort (for [CoapSessionCloseReason::Abort])
+    ///   signaling message is sent to the peer before the connection is closed.
+    /// - For DTLS, a close_notify alert is sent to the peer.
+    /// - For UDP, the session is simply dropped. If the peer continues to send requests, a new
+    ///   session is created for it.
+    ///
+    /// Afterwards, sending messages on this session fails with
+    /// [MessageConversionError::SessionDisconnected](crate::error::MessageConversionError::SessionDisconnected),
+    /// requests on this session that were still awaiting responses fail with
+    /// [RequestPollError::SessionDisconnected], pending asynchronous requests of the peer are
+    /// abandoned (see [CoapAsyncHandle](crate::session::CoapAsyncHandle)) and further requests
+    /// received from the peer are no longer passed to request handlers.
+    ///
+    /// As this function may be called from within request handlers, the context releases the
+    /// underlying raw session at the end of the next call to
+    /// [CoapContext::do_io()](crate::CoapContext::do_io), calling
+    /// [CoapEventHandler::handle_session_closed()](crate::CoapEventHandler::handle_session_closed)
+    /// and [CoapEventHandler::handle_server_session_del()](crate::CoapEventHandler::handle_server_session_del)
+    /// for this session. libcoap frees the raw session as soon as no references to it remain.
+    /// Until then, requests received on it are answered with 5.03 (Service Unavailable), and
+    /// observations of the peer (which also refer to the raw session) are ended with a 5.03
+    /// notification the next time the observed resource changes.
+    /// Remaining handles to this session can still be used safely, but only refer to the closed
+    /// session.
+    ///
+    /// Calling this function on a session that has already been disconnected has no effect.
+    pub fn disconnect(&self, reason: CoapSessionCloseReason) {
+        if self.close_reason().is_some() {
+            return;
+        }
+        if self.proto().is_reliable() {
+            let code = match reason {
+                CoapSessionCloseReason::Release => coap_pdu_code_t::COAP_SIGNALING_CODE_RELEASE,
+                CoapSessionCloseReason::Abort => coap_pdu_code_t::COAP_SIGNALING_CODE_ABORT,
+            };
+            let raw_session = self.inner_ref().raw_session;
+            // SAFETY: Raw session is valid for the lifetime of this object, the PDU is freed by
+            // libcoap after sending it. If sending fails, the connection is closed nonetheless.
+            unsafe {
+                let raw_pdu = coap_pdu_init(
+                    CoapMessageType::Con.to_raw_pdu_type(),
+                    code,
+                    0,
+                    coap_session_max_pdu_size(raw_session),
+                );
+                if !raw_pdu.is_null() {
+                    coap_send(raw_session, raw_pdu);
+                }
+            }
+        }
+        {
+            let mut inner = self.inner_mut();
+            inner.close_reason = Some(reason);
+            inner.refuse_requests = true;
+            inner.fail_requests(RequestPollError::SessionDisconnected);
+        }
+        // libcoap may currently process the async states, they are freed once the session is
+        // released.
+        #[cfg(feature = "async")]
+        abandon_async_requests(self);
+    }
+
+    /// Returns the reason this session was disconnected for using
+    /// [CoapServerSession::disconnect()], or None if it has not been disconnected.
+    pub fn close_reason(&self) -> Option<CoapSessionCloseReason> {
+        self.inner_ref().close_reason
+    }
+
+    /// Returns the handle of the endpoint this session was created on, which can be used to
+    /// access the local address and protocol of the endpoint using
+    /// [CoapContext::with_endpoint()] (see [CoapContext::session_endpoint()]).
+    pub fn endpoint(&self) -> Option<CoapEndpointHandle> {
+        // SAFETY: The raw session is valid, and its context is valid for at least as long as the
+        // session.
+        let context = unsafe { CoapContext::restore_from_raw(coap_session_get_context(self.inner_ref().raw_session)) };
+        context.session_endpoint(self)
+    }
+
+    /// Returns whether handles to this session exist apart from this one (e.g., handles held by
+    /// the application).
+    pub(crate) fn has_other_handles(&self) -> bool {
+        self.inner.strong_count() > 1
+    }
+
+    /// Releases the reference the context holds to this disconnected session, causing libcoap to
+    /// shut down and free the raw session once all other references are gone.
+    ///
+    /// # Safety
+    /// Must not be called while libcoap is processing this session, i.e., from within a callback
+    /// called by libcoap. This session must be the handle stored in the context for this session.
+    pub(crate) unsafe fn release_disconnected(self) {
+        let raw_session = self.inner_ref().raw_session;
+        // Server-side sessions are only freed by libcoap once they are idle, while client-side
+        // sessions are freed as soon as their reference counter reaches zero. Converting the
+        // session increments the reference counter, which is released again below.
+        if coap_session_set_type_client(raw_session) == 0 {
+            return;
+        }
+        // Pending asynchronous requests would otherwise keep the raw session alive.
+        #[cfg(feature = "async")]
+        release_async_requests(&self);
+        // Remaining handles must no longer be restored from the raw session, as it is no longer
+        // a server-side session (and events for it are therefore ignored from now on).
+        self.inner.borrow_mut().detach_raw();
+        std::mem::drop(self);
+        coap_session_release(raw_session);
+    }
+}
+
+impl Clone for CoapServerSession<'_> {
+    fn clone(&self) -> Self {
+        if self.ref_counted {
+            // SAFETY: raw_session is always valid for the lifetime of this object, the reference
+            // is released again when the clone is dropped.
+            unsafe { coap_session_reference(self.inner.borrow().inner.raw_session) };
+        }
+        CoapServerSession {
+            inner: self.inner.clone(),
+            ref_counted: self.ref_counted,
+        }
+    }
+}
+
 impl<'a> Drop for CoapServerSession<'a> {
     fn drop(&mut self) {
         let raw_session = self.inner.borrow_mut().inner.raw_session;
@@ -184,21 +321,30 @@ impl<'a> CoapSessionInnerProvider<'a> for CoapServerSession<'a> {
 impl<'a, T: CoapSessionCommon<'a>> PartialEq<T> for CoapServerSession<'_> {
     fn eq(&self, other: &T) -> bool {
         // SAFETY: Pointers are only compared, never accessed.
-        self.if_index() == other.if_index()
-            && unsafe { self.raw_session() == other.raw_session() }
-            && self.addr_local() == other.addr_local()
-            && self.addr_remote() == other.addr_remote()
+        // The addresses are not compared, as they are the same for identical raw sessions (and
+        // can't be represented as a SocketAddr for Unix domain socket sessions).
+        self.if_index() == other.if_index() && unsafe { self.raw_session() == other.raw_session() }
     }
 }
 
 impl Eq for CoapServerSession<'_> {}
 
-impl Drop for CoapServerSessionInner<'_> {
-    fn drop(&mut self) {
+impl CoapServerSessionInner<'_> {
+    /// Removes the reference to this session from the app data of the raw session (if it has not
+    /// been removed already, see [CoapServerSession::release_disconnected()]).
+    fn detach_raw(&mut self) {
         unsafe {
             let app_data = coap_session_get_app_data(self.inner.raw_session);
-            assert!(!app_data.is_null());
-            std::mem::drop(CoapFfiRcCell::<CoapServerSessionInner>::raw_ptr_to_weak(app_data));
+            if !app_data.is_null() {
+                std::mem::drop(CoapFfiRcCell::<CoapServerSessionInner>::raw_ptr_to_weak(app_data));
+                coap_session_set_app_data(self.inner.raw_session, std::ptr::null_mut());
+            }
         }
     }
 }
+
+impl Drop for CoapServerSessionInner<'_> {
+    fn drop(&mut self) {
+        self.detach_raw();
+    }
+}
@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session/transmission.rs - Observation of retransmissions and round-trip times of sessions.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::Duration;
+
+use crate::{protocol::CoapToken, types::CoapMessageId};
+
+/// Gain of the smoothed round-trip time
+/// ([RFC 6298, Section 2](https://datatracker.ietf.org/doc/html/rfc6298#section-2)).
+const ALPHA: f64 = 1.0 / 8.0;
+/// Gain of the round-trip time variation.
+const BETA: f64 = 1.0 / 4.0;
+/// Factor the round-trip time variation is multiplied with for the retransmission timeout.
+///
+/// This is the value used by the strong estimator of CoCoA
+/// ([draft-ietf-core-cocoa, Section 4.2.1](https://datatracker.ietf.org/doc/html/draft-ietf-core-cocoa#section-4.2.1)).
+const K: f64 = 4.0;
+
+/// Parts of the congestion control of the linked libcoap version that can be configured or
+/// observed, see [transmission_capabilities()].
+///
+/// libcoap implements the retransmission scheme of
+/// [RFC 7252, Section 4.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.2): The initial
+/// timeout of a confirmable message is chosen randomly between ACK_TIMEOUT and
+/// ACK_TIMEOUT * ACK_RANDOM_FACTOR and doubled after each retransmission.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CoapTransmissionCapabilities {
+    initial_rto: bool,
+    backoff_factor: bool,
+    rto_state: bool,
+}
+
+impl CoapTransmissionCapabilities {
+    /// Returns whether the initial retransmission timeout can be set to a fixed value (see
+    /// [CoapSessionCommon::set_initial_rto()](crate::session::CoapSessionCommon::set_initial_rto)).
+    pub fn initial_rto_supported(&self) -> bool {
+        self.initial_rto
+    }
+
+    /// Returns whether the factor by which the retransmission timeout is increased after each
+    /// retransmission can be configured.
+    pub fn backoff_factor_supported(&self) -> bool {
+        self.backoff_factor
+    }
+
+    /// Returns whether libcoap provides access to the retransmission timeouts it uses for
+    /// outstanding messages.
+    ///
+    /// If not, the timeouts reported in [CoapRetransmission]s are the time spans the wrapper
+    /// observed between transmissions, and [CoapTransmissionState::rto_estimate] is computed by the
+    /// wrapper.
+    pub fn rto_state_supported(&self) -> bool {
+        self.rto_state
+    }
+}
+
+/// Returns which parts of the congestion control of the linked libcoap version can be configured
+/// or observed.
+pub fn transmission_capabilities() -> CoapTransmissionCapabilities {
+    CoapTransmissionCapabilities {
+        // A fixed initial timeout is achieved by setting ACK_RANDOM_FACTOR to 1.
+        initial_rto: true,
+        // libcoap always doubles the timeout and keeps the timeouts of its retransmission queue
+        // private.
+        backoff_factor: false,
+        rto_state: false,
+    }
+}
+
+/// A retransmission of a confirmable message, see
+/// [CoapEventHandler::handle_retransmission()](crate::CoapEventHandler::handle_retransmission).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CoapRetransmission {
+    /// Message ID of the retransmitted message.
+    pub mid: CoapMessageId,
+    /// Token of the retransmitted message.
+    pub token: CoapToken,
+    /// Number of this retransmission (1 for the first one).
+    pub attempt: u32,
+    /// Timeout that elapsed before the message was retransmitted, i.e., the time since its
+    /// previous transmission.
+    pub timeout: Duration,
+}
+
+/// Estimate of the round-trip time of a session, see [CoapTransmissionState].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CoapRtoEstimate {
+    /// Smoothed round-trip time.
+    pub srtt: Duration,
+    /// Round-trip time variation.
+    pub rttvar: Duration,
+    /// Retransmission timeout derived from the round-trip time (SRTT + 4 * RTTVAR).
+    pub rto: Duration,
+    /// Number of round-trip time samples the estimate is based on.
+    pub samples: u64,
+}
+
+/// Retransmission state of a session, see
+/// [CoapSessionCommon::transmission_state()](crate::session::CoapSessionCommon::transmission_state).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CoapTransmissionState {
+    /// Estimate of the round-trip time computed from the acknowledgements of messages that were
+    /// not retransmitted (None if no such acknowledgement has been received yet).
+    ///
+    /// The estimate follows the strong estimator of CoCoA
+    /// ([draft-ietf-core-cocoa](https://datatracker.ietf.org/doc/html/draft-ietf-core-cocoa)) and
+    /// is only provided for observation, libcoap does not use it for its retransmissions.
+    pub rto_estimate: Option<CoapRtoEstimate>,
+    /// Number of confirmable messages that libcoap may still have to retransmit (see
+    /// [CoapSessionCommon::queued_pdus()](crate::session::CoapSessionCommon::queued_pdus)).
+    pub outstanding: usize,
+    /// Largest number of retransmissions of any of the outstanding messages, i.e., the number of
+    /// times the timeout of the most backed-off message has been doubled.
+    pub backoff: u32,
+}
+
+/// Estimator for the round-trip time of a session, see [CoapTransmissionState::rto_estimate].
+#[derive(Debug, Default)]
+pub(crate) struct RtoEstimator {
+    estimate: Option<CoapRtoEstimate>,
+}
+
+impl RtoEstimator {
+    /// Returns the current estimate (if any).
+    pub(crate) fn estimate(&self) -> Option<CoapRtoEstimate> {
+        self.estimate
+    }
+
+    /// Updates the estimate with the given round-trip time sample (see
+    /// [RFC 6298, Section 2](https://datatracker.ietf.org/doc/html/rfc6298#section-2)).
+    pub(crate) fn add_sample(&mut self, rtt: Duration) {
+        let rtt = rtt.as_secs_f64();
+        let (srtt, rttvar, samples) = match self.estimate {
+            None => (rtt, rtt / 2.0, 1),
+            Some(estimate) => {
+                let (srtt, rttvar) = (estimate.srtt.as_secs_f64(), estimate.rttvar.as_secs_f64());
+                let rttvar = (1.0 - BETA) * rttvar + BETA * (srtt - rtt).abs();
+                ((1.0 - ALPHA) * srtt + ALPHA * rtt, rttvar, estimate.samples + 1)
+            },
+        };
+        let duration = |secs: f64| Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX);
+        self.estimate = Some(CoapRtoEstimate {
+            srtt: duration(srtt),
+            rttvar: duration(rttvar),
+            rto: duration(srtt + K * rttvar),
+            samples,
+        });
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * startup.rs - Initialization and teardown of libcoap's global state.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Initialization and teardown of libcoap's global state.
+//!
+//! libcoap requires `coap_startup()` to be called before any other function and provides
+//! `coap_cleanup()` to release its global state again.
+//! This crate takes care of both automatically: libcoap is started whenever it is first needed
+//! (e.g., when the first [CoapContext](crate::CoapContext) is created) and cleaned up once the last
+//! context has been dropped.
+//! If raw contexts are wrapped using [CoapContext::from_raw()](crate::CoapContext::from_raw),
+//! libcoap is never cleaned up automatically, as it is presumably still used by raw libcoap-sys
+//! code.
+//!
+//! If libcoap needs to be configured before it is started (e.g., to select a TLS engine), call
+//! [startup_with()] before creating the first context.
+
+use std::sync::{Mutex, MutexGuard};
+
+use libcoap_sys::{coap_cleanup, coap_startup_with_feature_checks};
+#[cfg(tls_engine_support)]
+use libcoap_sys::{coap_str_const_t, coap_tls_engine_configure};
+
+use crate::error::StartupError;
+
+/// Configuration that is applied whenever libcoap is started, see [startup_with()].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CoapStartupConfig {
+    /// Configuration of the TLS engine to use (see `coap_tls_engine_configure(3)` for the format),
+    /// or None to use the default configuration of the TLS library.
+    ///
+    /// TLS engines are only supported by the OpenSSL DTLS backend and libcoap >= 4.3.5.
+    pub tls_engine_config: Option<String>,
+}
+
+// libcoap's global state (initialized by coap_startup()) is shared by all contexts in this process,
+// so it must only be initialized once, regardless of how many contexts are created (and on which
+// thread). All other state (event handlers, resources, sessions, crypto information) is stored
+// per context and resolved from the raw context's app data pointer in callbacks.
+#[derive(Debug)]
+struct LibraryState {
+    /// Whether coap_startup() has been called without a subsequent coap_cleanup().
+    started: bool,
+    /// Number of currently existing [LibraryGuard]s.
+    users: usize,
+    /// Whether libcoap must never be cleaned up, see [disable_cleanup()].
+    cleanup_disabled: bool,
+    /// Configuration that is applied whenever libcoap is started.
+    config: CoapStartupConfig,
+}
+
+static LIBRARY_STATE: Mutex<LibraryState> = Mutex::new(LibraryState {
+    started: false,
+    users: 0,
+    cleanup_disabled: false,
+    config: CoapStartupConfig {
+        tls_engine_config: None,
+    },
+});
+
+/// Locks the global library state.
+///
+/// The state stays consistent even if a thread panicked while holding the lock, so poisoning is
+/// ignored.
+fn lock_state() -> MutexGuard<'static, LibraryState> {
+    LIBRARY_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Starts libcoap and applies the given configuration.
+fn start(config: &CoapStartupConfig) -> Result<(), StartupError> {
+    #[cfg(not(tls_engine_support))]
+    if config.tls_engine_config.is_some() {
+        return Err(StartupError::UnsupportedFeature("TLS engine configuration"));
+    }
+    coap_startup_with_feature_checks();
+    #[cfg(tls_engine_support)]
+    if let Some(engine_config) = &config.tls_engine_config {
+        let mut raw_config = coap_str_const_t {
+            length: engine_config.len(),
+            s: engine_config.as_ptr(),
+        };
+        // SAFETY: raw_config refers to the engine configuration, which outlives this call.
+        if unsafe { coap_tls_engine_configure(&mut raw_config) } != 1 {
+            // SAFETY: libcoap was started above and is not used by anyone else yet.
+            unsafe { coap_cleanup() };
+            return Err(StartupError::TlsEngineConfiguration);
+        }
+    }
+    Ok(())
+}
+
+/// Configures libcoap's global state and starts libcoap.
+///
+/// The configuration is also applied whenever libcoap is started again after it has been cleaned
+/// up (i.e., after the last [CoapContext](crate::CoapContext) was dropped), so it only needs to be
+/// provided once.
+/// Calling this function again with the same configuration has no effect.
+///
+/// # Errors
+/// Returns [StartupError::AlreadyStarted] if libcoap has already been started with a different
+/// configuration (e.g., because a context was created before calling this function), as the
+/// configuration could not be applied anymore.
+/// Returns [StartupError::UnsupportedFeature] or [StartupError::TlsEngineConfiguration] if the
+/// configuration could not be applied, in which case libcoap is not started.
+pub fn startup_with(config: CoapStartupConfig) -> Result<(), StartupError> {
+    let mut state = lock_state();
+    if state.started {
+        if state.config != config {
+            return Err(StartupError::AlreadyStarted);
+        }
+        return Ok(());
+    }
+    start(&config)?;
+    state.started = true;
+    state.config = config;
+    Ok(())
+}
+
+/// Ensures that libcoap has been started, starting it with the configuration provided to
+/// [startup_with()] if necessary.
+///
+/// Unlike [LibraryGuard], this does not prevent libcoap from being cleaned up later on, and should
+/// therefore only be used for short-lived operations that do not involve a context.
+///
+/// # Panics
+/// Panics if the configuration provided to [startup_with()] can no longer be applied.
+pub(crate) fn ensure_coap_started() {
+    ensure_started(&mut lock_state());
+}
+
+/// Starts libcoap using the stored configuration if it is not started yet.
+fn ensure_started(state: &mut LibraryState) {
+    if !state.started {
+        start(&state.config).expect("unable to apply libcoap startup configuration");
+        state.started = true;
+    }
+}
+
+/// Prevents libcoap from ever being cleaned up by this crate.
+///
+/// Used if libcoap is also used through raw libcoap-sys calls (see
+/// [CoapContext::from_raw()](crate::CoapContext::from_raw)), in which case the caller is
+/// responsible for calling `coap_cleanup()` (if at all).
+pub(crate) fn disable_cleanup() {
+    lock_state().cleanup_disabled = true;
+}
+
+/// Guard that keeps libcoap started for as long as it exists.
+///
+/// Held by every [CoapContext](crate::CoapContext), libcoap is cleaned up once the last guard is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct LibraryGuard(());
+
+impl LibraryGuard {
+    /// Starts libcoap if necessary and returns a guard keeping it started.
+    ///
+    /// # Panics
+    /// Panics if the configuration provided to [startup_with()] can no longer be applied.
+    pub(crate) fn acquire() -> LibraryGuard {
+        let mut state = lock_state();
+        ensure_started(&mut state);
+        state.users += 1;
+        LibraryGuard(())
+    }
+}
+
+impl Drop for LibraryGuard {
+    fn drop(&mut self) {
+        let mut state = lock_state();
+        state.users -= 1;
+        if state.users == 0 && state.started && !state.cleanup_disabled {
+            // SAFETY: No context exists anymore, so libcoap's global state is no longer in use.
+            unsafe { coap_cleanup() };
+            state.started = false;
+        }
+    }
+}
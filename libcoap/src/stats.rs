@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * stats.rs - Traffic statistics and memory usage of sessions and contexts.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing traffic statistics for sessions and contexts, as well as lifecycle counters
+//! for server-side sessions and the memory usage of contexts.
+
+use std::{cell::Cell, time::Duration};
+
+use crate::message::CoapMessageCommon;
+
+/// Traffic statistics of a session or context, see
+/// [CoapSessionCommon::stats()](crate::session::CoapSessionCommon::stats) and
+/// [CoapContext::stats()](crate::CoapContext::stats).
+///
+/// All counters only include messages that pass through this wrapper, i.e., messages that are
+/// handled internally by libcoap (such as empty ACKs, retransmissions or block-wise transfer
+/// fragments) are not counted.
+/// Counters are updated on the thread performing the IO operations and can be reset using
+/// [CoapSessionCommon::reset_stats()](crate::session::CoapSessionCommon::reset_stats) and
+/// [CoapContext::reset_stats()](crate::CoapContext::reset_stats).
+///
+/// Use [CoapStats::counters()] to export the statistics to monitoring systems (e.g., as
+/// Prometheus counters).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CoapStats {
+    /// Number of messages (requests, responses and pings) that were sent.
+    pub messages_sent: u64,
+    /// Number of messages (requests, responses and pongs) that were received.
+    pub messages_received: u64,
+    /// Total number of payload bytes in sent messages.
+    pub payload_bytes_sent: u64,
+    /// Total number of payload bytes in received messages.
+    pub payload_bytes_received: u64,
+    /// Number of received requests that were retransmissions of previously received ones.
+    pub retransmissions_received: u64,
+    /// Number of sent messages that could not be delivered (e.g., because no acknowledgement was
+    /// received after the maximum number of retransmissions).
+    pub failed_deliveries: u64,
+    /// Number of (D)TLS handshakes that failed.
+    pub handshake_failures: u64,
+    /// Number of received responses whose body was transferred block-wise (see
+    /// [CoapTransferStats::blockwise]).
+    pub blockwise_responses_received: u64,
+    /// Total number of blocks of block-wise responses that were received (only including
+    /// responses for which the number of blocks is known, see [CoapTransferStats::blocks]).
+    pub response_blocks_received: u64,
+    /// Number of received requests that exceeded the rate limit of their peer (see
+    /// [CoapContext::set_rate_limit()](crate::CoapContext::set_rate_limit)).
+    pub requests_throttled: u64,
+    /// Number of times the MTU of a session was reduced because a request was too large for the
+    /// path to its peer (see
+    /// [IcmpErrorPolicy::reduce_mtu](crate::session::IcmpErrorPolicy::reduce_mtu)).
+    pub mtu_reductions: u64,
+}
+
+impl CoapStats {
+    /// Returns the names and values of all counters.
+    ///
+    /// The names are valid Prometheus metric names (without any prefix), e.g. `messages_sent`.
+    pub fn counters(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        [
+            ("messages_sent", self.messages_sent),
+            ("messages_received", self.messages_received),
+            ("payload_bytes_sent", self.payload_bytes_sent),
+            ("payload_bytes_received", self.payload_bytes_received),
+            ("retransmissions_received", self.retransmissions_received),
+            ("failed_deliveries", self.failed_deliveries),
+            ("handshake_failures", self.handshake_failures),
+            ("blockwise_responses_received", self.blockwise_responses_received),
+            ("response_blocks_received", self.response_blocks_received),
+            ("requests_throttled", self.requests_throttled),
+            ("mtu_reductions", self.mtu_reductions),
+        ]
+        .into_iter()
+    }
+
+    /// Records a sent message with the given payload length.
+    pub(crate) fn record_sent(&mut self, payload_len: usize) {
+        self.messages_sent += 1;
+        self.payload_bytes_sent += payload_len as u64;
+    }
+
+    /// Records a received message with the given payload length.
+    pub(crate) fn record_received(&mut self, payload_len: usize) {
+        self.messages_received += 1;
+        self.payload_bytes_received += payload_len as u64;
+    }
+
+    /// Records a received response that was transferred as described by `transfer`.
+    pub(crate) fn record_received_response(&mut self, transfer: &CoapTransferStats) {
+        self.record_received(transfer.bytes);
+        if transfer.blockwise {
+            self.blockwise_responses_received += 1;
+            self.response_blocks_received += transfer.blocks.map_or(0, u64::from);
+        }
+    }
+}
+
+/// Metadata on how the body of a received response was transferred, see
+/// [CoapResponse::transfer_stats()](crate::message::CoapResponse::transfer_stats).
+///
+/// Block-wise transfers of response bodies ([RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959))
+/// are handled by libcoap, which only passes the reassembled body to this wrapper. The block size
+/// and number of blocks are therefore derived from the Block2 option of the final block (if
+/// libcoap retains it). If it does not, but the body is too large to fit into a single message,
+/// the transfer is still reported as block-wise, with the block size and number of blocks being
+/// unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoapTransferStats {
+    /// Whether the body was transferred block-wise.
+    pub blockwise: bool,
+    /// Block size exponent (SZX) used for the transfer (the block size is `2^(szx + 4)` bytes), if
+    /// known.
+    pub block_szx: Option<u8>,
+    /// Number of blocks the body was transferred in, if known (1 for bodies that were not
+    /// transferred block-wise).
+    pub blocks: Option<u32>,
+    /// Total size of the (reassembled) body in bytes.
+    pub bytes: usize,
+    /// Time from sending the request until the final block of the response was received (based
+    /// on the clock of the context, see [CoapContext::set_clock()](crate::CoapContext::set_clock)).
+    ///
+    /// Only known for the first response to a request, not for subsequent ones (e.g.,
+    /// notifications of an observation).
+    pub duration: Option<Duration>,
+}
+
+/// Lifecycle counters of the server-side sessions of a context (either for all transports or for
+/// a single one), see [CoapContext::server_session_stats()](crate::CoapContext::server_session_stats)
+/// and [CoapContext::server_session_stats_by_protocol()](crate::CoapContext::server_session_stats_by_protocol).
+///
+/// Unlike [CoapStats], these counters are not affected by
+/// [CoapContext::reset_stats()](crate::CoapContext::reset_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CoapServerSessionStats {
+    /// Number of server-side sessions that currently exist.
+    pub current: u64,
+    /// Number of server-side sessions that were created.
+    pub created: u64,
+    /// Number of server-side sessions that were freed (reaped) again, e.g., because they were idle
+    /// for longer than the session timeout, because they were disconnected or because their
+    /// endpoint was removed.
+    pub reaped: u64,
+}
+
+impl CoapServerSessionStats {
+    /// Returns the names and values of all counters (see [CoapStats::counters()]).
+    ///
+    /// Note that `server_sessions` is a gauge rather than a counter, as it may also decrease.
+    pub fn counters(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        [
+            ("server_sessions", self.current),
+            ("server_sessions_created", self.created),
+            ("server_sessions_reaped", self.reaped),
+        ]
+        .into_iter()
+    }
+
+    /// Records a newly created session.
+    pub(crate) fn record_created(&mut self) {
+        self.current += 1;
+        self.created += 1;
+    }
+
+    /// Records a session that was freed.
+    pub(crate) fn record_reaped(&mut self) {
+        self.current = self.current.saturating_sub(1);
+        self.reaped += 1;
+    }
+}
+
+impl std::ops::Add for CoapServerSessionStats {
+    type Output = CoapServerSessionStats;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        CoapServerSessionStats {
+            current: self.current + rhs.current,
+            created: self.created + rhs.created,
+            reaped: self.reaped + rhs.reaped,
+        }
+    }
+}
+
+/// Memory used by the data structures of this wrapper, broken down by category, see
+/// [CoapContext::memory_usage()](crate::CoapContext::memory_usage).
+///
+/// Sizes are estimated in bytes from the number and size of the entries stored by the wrapper
+/// whenever they are inserted or removed, i.e., memory allocated by libcoap itself, spare capacity
+/// of collections and allocator overhead are not included. The sizes are therefore lower bounds
+/// that are meant to show which category grows, not to match the memory usage of the process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CoapMemoryReport {
+    /// Bytes used by the wrappers of the sessions of the context, including their application
+    /// data and the certificates of their peers.
+    pub sessions: usize,
+    /// Bytes used for requests whose handles have not been removed yet, including received
+    /// responses that have not been polled, values stored alongside requests, queued requests and
+    /// the registrations of observations.
+    pub pending_requests: usize,
+    /// Bytes used by the resources of the context, including the records of their observers.
+    pub resources: usize,
+    /// Bytes used by cached representations, i.e., responses in the response caches of client
+    /// sessions and notification snapshots of resources.
+    pub cached_representations: usize,
+    /// Bytes used for notifications that are queued because of notification pacing (see
+    /// [CoapContext::set_notification_pacing()](crate::CoapContext::set_notification_pacing)).
+    pub queued_notifications: usize,
+    /// Number of sessions that were rejected because a memory limit was exceeded (see
+    /// [CoapMemoryLimits]).
+    pub sessions_rejected: u64,
+    /// Number of requests that were rejected because a memory limit was exceeded (see
+    /// [CoapMemoryLimits]).
+    pub requests_rejected: u64,
+}
+
+impl CoapMemoryReport {
+    /// Returns the total number of bytes used by all categories.
+    pub fn total(&self) -> usize {
+        self.sessions + self.pending_requests + self.resources + self.cached_representations + self.queued_notifications
+    }
+
+    /// Returns the names and values of all categories (in bytes) and rejection counters (see
+    /// [CoapStats::counters()]).
+    ///
+    /// Unlike the rejection counters, the sizes are gauges rather than counters, as they may also
+    /// decrease.
+    pub fn counters(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        [
+            ("memory_sessions_bytes", self.sessions as u64),
+            ("memory_pending_requests_bytes", self.pending_requests as u64),
+            ("memory_resources_bytes", self.resources as u64),
+            (
+                "memory_cached_representations_bytes",
+                self.cached_representations as u64,
+            ),
+            ("memory_queued_notifications_bytes", self.queued_notifications as u64),
+            ("memory_sessions_rejected", self.sessions_rejected),
+            ("memory_requests_rejected", self.requests_rejected),
+        ]
+        .into_iter()
+    }
+}
+
+/// Limits for the memory used by the data structures of this wrapper, see
+/// [CoapContext::set_memory_limits()](crate::CoapContext::set_memory_limits).
+///
+/// The limits refer to the categories of [CoapMemoryReport] and are checked before new sessions
+/// or requests are created. As the size of a new entry is not known in advance, a limit may be
+/// exceeded by the last entry that was accepted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoapMemoryLimits {
+    /// Maximum number of bytes used by sessions ([CoapMemoryReport::sessions]), new client
+    /// sessions are rejected and new server-side sessions refuse all requests once it is reached.
+    pub max_session_bytes: Option<usize>,
+    /// Maximum number of bytes used by pending requests ([CoapMemoryReport::pending_requests]),
+    /// new requests are rejected once it is reached.
+    pub max_pending_request_bytes: Option<usize>,
+}
+
+/// Memory usage of a single session, see [MemoryAccounting].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) struct SessionMemoryUsage {
+    pub(crate) session: usize,
+    pub(crate) pending_requests: usize,
+    pub(crate) cached_representations: usize,
+}
+
+/// Memory usage of the sessions of a context, which is updated by the sessions whenever their
+/// bookkeeping changes.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryAccounting {
+    sessions: Cell<usize>,
+    pending_requests: Cell<usize>,
+    cached_representations: Cell<usize>,
+    sessions_rejected: Cell<u64>,
+    requests_rejected: Cell<u64>,
+    limits: Cell<CoapMemoryLimits>,
+}
+
+impl MemoryAccounting {
+    /// Replaces the usage `old` previously accounted for a session with `new`.
+    pub(crate) fn update(&self, old: SessionMemoryUsage, new: SessionMemoryUsage) {
+        let apply = |cell: &Cell<usize>, old: usize, new: usize| cell.set((cell.get() + new).saturating_sub(old));
+        apply(&self.sessions, old.session, new.session);
+        apply(&self.pending_requests, old.pending_requests, new.pending_requests);
+        apply(
+            &self.cached_representations,
+            old.cached_representations,
+            new.cached_representations,
+        );
+    }
+
+    pub(crate) fn limits(&self) -> CoapMemoryLimits {
+        self.limits.get()
+    }
+
+    pub(crate) fn set_limits(&self, limits: CoapMemoryLimits) {
+        self.limits.set(limits);
+    }
+
+    /// Checks whether a new session may be created, returning the exceeded limit (and counting
+    /// the rejection) if it may not.
+    pub(crate) fn check_session(&self) -> Result<(), usize> {
+        match self.limits.get().max_session_bytes {
+            Some(limit) if self.sessions.get() >= limit => {
+                self.sessions_rejected.set(self.sessions_rejected.get() + 1);
+                Err(limit)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether a new request may be sent, returning the exceeded limit (and counting the
+    /// rejection) if it may not.
+    pub(crate) fn check_request(&self) -> Result<(), usize> {
+        match self.limits.get().max_pending_request_bytes {
+            Some(limit) if self.pending_requests.get() >= limit => {
+                self.requests_rejected.set(self.requests_rejected.get() + 1);
+                Err(limit)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns a report containing the usage of the sessions, the remaining categories are left
+    /// empty.
+    pub(crate) fn report(&self) -> CoapMemoryReport {
+        CoapMemoryReport {
+            sessions: self.sessions.get(),
+            pending_requests: self.pending_requests.get(),
+            cached_representations: self.cached_representations.get(),
+            sessions_rejected: self.sessions_rejected.get(),
+            requests_rejected: self.requests_rejected.get(),
+            ..CoapMemoryReport::default()
+        }
+    }
+}
+
+/// Returns the number of bytes used by the given message and its payload (see
+/// [CoapMemoryReport]).
+pub(crate) fn message_memory<M: CoapMessageCommon>(message: &M) -> usize {
+    std::mem::size_of_val(message) + message.data().map_or(0, <[u8]>::len)
+}
@@ -0,0 +1,446 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * test_vectors.rs - Golden encodings of CoAP messages for conformance tests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Golden byte-level encodings of CoAP messages.
+//!
+//! Each [CoapMessageVector] pairs a message constructed using the typed message API with its
+//! expected encoding on the wire, which was derived by hand from the respective RFCs
+//! ([RFC 7252](https://datatracker.ietf.org/doc/html/rfc7252) for UDP,
+//! [RFC 8323](https://datatracker.ietf.org/doc/html/rfc8323) for TCP framing).
+//! The vectors cover every option type known to this crate, token lengths from 0 to 8 bytes,
+//! option deltas and lengths requiring extended fields, Block1/Block2 values at the boundaries of
+//! their encoded sizes, the placement of the OSCORE option and TCP framing with extended length
+//! fields.
+//!
+//! This crate uses these vectors to check its own encoding and decoding of messages, but
+//! applications can use them as well to validate their own handling of messages.
+//!
+//! This module is only available if the `test-util` feature is enabled.
+
+use crate::{
+    message::{CoapMessage, CoapMessageCommon, CoapOption},
+    protocol::{CoapMatch, CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+    types::{CoapMessageId, CoapProtocol},
+};
+
+/// A CoAP message and its expected encoding.
+#[derive(Debug, Clone)]
+pub struct CoapMessageVector {
+    /// Short, unique name of the vector (e.g., for test failure messages).
+    pub name: &'static str,
+    /// Transport the message is encoded for, either [CoapProtocol::Udp] or [CoapProtocol::Tcp].
+    ///
+    /// Messages for TCP have neither a message type nor a message ID on the wire, their
+    /// [CoapMessage] therefore has no message ID and an (ignored) message type of
+    /// [CoapMessageType::Con].
+    pub proto: CoapProtocol,
+    /// The message, with the options in the order they are placed on the wire.
+    pub message: CoapMessage,
+    /// The expected encoding of the message.
+    pub bytes: Vec<u8>,
+}
+
+/// Creates a message with the given parameters.
+fn message(
+    type_: CoapMessageType,
+    code: CoapMessageCode,
+    mid: Option<CoapMessageId>,
+    token: &[u8],
+    options: Vec<CoapOption>,
+    data: Option<&[u8]>,
+) -> CoapMessage {
+    let mut message = CoapMessage::new(type_, code);
+    message.set_mid(mid);
+    message.set_token(Some(token));
+    for option in options {
+        message.add_option(option);
+    }
+    message.set_data(data);
+    message
+}
+
+/// Creates a vector for the given UDP message.
+fn udp(name: &'static str, message: CoapMessage, bytes: &[u8]) -> CoapMessageVector {
+    CoapMessageVector {
+        name,
+        proto: CoapProtocol::Udp,
+        message,
+        bytes: bytes.to_vec(),
+    }
+}
+
+/// Creates a vector for the given TCP message.
+fn tcp(name: &'static str, message: CoapMessage, bytes: Vec<u8>) -> CoapMessageVector {
+    CoapMessageVector {
+        name,
+        proto: CoapProtocol::Tcp,
+        message,
+        bytes,
+    }
+}
+
+/// Returns all message vectors.
+pub fn message_vectors() -> Vec<CoapMessageVector> {
+    let get = CoapMessageCode::Request(CoapRequestCode::Get);
+    let content = CoapMessageCode::Response(CoapResponseCode::Content);
+    let mut vectors = vec![
+        udp(
+            "empty_con",
+            message(
+                CoapMessageType::Con,
+                CoapMessageCode::Empty,
+                Some(0x1234),
+                &[],
+                vec![],
+                None,
+            ),
+            &[0x40, 0x00, 0x12, 0x34],
+        ),
+        udp(
+            "empty_rst",
+            message(
+                CoapMessageType::Rst,
+                CoapMessageCode::Empty,
+                Some(0x1234),
+                &[],
+                vec![],
+                None,
+            ),
+            &[0x70, 0x00, 0x12, 0x34],
+        ),
+    ];
+
+    // GET requests with tokens of all lengths allowed by RFC 7252.
+    let token_vectors: [(&'static str, &[u8]); 9] = [
+        ("token_length_0", &[0x40, 0x01, 0x12, 0x34, 0xB1, 0x74]),
+        ("token_length_1", &[0x41, 0x01, 0x12, 0x34, 0x01, 0xB1, 0x74]),
+        ("token_length_2", &[0x42, 0x01, 0x12, 0x34, 0x01, 0x02, 0xB1, 0x74]),
+        (
+            "token_length_3",
+            &[0x43, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0xB1, 0x74],
+        ),
+        (
+            "token_length_4",
+            &[0x44, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04, 0xB1, 0x74],
+        ),
+        (
+            "token_length_5",
+            &[0x45, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04, 0x05, 0xB1, 0x74],
+        ),
+        (
+            "token_length_6",
+            &[0x46, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0xB1, 0x74],
+        ),
+        (
+            "token_length_7",
+            &[
+                0x47, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0xB1, 0x74,
+            ],
+        ),
+        (
+            "token_length_8",
+            &[
+                0x48, 0x01, 0x12, 0x34, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0xB1, 0x74,
+            ],
+        ),
+    ];
+    for (len, (name, bytes)) in token_vectors.into_iter().enumerate() {
+        let token: Vec<u8> = (1..=len as u8).collect();
+        vectors.push(udp(
+            name,
+            message(
+                CoapMessageType::Con,
+                get,
+                Some(0x1234),
+                &token,
+                vec![CoapOption::UriPath("t".to_string())],
+                None,
+            ),
+            bytes,
+        ));
+    }
+
+    vectors.extend([
+        udp(
+            "request_options",
+            message(
+                CoapMessageType::Con,
+                get,
+                Some(0x0002),
+                &[0xAB],
+                vec![
+                    CoapOption::IfMatch(CoapMatch::ETag(Box::new([0x01, 0x02]))),
+                    CoapOption::UriHost("example.com".to_string()),
+                    CoapOption::ETag(Box::new([0x03])),
+                    CoapOption::IfNoneMatch,
+                    CoapOption::Observe(0),
+                    CoapOption::UriPort(5683),
+                    CoapOption::UriPath("a".to_string()),
+                    CoapOption::UriPath("b".to_string()),
+                    CoapOption::UriQuery("k=v".to_string()),
+                    CoapOption::HopLimit(16),
+                    CoapOption::Accept(50),
+                    CoapOption::Block2(0x06),
+                    CoapOption::Size2(0),
+                    CoapOption::Echo(Box::new([0xAA, 0xBB])),
+                    CoapOption::NoResponse(0x1A),
+                    CoapOption::RTag(Box::new([0x01])),
+                ],
+                None,
+            ),
+            &[
+                0x41, 0x01, 0x00, 0x02, 0xAB, // Header and token
+                0x12, 0x01, 0x02, // If-Match (1)
+                0x2B, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm', // Uri-Host (3)
+                0x11, 0x03, // ETag (4)
+                0x10, // If-None-Match (5)
+                0x10, // Observe (6)
+                0x12, 0x16, 0x33, // Uri-Port (7)
+                0x41, b'a', // Uri-Path (11)
+                0x01, b'b', // Uri-Path (11)
+                0x43, b'k', b'=', b'v', // Uri-Query (15)
+                0x11, 0x10, // Hop-Limit (16)
+                0x11, 0x32, // Accept (17)
+                0x61, 0x06, // Block2 (23)
+                0x50, // Size2 (28)
+                0xD2, 0xD3, 0xAA, 0xBB, // Echo (252)
+                0x61, 0x1A, // No-Response (258)
+                0xD1, 0x15, 0x01, // Request-Tag (292)
+            ],
+        ),
+        udp(
+            "response_options",
+            message(
+                CoapMessageType::Ack,
+                content,
+                Some(0x0003),
+                &[0xAB],
+                vec![
+                    CoapOption::ETag(Box::new([0x01, 0x02, 0x03, 0x04])),
+                    CoapOption::Observe(0x010203),
+                    CoapOption::LocationPath("loc".to_string()),
+                    CoapOption::ContentFormat(0),
+                    CoapOption::MaxAge(60),
+                    CoapOption::LocationQuery("q=1".to_string()),
+                ],
+                Some(b"ok"),
+            ),
+            &[
+                0x61, 0x45, 0x00, 0x03, 0xAB, // Header and token
+                0x44, 0x01, 0x02, 0x03, 0x04, // ETag (4)
+                0x23, 0x01, 0x02, 0x03, // Observe (6)
+                0x23, b'l', b'o', b'c', // Location-Path (8)
+                0x40, // Content-Format (12)
+                0x21, 0x3C, // Max-Age (14)
+                0x63, b'q', b'=', b'1', // Location-Query (20)
+                0xFF, b'o', b'k', // Payload
+            ],
+        ),
+        udp(
+            "proxy_uri",
+            message(
+                CoapMessageType::Con,
+                get,
+                Some(0x0004),
+                &[0x01],
+                vec![CoapOption::ProxyUri("coap://h/p".to_string())],
+                None,
+            ),
+            &[
+                0x41, 0x01, 0x00, 0x04, 0x01, // Header and token
+                0xDA, 0x16, b'c', b'o', b'a', b'p', b':', b'/', b'/', b'h', b'/', b'p', // Proxy-Uri (35)
+            ],
+        ),
+        udp(
+            "proxy_scheme",
+            message(
+                CoapMessageType::Con,
+                get,
+                Some(0x0005),
+                &[0x01],
+                vec![
+                    CoapOption::UriHost("h".to_string()),
+                    CoapOption::UriPath("p".to_string()),
+                    CoapOption::ProxyScheme("coap".to_string()),
+                ],
+                None,
+            ),
+            &[
+                0x41, 0x01, 0x00, 0x05, 0x01, // Header and token
+                0x31, b'h', // Uri-Host (3)
+                0x81, b'p', // Uri-Path (11)
+                0xD4, 0x0F, b'c', b'o', b'a', b'p', // Proxy-Scheme (39)
+            ],
+        ),
+        udp(
+            "q_block_options",
+            message(
+                CoapMessageType::Con,
+                CoapMessageCode::Request(CoapRequestCode::Put),
+                Some(0x0006),
+                &[0x02],
+                vec![
+                    CoapOption::UriPath("q".to_string()),
+                    CoapOption::ContentFormat(42),
+                    CoapOption::QBlock1(0x0E),
+                    CoapOption::QBlock2(0x16),
+                ],
+                None,
+            ),
+            &[
+                0x41, 0x03, 0x00, 0x06, 0x02, // Header and token
+                0xB1, b'q', // Uri-Path (11)
+                0x11, 0x2A, // Content-Format (12)
+                0x71, 0x0E, // Q-Block1 (19)
+                0xC1, 0x16, // Q-Block2 (31)
+            ],
+        ),
+        udp(
+            "oscore_option_placement",
+            message(
+                CoapMessageType::Con,
+                CoapMessageCode::Request(CoapRequestCode::Post),
+                Some(0x0007),
+                &[0x05],
+                vec![
+                    CoapOption::UriHost("h".to_string()),
+                    CoapOption::Oscore(Box::new([0x09, 0x14, 0x01])),
+                ],
+                Some(&[0xDE, 0xAD, 0xBE, 0xEF]),
+            ),
+            &[
+                0x41, 0x02, 0x00, 0x07, 0x05, // Header and token
+                0x31, b'h', // Uri-Host (3)
+                0x63, 0x09, 0x14, 0x01, // OSCORE (9)
+                0xFF, 0xDE, 0xAD, 0xBE, 0xEF, // Payload (the encrypted request)
+            ],
+        ),
+        udp(
+            "extended_option_fields",
+            message(
+                CoapMessageType::Non,
+                get,
+                Some(0x0008),
+                &[0x04],
+                vec![
+                    CoapOption::UriPath("abcdefghijklmnopqrst".to_string()),
+                    CoapOption::Other(65000, Box::new([0x01])),
+                ],
+                None,
+            ),
+            &[
+                0x51, 0x01, 0x00, 0x08, 0x04, // Header and token
+                0xBD, 0x07, b'a', b'b', b'c', b'd', b'e', b'f', b'g', b'h', b'i', b'j', b'k', b'l', b'm', b'n', b'o',
+                b'p', b'q', b'r', b's', b't', // Uri-Path (11) with 1-byte extended length
+                0xE1, 0xFC, 0xD0, 0x01, // Unknown option 65000 with 2-byte extended delta
+            ],
+        ),
+    ]);
+
+    // Block options at the boundaries of their encoded sizes.
+    let block2_vectors: [(&'static str, u32, &[u8]); 6] = [
+        ("block2_num_0_szx_0", 0x00, &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD0, 0x0A]),
+        (
+            "block2_num_15_more",
+            0xFE,
+            &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD1, 0x0A, 0xFE],
+        ),
+        (
+            "block2_num_16",
+            0x0106,
+            &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD2, 0x0A, 0x01, 0x06],
+        ),
+        (
+            "block2_num_4095_more",
+            0xFFFA,
+            &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD2, 0x0A, 0xFF, 0xFA],
+        ),
+        (
+            "block2_num_4096_szx_7",
+            0x010007,
+            &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD3, 0x0A, 0x01, 0x00, 0x07],
+        ),
+        (
+            "block2_num_max",
+            0xFFFFF6,
+            &[0x61, 0x45, 0x00, 0x10, 0x01, 0xD3, 0x0A, 0xFF, 0xFF, 0xF6],
+        ),
+    ];
+    for (name, block, bytes) in block2_vectors {
+        vectors.push(udp(
+            name,
+            message(
+                CoapMessageType::Ack,
+                content,
+                Some(0x0010),
+                &[0x01],
+                vec![CoapOption::Block2(block)],
+                None,
+            ),
+            bytes,
+        ));
+    }
+    vectors.extend([
+        udp(
+            "block1_num_max_more",
+            message(
+                CoapMessageType::Ack,
+                CoapMessageCode::Response(CoapResponseCode::Continue),
+                Some(0x0011),
+                &[0x01],
+                vec![CoapOption::Block1(0xFFFFFE)],
+                None,
+            ),
+            &[0x61, 0x5F, 0x00, 0x11, 0x01, 0xD3, 0x0E, 0xFF, 0xFF, 0xFE],
+        ),
+        udp(
+            "size1_request_too_large",
+            message(
+                CoapMessageType::Ack,
+                CoapMessageCode::Response(CoapResponseCode::RequestTooLarge),
+                Some(0x0012),
+                &[0x01],
+                vec![CoapOption::Size1(1024)],
+                None,
+            ),
+            &[0x61, 0x8D, 0x00, 0x12, 0x01, 0xD2, 0x2F, 0x04, 0x00],
+        ),
+    ]);
+
+    // TCP framing (RFC 8323, Section 3.2), with the Len field and its extensions covering the
+    // options and the payload.
+    vectors.push(tcp(
+        "tcp_length_2",
+        message(
+            CoapMessageType::Con,
+            get,
+            None,
+            &[0x01],
+            vec![CoapOption::UriPath("t".to_string())],
+            None,
+        ),
+        vec![0x21, 0x01, 0x01, 0xB1, 0x74],
+    ));
+    for (name, payload_len, header) in [
+        ("tcp_length_13", 12, vec![0xD1, 0x00]),
+        ("tcp_length_269", 268, vec![0xE1, 0x00, 0x00]),
+        ("tcp_length_65805", 65804, vec![0xF1, 0x00, 0x00, 0x00, 0x00]),
+    ] {
+        let payload = vec![0x2A; payload_len];
+        let mut bytes = header;
+        bytes.extend_from_slice(&[0x45, 0x01, 0xFF]);
+        bytes.extend_from_slice(&payload);
+        vectors.push(tcp(
+            name,
+            message(CoapMessageType::Con, content, None, &[0x01], vec![], Some(&payload)),
+            bytes,
+        ));
+    }
+    vectors
+}
@@ -0,0 +1,591 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * testing.rs - In-process client/server pairs for testing CoAP applications.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Helpers for testing CoAP servers and clients within a single thread.
+//!
+//! [CoapTestPair] creates a server context with an endpoint on an ephemeral loopback port and a
+//! client context with a session connected to it. Both contexts are driven by the pair itself
+//! (see [CoapTestPair::step()] and [CoapTestPair::run_until()]), so tests neither need a separate
+//! server thread nor have to guess free port numbers.
+//!
+//! libcoap does not allow replacing its network I/O, so the contexts always communicate using
+//! real sockets. To test the behavior of applications on lossy networks, the pair can instead
+//! route all datagrams through a [CoapTestLink], a relay on another loopback socket that drops,
+//! duplicates or delays (and thereby reorders) individual datagrams as instructed by the test.
+//!
+//! ```no_run
+//! use libcoap_rs::message::{CoapMessageCommon, CoapRequest};
+//! use libcoap_rs::protocol::{CoapMessageType, CoapRequestCode, CoapResponseCode};
+//! use libcoap_rs::session::CoapSessionCommon;
+//! use libcoap_rs::testing::{assert_requests_handled, assert_response_code, CoapTestPair};
+//! use libcoap_rs::{CoapRequestHandler, CoapResource};
+//!
+//! let mut pair = CoapTestPair::new().unwrap();
+//! let resource = CoapResource::new("hello", (), false);
+//! resource.set_method_handler(
+//!     CoapRequestCode::Get,
+//!     Some(CoapRequestHandler::new(|_, session, _request, mut response| {
+//!         response.set_code(CoapResponseCode::Content);
+//!         session.send(response).unwrap();
+//!     })),
+//! );
+//! let handle = pair.add_resource(resource);
+//!
+//! let request = CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Get, "/hello".parse().unwrap()).unwrap();
+//! let response = pair.request(request).unwrap();
+//! assert_response_code(&response, CoapResponseCode::Content);
+//! assert_requests_handled(&handle.resource().unwrap(), CoapRequestCode::Get, 1);
+//! ```
+//!
+//! This module is only available if the `test-util` feature is enabled.
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt::Debug,
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+#[cfg(feature = "dtls-psk")]
+use crate::crypto::psk::{ClientPskContextBuilder, PskKey, ServerPskContextBuilder};
+use crate::{
+    context::{CoapContext, CoapResourceHandle},
+    error::{IoProcessError, RequestWaitError, TestPairError},
+    message::{request::CoapRequest, response::CoapResponse, CoapMessageCommon},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    resource::CoapResource,
+    session::{CoapClientSession, CoapSessionCommon},
+};
+
+/// PSK identity used by pairs created with [CoapTestPairBuilder::dtls_psk()].
+#[cfg(feature = "dtls-psk")]
+pub const TEST_PSK_IDENTITY: &str = "libcoap-rs-test";
+
+/// Pre-shared key used by pairs created with [CoapTestPairBuilder::dtls_psk()].
+#[cfg(feature = "dtls-psk")]
+pub const TEST_PSK_KEY: &str = "libcoap-rs-test-key";
+
+/// Default maximum number of iterations of [CoapTestPair::request()].
+const DEFAULT_MAX_REQUEST_ITERATIONS: usize = 1000;
+
+/// Default time each context waits for IO in [CoapTestPair::step()].
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// Maximum size of datagrams relayed by a [CoapTestLink].
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// Direction of a datagram relayed by a [CoapTestLink].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoapLinkDirection {
+    /// Datagrams sent by the client context to the server.
+    ClientToServer,
+    /// Datagrams sent by the server context to the client.
+    ServerToClient,
+}
+
+/// Fault applied by a [CoapTestLink] to a single datagram, see [CoapTestLink::inject()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoapLinkFault {
+    /// The datagram is discarded.
+    Drop,
+    /// The datagram is forwarded twice.
+    Duplicate,
+    /// The datagram is held back until the given number of later datagrams in the same direction
+    /// have been forwarded, i.e., it arrives after them.
+    ///
+    /// Held datagrams are released once [CoapTestLink::release_delayed()] is called, even if not
+    /// enough later datagrams have been forwarded. A delay of 0 forwards the datagram right away.
+    Delay(usize),
+}
+
+/// Faults and counters of one direction of a [CoapTestLink].
+#[derive(Debug, Default)]
+struct LinkDirectionState {
+    /// Faults to apply to the next datagrams, in order.
+    faults: VecDeque<CoapLinkFault>,
+    /// Held datagrams and the number of datagrams that have to be forwarded before each of them.
+    delayed: Vec<(usize, Vec<u8>)>,
+    forwarded: usize,
+    dropped: usize,
+}
+
+/// Relay between the contexts of a [CoapTestPair] that injects faults into the relayed datagrams.
+///
+/// The client session of the pair is connected to the socket of the link, which forwards
+/// datagrams received from the client to the server endpoint and datagrams received from the
+/// server back to the client. Datagrams are only relayed while the pair is stepped (see
+/// [CoapTestPair::step()]).
+///
+/// By default, all datagrams are forwarded unchanged. Faults queued using [CoapTestLink::inject()]
+/// are applied to the next datagrams in the respective direction, one fault per datagram.
+#[derive(Debug)]
+pub struct CoapTestLink {
+    socket: UdpSocket,
+    server_addr: SocketAddr,
+    /// Address of the client session, learned from the first datagram it sent.
+    client_addr: Option<SocketAddr>,
+    client_to_server: LinkDirectionState,
+    server_to_client: LinkDirectionState,
+    buffer: Vec<u8>,
+}
+
+impl CoapTestLink {
+    /// Creates a link relaying datagrams to the server at `server_addr`.
+    fn new(server_addr: SocketAddr) -> std::io::Result<CoapTestLink> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        socket.set_nonblocking(true)?;
+        Ok(CoapTestLink {
+            socket,
+            server_addr,
+            client_addr: None,
+            client_to_server: LinkDirectionState::default(),
+            server_to_client: LinkDirectionState::default(),
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    /// Returns the address the client session is connected to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.socket.local_addr().expect("bound socket has no local address")
+    }
+
+    /// Queues a fault that is applied to the next datagram in the given direction that does not
+    /// already have a fault assigned.
+    pub fn inject(&mut self, direction: CoapLinkDirection, fault: CoapLinkFault) {
+        self.state_mut(direction).faults.push_back(fault);
+    }
+
+    /// Returns the number of faults queued for the given direction that have not been applied yet.
+    pub fn pending_faults(&self, direction: CoapLinkDirection) -> usize {
+        self.state(direction).faults.len()
+    }
+
+    /// Discards all faults queued for the given direction that have not been applied yet.
+    pub fn clear_faults(&mut self, direction: CoapLinkDirection) {
+        self.state_mut(direction).faults.clear();
+    }
+
+    /// Returns the number of datagrams forwarded in the given direction (including duplicates and
+    /// released delayed datagrams).
+    pub fn forwarded(&self, direction: CoapLinkDirection) -> usize {
+        self.state(direction).forwarded
+    }
+
+    /// Returns the number of datagrams discarded in the given direction.
+    ///
+    /// Besides datagrams dropped using [CoapLinkFault::Drop], this includes datagrams from the
+    /// server that were received before the client sent its first datagram.
+    pub fn dropped(&self, direction: CoapLinkDirection) -> usize {
+        self.state(direction).dropped
+    }
+
+    /// Returns the number of datagrams currently held back in the given direction.
+    pub fn delayed(&self, direction: CoapLinkDirection) -> usize {
+        self.state(direction).delayed.len()
+    }
+
+    /// Forwards all datagrams currently held back in the given direction, in the order they were
+    /// received.
+    pub fn release_delayed(&mut self, direction: CoapLinkDirection) -> std::io::Result<()> {
+        let delayed = std::mem::take(&mut self.state_mut(direction).delayed);
+        for (_, datagram) in delayed {
+            self.send(direction, &datagram)?;
+        }
+        Ok(())
+    }
+
+    /// Relays all datagrams that are currently available on the socket of the link.
+    fn pump(&mut self) -> std::io::Result<()> {
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(v) => v,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                // Some operating systems report ICMP errors caused by earlier datagrams (e.g.,
+                // after the client session was closed) on unconnected sockets.
+                Err(e) if matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused) => continue,
+                Err(e) => return Err(e),
+            };
+            let datagram = self.buffer[..len].to_vec();
+            let direction = if from == self.server_addr {
+                CoapLinkDirection::ServerToClient
+            } else {
+                self.client_addr = Some(from);
+                CoapLinkDirection::ClientToServer
+            };
+            match self.state_mut(direction).faults.pop_front() {
+                Some(CoapLinkFault::Drop) => self.state_mut(direction).dropped += 1,
+                Some(CoapLinkFault::Duplicate) => {
+                    self.forward(direction, &datagram)?;
+                    self.forward(direction, &datagram)?;
+                },
+                Some(CoapLinkFault::Delay(count)) if count > 0 => {
+                    self.state_mut(direction).delayed.push((count, datagram));
+                },
+                Some(CoapLinkFault::Delay(_)) | None => self.forward(direction, &datagram)?,
+            }
+        }
+    }
+
+    /// Forwards a datagram, releasing held datagrams whose delay has elapsed afterwards.
+    fn forward(&mut self, direction: CoapLinkDirection, datagram: &[u8]) -> std::io::Result<()> {
+        self.send(direction, datagram)?;
+        let state = self.state_mut(direction);
+        let mut released = Vec::new();
+        state.delayed.retain_mut(|(remaining, datagram)| {
+            *remaining -= 1;
+            if *remaining == 0 {
+                released.push(std::mem::take(datagram));
+            }
+            *remaining != 0
+        });
+        for datagram in released {
+            self.send(direction, &datagram)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a datagram to its destination without applying any faults.
+    fn send(&mut self, direction: CoapLinkDirection, datagram: &[u8]) -> std::io::Result<()> {
+        let destination = match direction {
+            CoapLinkDirection::ClientToServer => Some(self.server_addr),
+            CoapLinkDirection::ServerToClient => self.client_addr,
+        };
+        let Some(destination) = destination else {
+            self.state_mut(direction).dropped += 1;
+            return Ok(());
+        };
+        match self.socket.send_to(datagram, destination) {
+            Ok(_) => {},
+            // The datagram is lost like on any real network.
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                self.state_mut(direction).dropped += 1;
+                return Ok(());
+            },
+            Err(e) => return Err(e),
+        }
+        self.state_mut(direction).forwarded += 1;
+        Ok(())
+    }
+
+    fn state(&self, direction: CoapLinkDirection) -> &LinkDirectionState {
+        match direction {
+            CoapLinkDirection::ClientToServer => &self.client_to_server,
+            CoapLinkDirection::ServerToClient => &self.server_to_client,
+        }
+    }
+
+    fn state_mut(&mut self, direction: CoapLinkDirection) -> &mut LinkDirectionState {
+        match direction {
+            CoapLinkDirection::ClientToServer => &mut self.client_to_server,
+            CoapLinkDirection::ServerToClient => &mut self.server_to_client,
+        }
+    }
+}
+
+/// Builder for a [CoapTestPair].
+#[derive(Debug, Clone)]
+pub struct CoapTestPairBuilder {
+    #[cfg(feature = "dtls-psk")]
+    dtls_psk: bool,
+    link: bool,
+    step_timeout: Duration,
+}
+
+impl Default for CoapTestPairBuilder {
+    fn default() -> Self {
+        CoapTestPairBuilder {
+            #[cfg(feature = "dtls-psk")]
+            dtls_psk: false,
+            link: false,
+            step_timeout: DEFAULT_STEP_TIMEOUT,
+        }
+    }
+}
+
+impl CoapTestPairBuilder {
+    /// Creates a builder for an unencrypted pair without a [CoapTestLink].
+    pub fn new() -> CoapTestPairBuilder {
+        CoapTestPairBuilder::default()
+    }
+
+    /// Sets whether the client and server communicate using DTLS with the fixed pre-shared key
+    /// [TEST_PSK_KEY] and identity [TEST_PSK_IDENTITY] (false by default).
+    #[cfg(feature = "dtls-psk")]
+    pub fn dtls_psk(mut self, dtls_psk: bool) -> Self {
+        self.dtls_psk = dtls_psk;
+        self
+    }
+
+    /// Sets whether datagrams are routed through a [CoapTestLink] (false by default).
+    pub fn link(mut self, link: bool) -> Self {
+        self.link = link;
+        self
+    }
+
+    /// Sets the maximum time each context waits for IO in [CoapTestPair::step()] (5 milliseconds
+    /// by default).
+    ///
+    /// As libcoap treats a timeout of 0 as "wait indefinitely", a zero duration is treated like
+    /// one millisecond.
+    pub fn step_timeout(mut self, step_timeout: Duration) -> Self {
+        self.step_timeout = step_timeout;
+        self
+    }
+
+    /// Creates the server and client contexts, the server endpoint and the client session.
+    ///
+    /// # Errors
+    /// Returns [TestPairError::Context] if a context could not be created or configured,
+    /// [TestPairError::Endpoint] if the server endpoint could not be created,
+    /// [TestPairError::Link] if the socket of the [CoapTestLink] could not be created and
+    /// [TestPairError::Session] if the client session could not be created.
+    pub fn build(self) -> Result<CoapTestPair, TestPairError> {
+        let mut server = CoapContext::new()?;
+        let mut client = CoapContext::new()?;
+        let bind_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+
+        #[cfg(feature = "dtls-psk")]
+        let endpoint = if self.dtls_psk {
+            let key = PskKey::new(Some(TEST_PSK_IDENTITY), TEST_PSK_KEY);
+            server.set_psk_context(ServerPskContextBuilder::new(key).build())?;
+            server.add_endpoint_dtls(bind_addr)?
+        } else {
+            server.add_endpoint_udp(bind_addr)?
+        };
+        #[cfg(not(feature = "dtls-psk"))]
+        let endpoint = server.add_endpoint_udp(bind_addr)?;
+        let server_addr = server
+            .with_endpoint(endpoint, |endpoint| endpoint.local_addr())
+            .flatten()
+            .expect("newly created UDP endpoint has no local address");
+
+        let link = if self.link {
+            Some(CoapTestLink::new(server_addr).map_err(|e| TestPairError::Link(e.kind()))?)
+        } else {
+            None
+        };
+        let remote_addr = link.as_ref().map_or(server_addr, CoapTestLink::local_addr);
+
+        #[cfg(feature = "dtls-psk")]
+        let session = if self.dtls_psk {
+            let key = PskKey::new(Some(TEST_PSK_IDENTITY), TEST_PSK_KEY);
+            CoapClientSession::connect_dtls(&mut client, remote_addr, ClientPskContextBuilder::new(key).build())?
+        } else {
+            CoapClientSession::connect_udp(&mut client, remote_addr)?
+        };
+        #[cfg(not(feature = "dtls-psk"))]
+        let session = CoapClientSession::connect_udp(&mut client, remote_addr)?;
+
+        Ok(CoapTestPair {
+            server,
+            client,
+            session,
+            server_addr,
+            link,
+            step_timeout: self.step_timeout.max(Duration::from_millis(1)),
+        })
+    }
+}
+
+/// A server context and a client context with a session connected to it, driven from the same
+/// thread, see the [module documentation](crate::testing).
+#[derive(Debug)]
+pub struct CoapTestPair {
+    server: CoapContext<'static>,
+    client: CoapContext<'static>,
+    session: CoapClientSession<'static>,
+    server_addr: SocketAddr,
+    link: Option<CoapTestLink>,
+    step_timeout: Duration,
+}
+
+impl CoapTestPair {
+    /// Creates an unencrypted pair without a [CoapTestLink], see [CoapTestPairBuilder::build()].
+    pub fn new() -> Result<CoapTestPair, TestPairError> {
+        CoapTestPairBuilder::new().build()
+    }
+
+    /// Returns a builder for a pair with non-default settings.
+    pub fn builder() -> CoapTestPairBuilder {
+        CoapTestPairBuilder::new()
+    }
+
+    /// Returns the server context.
+    pub fn server(&mut self) -> &mut CoapContext<'static> {
+        &mut self.server
+    }
+
+    /// Returns the client context.
+    pub fn client(&mut self) -> &mut CoapContext<'static> {
+        &mut self.client
+    }
+
+    /// Returns the client session connected to the server (or to the [CoapTestLink]).
+    pub fn session(&self) -> &CoapClientSession<'static> {
+        &self.session
+    }
+
+    /// Returns the address of the server endpoint.
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server_addr
+    }
+
+    /// Returns the link datagrams are routed through, if the pair was created with one (see
+    /// [CoapTestPairBuilder::link()]).
+    pub fn link(&mut self) -> Option<&mut CoapTestLink> {
+        self.link.as_mut()
+    }
+
+    /// Adds a resource to the server context, see [CoapContext::add_resource()].
+    pub fn add_resource<D: Any + ?Sized + Debug>(
+        &mut self,
+        resource: CoapResource<D>,
+    ) -> CoapResourceHandle<'static, D> {
+        self.server.add_resource(resource)
+    }
+
+    /// Performs one round of IO: relays pending datagrams (if a link is used), performs IO for the
+    /// server context, relays the datagrams sent by the server and performs IO for the client
+    /// context.
+    ///
+    /// Each context waits for at most the step timeout (see [CoapTestPairBuilder::step_timeout()]).
+    ///
+    /// # Errors
+    /// Returns an [IoProcessError] if performing IO for one of the contexts failed or if the link
+    /// was unable to relay a datagram.
+    pub fn step(&mut self) -> Result<(), IoProcessError> {
+        self.pump_link()?;
+        self.server.do_io(Some(self.step_timeout))?;
+        self.pump_link()?;
+        self.client.do_io(Some(self.step_timeout))?;
+        Ok(())
+    }
+
+    /// Steps the pair until `predicate` returns true, performing at most `max_iters` steps.
+    ///
+    /// The predicate is checked before each step and once more after the last step. Returns
+    /// whether the predicate returned true.
+    ///
+    /// # Errors
+    /// Returns an [IoProcessError] if a step failed (see [CoapTestPair::step()]).
+    pub fn run_until<F: FnMut(&mut CoapTestPair) -> bool>(
+        &mut self,
+        mut predicate: F,
+        max_iters: usize,
+    ) -> Result<bool, IoProcessError> {
+        for _ in 0..max_iters {
+            if predicate(self) {
+                return Ok(true);
+            }
+            self.step()?;
+        }
+        Ok(predicate(self))
+    }
+
+    /// Sends the given request using the client session and steps the pair until the (first)
+    /// response to it has been received, like [CoapContext::send_and_wait()].
+    ///
+    /// At most 1000 steps are performed, use [CoapTestPair::request_with_limit()] to change this
+    /// limit.
+    ///
+    /// # Errors
+    /// See [CoapTestPair::request_with_limit()].
+    pub fn request(&mut self, request: CoapRequest) -> Result<CoapResponse, RequestWaitError> {
+        self.request_with_limit(request, DEFAULT_MAX_REQUEST_ITERATIONS)
+    }
+
+    /// Sends the given request using the client session and steps the pair until the (first)
+    /// response to it has been received, performing at most `max_iters` steps.
+    ///
+    /// The request handle is removed before returning, so further responses (e.g., notifications
+    /// if the request contained an Observe option) are rejected.
+    ///
+    /// # Errors
+    /// Returns [RequestWaitError::Send] if the request could not be sent,
+    /// [RequestWaitError::NoResponseExpected] if the request suppresses all responses,
+    /// [RequestWaitError::Request] if the request failed before a response was received,
+    /// [RequestWaitError::Timeout] if no response was received within `max_iters` steps and
+    /// [RequestWaitError::Io] if a step failed.
+    pub fn request_with_limit(
+        &mut self,
+        request: CoapRequest,
+        max_iters: usize,
+    ) -> Result<CoapResponse, RequestWaitError> {
+        let req_handle = self.session.send_request(request)?;
+        if !req_handle.expects_response() {
+            self.session.remove_handle(req_handle);
+            return Err(RequestWaitError::NoResponseExpected);
+        }
+        let mut iterations = 0;
+        let result = loop {
+            match self
+                .session
+                .try_poll_handle(&req_handle)
+                .map(|mut responses| responses.next())
+            {
+                Ok(Some(response)) => break Ok(response),
+                Ok(None) => {},
+                Err(e) => break Err(e.into()),
+            }
+            if iterations == max_iters {
+                break Err(RequestWaitError::Timeout);
+            }
+            iterations += 1;
+            if let Err(e) = self.step() {
+                break Err(e.into());
+            }
+        };
+        self.session.remove_handle(req_handle);
+        result
+    }
+
+    fn pump_link(&mut self) -> Result<(), IoProcessError> {
+        match &mut self.link {
+            Some(link) => link
+                .pump()
+                .map_err(|e| e.raw_os_error().map_or(IoProcessError::Unknown, IoProcessError::Os)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Asserts that the given response has the expected response code.
+///
+/// # Panics
+/// Panics if the code of the response differs from `expected`.
+#[track_caller]
+pub fn assert_response_code(response: &CoapResponse, expected: CoapResponseCode) {
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(expected),
+        "unexpected response code (response: {:?})",
+        response
+    );
+}
+
+/// Asserts that the handler of the given resource has received the expected number of requests
+/// with the given method so far (see [CoapResource::stats()]).
+///
+/// # Panics
+/// Panics if the number of requests differs from `expected`.
+#[track_caller]
+pub fn assert_requests_handled<D: Any + ?Sized + Debug>(
+    resource: &CoapResource<D>,
+    code: CoapRequestCode,
+    expected: u64,
+) {
+    let handled = resource.stats().requests.get(&code).copied().unwrap_or(0);
+    assert_eq!(
+        handled, expected,
+        "unexpected number of {:?} requests handled by resource",
+        code
+    );
+}
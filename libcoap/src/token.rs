@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * token.rs - Generators for the tokens of requests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing generators for the tokens of requests.
+//!
+//! Whenever the wrapper needs a fresh token for a request (i.e., for requests sent using
+//! [CoapSessionCommon::send_request()](crate::session::CoapSessionCommon::send_request) without
+//! an explicit token, including observe registrations, and for
+//! [CoapSessionCommon::new_token()](crate::session::CoapSessionCommon::new_token)), it consults
+//! the [CoapTokenGenerator] of the context (see
+//! [CoapContext::set_token_generator()](crate::CoapContext::set_token_generator)), which is a
+//! [RandomTokens] generator by default.
+//! Generated tokens that are still used by an outstanding request of the same session are
+//! discarded, and a new token is generated (at most [MAX_TOKEN_GENERATION_ATTEMPTS] times).
+//!
+//! Pings are empty messages and therefore never have a token. Tokens of requests that libcoap
+//! sends on its own (e.g., for the subsequent blocks of block-wise transfers) are still generated
+//! by libcoap, which increments the random initial token of the session (see
+//! [CoapSessionCommon::init_token()](crate::session::CoapSessionCommon::init_token)).
+//!
+//! libcoap does not allow setting the message IDs of sessions, but the initial message ID of each
+//! session is chosen randomly using libcoap's PRNG. For reproducible test runs, the PRNG can be
+//! seeded using [seed_coap_prng()](crate::prng::seed_coap_prng) (or replaced using
+//! `set_coap_prng()`) before creating any sessions, in which case the generated message IDs (and
+//! the tokens generated by [RandomTokens]) are deterministic as well.
+
+use std::fmt::Debug;
+
+use crate::{
+    prng::coap_prng_try_fill,
+    protocol::{CoapToken, DEFAULT_MAX_TOKEN_SIZE, MAX_EXTENDED_TOKEN_SIZE},
+    session::CoapSessionId,
+};
+
+/// Maximum number of tokens that are generated for a single request before giving up if all of
+/// them are already in use.
+pub const MAX_TOKEN_GENERATION_ATTEMPTS: usize = 16;
+
+/// Trait for types that generate the tokens of requests, see the [module documentation](self).
+pub trait CoapTokenGenerator: Debug {
+    /// Returns a new token for a request of the session with the given ID.
+    ///
+    /// `in_use` returns whether the given token is already used by an outstanding request of the
+    /// session, which allows generators to skip such tokens themselves. Tokens for which it
+    /// returns true are discarded by the wrapper either way.
+    ///
+    /// Returning None (e.g., because no random bytes could be obtained) causes the request to be
+    /// rejected with [MessageConversionError::TokenGenerationFailed](crate::error::MessageConversionError::TokenGenerationFailed).
+    fn generate(&mut self, session: CoapSessionId, in_use: &dyn Fn(&[u8]) -> bool) -> Option<CoapToken>;
+}
+
+/// The default [CoapTokenGenerator], which generates random tokens of a fixed length.
+///
+/// Random bytes are obtained using [coap_prng_try_fill()], i.e., libcoap's PRNG, which is
+/// cryptographically secure unless libcoap had to fall back to `rand()`.
+/// In contrast to libcoap's own tokens (which increment the initial token of each session),
+/// random tokens do not reveal how many requests were sent before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RandomTokens {
+    len: usize,
+}
+
+impl RandomTokens {
+    /// Creates a generator for random tokens of the given length (in bytes).
+    ///
+    /// Tokens that are longer than [DEFAULT_MAX_TOKEN_SIZE] bytes require extended token support
+    /// (see [CoapContext::set_max_token_size()](crate::CoapContext::set_max_token_size)).
+    ///
+    /// # Panics
+    /// Panics if `len` is zero or larger than [MAX_EXTENDED_TOKEN_SIZE].
+    pub fn new(len: usize) -> RandomTokens {
+        assert!(
+            (1..=MAX_EXTENDED_TOKEN_SIZE).contains(&len),
+            "invalid length for random tokens: {}",
+            len
+        );
+        RandomTokens { len }
+    }
+
+    /// Returns the length of the generated tokens.
+    pub fn token_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Default for RandomTokens {
+    /// Creates a generator for random tokens of [DEFAULT_MAX_TOKEN_SIZE] bytes.
+    fn default() -> Self {
+        RandomTokens::new(DEFAULT_MAX_TOKEN_SIZE)
+    }
+}
+
+impl CoapTokenGenerator for RandomTokens {
+    fn generate(&mut self, _session: CoapSessionId, _in_use: &dyn Fn(&[u8]) -> bool) -> Option<CoapToken> {
+        let mut token = vec![0; self.len];
+        coap_prng_try_fill(&mut token).ok()?;
+        Some(token.into_boxed_slice())
+    }
+}
+
+/// [CoapTokenGenerator] that generates consecutive tokens, e.g., to obtain reproducible wire
+/// captures in tests.
+///
+/// Tokens are the 8 byte big-endian representation of a counter that is shared between all
+/// sessions of the context and skips values that are already in use. As sequential tokens are
+/// predictable, they should not be used in production (see
+/// [RFC 7252, Section 5.3.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.3.1)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SequentialTokens {
+    next: u64,
+}
+
+impl SequentialTokens {
+    /// Creates a generator whose first token is the representation of `first`.
+    pub fn new(first: u64) -> SequentialTokens {
+        SequentialTokens { next: first }
+    }
+
+    /// Returns the counter value of the next token that will be generated.
+    pub fn next_value(&self) -> u64 {
+        self.next
+    }
+}
+
+impl CoapTokenGenerator for SequentialTokens {
+    fn generate(&mut self, _session: CoapSessionId, in_use: &dyn Fn(&[u8]) -> bool) -> Option<CoapToken> {
+        loop {
+            let token = self.next.to_be_bytes();
+            self.next = self.next.wrapping_add(1);
+            if !in_use(&token) {
+                return Some(Box::from(token));
+            }
+        }
+    }
+}
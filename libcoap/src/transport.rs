@@ -7,25 +7,111 @@
  * See the README as well as the LICENSE file for more information.
  */
 
-use std::{net::SocketAddr, os::raw::c_uint};
+use std::{
+    ffi::CStr,
+    net::SocketAddr,
+    os::raw::c_uint,
+    sync::atomic::{AtomicU64, Ordering},
+};
+#[cfg(all(feature = "af-unix", unix))]
+use std::{
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
 
 use libcoap_sys::{
-    coap_endpoint_set_default_mtu, coap_endpoint_t, coap_free_endpoint, coap_new_endpoint, coap_proto_t,
+    coap_endpoint_set_default_mtu, coap_endpoint_str, coap_endpoint_t, coap_free_endpoint, coap_new_endpoint,
+    coap_proto_t,
 };
 
-use crate::{error::EndpointCreationError, types::CoapAddress, CoapContext};
+#[cfg(all(feature = "af-unix", unix))]
+use crate::error::UnixSocketPathError;
+use crate::{
+    crypto::transport_supported,
+    error::EndpointCreationError,
+    types::{is_unscoped_link_local, CoapAddress, CoapProtocol},
+    CoapContext,
+};
 
 pub type EndpointMtu = c_uint;
 
+/// Whether an endpoint bound to an IPv6 address also receives IPv4 traffic, see
+/// [CoapContext::add_endpoint_udp_ipv6()].
+///
+/// libcoap binds endpoint sockets itself and always clears `IPV6_V6ONLY` before doing so (if the
+/// platform supports this option), so endpoints bound to the unspecified IPv6 address (`[::]`) are
+/// always dual-stack. IPv4 peers of such endpoints are represented using IPv4-mapped IPv6
+/// addresses (e.g., `[::ffff:192.0.2.1]:5683`). In order to only receive IPv6 traffic, bind the
+/// endpoint to a specific IPv6 address of the host instead.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CoapIpv6Mode {
+    /// The endpoint receives both IPv6 and IPv4 traffic (`IPV6_V6ONLY` is cleared before binding).
+    #[default]
+    DualStack,
+}
+
+impl CoapIpv6Mode {
+    /// Returns whether endpoints using this mode can be created on the current platform.
+    ///
+    /// [CoapIpv6Mode::DualStack] is not supported on platforms on which IPv6 sockets are always
+    /// IPv6-only (OpenBSD).
+    pub fn is_supported(&self) -> bool {
+        match self {
+            CoapIpv6Mode::DualStack => !cfg!(target_os = "openbsd"),
+        }
+    }
+}
+
+/// Counter used to assign endpoint handles, see [CoapEndpointHandle].
+static NEXT_ENDPOINT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+/// Handle referring to an endpoint of a [CoapContext].
+///
+/// Handles are returned when adding an endpoint to a context (e.g., using
+/// [CoapContext::add_endpoint_udp()]) and can be used to refer to this endpoint later on (e.g., in
+/// [CoapContext::rebind_endpoint()]).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CoapEndpointHandle(u64);
+
+impl CoapEndpointHandle {
+    fn next() -> CoapEndpointHandle {
+        CoapEndpointHandle(NEXT_ENDPOINT_HANDLE.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// An endpoint of a [CoapContext], i.e., a socket that the context listens on for incoming
+/// requests.
+///
+/// Endpoints are owned by their context and can be accessed using [CoapContext::with_endpoint()].
 #[derive(Debug)]
 pub struct CoapEndpoint {
+    /// Raw endpoint, or null for the endpoint libcoap created for the listen address of the
+    /// context (see [CoapContext::new_with_listen_addr()]), which libcoap does not expose and frees
+    /// alongside the context.
     raw_endpoint: *mut coap_endpoint_t,
+    handle: CoapEndpointHandle,
+    proto: CoapProtocol,
+    /// Socket address this endpoint is actually bound to (None for Unix domain socket endpoints).
+    socket_addr: Option<SocketAddr>,
+    /// Human-readable representation of the local address (or socket path) of this endpoint.
+    local_addr: String,
+    /// Path of the socket file for Unix domain socket endpoints, removed when the endpoint is
+    /// dropped.
+    #[cfg(all(feature = "af-unix", unix))]
+    unix_path: Option<PathBuf>,
 }
 
 /// Trait for functions common between all types of endpoints.
 impl CoapEndpoint {
     /// Sets the default MTU value of the endpoint.
+    ///
+    /// Has no effect on the listen endpoint of a context (see
+    /// [CoapContext::new_with_listen_addr()]), as libcoap does not expose that endpoint.
     pub fn set_default_mtu(&mut self, mtu: EndpointMtu) {
+        if self.is_listen_endpoint() {
+            return;
+        }
         // SAFETY: as_mut_raw_endpoint cannot fail and will always return a valid reference.
         // Modifying the state of the endpoint is also fine, because we have a mutable reference
         // of the whole endpoint.
@@ -34,33 +120,195 @@ impl CoapEndpoint {
         }
     }
 
+    /// Returns the transport protocol used by this endpoint.
+    pub fn proto(&self) -> CoapProtocol {
+        self.proto
+    }
+
+    /// Returns the handle referring to this endpoint.
+    pub fn handle(&self) -> CoapEndpointHandle {
+        self.handle
+    }
+
+    /// Returns the socket address this endpoint is bound to, or None for Unix domain socket
+    /// endpoints.
+    ///
+    /// If the endpoint was created for port 0, the returned address contains the port that was
+    /// chosen by the operating system.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket_addr
+    }
+
+    /// Returns a human-readable representation of the local address (or Unix socket path) this
+    /// endpoint is bound to.
+    pub(crate) fn display_addr(&self) -> &str {
+        &self.local_addr
+    }
+
+    /// Returns whether this is the endpoint libcoap created for the listen address of the context,
+    /// which is owned by libcoap and can therefore neither be removed nor rebound.
+    pub(crate) fn is_listen_endpoint(&self) -> bool {
+        self.raw_endpoint.is_null()
+    }
+
+    /// Creates the representation of the UDP endpoint libcoap created for the listen address of
+    /// the context (see [CoapContext::new_with_listen_addr()]), which is bound to `addr`.
+    pub(crate) fn listen_endpoint(addr: SocketAddr) -> Self {
+        Self {
+            raw_endpoint: std::ptr::null_mut(),
+            handle: CoapEndpointHandle::next(),
+            proto: CoapProtocol::Udp,
+            socket_addr: Some(addr),
+            local_addr: addr.to_string(),
+            #[cfg(all(feature = "af-unix", unix))]
+            unix_path: None,
+        }
+    }
+
     /// Method utilized by transport protocol specific constructors to actually create the endpoint in libcoap
     pub(crate) fn new_endpoint(
         context: &mut CoapContext,
         addr: SocketAddr,
         proto: coap_proto_t,
     ) -> Result<Self, EndpointCreationError> {
+        check_bind_addr(addr)?;
+        let raw_endpoint = Self::new_raw_endpoint(context, &CoapAddress::from(addr), proto)?;
+        // SAFETY: The raw endpoint was just created and is therefore valid.
+        let addr = unsafe { bound_socket_addr(raw_endpoint) }.unwrap_or(addr);
+        Ok(Self {
+            raw_endpoint,
+            handle: CoapEndpointHandle::next(),
+            proto: proto.into(),
+            socket_addr: Some(addr),
+            local_addr: addr.to_string(),
+            #[cfg(all(feature = "af-unix", unix))]
+            unix_path: None,
+        })
+    }
+
+    /// Creates a new endpoint bound to the Unix domain socket at the given `path`.
+    ///
+    /// Stale socket files at the given path are removed before creating the endpoint, the socket
+    /// file is removed again once the endpoint is dropped.
+    #[cfg(all(feature = "af-unix", unix))]
+    pub(crate) fn new_unix_endpoint(context: &mut CoapContext, path: &Path) -> Result<Self, EndpointCreationError> {
+        let addr = prepare_unix_socket_path(path)?;
+        let raw_endpoint = Self::new_raw_endpoint(context, &addr, coap_proto_t::COAP_PROTO_UDP)?;
+        Ok(Self {
+            raw_endpoint,
+            handle: CoapEndpointHandle::next(),
+            proto: CoapProtocol::Udp,
+            socket_addr: None,
+            local_addr: path.display().to_string(),
+            unix_path: Some(path.to_path_buf()),
+        })
+    }
+
+    fn new_raw_endpoint(
+        context: &mut CoapContext,
+        addr: &CoapAddress,
+        proto: coap_proto_t,
+    ) -> Result<*mut coap_endpoint_t, EndpointCreationError> {
+        if !transport_supported(proto) {
+            return Err(EndpointCreationError::TransportUnsupported(proto.into()));
+        }
         let endpoint = unsafe {
             // SAFETY: coap_new_endpoint will return null if it is unable to add new endpoint.
             // These states are processed further in the code
-            coap_new_endpoint(
-                context.as_mut_raw_context(),
-                CoapAddress::from(addr).as_raw_address(),
-                proto,
-            )
+            coap_new_endpoint(context.as_mut_raw_context(), addr.as_raw_address(), proto)
         };
 
         if endpoint.is_null() {
-            Err(EndpointCreationError::Unknown)
+            Err(endpoint_creation_failure())
         } else {
-            Ok(Self { raw_endpoint: endpoint })
+            Ok(endpoint)
+        }
+    }
+}
+
+/// Checks that the given address can be bound to without ending up with a socket of an
+/// unexpected address family or on an unexpected interface.
+pub(crate) fn check_bind_addr(addr: SocketAddr) -> Result<(), EndpointCreationError> {
+    if let SocketAddr::V6(addr) = addr {
+        // On dual-stack sockets, binding to an IPv4-mapped address would silently create an
+        // endpoint that only receives IPv4 traffic.
+        if addr.ip().to_ipv4_mapped().is_some() {
+            return Err(EndpointCreationError::Ipv4MappedAddress);
         }
     }
+    if is_unscoped_link_local(&addr) {
+        return Err(EndpointCreationError::MissingScopeId);
+    }
+    Ok(())
+}
+
+/// Reads back the socket address the given raw endpoint is bound to.
+///
+/// The definition of `coap_endpoint_t` is not part of libcoap's public API, so the address is
+/// parsed from the description returned by `coap_endpoint_str()` (which has the format
+/// `<address> <protocol>`, with the address being updated by libcoap after binding the socket).
+/// Returns None if the description could not be parsed.
+///
+/// # Safety
+/// `raw_endpoint` must point to a valid endpoint.
+unsafe fn bound_socket_addr(raw_endpoint: *const coap_endpoint_t) -> Option<SocketAddr> {
+    // coap_endpoint_str() writes into a static buffer, so its contents are copied immediately.
+    let description = CStr::from_ptr(coap_endpoint_str(raw_endpoint))
+        .to_str()
+        .ok()?
+        .to_owned();
+    description.split_whitespace().next()?.parse().ok()
+}
+
+/// Returns the error that should be reported if libcoap was unable to create an endpoint.
+///
+/// libcoap does not report why endpoint creation failed, but the most likely cause is that
+/// creating or binding the socket failed, in which case `errno` is still set accordingly.
+pub(crate) fn endpoint_creation_failure() -> EndpointCreationError {
+    let error = std::io::Error::last_os_error();
+    if error.raw_os_error().unwrap_or(0) == 0 {
+        return EndpointCreationError::Unknown;
+    }
+    io_failure(error.kind())
+}
+
+/// Returns the error that should be reported if creating or binding an endpoint socket failed with
+/// the given kind of OS error.
+fn io_failure(kind: std::io::ErrorKind) -> EndpointCreationError {
+    match kind {
+        std::io::ErrorKind::AddrInUse => EndpointCreationError::AddressInUse,
+        std::io::ErrorKind::PermissionDenied => EndpointCreationError::PermissionDenied,
+        kind => EndpointCreationError::Io(kind),
+    }
+}
+
+/// Converts the given `path` into an address for a Unix domain socket, removing any stale socket
+/// file at that path.
+///
+/// Files at that path that are not sockets are never removed.
+#[cfg(all(feature = "af-unix", unix))]
+pub(crate) fn prepare_unix_socket_path(path: &Path) -> Result<CoapAddress, UnixSocketPathError> {
+    let addr = CoapAddress::unix(path)?;
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(|e| UnixSocketPathError::Io(e.kind()))?
+        },
+        Ok(_) => return Err(UnixSocketPathError::NotASocket),
+        Err(_) => {},
+    }
+    Ok(addr)
 }
 
 impl Drop for CoapEndpoint {
     fn drop(&mut self) {
-        // SAFETY: Raw endpoint is guaranteed to exist for as long as the container exists.
-        unsafe { coap_free_endpoint(self.raw_endpoint) }
+        if !self.is_listen_endpoint() {
+            // SAFETY: Raw endpoint is guaranteed to exist for as long as the container exists.
+            unsafe { coap_free_endpoint(self.raw_endpoint) }
+        }
+        #[cfg(all(feature = "af-unix", unix))]
+        if let Some(path) = &self.unix_path {
+            // If the file was already removed by someone else, there is nothing left to do.
+            let _ = std::fs::remove_file(path);
+        }
     }
 }
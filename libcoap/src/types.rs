@@ -9,6 +9,8 @@
 
 //! Types required for conversion between libcoap C library abstractions and Rust types.
 
+use std::borrow::Cow;
+use std::convert::Infallible;
 use std::ffi::CString;
 use std::fmt::{Display, Formatter};
 use std::marker::PhantomPinned;
@@ -16,34 +18,49 @@ use std::pin::Pin;
 use std::{
     fmt::Debug,
     mem::MaybeUninit,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs},
-    os::raw::c_int,
+    net::{SocketAddr, ToSocketAddrs},
+    os::raw::{c_int, c_ushort},
     str::FromStr,
 };
+#[cfg(all(feature = "af-unix", unix))]
+use std::{
+    os::raw::c_char,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
 
-use libc::{c_ushort, in6_addr, in_addr, sa_family_t, sockaddr_in, sockaddr_in6, socklen_t, AF_INET, AF_INET6};
+#[cfg(all(feature = "af-unix", unix))]
+use libc::{sa_family_t, socklen_t, AF_UNIX};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use socket2::SockAddr;
 #[cfg(feature = "url")]
 use url::Url;
 
+#[cfg(all(feature = "af-unix", unix))]
+use libcoap_sys::coap_sockaddr_un;
 use libcoap_sys::coap_uri_scheme_t::{COAP_URI_SCHEME_COAPS_WS, COAP_URI_SCHEME_COAP_WS};
 use libcoap_sys::{
     coap_address_t, coap_delete_optlist, coap_mid_t, coap_proto_t,
-    coap_proto_t::{COAP_PROTO_DTLS, COAP_PROTO_NONE, COAP_PROTO_TCP, COAP_PROTO_TLS, COAP_PROTO_UDP},
+    coap_proto_t::{
+        COAP_PROTO_DTLS, COAP_PROTO_NONE, COAP_PROTO_TCP, COAP_PROTO_TLS, COAP_PROTO_UDP, COAP_PROTO_WS, COAP_PROTO_WSS,
+    },
     coap_split_proxy_uri, coap_split_uri, coap_str_const_t, coap_string_equal, coap_uri_into_options,
     coap_uri_scheme_t,
     coap_uri_scheme_t::{
         COAP_URI_SCHEME_COAP, COAP_URI_SCHEME_COAPS, COAP_URI_SCHEME_COAPS_TCP, COAP_URI_SCHEME_COAP_TCP,
         COAP_URI_SCHEME_HTTP, COAP_URI_SCHEME_HTTPS,
     },
-    coap_uri_t, COAP_URI_SCHEME_SECURE_MASK,
+    coap_uri_t, COAPS_DEFAULT_PORT, COAP_DEFAULT_PORT, COAP_URI_SCHEME_SECURE_MASK,
 };
 
-use crate::context::ensure_coap_started;
+use crate::crypto::transport_supported;
+#[cfg(all(feature = "af-unix", unix))]
+use crate::error::UnixSocketPathError;
 use crate::error::UriParsingError;
 use crate::message::CoapOption;
 use crate::protocol::UriPort;
+use crate::startup::ensure_coap_started;
 
 /// Interface index used internally by libcoap to refer to an endpoint.
 pub type IfIndex = c_int;
@@ -52,10 +69,54 @@ pub type MaxRetransmit = c_ushort;
 /// Identifier for a CoAP message.
 pub type CoapMessageId = coap_mid_t;
 
-/// Internal wrapper for the raw coap_address_t type, mainly used for conversion between types.
-pub(crate) struct CoapAddress(coap_address_t);
+/// Ownership of a raw libcoap object that is wrapped by one of the safe wrapper types (see
+/// [CoapContext::from_raw()](crate::CoapContext::from_raw) and
+/// [CoapClientSession::from_raw_external()](crate::session::CoapClientSession::from_raw_external)).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Ownership {
+    /// The raw object remains owned by the caller, who is responsible for freeing it once the
+    /// wrapper has been dropped.
+    Borrowed,
+    /// Ownership of the raw object is transferred to the wrapper, which frees it when it is
+    /// dropped.
+    Owned,
+}
+
+/// Address of a CoAP endpoint or session, i.e., either an IP socket address or (if the `af-unix`
+/// feature is enabled) the path of a Unix domain socket.
+///
+/// Wraps the raw [coap_address_t] and is mainly used for conversion between types. IP socket
+/// addresses can be converted from and into [SocketAddr]s (see [CoapAddress::socket_addr()]).
+pub struct CoapAddress(coap_address_t);
 
 impl CoapAddress {
+    /// Returns this address as a [SocketAddr], or None if it is not an IP socket address (i.e.,
+    /// if it refers to a Unix domain socket).
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.to_sock_addr().as_socket()
+    }
+
+    /// Returns the path of the Unix domain socket this address refers to, or None if it is not a
+    /// Unix domain socket address.
+    ///
+    /// This function is only available if the `af-unix` feature is enabled (on Unix platforms).
+    #[cfg(all(feature = "af-unix", unix))]
+    pub fn unix_path(&self) -> Option<PathBuf> {
+        if !self.to_sock_addr().is_unix() {
+            return None;
+        }
+        // SAFETY: The address is a Unix domain socket address, i.e., the union contains a
+        // coap_sockaddr_un (whose remaining bytes are zeroed if the path is shorter than sun_path).
+        let raw_unix_addr = unsafe { self.0.addr.cun.as_ref() };
+        let path_bytes: Vec<u8> = raw_unix_addr
+            .sun_path
+            .iter()
+            .take_while(|v| **v != 0)
+            .map(|v| *v as u8)
+            .collect();
+        Some(PathBuf::from(std::ffi::OsString::from_vec(path_bytes)))
+    }
+
     /// Returns a reference to the underlying raw [coap_address_t].
     pub(crate) fn as_raw_address(&self) -> &coap_address_t {
         &self.0
@@ -68,9 +129,9 @@ impl CoapAddress {
     /// If you want to get the coap_address_t safely, use [into_raw_address()](CoapAddress::into_raw_address()).
     ///
     /// # Safety
-    /// The underlying [coap_address_t] must always refer to a valid instance of sockaddr_in or
-    /// sockaddr_in6, and [coap_address_t::size] must always be the correct size of the sockaddr
-    /// in the [coap_address_t::addr] field.
+    /// The underlying [coap_address_t] must always refer to a valid instance of sockaddr_in,
+    /// sockaddr_in6 (or sockaddr_un), and [coap_address_t::size] must always be the correct size of
+    /// the sockaddr in the [coap_address_t::addr] field.
     // Kept for consistency
     #[allow(dead_code)]
     pub(crate) unsafe fn as_mut_raw_address(&mut self) -> &mut coap_address_t {
@@ -85,113 +146,143 @@ impl CoapAddress {
     }
 }
 
+impl CoapAddress {
+    /// Returns a copy of the underlying socket address in the platform-independent representation
+    /// of [socket2], which takes care of the platform-specific `sockaddr` layouts.
+    fn to_sock_addr(&self) -> SockAddr {
+        let len = (self.0.size as usize).min(std::mem::size_of_val(&self.0.addr));
+        // SAFETY: That the underlying value of addr is a valid sockaddr of the given size is an
+        // invariant, the only way the value could be invalid is if as_mut_coap_address_t() (an
+        // unsafe function) is used incorrectly. The union in coap_address_t is at most as large
+        // as sockaddr_storage, which is what try_init() provides.
+        let ((), sock_addr) = unsafe {
+            SockAddr::try_init(|storage, storage_len| {
+                std::ptr::copy_nonoverlapping(std::ptr::addr_of!(self.0.addr).cast::<u8>(), storage.cast::<u8>(), len);
+                *storage_len = len as _;
+                Ok(())
+            })
+        }
+        .expect("copying a socket address cannot fail");
+        sock_addr
+    }
+}
+
 impl ToSocketAddrs for CoapAddress {
     type Iter = std::option::IntoIter<SocketAddr>;
 
     fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
-        // SAFETY: That the underlying value of addr is a valid sockaddr is an invariant, the only
-        // way the value could be invalid is if as_mut_coap_address_t() (an unsafe function) is used
-        // incorrectly.
-        let socketaddr = match unsafe { self.0.addr.sa.as_ref().sa_family } as i32 {
-            AF_INET => {
-                // SAFETY: Validity of addr is an invariant, and we checked that the type of the
-                // underlying sockaddr is actually sockaddr_in.
-                let raw_addr = unsafe { self.0.addr.sin.as_ref() };
-                SocketAddrV4::new(
-                    Ipv4Addr::from(raw_addr.sin_addr.s_addr.to_ne_bytes()),
-                    u16::from_be(raw_addr.sin_port),
-                )
-                .into()
-            },
-            AF_INET6 => {
-                // SAFETY: Validity of addr is an invariant, and we checked that the type of the
-                // underlying sockaddr is actually sockaddr_in6.
-                let raw_addr = unsafe { self.0.addr.sin6.as_ref() };
-                SocketAddrV6::new(
-                    Ipv6Addr::from(raw_addr.sin6_addr.s6_addr),
-                    u16::from_be(raw_addr.sin6_port),
-                    raw_addr.sin6_flowinfo,
-                    raw_addr.sin6_scope_id,
-                )
-                .into()
-            },
+        let sock_addr = self.to_sock_addr();
+        match sock_addr.as_socket() {
+            Some(socketaddr) => Ok(Some(socketaddr).into_iter()),
+            // Unix domain socket addresses can't be represented as a SocketAddr.
+            #[cfg(all(feature = "af-unix", unix))]
+            None if sock_addr.is_unix() => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix domain socket addresses can not be converted into socket addresses",
+            )),
             // This should not happen as long as the invariants are kept.
-            _ => panic!("sa_family_t of underlying coap_address_t is invalid!"),
-        };
-        Ok(Some(socketaddr).into_iter())
+            None => panic!("sa_family_t of underlying coap_address_t is invalid!"),
+        }
+    }
+}
+
+#[cfg(all(feature = "af-unix", unix))]
+impl CoapAddress {
+    /// Returns the maximum length of a Unix domain socket path supported by libcoap.
+    pub fn max_unix_path_len() -> usize {
+        // SAFETY: coap_sockaddr_un consists only of plain integers, for which all-zero is valid.
+        let raw_addr: coap_sockaddr_un = unsafe { std::mem::zeroed() };
+        // One byte is reserved for the terminating null byte.
+        raw_addr.sun_path.len() - 1
+    }
+
+    /// Creates a new address referring to the Unix domain socket at the given `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the path is longer than [CoapAddress::max_unix_path_len()] or contains
+    /// a null byte.
+    pub fn unix(path: &Path) -> Result<CoapAddress, UnixSocketPathError> {
+        let path_bytes = path.as_os_str().as_bytes();
+        if path_bytes.len() > Self::max_unix_path_len() {
+            return Err(UnixSocketPathError::TooLong(Self::max_unix_path_len()));
+        }
+        if path_bytes.contains(&0) {
+            return Err(UnixSocketPathError::ContainsNullByte);
+        }
+        // addr is a bindgen-type union wrapper, so we can't assign to it directly and have
+        // to use a pointer instead.
+        // SAFETY: addr is not read before it is assigned properly, the path length was checked
+        // above (and the remaining bytes of sun_path are already zeroed).
+        unsafe {
+            let mut coap_addr = coap_address_t {
+                size: std::mem::size_of::<coap_sockaddr_un>() as socklen_t,
+                addr: std::mem::zeroed(),
+            };
+            let raw_unix_addr = coap_addr.addr.cun.as_mut();
+            raw_unix_addr.sun_family = AF_UNIX as sa_family_t;
+            for (dst, src) in raw_unix_addr.sun_path.iter_mut().zip(path_bytes) {
+                *dst = *src as c_char;
+            }
+            Ok(CoapAddress(coap_addr))
+        }
+    }
+}
+
+impl Clone for CoapAddress {
+    fn clone(&self) -> Self {
+        CoapAddress::from(&self.0)
+    }
+}
+
+impl Debug for CoapAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(addr) = self.socket_addr() {
+            return f.debug_tuple("CoapAddress").field(&addr).finish();
+        }
+        #[cfg(all(feature = "af-unix", unix))]
+        if let Some(path) = self.unix_path() {
+            return f.debug_tuple("CoapAddress").field(&path).finish();
+        }
+        f.debug_tuple("CoapAddress").finish_non_exhaustive()
     }
 }
 
 impl From<SocketAddr> for CoapAddress {
     fn from(addr: SocketAddr) -> Self {
-        match addr {
-            SocketAddr::V4(addr) => {
-                // addr is a bindgen-type union wrapper, so we can't assign to it directly and have
-                // to use a pointer instead.
-                // SAFETY: addr is not read before it is assigned properly, assignment cannot fail.
-                unsafe {
-                    let mut coap_addr = coap_address_t {
-                        size: std::mem::size_of::<sockaddr_in>() as socklen_t,
-                        addr: std::mem::zeroed(),
-                    };
-
-                    *coap_addr.addr.sin.as_mut() = sockaddr_in {
-                        #[cfg(any(
-                            target_os = "freebsd",
-                            target_os = "dragonfly",
-                            target_os = "openbsd",
-                            target_os = "netbsd",
-                            target_os = "aix",
-                            target_os = "haiku",
-                            target_os = "hurd",
-                            target_os = "espidf",
-                        ))]
-                        sin_len: (std::mem::size_of::<sockaddr_in>() as u8),
-                        sin_family: AF_INET as sa_family_t,
-                        sin_port: addr.port().to_be(),
-                        sin_addr: in_addr {
-                            s_addr: u32::from_ne_bytes(addr.ip().octets()),
-                        },
-                        sin_zero: Default::default(),
-                    };
-                    CoapAddress(coap_addr)
-                }
-            },
-            SocketAddr::V6(addr) => {
-                // addr is a bindgen-type union wrapper, so we can't assign to it directly and have
-                // to use a pointer instead.
-                // SAFETY: addr is not read before it is assigned properly, assignment cannot fail.
-                unsafe {
-                    let mut coap_addr = coap_address_t {
-                        size: std::mem::size_of::<sockaddr_in6>() as socklen_t,
-                        addr: std::mem::zeroed(),
-                    };
-
-                    *coap_addr.addr.sin6.as_mut() = sockaddr_in6 {
-                        #[cfg(any(
-                            target_os = "freebsd",
-                            target_os = "dragonfly",
-                            target_os = "openbsd",
-                            target_os = "netbsd",
-                            target_os = "aix",
-                            target_os = "haiku",
-                            target_os = "hurd",
-                            target_os = "espidf",
-                        ))]
-                        sin6_len: (std::mem::size_of::<sockaddr_in6>() as u8),
-                        sin6_family: AF_INET6 as sa_family_t,
-                        sin6_port: addr.port().to_be(),
-                        sin6_addr: in6_addr {
-                            s6_addr: addr.ip().octets(),
-                        },
-                        sin6_flowinfo: addr.flowinfo(),
-                        sin6_scope_id: addr.scope_id(),
-                    };
-
-                    CoapAddress(coap_addr)
-                }
-            },
+        // socket2 creates the sockaddr_in(6) in the layout of the current platform (including,
+        // e.g., the length fields used by BSDs or the Winsock layout on Windows).
+        let sock_addr = SockAddr::from(addr);
+        let len = sock_addr.len() as usize;
+        // SAFETY: coap_address_t consists only of plain integers, for which all-zero is valid.
+        let mut coap_addr: coap_address_t = unsafe { std::mem::zeroed() };
+        assert!(
+            len <= std::mem::size_of_val(&coap_addr.addr),
+            "socket address does not fit into coap_address_t"
+        );
+        // addr is a bindgen-type union wrapper, so we can't assign to it directly and have to use
+        // a pointer instead.
+        // SAFETY: sock_addr points to a valid socket address of the given length, which fits into
+        // the union of coap_address_t (checked above).
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                sock_addr.as_ptr().cast::<u8>(),
+                std::ptr::addr_of_mut!(coap_addr.addr).cast::<u8>(),
+                len,
+            );
         }
+        coap_addr.size = sock_addr.len() as _;
+        CoapAddress(coap_addr)
+    }
+}
+
+/// Returns whether the given address is an IPv6 link-local address without a zone (scope) ID.
+///
+/// Such addresses can neither be bound to nor connected to, as the interface they refer to is
+/// ambiguous.
+pub(crate) fn is_unscoped_link_local(addr: &SocketAddr) -> bool {
+    match addr {
+        SocketAddr::V6(addr) => (addr.ip().segments()[0] & 0xffc0) == 0xfe80 && addr.scope_id() == 0,
+        SocketAddr::V4(_) => false,
     }
 }
 
@@ -235,6 +326,16 @@ impl CoapUriScheme {
     pub fn from_raw_scheme(scheme: coap_uri_scheme_t) -> CoapUriScheme {
         FromPrimitive::from_u32(scheme as u32).expect("unknown scheme")
     }
+
+    /// Returns the port that is used for URIs with this scheme if they do not specify one.
+    pub fn default_port(self) -> UriPort {
+        match self {
+            CoapUriScheme::Coap | CoapUriScheme::CoapTcp => COAP_DEFAULT_PORT as UriPort,
+            CoapUriScheme::Coaps | CoapUriScheme::CoapsTcp => COAPS_DEFAULT_PORT as UriPort,
+            CoapUriScheme::Http | CoapUriScheme::CoapWs => 80,
+            CoapUriScheme::Https | CoapUriScheme::CoapsWs => 443,
+        }
+    }
 }
 
 impl FromStr for CoapUriScheme {
@@ -283,6 +384,8 @@ impl From<CoapProtocol> for CoapUriScheme {
             CoapProtocol::Dtls => CoapUriScheme::Coaps,
             CoapProtocol::Tcp => CoapUriScheme::CoapTcp,
             CoapProtocol::Tls => CoapUriScheme::CoapsTcp,
+            CoapProtocol::Ws => CoapUriScheme::CoapWs,
+            CoapProtocol::Wss => CoapUriScheme::CoapsWs,
         }
     }
 }
@@ -311,6 +414,27 @@ impl From<CoapProtocol> for CoapUriScheme {
 /// # Result::<(), UriParsingError>::Ok(())
 /// ```
 ///
+/// Path segments and query components can be obtained in their percent-decoded form, and IPv6
+/// addresses (including zone identifiers) are supported as hosts:
+/// ```
+/// use libcoap_rs::error::UriParsingError;
+/// use libcoap_rs::types::{CoapUri, CoapUriScheme};
+///
+/// let uri: CoapUri = "coaps+tcp://[fe80::1%25eth0]/a%20b/c?unit=%C2%B0C&flag".parse()?;
+///
+/// assert_eq!(uri.host(), Some("fe80::1%25eth0".as_bytes()));
+/// assert_eq!(uri.decoded_host().as_deref(), Some("fe80::1%eth0"));
+/// assert_eq!(uri.port(), Some(CoapUriScheme::CoapsTcp.default_port()));
+/// assert_eq!(uri.path_segments().collect::<Vec<_>>(), vec!["a b", "c"]);
+/// assert_eq!(
+///     uri.query_pairs().collect::<Vec<_>>(),
+///     vec![("unit".into(), Some("°C".into())), ("flag".into(), None)]
+/// );
+/// assert_eq!(uri.to_string(), "coaps+tcp://[fe80::1%25eth0]/a%20b/c?unit=%C2%B0C&flag");
+///
+/// # Result::<(), UriParsingError>::Ok(())
+/// ```
+///
 /// Alternatively, a [CoapUri] may be constructed from its parts using [CoapUri::new] or
 /// [CoapUri::new_relative] or from a [Url] (requires the `url` feature), refer to the method level
 /// documentation for more information.
@@ -546,7 +670,19 @@ impl CoapUri {
         Some(unsafe { std::slice::from_raw_parts(raw_str.s, raw_str.length) })
     }
 
+    /// Returns the percent-decoded host part of this URI.
+    ///
+    /// In contrast to [CoapUri::host()], percent-encoded characters are decoded, e.g., the zone
+    /// identifier of the IPv6 address in `coap://[fe80::1%25eth0]` is returned as `fe80::1%eth0`.
+    /// As with [CoapUri::host()], IPv6 addresses are returned without the enclosing brackets.
+    pub fn decoded_host(&self) -> Option<Cow<'_, str>> {
+        self.host().map(|host| utf8_lossy(percent_decode(host)))
+    }
+
     /// Returns the port of this URI (if provided).
+    ///
+    /// When parsing an absolute URI without a port, libcoap sets the port to the default port
+    /// of the URI scheme, see also [CoapUri::port_or_default()].
     pub fn port(&self) -> Option<UriPort> {
         match self.raw_uri.port {
             0 => None,
@@ -554,6 +690,14 @@ impl CoapUri {
         }
     }
 
+    /// Returns the port of this URI, or the default port of its scheme (see
+    /// [CoapUriScheme::default_port()]) if the URI does not contain a port.
+    ///
+    /// Returns None only for relative URIs without a port.
+    pub fn port_or_default(&self) -> Option<UriPort> {
+        self.port().or_else(|| self.scheme().map(CoapUriScheme::default_port))
+    }
+
     /// Returns the URI path part of this URI.
     pub fn path(&self) -> Option<&[u8]> {
         let raw_str = self.raw_uri.path;
@@ -578,6 +722,41 @@ impl CoapUri {
         Some(unsafe { std::slice::from_raw_parts(raw_str.s, raw_str.length) })
     }
 
+    /// Returns the percent-decoded segments of the path of this URI.
+    ///
+    /// Invalid UTF-8 sequences in the decoded segments are replaced with U+FFFD.
+    pub fn path_segments(&self) -> impl Iterator<Item = Cow<'_, str>> + '_ {
+        self.path()
+            .filter(|path| !path.is_empty())
+            .into_iter()
+            .flat_map(|path| path.split(|c| *c == b'/'))
+            .map(|segment| utf8_lossy(percent_decode(segment)))
+    }
+
+    /// Returns the components of the query of this URI as percent-decoded key-value pairs.
+    ///
+    /// Each query component is split at the first `=` before key and value are percent-decoded,
+    /// components without a `=` are returned with a value of None.
+    /// Invalid UTF-8 sequences in the decoded values are replaced with U+FFFD.
+    pub fn query_pairs(&self) -> impl Iterator<Item = (Cow<'_, str>, Option<Cow<'_, str>>)> + '_ {
+        self.query_components()
+            .map(|component| match component.iter().position(|c| *c == b'=') {
+                Some(pos) => (
+                    utf8_lossy(percent_decode(&component[..pos])),
+                    Some(utf8_lossy(percent_decode(&component[pos + 1..]))),
+                ),
+                None => (utf8_lossy(percent_decode(component)), None),
+            })
+    }
+
+    /// Returns the raw (i.e., still percent-encoded) components of the query of this URI.
+    pub(crate) fn query_components(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.query()
+            .filter(|query| !query.is_empty())
+            .into_iter()
+            .flat_map(|query| query.split(|c| *c == b'&'))
+    }
+
     /// Returns whether this URI is a proxy URI.
     pub fn is_proxy(&self) -> bool {
         self.is_proxy
@@ -794,13 +973,20 @@ impl CoapUri {
         path: &[u8],
         query: &[u8],
     ) -> Result<(CString, usize, usize, usize), UriParsingError> {
+        // IPv6 addresses have to be enclosed in brackets.
+        let bracketed = host.contains(&b':') && !host.starts_with(b"[");
         // Reconstruct string for scheme.
-        let scheme = if !host.is_empty() {
-            format!("{}://", scheme)
-        } else {
-            String::new()
+        let scheme = match (host.is_empty(), bracketed) {
+            (true, _) => String::new(),
+            (false, false) => format!("{}://", scheme),
+            (false, true) => format!("{}://[", scheme),
+        };
+        let port = match (port, bracketed) {
+            (0, false) => String::new(),
+            (0, true) => "]".to_string(),
+            (port, false) => format!(":{}", port),
+            (port, true) => format!("]:{}", port),
         };
-        let port = if port != 0 { format!(":{}", port) } else { String::new() };
         let parts = [scheme.as_bytes(), host, port.as_bytes(), path, query];
         let uri_str_len = parts.iter().map(|v| v.len()).sum::<usize>();
 
@@ -867,31 +1053,148 @@ impl FromStr for CoapUri {
     }
 }
 
+impl TryFrom<&str> for CoapUri {
+    type Error = UriParsingError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        CoapUri::try_from_str(value)
+    }
+}
+
+impl TryFrom<&String> for CoapUri {
+    type Error = UriParsingError;
+
+    fn try_from(value: &String) -> Result<Self, Self::Error> {
+        CoapUri::try_from_str(value)
+    }
+}
+
+impl From<&CoapUri> for CoapUri {
+    fn from(value: &CoapUri) -> Self {
+        value.clone()
+    }
+}
+
+impl From<Infallible> for UriParsingError {
+    fn from(value: Infallible) -> Self {
+        match value {}
+    }
+}
+
 impl Display for CoapUri {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.uri_str.fmt(f)
+        // The URI string may not be a valid URI for instances created from raw URIs (see
+        // from_raw_uri()), so it is reconstructed from its parts instead.
+        if let (Some(scheme), Some(host)) = (self.scheme(), self.host()) {
+            let host = String::from_utf8_lossy(host);
+            if host.contains(':') {
+                write!(f, "{}://[{}]", scheme, host)?;
+            } else {
+                write!(f, "{}://{}", scheme, host)?;
+            }
+            if let Some(port) = self.port().filter(|port| *port != scheme.default_port()) {
+                write!(f, ":{}", port)?;
+            }
+        }
+        write!(f, "/{}", String::from_utf8_lossy(self.path().unwrap_or(&[])))?;
+        if let Some(query) = self.query() {
+            write!(f, "?{}", String::from_utf8_lossy(query))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes all valid percent-encoded octets in `value`, leaving invalid sequences unchanged.
+pub(crate) fn percent_decode(value: &[u8]) -> Cow<'_, [u8]> {
+    if !value.contains(&b'%') {
+        return Cow::Borrowed(value);
+    }
+    let hex_digit = |index: usize| value.get(index).and_then(|c| (*c as char).to_digit(16));
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut index = 0;
+    while index < value.len() {
+        match (value[index], hex_digit(index + 1), hex_digit(index + 2)) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                index += 3;
+            },
+            (byte, ..) => {
+                decoded.push(byte);
+                index += 1;
+            },
+        }
+    }
+    Cow::Owned(decoded)
+}
+
+/// Converts the given bytes into a string, replacing invalid UTF-8 sequences with U+FFFD.
+pub(crate) fn utf8_lossy(value: Cow<'_, [u8]>) -> Cow<'_, str> {
+    match value {
+        Cow::Borrowed(value) => String::from_utf8_lossy(value),
+        Cow::Owned(value) => match String::from_utf8(value) {
+            Ok(value) => Cow::Owned(value),
+            Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        },
     }
 }
 
 /// Transport protocols that can be used with libcoap.
 #[repr(u32)]
 #[non_exhaustive]
-#[derive(Copy, Clone, FromPrimitive, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, FromPrimitive, PartialEq, Eq, Hash, Debug)]
 pub enum CoapProtocol {
     None = COAP_PROTO_NONE as u32,
     Udp = COAP_PROTO_UDP as u32,
     Dtls = COAP_PROTO_DTLS as u32,
     Tcp = COAP_PROTO_TCP as u32,
     Tls = COAP_PROTO_TLS as u32,
+    Ws = COAP_PROTO_WS as u32,
+    Wss = COAP_PROTO_WSS as u32,
 }
 
 impl CoapProtocol {
     pub fn is_secure(&self) -> bool {
         match self {
-            CoapProtocol::None | CoapProtocol::Udp | CoapProtocol::Tcp => false,
-            CoapProtocol::Dtls | CoapProtocol::Tls => true,
+            CoapProtocol::None | CoapProtocol::Udp | CoapProtocol::Tcp | CoapProtocol::Ws => false,
+            CoapProtocol::Dtls | CoapProtocol::Tls | CoapProtocol::Wss => true,
+        }
+    }
+
+    pub fn is_reliable(&self) -> bool {
+        match self {
+            CoapProtocol::None | CoapProtocol::Udp | CoapProtocol::Dtls => false,
+            CoapProtocol::Tcp | CoapProtocol::Tls | CoapProtocol::Ws | CoapProtocol::Wss => true,
+        }
+    }
+
+    /// Returns whether libcoap-rs was compiled with support for this transport, i.e., whether
+    /// the functions for creating sessions and endpoints using this transport are available.
+    ///
+    /// Transports are enabled using the crate features of the same name (`tcp`, `tls`,
+    /// `websockets`), encrypted transports additionally require at least one of the `dtls-psk`,
+    /// `dtls-pki` and `dtls-rpk` features.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            CoapProtocol::None => false,
+            CoapProtocol::Udp => true,
+            CoapProtocol::Dtls => cfg!(dtls),
+            CoapProtocol::Tcp => cfg!(feature = "tcp"),
+            CoapProtocol::Tls => cfg!(tls),
+            CoapProtocol::Ws => cfg!(feature = "websockets"),
+            CoapProtocol::Wss => cfg!(all(feature = "websockets", dtls)),
         }
     }
+
+    /// Returns whether this transport can be used, i.e., whether it is enabled (see
+    /// [CoapProtocol::is_enabled()]) and supported by the libcoap library in use.
+    ///
+    /// If libcoap is linked dynamically, the features the library was built with are only known
+    /// at runtime. Creating sessions or endpoints for transports that are enabled but not
+    /// supported will fail with [SessionCreationError::TransportUnsupported](crate::error::SessionCreationError::TransportUnsupported)
+    /// or [EndpointCreationError::TransportUnsupported](crate::error::EndpointCreationError::TransportUnsupported).
+    pub fn is_supported(&self) -> bool {
+        self.is_enabled() && transport_supported((*self).into())
+    }
 }
 
 #[doc(hidden)]
@@ -901,6 +1204,21 @@ impl From<coap_proto_t> for CoapProtocol {
     }
 }
 
+#[doc(hidden)]
+impl From<CoapProtocol> for coap_proto_t {
+    fn from(value: CoapProtocol) -> Self {
+        match value {
+            CoapProtocol::None => COAP_PROTO_NONE,
+            CoapProtocol::Udp => COAP_PROTO_UDP,
+            CoapProtocol::Dtls => COAP_PROTO_DTLS,
+            CoapProtocol::Tcp => COAP_PROTO_TCP,
+            CoapProtocol::Tls => COAP_PROTO_TLS,
+            CoapProtocol::Ws => COAP_PROTO_WS,
+            CoapProtocol::Wss => COAP_PROTO_WSS,
+        }
+    }
+}
+
 impl Display for CoapProtocol {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -909,6 +1227,8 @@ impl Display for CoapProtocol {
             CoapProtocol::Dtls => "dtls",
             CoapProtocol::Tcp => "tcp",
             CoapProtocol::Tls => "tls",
+            CoapProtocol::Ws => "ws",
+            CoapProtocol::Wss => "wss",
         })
     }
 }
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * unwind.rs - Helpers for handling panics in callbacks invoked by libcoap.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+//! Module containing helpers that prevent panics in callbacks from unwinding into libcoap.
+//!
+//! Unwinding across the FFI boundary is undefined behavior, so all functions that are called by
+//! libcoap catch panics of the Rust code they invoke and return a fallback value instead.
+//! The payload of the most recent caught panic is recorded in the state of the context the
+//! callback was invoked for and can be retrieved using
+//! [CoapContext::take_last_handler_panic()](crate::CoapContext::take_last_handler_panic).
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use libcoap_sys::{coap_context_t, coap_session_get_context, coap_session_t};
+
+use crate::CoapContext;
+
+/// Calls `f`, returning `fallback` (and recording the panic payload for `raw_context`) if it
+/// panics.
+///
+/// `raw_context` may be null for callbacks that are not tied to a context, in which case the
+/// payload of a caught panic is discarded.
+///
+/// State that is accessed by `f` is not poisoned by a panic, callers are therefore responsible
+/// for leaving it in a consistent state (which is the case for all wrapper types of this crate).
+///
+/// # Safety
+/// `raw_context` must either be null or point to a valid raw context.
+pub(crate) unsafe fn catch_callback_panic<R>(
+    raw_context: *mut coap_context_t,
+    fallback: R,
+    f: impl FnOnce() -> R,
+) -> R {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        record_panic(raw_context, payload);
+        fallback
+    })
+}
+
+/// Calls `f`, returning `fallback` (and recording the panic payload for the context of
+/// `raw_session`) if it panics, see [catch_callback_panic()].
+///
+/// # Safety
+/// `raw_session` must either be null or point to a valid raw session.
+pub(crate) unsafe fn catch_session_callback_panic<R>(
+    raw_session: *mut coap_session_t,
+    fallback: R,
+    f: impl FnOnce() -> R,
+) -> R {
+    let raw_context = if raw_session.is_null() {
+        std::ptr::null_mut()
+    } else {
+        coap_session_get_context(raw_session)
+    };
+    catch_callback_panic(raw_context, fallback, f)
+}
+
+/// Records the payload of a caught panic for the given raw context, replacing any previously
+/// recorded payload.
+///
+/// The payload is discarded if `raw_context` is null or not managed by this wrapper.
+///
+/// # Safety
+/// `raw_context` must either be null or point to a valid raw context.
+pub(crate) unsafe fn record_panic(raw_context: *mut coap_context_t, payload: Box<dyn Any + Send>) {
+    if !raw_context.is_null() {
+        *CoapContext::shared_state_of_raw(raw_context)
+            .last_handler_panic
+            .borrow_mut() = Some(payload);
+    }
+}
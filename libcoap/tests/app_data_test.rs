@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * app_data_test.rs - Tests for application data attached to contexts, sessions and resources.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use libcoap_rs::{
+    error::{ContextGetAppDataError, ResourceUserDataError, SessionGetAppDataError},
+    message::{CoapMessageCommon, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+/// Application data that records when it is dropped.
+struct DropTracker(Rc<Cell<bool>>);
+
+impl Drop for DropTracker {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+pub fn session_app_data() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let server_data_dropped = Rc::new(Cell::new(false));
+    let resource = CoapResource::new("test1", Rc::clone(&server_data_dropped), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |dropped: &mut Rc<Cell<bool>>, sess: &mut CoapServerSession, _req, mut rsp: CoapResponse| {
+                if sess.app_data::<DropTracker>().unwrap().is_none() {
+                    sess.set_app_data(Some(DropTracker(Rc::clone(dropped))));
+                }
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert!(session.app_data::<u32>().unwrap().is_none());
+    session.set_app_data(Some(42u32));
+    assert_eq!(*session.app_data::<u32>().unwrap().unwrap(), 42);
+    // Requesting the wrong type is reported instead of panicking.
+    assert_eq!(
+        session.app_data::<String>().unwrap_err(),
+        SessionGetAppDataError::WrongType
+    );
+
+    // Replacing or clearing the data drops the previous value.
+    let client_data_dropped = Rc::new(Cell::new(false));
+    session.set_app_data(Some(DropTracker(Rc::clone(&client_data_dropped))));
+    session.clear_app_data();
+    assert!(client_data_dropped.get());
+    session.set_app_data(Some(DropTracker(Rc::clone(&client_data_dropped))));
+    client_data_dropped.set(false);
+
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert!(!server_data_dropped.get());
+
+    // Data of client sessions is dropped alongside the session, data of server sessions once the
+    // context owning them is dropped.
+    std::mem::drop(session);
+    assert!(client_data_dropped.get());
+    std::mem::drop(server_context);
+    assert!(server_data_dropped.get());
+}
+
+#[test]
+pub fn context_app_data() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    server_context.set_app_data(Some(RefCell::new(0u32)));
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess: &mut CoapServerSession, _req, mut rsp: CoapResponse| {
+                assert_eq!(
+                    sess.context_app_data::<String>().unwrap_err(),
+                    ContextGetAppDataError::WrongType
+                );
+                let counter = sess.context_app_data::<RefCell<u32>>().unwrap().unwrap();
+                *counter.borrow_mut() += 1;
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    assert!(context.app_data::<u32>().unwrap().is_none());
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    for _ in 0..2 {
+        let req_handle = session.send_request(common::gen_test_request()).unwrap();
+        common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    }
+    assert_eq!(*server_context.app_data::<RefCell<u32>>().unwrap().unwrap().borrow(), 2);
+
+    // The data is dropped alongside the context.
+    let dropped = Rc::new(Cell::new(false));
+    server_context.set_app_data(Some(DropTracker(Rc::clone(&dropped))));
+    std::mem::drop(session);
+    std::mem::drop(server_context);
+    assert!(dropped.get());
+}
+
+#[test]
+pub fn typed_resource_user_data() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+
+    let config = CoapResource::new("config", String::from("step=2"), false);
+    server_context.add_resource(config);
+    let config = server_context.typed_resource_by_uri_path::<String>("config").unwrap();
+    assert!(server_context.typed_resource_by_uri_path::<u64>("config").is_none());
+    assert!(server_context.typed_resource_by_uri_path::<String>("unknown").is_none());
+
+    let counter = CoapResource::new("counter", 0u64, false);
+    counter.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new_resource_ref(
+            move |resource: &CoapResource<u64>, sess, _, mut rsp: CoapResponse| {
+                // The user data of other resources can be accessed from within handlers.
+                let step: u64 = config.user_data().trim_start_matches("step=").parse().unwrap();
+                let mut count = resource.user_data_mut();
+                // Nested borrows of the same user data fail instead of panicking.
+                assert_eq!(
+                    resource.try_user_data().unwrap_err(),
+                    ResourceUserDataError::BorrowedMutably
+                );
+                *count += step;
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(count.to_string().into_bytes()));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(counter);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = || {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["counter"])
+            .build()
+            .unwrap()
+    };
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request());
+    assert_eq!(response.data(), Some("2".as_bytes()));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request());
+    assert_eq!(response.data(), Some("4".as_bytes()));
+
+    let counter = server_context.typed_resource_by_uri_path::<u64>("counter").unwrap();
+    assert_eq!(*counter.user_data(), 4);
+    *counter.try_user_data_mut().unwrap() = 10;
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request());
+    assert_eq!(response.data(), Some("12".as_bytes()));
+    let guard = counter.user_data();
+    assert_eq!(
+        counter.try_user_data_mut().unwrap_err(),
+        ResourceUserDataError::Borrowed
+    );
+    std::mem::drop(guard);
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * async_test.rs - Tests for asynchronous (separate) responses.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(feature = "async")]
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use libcoap_rs::{
+    error::AsyncRequestError,
+    message::{CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+    session::{
+        CoapAsyncHandle, CoapAsyncState, CoapClientSession, CoapServerSession, CoapSessionCloseReason,
+        CoapSessionCommon,
+    },
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn async_responses() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let handles: Rc<RefCell<Vec<CoapAsyncHandle>>> = Rc::default();
+    let resource = CoapResource::new("async", handles.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            move |handles: &mut Rc<RefCell<Vec<CoapAsyncHandle>>>,
+                  sess: &mut CoapServerSession,
+                  req: &CoapRequest,
+                  mut rsp: CoapResponse| {
+                if req.is_async_replay() {
+                    rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                    rsp.set_data(Some("replayed".as_bytes().to_vec()));
+                    sess.send(rsp).unwrap();
+                    return;
+                }
+                let delay = req.query_value("delay").map(|_| Duration::from_millis(100));
+                handles.borrow_mut().push(req.clone().into_async(sess, delay).unwrap());
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    // Requests are acknowledged with an empty ACK and answered once the handle is completed.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["async"])
+        .confirmable(true)
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    common::run_until(&mut [&mut server_context, &mut context], "async request", |_| {
+        (!handles.borrow().is_empty()).then_some(())
+    });
+    let handle = handles.borrow_mut().pop().unwrap();
+    assert_eq!(handle.state(), CoapAsyncState::Pending);
+    assert_eq!(handle.request().code(), CoapMessageCode::Request(CoapRequestCode::Get));
+    let mut response = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content).unwrap();
+    response.set_data(Some("completed".as_bytes().to_vec()));
+    handle.complete(response).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert!(!response.is_piggybacked());
+    assert_eq!(response.response_code(), Some(CoapResponseCode::Content));
+    assert_eq!(response.data(), Some("completed".as_bytes()));
+    session.remove_handle(req_handle);
+
+    // Once the delay has expired, the request is passed to the request handler again.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["async"])
+        .uri_query([("delay", "")])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.data(), Some("replayed".as_bytes()));
+    assert_eq!(handles.borrow_mut().pop().unwrap().state(), CoapAsyncState::Completed);
+
+    // Requests of sessions that are disconnected are abandoned.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["async"])
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    common::run_until(&mut [&mut server_context, &mut context], "async request", |_| {
+        (!handles.borrow().is_empty()).then_some(())
+    });
+    let handle = handles.borrow_mut().pop().unwrap();
+    let abandoned = Rc::new(Cell::new(false));
+    let abandoned_callback = abandoned.clone();
+    handle.set_abandon_callback(move || abandoned_callback.set(true));
+    let server_session = server_context.session_by_peer(session.addr_local()).unwrap();
+    assert_eq!(server_session.id(), handle.session_id());
+    server_session.disconnect(CoapSessionCloseReason::Abort);
+    std::mem::drop(server_session);
+    assert!(abandoned.get());
+    assert_eq!(handle.state(), CoapAsyncState::Abandoned);
+    assert_eq!(handle.trigger(), Err(AsyncRequestError::SessionClosed));
+    server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+    session.remove_handle(req_handle);
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * cache_test.rs - Tests for the server-side and client-side response caches.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use libcoap_rs::{
+    cache::CoapCacheEntry,
+    error::CacheError,
+    message::{CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapMessageType, CoapOptionType, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn response_cache() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    // Requests that only differ in their query are served by the same cache entry.
+    server_context
+        .cache_ignore_options(&[CoapOptionType::UriQuery.to_raw_option_num()])
+        .unwrap();
+    let backend_hits = Rc::new(Cell::new(0u32));
+    let resource = CoapResource::new("test1", Rc::clone(&backend_hits), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |backend_hits: &mut Rc<Cell<u32>>,
+             sess: &mut CoapServerSession,
+             req: &CoapRequest,
+             mut rsp: CoapResponse| {
+                let cached = CoapCacheEntry::lookup(sess, req, false)
+                    .unwrap()
+                    .and_then(|entry| entry.app_data::<Vec<u8>>());
+                let data = match cached {
+                    Some(data) => data.as_ref().clone(),
+                    None => {
+                        backend_hits.set(backend_hits.get() + 1);
+                        let data = format!("computed {} time(s)", backend_hits.get()).into_bytes();
+                        let entry = CoapCacheEntry::create(sess, req, false, Some(Duration::from_secs(60))).unwrap();
+                        entry.set_app_data(data.clone()).unwrap();
+                        assert_eq!(
+                            CoapCacheEntry::create(sess, req, false, None).unwrap_err(),
+                            CacheError::AlreadyExists
+                        );
+                        data
+                    },
+                };
+                rsp.set_data(Some(data));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let queried_request = CoapRequest::new(
+        CoapMessageType::Con,
+        CoapRequestCode::Get,
+        "/test1?a=b".parse().unwrap(),
+    )
+    .unwrap();
+    for request in [common::gen_test_request(), common::gen_test_request(), queried_request] {
+        let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+        assert_eq!(response.data().unwrap().as_ref(), b"computed 1 time(s)");
+    }
+    assert_eq!(backend_hits.get(), 1);
+}
+
+#[test]
+pub fn client_response_cache() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let handler = || {
+        CoapRequestHandler::new(
+            |(count, max_age): &mut (Rc<Cell<u32>>, Duration), sess, _req, mut rsp: CoapResponse| {
+                count.set(count.get() + 1);
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(count.get().to_string().into_bytes()));
+                sess.send(rsp.with_max_age(*max_age)).unwrap();
+            },
+        )
+    };
+    let fresh_count = Rc::new(Cell::new(0));
+    let fresh_resource = CoapResource::new("fresh", (fresh_count.clone(), Duration::from_secs(60)), false);
+    fresh_resource.set_method_handler(CoapRequestCode::Get, Some(handler()));
+    server_context.add_resource(fresh_resource);
+    let stale_count = Rc::new(Cell::new(0));
+    // Responses of this resource need to be revalidated right away.
+    let stale_resource = CoapResource::new("stale", (stale_count.clone(), Duration::ZERO), false);
+    stale_resource.set_etag_from_content("stale".as_bytes());
+    stale_resource.set_method_handler(CoapRequestCode::Get, Some(handler()));
+    server_context.add_resource(stale_resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.response_cache_capacity(), None);
+    session.set_response_cache_capacity(Some(1));
+    assert_eq!(session.response_cache_capacity(), Some(1));
+    let get = |path: &str| CoapRequestBuilder::new(CoapRequestCode::Get).uri_path([path]);
+
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        get("fresh").build().unwrap(),
+    );
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+    assert_eq!(response.max_age(), Some(60));
+
+    // Fresh responses are returned without contacting the server.
+    let req_handle = session.send_request(get("fresh").build().unwrap()).unwrap();
+    let response = session.poll_handle(&req_handle).next().unwrap();
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+    assert!(response.max_age().unwrap() <= 60);
+    session.remove_handle(req_handle);
+    assert_eq!(fresh_count.get(), 1);
+
+    // Requests can bypass the cache.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        get("fresh").bypass_cache().build().unwrap(),
+    );
+    assert_eq!(response.data().unwrap().as_ref(), "2".as_bytes());
+    assert_eq!(fresh_count.get(), 2);
+
+    // Stale responses are revalidated using their ETag (evicting the least recently used entry).
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        get("stale").build().unwrap(),
+    );
+    assert_eq!(response.max_age(), Some(0));
+    assert!(response.etag().is_some());
+    let req_handle = session.send_request(get("stale").build().unwrap()).unwrap();
+    assert!(session.poll_handle(&req_handle).next().is_none());
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+    assert_eq!(stale_count.get(), 1);
+
+    // The response for the "fresh" resource has been evicted.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        get("fresh").build().unwrap(),
+    );
+    assert_eq!(response.data().unwrap().as_ref(), "3".as_bytes());
+    assert_eq!(fresh_count.get(), 3);
+
+    session.clear_response_cache();
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        get("fresh").build().unwrap(),
+    );
+    assert_eq!(response.data().unwrap().as_ref(), "4".as_bytes());
+}
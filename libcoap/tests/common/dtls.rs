@@ -39,6 +39,12 @@ pub fn dtls_client_server_request_common<KTY: KeyType, FC, FS>(
     #[cfg(feature = "dtls-pki")]
     context.set_pki_root_ca_paths(Some("./resources/test-keys/ca/ca.crt.pem"), None::<PathBuf>);
     let session = CoapClientSession::connect_dtls(&mut context, server_address, client_crypto_ctx).unwrap();
+    context
+        .wait_for_session_established(&session, Duration::from_secs(10))
+        .expect("DTLS session was not established");
+    let crypto_info = session.crypto_info().expect("DTLS session has no crypto information");
+    assert!(crypto_info.is_transport_secure());
+    assert!(!crypto_info.raw_tls_session().is_null());
 
     let request = common::gen_test_request();
     let req_handle = session.send_request(request).unwrap();
@@ -15,12 +15,12 @@ use std::rc::Rc;
 use std::sync::{Arc, Condvar, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use libcoap_rs::{CoapContext, CoapRequestHandler, CoapResource};
 use libcoap_rs::message::{CoapMessageCommon, CoapRequest, CoapResponse};
 use libcoap_rs::protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode};
-use libcoap_rs::session::CoapSessionCommon;
+use libcoap_rs::session::{CoapClientSession, CoapRequestHandle, CoapSessionCommon};
 use libcoap_sys::{coap_dtls_set_log_level, coap_log_t, coap_set_log_level};
 
 pub(crate) fn get_unused_server_addr() -> SocketAddr {
@@ -115,3 +115,52 @@ pub(crate) fn gen_test_request() -> CoapRequest {
 
     CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Get, uri).unwrap()
 }
+
+/// Runs the given contexts until `poll` returns a value, failing the test if this takes longer
+/// than ten seconds.
+///
+/// `poll` is called with the contexts after each round of IO, so that it can inspect their state.
+/// All contexts have to share the same lifetime, as [CoapContext] is invariant over it.
+pub(crate) fn run_until<'a, T>(
+    contexts: &mut [&mut CoapContext<'a>],
+    what: &str,
+    mut poll: impl FnMut(&mut [&mut CoapContext<'a>]) -> Option<T>,
+) -> T {
+    let start = Instant::now();
+    loop {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "timeout while waiting for {}",
+            what
+        );
+        for context in contexts.iter_mut() {
+            context.do_io(Some(Duration::from_millis(10))).unwrap();
+        }
+        if let Some(value) = poll(contexts) {
+            return value;
+        }
+    }
+}
+
+/// Runs both contexts until the next response for the given request handle has been received.
+pub(crate) fn wait_for_response<'a>(
+    server_context: &mut CoapContext<'a>,
+    context: &mut CoapContext<'a>,
+    session: &CoapClientSession,
+    req_handle: &CoapRequestHandle,
+) -> CoapResponse {
+    run_until(&mut [server_context, context], "response", |_| {
+        session.poll_handle(req_handle).next()
+    })
+}
+
+/// Sends the given request and runs both contexts until the response has been received.
+pub(crate) fn exchange_request<'a>(
+    server_context: &mut CoapContext<'a>,
+    context: &mut CoapContext<'a>,
+    session: &CoapClientSession,
+    request: CoapRequest,
+) -> CoapResponse {
+    let req_handle = session.send_request(request).unwrap();
+    wait_for_response(server_context, context, session, &req_handle)
+}
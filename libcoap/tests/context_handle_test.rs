@@ -0,0 +1,444 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * context_handle_test.rs - Tests for controlling a CoAP context from other threads.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(any(unix, windows))]
+
+use std::cell::Cell;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{error::ContextHandleError, CoapContext, CoapJobDropAction, CoapJobHandle, CoapResource, RunOptions};
+
+mod common;
+
+#[test]
+pub fn queued_operations_interrupt_io() {
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(common::get_unused_server_addr()).unwrap();
+    let handle = context.handle().unwrap();
+    let executed = Arc::new(AtomicBool::new(false));
+
+    let worker = {
+        let handle = handle.clone();
+        let executed = Arc::clone(&executed);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle
+                .add_resource_with(|| CoapResource::new("test1", (), true))
+                .unwrap();
+            handle.notify_observers("test1").unwrap();
+            handle
+                .execute(move |_context| executed.store(true, Ordering::SeqCst))
+                .unwrap();
+            handle.request_stop().unwrap();
+        })
+    };
+
+    // Without being interrupted by the worker, each call would wait for a minute.
+    let start = Instant::now();
+    while !context.stop_requested() {
+        context.do_io(Some(Duration::from_secs(60))).unwrap();
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "do_io() was not interrupted by queued operations"
+        );
+    }
+    worker.join().unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert!(executed.load(Ordering::SeqCst));
+
+    std::mem::drop(context);
+    assert_eq!(handle.request_stop().unwrap_err(), ContextHandleError::ContextDropped);
+    assert_eq!(
+        handle.notify_observers("test1").unwrap_err(),
+        ContextHandleError::ContextDropped
+    );
+}
+
+#[test]
+pub fn run_until_stopped() {
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(common::get_unused_server_addr()).unwrap();
+    let stop_handle = context.stop_handle().unwrap();
+    let iterations = Cell::new(0);
+
+    let worker = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        stop_handle.stop();
+    });
+    let start = Instant::now();
+    context
+        .run(RunOptions::new().idle_callback(|_context| iterations.set(iterations.get() + 1)))
+        .unwrap();
+    // Without the stop handle interrupting it, run() would wait for IO indefinitely.
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(iterations.get() > 0);
+    worker.join().unwrap();
+    assert!(!context.stop_requested());
+
+    // Without outstanding IO, a requested shutdown stops the loop immediately.
+    let stop_handle = context.stop_handle().unwrap();
+    stop_handle.request_shutdown();
+    context
+        .run(RunOptions::new().io_timeout(Duration::from_millis(10)))
+        .unwrap();
+    context.shutdown(Some(Duration::from_secs(0))).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+pub fn split_io_processing() {
+    use libcoap_rs::{
+        error::IoProcessError,
+        io::{CoapSocketInterest, CoapSocketReadiness},
+        message::{CoapMessageCommon, CoapResponse},
+        protocol::{CoapRequestCode, CoapResponseCode},
+        session::{CoapClientSession, CoapSessionCommon},
+        CoapRequestHandler,
+    };
+
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_, session, _request, mut response: CoapResponse| {
+                response.set_code(CoapResponseCode::Content);
+                response.set_data(Some("split".as_bytes().to_vec()));
+                session.send(response).unwrap();
+            },
+        )),
+    );
+    context.add_resource(resource);
+    let handle = context.handle().unwrap();
+    match context.prepare_io() {
+        // libcoap was built without epoll support.
+        Err(IoProcessError::SplitIoUnsupported) => return,
+        Err(e) => panic!("unable to prepare IO: {}", e),
+        Ok((sockets, _)) => {
+            // The epoll file descriptor of libcoap and the file descriptor of the handle.
+            assert_eq!(sockets.len(), 2);
+            assert!(sockets.iter().all(|v| v.interest() == CoapSocketInterest::READ));
+        },
+    }
+
+    let client = std::thread::spawn(move || {
+        let mut context = CoapContext::new().unwrap();
+        let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+        let response = context
+            .send_and_wait(&session, common::gen_test_request(), Duration::from_secs(10))
+            .unwrap();
+        handle.request_stop().unwrap();
+        response.data().unwrap().to_vec()
+    });
+
+    let start = Instant::now();
+    while !context.stop_requested() {
+        assert!(start.elapsed() < Duration::from_secs(10), "request was not answered");
+        let (sockets, timeout) = context.prepare_io().unwrap();
+        let mut fds: Vec<_> = sockets
+            .iter()
+            .map(|socket| libc::pollfd {
+                fd: socket.raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let timeout = timeout.map_or(1000, |v| v.as_millis().min(1000) as i32);
+        // SAFETY: fds contains valid pollfd structs and has the provided length.
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+        let ready: Vec<_> = fds
+            .iter()
+            .filter(|fd| fd.revents != 0)
+            .map(|fd| CoapSocketReadiness::new(fd.fd, fd.revents & libc::POLLIN != 0, false))
+            .collect();
+        context.complete_io(&ready).unwrap();
+    }
+    assert_eq!(client.join().unwrap(), "split".as_bytes());
+}
+
+#[test]
+pub fn job_handles_complete_from_other_threads() {
+    use libcoap_rs::{
+        message::{CoapMessageCommon, CoapRequest},
+        protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+        session::{CoapClientSession, CoapSessionCommon},
+    };
+
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("firmware", String::from("idle"))
+        .get(|resource, session, _request, mut response| {
+            response.set_code(CoapResponseCode::Content);
+            response.set_data(Some(resource.user_data().as_bytes().to_vec()));
+            session.send(response).unwrap();
+        })
+        .post(|resource, session, request, mut response| {
+            let mode = request.query_value("mode").map(|v| v.into_owned());
+            let mut job = match mode {
+                #[cfg(feature = "async")]
+                Some(_) => {
+                    let async_request = request.clone().into_async(session, None).unwrap();
+                    CoapJobHandle::start_with_response(session, resource, async_request).unwrap()
+                },
+                _ => CoapJobHandle::start(session, resource).unwrap(),
+            };
+            assert_eq!(job.drop_action(), CoapJobDropAction::ServiceUnavailable);
+            let abandon = mode.as_deref() == Some("abandon");
+            if abandon {
+                job.set_drop_action(CoapJobDropAction::CancelObservers);
+            }
+            std::thread::spawn(move || {
+                if abandon {
+                    return;
+                }
+                job.update(|resource| *resource.user_data_mut() = String::from("verifying"))
+                    .unwrap();
+                job.complete(|resource, response| {
+                    *resource.user_data_mut() = String::from("verified");
+                    response.set_data(Some("done".as_bytes().to_vec()));
+                })
+                .unwrap();
+            });
+            if mode.is_none() {
+                response.set_code(CoapResponseCode::Created);
+                session.send(response).unwrap();
+            }
+        })
+        .build()
+        .unwrap();
+    context.add_resource(resource);
+    let handle = context.handle().unwrap();
+
+    let client = std::thread::spawn(move || {
+        let mut context = CoapContext::new().unwrap();
+        let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+        let mut send = |code, uri: &str| {
+            let request = CoapRequest::new(CoapMessageType::Con, code, uri.parse().unwrap()).unwrap();
+            context
+                .send_and_wait(&session, request, Duration::from_secs(10))
+                .unwrap()
+        };
+        // The POST handler answers right away, the job is completed in the background.
+        let response = send(CoapRequestCode::Post, "/firmware");
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Created));
+        let start = Instant::now();
+        loop {
+            let response = send(CoapRequestCode::Get, "/firmware");
+            if response.data().unwrap().as_ref() == "verified".as_bytes() {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "job was not completed");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        #[cfg(feature = "async")]
+        {
+            // Deferred responses are sent once the job is completed.
+            let response = send(CoapRequestCode::Post, "/firmware?mode=defer");
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            assert_eq!(response.data().unwrap().as_ref(), "done".as_bytes());
+            // Jobs whose handles are dropped answer their requests with 5.03.
+            let response = send(CoapRequestCode::Post, "/firmware?mode=abandon");
+            assert_eq!(
+                response.code(),
+                CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable)
+            );
+        }
+        handle.request_stop().unwrap();
+    });
+
+    common::run_until(&mut [&mut context], "client", |contexts| {
+        contexts[0].stop_requested().then_some(())
+    });
+    client.join().unwrap();
+}
+
+#[test]
+pub fn handle_removes_resources_and_closes_sessions() {
+    use std::sync::Mutex;
+
+    use libcoap_rs::{
+        message::{CoapMessageCommon, CoapRequest},
+        protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+        session::{CoapClientSession, CoapSessionCloseReason, CoapSessionCommon},
+    };
+
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(server_address).unwrap();
+    let peer = Arc::new(Mutex::new(None));
+    let resource = {
+        let peer = Arc::clone(&peer);
+        CoapResource::builder("test1", ())
+            .get(move |_resource, session, _request, mut response| {
+                *peer.lock().unwrap() = Some((session.id(), session.addr_remote()));
+                response.set_code(CoapResponseCode::Content);
+                session.send(response).unwrap();
+            })
+            .build()
+            .unwrap()
+    };
+    context.add_resource(resource);
+    let handle = context.handle().unwrap();
+
+    let client = {
+        let peer = Arc::clone(&peer);
+        std::thread::spawn(move || {
+            let mut context = CoapContext::new().unwrap();
+            let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+            let mut send = || {
+                let request =
+                    CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Get, "/test1".parse().unwrap()).unwrap();
+                context
+                    .send_and_wait(&session, request, Duration::from_secs(10))
+                    .unwrap()
+                    .code()
+            };
+            assert_eq!(send(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+            handle.remove_resource("test1").unwrap();
+            // Removing a path the context has no resource for has no effect.
+            handle.remove_resource("unknown").unwrap();
+            let start = Instant::now();
+            while send() != CoapMessageCode::Response(CoapResponseCode::NotFound) {
+                assert!(start.elapsed() < Duration::from_secs(10), "resource was not removed");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let (id, _) = peer.lock().unwrap().unwrap();
+            handle.close_session(id, CoapSessionCloseReason::Release).unwrap();
+            // Closing a session that is already closed has no effect.
+            handle.close_session(id, CoapSessionCloseReason::Release).unwrap();
+            handle.request_stop().unwrap();
+        })
+    };
+
+    common::run_until(&mut [&mut context], "client", |contexts| {
+        contexts[0].stop_requested().then_some(())
+    });
+    client.join().unwrap();
+    // The session may have been closed after the stop request was noticed.
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    let (_, addr) = peer.lock().unwrap().unwrap();
+    assert!(context.session_by_peer(addr).is_none());
+}
+
+#[test]
+pub fn session_guards_are_used_from_other_threads() {
+    use std::sync::Mutex;
+
+    use libcoap_rs::{
+        message::{CoapMessageCommon, CoapRequest},
+        protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+        session::{CoapClientSession, CoapSessionCommon, SessionGuard},
+    };
+
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(server_address).unwrap();
+    let guard_slot: Arc<Mutex<Option<SessionGuard>>> = Arc::new(Mutex::new(None));
+    let executed = Arc::new(AtomicBool::new(false));
+    let resource = {
+        let guard_slot = Arc::clone(&guard_slot);
+        let executed = Arc::clone(&executed);
+        CoapResource::builder("test1", ())
+            .post(move |_resource, session, _request, mut response| {
+                let guard = assert_send(session.guard().unwrap());
+                assert_eq!(guard.session_id(), session.id());
+                let executed = Arc::clone(&executed);
+                let worker_guard = guard.clone();
+                std::thread::spawn(move || {
+                    let id = worker_guard.session_id();
+                    worker_guard
+                        .execute(move |session| executed.store(session.id() == id, Ordering::SeqCst))
+                        .unwrap();
+                });
+                *guard_slot.lock().unwrap() = Some(guard);
+                response.set_code(CoapResponseCode::Changed);
+                session.send(response).unwrap();
+            })
+            .get(|_resource, session, request, response| {
+                #[cfg(feature = "async")]
+                {
+                    let token = request.token().unwrap().to_vec();
+                    request.clone().into_async(session, None).unwrap();
+                    let guard = session.guard().unwrap();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_millis(50));
+                        guard
+                            .send_deferred_response(&token, |response| {
+                                response.set_data(Some("deferred".as_bytes().to_vec()))
+                            })
+                            .unwrap();
+                        // Requests that have already been answered are not answered again.
+                        guard
+                            .send_deferred_response(&token, |response| {
+                                response.set_code(CoapResponseCode::InternalServerError)
+                            })
+                            .unwrap();
+                    });
+                    std::mem::drop(response);
+                }
+                #[cfg(not(feature = "async"))]
+                {
+                    let _ = request;
+                    let mut response = response;
+                    response.set_code(CoapResponseCode::Content);
+                    session.send(response).unwrap();
+                }
+            })
+            .build()
+            .unwrap()
+    };
+    context.add_resource(resource);
+    let handle = context.handle().unwrap();
+
+    let client = std::thread::spawn(move || {
+        let mut context = CoapContext::new().unwrap();
+        let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+        let mut send = |code| {
+            let request = CoapRequest::new(CoapMessageType::Con, code, "/test1".parse().unwrap()).unwrap();
+            context
+                .send_and_wait(&session, request, Duration::from_secs(10))
+                .unwrap()
+        };
+        let response = send(CoapRequestCode::Post);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+
+        let response = send(CoapRequestCode::Get);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+        #[cfg(feature = "async")]
+        assert_eq!(response.data().unwrap().as_ref(), "deferred".as_bytes());
+        handle.request_stop().unwrap();
+    });
+
+    common::run_until(&mut [&mut context], "client", |contexts| {
+        (contexts[0].stop_requested() && executed.load(Ordering::SeqCst)).then_some(())
+    });
+    client.join().unwrap();
+
+    // Guards may outlive the context of their session.
+    let guard = guard_slot.lock().unwrap().take().unwrap();
+    std::mem::drop(context);
+    assert_eq!(
+        guard.execute(|_session| unreachable!()).unwrap_err(),
+        ContextHandleError::ContextDropped
+    );
+    std::mem::drop(guard);
+}
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * context_test.rs - Tests for context configuration and lifecycle.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::{ContextBuildError, ContextConfigurationError, EndpointCreationError, IoProcessError, RequestPollError},
+    session::{CoapClientSession, CoapSessionCommon},
+    types::CoapProtocol,
+    CoapContext, CoapContextBuilder, CoapWellKnownCoreMode,
+};
+
+mod common;
+
+#[test]
+pub fn shutdown_with_outstanding_messages() {
+    // The peer never acknowledges the confirmable request, so it is retransmitted for much longer
+    // than the shutdown timeout.
+    let silent_peer = UdpSocket::bind("localhost:0").unwrap();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, silent_peer.local_addr().unwrap()).unwrap();
+    session.send_request(common::gen_test_request()).unwrap();
+    std::mem::drop(session);
+
+    let start = Instant::now();
+    assert_eq!(
+        context.shutdown(Some(Duration::from_millis(500))).unwrap_err(),
+        IoProcessError::ShutdownTimeout
+    );
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(500) && elapsed < Duration::from_secs(2));
+}
+
+#[test]
+pub fn abort_pending_transmissions() {
+    let silent_peer = UdpSocket::bind("localhost:0").unwrap();
+    let mut context = CoapContext::new().unwrap();
+    assert!(context.can_exit());
+    assert_eq!(context.pending_transmissions(), 0);
+    let session = CoapClientSession::connect_udp(&mut context, silent_peer.local_addr().unwrap()).unwrap();
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    let removed_handle = session.send_request(common::gen_test_request()).unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert!(!context.can_exit());
+    assert_eq!(context.pending_transmissions(), 2);
+    session.remove_handle(removed_handle);
+    assert_eq!(context.pending_transmissions(), 1);
+
+    context.abort_pending();
+    assert!(context.can_exit());
+    assert_eq!(context.pending_transmissions(), 0);
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::Aborted
+    );
+
+    // Requests sent afterwards are counted again.
+    session.send_request(common::gen_test_request()).unwrap();
+    assert!(!context.can_exit());
+    assert_eq!(context.pending_transmissions(), 1);
+    context.abort_pending();
+    std::mem::drop(session);
+
+    let start = Instant::now();
+    context.shutdown(Some(Duration::from_secs(5))).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+pub fn context_config_diff() {
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    let initial = context.config();
+    assert!(initial.diff(&context.config()).is_empty());
+
+    context.add_endpoint_udp(server_address).unwrap();
+    context.set_session_timeout(Duration::from_secs(42));
+    context.set_keepalive(Some(Duration::from_secs(10)));
+    let changed = context.config();
+
+    assert_eq!(changed.session_timeout, Duration::from_secs(42));
+    assert_eq!(changed.keepalive, Some(Duration::from_secs(10)));
+    assert_eq!(changed.endpoints.len(), 1);
+    assert_eq!(changed.endpoints[0].addr, server_address.to_string());
+    assert!(!changed.psk);
+
+    let diff = initial.diff(&changed);
+    assert_eq!(diff.len(), 3);
+    assert!(diff
+        .iter()
+        .any(|v| v.starts_with("session_timeout: ") && v.ends_with(" -> 42s")));
+    assert!(diff.contains(&"keepalive: None -> Some(10s)".to_string()));
+    assert!(diff.contains(&format!("added udp endpoint at {}", server_address)));
+
+    // Settings of the wrapper are part of the snapshot as well.
+    context.set_default_host(Some("Example.org"));
+    context.set_wellknown_core_mode(CoapWellKnownCoreMode::Disabled);
+    context.set_session_pool_capacity(3);
+    let wrapper_changed = context.config();
+    assert_eq!(wrapper_changed.default_host.as_deref(), Some("example.org"));
+    assert_eq!(wrapper_changed.wellknown_core_mode, "disabled");
+    assert_eq!(wrapper_changed.session_pool_capacity, 3);
+    let diff = changed.diff(&wrapper_changed);
+    assert_eq!(diff.len(), 3);
+    assert!(diff.contains(&"default_host: None -> Some(\"example.org\")".to_string()));
+    assert!(diff.contains(&"wellknown_core_mode: default -> disabled".to_string()));
+    assert!(diff
+        .iter()
+        .any(|v| v.starts_with("session_pool_capacity: ") && v.ends_with(" -> 3")));
+}
+
+#[test]
+pub fn context_builder() {
+    let server_address = common::get_unused_server_addr();
+    let context = CoapContextBuilder::new()
+        .endpoint_udp(server_address)
+        .session_timeout(Duration::from_secs(42))
+        .max_idle_sessions(7)
+        .csm_max_message_size(2048)
+        .max_token_size(16)
+        .dedup_capacity(32)
+        .keepalive(Duration::from_secs(30))
+        .build()
+        .unwrap();
+    assert_eq!(context.session_timeout(), Duration::from_secs(42));
+    assert_eq!(context.max_idle_sessions(), 7);
+    assert_eq!(context.csm_max_message_size(), 2048);
+    assert_eq!(context.max_token_size(), 16);
+    assert_eq!(context.dedup_capacity(), 32);
+    assert_eq!(context.keepalive(), Some(Duration::from_secs(30)));
+    assert_eq!(context.endpoints().len(), 1);
+
+    // Invalid settings are rejected before any endpoint is created.
+    let builder = CoapContextBuilder::new()
+        .endpoint_udp(server_address)
+        .max_token_size(1000);
+    assert_eq!(
+        builder.validate(),
+        Err(ContextConfigurationError::InvalidMaxTokenSize(1000))
+    );
+    assert_eq!(
+        builder.build().unwrap_err(),
+        ContextBuildError::Configuration(ContextConfigurationError::InvalidMaxTokenSize(1000))
+    );
+
+    // Endpoints that cannot be created are reported alongside their address.
+    assert_eq!(
+        CoapContextBuilder::new()
+            .endpoint_udp(server_address)
+            .build()
+            .unwrap_err(),
+        ContextBuildError::Endpoint(CoapProtocol::Udp, server_address, EndpointCreationError::AddressInUse)
+    );
+}
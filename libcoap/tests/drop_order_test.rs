@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * drop_order_test.rs - Tests for dropping contexts before their sessions and resources.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use libcoap_rs::{
+    message::{CoapMessageCommon, CoapRequest, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+/// Values that are dropped in varying order by [drop_in_order()].
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Context,
+    ServerSession,
+    Resource,
+}
+
+/// Sets up a server context that has answered a single request, then drops the server context,
+/// a handle to the server-side session of the request and a handle to the requested resource in
+/// the given order.
+fn drop_in_order(order: [Item; 3]) {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let server_session: Rc<RefCell<Option<CoapServerSession<'static>>>> = Rc::default();
+    let server_session_handler = server_session.clone();
+    let resource = CoapResource::new("test1", 0u32, false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            move |requests: &mut u32, sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+                *requests += 1;
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+                *server_session_handler.borrow_mut() = Some(sess.clone());
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+
+    let mut server_context = Some(server_context);
+    let mut resource = server_context
+        .as_ref()
+        .unwrap()
+        .typed_resource_by_uri_path::<u32>("test1");
+    let mut server_session = server_session.borrow_mut().take();
+    for item in order {
+        match item {
+            Item::Context => std::mem::drop(server_context.take()),
+            Item::ServerSession => std::mem::drop(server_session.take()),
+            Item::Resource => std::mem::drop(resource.take()),
+        }
+        // Handles that outlive the context remain usable, but no longer perform IO.
+        if let Some(resource) = &resource {
+            assert_eq!(*resource.user_data(), 1);
+            assert_eq!(resource.stats().total_requests(), 1);
+        }
+        if let Some(server_session) = &server_session {
+            assert_eq!(server_session.stats().messages_received, 1);
+            assert_eq!(server_session.addr_local().port(), server_address.port());
+        }
+    }
+}
+
+#[test]
+pub fn drop_order_permutations() {
+    use Item::*;
+    for order in [
+        [Context, ServerSession, Resource],
+        [Context, Resource, ServerSession],
+        [ServerSession, Context, Resource],
+        [ServerSession, Resource, Context],
+        [Resource, Context, ServerSession],
+        [Resource, ServerSession, Context],
+    ] {
+        drop_in_order(order);
+    }
+}
+
+#[test]
+pub fn client_session_after_context() {
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    std::mem::drop(context);
+
+    // The request is never sent, as the context no longer performs IO.
+    assert_eq!(session.addr_remote(), server_address);
+    assert_eq!(session.poll_handle(&req_handle).count(), 0);
+    let clone = session.clone();
+    std::mem::drop(session);
+    assert_eq!(clone.addr_remote(), server_address);
+    // libcoap is only cleaned up once the raw context has been freed alongside the last session.
+    std::mem::drop(clone);
+    let mut context = CoapContext::new().unwrap();
+    CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+}
@@ -11,8 +11,14 @@
 
 use crate::common::dtls::dtls_client_server_request_common;
 use libcoap_rs::crypto::pki_rpk::{Asn1PrivateKeyType, DerFileKeyComponent, NonCertVerifying, PkiRpkContextBuilder};
-use libcoap_rs::crypto::pki_rpk::{Pki, PkiKeyDef};
+use libcoap_rs::crypto::pki_rpk::{Pki, PkiKeyDef, RawTlsSession};
+use libcoap_rs::crypto::{tls_backend, TlsLibrary};
+use libcoap_rs::error::TlsSetupHookError;
+use std::cell::Cell;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 mod common;
 
@@ -118,3 +124,75 @@ pub fn dtls_pki_asn1_memory_client_server_request() {
     };
     dtls_client_server_request_common(client_key, server_key, ctx_configurator, ctx_configurator)
 }
+
+/// Returns whether `session` is a native session of the TLS library in use.
+fn matches_tls_library(session: RawTlsSession) -> bool {
+    match session {
+        RawTlsSession::OpenSsl(_) => tls_backend().library() == TlsLibrary::OpenSsl,
+        RawTlsSession::GnuTls(_) => tls_backend().library() == TlsLibrary::GnuTls,
+        _ => false,
+    }
+}
+
+#[test]
+pub fn dtls_pki_tls_setup_hook() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let key_storage = manifest_dir.join("./resources/test-keys");
+    let client_key = PkiKeyDef::with_pem_files(
+        Some(key_storage.join("./ca/ca.crt.pem")),
+        key_storage.join("./client/client.crt.pem"),
+        key_storage.join("./client/client.key.pem"),
+    );
+    let server_key = PkiKeyDef::with_pem_files(
+        Some(key_storage.join("./ca/ca.crt.pem")),
+        key_storage.join("./server/server.crt.pem"),
+        key_storage.join("./server/server.key.pem"),
+    );
+
+    if !tls_backend().tls_setup_hook_supported() {
+        // SAFETY: The hook does not access the TLS session.
+        let result = unsafe { PkiRpkContextBuilder::<Pki, NonCertVerifying>::new(client_key).tls_setup_hook(|_| true) };
+        assert!(
+            matches!(result, Err(TlsSetupHookError::UnsupportedLibrary(library)) if library == tls_backend().library())
+        );
+        return;
+    }
+
+    let client_hook_calls = Rc::new(Cell::new(0));
+    let server_hook_calls = Arc::new(AtomicUsize::new(0));
+    let client_configurator = {
+        let client_hook_calls = Rc::clone(&client_hook_calls);
+        move |ctx: PkiRpkContextBuilder<'static, Pki, NonCertVerifying>| {
+            // SAFETY: The hook does not access the TLS session.
+            unsafe {
+                ctx.tls_setup_hook(move |session| {
+                    client_hook_calls.set(client_hook_calls.get() + 1);
+                    matches_tls_library(session)
+                })
+            }
+            .unwrap()
+            .verify_peer_cert()
+            .check_common_ca(true)
+            .build()
+        }
+    };
+    let server_configurator = {
+        let server_hook_calls = Arc::clone(&server_hook_calls);
+        move |ctx: PkiRpkContextBuilder<'static, Pki, NonCertVerifying>| {
+            // SAFETY: The hook does not access the TLS session.
+            unsafe {
+                ctx.tls_setup_hook(move |session| {
+                    server_hook_calls.fetch_add(1, Ordering::Relaxed);
+                    matches_tls_library(session)
+                })
+            }
+            .unwrap()
+            .verify_peer_cert()
+            .check_common_ca(true)
+            .build()
+        }
+    };
+    dtls_client_server_request_common(client_key, server_key, client_configurator, server_configurator);
+    assert_eq!(client_hook_calls.get(), 1);
+    assert!(server_hook_calls.load(Ordering::Relaxed) >= 1);
+}
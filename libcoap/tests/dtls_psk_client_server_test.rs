@@ -8,16 +8,25 @@
  */
 
 #![cfg(feature = "dtls-psk")]
-use std::time::Duration;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use libcoap_rs::crypto::psk::PskKey;
-use libcoap_rs::crypto::psk::{ClientPskContextBuilder, ServerPskContextBuilder};
-use libcoap_rs::session::CoapClientSession;
+use libcoap_rs::crypto::psk::{ClientPskContextBuilder, ClientPskHintKeyProvider, ServerPskContextBuilder};
+use libcoap_rs::crypto::psk::{PskKey, MAX_PSK_IDENTITY_LENGTH, MAX_PSK_KEY_LENGTH};
+use libcoap_rs::crypto::tls_backend;
+use libcoap_rs::error::{
+    ClientSniError, ContextBuildError, ContextConfigurationError, MessageConversionError, PskKeyError,
+    RequestPollError, SessionCreationError, SessionEstablishError,
+};
+use libcoap_rs::session::{CoapClientSession, CoapSession, DtlsFallbackPolicy};
 use libcoap_rs::{
-    message::CoapMessageCommon,
-    protocol::{CoapMessageCode, CoapResponseCode},
-    session::CoapSessionCommon,
-    CoapContext,
+    access::CoapAccessDecision,
+    message::{CoapMessageCommon, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapSessionCommon, CoapSessionState},
+    types::CoapProtocol,
+    CoapContext, CoapContextBuilder, CoapEventHandler, CoapRequestHandler, CoapResource,
 };
 
 mod common;
@@ -37,6 +46,15 @@ pub fn dtls_psk_client_server_request() {
 
     let mut context = CoapContext::new().unwrap();
     let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+    context
+        .wait_for_session_established(&session, Duration::from_secs(10))
+        .expect("DTLS session was not established");
+    assert_eq!(session.state(), CoapSessionState::Established);
+    let crypto_info = session.crypto_info().expect("DTLS session has no crypto information");
+    assert_eq!(crypto_info.proto(), CoapProtocol::Dtls);
+    assert!(crypto_info.is_transport_secure());
+    assert!(!crypto_info.is_oscore());
+    assert_eq!(crypto_info.psk_identity(), Some("dtls_test_id".as_bytes()));
 
     let request = common::gen_test_request();
     let req_handle = session.send_request(request).unwrap();
@@ -50,3 +68,526 @@ pub fn dtls_psk_client_server_request() {
         }
     }
 }
+
+#[test]
+pub fn dtls_psk_handshake_counters() {
+    let server_address = common::get_unused_server_addr();
+    let dummy_key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+    let client_psk_context = ClientPskContextBuilder::new(dummy_key.clone()).build();
+
+    let mut server_context = CoapContext::new().unwrap();
+    match server_context.require_handshake_cookie(true) {
+        Ok(()) => assert!(server_context.requires_handshake_cookie()),
+        // DTLS library does not support stateless verification, nothing to test here.
+        Err(ContextConfigurationError::UnsupportedFeature(_)) => {
+            assert!(!tls_backend().stateless_handshake_verification_supported());
+            return;
+        },
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(dummy_key).build())
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+    let mut remaining_iterations = 1000;
+    while server_context.dtls_handshakes_completed() == 0 || session.state() != CoapSessionState::Established {
+        assert!(remaining_iterations > 0, "timeout while waiting for DTLS handshake");
+        remaining_iterations -= 1;
+        server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+        context.do_io(Some(Duration::from_millis(10))).unwrap();
+    }
+
+    assert_eq!(server_context.dtls_handshakes_verified(), 1);
+    assert_eq!(server_context.dtls_handshakes_completed(), 1);
+    assert_eq!(server_context.dtls_handshakes_failed(), 0);
+    // Client-side handshakes are not counted.
+    assert_eq!(context.dtls_handshakes_verified(), 0);
+    assert_eq!(context.dtls_handshakes_completed(), 0);
+
+    // Handshakes using a wrong key pass the cookie exchange, but fail afterwards.
+    let wrong_key = PskKey::new(Some("dtls_test_id"), "wrong_test_key__");
+    let _failing_session = CoapClientSession::connect_dtls(
+        &mut context,
+        server_address,
+        ClientPskContextBuilder::new(wrong_key).build(),
+    )
+    .unwrap();
+    common::run_until(
+        &mut [&mut server_context, &mut context],
+        "handshake failure",
+        |contexts| (contexts[0].dtls_handshakes_failed() > 0).then_some(()),
+    );
+    assert_eq!(server_context.dtls_handshakes_verified(), 2);
+    assert_eq!(server_context.dtls_handshakes_completed(), 1);
+    assert_eq!(server_context.dtls_handshakes_failed(), 1);
+}
+
+/// Client-side key provider that selects the key based on the identity hint sent by the server.
+#[derive(Debug)]
+struct HintKeyProvider {
+    received_hints: Rc<RefCell<Vec<Option<Vec<u8>>>>>,
+}
+
+impl ClientPskHintKeyProvider<'static> for HintKeyProvider {
+    fn key_for_identity_hint(
+        &self,
+        identity_hint: Option<&[u8]>,
+        _session: &CoapClientSession<'_>,
+    ) -> Option<PskKey<'static>> {
+        self.received_hints.borrow_mut().push(identity_hint.map(|v| v.to_vec()));
+        match identity_hint {
+            Some(b"dtls_test_hint") => Some(PskKey::new(Some("dtls_test_id"), "dtls_hint_key___")),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+pub fn dtls_psk_client_key_for_identity_hint() {
+    let server_address = common::get_unused_server_addr();
+    let received_hints = Rc::new(RefCell::new(Vec::new()));
+    // The default key of the client does not match the key of the server, so the handshake only
+    // succeeds if the key provider is consulted with the hint sent by the server.
+    let client_psk_context = ClientPskContextBuilder::new(PskKey::new(Some("dtls_test_id"), "dtls_test_key___"))
+        .key_provider(HintKeyProvider {
+            received_hints: received_hints.clone(),
+        })
+        .build();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(PskKey::new(Some("dtls_test_hint"), "dtls_hint_key___")).build())
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+    let mut remaining_iterations = 1000;
+    while session.state() != CoapSessionState::Established {
+        assert!(remaining_iterations > 0, "timeout while waiting for DTLS handshake");
+        remaining_iterations -= 1;
+        server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+        context.do_io(Some(Duration::from_millis(10))).unwrap();
+    }
+
+    assert_eq!(received_hints.borrow().as_slice(), &[Some(b"dtls_test_hint".to_vec())]);
+}
+
+#[test]
+pub fn dtls_psk_client_hint_only_key_provider() {
+    let server_address = common::get_unused_server_addr();
+    let received_hints = Rc::new(RefCell::new(Vec::new()));
+    let client_psk_context = ClientPskContextBuilder::from_key_provider(HintKeyProvider {
+        received_hints: received_hints.clone(),
+    })
+    .build();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(PskKey::new(Some("dtls_test_hint"), "dtls_hint_key___")).build())
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    assert_eq!(received_hints.borrow().as_slice(), &[Some(b"dtls_test_hint".to_vec())]);
+}
+
+#[test]
+pub fn dtls_psk_key_validation() {
+    assert_eq!(PskKey::new(Some("dtls_test_id"), "dtls_test_key___").validate(), Ok(()));
+    assert_eq!(PskKey::new(None::<Vec<u8>>, "dtls_test_key___").validate(), Ok(()));
+    assert_eq!(
+        PskKey::new(Some("dtls_test_id"), "").validate(),
+        Err(PskKeyError::EmptyKey)
+    );
+    assert_eq!(
+        PskKey::new(Some(""), "dtls_test_key___").validate(),
+        Err(PskKeyError::EmptyIdentity)
+    );
+    assert_eq!(
+        PskKey::new(Some("dtls_test_id"), vec![0u8; MAX_PSK_KEY_LENGTH + 1]).validate(),
+        Err(PskKeyError::KeyTooLong(MAX_PSK_KEY_LENGTH + 1))
+    );
+    assert_eq!(
+        PskKey::new(Some(vec![b'a'; MAX_PSK_IDENTITY_LENGTH + 1]), "dtls_test_key___").validate(),
+        Err(PskKeyError::IdentityTooLong(MAX_PSK_IDENTITY_LENGTH + 1))
+    );
+
+    // Sessions using an invalid default key are rejected instead of failing during the handshake.
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    let client_psk_context = ClientPskContextBuilder::new(PskKey::new(Some("dtls_test_id"), "")).build();
+    assert!(matches!(
+        CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context.clone()),
+        Err(SessionCreationError::InvalidPsk(PskKeyError::EmptyKey))
+    ));
+    // The key error is not mistaken for a failure to bind to the local address.
+    let local_address = std::net::SocketAddr::new(server_address.ip(), 0);
+    assert!(matches!(
+        CoapClientSession::connect_dtls_from(&mut context, local_address, server_address, client_psk_context),
+        Err(SessionCreationError::InvalidPsk(PskKeyError::EmptyKey))
+    ));
+}
+
+#[test]
+pub fn dtls_psk_server_key_for_sni() {
+    let server_address = common::get_unused_server_addr();
+    let key_a = PskKey::new(Some("dtls_test_id"), "dtls_key_a______");
+    let key_b = PskKey::new(Some("dtls_test_id"), "dtls_key_b______");
+
+    let mut server_context = CoapContext::new().unwrap();
+    // The default key of the server matches neither of the client keys, so handshakes only succeed
+    // if the key selected for the SNI sent by the client is used.
+    server_context
+        .set_psk_context(
+            ServerPskContextBuilder::new(PskKey::new(Some("dtls_test_id"), "dtls_test_key___"))
+                .sni_key_provider(vec![("a.example", key_a.clone()), ("b.example", key_b.clone())])
+                .build(),
+        )
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    for (sni, key) in [("a.example", key_a), ("b.example", key_b)] {
+        let client_psk_context = ClientPskContextBuilder::new(key).client_sni(sni).unwrap().build();
+        let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+        let mut remaining_iterations = 1000;
+        while session.state() != CoapSessionState::Established {
+            assert!(
+                remaining_iterations > 0,
+                "timeout while waiting for DTLS handshake with SNI {sni}"
+            );
+            remaining_iterations -= 1;
+            server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+            context.do_io(Some(Duration::from_millis(10))).unwrap();
+        }
+    }
+}
+
+#[test]
+pub fn dtls_psk_client_sni_validation() {
+    let key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+    let sni_error = |sni: &str| ClientPskContextBuilder::new(key.clone()).client_sni(sni).err();
+
+    assert_eq!(sni_error(""), Some(ClientSniError::Empty));
+    assert_eq!(sni_error("bücher.example"), Some(ClientSniError::NonAscii));
+    assert!(matches!(
+        sni_error("a\0.example"),
+        Some(ClientSniError::ContainsNullByte(_))
+    ));
+    assert_eq!(sni_error("192.0.2.1"), Some(ClientSniError::IpAddress));
+    assert_eq!(sni_error("[2001:db8::1]"), Some(ClientSniError::IpAddress));
+    assert_eq!(sni_error("xn--bcher-kva.example"), None);
+}
+
+#[test]
+pub fn dtls_psk_context_builder() {
+    let server_address = common::get_unused_server_addr();
+    let dummy_key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+
+    // DTLS endpoints without server-side credentials are rejected before creating the context.
+    let builder = CoapContextBuilder::new().endpoint_dtls(server_address);
+    assert_eq!(
+        builder.validate(),
+        Err(ContextConfigurationError::MissingServerCredentials(CoapProtocol::Dtls))
+    );
+    assert_eq!(
+        builder.build().unwrap_err(),
+        ContextBuildError::Configuration(ContextConfigurationError::MissingServerCredentials(CoapProtocol::Dtls))
+    );
+
+    let client_psk_context = ClientPskContextBuilder::new(dummy_key.clone()).build();
+    let mut server_context = CoapContextBuilder::new()
+        .psk_context(ServerPskContextBuilder::new(dummy_key).build())
+        .endpoint_dtls(server_address)
+        .build()
+        .unwrap();
+    assert!(server_context.validate_configuration().is_empty());
+    assert_eq!(server_context.endpoints().len(), 1);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_dtls(&mut context, server_address, client_psk_context).unwrap();
+    common::run_until(&mut [&mut server_context, &mut context], "DTLS session", |_| {
+        (session.state() == CoapSessionState::Established).then_some(())
+    });
+}
+
+#[test]
+pub fn dtls_psk_handshake_deadline() {
+    // A socket that never answers, so the handshake can not complete.
+    let silent_socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let dummy_key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+    let client_psk_context = ClientPskContextBuilder::new(dummy_key).build();
+
+    let mut context = CoapContext::new().unwrap();
+    let session =
+        CoapClientSession::connect_dtls(&mut context, silent_socket.local_addr().unwrap(), client_psk_context).unwrap();
+    context.set_handshake_deadline(&session, Duration::from_millis(200));
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    assert_eq!(session.connect_error(), None);
+
+    let start = Instant::now();
+    let error = common::run_until(&mut [&mut context], "handshake deadline", |_| session.connect_error());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(error, SessionCreationError::HandshakeTimeout);
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::SessionFailed
+    );
+    assert_eq!(
+        session.send_request(common::gen_test_request()).unwrap_err(),
+        MessageConversionError::SessionFailed
+    );
+    assert_eq!(
+        context
+            .wait_for_session_established(&session, Duration::from_secs(1))
+            .unwrap_err(),
+        SessionEstablishError::HandshakeTimeout
+    );
+}
+
+#[test]
+pub fn dtls_psk_access_hook() {
+    let udp_address = common::get_unused_server_addr();
+    let dtls_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(PskKey::new(Some("dtls_test_id"), "dtls_test_key___")).build())
+        .unwrap();
+    server_context.add_endpoint_udp(udp_address).unwrap();
+    server_context.add_endpoint_dtls(dtls_address).unwrap();
+    let resource = CoapResource::new("config", 0u32, false);
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new(
+            |updates: &mut u32, sess, _req, mut rsp: CoapResponse| {
+                *updates += 1;
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    resource.set_access_hook(
+        |crypto_info, _method, _request| match crypto_info.and_then(|info| info.psk_identity()) {
+            Some(b"allowed") => CoapAccessDecision::Allow,
+            Some(_) => CoapAccessDecision::deny_with_diagnostic(CoapResponseCode::Forbidden, "identity not allowed"),
+            None => CoapAccessDecision::deny(CoapResponseCode::Unauthorized),
+        },
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let allowed_session = CoapClientSession::connect_dtls(
+        &mut context,
+        dtls_address,
+        ClientPskContextBuilder::new(PskKey::new(Some("allowed"), "dtls_test_key___")).build(),
+    )
+    .unwrap();
+    let denied_session = CoapClientSession::connect_dtls(
+        &mut context,
+        dtls_address,
+        ClientPskContextBuilder::new(PskKey::new(Some("denied"), "dtls_test_key___")).build(),
+    )
+    .unwrap();
+    let udp_session = CoapClientSession::connect_udp(&mut context, udp_address).unwrap();
+
+    for (session, expected_code, expected_data) in [
+        (&allowed_session, CoapResponseCode::Changed, None),
+        (
+            &denied_session,
+            CoapResponseCode::Forbidden,
+            Some("identity not allowed".as_bytes()),
+        ),
+        (&udp_session, CoapResponseCode::Unauthorized, None),
+    ] {
+        let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+            .uri_path(["config"])
+            .payload(b"new value".to_vec())
+            .build()
+            .unwrap();
+        let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+        assert_eq!(response.code(), CoapMessageCode::Response(expected_code));
+        assert_eq!(response.data(), expected_data);
+    }
+
+    // The request handler is only called for the request of the allowed peer.
+    let resource = server_context.typed_resource_by_uri_path::<u32>("config").unwrap();
+    assert_eq!(*resource.user_data(), 1);
+}
+
+#[test]
+pub fn dtls_psk_handshake_failure_diagnostics() {
+    // The alert is taken from the log messages of libcoap.
+    libcoap_rs::logging::install_log_handler();
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(PskKey::new(Some("dtls_test_id"), "dtls_test_key___")).build())
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+
+    let wrong_key = PskKey::new(Some("dtls_test_id"), "wrong_test_key__");
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_dtls(
+        &mut context,
+        server_address,
+        ClientPskContextBuilder::new(wrong_key).build(),
+    )
+    .unwrap();
+    common::run_until(&mut [&mut server_context, &mut context], "handshake failure", |_| {
+        assert_ne!(session.state(), CoapSessionState::Established);
+        (session.state() == CoapSessionState::None).then_some(())
+    });
+
+    let error = session.connect_error().expect("handshake failure was not reported");
+    let SessionCreationError::HandshakeFailed { alert, backend_message } = &error else {
+        panic!("unexpected connect error: {error}");
+    };
+    let alert = alert.expect("handshake failure does not contain the alert");
+    assert!(error.to_string().contains(&alert.to_string()));
+    assert!(backend_message.is_some());
+    assert_eq!(session.last_error(), Some(error.clone()));
+    assert_eq!(session.stats().handshake_failures, 1);
+    assert!(matches!(
+        context.wait_for_session_established(&session, Duration::from_secs(1)),
+        Err(SessionEstablishError::HandshakeFailed { alert: Some(a), .. }) if a == alert
+    ));
+}
+
+#[test]
+pub fn dtls_psk_key_rotation() {
+    let server_address = common::get_unused_server_addr();
+    let old_key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+    let new_key = PskKey::new(Some("dtls_test_id"), "dtls_rotated_key");
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context
+        .set_psk_context(ServerPskContextBuilder::new(old_key.clone()).build())
+        .unwrap();
+    server_context.add_endpoint_dtls(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let connect =
+        |server_context: &mut CoapContext<'static>, context: &mut CoapContext<'static>, key: &PskKey<'static>| {
+            let session = CoapClientSession::connect_dtls(
+                context,
+                server_address,
+                ClientPskContextBuilder::new(key.clone()).build(),
+            )
+            .unwrap();
+            common::run_until(&mut [server_context, context], "DTLS handshake", |_| {
+                matches!(session.state(), CoapSessionState::Established | CoapSessionState::None).then_some(())
+            });
+            session
+        };
+    let established_session = connect(&mut server_context, &mut context, &old_key);
+    assert_eq!(established_session.state(), CoapSessionState::Established);
+
+    let previous = server_context
+        .replace_psk_context(ServerPskContextBuilder::new(new_key.clone()).build())
+        .unwrap();
+    assert!(previous.is_some());
+
+    // Only new handshakes use the new key.
+    assert_eq!(
+        connect(&mut server_context, &mut context, &old_key).state(),
+        CoapSessionState::None
+    );
+    assert_eq!(
+        connect(&mut server_context, &mut context, &new_key).state(),
+        CoapSessionState::Established
+    );
+
+    // The session established using the old key remains usable.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &established_session,
+        common::gen_test_request(),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+}
+
+/// Event handler that records the protocols of sessions created by DTLS fallbacks.
+#[derive(Debug)]
+struct FallbackRecorder {
+    fallbacks: Rc<RefCell<Vec<(CoapProtocol, SessionEstablishError)>>>,
+}
+
+impl CoapEventHandler for FallbackRecorder {
+    fn handle_dtls_fallback(&mut self, session: &mut CoapSession, dtls_error: &SessionEstablishError) {
+        self.fallbacks.borrow_mut().push((session.proto(), dtls_error.clone()));
+    }
+}
+
+#[test]
+pub fn dtls_psk_connect_with_fallback() {
+    let udp_address = common::get_unused_server_addr();
+    let dtls_address = common::get_unused_server_addr();
+    let dummy_key = PskKey::new(Some("dtls_test_id"), "dtls_test_key___");
+    let client_psk_context = ClientPskContextBuilder::new(dummy_key.clone()).build();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context
+            .set_psk_context(ServerPskContextBuilder::new(dummy_key.clone()).build())
+            .unwrap();
+        context.add_endpoint_udp(udp_address).unwrap();
+        context.add_endpoint_dtls(dtls_address).unwrap();
+        context
+    });
+
+    let fallbacks = Rc::new(RefCell::new(Vec::new()));
+    let mut context = CoapContext::new().unwrap();
+    context.set_event_handler(FallbackRecorder {
+        fallbacks: fallbacks.clone(),
+    });
+    let policy = DtlsFallbackPolicy::PreferDtlsFallbackPlain {
+        handshake_timeout: Duration::from_secs(1),
+    };
+
+    // The DTLS handshake succeeds if the peer supports DTLS.
+    let secure = context
+        .connect_with_fallback(dtls_address, policy, client_psk_context.clone())
+        .unwrap();
+    assert!(secure.is_secure());
+    assert_eq!(secure.session().state(), CoapSessionState::Established);
+    assert_eq!(secure.dtls_error(), None);
+    assert!(fallbacks.borrow().is_empty());
+
+    // The plain endpoint of the server ignores the DTLS handshake, so the session falls back to
+    // plain UDP once the handshake timeout has elapsed.
+    let plain = context
+        .connect_with_fallback(udp_address, policy, client_psk_context)
+        .unwrap();
+    assert!(!plain.is_secure());
+    assert_eq!(plain.protocol(), CoapProtocol::Udp);
+    assert_eq!(plain.dtls_error(), Some(&SessionEstablishError::Timeout));
+    assert_eq!(
+        *fallbacks.borrow(),
+        vec![(CoapProtocol::Udp, SessionEstablishError::Timeout)]
+    );
+
+    let response = context
+        .send_and_wait(plain.session(), common::gen_test_request(), Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    server_handle.join().expect("Test server crashed with failure.");
+}
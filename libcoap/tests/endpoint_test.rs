@@ -0,0 +1,522 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * endpoint_test.rs - Tests for endpoint creation, addressing and removal.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::{EndpointCreationError, MessageConversionError, RequestPollError, SessionCreationError},
+    message::{CoapMessageCommon, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapResponseAddressPolicy, CoapServerSession, CoapSessionCommon, IcmpErrorPolicy},
+    transport::CoapEndpointHandle,
+    types::CoapProtocol,
+    CoapContext, CoapEndpointRebindPhase, CoapEventHandler, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn endpoint_address_in_use() {
+    let server_address = common::get_unused_server_addr();
+    let _socket = UdpSocket::bind(server_address).unwrap();
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(
+        context.add_endpoint_udp(server_address).unwrap_err(),
+        EndpointCreationError::AddressInUse
+    );
+}
+
+#[test]
+pub fn endpoint_local_addr_and_removal() {
+    let mut server_context = CoapContext::new().unwrap();
+    let requested_address = SocketAddr::new(common::get_unused_server_addr().ip(), 0);
+    let endpoint = server_context.add_endpoint_udp(requested_address).unwrap();
+    assert_eq!(server_context.endpoints(), vec![endpoint]);
+    let (server_address, proto) = server_context
+        .with_endpoint(endpoint, |v| {
+            v.set_default_mtu(1280);
+            (v.local_addr().unwrap(), v.proto())
+        })
+        .unwrap();
+    // The port chosen by the operating system is reported instead of the requested one.
+    assert_eq!(server_address.ip(), requested_address.ip());
+    assert_ne!(server_address.port(), 0);
+    assert_eq!(proto, CoapProtocol::Udp);
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess: &mut CoapServerSession, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+
+    // Removing the endpoint also closes the server-side session created for the client.
+    let deleted_sessions = Rc::new(Cell::new(0));
+    server_context.set_event_handler(SessionDelCounter(Rc::clone(&deleted_sessions)));
+    assert!(server_context.remove_endpoint(endpoint));
+    assert_eq!(deleted_sessions.get(), 1);
+    assert!(server_context.endpoints().is_empty());
+    assert!(server_context.with_endpoint(endpoint, |v| v.local_addr()).is_none());
+    assert!(!server_context.remove_endpoint(endpoint));
+    // The address is free again.
+    server_context.add_endpoint_udp(server_address).unwrap();
+}
+
+/// Returns the sockets of this process that are bound to the given address.
+#[cfg(target_os = "linux")]
+fn bound_sockets(addr: SocketAddr) -> Vec<socket2::Socket> {
+    use std::os::fd::{FromRawFd, RawFd};
+
+    std::fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<RawFd>().ok())
+        .filter(|fd| {
+            // SAFETY: The file descriptor is only borrowed for the duration of this closure.
+            let socket = std::mem::ManuallyDrop::new(unsafe { socket2::Socket::from_raw_fd(*fd) });
+            socket.local_addr().ok().and_then(|v| v.as_socket()) == Some(addr)
+        })
+        // SAFETY: The duplicated file descriptor is owned by the returned socket.
+        .map(|fd| unsafe { socket2::Socket::from_raw_fd(libc::dup(fd)) })
+        .collect()
+}
+
+#[test]
+pub fn endpoint_restricted_resources() {
+    let mut server_context = CoapContext::new().unwrap();
+    let (management_address, public_address) = (common::get_unused_server_addr(), common::get_unused_server_addr());
+    let management = server_context.add_endpoint_udp(management_address).unwrap();
+    let public = server_context.add_endpoint_udp(public_address).unwrap();
+    let handled: Rc<RefCell<Vec<(&str, Option<CoapEndpointHandle>)>>> = Rc::default();
+    for (path, endpoints) in [("config", Some(vec![management])), ("status", None)] {
+        let handled = Rc::clone(&handled);
+        let mut builder = CoapResource::builder(path, ()).get(move |_, sess, _req, mut rsp: CoapResponse| {
+            handled.borrow_mut().push((path, sess.endpoint()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        });
+        if let Some(endpoints) = endpoints.clone() {
+            builder = builder.endpoints(endpoints);
+        }
+        let resource = builder.build().unwrap();
+        assert_eq!(resource.endpoints(), endpoints);
+        server_context.add_resource(resource);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let management_session = CoapClientSession::connect_udp(&mut context, management_address).unwrap();
+    let public_session = CoapClientSession::connect_udp(&mut context, public_address).unwrap();
+    let get_request = |path: &str| {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path([path])
+            .build()
+            .unwrap()
+    };
+    for (session, path, code) in [
+        (&management_session, "config", CoapResponseCode::Content),
+        (&public_session, "config", CoapResponseCode::NotFound),
+        (&management_session, "status", CoapResponseCode::Content),
+        (&public_session, "status", CoapResponseCode::Content),
+    ] {
+        let response = common::exchange_request(&mut server_context, &mut context, session, get_request(path));
+        assert_eq!(response.code(), CoapMessageCode::Response(code));
+    }
+    // The handler of the restricted resource is not called for requests on other endpoints.
+    assert_eq!(
+        *handled.borrow(),
+        [
+            ("config", Some(management)),
+            ("status", Some(management)),
+            ("status", Some(public))
+        ]
+    );
+    assert_eq!(
+        server_context.with_endpoint(public, |v| (v.local_addr(), v.proto())),
+        Some((Some(public_address), CoapProtocol::Udp))
+    );
+}
+
+#[test]
+pub fn context_listen_addr() {
+    let listen_addr = common::get_unused_server_addr();
+    let mut context = CoapContext::new_with_listen_addr(listen_addr).unwrap();
+    let listen_endpoint = context.listen_endpoint().unwrap();
+    assert_eq!(context.endpoints(), [listen_endpoint]);
+    assert_eq!(
+        context.with_endpoint(listen_endpoint, |v| v.local_addr()).flatten(),
+        Some(listen_addr)
+    );
+    let resource = CoapResource::builder("listen", ())
+        .get(|_, sess, _req, mut rsp: CoapResponse| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    context.add_resource(resource);
+
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let source_addr: Rc<Cell<Option<SocketAddr>>> = Rc::default();
+    let resource_source_addr = Rc::clone(&source_addr);
+    let resource = CoapResource::builder("source", ())
+        .get(move |_, sess, _req, mut rsp: CoapResponse| {
+            resource_source_addr.set(Some(sess.addr_remote()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+
+    // Client sessions of the context send from the listen address.
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.addr_local().port(), listen_addr.port());
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["source"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(source_addr.get().map(|v| v.port()), Some(listen_addr.port()));
+
+    // The listen endpoint still serves requests of other peers.
+    let mut client_context = CoapContext::new().unwrap();
+    let client_session = CoapClientSession::connect_udp(&mut client_context, listen_addr).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["listen"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut context, &mut client_context, &client_session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    // The listen endpoint is owned by libcoap, so it can neither be removed nor rebound, and is
+    // only freed alongside the context.
+    assert!(!context.remove_endpoint(listen_endpoint));
+    assert_eq!(
+        context.rebind_endpoint(listen_endpoint, "127.0.0.1:0".parse().unwrap(), Duration::ZERO),
+        Err(EndpointCreationError::UnsupportedEndpoint)
+    );
+    assert_eq!(context.listen_endpoint(), Some(listen_endpoint));
+    assert_eq!(context.endpoints(), [listen_endpoint]);
+    std::mem::drop(session);
+    std::mem::drop(context);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn context_listen_addr_socket_usage() {
+    let listen_addr = common::get_unused_server_addr();
+    let mut context = CoapContext::new_with_listen_addr(listen_addr).unwrap();
+    // The endpoint for the listen address is created by libcoap.
+    let sockets = bound_sockets(listen_addr);
+    assert_eq!(sockets.len(), 1);
+    assert!(sockets[0].peer_addr().is_err());
+    std::mem::drop(sockets);
+
+    // In shared source port mode, each client session uses its own socket bound to the listen
+    // address, which is connected to the peer.
+    let server_address = common::get_unused_server_addr();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let sockets = bound_sockets(listen_addr);
+    assert_eq!(sockets.len(), 2);
+    let connected: Vec<_> = sockets
+        .iter()
+        .filter_map(|v| v.peer_addr().ok().and_then(|v| v.as_socket()))
+        .collect();
+    assert_eq!(connected, [server_address]);
+    std::mem::drop(sockets);
+
+    // All of these sockets are closed alongside the context.
+    std::mem::drop(session);
+    std::mem::drop(context);
+    assert!(bound_sockets(listen_addr).is_empty());
+}
+
+/// Event handler that counts the server-side sessions that were deleted.
+struct SessionDelCounter(Rc<Cell<u32>>);
+
+impl CoapEventHandler for SessionDelCounter {
+    fn handle_server_session_del(&mut self, _session: &mut CoapServerSession) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[derive(Debug, Default)]
+struct RebindRecorder {
+    phases: Rc<RefCell<Vec<(CoapEndpointHandle, CoapEndpointHandle, CoapEndpointRebindPhase)>>>,
+    deleted_sessions: Rc<Cell<usize>>,
+}
+
+impl CoapEventHandler for RebindRecorder {
+    fn handle_server_session_del(&mut self, _session: &mut CoapServerSession) {
+        self.deleted_sessions.set(self.deleted_sessions.get() + 1);
+    }
+
+    fn handle_endpoint_rebind(
+        &mut self,
+        old_endpoint: CoapEndpointHandle,
+        new_endpoint: CoapEndpointHandle,
+        phase: CoapEndpointRebindPhase,
+    ) {
+        self.phases.borrow_mut().push((old_endpoint, new_endpoint, phase));
+    }
+}
+
+#[test]
+pub fn rebind_endpoint_with_grace_period() {
+    let old_address = common::get_unused_server_addr();
+    let new_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    let recorder = RebindRecorder::default();
+    let phases = recorder.phases.clone();
+    let deleted_sessions = recorder.deleted_sessions.clone();
+    server_context.set_event_handler(recorder);
+    let old_endpoint = server_context.add_endpoint_udp(old_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let existing_session = CoapClientSession::connect_udp(&mut context, old_address).unwrap();
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &existing_session,
+        common::gen_test_request(),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    let new_endpoint = server_context
+        .rebind_endpoint(old_endpoint, new_address, Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(
+        *phases.borrow(),
+        vec![
+            (old_endpoint, new_endpoint, CoapEndpointRebindPhase::Bound),
+            (old_endpoint, new_endpoint, CoapEndpointRebindPhase::Draining)
+        ]
+    );
+    // Draining endpoints can not be rebound again.
+    assert!(server_context
+        .rebind_endpoint(old_endpoint, common::get_unused_server_addr(), Duration::ZERO)
+        .is_err());
+
+    // Existing sessions on the old endpoint are still served, new ones are refused.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &existing_session,
+        common::gen_test_request(),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    let late_session = CoapClientSession::connect_udp(&mut context, old_address).unwrap();
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &late_session,
+        common::gen_test_request(),
+    );
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable)
+    );
+    let new_session = CoapClientSession::connect_udp(&mut context, new_address).unwrap();
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &new_session,
+        common::gen_test_request(),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    // After the grace period, the old endpoint is removed alongside its sessions.
+    common::run_until(&mut [&mut server_context], "endpoint removal", |_| {
+        (phases.borrow().len() >= 3).then_some(())
+    });
+    assert_eq!(
+        phases.borrow()[2],
+        (old_endpoint, new_endpoint, CoapEndpointRebindPhase::Removed)
+    );
+    assert_eq!(deleted_sessions.get(), 2);
+    assert_eq!(server_context.config().endpoints.len(), 1);
+    assert_eq!(server_context.config().endpoints[0].addr, new_address.to_string());
+}
+
+#[test]
+pub fn connect_from_local_address() {
+    let server_address = common::get_unused_server_addr();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context.add_endpoint_udp(server_address).unwrap();
+        context
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    let local_address = SocketAddr::new(server_address.ip(), 0);
+    let mismatched_address: SocketAddr = if server_address.is_ipv4() {
+        "[::1]:0".parse().unwrap()
+    } else {
+        "127.0.0.1:0".parse().unwrap()
+    };
+    assert_eq!(
+        CoapClientSession::connect_udp_from(&mut context, mismatched_address, server_address).unwrap_err(),
+        SessionCreationError::AddressFamilyMismatch
+    );
+    // Documentation addresses (RFC 5737 and RFC 3849) are not assigned to any local interface.
+    let unavailable_address: SocketAddr = if server_address.is_ipv4() {
+        "192.0.2.1:0".parse().unwrap()
+    } else {
+        "[2001:db8::1]:0".parse().unwrap()
+    };
+    assert_eq!(
+        CoapClientSession::connect_udp_from(&mut context, unavailable_address, server_address).unwrap_err(),
+        SessionCreationError::BindFailed(unavailable_address, std::io::ErrorKind::AddrNotAvailable)
+    );
+    #[cfg(unix)]
+    {
+        let occupied_socket = UdpSocket::bind(local_address).unwrap();
+        let occupied_address = occupied_socket.local_addr().unwrap();
+        assert_eq!(
+            CoapClientSession::connect_udp_from(&mut context, occupied_address, server_address).unwrap_err(),
+            SessionCreationError::BindFailed(occupied_address, std::io::ErrorKind::AddrInUse)
+        );
+    }
+    let session = CoapClientSession::connect_udp_from(&mut context, local_address, server_address).unwrap();
+    assert_eq!(session.addr_local().ip(), local_address.ip());
+
+    let request = common::gen_test_request();
+    let req_handle = session.send_request(request).unwrap();
+    loop {
+        assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            server_handle.join().unwrap();
+            return;
+        }
+    }
+}
+
+#[test]
+pub fn response_address_policy() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let uri = format!("coap://{}:{}", server_address.ip(), server_address.port());
+    let session = CoapClientSession::connect_uri(&mut context, &uri).unwrap();
+    assert_eq!(session.response_address_policy(), CoapResponseAddressPolicy::Permissive);
+    session.set_response_address_policy(CoapResponseAddressPolicy::Strict);
+    assert_eq!(session.response_address_policy(), CoapResponseAddressPolicy::Strict);
+
+    // Responses from the address the request was sent to are always accepted.
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(session.response_address_mismatch_count(), 0);
+}
+
+#[test]
+pub fn icmp_unreachable_fails_requests() {
+    // Nobody listens on the port of a socket that has already been closed.
+    let closed_addr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, closed_addr).unwrap();
+    assert_eq!(session.icmp_error_policy(), IcmpErrorPolicy::default());
+    let build_request = || {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["unreachable"])
+            .build()
+            .unwrap()
+    };
+
+    // The request fails long before libcoap would retransmit it (after at least two seconds).
+    let start = Instant::now();
+    let req_handle = session.send_request(build_request()).unwrap();
+    let error = common::run_until(&mut [&mut context], "ICMP error", |_| {
+        session.try_poll_handle(&req_handle).err()
+    });
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(error, RequestPollError::IcmpUnreachable);
+    assert_eq!(session.stats().failed_deliveries, 0);
+
+    // Unless disabled, in which case requests keep waiting for a response.
+    session.set_icmp_error_policy(IcmpErrorPolicy {
+        fail_requests: false,
+        ..Default::default()
+    });
+    let req_handle = session.send_request(build_request()).unwrap();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(200) {
+        context.do_io(Some(Duration::from_millis(10))).unwrap();
+    }
+    assert!(session.try_poll_handle(&req_handle).is_ok());
+}
+
+#[test]
+pub fn send_failures_do_not_reduce_mtu() {
+    let closed_addr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, closed_addr).unwrap();
+    let mtu = session.max_pdu_size();
+    let build_request = || {
+        CoapRequestBuilder::new(CoapRequestCode::Post)
+            .uri_path(["unreachable"])
+            .payload(vec![0x2a; 1024])
+            .build()
+            .unwrap()
+    };
+
+    for _ in 0..3 {
+        // A stale "message too long" error of an unrelated socket on this thread must not be
+        // mistaken for the reason why libcoap failed to send a request.
+        let peer_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(peer_socket.send_to(&vec![0; 1 << 17], closed_addr).is_err());
+        // Sending to a closed port fails once the ICMP error of a previous datagram was reported
+        // for the socket of the session (which is not related to the MTU).
+        let result = session.send_request(build_request());
+        assert!(!matches!(result, Err(MessageConversionError::PacketTooBig(_))));
+    }
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(session.max_pdu_size(), mtu);
+    assert_eq!(session.stats().mtu_reductions, 0);
+}
@@ -0,0 +1,187 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * ipv6_test.rs - Tests for IPv6 and dual-stack endpoints and sessions.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::RefCell;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::rc::Rc;
+
+use libcoap_rs::{
+    error::{EndpointCreationError, SessionCreationError},
+    message::{CoapMessageCommon, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    transport::CoapIpv6Mode,
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+/// Creates a context providing the `test1` resource, which records the remote address of the
+/// sessions requests were received on.
+fn address_recording_server() -> (CoapContext<'static>, Rc<RefCell<Option<SocketAddr>>>) {
+    let mut server_context = CoapContext::new().unwrap();
+    let peer_addr = Rc::new(RefCell::new(None));
+    let resource = CoapResource::new("test1", peer_addr.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |peer_addr: &mut Rc<RefCell<Option<SocketAddr>>>,
+             sess: &mut CoapServerSession,
+             _req,
+             mut rsp: CoapResponse| {
+                peer_addr.replace(Some(sess.addr_remote()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    (server_context, peer_addr)
+}
+
+/// Sends a test request on the given session and performs IO on both contexts until the
+/// response has been received.
+fn exchange<'a>(server_context: &mut CoapContext<'a>, context: &mut CoapContext<'a>, session: &CoapClientSession) {
+    let response = common::exchange_request(server_context, context, session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+}
+
+#[test]
+pub fn dual_stack_endpoint_ipv4_client() {
+    let port = common::get_unused_server_addr().port();
+    let server_address = SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0);
+
+    let (mut server_context, peer_addr) = address_recording_server();
+    let result = server_context.add_endpoint_udp_ipv6(server_address, CoapIpv6Mode::DualStack);
+    if !CoapIpv6Mode::DualStack.is_supported() {
+        assert_eq!(
+            result,
+            Err(EndpointCreationError::Ipv6ModeUnsupported(CoapIpv6Mode::DualStack))
+        );
+        return;
+    }
+    let endpoint = result.unwrap();
+    assert_eq!(
+        server_context.with_endpoint(endpoint, |ep| ep.local_addr()),
+        Some(Some(server_address.into()))
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session =
+        CoapClientSession::connect_udp(&mut context, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)).unwrap();
+    exchange(&mut server_context, &mut context, &session);
+
+    // The IPv4 client is represented using an IPv4-mapped address on the server side.
+    let peer_addr = peer_addr.take().unwrap();
+    match peer_addr {
+        SocketAddr::V6(addr) => assert_eq!(addr.ip().to_ipv4_mapped(), Some(Ipv4Addr::LOCALHOST)),
+        SocketAddr::V4(_) => panic!("peer address of dual-stack endpoint is not an IPv6 address"),
+    }
+    assert_eq!(peer_addr.port(), session.addr_local().port());
+    // Resource lookup does not depend on the address family of the endpoint.
+    assert!(server_context
+        .typed_resource_by_uri_path::<Rc<RefCell<Option<SocketAddr>>>>("test1")
+        .is_some());
+}
+
+#[test]
+pub fn ipv6_address_validation() {
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(
+        context.add_endpoint_udp("[::ffff:127.0.0.1]:0".parse().unwrap()),
+        Err(EndpointCreationError::Ipv4MappedAddress)
+    );
+    assert_eq!(
+        context.add_endpoint_udp("[fe80::1]:0".parse().unwrap()),
+        Err(EndpointCreationError::MissingScopeId)
+    );
+    assert_eq!(
+        CoapClientSession::connect_udp(&mut context, "[fe80::1]:5683".parse().unwrap()).unwrap_err(),
+        SessionCreationError::MissingScopeId
+    );
+    assert_eq!(
+        CoapClientSession::connect_udp_from(
+            &mut context,
+            "[fe80::1]:0".parse().unwrap(),
+            "[::1]:5683".parse().unwrap()
+        )
+        .unwrap_err(),
+        SessionCreationError::MissingScopeId
+    );
+}
+
+#[test]
+pub fn loopback_address_conversions() {
+    for ip in [Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()] {
+        let server_address = SocketAddr::new(ip, common::get_unused_server_addr().port());
+        let (mut server_context, peer_addr) = address_recording_server();
+        server_context.add_endpoint_udp(server_address).unwrap();
+
+        let mut context = CoapContext::new().unwrap();
+        let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+        assert_eq!(session.addr_remote(), server_address);
+        exchange(&mut server_context, &mut context, &session);
+
+        // Addresses provided by libcoap are converted back using the layout of the platform.
+        let local_addr = session.addr_local();
+        assert_eq!(local_addr.ip(), ip);
+        assert_ne!(local_addr.port(), 0);
+        assert_eq!(peer_addr.take(), Some(local_addr));
+    }
+}
+
+/// Returns a link-local address (including its zone ID) assigned to one of the interfaces of the
+/// host, if there is one.
+#[cfg(target_os = "linux")]
+fn local_link_local_addr() -> Option<SocketAddrV6> {
+    // Each line has the format `<address> <ifindex> <prefix_len> <scope> <flags> <name>`, with the
+    // address being written as 32 hex digits.
+    let if_inet6 = std::fs::read_to_string("/proc/net/if_inet6").ok()?;
+    if_inet6.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let addr = u128::from_str_radix(fields.next()?, 16).ok()?;
+        let if_index = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let addr = Ipv6Addr::from(addr);
+        ((addr.segments()[0] & 0xffc0) == 0xfe80).then_some(SocketAddrV6::new(addr, 0, 0, if_index))
+    })
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn link_local_zone_ids() {
+    let Some(mut link_local_addr) = local_link_local_addr() else {
+        // No interface with a link-local address available in this environment.
+        return;
+    };
+    link_local_addr.set_port(common::get_unused_server_addr().port());
+    let (mut server_context, peer_addr) = address_recording_server();
+    let endpoint = server_context.add_endpoint_udp(link_local_addr.into()).unwrap();
+    let server_address = server_context
+        .with_endpoint(endpoint, |ep| ep.local_addr())
+        .flatten()
+        .unwrap();
+    match server_address {
+        SocketAddr::V6(addr) => assert_eq!(addr.scope_id(), link_local_addr.scope_id()),
+        SocketAddr::V4(_) => panic!("link-local endpoint is not bound to an IPv6 address"),
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let local_addr = SocketAddrV6::new(*link_local_addr.ip(), 0, 0, link_local_addr.scope_id());
+    let session = CoapClientSession::connect_udp_from(&mut context, local_addr.into(), server_address).unwrap();
+    assert_eq!(session.addr_remote(), server_address);
+    exchange(&mut server_context, &mut context, &session);
+
+    match peer_addr.take().unwrap() {
+        SocketAddr::V6(addr) => {
+            assert_eq!(*addr.ip(), *link_local_addr.ip());
+            assert_eq!(addr.scope_id(), link_local_addr.scope_id());
+        },
+        SocketAddr::V4(_) => panic!("peer address of link-local session is not an IPv6 address"),
+    }
+}
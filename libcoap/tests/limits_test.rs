@@ -0,0 +1,475 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * limits_test.rs - Tests for datagram size, memory, input and rate limits.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::net::UdpSocket;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::{IoProcessError, MessageConversionError, RequestPollError, SessionCreationError},
+    limits::{CoapInputLimit, CoapInputLimitExceeded, CoapInputLimits},
+    message::{CoapBlock1Chunk, CoapMessage, CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapMessageType, CoapOptionType, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSession, CoapSessionCommon, SendQueueLimits},
+    types::CoapProtocol,
+    CoapContext, CoapEventHandler, CoapMemoryLimits, CoapResource, ResourceFlags,
+};
+
+mod common;
+
+#[test]
+pub fn memory_usage_limits() {
+    let silent_peer = UdpSocket::bind("localhost:0").unwrap();
+    let peer_addr = silent_peer.local_addr().unwrap();
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(context.memory_limits(), CoapMemoryLimits::default());
+    let initial = context.memory_usage();
+    assert_eq!(initial.sessions, 0);
+    assert_eq!(initial.total(), initial.resources);
+
+    let session = CoapClientSession::connect_udp(&mut context, peer_addr).unwrap();
+    session.set_app_data(Some([0u8; 1024]));
+    let with_session = context.memory_usage();
+    assert!(with_session.sessions > 1024);
+    assert_eq!(with_session.pending_requests, 0);
+
+    let handle = session.send_request(common::gen_test_request()).unwrap();
+    let with_request = context.memory_usage();
+    assert_eq!(with_request.sessions, with_session.sessions);
+    assert!(with_request.pending_requests > 0);
+
+    // The limits have been reached by the current usage, so further sessions and requests are
+    // rejected.
+    context.set_memory_limits(CoapMemoryLimits {
+        max_session_bytes: Some(with_request.sessions),
+        max_pending_request_bytes: Some(with_request.pending_requests),
+    });
+    assert_eq!(
+        session.send_request(common::gen_test_request()),
+        Err(MessageConversionError::MemoryLimitExceeded(
+            with_request.pending_requests
+        ))
+    );
+    assert_eq!(
+        CoapClientSession::connect_udp(&mut context, peer_addr).err(),
+        Some(SessionCreationError::MemoryLimitExceeded(with_request.sessions))
+    );
+    let report = context.memory_usage();
+    assert_eq!(report.sessions_rejected, 1);
+    assert_eq!(report.requests_rejected, 1);
+
+    // Removing the handle releases the memory used by the request.
+    session.remove_handle(handle);
+    assert_eq!(context.memory_usage().pending_requests, 0);
+    session.send_request(common::gen_test_request()).unwrap();
+
+    context.add_resource(CoapResource::new("memory", (), false));
+    assert!(context.memory_usage().resources > initial.resources);
+
+    std::mem::drop(session);
+    let report = context.memory_usage();
+    assert_eq!(report.sessions, 0);
+    assert_eq!(report.pending_requests, 0);
+}
+
+#[test]
+pub fn resource_max_request_size() {
+    const MAX_REQUEST_SIZE: usize = 1000;
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let received: Rc<RefCell<Vec<usize>>> = Rc::default();
+    for (path, flags) in [
+        ("stream", ResourceFlags::NOTIFY_NON | ResourceFlags::STREAM_BLOCK1),
+        ("single", ResourceFlags::NOTIFY_NON),
+    ] {
+        let received_handler = received.clone();
+        let resource = CoapResource::builder(path, ())
+            .flags(flags)
+            .max_request_size(MAX_REQUEST_SIZE)
+            .put(move |_, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                let chunk = CoapBlock1Chunk::from_request(req);
+                received_handler.borrow_mut().push(chunk.data().len());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                chunk.acknowledge(&mut rsp);
+                sess.send(rsp).unwrap();
+            })
+            .build()
+            .unwrap();
+        assert_eq!(resource.max_request_size(), Some(MAX_REQUEST_SIZE));
+        server_context.add_resource(resource);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let put_request = |path: &str, len: usize| {
+        CoapRequestBuilder::new(CoapRequestCode::Put)
+            .uri_path([path])
+            .payload(vec![0x55; len])
+            .build()
+            .unwrap()
+    };
+
+    for path in ["stream", "single"] {
+        let response = common::exchange_request(
+            &mut server_context,
+            &mut context,
+            &session,
+            put_request(path, MAX_REQUEST_SIZE),
+        );
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+        assert_eq!(received.borrow().iter().sum::<usize>(), MAX_REQUEST_SIZE);
+        received.borrow_mut().clear();
+
+        // The client announces the size of the upload, so the streaming resource rejects it with
+        // the first block, the other one once it has been reassembled by libcoap.
+        let response = common::exchange_request(
+            &mut server_context,
+            &mut context,
+            &session,
+            put_request(path, 3 * MAX_REQUEST_SIZE),
+        );
+        assert_eq!(
+            response.code(),
+            CoapMessageCode::Response(CoapResponseCode::RequestTooLarge)
+        );
+        assert_eq!(response.size1(), Some(MAX_REQUEST_SIZE as u32));
+        assert!(received.borrow().is_empty());
+    }
+}
+
+/// Event handler that records the requests that were rejected because of their input limits.
+struct InputLimitRecorder(Rc<RefCell<Vec<CoapInputLimitExceeded>>>);
+
+impl CoapEventHandler for InputLimitRecorder {
+    fn handle_input_limit_exceeded(&mut self, _session: &mut CoapServerSession, exceeded: &CoapInputLimitExceeded) {
+        self.0.borrow_mut().push(*exceeded);
+    }
+}
+
+#[test]
+pub fn resource_input_limits() {
+    let limits = CoapInputLimits {
+        max_options: 8,
+        max_option_length: 32,
+        max_payload_size: 256,
+        max_body_size: 1024,
+    };
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    assert_eq!(server_context.input_limits(), CoapInputLimits::default());
+    server_context.set_input_limits(limits);
+    let rejected: Rc<RefCell<Vec<CoapInputLimitExceeded>>> = Rc::default();
+    server_context.set_event_handler(InputLimitRecorder(Rc::clone(&rejected)));
+    let handled = Rc::new(Cell::new(0));
+    // The second resource accepts more options and larger PDUs, but not larger bodies.
+    let override_limits = CoapInputLimits {
+        max_options: 16,
+        max_payload_size: 4096,
+        ..limits
+    };
+    for (path, resource_limits) in [("limited", None), ("override", Some(override_limits))] {
+        let handled = Rc::clone(&handled);
+        let mut builder = CoapResource::builder(path, ()).put(move |_, sess, _req, mut rsp: CoapResponse| {
+            handled.set(handled.get() + 1);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+            sess.send(rsp).unwrap();
+        });
+        if let Some(resource_limits) = resource_limits {
+            builder = builder.input_limits(resource_limits);
+        }
+        let resource = builder.build().unwrap();
+        assert_eq!(resource.input_limits(), resource_limits);
+        server_context.add_resource(resource);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let put_request = |path: &str, queries: usize, query_len: usize, payload_len: usize| {
+        let mut builder = CoapRequestBuilder::new(CoapRequestCode::Put).uri_path([path]);
+        let value = "v".repeat(query_len);
+        for i in 0..queries {
+            builder = builder.query_pair(&format!("q{}", i), Some(&value));
+        }
+        builder.payload(vec![0x55; payload_len]).build().unwrap()
+    };
+    let mut exchange = |request| common::exchange_request(&mut server_context, &mut context, &session, request);
+
+    // Uri-Path and ten Uri-Query options.
+    let response = exchange(put_request("limited", 10, 1, 0));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::BadOption));
+    let response = exchange(put_request("override", 10, 1, 0));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+
+    let response = exchange(put_request("limited", 1, 64, 0));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::BadOption));
+
+    let response = exchange(put_request("limited", 0, 0, 512));
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::RequestTooLarge)
+    );
+    assert_eq!(response.size1(), Some(256));
+    let response = exchange(put_request("override", 0, 0, 512));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+
+    // The upload is sent block-wise and reassembled by libcoap before the limits are checked.
+    let response = exchange(put_request("override", 0, 0, 3000));
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::RequestTooLarge)
+    );
+    assert_eq!(response.size1(), Some(1024));
+    assert_eq!(handled.get(), 2);
+
+    let rejected = rejected.borrow();
+    let reasons: Vec<_> = rejected.iter().map(|v| v.limit).collect();
+    assert_eq!(
+        reasons,
+        [
+            CoapInputLimit::Options,
+            CoapInputLimit::OptionLength,
+            CoapInputLimit::PayloadSize,
+            CoapInputLimit::BodySize
+        ]
+    );
+    assert_eq!((rejected[0].max, rejected[0].actual), (8, 8));
+    assert_eq!(
+        (rejected[1].actual, rejected[1].option),
+        (67, Some(CoapOptionType::UriQuery as u16))
+    );
+    assert_eq!((rejected[2].max, rejected[2].actual), (256, 512));
+    assert_eq!((rejected[3].max, rejected[3].actual), (1024, 3000));
+}
+
+#[test]
+pub fn per_peer_rate_limit() {
+    use libcoap_rs::rate_limit::{CoapRateLimit, CoapRateLimitAction, CoapThrottledRequest};
+
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let handled = Rc::new(Cell::new(0));
+    let handled_handler = handled.clone();
+    let resource = CoapResource::builder("test1", ())
+        .get(move |_, sess, _req, mut rsp: CoapResponse| {
+            handled_handler.set(handled_handler.get() + 1);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    // One request every 100 seconds, so that the bucket does not refill during the test.
+    let limit = CoapRateLimit::new(0.01, 2);
+    assert_eq!(limit.confirmable_action, CoapRateLimitAction::Reject);
+    assert_eq!(limit.non_confirmable_action, CoapRateLimitAction::Drop);
+    server_context.set_rate_limit(Some(limit));
+    assert_eq!(server_context.rate_limit(), Some(limit));
+    let throttled: Rc<RefCell<Vec<CoapThrottledRequest>>> = Rc::default();
+    let throttled_hook = throttled.clone();
+    server_context.set_rate_limit_hook(move |request| throttled_hook.borrow_mut().push(*request));
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    for _ in 0..2 {
+        let response =
+            common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    }
+    assert_eq!(handled.get(), 2);
+
+    // Confirmable requests exceeding the burst are rejected with a backoff hint.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::TooManyRequests)
+    );
+    let max_age = response.max_age().unwrap();
+    assert!((1..=100).contains(&max_age), "unexpected Max-Age {max_age}");
+
+    // Non-confirmable requests are dropped.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .confirmable(false)
+        .uri_path(["test1"])
+        .timeout(Duration::from_millis(300))
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(300) {
+        server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+        context.do_io(Some(Duration::from_millis(10))).unwrap();
+    }
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::TimedOut
+    );
+    assert_eq!(handled.get(), 2);
+
+    let throttled = throttled.borrow();
+    assert_eq!(throttled.len(), 2);
+    assert_eq!(throttled[0].peer, session.addr_local().ip());
+    assert_eq!(throttled[0].code, CoapRequestCode::Get);
+    assert_eq!(throttled[0].action, CoapRateLimitAction::Reject);
+    assert_eq!(throttled[1].type_, CoapMessageType::Non);
+    assert_eq!(throttled[1].action, CoapRateLimitAction::Drop);
+    assert_eq!(server_context.stats().requests_throttled, 2);
+
+    // Removing the limit accepts requests again.
+    server_context.set_rate_limit(None);
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+}
+
+/// Event handler that counts the sessions whose send queues have drained.
+struct SendQueueDrainCounter(Rc<Cell<u32>>);
+
+impl CoapEventHandler for SendQueueDrainCounter {
+    fn handle_send_queue_drained(&mut self, _session: &mut CoapSession) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+pub fn send_queue_backpressure() {
+    // The peer only answers when told to, so confirmable requests remain queued until then.
+    let peer = UdpSocket::bind("localhost:0").unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let mut context = CoapContext::new().unwrap();
+    let drained = Rc::new(Cell::new(0));
+    context.set_event_handler(SendQueueDrainCounter(Rc::clone(&drained)));
+    let session = CoapClientSession::connect_udp(&mut context, peer.local_addr().unwrap()).unwrap();
+    assert_eq!(context.send_queue_limits(), None);
+
+    let mut handles = vec![session.send_request(common::gen_test_request()).unwrap()];
+    assert_eq!(session.queued_pdus(), 1);
+    let request_size = session.queued_bytes();
+    assert!(request_size > 0);
+
+    // Requests delayed by libcoap due to NSTART are queued as well.
+    context.set_send_queue_limits(Some(SendQueueLimits::new(3 * request_size, 0)));
+    let err = loop {
+        match session.send_request(common::gen_test_request()) {
+            Ok(handle) => handles.push(handle),
+            Err(e) => break e,
+        }
+        assert!(handles.len() < 10, "send queue never became congested");
+    };
+    assert!(handles.len() >= 3);
+    assert!(session.is_send_queue_congested());
+    assert_eq!(session.queued_pdus(), handles.len());
+    assert!(session.queued_bytes() >= 3 * request_size);
+    assert_eq!(err, MessageConversionError::SendQueueFull(session.queued_bytes()));
+    assert_eq!(drained.get(), 0);
+
+    // Acknowledge the requests one by one, libcoap then sends the next delayed request.
+    let mut buf = [0; 1500];
+    for _ in 0..handles.len() {
+        let (len, addr) = peer.recv_from(&mut buf).unwrap();
+        let request = CoapMessage::from_bytes(CoapProtocol::Udp, &buf[..len]).unwrap();
+        let mut response = CoapMessage::new(
+            CoapMessageType::Ack,
+            CoapMessageCode::Response(CoapResponseCode::Content),
+        );
+        response.set_mid(request.mid());
+        response.set_token(request.token());
+        peer.send_to(&response.to_bytes(CoapProtocol::Udp).unwrap(), addr)
+            .unwrap();
+        context.do_io(Some(Duration::from_millis(100))).unwrap();
+    }
+    assert_eq!(drained.get(), 1);
+    assert_eq!(session.queued_pdus(), 0);
+    assert_eq!(session.queued_bytes(), 0);
+    assert!(!session.is_send_queue_congested());
+    for handle in handles {
+        assert_eq!(session.try_poll_handle(&handle).unwrap().count(), 1);
+        session.remove_handle(handle);
+    }
+    session.send_request(common::gen_test_request()).unwrap();
+}
+
+#[test]
+pub fn budgeted_io_prevents_starvation() {
+    const FLOOD_SIZE: usize = 100;
+    const MAX_PDUS: usize = 4;
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let (flood_requests, status_requests) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+    for (path, counter) in [("flood", &flood_requests), ("status", &status_requests)] {
+        let counter = Rc::clone(counter);
+        let resource = CoapResource::builder(path, ())
+            .get(move |_, sess, _req, mut rsp: CoapResponse| {
+                counter.set(counter.get() + 1);
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            })
+            .build()
+            .unwrap();
+        server_context.add_resource(resource);
+    }
+    assert_eq!(
+        server_context.do_io_budgeted(Some(Duration::ZERO), 0).unwrap_err(),
+        IoProcessError::InvalidBudget
+    );
+
+    // The flooding client sends all of its requests at once, so that they are queued in the
+    // socket buffer of the server ahead of the request of the occasional client, which is sent to
+    // the same endpoint.
+    let flooder = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for mid in 0..FLOOD_SIZE as u16 {
+        // Non-confirmable GET request without a token and with a Uri-Path option of 5 bytes.
+        let mut pdu = vec![0x50, 0x01];
+        pdu.extend_from_slice(&mid.to_be_bytes());
+        pdu.push(0xb5);
+        pdu.extend_from_slice(b"flood");
+        flooder.send_to(&pdu, server_address).unwrap();
+    }
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["status"])
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+
+    // Each call returns once the budget is exhausted, reporting the queued requests as remaining
+    // work, so the caller regains control regularly and the occasional request is serviced after
+    // a bounded number of calls.
+    let mut calls = 0;
+    while status_requests.get() == 0 {
+        let outcome = server_context
+            .do_io_budgeted(Some(Duration::from_millis(100)), MAX_PDUS)
+            .unwrap();
+        assert!(outcome.handled_pdus <= MAX_PDUS + 1);
+        if status_requests.get() == 0 {
+            assert!(outcome.work_remaining);
+        }
+        calls += 1;
+        assert!(
+            calls <= FLOOD_SIZE / MAX_PDUS + 1,
+            "occasional request was not serviced in time"
+        );
+    }
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    // Any requests of the flood that are left are processed by subsequent calls.
+    common::run_until(&mut [&mut server_context], "flood", |_| {
+        (flood_requests.get() == FLOOD_SIZE).then_some(())
+    });
+    let outcome = server_context
+        .do_io_budgeted(Some(Duration::from_millis(10)), MAX_PDUS)
+        .unwrap();
+    assert_eq!((outcome.handled_pdus, outcome.work_remaining), (0, false));
+}
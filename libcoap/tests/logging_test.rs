@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * logging_test.rs - Tests for forwarding libcoap log messages to the log crate.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(not(feature = "tracing"))]
+
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+use libcoap_rs::{
+    logging::{install_log_handler, log_level, set_log_level, CoapLogLevel, LOG_TARGET, TRACE_LOG_TARGET},
+    session::{CoapClientSession, CoapSessionCommon},
+    CoapContext,
+};
+
+mod common;
+
+/// Logger that records all messages forwarded from libcoap and all PDU traces.
+struct CapturingLogger {
+    records: Mutex<Vec<(&'static str, log::Level, String)>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == LOG_TARGET || metadata.target() == TRACE_LOG_TARGET
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let target = if record.target() == LOG_TARGET {
+                LOG_TARGET
+            } else {
+                TRACE_LOG_TARGET
+            };
+            self.records
+                .lock()
+                .unwrap()
+                .push((target, record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: Mutex::new(Vec::new()),
+};
+
+static LOGGER_ONCE: Once = Once::new();
+
+fn install_logger() {
+    LOGGER_ONCE.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
+#[test]
+pub fn log_messages_are_forwarded() {
+    install_logger();
+    // Installing the handler multiple times has no effect.
+    install_log_handler();
+    install_log_handler();
+
+    set_log_level(CoapLogLevel::Debug);
+    assert_eq!(log_level(), CoapLogLevel::Debug);
+
+    // Creating a session and sending a request causes libcoap to log debug messages.
+    let server_address = common::get_unused_server_addr();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    session.send_request(common::gen_test_request()).unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    std::mem::drop(session);
+    // There is no server, so the request is still outstanding.
+    let _ = context.shutdown(Some(Duration::from_secs(0)));
+
+    set_log_level(CoapLogLevel::Warning);
+    assert_eq!(log_level(), CoapLogLevel::Warning);
+
+    let records = LOGGER.records.lock().unwrap();
+    let mut records = records.iter().filter(|(target, ..)| *target == LOG_TARGET).peekable();
+    assert!(records.peek().is_some());
+    assert!(records.all(|(_, level, message)| *level <= log::Level::Debug && !message.ends_with('\n')));
+}
+
+#[test]
+pub fn traced_sessions_log_pdus() {
+    install_logger();
+    let server_address = common::get_unused_server_addr();
+    let traced_messages = || {
+        let prefix = format!("sent to {}: ", server_address);
+        LOGGER
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(target, _, message)| *target == TRACE_LOG_TARGET && message.starts_with(&prefix))
+            .map(|(_, level, message)| (*level, message[prefix.len()..].to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut context = CoapContext::new().unwrap();
+    // Only the first session for the server address is traced.
+    context.trace_next_sessions(1, move |addr| *addr == server_address);
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let untraced_session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert!(session.is_traced());
+    assert!(!untraced_session.is_traced());
+
+    session.send_request(common::gen_test_request()).unwrap();
+    untraced_session.send_request(common::gen_test_request()).unwrap();
+    let messages = traced_messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].0, log::Level::Debug);
+    assert!(messages[0].1.starts_with("CON 0.01 MID="));
+    assert!(messages[0].1.contains("options=[UriPath(11): \"test1\"]"));
+
+    // Tracing can be switched at runtime.
+    session.set_trace(false);
+    untraced_session.set_trace(true);
+    session.send_request(common::gen_test_request()).unwrap();
+    untraced_session.send_request(common::gen_test_request()).unwrap();
+    assert_eq!(traced_messages().len(), 2);
+
+    std::mem::drop(session);
+    std::mem::drop(untraced_session);
+    // There is no server, so the requests are still outstanding.
+    let _ = context.shutdown(Some(Duration::from_secs(0)));
+}
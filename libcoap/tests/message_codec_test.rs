@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * message_codec_test.rs - Tests for encoding and parsing messages without a session.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use libcoap_rs::{
+    error::{MessageConversionError, MessageParsingError},
+    message::{CoapMessage, CoapMessageCommon, CoapOption},
+    protocol::{CoapMatch, CoapMessageCode, CoapMessageType, CoapOptionType, CoapRequestCode, CoapResponseCode},
+    types::CoapProtocol,
+};
+
+/// Small deterministic PRNG (xorshift64), so that failing cases can be reproduced.
+struct TestRng(u64);
+
+impl TestRng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a random value in `0..bound`.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    fn string(&mut self, len: usize) -> String {
+        (0..len).map(|_| (b'a' + self.below(26) as u8) as char).collect()
+    }
+}
+
+/// Generates a random valid message for the given transport, with options in the order of their
+/// option numbers (the order in which they are encoded).
+fn random_message(rng: &mut TestRng, proto: CoapProtocol) -> CoapMessage {
+    let codes = [
+        CoapMessageCode::Request(CoapRequestCode::Get),
+        CoapMessageCode::Request(CoapRequestCode::Post),
+        CoapMessageCode::Request(CoapRequestCode::Put),
+        CoapMessageCode::Response(CoapResponseCode::Content),
+        CoapMessageCode::Response(CoapResponseCode::Changed),
+        CoapMessageCode::Response(CoapResponseCode::NotFound),
+    ];
+    let code = codes[rng.below(codes.len())];
+    let type_ = match (proto.is_reliable(), code, rng.below(3)) {
+        // Reliable transports do not transmit the message type.
+        (true, ..) => CoapMessageType::Con,
+        (false, _, 0) => CoapMessageType::Con,
+        (false, CoapMessageCode::Response(_), 1) => CoapMessageType::Ack,
+        (false, ..) => CoapMessageType::Non,
+    };
+    let mut message = CoapMessage::new(type_, code);
+    if !proto.is_reliable() {
+        message.set_mid(Some(rng.next() as u16 as i32));
+    }
+    let token_len = match rng.below(10) {
+        // Extended token lengths (RFC 8974).
+        0 => 9 + rng.below(300),
+        _ => rng.below(9),
+    };
+    message.set_token(Some(rng.bytes(token_len)));
+
+    let mut options = Vec::new();
+    if rng.below(4) == 0 {
+        options.push(CoapOption::IfMatch(CoapMatch::ETag(rng.bytes(1 + rng.below(8)).into())));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::UriHost(rng.string(1 + rng.below(20))));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::ETag(rng.bytes(1 + rng.below(8)).into()));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::Observe(rng.below(1 << 24) as u32));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::UriPort(rng.next() as u16));
+    }
+    for _ in 0..rng.below(4) {
+        options.push(CoapOption::UriPath(rng.string(rng.below(300))));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::ContentFormat(rng.next() as u16));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::MaxAge(rng.next() as u32));
+    }
+    for _ in 0..rng.below(3) {
+        options.push(CoapOption::UriQuery(rng.string(rng.below(20))));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::Size2(rng.next() as u32));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::Echo(rng.bytes(1 + rng.below(40)).into()));
+    }
+    if rng.below(4) == 0 {
+        options.push(CoapOption::Other(65000, rng.bytes(rng.below(16)).into()));
+    }
+    for option in options {
+        message.add_option(option);
+    }
+    let payload_len = rng.below(600);
+    if payload_len > 0 {
+        message.set_data(Some(rng.bytes(payload_len)));
+    }
+    message
+}
+
+#[test]
+pub fn random_messages_round_trip() {
+    let mut rng = TestRng(0x5EED_C0A9_0000_0001);
+    for proto in [
+        CoapProtocol::Udp,
+        CoapProtocol::Dtls,
+        CoapProtocol::Tcp,
+        CoapProtocol::Tls,
+        CoapProtocol::Ws,
+        CoapProtocol::Wss,
+    ] {
+        for iteration in 0..500 {
+            let message = random_message(&mut rng, proto);
+            let bytes = message
+                .to_bytes(proto)
+                .unwrap_or_else(|e| panic!("encoding failed for {proto} message {iteration}: {e}"));
+            let parsed = CoapMessage::from_bytes(proto, &bytes)
+                .unwrap_or_else(|e| panic!("parsing failed for {proto} message {iteration}: {e}"));
+            assert_eq!(parsed, message, "{proto} message {iteration} differs after round trip");
+            assert_eq!(parsed.to_bytes(proto).unwrap(), bytes);
+        }
+    }
+}
+
+#[test]
+pub fn encoding_framing() {
+    let mut message = CoapMessage::new(CoapMessageType::Con, CoapMessageCode::Request(CoapRequestCode::Get));
+    message.set_token(Some([0xAB]));
+    message.add_option(CoapOption::UriPath("a".to_string()));
+    message.set_data(Some(b"x".to_vec()));
+
+    // Datagrams require a message ID, reliable transports do not transmit one.
+    assert_eq!(
+        message.to_bytes(CoapProtocol::Udp),
+        Err(MessageConversionError::MissingMessageId)
+    );
+    assert_eq!(
+        message.to_bytes(CoapProtocol::Tcp).unwrap(),
+        [0x41, 0x01, 0xAB, 0xB1, b'a', 0xFF, b'x']
+    );
+    assert_eq!(
+        message.to_bytes(CoapProtocol::Ws).unwrap(),
+        [0x01, 0x01, 0xAB, 0xB1, b'a', 0xFF, b'x']
+    );
+    message.set_mid(Some(0x1234));
+    assert_eq!(
+        message.to_bytes(CoapProtocol::Udp).unwrap(),
+        [0x41, 0x01, 0x12, 0x34, 0xAB, 0xB1, b'a', 0xFF, b'x']
+    );
+
+    let mut empty = CoapMessage::new(CoapMessageType::Con, CoapMessageCode::Empty);
+    empty.set_mid(Some(1));
+    empty.set_token(Some([]));
+    empty.set_data(Some(b"x".to_vec()));
+    assert_eq!(
+        empty.to_bytes(CoapProtocol::Udp),
+        Err(MessageConversionError::DataInEmptyMessage)
+    );
+}
+
+#[test]
+pub fn parsing_errors() {
+    use MessageParsingError::*;
+    let udp_cases: [(&[u8], MessageParsingError); 10] = [
+        (&[0x40], TruncatedHeader),
+        (&[0x80, 0x01, 0x00, 0x00], UnsupportedVersion(2)),
+        (&[0x4F, 0x01, 0x00, 0x00], ReservedTokenLength),
+        (&[0x42, 0x01, 0x00, 0x00, 0xAA], TruncatedToken),
+        (&[0x40, 0x20, 0x00, 0x00], InvalidCode(0x20)),
+        (&[0x41, 0x00, 0x00, 0x00, 0xAA], ContentInEmptyMessage),
+        (&[0x40, 0x01, 0x00, 0x00, 0xF1, 0x00], BadOptionDelta(4)),
+        (&[0x40, 0x01, 0x00, 0x00, 0x1F], BadOptionLength(4)),
+        (&[0x40, 0x01, 0x00, 0x00, 0xB1, b'a', 0xB3, b'b'], TruncatedOption(6)),
+        (&[0x40, 0x01, 0x00, 0x00, 0xFF], PayloadMarkerWithoutPayload),
+    ];
+    for (bytes, expected) in udp_cases {
+        assert_eq!(CoapMessage::from_bytes(CoapProtocol::Udp, bytes), Err(expected));
+    }
+    // Option numbers exceeding 65535.
+    assert_eq!(
+        CoapMessage::from_bytes(
+            CoapProtocol::Udp,
+            &[0x40, 0x01, 0x00, 0x00, 0xE0, 0xFE, 0x00, 0xE0, 0x01, 0x00]
+        ),
+        Err(BadOptionDelta(7))
+    );
+    // Tokens longer than supported by libcoap.
+    assert!(matches!(
+        CoapMessage::from_bytes(CoapProtocol::Udp, &[0x4E, 0x01, 0x00, 0x00, 0xFF, 0xFF]),
+        Err(TokenTooLong(_))
+    ));
+
+    assert_eq!(
+        CoapMessage::from_bytes(CoapProtocol::Tcp, &[0x30, 0x01]),
+        Err(LengthMismatch(3, 0))
+    );
+    assert_eq!(
+        CoapMessage::from_bytes(CoapProtocol::Tcp, &[0xD0]),
+        Err(TruncatedHeader)
+    );
+    assert_eq!(
+        CoapMessage::from_bytes(CoapProtocol::Ws, &[0x10, 0x01, 0x00]),
+        Err(InvalidLengthField)
+    );
+    assert_eq!(
+        CoapMessage::from_bytes(CoapProtocol::None, &[0x40, 0x01, 0x00, 0x00]),
+        Err(UnsupportedProtocol(CoapProtocol::None))
+    );
+    // Well-formed messages with invalid option values can not be converted.
+    assert!(matches!(
+        CoapMessage::from_bytes(CoapProtocol::Udp, &[0x40, 0x01, 0x00, 0x00, 0x73, 0x01, 0x02, 0x03]),
+        Err(Conversion(MessageConversionError::InvalidOptionValue(
+            Some(CoapOptionType::UriPort),
+            _
+        )))
+    ));
+}
+
+#[test]
+pub fn parsing_arbitrary_input() {
+    let mut rng = TestRng(0x5EED_C0A9_0000_0002);
+    for proto in [CoapProtocol::Udp, CoapProtocol::Tcp, CoapProtocol::Ws] {
+        for _ in 0..2000 {
+            // Mutations of valid encodings reach deeper into the parser than random bytes.
+            let mut bytes = match rng.below(2) {
+                0 => random_message(&mut rng, proto).to_bytes(proto).unwrap(),
+                _ => {
+                    let len = rng.below(64);
+                    rng.bytes(len)
+                },
+            };
+            for _ in 0..rng.below(4) {
+                if !bytes.is_empty() {
+                    let idx = rng.below(bytes.len());
+                    bytes[idx] = rng.next() as u8;
+                }
+            }
+            bytes.truncate(rng.below(bytes.len() + 1));
+            // Parsing must never panic, but any message that is parsed must be re-encodable.
+            if let Ok(message) = CoapMessage::from_bytes(proto, &bytes) {
+                let reparsed = CoapMessage::from_bytes(proto, &message.to_bytes(proto).unwrap()).unwrap();
+                assert_eq!(reparsed, message);
+            }
+        }
+    }
+}
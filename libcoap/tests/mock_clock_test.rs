@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * mock_clock_test.rs - Tests using the mock clock of the test-util feature.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(feature = "test-util")]
+
+use std::cell::{Cell, RefCell};
+use std::net::UdpSocket;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::RequestPollError,
+    message::{CoapMessageCommon, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapSessionCloseReason, CoapSessionCommon},
+    types::CoapProtocol,
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn mock_clock_timeouts() {
+    use libcoap_rs::clock::{CoapClock, MockClock};
+
+    let start = Instant::now();
+    // Clones of a mock clock share the same time.
+    let clock = MockClock::new();
+    let clock_start = clock.now();
+    clock.clone().advance(Duration::from_secs(5));
+    assert_eq!(clock.now() - clock_start, Duration::from_secs(5));
+    assert_eq!(clock.elapsed(), Duration::from_secs(5));
+
+    let silent_peer = UdpSocket::bind("localhost:0").unwrap();
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, silent_peer.local_addr().unwrap()).unwrap();
+    let mut request = common::gen_test_request();
+    request.set_timeout(Some(Duration::from_secs(30)));
+    let req_handle = session.send_request(request).unwrap();
+    context.advance_time_for_test(Duration::from_secs(29)).unwrap();
+    assert_eq!(session.try_poll_handle(&req_handle).unwrap().count(), 0);
+    context.advance_time_for_test(Duration::from_secs(2)).unwrap();
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::TimedOut
+    );
+    session.remove_handle(req_handle);
+
+    // Cached responses become stale once their freshness lifetime has elapsed on the clock of the
+    // client context.
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let count = Rc::new(Cell::new(0u32));
+    let resource = CoapResource::new("fresh", count.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |count: &mut Rc<Cell<u32>>, sess, _req, mut rsp: CoapResponse| {
+                count.set(count.get() + 1);
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(count.get().to_string().into_bytes()));
+                sess.send(rsp.with_max_age(Duration::from_secs(60))).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    session.set_response_cache_capacity(Some(1));
+    let get = || {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["fresh"])
+            .build()
+            .unwrap()
+    };
+
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get());
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+    context.advance_time_for_test(Duration::from_secs(59)).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get());
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+    assert_eq!(response.max_age(), Some(1));
+    context.advance_time_for_test(Duration::from_secs(2)).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get());
+    assert_eq!(response.data().unwrap().as_ref(), "2".as_bytes());
+    assert_eq!(count.get(), 2);
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+pub fn retry_service_unavailable() {
+    use libcoap_rs::session::RetryPolicy;
+
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    // The first request to the resource is answered with 5.03, all following ones with 2.05.
+    let count = Rc::new(Cell::new(0u32));
+    let resource = CoapResource::new("busy", count.clone(), false);
+    for code in [CoapRequestCode::Get, CoapRequestCode::Post] {
+        resource.set_method_handler(
+            code,
+            Some(CoapRequestHandler::new(
+                |count: &mut Rc<Cell<u32>>, sess, _req, mut rsp: CoapResponse| {
+                    count.set(count.get() + 1);
+                    if count.get() == 1 {
+                        rsp.set_code(CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable));
+                        sess.send(rsp.with_max_age(Duration::from_secs(5))).unwrap();
+                    } else {
+                        rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                        sess.send(rsp).unwrap();
+                    }
+                },
+            )),
+        );
+    }
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    session.set_retry_policy(Some(RetryPolicy::new(2)));
+    let attempts = Rc::new(RefCell::new(Vec::new()));
+    let hook_attempts = Rc::clone(&attempts);
+    session.set_retry_hook(move |attempt| hook_attempts.borrow_mut().push(attempt.clone()));
+    let request = |code| CoapRequestBuilder::new(code).uri_path(["busy"]).build().unwrap();
+
+    // The request is only re-sent once the Max-Age of the 5.03 response has elapsed.
+    let req_handle = session.send_request(request(CoapRequestCode::Get)).unwrap();
+    common::run_until(&mut [&mut server_context, &mut context], "response", |_| {
+        assert_eq!(session.try_poll_handle(&req_handle).unwrap().count(), 0);
+        (!attempts.borrow().is_empty()).then_some(())
+    });
+    server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(session.try_poll_handle(&req_handle).unwrap().count(), 0);
+    assert_eq!(count.get(), 1);
+    {
+        let attempts = attempts.borrow();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].attempt, 1);
+        assert_eq!(attempts[0].code, CoapResponseCode::ServiceUnavailable);
+        assert_eq!(attempts[0].delay, Duration::from_secs(5));
+    }
+    context.advance_time_for_test(Duration::from_secs(5)).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    // The request was re-sent using its original token.
+    assert_eq!(response.token(), Some(attempts.borrow()[0].token.as_ref()));
+    assert_eq!(count.get(), 2);
+    session.remove_handle(req_handle);
+
+    // POST requests are not re-sent unless explicitly enabled.
+    count.set(0);
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        request(CoapRequestCode::Post),
+    );
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable)
+    );
+    assert_eq!(count.get(), 1);
+    assert_eq!(attempts.borrow().len(), 1);
+}
+
+#[test]
+pub fn idle_session_hook_and_stats() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .get(|_, sess, _req, mut rsp: CoapResponse| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    server_context.set_session_timeout(Duration::from_secs(10));
+    // Keeps the first idle session alive once.
+    let hook_calls = Rc::new(Cell::new(0));
+    let hook_calls_clone = Rc::clone(&hook_calls);
+    server_context.set_idle_session_hook(move |_session| {
+        hook_calls_clone.set(hook_calls_clone.get() + 1);
+        hook_calls_clone.get() == 1
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    let stats = server_context.server_session_stats();
+    assert_eq!(stats.current, 1);
+    assert_eq!(stats.created, 1);
+    assert_eq!(stats.reaped, 0);
+    assert_eq!(
+        server_context.server_session_stats_by_protocol(),
+        vec![(CoapProtocol::Udp, stats)]
+    );
+
+    // The hook is called shortly before the session timeout elapses.
+    server_context.advance_time_for_test(Duration::from_secs(8)).unwrap();
+    assert_eq!(hook_calls.get(), 0);
+    server_context.advance_time_for_test(Duration::from_secs(1)).unwrap();
+    assert_eq!(hook_calls.get(), 1);
+    // The session was kept alive by sending a ping, so the hook is called again after another
+    // session timeout.
+    server_context.advance_time_for_test(Duration::from_secs(9)).unwrap();
+    assert_eq!(hook_calls.get(), 2);
+    // Once the hook declined to keep the session alive, it is not called again.
+    server_context.advance_time_for_test(Duration::from_secs(20)).unwrap();
+    assert_eq!(hook_calls.get(), 2);
+
+    let server_session = server_context.session_by_peer(session.addr_local()).unwrap();
+    server_session.disconnect(CoapSessionCloseReason::Release);
+    std::mem::drop(server_session);
+    server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+    let stats = server_context.server_session_stats();
+    assert_eq!(stats.current, 0);
+    assert_eq!(stats.created, 1);
+    assert_eq!(stats.reaped, 1);
+}
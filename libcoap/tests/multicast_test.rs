@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * multicast_test.rs - Tests for multicast requests and response delays.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::ContextConfigurationError,
+    message::{CoapMessageCommon, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode, DEFAULT_LEISURE, DEFAULT_PROBING_RATE},
+    session::{CoapClientSession, CoapSessionCommon},
+    CoapContext, CoapContextBuilder, CoapRequestHandler, CoapResource, ResourceFlags,
+};
+
+mod common;
+
+/// "All CoAP Nodes" IPv4 multicast address, see
+/// [RFC 7252, Section 12.8](https://datatracker.ietf.org/doc/html/rfc7252#section-12.8).
+const ALL_COAP_NODES: Ipv4Addr = Ipv4Addr::new(224, 0, 1, 187);
+
+#[test]
+pub fn leisure_and_probing_rate_configuration() {
+    let context = CoapContext::new().unwrap();
+    assert_eq!(context.default_leisure(), DEFAULT_LEISURE);
+    assert_eq!(context.probing_rate(), DEFAULT_PROBING_RATE);
+    let old_config = context.config();
+    context.set_default_leisure(Duration::from_millis(1500));
+    context.set_probing_rate(100);
+    assert_eq!(context.default_leisure(), Duration::from_millis(1500));
+    assert_eq!(context.probing_rate(), 100);
+    assert_eq!(
+        old_config.diff(&context.config()),
+        vec!["default_leisure: 5s -> 1.5s", "probing_rate: 1 -> 100"]
+    );
+
+    // New sessions use the values configured for the context, which can be changed per session.
+    let mut context = CoapContextBuilder::new()
+        .default_leisure(Duration::from_millis(250))
+        .probing_rate(50)
+        .build()
+        .unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, common::get_unused_server_addr()).unwrap();
+    assert_eq!(session.default_leisure(), Duration::from_millis(250));
+    assert_eq!(session.probing_rate(), 50);
+    session.set_default_leisure(Duration::from_secs(2));
+    session.set_probing_rate(10);
+    assert_eq!(session.default_leisure(), Duration::from_secs(2));
+    assert_eq!(session.probing_rate(), 10);
+    assert_eq!(context.default_leisure(), Duration::from_millis(250));
+}
+
+#[test]
+pub fn multicast_responses_are_delayed() {
+    const LEISURE: Duration = Duration::from_secs(2);
+    let port = common::get_unused_server_addr().port();
+    let server_address = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port);
+
+    let mut server_contexts = Vec::new();
+    for _ in 0..2 {
+        let mut server_context = CoapContext::new().unwrap();
+        server_context.set_default_leisure(LEISURE);
+        server_context.add_endpoint_udp(server_address).unwrap();
+        if let Err(e) = server_context.join_multicast_group(ALL_COAP_NODES.into(), None) {
+            // No multicast-capable interface available in this environment.
+            assert_eq!(e, ContextConfigurationError::MulticastJoinFailed(ALL_COAP_NODES.into()));
+            return;
+        }
+        let resource = CoapResource::new_with_flags("test1", (), ResourceFlags::HAS_MCAST_SUPPORT).unwrap();
+        resource.set_method_handler(
+            CoapRequestCode::Get,
+            Some(CoapRequestHandler::new(
+                |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                    rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                    sess.send(rsp).unwrap();
+                },
+            )),
+        );
+        server_context.add_resource(resource);
+        server_contexts.push(server_context);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session =
+        CoapClientSession::connect_udp(&mut context, SocketAddr::new(IpAddr::V4(ALL_COAP_NODES), port)).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .confirmable(false)
+        .uri_path(["test1"])
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let Ok(req_handle) = session.send_request(request) else {
+        // Sending to multicast addresses is not possible in this environment.
+        return;
+    };
+    let mut arrivals = Vec::new();
+    while arrivals.len() < server_contexts.len() && start.elapsed() < LEISURE + Duration::from_secs(3) {
+        for server_context in &mut server_contexts {
+            server_context.do_io(Some(Duration::from_millis(5))).unwrap();
+        }
+        context.do_io(Some(Duration::from_millis(5))).unwrap();
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            arrivals.push(start.elapsed());
+        }
+    }
+    if arrivals.is_empty() {
+        // Multicast traffic is not delivered in this environment.
+        return;
+    }
+    // Responses are sent after a random delay within the leisure window instead of right away.
+    assert!(arrivals.iter().all(|v| *v < LEISURE + Duration::from_secs(1)));
+    assert!(arrivals.iter().any(|v| *v > Duration::from_millis(50)));
+}
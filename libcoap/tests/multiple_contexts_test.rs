@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * multiple_contexts_test.rs - Tests for running multiple contexts in the same process.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::Duration;
+
+use libcoap_rs::session::CoapClientSession;
+use libcoap_rs::{
+    message::{CoapMessageCommon, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::CoapSessionCommon,
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+/// Runs a single client request against a test server spawned in its own thread.
+fn run_loopback_pair() {
+    let server_address = common::get_unused_server_addr();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context.add_endpoint_udp(server_address).unwrap();
+        context
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    let request = common::gen_test_request();
+    let req_handle = session.send_request(request).unwrap();
+    loop {
+        assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            assert_eq!(response.data().unwrap().as_ref(), "Hello World!".as_bytes());
+            server_handle.join().unwrap();
+            return;
+        }
+    }
+}
+
+#[test]
+pub fn concurrent_loopback_pairs() {
+    let pair_handles: Vec<_> = (0..2).map(|_| std::thread::spawn(run_loopback_pair)).collect();
+    for handle in pair_handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+pub fn loopback_pairs_on_same_thread() {
+    let server_addresses = [common::get_unused_server_addr(), common::get_unused_server_addr()];
+
+    // Each server context answers with its own index, so that we can check that responses are
+    // dispatched to the handlers of the correct context.
+    let mut server_contexts: Vec<CoapContext> = server_addresses
+        .iter()
+        .enumerate()
+        .map(|(idx, addr)| {
+            let mut context = CoapContext::new().unwrap();
+            context.add_endpoint_udp(*addr).unwrap();
+            let resource = CoapResource::new("test1", idx, false);
+            resource.set_method_handler(
+                CoapRequestCode::Get,
+                Some(CoapRequestHandler::new(
+                    |idx: &mut usize, sess, _req, mut rsp: CoapResponse| {
+                        rsp.set_data(Some(format!("Hello from {}!", idx).into_bytes()));
+                        rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                        sess.send(rsp).unwrap();
+                    },
+                )),
+            );
+            context.add_resource(resource);
+            context
+        })
+        .collect();
+
+    let mut client_context = CoapContext::new().unwrap();
+    let sessions: Vec<_> = server_addresses
+        .iter()
+        .map(|addr| CoapClientSession::connect_udp(&mut client_context, *addr).unwrap())
+        .collect();
+    let handles: Vec<_> = sessions
+        .iter()
+        .map(|session| session.send_request(common::gen_test_request()).unwrap())
+        .collect();
+
+    let mut completed = vec![false; sessions.len()];
+    let mut remaining_iterations = 1000;
+    while !completed.iter().all(|v| *v) {
+        assert!(remaining_iterations > 0, "timeout while waiting for test responses");
+        remaining_iterations -= 1;
+        for context in server_contexts.iter_mut() {
+            context.do_io(Some(Duration::from_millis(10))).expect("error during server IO");
+        }
+        client_context
+            .do_io(Some(Duration::from_millis(10)))
+            .expect("error during client IO");
+        for (idx, (session, handle)) in sessions.iter().zip(handles.iter()).enumerate() {
+            for response in session.poll_handle(handle) {
+                assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+                assert_eq!(
+                    response.data().unwrap().as_ref(),
+                    format!("Hello from {}!", idx).as_bytes()
+                );
+                completed[idx] = true;
+            }
+        }
+    }
+
+    std::mem::drop(sessions);
+    client_context.shutdown(Some(Duration::from_secs(0))).unwrap();
+    for context in server_contexts {
+        context.shutdown(Some(Duration::from_secs(0))).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * notification_order_test.rs - Tests for the detection of reordered notifications.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::{Duration, Instant};
+
+use libcoap_rs::session::{is_fresher_notification, CoapNotificationOrder, NOTIFICATION_FRESHNESS_TIMEOUT};
+
+/// Checks the given Observe values (all received at `now`) and returns which ones were fresh.
+fn check_sequence(order: &mut CoapNotificationOrder, values: &[u32], now: Instant) -> Vec<bool> {
+    values.iter().map(|value| order.check(*value, now)).collect()
+}
+
+#[test]
+pub fn reordered_and_duplicated_notifications() {
+    let now = Instant::now();
+    let mut order = CoapNotificationOrder::new();
+    assert_eq!(order.freshest(), None);
+    assert_eq!(
+        check_sequence(&mut order, &[2, 3, 3, 1, 7, 5, 8], now),
+        [true, true, false, false, true, false, true]
+    );
+    assert_eq!(order.freshest(), Some(8));
+    assert_eq!(order.stale_count(), 3);
+}
+
+#[test]
+pub fn observe_value_wraparound() {
+    let now = Instant::now();
+    let mut order = CoapNotificationOrder::new();
+    assert_eq!(
+        check_sequence(&mut order, &[0xff_fffe, 0xff_ffff, 0, 1, 0xff_ffff, 2], now),
+        [true, true, true, true, false, true]
+    );
+    assert_eq!(order.freshest(), Some(2));
+    assert_eq!(order.stale_count(), 1);
+
+    // Values are compared modulo 2^24.
+    assert!(!is_fresher_notification(5, now, (1 << 24) | 5, now));
+    assert!(is_fresher_notification(5, now, (1 << 24) | 6, now));
+    let mut order = CoapNotificationOrder::new();
+    assert!(order.check((1 << 24) | 6, now));
+    assert_eq!(order.freshest(), Some(6));
+
+    // Values that are exactly half of the value range apart are never fresher than each other.
+    assert!(!is_fresher_notification(0, now, 1 << 23, now));
+    assert!(!is_fresher_notification(1 << 23, now, 0, now));
+    assert!(is_fresher_notification(0, now, (1 << 23) - 1, now));
+    assert!(is_fresher_notification((1 << 23) + 1, now, 0, now));
+}
+
+#[test]
+pub fn notification_freshness_timeout() {
+    assert_eq!(NOTIFICATION_FRESHNESS_TIMEOUT, Duration::from_secs(128));
+    let start = Instant::now();
+    let mut order = CoapNotificationOrder::new();
+    assert!(order.check(10, start));
+    assert!(!order.check(5, start + Duration::from_secs(100)));
+    assert!(!order.check(5, start + NOTIFICATION_FRESHNESS_TIMEOUT));
+    // After 128 seconds, the server may have restarted its sequence of Observe values.
+    assert!(order.check(5, start + NOTIFICATION_FRESHNESS_TIMEOUT + Duration::from_secs(1)));
+    assert_eq!(order.freshest(), Some(5));
+    assert_eq!(order.stale_count(), 2);
+    // The timeout is measured from the freshest notification, not from the first one.
+    let later = start + Duration::from_secs(200);
+    assert!(!order.check(4, later));
+    assert!(order.check(6, later));
+}
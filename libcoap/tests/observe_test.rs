@@ -0,0 +1,608 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * observe_test.rs - Tests for resource observation and notifications.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use libcoap_rs::{
+    error::PersistError,
+    message::{CoapMessage, CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    persist::{CoapObserveKey, CoapObserveRecord, CoapPersistHandler, PersistConfig},
+    protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    types::CoapProtocol,
+    CoapContext, CoapEventHandler, CoapObserver, CoapRequestHandler, CoapResource, NotificationConsistency,
+    NotificationPacing, NotificationTypePolicy,
+};
+
+mod common;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SentMessage {
+    Notification(u32),
+    Response(u32),
+}
+
+#[test]
+pub fn observe_serve_after_pending_notify() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let sent_messages: Rc<RefCell<Vec<SentMessage>>> = Rc::new(RefCell::new(Vec::new()));
+    let sent_messages_handler = sent_messages.clone();
+    let resource = CoapResource::new("test1", 0u32, false);
+    resource.set_get_observable(true);
+    resource.set_notification_consistency(NotificationConsistency::ServeAfterPendingNotify);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new_resource_ref(
+            move |resource: &CoapResource<u32>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                let value = *resource.user_data();
+                if req.observe().is_some() {
+                    rsp.set_observe(Some(value));
+                    sent_messages_handler
+                        .borrow_mut()
+                        .push(SentMessage::Notification(value));
+                } else {
+                    // Deferred requests are only handled after the notification has been sent.
+                    assert!(!resource.is_notification_pending());
+                    sent_messages_handler.borrow_mut().push(SentMessage::Response(value));
+                }
+                rsp.set_data(Some(value.to_string().into_bytes()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<u32>, sess, _req, mut rsp: CoapResponse| {
+                *resource.user_data_mut() += 1;
+                assert!(resource.notify_observers());
+                assert!(resource.is_notification_pending());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    let mut observe_request = common::gen_test_request();
+    observe_request.set_observe(Some(0));
+    let observe_handle = session.send_request(observe_request).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+
+    let put_request = CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Put, "/test1".parse().unwrap()).unwrap();
+    session.send_request(put_request).unwrap();
+    let get_handle = session.send_request(common::gen_test_request()).unwrap();
+    let response = common::run_until(&mut [&mut server_context, &mut context], "response", |_| {
+        session
+            .poll_handle(&get_handle)
+            .find(|response| response.code() == CoapMessageCode::Response(CoapResponseCode::Content))
+    });
+    assert_eq!(response.data().unwrap().as_ref(), "1".as_bytes());
+
+    // The response to the plain GET request must not be sent before the notification.
+    assert_eq!(
+        sent_messages.borrow().as_slice(),
+        &[
+            SentMessage::Notification(0),
+            SentMessage::Notification(1),
+            SentMessage::Response(1)
+        ]
+    );
+}
+
+#[test]
+pub fn observe_notification_type_overrides() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", 0u32, false);
+    resource.set_get_observable(true);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<u32>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                let value = *resource.user_data();
+                if req.observe() == Some(0) && req.uri().query() == Some("reliable=1".as_bytes()) {
+                    resource.set_observer_notify_type(sess, req, Some(CoapMessageType::Con));
+                }
+                rsp.set_observe(Some(value));
+                rsp.set_data(Some(value.to_string().into_bytes()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<u32>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                *resource.user_data_mut() += 1;
+                if req.uri().query() == Some("quiet=1".as_bytes()) {
+                    assert!(resource.notify_observers_with(CoapMessageType::Non));
+                } else {
+                    assert!(resource.notify_observers());
+                }
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    let observe_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .uri_query([("reliable", "1")])
+        .observe(0)
+        .build()
+        .unwrap();
+    let observe_handle = session.send_request(observe_request).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(response.data().unwrap().as_ref(), "0".as_bytes());
+
+    // Expected notification value and type for each PUT request (with the given query).
+    for (query, value, type_) in [
+        // The override of the observer applies.
+        (None, 1, CoapMessageType::Con),
+        // The type requested for the notification takes precedence.
+        (Some(("quiet", "1")), 2, CoapMessageType::Non),
+        // The type requested for the previous notification no longer applies.
+        (None, 3, CoapMessageType::Con),
+    ] {
+        let mut put_request = CoapRequestBuilder::new(CoapRequestCode::Put).uri_path(["test1"]);
+        if let Some(query) = query {
+            put_request = put_request.uri_query([query]);
+        }
+        let response = common::exchange_request(
+            &mut server_context,
+            &mut context,
+            &session,
+            put_request.build().unwrap(),
+        );
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+        let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+        assert_eq!(notification.data().unwrap().as_ref(), value.to_string().as_bytes());
+        assert_eq!(notification.type_(), type_);
+    }
+}
+
+#[test]
+pub fn observe_snapshot_notifications() {
+    const COALESCE_INTERVAL: Duration = Duration::from_millis(300);
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .observable(true)
+        .coalesce_interval(COALESCE_INTERVAL)
+        .get(|_, sess, _req, mut rsp| {
+            rsp.set_data(Some("handler".as_bytes()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    assert_eq!(resource.coalesce_interval(), Some(COALESCE_INTERVAL));
+    server_context.add_resource(resource);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut observe_request = common::gen_test_request();
+    observe_request.set_observe(Some(0));
+    let observe_handle = session.send_request(observe_request).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(response.data().unwrap().as_ref(), "handler".as_bytes());
+
+    // The first notification is sent right away, using the snapshot instead of the GET handler.
+    assert!(resource.notify_observers_with_snapshot("1".as_bytes(), Some(0), Some(30)));
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "1".as_bytes());
+    assert_eq!(notification.content_format(), Some(0));
+    assert_eq!(notification.max_age(), Some(30));
+    let first_notification = Instant::now();
+
+    // Further notifications within the interval are coalesced, only the latest one is sent.
+    assert!(resource.notify_observers_with_snapshot("2".as_bytes(), None, None));
+    assert!(resource.notify_observers_with_snapshot("3".as_bytes(), None, None));
+    assert!(resource.is_notification_pending());
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "3".as_bytes());
+    assert!(first_notification.elapsed() >= COALESCE_INTERVAL);
+    assert!(!resource.is_notification_pending());
+
+    // Large snapshots are sent block-wise.
+    std::thread::sleep(COALESCE_INTERVAL);
+    let large_snapshot: Arc<[u8]> = (0..4000u32).map(|v| (v % 251) as u8).collect();
+    assert!(resource.notify_observers_with_snapshot(large_snapshot.clone(), None, None));
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), large_snapshot.as_ref());
+
+    // Without a snapshot, the GET handler is called again.
+    resource.set_coalesce_interval(None);
+    assert!(resource.notify_observers());
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "handler".as_bytes());
+}
+
+/// Persistence handler that records all tracked observe relationships.
+#[derive(Debug, Default)]
+struct ObserveRecorder {
+    added: Rc<RefCell<Vec<CoapObserveRecord>>>,
+    deleted: Rc<Cell<usize>>,
+}
+
+impl CoapPersistHandler for ObserveRecorder {
+    fn observe_added(&mut self, _key: CoapObserveKey, record: &CoapObserveRecord) {
+        self.added.borrow_mut().push(record.clone());
+    }
+
+    fn observe_deleted(&mut self, _key: CoapObserveKey) {
+        self.deleted.set(self.deleted.get() + 1);
+    }
+}
+
+fn persistent_observe_server(server_address: SocketAddr, recorder: ObserveRecorder) -> CoapContext<'static> {
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", 0u32, false);
+    resource.set_get_observable(true);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<u32>, sess, _req, mut rsp: CoapResponse| {
+                rsp.set_data(Some(resource.user_data().to_string().into_bytes()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<u32>, sess, _req, mut rsp: CoapResponse| {
+                *resource.user_data_mut() += 1;
+                assert!(resource.notify_observers());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    server_context
+        .enable_persistence(PersistConfig::Callbacks {
+            handler: Box::new(recorder),
+            save_frequency: 1,
+        })
+        .unwrap();
+    server_context
+}
+
+#[test]
+pub fn observe_persistence() {
+    let server_address = common::get_unused_server_addr();
+    let recorder = ObserveRecorder::default();
+    let added = recorder.added.clone();
+    let deleted = recorder.deleted.clone();
+    let mut server_context = persistent_observe_server(server_address, recorder);
+    assert_eq!(
+        server_context.enable_persistence(PersistConfig::Callbacks {
+            handler: Box::new(ObserveRecorder::default()),
+            save_frequency: 1,
+        }),
+        Err(PersistError::AlreadyEnabled)
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut observe_request = common::gen_test_request();
+    observe_request.set_observe(Some(0));
+    let observe_handle = session.send_request(observe_request).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(response.data().unwrap().as_ref(), "0".as_bytes());
+    assert_eq!(added.borrow().len(), 1);
+    let record = added.borrow()[0].clone();
+    assert_eq!(record.proto, CoapProtocol::Udp);
+    assert_eq!(record.endpoint_addr, server_address);
+
+    // Relationships that still exist when the server is stopped must not be reported as deleted.
+    std::mem::drop(server_context);
+    assert_eq!(deleted.get(), 0);
+
+    let mut server_context = persistent_observe_server(server_address, ObserveRecorder::default());
+    server_context.restore_observer(&record).unwrap();
+    let put_request = CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Put, "/test1".parse().unwrap()).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, put_request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "1".as_bytes());
+}
+
+#[test]
+pub fn observer_introspection() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .observable(true)
+        .get(|_, sess, _req, mut rsp| {
+            rsp.set_data(Some("handler".as_bytes()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+    assert_eq!(resource.observer_count(), 0);
+
+    let mut context = CoapContext::new().unwrap();
+    let first = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let second = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let observe_request = |token: &[u8]| {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["test1"])
+            .query_pair("filter", Some("temp"))
+            .observe(0)
+            .token(token.to_vec())
+            .build()
+            .unwrap()
+    };
+    let registered_after = SystemTime::now();
+    let first_handle = first.send_request(observe_request(&[1])).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &first, &first_handle);
+    let second_handle = second.send_request(observe_request(&[2])).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &second, &second_handle);
+    // Plain GET requests do not register observers.
+    common::exchange_request(&mut server_context, &mut context, &first, common::gen_test_request());
+
+    let observers: Vec<_> = resource.observers().collect();
+    assert_eq!(resource.observer_count(), 2);
+    assert_eq!(observers[0].token(), [1]);
+    assert_eq!(
+        observers[0].addr_remote().map(|addr| addr.port()),
+        Some(first.addr_local().port())
+    );
+    assert_eq!(observers[0].query(), Some("filter=temp"));
+    assert!(observers[0].established() >= registered_after);
+    assert_eq!(observers[1].token(), [2]);
+    assert_eq!(
+        observers[1].addr_remote().map(|addr| addr.port()),
+        Some(second.addr_local().port())
+    );
+    assert_ne!(observers[0].session_id(), observers[1].session_id());
+
+    // The cancelled observer receives a final error notification, the other one a regular one.
+    assert!(resource.cancel_observer(&observers[0]));
+    let notification = common::wait_for_response(&mut server_context, &mut context, &first, &first_handle);
+    assert_eq!(
+        notification.code(),
+        CoapMessageCode::Response(CoapResponseCode::ServiceUnavailable)
+    );
+    let notification = common::wait_for_response(&mut server_context, &mut context, &second, &second_handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "handler".as_bytes());
+    assert_eq!(resource.observers().collect::<Vec<_>>(), [observers[1].clone()]);
+    assert!(!resource.cancel_observer(&observers[0]));
+
+    // Observers that cancel their registration themselves are removed as well.
+    let mut deregistration = observe_request(&[2]);
+    deregistration.set_observe(Some(1));
+    second.remove_handle(second_handle);
+    common::exchange_request(&mut server_context, &mut context, &second, deregistration);
+    assert_eq!(resource.observer_count(), 0);
+}
+
+#[test]
+pub fn observe_notification_type_policy() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .observable(true)
+        .notification_type_policy(NotificationTypePolicy::Mixed {
+            con_every: 3,
+            con_interval: None,
+        })
+        .get(|_, sess, _req, mut rsp| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let observe_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .observe(0)
+        .build()
+        .unwrap();
+    let observe_handle = session.send_request(observe_request).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+
+    // Every third notification since the registration is confirmable.
+    let non = CoapMessageType::Non;
+    let con = CoapMessageType::Con;
+    for type_ in [non, non, con, non, non, con] {
+        assert!(resource.notify_observers());
+        let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+        assert_eq!(notification.type_(), type_);
+        let observer = resource.observers().next().unwrap();
+        assert_eq!(observer.uri_path(), "test1");
+        assert_eq!(observer.last_observe(), notification.observe());
+    }
+
+    // The message type requested for a notification still takes precedence.
+    resource.set_notification_type_policy(Some(NotificationTypePolicy::AlwaysNon));
+    assert!(resource.notify_observers_with(CoapMessageType::Con));
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.type_(), con);
+    assert!(resource.notify_observers());
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(notification.type_(), non);
+}
+
+/// Event handler that records the observers that were removed because they were unreachable.
+#[derive(Debug)]
+struct UnreachableObserverRecorder(Rc<RefCell<Vec<CoapObserver>>>);
+
+impl CoapEventHandler for UnreachableObserverRecorder {
+    fn handle_observer_unreachable(&mut self, _session: &mut CoapServerSession, observer: &CoapObserver) {
+        self.0.borrow_mut().push(observer.clone());
+    }
+}
+
+#[test]
+pub fn observe_unreachable_observer_removal() {
+    let server_address = common::get_unused_server_addr();
+
+    let removed = Rc::new(RefCell::new(Vec::new()));
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.set_event_handler(UnreachableObserverRecorder(Rc::clone(&removed)));
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .observable(true)
+        .notification_type_policy(NotificationTypePolicy::AlwaysCon)
+        .get(|_, sess, _req, mut rsp| {
+            // Give up on unacknowledged notifications quickly.
+            sess.set_ack_timeout(0, 100);
+            sess.set_max_retransmit(1);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+
+    // The peer registers as an observer (confirmable GET with token 0x07, Observe 0 and Uri-Path
+    // "test1"), but never acknowledges any notifications.
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut datagram = vec![0x41, 0x01, 0x43, 0x21, 0x07, 0x60, 0x55];
+    datagram.extend_from_slice("test1".as_bytes());
+    peer_socket.send_to(&datagram, server_address).unwrap();
+    common::run_until(&mut [&mut server_context], "registration", |_| {
+        (resource.observer_count() > 0).then_some(())
+    });
+    let mut buf = [0; 1500];
+    let (len, _) = peer_socket.recv_from(&mut buf).unwrap();
+    let response = CoapMessage::from_bytes(CoapProtocol::Udp, &buf[..len]).unwrap();
+    assert_eq!(response.type_(), CoapMessageType::Ack);
+    let observer = resource.observers().next().unwrap();
+
+    assert!(resource.notify_observers());
+    common::run_until(&mut [&mut server_context], "observer removal", |_| {
+        (!removed.borrow().is_empty()).then_some(())
+    });
+    let (len, _) = peer_socket.recv_from(&mut buf).unwrap();
+    let notification = CoapMessage::from_bytes(CoapProtocol::Udp, &buf[..len]).unwrap();
+    assert_eq!(notification.type_(), CoapMessageType::Con);
+    assert_eq!(removed.borrow().len(), 1);
+    assert_eq!(removed.borrow()[0].token(), [0x07]);
+    assert_eq!(removed.borrow()[0].session_id(), observer.session_id());
+    assert_eq!(resource.observer_count(), 0);
+}
+
+#[test]
+pub fn paced_notifications() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    server_context.set_notification_pacing(NotificationPacing::PdusPerIteration(1));
+    assert_eq!(
+        server_context.notification_pacing(),
+        NotificationPacing::PdusPerIteration(1)
+    );
+    let resource = CoapResource::builder("test1", ())
+        .observable(true)
+        .get(|_, sess, _req, mut rsp| {
+            rsp.set_data(Some("handler".as_bytes()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let mut observers = Vec::new();
+    for token in 1..=3u8 {
+        let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+        let observe_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["test1"])
+            .observe(0)
+            .token(vec![token])
+            .build()
+            .unwrap();
+        let handle = session.send_request(observe_request).unwrap();
+        common::wait_for_response(&mut server_context, &mut context, &session, &handle);
+        observers.push((session, handle));
+    }
+
+    // Each call to do_io() sends a single notification.
+    assert!(resource.notify_observers_with_snapshot("1".as_bytes(), None, None));
+    assert_eq!(resource.queued_notifications(), 3);
+    assert_eq!(server_context.queued_notifications(), 3);
+    server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(resource.queued_notifications(), 2);
+    assert!(resource.is_notification_pending());
+
+    // A newer snapshot supersedes the queued notifications, the first observer is queued again.
+    assert!(resource.notify_observers_with_snapshot("2".as_bytes(), None, None));
+    assert_eq!(resource.queued_notifications(), 3);
+    let (session, handle) = &observers[0];
+    let notification = common::wait_for_response(&mut server_context, &mut context, session, handle);
+    assert_eq!(notification.data().unwrap().as_ref(), "1".as_bytes());
+    for (session, handle) in &observers {
+        let notification = common::wait_for_response(&mut server_context, &mut context, session, handle);
+        assert_eq!(notification.data().unwrap().as_ref(), "2".as_bytes());
+        assert!(notification.observe().is_some());
+    }
+    assert_eq!(server_context.queued_notifications(), 0);
+    assert!(!resource.is_notification_pending());
+
+    // Notifications generated by the GET handler are paced as well, unless the resource
+    // overrides the pacing of the context.
+    assert!(resource.notify_observers());
+    assert_eq!(resource.queued_notifications(), 3);
+    for (session, handle) in &observers {
+        let notification = common::wait_for_response(&mut server_context, &mut context, session, handle);
+        assert_eq!(notification.data().unwrap().as_ref(), "handler".as_bytes());
+    }
+    resource.set_notification_pacing(Some(NotificationPacing::Unlimited));
+    assert_eq!(resource.notification_pacing(), Some(NotificationPacing::Unlimited));
+    assert!(resource.notify_observers());
+    assert_eq!(resource.queued_notifications(), 0);
+    for (session, handle) in &observers {
+        let notification = common::wait_for_response(&mut server_context, &mut context, session, handle);
+        assert_eq!(notification.data().unwrap().as_ref(), "handler".as_bytes());
+    }
+}
@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * option_conversion_test.rs - Tests for conversions between typed and raw CoAP options.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::Duration;
+
+use libcoap_rs::message::{CoapMessageCommon, CoapOption, CoapOptionFilter, CoapOptionSet, CoapRequest, CoapResponse};
+use libcoap_rs::protocol::{
+    CoapContentFormat, CoapMessageType, CoapOptionNum, CoapOptionType, CoapRequestCode, CoapResponseCode,
+};
+
+/// Generates a set of test values for the given option type, including values that are invalid
+/// for the option type.
+fn test_values(opt_type: Option<CoapOptionType>) -> Vec<Vec<u8>> {
+    let mut values = vec![
+        vec![],
+        vec![0],
+        vec![1],
+        vec![0, 1],
+        vec![0xff, 0xff, 0xff, 0xff],
+        vec![0xff, 0xfe],
+        "test".as_bytes().to_vec(),
+    ];
+    if let Some(opt_type) = opt_type {
+        values.push(vec![b'a'; opt_type.min_len()]);
+        values.push(vec![b'a'; opt_type.max_len()]);
+        values.push(vec![b'a'; opt_type.max_len() + 1]);
+    }
+    values
+}
+
+#[test]
+pub fn raw_option_round_trip_known_options() {
+    let known_options: Vec<(CoapOptionNum, CoapOptionType)> = (0..=CoapOptionNum::MAX)
+        .filter_map(|num| CoapOptionType::try_from(num).ok().map(|opt_type| (num, opt_type)))
+        .collect();
+    assert!(!known_options.is_empty());
+    for (num, opt_type) in known_options {
+        for value in test_values(Some(opt_type)) {
+            let option = CoapOption::from_raw_parts(num, value.clone());
+            assert_eq!(option.number(), num);
+            assert_eq!(
+                option.into_raw_parts().unwrap(),
+                (num, value.clone()),
+                "round trip failed for option {:?} with value {:?}",
+                opt_type,
+                value
+            );
+        }
+    }
+}
+
+#[test]
+pub fn raw_option_round_trip_unknown_options() {
+    for num in [9, 13, 2049, 65000, CoapOptionNum::MAX] {
+        assert!(CoapOptionType::try_from(num).is_err());
+        for value in test_values(None) {
+            let option = CoapOption::from((num, value.clone()));
+            assert_eq!(option, CoapOption::Other(num, value.clone().into_boxed_slice()));
+            assert_eq!(<(CoapOptionNum, Vec<u8>)>::try_from(option).unwrap(), (num, value));
+        }
+    }
+}
+
+#[test]
+pub fn raw_option_conversion_prefers_typed_variants() {
+    assert_eq!(
+        CoapOption::from_raw_parts(CoapOptionType::UriPath as CoapOptionNum, "test".as_bytes().to_vec()),
+        CoapOption::UriPath("test".to_string())
+    );
+    assert_eq!(
+        CoapOption::from_raw_parts(CoapOptionType::UriPort as CoapOptionNum, vec![0x16, 0x33]),
+        CoapOption::UriPort(5683)
+    );
+    // Non-minimal integer encodings can't be represented by the typed variant without losing
+    // information.
+    assert_eq!(
+        CoapOption::from_raw_parts(CoapOptionType::UriPort as CoapOptionNum, vec![0x00, 0x01]),
+        CoapOption::Other(CoapOptionType::UriPort as CoapOptionNum, Box::new([0x00, 0x01]))
+    );
+}
+
+#[test]
+pub fn option_set_mixed_representations() {
+    let uri_path = CoapOptionType::UriPath as CoapOptionNum;
+    let mut options = CoapOptionSet::new();
+    options.push(CoapOption::UriPath("a".to_string()));
+    options.push_raw(uri_path, "b".as_bytes().to_vec());
+    options.push_raw(65000, vec![1, 2, 3]);
+
+    assert_eq!(options.len(), 3);
+    assert_eq!(
+        options.get_all(uri_path).collect::<Vec<_>>(),
+        vec![
+            &CoapOption::UriPath("a".to_string()),
+            &CoapOption::UriPath("b".to_string())
+        ]
+    );
+    assert_eq!(
+        options.clone().into_raw().unwrap(),
+        vec![
+            (uri_path, "a".as_bytes().to_vec()),
+            (uri_path, "b".as_bytes().to_vec()),
+            (65000, vec![1, 2, 3])
+        ]
+    );
+    let restored: CoapOptionSet = options.clone().into_raw().unwrap().into_iter().collect();
+    assert_eq!(restored, options);
+
+    options.remove_all(uri_path);
+    assert_eq!(options.len(), 1);
+}
+
+#[test]
+pub fn message_raw_option_interoperability() {
+    let mut request = CoapRequest::new(CoapMessageType::Con, CoapRequestCode::Get, "/test".parse().unwrap()).unwrap();
+    request.add_option(CoapOption::Other(65000, Box::new([1])));
+    request.add_raw_option(65001, vec![2]);
+    request.add_raw_option(CoapOptionType::Echo as CoapOptionNum, vec![3, 4]);
+
+    assert_eq!(
+        request.raw_options().unwrap(),
+        vec![
+            (65000, vec![1]),
+            (65001, vec![2]),
+            (CoapOptionType::Echo as CoapOptionNum, vec![3, 4])
+        ]
+    );
+    assert!(request.options_iter().any(|v| *v == CoapOption::Echo(Box::new([3, 4]))));
+}
+
+#[test]
+pub fn option_filter_proxy_safe() {
+    let filter = CoapOptionFilter::proxy_safe();
+    // Options that must not be forwarded as-is (RFC 7252, Section 5.7.2, RFC 7959 and RFC 8768).
+    for option in [
+        CoapOptionType::UriHost,
+        CoapOptionType::UriPort,
+        CoapOptionType::ProxyUri,
+        CoapOptionType::ProxyScheme,
+        CoapOptionType::HopLimit,
+        CoapOptionType::Block1,
+        CoapOptionType::Block2,
+        CoapOptionType::QBlock1,
+        CoapOptionType::QBlock2,
+        CoapOptionType::Size1,
+        CoapOptionType::Size2,
+    ] {
+        assert!(
+            !filter.allows(option as CoapOptionNum),
+            "{:?} must not be forwarded",
+            option
+        );
+    }
+    for option in [
+        CoapOptionType::IfMatch,
+        CoapOptionType::IfNoneMatch,
+        CoapOptionType::ETag,
+        CoapOptionType::Observe,
+        CoapOptionType::UriPath,
+        CoapOptionType::UriQuery,
+        CoapOptionType::LocationPath,
+        CoapOptionType::LocationQuery,
+        CoapOptionType::ContentFormat,
+        CoapOptionType::Accept,
+        CoapOptionType::MaxAge,
+    ] {
+        assert!(filter.allows(option as CoapOptionNum), "{:?} must be forwarded", option);
+    }
+    assert!(filter.allows(65000));
+
+    let filter = CoapOptionFilter::allow([CoapOptionType::ETag as CoapOptionNum, 65000, 65001]).deny([65001]);
+    assert!(filter.allows(CoapOptionType::ETag as CoapOptionNum));
+    assert!(filter.allows(65000));
+    assert!(!filter.allows(65001));
+    assert!(!filter.allows(CoapOptionType::UriPath as CoapOptionNum));
+    assert!(CoapOptionFilter::all().allows(CoapOptionType::UriHost as CoapOptionNum));
+}
+
+#[test]
+pub fn message_copy_options_from() {
+    let mut incoming = CoapOptionSet::new();
+    incoming.push(CoapOption::ETag(Box::new([1])));
+    incoming.push(CoapOption::UriHost("example.com".to_string()));
+    incoming.push(CoapOption::ETag(Box::new([2])));
+    incoming.push(CoapOption::Block2(0x16));
+    incoming.push_raw(65000, vec![2]);
+    incoming.push_raw(65000, vec![1]);
+    incoming.push(CoapOption::HopLimit(16));
+
+    let mut response = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content).unwrap();
+    response.copy_options_from(&incoming, &CoapOptionFilter::proxy_safe());
+    assert_eq!(
+        response.options_iter().cloned().collect::<Vec<_>>(),
+        vec![
+            CoapOption::ETag(Box::new([1])),
+            CoapOption::ETag(Box::new([2])),
+            CoapOption::Other(65000, Box::new([2])),
+            CoapOption::Other(65000, Box::new([1])),
+        ]
+    );
+
+    response.clear_options();
+    response.copy_options_from(&incoming, &CoapOptionFilter::allow([65000]));
+    assert_eq!(
+        response.raw_options().unwrap(),
+        vec![(65000, vec![2]), (65000, vec![1])]
+    );
+}
+
+#[test]
+pub fn response_max_age_encoding() {
+    let max_age_option = |max_age: Duration| {
+        let response = CoapResponse::content(CoapContentFormat::TextPlain, "test").with_max_age(max_age);
+        let message = response.into_message();
+        let max_age_num = CoapOptionType::MaxAge as CoapOptionNum;
+        message
+            .raw_options()
+            .unwrap()
+            .into_iter()
+            .find(|(num, _)| *num == max_age_num)
+            .map(|(_, value)| value)
+    };
+    // A Max-Age of zero is encoded as an empty option value, values use the shortest encoding.
+    assert_eq!(max_age_option(Duration::ZERO), Some(vec![]));
+    assert_eq!(max_age_option(Duration::from_millis(1500)), Some(vec![1]));
+    assert_eq!(max_age_option(Duration::from_secs(300)), Some(vec![0x01, 0x2c]));
+    // Durations exceeding the maximum option value are capped.
+    assert_eq!(max_age_option(Duration::from_secs(u64::MAX)), Some(vec![0xff; 4]));
+
+    let response = CoapResponse::content(CoapContentFormat::TextPlain, "test");
+    assert_eq!(response.freshness_lifetime(), Duration::from_secs(60));
+    let response = response.with_max_age(Duration::from_secs(u64::from(u32::MAX) + 1));
+    assert_eq!(response.max_age(), Some(u32::MAX));
+    assert_eq!(response.freshness_lifetime(), Duration::from_secs(u32::MAX.into()));
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * oscore_test.rs - Tests for OSCORE configurations.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(feature = "oscore")]
+
+use libcoap_rs::{
+    error::OscoreConfError,
+    oscore::{OscoreConf, OscoreGroupConfBuilder, OscoreSignatureAlgorithm},
+    CoapContext,
+};
+
+const CLIENT_CONF: &str = "master_secret,hex,\"0102030405060708090a0b0c0d0e0f10\"\n\
+                           sender_id,ascii,\"client\"\n\
+                           recipient_id,ascii,\"server\"\n";
+
+#[test]
+pub fn oscore_conf_parsing() {
+    // Ensures that libcoap is initialized before configurations are parsed.
+    let _context = CoapContext::new().unwrap();
+
+    OscoreConf::new(CLIENT_CONF, 0).unwrap();
+    OscoreConf::from_config_bytes(
+        format!("# Provisioned configuration\n\n{CLIENT_CONF}").as_bytes(),
+        42,
+        Some(Box::new(|_seq_num| true)),
+    )
+    .unwrap();
+
+    assert_eq!(
+        OscoreConf::new("master_secret,hex,\"not hex\"\n", 0).unwrap_err(),
+        OscoreConfError::InvalidValue(1, String::from("master_secret"))
+    );
+    assert_eq!(
+        OscoreConf::new("# comment\nsender_id\n", 0).unwrap_err(),
+        OscoreConfError::MalformedLine(2)
+    );
+    assert_eq!(
+        OscoreConf::new("sender_id,base64,\"Y2xpZW50\"\n", 0).unwrap_err(),
+        OscoreConfError::UnknownType(1, String::from("sender_id"))
+    );
+    // Syntactically valid, but the master secret is missing.
+    assert_eq!(
+        OscoreConf::new("sender_id,ascii,\"client\"\n", 0).unwrap_err(),
+        OscoreConfError::Invalid
+    );
+}
+
+#[test]
+pub fn oscore_group_conf() {
+    let _context = CoapContext::new().unwrap();
+    let builder = || OscoreGroupConfBuilder::new(CLIENT_CONF, [0xfe, 0xed], OscoreSignatureAlgorithm::EdDsa, [1u8; 32]);
+
+    assert_eq!(
+        OscoreGroupConfBuilder::new(CLIENT_CONF, [], OscoreSignatureAlgorithm::EdDsa, [1u8; 32])
+            .build()
+            .unwrap_err(),
+        OscoreConfError::InvalidGroupParameter("group_id")
+    );
+    assert_eq!(
+        OscoreGroupConfBuilder::new(CLIENT_CONF, [0xfe], OscoreSignatureAlgorithm::Es384, [1u8; 32])
+            .build()
+            .unwrap_err(),
+        OscoreConfError::InvalidGroupParameter("sender_private_key")
+    );
+    assert_eq!(
+        builder().peer_public_key([0x52], [2u8; 33]).build().unwrap_err(),
+        OscoreConfError::InvalidGroupParameter("peer_public_key")
+    );
+    assert_eq!(
+        builder()
+            .peer_public_key([0x52], [2u8; 32])
+            .peer_public_key([0x52], [3u8; 32])
+            .build()
+            .unwrap_err(),
+        OscoreConfError::InvalidGroupParameter("recipient_id")
+    );
+
+    // Without group mode support, no (pairwise) configuration is created instead.
+    assert!(!OscoreConf::group_mode_supported());
+    let result = builder()
+        .peer_public_key([0x52], [2u8; 32])
+        .sequence_numbers(7, Some(Box::new(|_seq_num| true)))
+        .build();
+    assert_eq!(result.unwrap_err(), OscoreConfError::FeatureUnavailable("group OSCORE"));
+}
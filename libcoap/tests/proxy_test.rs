@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * proxy_test.rs - Tests for reverse proxy resources.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::{ContextConfigurationError, OptionValueError, RequestBuildError},
+    message::{CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapOptionType, CoapRequestCode, CoapResponseCode, DEFAULT_HOP_LIMIT},
+    proxy::CoapReverseProxyResource,
+    session::{CoapClientSession, CoapSessionCommon},
+    types::CoapUri,
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn reverse_proxy() {
+    let upstream_address = common::get_unused_server_addr();
+    let proxy_address = common::get_unused_server_addr();
+    let unreachable_address = common::get_unused_server_addr();
+
+    let mut upstream_context = CoapContext::new().unwrap();
+    upstream_context.add_endpoint_udp(upstream_address).unwrap();
+    let temp_resource = CoapResource::new("api/temp", (), false);
+    temp_resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                let fahrenheit = req.uri_query_pairs() == [("unit".to_string(), Some("F".to_string()))];
+                rsp.set_data(Some(if fahrenheit { b"70.7" } else { b"21.5" }.as_slice()));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    upstream_context.add_resource(temp_resource);
+    let items_resource = CoapResource::new("api/items", (), false);
+    items_resource.set_method_handler(
+        CoapRequestCode::Post,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Created));
+                rsp.set_location(Some(CoapUri::try_from_str("api/items/7").unwrap()))
+                    .unwrap();
+                rsp.set_data(Some(req.payload().len().to_string().into_bytes()));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    upstream_context.add_resource(items_resource);
+
+    let mut proxy_context = CoapContext::new().unwrap();
+    proxy_context.add_endpoint_udp(proxy_address).unwrap();
+    let proxy = CoapReverseProxyResource::new("devices", move |id| match id {
+        "1" => format!("coap://{}/api", upstream_address).parse().ok(),
+        "3" => format!("coap://{}/api", unreachable_address).parse().ok(),
+        _ => None,
+    })
+    .timeout(Duration::from_millis(500));
+    proxy_context.add_reverse_proxy(proxy).unwrap();
+    assert_eq!(
+        proxy_context.add_reverse_proxy(CoapReverseProxyResource::new("other", |_| None)),
+        Err(ContextConfigurationError::ReverseProxyAlreadySet)
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, proxy_address).unwrap();
+    let mut send = |request: CoapRequest| {
+        let req_handle = session.send_request(request).unwrap();
+        common::run_until(
+            &mut [&mut upstream_context, &mut proxy_context, &mut context],
+            "response",
+            |_| session.poll_handle(&req_handle).next(),
+        )
+    };
+
+    // The prefix and device ID are replaced with the path of the upstream URI, the query is kept.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["devices", "1", "temp"])
+        .build()
+        .unwrap();
+    let response = send(request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.payload(), b"21.5");
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["devices", "1", "temp"])
+        .uri_query([("unit", "F")])
+        .build()
+        .unwrap();
+    let response = send(request);
+    assert_eq!(response.payload(), b"70.7");
+
+    // Bodies are transferred block-wise on both sides of the proxy, locations are mapped to the
+    // resource tree of the proxy.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Post)
+        .uri_path(["devices", "1", "items"])
+        .payload(vec![0x5a; 4000])
+        .build()
+        .unwrap();
+    let response = send(request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Created));
+    assert_eq!(response.payload(), b"4000");
+    let location: Vec<_> = response.location().unwrap().path_segments().collect();
+    assert_eq!(location, ["devices", "1", "items", "7"]);
+
+    // Unknown devices and paths outside of the prefix are not forwarded.
+    for path in [["devices", "2", "temp"], ["other", "1", "temp"]] {
+        let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(path)
+            .build()
+            .unwrap();
+        let response = send(request);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    }
+
+    // Unreachable upstream servers do not block the proxy. Depending on whether the ICMP error
+    // is reported by the operating system, the upstream request either fails or times out.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["devices", "3", "temp"])
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let response = send(request);
+    assert!(matches!(
+        response.response_code(),
+        Some(CoapResponseCode::GatewayTimeout | CoapResponseCode::BadGateway)
+    ));
+    assert!(!response.payload().is_empty());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+pub fn reverse_proxy_hop_limit() {
+    let first_address = common::get_unused_server_addr();
+    let second_address = common::get_unused_server_addr();
+
+    // Both proxies forward requests to each other, i.e., requests are forwarded in a loop.
+    let mut proxy_contexts = Vec::new();
+    for (address, upstream_address) in [(first_address, second_address), (second_address, first_address)] {
+        let mut proxy_context = CoapContext::new().unwrap();
+        proxy_context.add_endpoint_udp(address).unwrap();
+        let proxy = CoapReverseProxyResource::new("loop", move |id| {
+            format!("coap://{}/loop/{}", upstream_address, id).parse().ok()
+        });
+        proxy_context.add_reverse_proxy(proxy).unwrap();
+        proxy_contexts.push(proxy_context);
+    }
+    let mut second_context = proxy_contexts.pop().unwrap();
+    let mut first_context = proxy_contexts.pop().unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, first_address).unwrap();
+    let mut send = |request: CoapRequest| {
+        let req_handle = session.send_request(request).unwrap();
+        common::run_until(
+            &mut [&mut first_context, &mut second_context, &mut context],
+            "response",
+            |_| session.poll_handle(&req_handle).next(),
+        )
+    };
+
+    // The loop terminates once the default hop limit has been reached.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["loop", "1", "temp"])
+        .build()
+        .unwrap();
+    let response = send(request);
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::HopLimitReached)
+    );
+    assert_eq!(response.diagnostic(), Some("hop limit reached"));
+
+    // Requests that may not be forwarded any further are rejected right away.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["loop", "1", "temp"])
+        .hop_limit(1)
+        .build()
+        .unwrap();
+    let response = send(request);
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::HopLimitReached)
+    );
+
+    // Hop-Limit values must be within 1..=255.
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get).hop_limit(0).build(),
+        Err(RequestBuildError::IllegalOptionValue(CoapOptionType::HopLimit))
+    );
+    let mut request = common::gen_test_request();
+    assert_eq!(request.set_hop_limit(Some(256)), Err(OptionValueError::IllegalValue));
+    request.set_hop_limit(Some(DEFAULT_HOP_LIMIT)).unwrap();
+    assert_eq!(request.hop_limit(), Some(DEFAULT_HOP_LIMIT));
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * raw_interop_test.rs - Tests for mixing raw libcoap-sys calls with the safe wrapper.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use libc::{in_addr, sa_family_t, sockaddr_in, socklen_t, AF_INET};
+use libcoap_rs::{
+    error::{ContextConfigurationError, SessionCreationError},
+    message::{CoapMessageCommon, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapSessionCommon},
+    types::Ownership,
+    CoapContext, CoapRequestHandler, CoapResource,
+};
+use libcoap_sys::{
+    coap_address_t, coap_context_get_max_idle_sessions, coap_context_set_max_idle_sessions, coap_free_context,
+    coap_new_client_session, coap_new_context, coap_proto_t, coap_session_release, coap_startup,
+};
+
+mod common;
+
+/// Converts the given address into its raw representation.
+fn raw_address(addr: SocketAddrV4) -> coap_address_t {
+    // SAFETY: The address is fully initialized before it is returned, zeroed memory is valid for
+    // all fields.
+    unsafe {
+        let mut raw_addr = coap_address_t {
+            size: std::mem::size_of::<sockaddr_in>() as socklen_t,
+            addr: std::mem::zeroed(),
+        };
+        *raw_addr.addr.sin.as_mut() = sockaddr_in {
+            sin_family: AF_INET as sa_family_t,
+            sin_port: addr.port().to_be(),
+            sin_addr: in_addr {
+                s_addr: u32::from_ne_bytes(addr.ip().octets()),
+            },
+            ..std::mem::zeroed()
+        };
+        raw_addr
+    }
+}
+
+#[test]
+pub fn mixed_raw_and_wrapper_calls() {
+    let server_address = match common::get_unused_server_addr() {
+        std::net::SocketAddr::V4(addr) => addr,
+        std::net::SocketAddr::V6(_) => panic!("expected IPv4 test server address"),
+    };
+
+    // SAFETY: Raw contexts are checked for null and only freed by their owner.
+    let (raw_server_context, raw_client_context) = unsafe {
+        coap_startup();
+        let server = coap_new_context(std::ptr::null());
+        let client = coap_new_context(std::ptr::null());
+        assert!(!server.is_null() && !client.is_null());
+        (server, client)
+    };
+
+    // The server context stays owned by the raw code, the client context is handed over.
+    let mut server_context = unsafe { CoapContext::from_raw(raw_server_context, Ownership::Borrowed) }.unwrap();
+    let mut context = unsafe { CoapContext::from_raw(raw_client_context, Ownership::Owned) }.unwrap();
+    assert_eq!(
+        unsafe { CoapContext::from_raw(raw_server_context, Ownership::Borrowed) }.unwrap_err(),
+        ContextConfigurationError::AppDataInUse
+    );
+
+    // Settings applied using raw calls are visible through the wrapper and vice versa.
+    unsafe { coap_context_set_max_idle_sessions(raw_server_context, 5) };
+    assert_eq!(server_context.max_idle_sessions(), 5);
+    server_context.set_max_idle_sessions(7);
+    assert_eq!(unsafe { coap_context_get_max_idle_sessions(raw_server_context) }, 7);
+
+    server_context.add_endpoint_udp(server_address.into()).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    // Create the client session using raw calls and wrap it afterwards.
+    let raw_remote = raw_address(server_address);
+    let raw_session = unsafe {
+        coap_new_client_session(
+            raw_client_context,
+            std::ptr::null(),
+            &raw_remote,
+            coap_proto_t::COAP_PROTO_UDP,
+        )
+    };
+    assert!(!raw_session.is_null());
+    assert_eq!(
+        unsafe { CoapClientSession::from_raw_external(&server_context, raw_session, Ownership::Borrowed) }.unwrap_err(),
+        SessionCreationError::InvalidRawSession
+    );
+    let session = unsafe { CoapClientSession::from_raw_external(&context, raw_session, Ownership::Borrowed) }.unwrap();
+    assert_eq!(
+        unsafe { CoapClientSession::from_raw_external(&context, raw_session, Ownership::Borrowed) }.unwrap_err(),
+        SessionCreationError::AppDataInUse
+    );
+
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    // Hand the session back to the raw code, which releases the reference it held all along.
+    session.remove_handle(req_handle);
+    let returned_session = session.into_raw().unwrap();
+    assert_eq!(returned_session, raw_session);
+    unsafe { coap_session_release(returned_session) };
+
+    // The owned client context is freed by the wrapper, the borrowed server context is returned.
+    context.shutdown(Some(Duration::from_secs(0))).unwrap();
+    let returned_context = server_context.into_raw();
+    assert_eq!(returned_context, raw_server_context);
+    assert_eq!(unsafe { coap_context_get_max_idle_sessions(returned_context) }, 7);
+    unsafe { coap_free_context(returned_context) };
+}
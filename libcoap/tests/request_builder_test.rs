@@ -0,0 +1,476 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * request_builder_test.rs - Tests for building CoAP requests.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use libcoap_rs::error::{NotAcceptable, RequestBuildError};
+use libcoap_rs::message::{CoapMessageCommon, CoapOption, CoapRequest, CoapRequestBuilder, CoapResponse};
+use libcoap_rs::protocol::{
+    CoapContentFormat, CoapMatch, CoapMessageCode, CoapMessageType, CoapNoResponse, CoapOptionType, CoapRequestCode,
+    CoapResponseCode, MAX_EXTENDED_TOKEN_SIZE,
+};
+use libcoap_rs::session::{CoapClientSession, CoapSessionCommon};
+use libcoap_rs::types::CoapUri;
+use libcoap_rs::{CoapContext, CoapRequestHandler, CoapResource};
+
+mod common;
+
+#[test]
+pub fn content_format_conversion() {
+    assert_eq!(CoapContentFormat::from(0), CoapContentFormat::TextPlain);
+    assert_eq!(CoapContentFormat::from(60), CoapContentFormat::Cbor);
+    assert_eq!(u16::from(CoapContentFormat::LinkFormat), 40);
+    assert_eq!(u16::from(CoapContentFormat::ImagePng), 23);
+    assert_eq!(CoapContentFormat::from(65000), CoapContentFormat::Other(65000));
+    assert_eq!(u16::from(CoapContentFormat::Other(65000)), 65000);
+}
+
+#[test]
+pub fn preferred_format() {
+    let supported = [CoapContentFormat::Cbor, CoapContentFormat::Json];
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get).build().unwrap();
+    assert_eq!(request.accept_format(), None);
+    assert_eq!(request.preferred_format(&supported), Ok(CoapContentFormat::Cbor));
+    assert_eq!(request.preferred_format(&[]), Err(NotAcceptable(None)));
+
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .accept(CoapContentFormat::Json)
+        .build()
+        .unwrap();
+    assert_eq!(request.accept_format(), Some(CoapContentFormat::Json));
+    assert_eq!(request.preferred_format(&supported), Ok(CoapContentFormat::Json));
+
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .accept(CoapContentFormat::Other(65000))
+        .build()
+        .unwrap();
+    let err = request.preferred_format(&supported).unwrap_err();
+    assert_eq!(err, NotAcceptable(Some(CoapContentFormat::Other(65000))));
+    assert_eq!(CoapResponseCode::from(err), CoapResponseCode::NotAcceptable);
+}
+
+#[test]
+pub fn build_request_with_options() {
+    let request = CoapRequestBuilder::new(CoapRequestCode::Post)
+        .uri_path(["a", "b/c"])
+        .uri_query([("key", "value"), ("flag", "")])
+        .accept(CoapContentFormat::Json)
+        .content_format(CoapContentFormat::TextPlain)
+        .etag(vec![1, 2, 3])
+        .etag(vec![4])
+        .if_match(CoapMatch::Empty)
+        .observe(0)
+        .no_response(CoapNoResponse::SUPPRESS_SUCCESS)
+        .payload("test".as_bytes().to_vec())
+        .confirmable(false)
+        .build()
+        .unwrap();
+
+    assert_eq!(request.type_(), CoapMessageType::Non);
+    assert_eq!(request.code(), CoapRequestCode::Post.into());
+    assert_eq!(request.accept(), Some(50));
+    assert_eq!(request.content_format(), Some(0));
+    assert_eq!(request.etag().unwrap().len(), 2);
+    assert_eq!(request.if_match().unwrap(), &vec![CoapMatch::Empty]);
+    assert_eq!(request.observe(), Some(0));
+    assert_eq!(request.no_response(), Some(2));
+    assert_eq!(request.data().unwrap().as_ref(), "test".as_bytes());
+
+    let message = request.into_message();
+    let paths: Vec<_> = message
+        .options_iter()
+        .filter_map(|opt| match opt {
+            CoapOption::UriPath(v) => Some(v.clone()),
+            _ => None,
+        })
+        .collect();
+    // Segments are kept separate even if they contain slashes, and keep their order.
+    assert_eq!(paths, vec!["a".to_string(), "b/c".to_string()]);
+    let queries: Vec<_> = message
+        .options_iter()
+        .filter_map(|opt| match opt {
+            CoapOption::UriQuery(v) => Some(v.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(queries, vec!["key=value".to_string(), "flag".to_string()]);
+}
+
+#[test]
+pub fn query_pairs() {
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_query([("unit", "celsius"), ("flag", ""), ("expr", "a=b")])
+        .query_pair("unit", Some("kelvin"))
+        .query_pair("empty", Some(""))
+        .query_pair("a=b&c", Some("d=e%f"))
+        .query_pair("percent%", None)
+        .build()
+        .unwrap();
+
+    let pairs: Vec<_> = request
+        .query()
+        .map(|(key, value)| (key.into_owned(), value.map(|v| v.into_owned())))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("unit".to_string(), Some("celsius".to_string())),
+            ("flag".to_string(), None),
+            ("expr".to_string(), Some("a=b".to_string())),
+            ("unit".to_string(), Some("kelvin".to_string())),
+            ("empty".to_string(), Some("".to_string())),
+            ("a=b&c".to_string(), Some("d=e%f".to_string())),
+            ("percent%".to_string(), None),
+        ]
+    );
+    // The first component with a matching key is used.
+    assert_eq!(request.query_value("unit").as_deref(), Some("celsius"));
+    assert_eq!(request.query_value("flag").as_deref(), Some(""));
+    assert_eq!(request.query_value("a=b&c").as_deref(), Some("d=e%f"));
+    assert_eq!(request.query_value("missing"), None);
+
+    // Invalid UTF-8 after decoding is only available using query_raw().
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_query([("bin", "%FF")])
+        .build()
+        .unwrap();
+    let (key, value) = request.query_raw().next().unwrap();
+    assert_eq!(key.as_ref(), b"bin");
+    assert_eq!(value.as_deref(), Some([0xFFu8].as_slice()));
+    assert_eq!(request.query_value("bin").as_deref(), Some("\u{FFFD}"));
+
+    // The length limit applies to the encoded pair.
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .query_pair("k", Some(&"&".repeat(100)))
+            .build()
+            .unwrap_err(),
+        RequestBuildError::OptionValueTooLong(CoapOptionType::UriQuery, 302)
+    );
+}
+
+#[test]
+pub fn build_request_from_uri() {
+    let uri: CoapUri = "coap://[::1]:5700/sensors/temp%2Fout?unit=%C2%B0C&flag"
+        .parse()
+        .unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get).uri(&uri).build().unwrap();
+    assert_eq!(request.uri().path(), Some("sensors/temp%2Fout".as_bytes()));
+    assert_eq!(request.query_value("unit").as_deref(), Some("°C"));
+    assert_eq!(request.query_value("flag").as_deref(), Some(""));
+    let paths: Vec<_> = request
+        .into_message()
+        .options_iter()
+        .filter_map(|opt| match opt {
+            CoapOption::UriPath(v) => Some(v.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(paths, vec!["sensors".to_string(), "temp/out".to_string()]);
+
+    let proxy_uri = CoapUri::try_from_str_proxy("coap://example.com/test").unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri(&proxy_uri)
+        .build()
+        .unwrap();
+    assert!(request.uri().is_proxy());
+    assert_eq!(request.uri().host(), Some("example.com".as_bytes()));
+}
+
+#[test]
+pub fn build_request_errors() {
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .etag(vec![0; 9])
+            .build()
+            .unwrap_err(),
+        RequestBuildError::OptionValueTooLong(CoapOptionType::ETag, 9)
+    );
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .etag(Vec::<u8>::new())
+            .build()
+            .unwrap_err(),
+        RequestBuildError::OptionValueTooShort(CoapOptionType::ETag, 0)
+    );
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["a".repeat(256)])
+            .build()
+            .unwrap_err(),
+        RequestBuildError::OptionValueTooLong(CoapOptionType::UriPath, 256)
+    );
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .payload(vec![1])
+            .build()
+            .unwrap_err(),
+        RequestBuildError::PayloadWithoutContentFormat(CoapRequestCode::Get)
+    );
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .proxy_uri("coap://example.com/test")
+            .uri_path(["test"])
+            .build()
+            .unwrap_err(),
+        RequestBuildError::InvalidOptionCombination(CoapOptionType::ProxyUri, CoapOptionType::UriPath)
+    );
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .token(vec![0; MAX_EXTENDED_TOKEN_SIZE + 1])
+            .build()
+            .unwrap_err(),
+        RequestBuildError::TokenTooLong(MAX_EXTENDED_TOKEN_SIZE + 1)
+    );
+    // Max-Age is only defined for responses.
+    assert_eq!(
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .max_age(60)
+            .build()
+            .unwrap_err(),
+        RequestBuildError::OptionNotAllowedInRequest(CoapOptionType::MaxAge)
+    );
+    // Payloads for GET requests are fine if they have a content format.
+    assert!(CoapRequestBuilder::new(CoapRequestCode::Get)
+        .payload(vec![1])
+        .content_format(CoapContentFormat::TextPlain)
+        .build()
+        .is_ok());
+}
+
+#[test]
+pub fn received_request_options_round_trip() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let received: Rc<RefCell<Option<CoapRequest>>> = Rc::new(RefCell::new(None));
+    let received_handler = received.clone();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Post,
+        Some(CoapRequestHandler::new(
+            move |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                *received_handler.borrow_mut() = Some(req.clone());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut request = CoapRequestBuilder::new(CoapRequestCode::Post)
+        .uri_path(["test1"])
+        .uri_query([("key", "value"), ("flag", "")])
+        .accept(CoapContentFormat::Json)
+        .content_format(CoapContentFormat::TextPlain)
+        .etag(vec![1, 2])
+        .etag(vec![3])
+        .if_match(CoapMatch::ETag(Box::new([4])))
+        .payload("test".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    request.set_size2(Some(0));
+    // Unknown elective option, which should be passed through as-is.
+    request.add_raw_option(65000, vec![5, 6, 7]);
+    let sent_options = request.options();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+
+    let received = received.borrow().clone().unwrap();
+    assert_eq!(received.accept(), Some(50));
+    assert_eq!(received.content_format(), Some(0));
+    assert_eq!(received.payload_format(), Some(CoapContentFormat::TextPlain));
+    assert_eq!(
+        received.etag(),
+        Some(&vec![Box::from([1u8, 2].as_slice()), Box::from([3u8].as_slice())])
+    );
+    assert_eq!(received.if_match(), Some(&vec![CoapMatch::ETag(Box::new([4]))]));
+    assert_eq!(received.size2(), Some(0));
+    assert_eq!(
+        received.uri_query_pairs(),
+        vec![
+            ("key".to_string(), Some("value".to_string())),
+            ("flag".to_string(), None)
+        ]
+    );
+
+    assert_eq!(received.query_value("key").as_deref(), Some("value"));
+    assert_eq!(received.query_value("flag").as_deref(), Some(""));
+
+    let received_options = received.options();
+    assert_eq!(received_options, sent_options);
+    let unknown: Vec<_> = received_options.get_all(65000).collect();
+    assert_eq!(unknown, vec![&CoapOption::Other(65000, Box::new([5, 6, 7]))]);
+    assert!(!unknown[0].is_critical());
+    // Options are ordered by their option number.
+    let numbers: Vec<_> = received_options.iter().map(CoapOption::number).collect();
+    let mut sorted_numbers = numbers.clone();
+    sorted_numbers.sort();
+    assert_eq!(numbers, sorted_numbers);
+    assert_eq!(
+        received_options.into_raw().unwrap(),
+        sent_options
+            .iter()
+            .map(|v| v.clone().into_raw_parts().unwrap())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn response_code_classification() {
+    assert!(CoapResponseCode::Content.is_success());
+    assert!(CoapResponseCode::NotFound.is_client_error());
+    assert!(CoapResponseCode::NotFound.is_error());
+    assert!(CoapResponseCode::InternalError.is_server_error());
+    assert!(!CoapResponseCode::Content.is_error());
+    assert_eq!(CoapResponseCode::NotFound.class(), 4);
+    assert_eq!(CoapResponseCode::NotFound.detail(), 4);
+
+    // Unknown response codes are preserved, while non-response codes are rejected.
+    let unknown = CoapResponseCode::from_raw_code(0x9F).unwrap();
+    assert_eq!(unknown, CoapResponseCode::Other(0x9F));
+    assert!(unknown.is_client_error());
+    assert_eq!(unknown.to_raw_code(), 0x9F);
+    assert_eq!(CoapResponseCode::from_raw_code(0x45), Some(CoapResponseCode::Content));
+    assert_eq!(CoapResponseCode::from_raw_code(0x20), None);
+    assert_eq!(CoapResponseCode::from_raw_code(0x00), None);
+    assert_eq!(
+        CoapMessageCode::from_raw_code(0x01).unwrap(),
+        CoapMessageCode::Request(CoapRequestCode::Get)
+    );
+    assert_eq!(CoapMessageCode::from_raw_code(0x00).unwrap(), CoapMessageCode::Empty);
+    assert!(CoapMessageCode::from_raw_code(0x20).is_err());
+}
+
+#[test]
+fn response_constructors() {
+    let content = CoapResponse::content(CoapContentFormat::TextPlain, "hello");
+    assert_eq!(content.type_(), CoapMessageType::Ack);
+    assert_eq!(content.response_code(), Some(CoapResponseCode::Content));
+    assert!(content.is_success());
+    assert_eq!(content.data(), Some("hello".as_bytes()));
+    assert_eq!(content.content_format(), Some(CoapContentFormat::TextPlain.into()));
+    assert_eq!(content.diagnostic(), None);
+
+    let bad_request = CoapResponse::bad_request("missing parameter");
+    assert!(bad_request.is_client_error());
+    assert_eq!(bad_request.content_format(), None);
+    assert_eq!(bad_request.diagnostic(), Some("missing parameter"));
+
+    let internal_error = CoapResponse::internal_error("");
+    assert!(internal_error.is_server_error());
+    assert_eq!(internal_error.data(), None);
+    assert_eq!(internal_error.diagnostic(), None);
+
+    let not_found = CoapResponse::not_found();
+    assert_eq!(not_found.response_code(), Some(CoapResponseCode::NotFound));
+    assert_eq!(CoapResponse::changed().response_code(), Some(CoapResponseCode::Changed));
+
+    let mut prepared = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content).unwrap();
+    prepared.set_mid(Some(1234));
+    prepared.set_token(Some(vec![1, 2, 3]));
+    let reply = not_found.in_reply_to(&prepared);
+    assert_eq!(reply.type_(), CoapMessageType::Con);
+    assert_eq!(reply.mid(), Some(1234));
+    assert_eq!(reply.token(), Some([1, 2, 3].as_slice()));
+    assert_eq!(reply.response_code(), Some(CoapResponseCode::NotFound));
+}
+
+#[cfg(feature = "serde-json")]
+#[test]
+pub fn json_payloads() {
+    use libcoap_rs::error::{PayloadDecodeError, PayloadEncodeError};
+    use std::collections::BTreeMap;
+
+    let value = BTreeMap::from([("temperature".to_string(), 21), ("humidity".to_string(), 40)]);
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .uri_path(["sensor"])
+        .json_payload(&value)
+        .build()
+        .unwrap();
+    assert_eq!(request.payload_format(), Some(CoapContentFormat::Json));
+    assert_eq!(request.payload(), br#"{"humidity":40,"temperature":21}"#);
+    assert_eq!(request.parse_payload::<BTreeMap<String, i32>>(), Ok(value.clone()));
+
+    // Payloads that do not match the target type result in 4.00 (Bad Request).
+    let error = request.parse_payload::<Vec<u32>>().unwrap_err();
+    assert!(matches!(
+        error,
+        PayloadDecodeError::InvalidPayload(CoapContentFormat::Json, _)
+    ));
+    assert_eq!(CoapResponseCode::from(error), CoapResponseCode::BadRequest);
+    // Other content formats are not decoded, resulting in 4.15 (Unsupported Content-Format).
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .content_format(CoapContentFormat::TextPlain)
+        .payload(b"[1, 2]".to_vec())
+        .build()
+        .unwrap();
+    let error = request.parse_payload::<Vec<u32>>().unwrap_err();
+    assert_eq!(
+        error,
+        PayloadDecodeError::UnsupportedContentFormat(Some(CoapContentFormat::TextPlain))
+    );
+    assert_eq!(
+        CoapMessageCode::from(error),
+        CoapMessageCode::Response(CoapResponseCode::UnsupportedContentFormat)
+    );
+
+    let mut response = CoapResponse::new(CoapMessageType::Ack, CoapResponseCode::Content).unwrap();
+    response.set_typed_payload(&[1, 2, 3], CoapContentFormat::Json).unwrap();
+    assert_eq!(response.payload_format(), Some(CoapContentFormat::Json));
+    assert_eq!(response.payload(), b"[1,2,3]");
+    assert_eq!(response.parse_payload::<Vec<u32>>(), Ok(vec![1, 2, 3]));
+    // Unsupported formats and serialization failures leave the response unchanged.
+    assert_eq!(
+        response.set_typed_payload(&[4], CoapContentFormat::LinkFormat),
+        Err(PayloadEncodeError::UnsupportedContentFormat(
+            CoapContentFormat::LinkFormat
+        ))
+    );
+    let unserializable = BTreeMap::from([((1, 2), 3)]);
+    assert!(matches!(
+        response.set_typed_payload(&unserializable, CoapContentFormat::Json),
+        Err(PayloadEncodeError::Serialization(CoapContentFormat::Json, _))
+    ));
+    assert_eq!(response.payload(), b"[1,2,3]");
+    assert_eq!(response.payload_format(), Some(CoapContentFormat::Json));
+
+    assert!(matches!(
+        CoapRequestBuilder::new(CoapRequestCode::Post)
+            .json_payload(&unserializable)
+            .build(),
+        Err(RequestBuildError::PayloadEncoding(PayloadEncodeError::Serialization(
+            CoapContentFormat::Json,
+            _
+        )))
+    ));
+}
+
+#[cfg(feature = "serde-cbor")]
+#[test]
+pub fn cbor_payloads() {
+    let request = CoapRequestBuilder::new(CoapRequestCode::Post)
+        .cbor_payload(&(1u8, "a"))
+        .build()
+        .unwrap();
+    assert_eq!(request.payload_format(), Some(CoapContentFormat::Cbor));
+    // A CBOR array of length 2 containing the unsigned integer 1 and the text string "a".
+    assert_eq!(request.payload(), [0x82, 0x01, 0x61, b'a']);
+    assert_eq!(request.parse_payload::<(u8, String)>(), Ok((1, "a".to_string())));
+
+    let mut response = CoapResponse::new(CoapMessageType::Ack, CoapResponseCode::Content).unwrap();
+    response.set_typed_payload("hello", CoapContentFormat::Cbor).unwrap();
+    assert_eq!(response.payload_format(), Some(CoapContentFormat::Cbor));
+    assert_eq!(response.parse_payload::<String>(), Ok("hello".to_string()));
+}
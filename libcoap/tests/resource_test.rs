@@ -0,0 +1,941 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * resource_test.rs - Tests for server-side resources and request handling.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use libcoap_rs::{
+    error::{ResourceCreationError, ResourceRemoved, ResourceTreeError},
+    message::{
+        CoapBlock1Chunk, CoapMessageCommon, CoapOption, CoapPagedResponder, CoapRepresentations, CoapRequest,
+        CoapRequestBuilder, CoapResponse,
+    },
+    protocol::{CoapContentFormat, CoapMatch, CoapMessageCode, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    CoapContext, CoapRequestHandler, CoapResource, CoapResourcePath, CoapResourceTree, CoapWellKnownCoreMode,
+    ResourceFlags,
+};
+
+mod common;
+
+#[test]
+pub fn resource_etag_validation() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", String::from("first"), false);
+    resource.set_etag_from_content("first".as_bytes());
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |value: &mut String, sess, _req, mut rsp: CoapResponse| {
+                rsp.set_data(Some(value.clone().into_bytes()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new_resource_ref(
+            |resource: &CoapResource<String>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                let data = req.data().unwrap().to_vec();
+                resource.set_etag_from_content(&data);
+                *resource.user_data_mut() = String::from_utf8(data).unwrap();
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    let initial_etag = resource.etag().unwrap();
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    // Responses to GET requests contain the current ETag.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.etag(), Some(&initial_etag));
+    assert_eq!(response.data().unwrap().as_ref(), "first".as_bytes());
+
+    // Requests with the current ETag are answered with 2.03 Valid and no payload.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .etag(vec![1, 2, 3])
+        .etag(initial_etag.clone())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Valid));
+    assert_eq!(response.etag(), Some(&initial_etag));
+    assert!(response.data().is_none());
+
+    // PUT requests with a non-matching If-Match option fail.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .uri_path(["test1"])
+        .if_match(CoapMatch::ETag(Box::from([1u8, 2, 3])))
+        .content_format(CoapContentFormat::TextPlain)
+        .payload("second".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::PreconditionFailed)
+    );
+
+    // The resource exists, so If-None-Match fails as well.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .uri_path(["test1"])
+        .if_none_match()
+        .content_format(CoapContentFormat::TextPlain)
+        .payload("second".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::PreconditionFailed)
+    );
+
+    // PUT requests with a matching If-Match option are passed to the handler, which updates the
+    // ETag.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .uri_path(["test1"])
+        .if_match(CoapMatch::ETag(initial_etag.clone()))
+        .content_format(CoapContentFormat::TextPlain)
+        .payload("second".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+
+    // The old ETag is no longer valid.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .etag(initial_etag.clone())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_ne!(response.etag(), Some(&initial_etag));
+    assert_eq!(response.data().unwrap().as_ref(), "second".as_bytes());
+}
+
+#[test]
+pub fn paged_fetch_response() {
+    let server_address = common::get_unused_server_addr();
+    let representation: Vec<u8> = (0..100u8).collect();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let mut responder = CoapPagedResponder::new();
+    responder.set_max_block_size(16);
+    responder.set_strict(true);
+    let requested_offsets: Rc<RefCell<Vec<usize>>> = Rc::new(RefCell::new(Vec::new()));
+    let requested_offsets_handler = requested_offsets.clone();
+    let server_representation = representation.clone();
+    let resource = CoapResource::new("test1", responder, false);
+    resource.set_method_handler(
+        CoapRequestCode::Fetch,
+        Some(CoapRequestHandler::new(
+            move |responder: &mut CoapPagedResponder, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                assert_eq!(req.data().unwrap().as_ref(), "filter".as_bytes());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                responder
+                    .respond(sess, req, &mut rsp, Box::from([1u8, 2, 3]), |offset, max_len| {
+                        requested_offsets_handler.borrow_mut().push(offset);
+                        let page = server_representation.get(offset..)?;
+                        Some(page[..page.len().min(max_len)].to_vec())
+                    })
+                    .unwrap();
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Fetch)
+        .uri_path(["test1"])
+        .content_format(CoapContentFormat::TextPlain)
+        .payload("filter".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    // libcoap reassembles the blocks on the client side.
+    assert_eq!(response.data().unwrap().as_ref(), representation.as_slice());
+    // The page provider is only called for the requested blocks.
+    assert_eq!(*requested_offsets.borrow(), (0..100).step_by(16).collect::<Vec<_>>());
+}
+
+#[test]
+pub fn resource_builder() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("builder", Vec::<CoapRequestCode>::new())
+        .get(|resource, sess, _req, mut rsp| {
+            resource.user_data_mut().push(CoapRequestCode::Get);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .put(|resource, sess, _req, mut rsp| {
+            resource.user_data_mut().push(CoapRequestCode::Put);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+            sess.send(rsp).unwrap();
+        })
+        .fallback(|resource, sess, req, mut rsp| {
+            let CoapMessageCode::Request(code) = req.code() else {
+                unreachable!()
+            };
+            resource.user_data_mut().push(code);
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::NotImplemented));
+            sess.send(rsp).unwrap();
+        })
+        .observable(true)
+        .attribute("rt", Some("\"test-builder\""))
+        .attribute("ct", Some("0"))
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let no_fallback = CoapResource::builder("no_fallback", ())
+        .post(|_resource, sess, _req, mut rsp| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Created));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(no_fallback);
+    assert_eq!(
+        CoapResource::builder("invalid", ())
+            .flags(ResourceFlags::NOTIFY_CON | ResourceFlags::NOTIFY_NON)
+            .build()
+            .unwrap_err(),
+        ResourceCreationError::ContradictoryFlags(ResourceFlags::NOTIFY_CON, ResourceFlags::NOTIFY_NON)
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut request = |code: CoapRequestCode, path: &[&str]| {
+        let request = CoapRequestBuilder::new(code)
+            .uri_path(path.iter().copied())
+            .build()
+            .unwrap();
+        common::exchange_request(&mut server_context, &mut context, &session, request).code()
+    };
+    assert_eq!(
+        request(CoapRequestCode::Get, &["builder"]),
+        CoapMessageCode::Response(CoapResponseCode::Content)
+    );
+    assert_eq!(
+        request(CoapRequestCode::Put, &["builder"]),
+        CoapMessageCode::Response(CoapResponseCode::Changed)
+    );
+    // Methods without their own handler use the shared fallback handler.
+    assert_eq!(
+        request(CoapRequestCode::Post, &["builder"]),
+        CoapMessageCode::Response(CoapResponseCode::NotImplemented)
+    );
+    assert_eq!(
+        request(CoapRequestCode::Delete, &["builder"]),
+        CoapMessageCode::Response(CoapResponseCode::NotImplemented)
+    );
+    // Without a fallback handler, other methods are rejected.
+    assert_eq!(
+        request(CoapRequestCode::Post, &["no_fallback"]),
+        CoapMessageCode::Response(CoapResponseCode::Created)
+    );
+    assert_eq!(
+        request(CoapRequestCode::Get, &["no_fallback"]),
+        CoapMessageCode::Response(CoapResponseCode::NotAllowed)
+    );
+
+    let resource = server_context
+        .typed_resource_by_uri_path::<Vec<CoapRequestCode>>("builder")
+        .unwrap();
+    assert_eq!(
+        *resource.user_data(),
+        vec![
+            CoapRequestCode::Get,
+            CoapRequestCode::Put,
+            CoapRequestCode::Post,
+            CoapRequestCode::Delete
+        ]
+    );
+
+    // The link attributes and the observable flag are part of the resource description.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path([".well-known", "core"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    let link_format = String::from_utf8(response.payload().to_vec()).unwrap();
+    let description = link_format
+        .split(',')
+        .find(|link| link.starts_with("</builder>"))
+        .unwrap();
+    assert!(description.contains(";rt=\"test-builder\""));
+    assert!(description.contains(";ct=0"));
+    assert!(description.contains(";obs"));
+}
+
+/// Block of a streamed upload as seen by the request handler: (offset, more, total size, data).
+type ReceivedChunk = (usize, bool, Option<usize>, Vec<u8>);
+
+#[test]
+pub fn streamed_block1_upload() {
+    const MAX_UPLOAD_SIZE: usize = 4000;
+    let server_address = common::get_unused_server_addr();
+    let upload: Vec<u8> = (0..3000u32).map(|v| (v % 251) as u8).collect();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let chunks: Rc<RefCell<Vec<ReceivedChunk>>> = Rc::default();
+    let chunks_handler = chunks.clone();
+    let streaming =
+        CoapResource::new_with_flags("stream", (), ResourceFlags::NOTIFY_NON | ResourceFlags::STREAM_BLOCK1).unwrap();
+    assert!(streaming.streams_block1());
+    streaming.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new(
+            move |_data: &mut (), sess: &mut CoapServerSession, req: &CoapRequest, mut rsp: CoapResponse| {
+                let chunk = CoapBlock1Chunk::from_request(req);
+                if chunk.total_size().unwrap_or(0) > MAX_UPLOAD_SIZE {
+                    chunk.abort_too_large(&mut rsp, MAX_UPLOAD_SIZE);
+                } else {
+                    chunks_handler.borrow_mut().push((
+                        chunk.offset(),
+                        chunk.more(),
+                        chunk.total_size(),
+                        chunk.data().to_vec(),
+                    ));
+                    rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                    chunk.acknowledge(&mut rsp);
+                }
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(streaming);
+    let reassembled: Rc<RefCell<Vec<Vec<u8>>>> = Rc::default();
+    let reassembled_handler = reassembled.clone();
+    let single_body = CoapResource::new("single", upload.clone(), false);
+    assert!(!single_body.streams_block1());
+    single_body.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new(
+            move |_data: &mut Vec<u8>, sess: &mut CoapServerSession, req: &CoapRequest, mut rsp: CoapResponse| {
+                reassembled_handler.borrow_mut().push(req.data().unwrap().to_vec());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    single_body.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |data: &mut Vec<u8>, sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(data.clone()));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(single_body);
+    assert_ne!(
+        server_context.config().block_mode,
+        CoapContext::new().unwrap().config().block_mode
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    // Client sessions of contexts with streaming resources still receive reassembled responses.
+    context.add_resource(CoapResource::new_with_flags("stream", (), ResourceFlags::STREAM_BLOCK1).unwrap());
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let put_request = |path: &str, payload: &[u8]| {
+        CoapRequestBuilder::new(CoapRequestCode::Put)
+            .uri_path([path])
+            .payload(payload.to_vec())
+            .build()
+            .unwrap()
+    };
+
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        put_request("stream", &upload),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+    {
+        let chunks = chunks.borrow();
+        assert!(chunks.len() > 1, "upload was not transferred block-wise");
+        assert_eq!(chunks[0].2, Some(upload.len()));
+        let mut next_offset = 0;
+        for (idx, (offset, more, _total_size, data)) in chunks.iter().enumerate() {
+            assert_eq!(*offset, next_offset);
+            assert_eq!(*more, idx + 1 < chunks.len());
+            next_offset += data.len();
+        }
+        let received: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.3.clone()).collect();
+        assert_eq!(received, upload);
+    }
+
+    // Other resources of the same context still receive the reassembled body.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        put_request("single", &upload),
+    );
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+    assert_eq!(*reassembled.borrow(), vec![upload.clone()]);
+    let get_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["single"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get_request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), upload.as_slice());
+
+    // Uploads exceeding the limit are aborted after the first block.
+    chunks.borrow_mut().clear();
+    let too_large: Vec<u8> = vec![0xAA; MAX_UPLOAD_SIZE + 1];
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        put_request("stream", &too_large),
+    );
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::RequestTooLarge)
+    );
+    assert_eq!(response.size1(), Some(MAX_UPLOAD_SIZE as u32));
+    assert!(chunks.borrow().is_empty());
+}
+
+#[test]
+pub fn virtual_host_routing() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let respond_with_user_data =
+        |resource: &CoapResource<String>, sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+            rsp.set_data(Some(resource.user_data().clone().into_bytes()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        };
+    for (path, host, value) in [
+        ("info", None, "generic"),
+        ("info", Some("Sensors.example"), "sensors"),
+        ("info", Some("actuators.example"), "actuators"),
+        ("sensors_only", Some("sensors.example"), "sensors_only"),
+    ] {
+        let mut builder = CoapResource::builder(path, String::from(value)).get(respond_with_user_data);
+        if let Some(host) = host {
+            builder = builder.host(host);
+        }
+        server_context.add_resource(builder.build().unwrap());
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut request = |server_context: &mut CoapContext, path: &[&str], host: Option<&str>| {
+        let mut request = CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(path.iter().copied())
+            .build()
+            .unwrap();
+        if let Some(host) = host {
+            request.add_option(CoapOption::UriHost(host.to_string()));
+        }
+        common::exchange_request(server_context, &mut context, &session, request)
+    };
+
+    // The same path yields different representations depending on the Uri-Host option, with the
+    // host-agnostic resource as fallback.
+    let response = request(&mut server_context, &["info"], Some("SENSORS.example"));
+    assert_eq!(response.data().unwrap().as_ref(), "sensors".as_bytes());
+    let response = request(&mut server_context, &["info"], Some("actuators.example"));
+    assert_eq!(response.data().unwrap().as_ref(), "actuators".as_bytes());
+    let response = request(&mut server_context, &["info"], Some("unknown.example"));
+    assert_eq!(response.data().unwrap().as_ref(), "generic".as_bytes());
+    let response = request(&mut server_context, &["info"], None);
+    assert_eq!(response.data().unwrap().as_ref(), "generic".as_bytes());
+
+    // Resources restricted to a host are not visible for other hosts.
+    let response = request(&mut server_context, &["sensors_only"], Some("sensors.example"));
+    assert_eq!(response.data().unwrap().as_ref(), "sensors_only".as_bytes());
+    let response = request(&mut server_context, &["sensors_only"], None);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+
+    // /.well-known/core only lists the resources visible for the requested host.
+    let links = |response: CoapResponse| {
+        let mut links: Vec<String> = String::from_utf8(response.payload().to_vec())
+            .unwrap()
+            .split(',')
+            .map(|link| link.split(';').next().unwrap().to_string())
+            .collect();
+        links.sort();
+        links
+    };
+    let response = request(&mut server_context, &[".well-known", "core"], Some("sensors.example"));
+    assert_eq!(links(response), ["</info>", "</sensors_only>"]);
+    let response = request(&mut server_context, &[".well-known", "core"], None);
+    assert_eq!(links(response), ["</info>"]);
+
+    // Requests without a Uri-Host option are routed using the default host of the context.
+    server_context.set_default_host(Some("sensors.example"));
+    let response = request(&mut server_context, &["info"], None);
+    assert_eq!(response.data().unwrap().as_ref(), "sensors".as_bytes());
+    let response = request(&mut server_context, &["sensors_only"], None);
+    assert_eq!(response.data().unwrap().as_ref(), "sensors_only".as_bytes());
+    server_context.set_default_host(None);
+
+    let lookup = |host: Option<&str>| {
+        server_context
+            .typed_resource_by_uri_path_and_host::<String>("info", host)
+            .map(|resource| resource.user_data().clone())
+    };
+    assert_eq!(lookup(Some("actuators.example")).as_deref(), Some("actuators"));
+    assert_eq!(lookup(Some("unknown.example")).as_deref(), Some("generic"));
+    assert_eq!(lookup(None).as_deref(), Some("generic"));
+    assert!(server_context
+        .typed_resource_by_uri_path_and_host::<String>("sensors_only", None)
+        .is_none());
+}
+
+#[test]
+pub fn resource_path_segments() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let respond_with_user_data =
+        |resource: &CoapResource<String>, sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+            rsp.set_data(Some(resource.user_data().clone().into_bytes()));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        };
+    let paths: [(CoapResourcePath, &str); 5] = [
+        (CoapResourcePath::from(&["config", "wifi/main"]), "slash"),
+        (CoapResourcePath::from(&["config", "wifi", "main"]), "segments"),
+        (CoapResourcePath::from(&["résumé", "温度"]), "utf8"),
+        (CoapResourcePath::from(&["a", "", "b"]), "empty"),
+        (CoapResourcePath::from(""), "root"),
+    ];
+    for (path, value) in paths {
+        let resource = CoapResource::builder(path, String::from(value))
+            .get(respond_with_user_data)
+            .build()
+            .unwrap();
+        server_context.add_resource(resource);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut request = |server_context: &mut CoapContext, path: &[&str]| {
+        let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(path.iter().copied())
+            .build()
+            .unwrap();
+        common::exchange_request(server_context, &mut context, &session, request)
+    };
+
+    // Segments containing a slash are told apart from multiple segments.
+    let response = request(&mut server_context, &["config", "wifi/main"]);
+    assert_eq!(response.data().unwrap().as_ref(), "slash".as_bytes());
+    let response = request(&mut server_context, &["config", "wifi", "main"]);
+    assert_eq!(response.data().unwrap().as_ref(), "segments".as_bytes());
+    let response = request(&mut server_context, &["résumé", "温度"]);
+    assert_eq!(response.data().unwrap().as_ref(), "utf8".as_bytes());
+    let response = request(&mut server_context, &["a", "", "b"]);
+    assert_eq!(response.data().unwrap().as_ref(), "empty".as_bytes());
+    let response = request(&mut server_context, &["a", "b"]);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    let response = request(&mut server_context, &[]);
+    assert_eq!(response.data().unwrap().as_ref(), "root".as_bytes());
+
+    // Paths are percent-encoded in /.well-known/core.
+    let response = request(&mut server_context, &[".well-known", "core"]);
+    let mut links: Vec<String> = String::from_utf8(response.payload().to_vec())
+        .unwrap()
+        .split(',')
+        .map(|link| link.split(';').next().unwrap().to_string())
+        .collect();
+    links.sort();
+    assert_eq!(
+        links,
+        [
+            "</>",
+            "</a//b>",
+            "</config/wifi%2Fmain>",
+            "</config/wifi/main>",
+            "</r%C3%A9sum%C3%A9/%E6%B8%A9%E5%BA%A6>",
+        ]
+    );
+
+    // Lookup by a joined string uses the percent-encoded textual representation.
+    let lookup = |path: &str| {
+        server_context
+            .typed_resource_by_uri_path::<String>(path)
+            .map(|resource| resource.user_data().clone())
+    };
+    assert_eq!(lookup("config/wifi%2Fmain").as_deref(), Some("slash"));
+    assert_eq!(lookup("config/wifi/main").as_deref(), Some("segments"));
+    assert_eq!(lookup("r%C3%A9sum%C3%A9/%E6%B8%A9%E5%BA%A6").as_deref(), Some("utf8"));
+    assert_eq!(lookup("a//b").as_deref(), Some("empty"));
+    assert_eq!(lookup("").as_deref(), Some("root"));
+
+    let path = CoapResourcePath::parse("/config/wifi%2Fmain");
+    assert_eq!(path.segments(), ["config", "wifi/main"]);
+    assert_eq!(path.to_string(), "config/wifi%2Fmain");
+    assert!(CoapResourcePath::parse("").is_root());
+}
+
+#[test]
+pub fn wellknown_core_modes() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    for (path, rt) in [
+        ("sensors/temp", "\"temperature-c\""),
+        ("sensors/hum", "\"humidity\""),
+        ("admin/reset", "\"reset\""),
+    ] {
+        let resource = CoapResource::builder(path, ())
+            .attribute("rt", Some(rt))
+            .build()
+            .unwrap();
+        server_context.add_resource(resource);
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut request = |server_context: &mut CoapContext, query: Option<&str>| {
+        let mut builder = CoapRequestBuilder::new(CoapRequestCode::Get).uri_path([".well-known", "core"]);
+        if let Some(query) = query {
+            builder = builder.query_pair("rt", Some(query));
+        }
+        common::exchange_request(server_context, &mut context, &session, builder.build().unwrap())
+    };
+    let links = |response: &CoapResponse| {
+        let mut links: Vec<String> = String::from_utf8(response.payload().to_vec())
+            .unwrap()
+            .split(',')
+            .filter(|link| !link.is_empty())
+            .map(|link| link.split(';').next().unwrap().to_string())
+            .collect();
+        links.sort();
+        links
+    };
+
+    // libcoap lists all resources by default.
+    let response = request(&mut server_context, None);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert!(links(&response).contains(&String::from("</admin/reset>")));
+
+    server_context.set_wellknown_core_mode(CoapWellKnownCoreMode::Disabled);
+    let response = request(&mut server_context, None);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+
+    // The custom handler hides the admin resources and supports filtering by resource type.
+    let handler = |context: &CoapContext, request: &CoapRequest| {
+        let rt = request.query_value("rt").map(|v| format!("rt=\"{}\"", v));
+        context.link_format(None, |path, link| {
+            path.segments()[0] != "admin" && rt.as_ref().map_or(true, |rt| link.contains(rt.as_str()))
+        })
+    };
+    server_context.set_wellknown_core_mode(CoapWellKnownCoreMode::Custom(Box::new(handler)));
+    let response = request(&mut server_context, None);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(links(&response), ["</sensors/hum>", "</sensors/temp>"]);
+    let response = request(&mut server_context, Some("temperature-c"));
+    assert_eq!(links(&response), ["</sensors/temp>"]);
+    assert!(String::from_utf8_lossy(response.payload()).contains("rt=\"temperature-c\""));
+
+    server_context.set_wellknown_core_mode(CoapWellKnownCoreMode::Default);
+    let response = request(&mut server_context, None);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert!(links(&response).contains(&String::from("</admin/reset>")));
+}
+
+#[test]
+pub fn resource_handle_removal() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let value_resource = |value: u32| {
+        CoapResource::builder("value", value)
+            .get(|resource: &CoapResource<u32>, sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(resource.user_data().to_string().into_bytes()));
+                sess.send(rsp).unwrap();
+            })
+            .build()
+            .unwrap()
+    };
+    let handle = server_context.add_resource(value_resource(1));
+    let handler_handle = handle.clone();
+    server_context.add_resource(
+        CoapResource::builder("remove", ())
+            .post(move |_, sess, _req, mut rsp: CoapResponse| {
+                let code = match handler_handle.clone().remove() {
+                    Ok(()) => CoapResponseCode::Deleted,
+                    Err(ResourceRemoved) => CoapResponseCode::NotFound,
+                };
+                rsp.set_code(CoapMessageCode::Response(code));
+                sess.send(rsp).unwrap();
+            })
+            .build()
+            .unwrap(),
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let get_value = || {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["value"])
+            .build()
+            .unwrap()
+    };
+    let post_remove = || {
+        CoapRequestBuilder::new(CoapRequestCode::Post)
+            .uri_path(["remove"])
+            .build()
+            .unwrap()
+    };
+
+    handle.with_user_data_mut(|value| *value = 2).unwrap();
+    handle.add_attribute("rt", Some("\"value\"")).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get_value());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), b"2");
+
+    // Resources can be removed from within request handlers.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, post_remove());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Deleted));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get_value());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, post_remove());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    assert!(handle.is_removed());
+    assert_eq!(handle.notify_observers(), Err(ResourceRemoved));
+    assert_eq!(handle.set_get_observable(true), Err(ResourceRemoved));
+    assert_eq!(handle.with_user_data(|value| *value), Err(ResourceRemoved));
+    assert!(server_context.typed_resource_by_uri_path::<u32>("value").is_none());
+
+    // The path can be used by a new resource, which can also be removed outside of handlers.
+    let new_handle = server_context.add_resource(value_resource(3));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get_value());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), b"3");
+    assert!(handle.is_removed());
+    new_handle.remove().unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get_value());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+
+    // Handles do not keep the context alive.
+    let last_handle = server_context.add_resource(value_resource(4));
+    assert_eq!(last_handle.with_user_data(|value| *value), Ok(4));
+    std::mem::drop(server_context);
+    assert_eq!(last_handle.with_user_data(|value| *value), Err(ResourceRemoved));
+}
+
+#[test]
+pub fn resource_tree_registration() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let leaf = |path: &str, value: &'static str| {
+        CoapResource::builder(path, ())
+            .observable(true)
+            .get(move |_, sess, _req, mut rsp| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(value.as_bytes()));
+                sess.send(rsp).unwrap();
+            })
+    };
+
+    // Errors are detected before any resource is added.
+    let invalid = CoapResourceTree::new("dev")
+        .node(leaf("status", "status"))
+        .node(leaf("cfg//net", "net"));
+    assert_eq!(
+        server_context.add_resource_tree(invalid).unwrap_err(),
+        ResourceTreeError::InvalidPath("dev/cfg//net".to_string())
+    );
+    let duplicate = CoapResourceTree::new("dev")
+        .node(leaf("status", "status"))
+        .subtree(CoapResourceTree::new("").node(leaf("status", "other")));
+    assert_eq!(
+        server_context.add_resource_tree(duplicate).unwrap_err(),
+        ResourceTreeError::DuplicatePath("dev/status".to_string())
+    );
+    assert!(server_context.typed_resource_by_uri_path::<()>("dev/status").is_none());
+    assert!(server_context.typed_resource_by_uri_path::<()>("dev").is_none());
+
+    let tree = CoapResourceTree::new("dev")
+        .subtree(CoapResourceTree::new("cfg").node(leaf("net", "net")))
+        .node(leaf("status", "status").attribute("rt", Some("\"status\"")));
+    let mut handle = server_context.add_resource_tree(tree).unwrap();
+    assert_eq!(
+        handle.resources().keys().collect::<Vec<_>>(),
+        ["dev/cfg/net", "dev/status"]
+    );
+    assert_eq!(handle.parents().keys().collect::<Vec<_>>(), ["dev", "dev/cfg"]);
+    assert_eq!(
+        server_context
+            .add_resource_tree(CoapResourceTree::new("dev").node(leaf("status", "status")))
+            .unwrap_err(),
+        ResourceTreeError::PathInUse("dev/status".to_string())
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let get = |path: &[&str]| {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(path.iter().copied())
+            .build()
+            .unwrap()
+    };
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get(&["dev", "cfg", "net"]));
+    assert_eq!(response.data().unwrap().as_ref(), b"net");
+    // Parent paths list their direct children.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get(&["dev"]));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.content_format(), Some(CoapContentFormat::LinkFormat.into()));
+    assert_eq!(
+        String::from_utf8(response.data().unwrap().to_vec()).unwrap(),
+        "</dev/cfg>;ct=40,</dev/status>;rt=\"status\";obs"
+    );
+
+    // Observers of removed resources receive a final error notification.
+    let observe_handle = session
+        .send_request(
+            CoapRequestBuilder::new(CoapRequestCode::Get)
+                .uri_path(["dev", "cfg", "net"])
+                .observe(0)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(handle.remove_subtree("dev/cfg"), 2);
+    assert!(handle.get("dev/cfg/net").is_none());
+    let notification = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert_eq!(
+        notification.code(),
+        CoapMessageCode::Response(CoapResponseCode::NotFound)
+    );
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get(&["dev"]));
+    assert_eq!(
+        String::from_utf8(response.data().unwrap().to_vec()).unwrap(),
+        "</dev/status>;rt=\"status\";obs"
+    );
+
+    assert_eq!(handle.remove(), 2);
+    let response = common::exchange_request(&mut server_context, &mut context, &session, get(&["dev", "status"]));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+}
+
+#[test]
+pub fn negotiated_representations() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let cbor_calls = Rc::new(Cell::new(0));
+    let cbor_calls_producer = cbor_calls.clone();
+    let mut representations = CoapRepresentations::new()
+        .with_data(CoapContentFormat::Json, "{\"value\":1}".as_bytes())
+        .with_producer(CoapContentFormat::Cbor, move || {
+            cbor_calls_producer.set(cbor_calls_producer.get() + 1);
+            vec![0xa1, 0x65, b'v', b'a', b'l', b'u', b'e', 0x01]
+        });
+    assert!(representations.set_etag(CoapContentFormat::Cbor, Some(Box::from(*b"cbor"))));
+    assert!(!representations.set_etag(CoapContentFormat::TextPlain, Some(Box::from(*b"text"))));
+    assert_eq!(
+        representations.formats(),
+        vec![CoapContentFormat::Json, CoapContentFormat::Cbor]
+    );
+    let resource = CoapResource::builder("value", ())
+        .representations(representations)
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = |accept: Option<CoapContentFormat>, etag: Option<&[u8]>| {
+        let mut builder = CoapRequestBuilder::new(CoapRequestCode::Get).uri_path(["value"]);
+        if let Some(accept) = accept {
+            builder = builder.accept(accept);
+        }
+        if let Some(etag) = etag {
+            builder = builder.etag(etag);
+        }
+        builder.build().unwrap()
+    };
+
+    // Requests without an Accept option receive the first representation.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request(None, None));
+    assert_eq!(response.response_code(), Some(CoapResponseCode::Content));
+    assert_eq!(response.content_format(), Some(u16::from(CoapContentFormat::Json)));
+    assert_eq!(response.data(), Some("{\"value\":1}".as_bytes()));
+    assert_eq!(response.etag(), None);
+    assert_eq!(cbor_calls.get(), 0);
+
+    // Producers are only called for the selected representation.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        request(Some(CoapContentFormat::Cbor), None),
+    );
+    assert_eq!(response.response_code(), Some(CoapResponseCode::Content));
+    assert_eq!(response.content_format(), Some(u16::from(CoapContentFormat::Cbor)));
+    assert_eq!(
+        response.data(),
+        Some([0xa1, 0x65, b'v', b'a', b'l', b'u', b'e', 0x01].as_slice())
+    );
+    assert_eq!(response.etag().map(|v| v.as_ref()), Some(b"cbor".as_slice()));
+    assert_eq!(cbor_calls.get(), 1);
+
+    // Requests containing the ETag of the selected representation are validated.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        request(Some(CoapContentFormat::Cbor), Some(b"cbor")),
+    );
+    assert_eq!(response.response_code(), Some(CoapResponseCode::Valid));
+    assert_eq!(response.data(), None);
+    assert_eq!(cbor_calls.get(), 1);
+
+    // Unavailable content formats are not acceptable.
+    let response = common::exchange_request(
+        &mut server_context,
+        &mut context,
+        &session,
+        request(Some(CoapContentFormat::TextPlain), None),
+    );
+    assert_eq!(response.response_code(), Some(CoapResponseCode::NotAcceptable));
+    assert_eq!(response.data(), None);
+    assert_eq!(cbor_calls.get(), 1);
+}
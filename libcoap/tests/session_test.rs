@@ -0,0 +1,485 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * session_test.rs - Tests for client and server session management.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use libcoap_rs::{
+    error::{MessageConversionError, SessionCreationError},
+    message::{CoapMessageCommon, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode, CoapToken},
+    session::{
+        CoapClientSession, CoapServerSession, CoapSession, CoapSessionCloseReason, CoapSessionCommon, CoapSessionId,
+    },
+    token::{CoapTokenGenerator, RandomTokens, SequentialTokens, MAX_TOKEN_GENERATION_ATTEMPTS},
+    types::{CoapMessageId, CoapProtocol, CoapUriScheme},
+    CoapContext, CoapEventHandler, CoapRequestHandler, CoapResource,
+};
+
+mod common;
+
+#[test]
+pub fn session_addresses() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let observed = Rc::new(RefCell::new(None));
+    let resource = CoapResource::new("test1", Rc::clone(&observed), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |observed: &mut Rc<RefCell<Option<(SocketAddr, SocketAddr, CoapProtocol)>>>,
+             sess: &mut CoapServerSession,
+             _req,
+             mut rsp: CoapResponse| {
+                observed.replace(Some((sess.addr_local(), sess.addr_remote(), sess.proto())));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.addr_remote(), server_address);
+    assert_eq!(session.proto(), CoapProtocol::Udp);
+    assert_eq!(session.crypto_info(), None);
+    // The local port was chosen by the operating system.
+    assert_ne!(session.addr_local().port(), 0);
+
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    let (server_local, server_remote, server_proto) = observed.borrow().unwrap();
+    assert_eq!(server_local.port(), server_address.port());
+    assert_eq!(server_remote.port(), session.addr_local().port());
+    assert_eq!(server_proto, CoapProtocol::Udp);
+}
+
+#[test]
+pub fn client_keepalive_ping() {
+    // Use a plain UDP socket as the peer so that we can observe the packets sent by the client.
+    let peer_socket = UdpSocket::bind("localhost:0").expect("Failed to bind peer socket");
+    peer_socket.set_nonblocking(true).unwrap();
+    let peer_address = peer_socket.local_addr().unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    context.set_keepalive(Some(Duration::from_secs(1)));
+    assert_eq!(context.keepalive(), Some(Duration::from_secs(1)));
+    let _session = CoapClientSession::connect_udp(&mut context, peer_address).unwrap();
+
+    let start = Instant::now();
+    let mut buf = [0u8; 64];
+    let len = common::run_until(&mut [&mut context], "keepalive ping", |_| {
+        peer_socket.recv(&mut buf).ok()
+    });
+    // A CoAP ping is an empty confirmable message (version 1, type CON, code 0.00).
+    assert!(len >= 4);
+    assert_eq!(buf[0] & 0xF0, 0x40);
+    assert_eq!(buf[1], 0x00);
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[derive(Debug)]
+struct PongRecorder {
+    received_pong: Rc<Cell<Option<CoapMessageId>>>,
+}
+
+impl CoapEventHandler for PongRecorder {
+    fn handle_pong(&mut self, _session: &mut CoapSession, mid: CoapMessageId) {
+        self.received_pong.set(Some(mid));
+    }
+}
+
+#[test]
+pub fn client_ping_pong() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+
+    let received_pong = Rc::new(Cell::new(None));
+    let mut context = CoapContext::new().unwrap();
+    context.set_event_handler(PongRecorder {
+        received_pong: received_pong.clone(),
+    });
+    let mut session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let ping_mid = session.send_ping().expect("unable to send ping");
+
+    let pong_mid = common::run_until(&mut [&mut server_context, &mut context], "pong", |_| {
+        received_pong.get()
+    });
+    assert_eq!(pong_mid, ping_mid);
+}
+
+#[test]
+pub fn client_disconnect_and_reconnect() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let session_clone = session.clone();
+    // Disconnecting must fail while other handles to the session exist.
+    let session = session
+        .disconnect()
+        .expect_err("disconnected session that is still in use");
+    std::mem::drop(session_clone);
+    session.disconnect().expect("unable to disconnect session");
+
+    // Reconnecting must create a fresh session.
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.addr_remote(), server_address);
+    session.disconnect().expect("unable to disconnect session");
+    context.shutdown(Some(Duration::from_secs(0))).unwrap();
+}
+
+#[test]
+pub fn stable_session_ids() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let seen_ids: Rc<RefCell<Vec<(CoapSessionId, Option<CoapSessionId>)>>> = Rc::new(RefCell::new(Vec::new()));
+    let seen_ids_handler = seen_ids.clone();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            move |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                seen_ids_handler.borrow_mut().push((sess.id(), req.session_id()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let sessions = [
+        CoapClientSession::connect_udp(&mut context, server_address).unwrap(),
+        CoapClientSession::connect_udp(&mut context, server_address).unwrap(),
+    ];
+    assert_ne!(sessions[0].id(), sessions[1].id());
+    for session in sessions.iter().chain(sessions.iter()) {
+        let response = common::exchange_request(&mut server_context, &mut context, session, common::gen_test_request());
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    }
+
+    let seen_ids = seen_ids.borrow();
+    assert_eq!(seen_ids.len(), 4);
+    for (session_id, request_session_id) in seen_ids.iter() {
+        assert_eq!(Some(*session_id), *request_session_id);
+    }
+    // Requests from the same client session arrive on the same server session.
+    assert_eq!(seen_ids[0].0, seen_ids[2].0);
+    assert_eq!(seen_ids[1].0, seen_ids[3].0);
+    assert_ne!(seen_ids[0].0, seen_ids[1].0);
+}
+
+/// Event handler that records the IDs of server-side sessions that were closed and deleted.
+#[derive(Debug, Default)]
+struct SessionCloseRecorder {
+    closed: Rc<RefCell<Vec<CoapSessionId>>>,
+    deleted: Rc<RefCell<Vec<CoapSessionId>>>,
+}
+
+impl CoapEventHandler for SessionCloseRecorder {
+    fn handle_session_closed(&mut self, session: &mut CoapSession) {
+        self.closed.borrow_mut().push(session.id());
+    }
+
+    fn handle_server_session_del(&mut self, session: &mut CoapServerSession) {
+        self.deleted.borrow_mut().push(session.id());
+    }
+}
+
+#[test]
+pub fn server_session_disconnect() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let recorder = SessionCloseRecorder::default();
+    let closed = recorder.closed.clone();
+    let deleted = recorder.deleted.clone();
+    server_context.set_event_handler(recorder);
+    let resource = CoapResource::builder("kick", ())
+        .get(|_resource, sess, _req, mut rsp| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+            sess.disconnect(CoapSessionCloseReason::Abort);
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let resource = CoapResource::builder("test1", ())
+        .get(|_resource, sess, _req, mut rsp| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    let held = server_context.session_by_peer(session.addr_local()).unwrap();
+    assert_eq!(held.close_reason(), None);
+
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["kick"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    // The session was released at the end of the IO iteration the handler was called in.
+    assert_eq!(*closed.borrow(), vec![held.id()]);
+    assert_eq!(*deleted.borrow(), vec![held.id()]);
+    assert!(server_context.session_by_peer(session.addr_local()).is_none());
+    // Remaining handles refer to the closed session.
+    assert_eq!(held.close_reason(), Some(CoapSessionCloseReason::Abort));
+    let response = CoapResponse::new(CoapMessageType::Non, CoapResponseCode::Content).unwrap();
+    assert_eq!(held.send(response), Err(MessageConversionError::SessionDisconnected));
+    held.disconnect(CoapSessionCloseReason::Release);
+    assert_eq!(held.close_reason(), Some(CoapSessionCloseReason::Abort));
+    let held_id = held.id();
+    std::mem::drop(held);
+
+    // Once all handles are dropped, the peer gets a new session for further requests.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    let new_session = server_context.session_by_peer(session.addr_local()).unwrap();
+    assert_ne!(new_session.id(), held_id);
+    assert_eq!(closed.borrow().len(), 1);
+}
+
+#[test]
+pub fn session_pool() {
+    let server_address = common::get_unused_server_addr();
+    let other_server_address = common::get_unused_server_addr();
+    let uri = format!("coap://{}", server_address);
+    let other_uri = format!("coap://{}", other_server_address);
+
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(context.session_pool_capacity(), 64);
+    assert_eq!(context.session_pool_idle_timeout(), None);
+
+    // Sessions are reused for the same peer and transport.
+    let session = context.get_or_connect(uri.as_str()).unwrap();
+    assert_eq!(context.get_or_connect(uri.as_str()).unwrap(), session);
+    assert_eq!(session.addr_remote(), server_address);
+    assert_eq!(context.pooled_sessions(), 1);
+    let other_session = context.get_or_connect(other_uri.as_str()).unwrap();
+    assert_ne!(other_session, session);
+    assert_eq!(context.pooled_sessions(), 2);
+    // Sessions created without the pool are not reused.
+    let unpooled_session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_ne!(context.get_or_connect(uri.as_str()).unwrap(), unpooled_session);
+
+    // The least recently used session (other_session) is evicted once the capacity is exceeded,
+    // the evicted session remains usable.
+    context.set_session_pool_capacity(1);
+    assert_eq!(context.pooled_sessions(), 1);
+    assert_eq!(context.get_or_connect(uri.as_str()).unwrap(), session);
+    let new_other_session = context.get_or_connect(other_uri.as_str()).unwrap();
+    assert_ne!(new_other_session, other_session);
+    assert_eq!(context.pooled_sessions(), 1);
+    assert!(other_session.send_request(common::gen_test_request()).is_ok());
+
+    // Idle sessions are removed while performing IO.
+    context.set_session_pool_capacity(16);
+    context.set_session_pool_idle_timeout(Some(Duration::from_millis(50)));
+    std::thread::sleep(Duration::from_millis(60));
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(context.pooled_sessions(), 0);
+    assert_ne!(context.get_or_connect(uri.as_str()).unwrap(), session);
+
+    assert!(matches!(
+        context.get_or_connect("coaps://127.0.0.1"),
+        Err(SessionCreationError::UnsupportedScheme(CoapUriScheme::Coaps))
+    ));
+}
+
+#[test]
+pub fn cloned_session_handles() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    let endpoint = server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .get(|_, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+            rsp.set_data(req.uri().query().map(<[u8]>::to_vec));
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let session_clone = session.clone();
+    let request = |query: &str| {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["test1"])
+            .uri_query([(query, "")])
+            .build()
+            .unwrap()
+    };
+    // Responses can be polled using any clone, regardless of which one sent the request.
+    let first = session_clone.send_request(request("first")).unwrap();
+    let second = session.send_request(request("second")).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &first);
+    assert!(response.data().unwrap().starts_with(b"first"));
+    let response = common::wait_for_response(&mut server_context, &mut context, &session_clone, &second);
+    assert!(response.data().unwrap().starts_with(b"second"));
+
+    // Removing the endpoint of a server-side session that is still referenced by the application
+    // disconnects the session instead of invalidating the handle.
+    let server_session = server_context.session_by_peer(session.addr_local()).unwrap();
+    assert!(server_context.remove_endpoint(endpoint));
+    assert_eq!(server_session.close_reason(), Some(CoapSessionCloseReason::Abort));
+    let response = CoapResponse::new(CoapMessageType::Non, CoapResponseCode::Content).unwrap();
+    assert_eq!(
+        server_session.send(response),
+        Err(MessageConversionError::SessionDisconnected)
+    );
+    std::mem::drop(server_session);
+    std::mem::drop(server_context);
+
+    // Handles may outlive their context.
+    std::mem::drop(session);
+    std::mem::drop(context);
+    std::mem::drop(session_clone);
+}
+
+#[test]
+pub fn user_specified_request_tokens() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_ne!(session.new_token(), session.new_token());
+
+    let token = session.new_token();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .token(token.clone())
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request.clone()).unwrap();
+
+    // Reusing the token of an outstanding request is rejected.
+    assert_eq!(
+        session.send_request(request.clone()).unwrap_err(),
+        MessageConversionError::TokenInUse
+    );
+
+    // Responses are matched using the token that was sent.
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.token(), Some(token.as_ref()));
+
+    // Once the request is no longer outstanding, its token can be used again.
+    session.remove_handle(req_handle);
+    let req_handle = session.send_request(request).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.token(), Some(token.as_ref()));
+}
+
+/// Token generator that always generates the same token, counting its calls.
+#[derive(Debug)]
+struct FixedTokenGenerator(Rc<Cell<usize>>);
+
+impl CoapTokenGenerator for FixedTokenGenerator {
+    fn generate(&mut self, _session: CoapSessionId, _in_use: &dyn Fn(&[u8]) -> bool) -> Option<CoapToken> {
+        self.0.set(self.0.get() + 1);
+        Some(Box::new([7]))
+    }
+}
+
+#[test]
+pub fn token_generators() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .get(|_, sess, _req, mut rsp: CoapResponse| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(resource);
+    let get_request = || {
+        CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["test1"])
+            .build()
+            .unwrap()
+    };
+    let sequential = |value: u64| Box::<[u8]>::from(value.to_be_bytes());
+
+    let mut context = CoapContext::new().unwrap();
+    context.set_token_generator(SequentialTokens::new(0x1234));
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.new_token(), sequential(0x1234));
+    let req_handle = session.send_request(get_request()).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.token(), Some(sequential(0x1235).as_ref()));
+
+    // Tokens of outstanding requests are skipped.
+    let explicit_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .token(sequential(0x1236))
+        .build()
+        .unwrap();
+    let explicit_handle = session.send_request(explicit_request).unwrap();
+    let req_handle = session.send_request(get_request()).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.token(), Some(sequential(0x1237).as_ref()));
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &explicit_handle);
+    assert_eq!(response.token(), Some(sequential(0x1236).as_ref()));
+
+    // Generating tokens that collide with outstanding requests is only retried a bounded number
+    // of times.
+    let calls = Rc::new(Cell::new(0));
+    context.set_token_generator(FixedTokenGenerator(Rc::clone(&calls)));
+    let req_handle = session.send_request(get_request()).unwrap();
+    assert_eq!(calls.get(), 1);
+    assert_eq!(
+        session.send_request(get_request()).unwrap_err(),
+        MessageConversionError::TokenGenerationFailed
+    );
+    assert_eq!(calls.get(), 1 + MAX_TOKEN_GENERATION_ATTEMPTS);
+    assert_ne!(*session.new_token(), [7]);
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(response.token(), Some(&[7][..]));
+
+    assert_eq!(RandomTokens::default().token_len(), 8);
+    context.set_token_generator(RandomTokens::new(4));
+    assert_eq!(session.new_token().len(), 4);
+    assert_ne!(session.new_token(), session.new_token());
+}
@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * startup_test.rs - Tests for the initialization and teardown of libcoap.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use libcoap_rs::{error::StartupError, startup_with, CoapContext, CoapStartupConfig};
+
+mod common;
+
+// libcoap's global state is shared by the entire test binary, so all checks are done in a single
+// test to avoid interference between tests running in parallel.
+#[test]
+pub fn startup_configuration_and_cleanup() {
+    let engine_config = CoapStartupConfig {
+        tls_engine_config: Some(String::from("engine:nonexistent\n")),
+    };
+
+    startup_with(CoapStartupConfig::default()).unwrap();
+    // Starting again using the same configuration has no effect, a different configuration is
+    // rejected instead of being ignored.
+    startup_with(CoapStartupConfig::default()).unwrap();
+    assert_eq!(
+        startup_with(engine_config.clone()).unwrap_err(),
+        StartupError::AlreadyStarted
+    );
+
+    let mut context = CoapContext::new().unwrap();
+    let other_context = CoapContext::new().unwrap();
+    assert_eq!(
+        startup_with(engine_config.clone()).unwrap_err(),
+        StartupError::AlreadyStarted
+    );
+    context.add_endpoint_udp(common::get_unused_server_addr()).unwrap();
+    std::mem::drop(context);
+    // libcoap is still in use by the other context.
+    assert_eq!(
+        startup_with(engine_config.clone()).unwrap_err(),
+        StartupError::AlreadyStarted
+    );
+    std::mem::drop(other_context);
+
+    // libcoap has been cleaned up after the last context was dropped, so it can be started again.
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(common::get_unused_server_addr()).unwrap();
+    std::mem::drop(context);
+
+    // An invalid TLS engine configuration is reported, and libcoap is not left started with it.
+    assert_ne!(startup_with(engine_config).unwrap_err(), StartupError::AlreadyStarted);
+    startup_with(CoapStartupConfig::default()).unwrap();
+}
@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * stats_test.rs - Tests for traffic, transfer and resource statistics.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use libcoap_rs::{
+    message::{CoapMessageCommon, CoapPduDirection, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{CoapMessageCode, CoapMessageType, CoapRequestCode, CoapResponseCode},
+    session::{CoapClientSession, CoapServerSession, CoapSessionCommon},
+    types::CoapProtocol,
+    CoapContext, CoapRequestHandler, CoapResource, CoapResourceStats, CoapStats,
+};
+
+mod common;
+
+#[test]
+pub fn resource_request_stats() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let last_stats: Rc<RefCell<Option<CoapResourceStats>>> = Rc::new(RefCell::new(None));
+    let last_stats_handler = last_stats.clone();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_get_observable(true);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new_resource_ref(
+            move |resource: &CoapResource<()>, sess, _req, mut rsp: CoapResponse| {
+                // Statistics of previous requests are available within the handler.
+                *last_stats_handler.borrow_mut() = Some(resource.stats());
+                rsp.set_data(Some("Hello World!".as_bytes().to_vec()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    for _ in 0..2 {
+        let response =
+            common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    }
+
+    let stats = last_stats.borrow().clone().unwrap();
+    assert_eq!(stats.requests.get(&CoapRequestCode::Get), Some(&2));
+    assert_eq!(stats.total_requests(), 2);
+    assert_eq!(stats.responses.get(&2), Some(&1));
+    assert_eq!(stats.bytes_served, "Hello World!".len() as u64);
+    assert_eq!(stats.active_observers, 0);
+
+    // Observers are counted as long as they are registered, even across resets.
+    let observe_request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .observe(0)
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(observe_request).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    let resource = server_context.typed_resource_by_uri_path::<()>("test1").unwrap();
+    assert_eq!(resource.stats().active_observers, 1);
+    assert_eq!(resource.stats().total_requests(), 3);
+    resource.reset_stats();
+    assert_eq!(resource.stats().total_requests(), 0);
+    assert_eq!(resource.stats().active_observers, 1);
+}
+
+#[test]
+pub fn traffic_stats() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let last_server_session: Rc<RefCell<Option<CoapServerSession<'static>>>> = Rc::new(RefCell::new(None));
+    let last_server_session_handler = last_server_session.clone();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            move |_data: &mut (), sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_data(Some("Hello World!".as_bytes().to_vec()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+                *last_server_session_handler.borrow_mut() = Some(sess.clone());
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let mut request = common::gen_test_request();
+    request.set_data(Some("ping".as_bytes().to_vec()));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    let client_stats = session.stats();
+    assert_eq!(client_stats.messages_sent, 1);
+    assert_eq!(client_stats.messages_received, 1);
+    assert_eq!(client_stats.payload_bytes_sent, 4);
+    assert_eq!(client_stats.payload_bytes_received, 12);
+    assert_eq!(client_stats.failed_deliveries, 0);
+    assert_eq!(context.stats(), client_stats);
+
+    let server_stats = last_server_session.borrow().as_ref().unwrap().stats();
+    assert_eq!(server_stats.messages_sent, 1);
+    assert_eq!(server_stats.messages_received, 1);
+    assert_eq!(server_stats.payload_bytes_sent, 12);
+    assert_eq!(server_stats.payload_bytes_received, 4);
+    assert_eq!(server_context.stats(), server_stats);
+    assert!(server_context
+        .stats()
+        .counters()
+        .any(|(name, value)| name == "messages_received" && value == 1));
+
+    // Resetting the statistics of a session does not affect the ones of its context (and vice
+    // versa).
+    session.reset_stats();
+    assert_eq!(session.stats(), CoapStats::default());
+    assert_eq!(context.stats(), client_stats);
+    context.reset_stats();
+    assert_eq!(context.stats(), CoapStats::default());
+
+    std::mem::drop(last_server_session.borrow_mut().take());
+}
+
+#[test]
+pub fn response_transfer_stats() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let body: Vec<u8> = (0..3000u32).map(|v| v as u8).collect();
+    let large_resource = CoapResource::builder("large", Arc::<[u8]>::from(body.as_slice()))
+        .get(|resource, sess, _req, mut rsp: CoapResponse| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            rsp.set_data_shared(Some(Arc::clone(&resource.user_data())));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(large_resource);
+    let small_resource = CoapResource::builder("test1", ())
+        .get(|_, sess, _req, mut rsp: CoapResponse| {
+            rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+            rsp.set_data(Some(b"small".to_vec()));
+            sess.send(rsp).unwrap();
+        })
+        .build()
+        .unwrap();
+    server_context.add_resource(small_resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    let transfer = response.transfer_stats().unwrap();
+    assert!(!transfer.blockwise);
+    assert_eq!(transfer.blocks, Some(1));
+    assert_eq!(transfer.bytes, 5);
+    assert!(transfer.duration.is_some());
+
+    // The body does not fit into a single message and is reassembled by libcoap.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["large"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.payload(), body.as_slice());
+    let transfer = response.transfer_stats().unwrap();
+    assert!(transfer.blockwise);
+    assert_eq!(transfer.bytes, body.len());
+    assert!(transfer.duration.is_some());
+    if let (Some(szx), Some(blocks)) = (transfer.block_szx, transfer.blocks) {
+        assert_eq!(blocks as usize, body.len().div_ceil(1 << (szx + 4)));
+    }
+
+    assert_eq!(context.stats().blockwise_responses_received, 1);
+    assert_eq!(session.stats().blockwise_responses_received, 1);
+    // Responses created by the application carry no transfer metadata.
+    let response = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content).unwrap();
+    assert_eq!(response.transfer_stats(), None);
+}
+
+#[test]
+pub fn pdu_inspector() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_data: &mut (), sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_data(Some("Hello World!".as_bytes().to_vec()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    let server_inspected: Rc<RefCell<Vec<(CoapPduDirection, CoapMessageCode, Vec<u8>)>>> = Rc::default();
+    let server_inspected_inspector = server_inspected.clone();
+    server_context.set_pdu_inspector(move |direction, _session, pdu| {
+        server_inspected_inspector
+            .borrow_mut()
+            .push((direction, pdu.code().unwrap(), pdu.encoded()))
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    let inspected: Rc<RefCell<Vec<(CoapPduDirection, CoapMessageCode, Vec<u8>, Option<Vec<u8>>)>>> = Rc::default();
+    let inspected_inspector = inspected.clone();
+    context.set_pdu_inspector(move |direction, session, pdu| {
+        assert_eq!(session.proto(), CoapProtocol::Udp);
+        inspected_inspector.borrow_mut().push((
+            direction,
+            pdu.code().unwrap(),
+            pdu.token().to_vec(),
+            pdu.data().map(|v| v.to_vec()),
+        ))
+    });
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+
+    let client_inspected = inspected.borrow();
+    assert_eq!(client_inspected.len(), 2);
+    assert_eq!(client_inspected[0].0, CoapPduDirection::Sent);
+    assert_eq!(client_inspected[0].1, CoapMessageCode::Request(CoapRequestCode::Get));
+    assert_eq!(client_inspected[0].3, None);
+    assert_eq!(client_inspected[1].0, CoapPduDirection::Received);
+    assert_eq!(
+        client_inspected[1].1,
+        CoapMessageCode::Response(CoapResponseCode::Content)
+    );
+    assert_eq!(client_inspected[1].3.as_deref(), Some("Hello World!".as_bytes()));
+    // The response has the token of the request.
+    assert_eq!(client_inspected[0].2, client_inspected[1].2);
+
+    // The request is encoded as a datagram with the token and a single Uri-Path option.
+    let server_inspected = server_inspected.borrow();
+    assert_eq!(server_inspected.len(), 2);
+    assert_eq!(server_inspected[0].0, CoapPduDirection::Received);
+    assert_eq!(server_inspected[1].0, CoapPduDirection::Sent);
+    let token = &client_inspected[0].2;
+    let encoded_request = &server_inspected[0].2;
+    assert_eq!(encoded_request[0], 0x40 | token.len() as u8);
+    assert_eq!(encoded_request[1], 0x01);
+    assert_eq!(&encoded_request[4..4 + token.len()], token.as_slice());
+    assert_eq!(&encoded_request[4 + token.len()..], b"\xB5test1");
+    assert!(server_inspected[1].2.ends_with(b"\xFFHello World!"));
+    std::mem::drop((client_inspected, server_inspected));
+
+    // After the inspector has been removed, PDUs are no longer inspected.
+    context.clear_pdu_inspector();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(inspected.borrow().len(), 2);
+}
@@ -8,13 +8,20 @@
  */
  #![cfg(feature = "tcp")]
 
-use libcoap_rs::session::CoapClientSession;
+use libcoap_rs::session::{
+    CoapClientSession, CoapObservationEvent, CoapServerSession, CoapSessionState, OutstandingRequestLimit,
+    ReconnectPolicy,
+};
 use libcoap_rs::{
-    message::CoapMessageCommon,
-    protocol::{CoapMessageCode, CoapResponseCode},
-    session::CoapSessionCommon,
-    CoapContext,
+    error::{ContextConfigurationError, MessageConversionError},
+    message::{CoapMessageCommon, CoapRequest, CoapResponse},
+    protocol::{CoapMessageCode, CoapRequestCode, CoapResponseCode, DEFAULT_MAX_TOKEN_SIZE},
+    session::{CoapSession, CoapSessionCommon},
+    CoapContext, CoapEventHandler, CoapRequestHandler, CoapResource,
 };
+use std::cell::{Cell, RefCell};
+use std::net::TcpListener;
+use std::rc::Rc;
 use std::time::Duration;
 
 mod common;
@@ -43,3 +50,303 @@ pub fn basic_client_server_request() {
         }
     }
 }
+
+#[test]
+pub fn extended_token_request() {
+    let server_address = common::get_unused_server_addr();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context.set_max_token_size(32).unwrap();
+        context.add_endpoint_tcp(server_address).unwrap();
+        context
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(context.max_token_size(), DEFAULT_MAX_TOKEN_SIZE);
+    assert_eq!(
+        context.set_max_token_size(DEFAULT_MAX_TOKEN_SIZE - 1),
+        Err(ContextConfigurationError::InvalidMaxTokenSize(DEFAULT_MAX_TOKEN_SIZE - 1))
+    );
+    context.set_max_token_size(16).unwrap();
+    assert_eq!(context.config().max_token_size, 16);
+
+    // Support for extended tokens is signaled in the CSMs exchanged while establishing the session.
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    context.wait_for_session_established(&session, Duration::from_secs(10)).unwrap();
+
+    let token: Vec<u8> = (0..16).collect();
+    let mut request = common::gen_test_request();
+    request.set_token(Some(token.clone()));
+    let req_handle = session.send_request(request).unwrap();
+    loop {
+        assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            assert_eq!(response.token(), Some(token.as_slice()));
+            assert_eq!(response.data().unwrap().as_ref(), "Hello World!".as_bytes());
+            server_handle.join().unwrap();
+            return;
+        }
+    }
+}
+
+#[test]
+pub fn reconnect_after_server_restart() {
+    let server_address = common::get_unused_server_addr();
+    let spawn_server = move || {
+        common::spawn_test_server(move |mut context| {
+            context.add_endpoint_tcp(server_address).unwrap();
+            context
+        })
+    };
+
+    let mut context = CoapContext::new().unwrap();
+    let policy = ReconnectPolicy {
+        max_queued_requests: 1,
+        ..ReconnectPolicy::new(Duration::from_secs(1))
+    };
+    if let Err(error) = context.set_reconnect_policy(Some(policy)) {
+        // libcoap < 4.3.5 does not support reconnecting sessions.
+        assert_eq!(
+            error,
+            ContextConfigurationError::UnsupportedFeature("session reconnection")
+        );
+        assert_eq!(context.reconnect_policy(), None);
+        return;
+    }
+    assert_eq!(context.reconnect_policy(), Some(policy));
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    let exchange = |context: &mut CoapContext| {
+        let req_handle = session.send_request(common::gen_test_request()).unwrap();
+        loop {
+            assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+            if let Some(response) = session.poll_handle(&req_handle).next() {
+                assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+                break;
+            }
+        }
+    };
+
+    let server_handle = spawn_server();
+    exchange(&mut context);
+    assert!(!session.is_reconnecting());
+    // The test server shuts down after it has answered the request, closing the connection.
+    server_handle.join().unwrap();
+    common::run_until(&mut [&mut context], "connection loss", |_| {
+        session.is_reconnecting().then_some(())
+    });
+
+    // Only one request may be sent while reconnecting.
+    session.send_request(common::gen_test_request()).unwrap();
+    assert_eq!(
+        session.send_request(common::gen_test_request()),
+        Err(MessageConversionError::SessionReconnecting)
+    );
+
+    let server_handle = spawn_server();
+    common::run_until(&mut [&mut context], "reconnection", |_| {
+        (!session.is_reconnecting()).then_some(())
+    });
+    exchange(&mut context);
+    server_handle.join().unwrap();
+}
+
+#[test]
+pub fn peer_max_message_size() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_tcp(server_address).unwrap();
+    let send_result = Rc::new(RefCell::new(None));
+    let resource = CoapResource::new("test1", send_result.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |send_result: &mut Rc<RefCell<Option<(Option<usize>, Result<_, MessageConversionError>)>>>,
+             sess: &mut CoapServerSession,
+             _req,
+             mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                let mut large_rsp = rsp.clone();
+                large_rsp.set_data(Some(vec![0; 4096]));
+                send_result.replace(Some((sess.peer_max_message_size(), sess.send(large_rsp))));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::InternalError));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    context.set_csm_max_message_size(1152);
+    assert_eq!(context.csm_max_message_size(), 1152);
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    assert_eq!(session.peer_max_message_size(), None);
+    common::run_until(&mut [&mut server_context, &mut context], "CSM exchange", |_| {
+        (session.state() == CoapSessionState::Established).then_some(())
+    });
+    assert!(session.peer_max_message_size().is_some());
+
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    // The large response exceeds the maximum message size announced by the client and is refused.
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::InternalError)
+    );
+    let (peer_max_message_size, result) = send_result.take().unwrap();
+    let peer_max_message_size = peer_max_message_size.unwrap();
+    assert!(peer_max_message_size <= 1152);
+    assert!(matches!(
+        result,
+        Err(MessageConversionError::MessageTooLarge(size, max)) if size > 4096 && max == peer_max_message_size
+    ));
+}
+
+#[derive(Debug)]
+struct CsmTimeoutRecorder {
+    timed_out: Rc<Cell<bool>>,
+}
+
+impl CoapEventHandler for CsmTimeoutRecorder {
+    fn handle_csm_timeout(&mut self, _session: &mut CoapSession) {
+        self.timed_out.set(true);
+    }
+}
+
+#[test]
+pub fn csm_timeout() {
+    // A peer that accepts the connection, but never sends its CSM.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_address = listener.local_addr().unwrap();
+
+    let timed_out = Rc::new(Cell::new(false));
+    let mut context = CoapContext::new().unwrap();
+    context.set_csm_timeout(Duration::from_millis(500));
+    assert_eq!(context.csm_timeout(), Duration::from_millis(500));
+    context.set_event_handler(CsmTimeoutRecorder {
+        timed_out: timed_out.clone(),
+    });
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    let _peer = listener.accept().unwrap();
+
+    common::run_until(&mut [&mut context], "CSM timeout", |_| timed_out.get().then_some(()));
+    assert_ne!(session.state(), CoapSessionState::Established);
+    assert_eq!(session.peer_max_message_size(), None);
+}
+
+#[test]
+pub fn outstanding_request_limit() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_tcp(server_address).unwrap();
+    let handled = Rc::new(Cell::new(0u8));
+    let resource = CoapResource::new("test1", handled.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |handled: &mut Rc<Cell<u8>>, sess: &mut CoapServerSession, _req, mut rsp: CoapResponse| {
+                handled.set(handled.get() + 1);
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data(Some(vec![handled.get()]));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    let mut limit = OutstandingRequestLimit::new(1);
+    limit.max_queued = 2;
+    session.set_max_outstanding_requests(Some(limit));
+    assert_eq!(session.max_outstanding_requests(), Some(limit));
+
+    let handles: Vec<_> = (0..3)
+        .map(|_| session.send_request(common::gen_test_request()).unwrap())
+        .collect();
+    assert_eq!(session.outstanding_requests(), 1);
+    assert_eq!(session.queued_requests(), 2);
+    assert!(matches!(
+        session.send_request(common::gen_test_request()),
+        Err(MessageConversionError::RequestQueueFull(2))
+    ));
+
+    // Requests are sent one after another, in the order they were queued.
+    let mut received = Vec::new();
+    common::run_until(&mut [&mut server_context, &mut context], "responses", |_| {
+        assert!(usize::from(handled.get()) <= received.len() + 1);
+        for (i, handle) in handles.iter().enumerate() {
+            for response in session.poll_handle(handle) {
+                assert_eq!(response.data().unwrap().as_ref(), &[i as u8 + 1]);
+                received.push(i);
+            }
+        }
+        (received.len() == handles.len()).then_some(())
+    });
+    assert_eq!(received, vec![0, 1, 2]);
+    assert_eq!(session.outstanding_requests(), 0);
+    assert_eq!(session.queued_requests(), 0);
+}
+
+#[test]
+pub fn reregister_observations_after_reconnect() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_tcp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_get_observable(true);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess: &mut CoapServerSession, req: &CoapRequest, mut rsp: CoapResponse| {
+                if req.observe().is_some() {
+                    rsp.set_observe(Some(0));
+                }
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let policy = ReconnectPolicy {
+        reregister_observations: true,
+        ..ReconnectPolicy::new(Duration::from_secs(1))
+    };
+    if context.set_reconnect_policy(Some(policy)).is_err() {
+        // libcoap < 4.3.5 does not support reconnecting sessions.
+        return;
+    }
+    let session = CoapClientSession::connect_tcp(&mut context, server_address).unwrap();
+    let mut observe_request = common::gen_test_request();
+    observe_request.set_observe(Some(0));
+    let observe_handle = session.send_request(observe_request).unwrap();
+    let response = common::wait_for_response(&mut server_context, &mut context, &session, &observe_handle);
+    assert!(response.observe().is_some());
+
+    // The restarted server no longer has the observed resource.
+    drop(server_context);
+    common::run_until(&mut [&mut context], "connection loss", |_| {
+        session.is_reconnecting().then_some(())
+    });
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_tcp(server_address).unwrap();
+
+    let mut events = Vec::new();
+    let response = common::run_until(&mut [&mut server_context, &mut context], "re-registration", |_| {
+        events.extend(session.poll_observation_events(&observe_handle));
+        session.poll_handle(&observe_handle).next()
+    });
+    events.extend(session.poll_observation_events(&observe_handle));
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotFound));
+    assert_eq!(
+        events,
+        vec![
+            CoapObservationEvent::Reregistered,
+            CoapObservationEvent::Terminated(CoapMessageCode::Response(CoapResponseCode::NotFound)),
+        ]
+    );
+}
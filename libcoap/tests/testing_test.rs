@@ -0,0 +1,305 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * testing_test.rs - Tests for the in-process client/server test pair.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(feature = "test-util")]
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use libcoap_rs::{
+    error::RequestWaitError,
+    message::{CoapMessageCommon, CoapRequest},
+    protocol::{CoapMessageType, CoapRequestCode, CoapResponseCode},
+    session::{transmission_capabilities, CoapRetransmission, CoapSession, CoapSessionCommon},
+    testing::{assert_requests_handled, assert_response_code, CoapLinkDirection, CoapLinkFault, CoapTestPair},
+    CoapEventHandler, CoapRequestHandler, CoapResource, ResourceFlags,
+};
+
+/// Creates a resource at `path` whose GET handler records the path in `log` and responds with it.
+fn logging_resource(path: &'static str, log: Rc<RefCell<Vec<&'static str>>>) -> CoapResource<()> {
+    let resource = CoapResource::new(path, (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(move |_, session, _request, mut response| {
+            log.borrow_mut().push(path);
+            response.set_code(CoapResponseCode::Content);
+            response.set_data(Some(path.as_bytes().to_vec()));
+            session.send(response).unwrap();
+        })),
+    );
+    resource
+}
+
+fn get_request(type_: CoapMessageType, path: &str) -> CoapRequest {
+    CoapRequest::new(type_, CoapRequestCode::Get, path.parse().unwrap()).unwrap()
+}
+
+/// Event handler that records the retransmissions reported for the sessions of a context.
+#[derive(Debug)]
+struct RetransmissionRecorder(Rc<RefCell<Vec<CoapRetransmission>>>);
+
+impl CoapEventHandler for RetransmissionRecorder {
+    fn handle_retransmission(&mut self, _session: &mut CoapSession, retransmission: &CoapRetransmission) {
+        self.0.borrow_mut().push(retransmission.clone());
+    }
+}
+
+#[test]
+pub fn test_pair_request() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::new().unwrap();
+    let handle = pair.add_resource(logging_resource("test1", Rc::clone(&log)));
+    assert_eq!(pair.session().addr_remote(), pair.server_addr());
+    assert!(pair.link().is_none());
+
+    let response = pair.request(get_request(CoapMessageType::Con, "/test1")).unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    assert_eq!(response.data().unwrap().as_ref(), b"test1");
+    assert_requests_handled(&handle.resource().unwrap(), CoapRequestCode::Get, 1);
+
+    let response = pair.request(get_request(CoapMessageType::Non, "/missing")).unwrap();
+    assert_response_code(&response, CoapResponseCode::NotFound);
+    assert_eq!(*log.borrow(), vec!["test1"]);
+
+    // No steps are performed if the limit is 0, so the request can not be answered.
+    assert!(matches!(
+        pair.request_with_limit(get_request(CoapMessageType::Con, "/test1"), 0),
+        Err(RequestWaitError::Timeout)
+    ));
+}
+
+#[test]
+pub fn test_pair_run_until() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::new().unwrap();
+    pair.add_resource(logging_resource("test1", Rc::clone(&log)));
+
+    let session = pair.session().clone();
+    let req_handle = session
+        .send_request(get_request(CoapMessageType::Con, "/test1"))
+        .unwrap();
+    let mut responses = Vec::new();
+    let reached = pair
+        .run_until(
+            |_| {
+                responses.extend(session.poll_handle(&req_handle));
+                !responses.is_empty()
+            },
+            1000,
+        )
+        .unwrap();
+    assert!(reached);
+    assert_response_code(&responses[0], CoapResponseCode::Content);
+    assert_eq!(*log.borrow(), vec!["test1"]);
+
+    // Predicates that never return true are checked once more after the last step.
+    let mut checks = 0;
+    assert!(!pair
+        .run_until(
+            |_| {
+                checks += 1;
+                false
+            },
+            3
+        )
+        .unwrap());
+    assert_eq!(checks, 4);
+}
+
+#[test]
+pub fn test_link_drop_and_duplicate() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::builder().link(true).build().unwrap();
+    pair.add_resource(logging_resource("test1", Rc::clone(&log)));
+    let link_addr = pair.link().unwrap().local_addr();
+    assert_eq!(pair.session().addr_remote(), link_addr);
+
+    // The first transmission is lost, the request is answered once it is retransmitted.
+    pair.link()
+        .unwrap()
+        .inject(CoapLinkDirection::ClientToServer, CoapLinkFault::Drop);
+    let response = pair
+        .request_with_limit(get_request(CoapMessageType::Con, "/test1"), 2000)
+        .unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    let link = pair.link().unwrap();
+    assert_eq!(link.dropped(CoapLinkDirection::ClientToServer), 1);
+    assert_eq!(link.forwarded(CoapLinkDirection::ClientToServer), 1);
+    assert_eq!(link.forwarded(CoapLinkDirection::ServerToClient), 1);
+    assert_eq!(link.pending_faults(CoapLinkDirection::ClientToServer), 0);
+
+    // The duplicated request is detected as a retransmission by the server.
+    link.inject(CoapLinkDirection::ClientToServer, CoapLinkFault::Duplicate);
+    let response = pair.request(get_request(CoapMessageType::Con, "/test1")).unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    assert!(pair
+        .run_until(|pair| pair.server().duplicate_request_count() == 1, 100)
+        .unwrap());
+    assert_eq!(pair.link().unwrap().forwarded(CoapLinkDirection::ClientToServer), 3);
+}
+
+#[test]
+pub fn test_link_retransmission_observation() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::builder().link(true).build().unwrap();
+    pair.add_resource(logging_resource("test1", Rc::clone(&log)));
+    let retransmissions = Rc::new(RefCell::new(Vec::new()));
+    pair.client()
+        .set_event_handler(RetransmissionRecorder(Rc::clone(&retransmissions)));
+
+    // The initial timeout is no longer chosen randomly.
+    assert!(transmission_capabilities().initial_rto_supported());
+    let session = pair.session().clone();
+    session.set_initial_rto(Duration::from_millis(500));
+    assert_eq!(session.ack_timeout(), (0, 500));
+    assert_eq!(session.ack_random_factor(), (1, 0));
+
+    pair.link()
+        .unwrap()
+        .inject(CoapLinkDirection::ClientToServer, CoapLinkFault::Drop);
+    let response = pair
+        .request_with_limit(get_request(CoapMessageType::Con, "/test1"), 2000)
+        .unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    {
+        let retransmissions = retransmissions.borrow();
+        assert_eq!(retransmissions.len(), 1);
+        assert_eq!(retransmissions[0].attempt, 1);
+        assert_eq!(retransmissions[0].token.as_ref(), response.token().unwrap());
+        assert!(retransmissions[0].timeout >= Duration::from_millis(400));
+        assert!(retransmissions[0].timeout < Duration::from_secs(2));
+    }
+    // The acknowledgement of a retransmitted request is not used as a round-trip time sample.
+    let state = session.transmission_state();
+    assert!(state.rto_estimate.is_none());
+    assert_eq!(state.outstanding, 0);
+    assert_eq!(state.backoff, 0);
+
+    let response = pair.request(get_request(CoapMessageType::Con, "/test1")).unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    assert_eq!(retransmissions.borrow().len(), 1);
+    let estimate = session.transmission_state().rto_estimate.unwrap();
+    assert_eq!(estimate.samples, 1);
+    assert!(estimate.srtt < Duration::from_millis(500));
+    // The first sample determines SRTT, RTTVAR is half of it.
+    assert!((estimate.rto.as_secs_f64() - 3.0 * estimate.srtt.as_secs_f64()).abs() < 1e-6);
+}
+
+#[test]
+pub fn test_link_reorder() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::builder().link(true).build().unwrap();
+    pair.add_resource(logging_resource("first", Rc::clone(&log)));
+    pair.add_resource(logging_resource("second", Rc::clone(&log)));
+
+    // The first request is held back until the second one has been forwarded.
+    pair.link()
+        .unwrap()
+        .inject(CoapLinkDirection::ClientToServer, CoapLinkFault::Delay(1));
+    let session = pair.session().clone();
+    let first_handle = session
+        .send_request(get_request(CoapMessageType::Non, "/first"))
+        .unwrap();
+    assert!(pair
+        .run_until(
+            |pair| pair.link().unwrap().delayed(CoapLinkDirection::ClientToServer) == 1,
+            100
+        )
+        .unwrap());
+    assert!(log.borrow().is_empty());
+
+    let response = pair.request(get_request(CoapMessageType::Non, "/second")).unwrap();
+    assert_eq!(response.data().unwrap().as_ref(), b"second");
+    let mut first_responses = Vec::new();
+    assert!(pair
+        .run_until(
+            |_| {
+                first_responses.extend(session.poll_handle(&first_handle));
+                !first_responses.is_empty()
+            },
+            100
+        )
+        .unwrap());
+    assert_eq!(first_responses[0].data().unwrap().as_ref(), b"first");
+    assert_eq!(*log.borrow(), vec!["second", "first"]);
+    assert_eq!(pair.link().unwrap().delayed(CoapLinkDirection::ClientToServer), 0);
+}
+
+#[test]
+pub fn test_link_reordered_notifications() {
+    let counter = Rc::new(Cell::new(0));
+    let mut pair = CoapTestPair::builder().link(true).build().unwrap();
+    let handler_counter = Rc::clone(&counter);
+    let resource = CoapResource::builder("state", ())
+        .observable(true)
+        .flags(ResourceFlags::NOTIFY_NON)
+        .get(move |_, session, _request, mut response| {
+            handler_counter.set(handler_counter.get() + 1);
+            response.set_code(CoapResponseCode::Content);
+            response.set_data(Some(handler_counter.get().to_string().into_bytes()));
+            session.send(response).unwrap();
+        })
+        .build()
+        .unwrap();
+    let handle = pair.add_resource(resource);
+
+    let session = pair.session().clone();
+    let mut request = get_request(CoapMessageType::Con, "/state");
+    request.set_observe(Some(0));
+    let req_handle = session.send_request(request).unwrap();
+    session.set_ordered_notifications(&req_handle, true);
+    let mut responses = Vec::new();
+    assert!(pair
+        .run_until(
+            |_| {
+                responses.extend(session.poll_handle(&req_handle));
+                !responses.is_empty()
+            },
+            100
+        )
+        .unwrap());
+    let initial_observe = responses[0].observe().unwrap();
+
+    // The first notification is held back until the second one has been forwarded, so it
+    // arrives after the fresher one and is dropped.
+    pair.link()
+        .unwrap()
+        .inject(CoapLinkDirection::ServerToClient, CoapLinkFault::Delay(1));
+    handle.resource().unwrap().notify_observers();
+    assert!(pair
+        .run_until(
+            |pair| pair.link().unwrap().delayed(CoapLinkDirection::ServerToClient) == 1,
+            100
+        )
+        .unwrap());
+    handle.resource().unwrap().notify_observers();
+    assert!(pair
+        .run_until(|_| session.stale_notifications(&req_handle) == 1, 100)
+        .unwrap());
+    let notifications: Vec<_> = session.poll_handle(&req_handle).collect();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].data().unwrap().as_ref(), b"3");
+    assert!(notifications[0].observe().unwrap() > initial_observe);
+    assert_eq!(counter.get(), 3);
+}
+
+#[cfg(feature = "dtls-psk")]
+#[test]
+pub fn test_pair_dtls_psk() {
+    use libcoap_rs::types::CoapProtocol;
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut pair = CoapTestPair::builder().dtls_psk(true).link(true).build().unwrap();
+    assert_eq!(pair.session().proto(), CoapProtocol::Dtls);
+    let handle = pair.add_resource(logging_resource("test1", Rc::clone(&log)));
+
+    let response = pair.request(get_request(CoapMessageType::Con, "/test1")).unwrap();
+    assert_response_code(&response, CoapResponseCode::Content);
+    assert_requests_handled(&handle.resource().unwrap(), CoapRequestCode::Get, 1);
+}
@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * tls_backend_test.rs - Tests for querying the TLS library used by libcoap.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+
+use libcoap_rs::crypto::{tls_backend, TlsLibrary};
+use libcoap_rs::types::CoapProtocol;
+
+#[test]
+pub fn tls_backend_matches_enabled_features() {
+    let backend = tls_backend();
+
+    // libcoap-sys checks that the features requested by enabled crate features are available.
+    #[cfg(feature = "dtls-psk")]
+    assert!(backend.dtls_supported() && backend.psk_supported());
+    #[cfg(feature = "dtls-pki")]
+    assert!(backend.dtls_supported() && backend.pki_supported());
+    #[cfg(feature = "dtls-rpk")]
+    assert!(backend.dtls_supported() && backend.rpk_supported());
+
+    if backend.library() == TlsLibrary::NoTls {
+        assert!(!backend.dtls_supported() && !backend.tls_supported());
+        assert_eq!(backend.version(), None);
+        assert_eq!(backend.to_string(), "none");
+    } else if backend.library() != TlsLibrary::Unknown {
+        let version = backend.version().expect("version of TLS library should be known");
+        assert!(backend.built_version().is_some());
+        assert_eq!(backend.to_string(), format!("{} {}", backend.library(), version));
+    }
+}
+
+#[test]
+pub fn transport_availability_matches_enabled_features() {
+    let backend = tls_backend();
+    assert!(CoapProtocol::Udp.is_enabled() && CoapProtocol::Udp.is_supported());
+    assert!(!CoapProtocol::None.is_enabled());
+    assert_eq!(CoapProtocol::Tcp.is_enabled(), cfg!(feature = "tcp"));
+    assert_eq!(CoapProtocol::Ws.is_enabled(), cfg!(feature = "websockets"));
+    assert!(!CoapProtocol::Tls.is_enabled() || cfg!(feature = "tls"));
+    assert_eq!(
+        CoapProtocol::Dtls.is_enabled(),
+        cfg!(any(feature = "dtls-psk", feature = "dtls-pki", feature = "dtls-rpk"))
+    );
+
+    // Transports that are enabled are also checked against the libcoap build in use.
+    #[cfg(feature = "tcp")]
+    assert!(CoapProtocol::Tcp.is_supported());
+    assert_eq!(
+        CoapProtocol::Dtls.is_supported(),
+        CoapProtocol::Dtls.is_enabled() && backend.dtls_supported()
+    );
+    assert_eq!(
+        CoapProtocol::Tls.is_supported(),
+        CoapProtocol::Tls.is_enabled() && backend.tls_supported()
+    );
+    assert_eq!(
+        CoapProtocol::Wss.is_supported(),
+        CoapProtocol::Wss.is_enabled() && backend.wss_supported()
+    );
+}
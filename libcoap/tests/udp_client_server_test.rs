@@ -7,14 +7,27 @@
  * See the README as well as the LICENSE file for more information.
  */
 
-use libcoap_rs::session::CoapClientSession;
+use std::cell::{Cell, RefCell};
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use libcoap_rs::{
-    message::CoapMessageCommon,
-    protocol::{CoapMessageCode, CoapResponseCode},
-    session::CoapSessionCommon,
-    CoapContext,
+    client::{self, RequestOptions},
+    echo::CoapEchoPolicy,
+    error::{
+        ClientRequestError, ContextConfigurationError, MessageConversionError, RequestPollError, RequestWaitError,
+        SessionCreationError, SessionGetAppDataError,
+    },
+    message::{CoapMessageCommon, CoapOption, CoapPduDirection, CoapRequest, CoapRequestBuilder, CoapResponse},
+    protocol::{
+        CoapContentFormat, CoapMessageCode, CoapMessageType, CoapNoResponse, CoapRequestCode, CoapResponseCode,
+    },
+    session::{CoapClientSession, CoapServerSession, CoapSession, CoapSessionCommon},
+    types::{CoapMessageId, CoapUri, CoapUriScheme},
+    CoapContext, CoapEventHandler, CoapRequestHandler, CoapResource,
 };
-use std::time::Duration;
 
 mod common;
 
@@ -42,3 +55,975 @@ pub fn basic_client_server_request() {
         }
     }
 }
+
+#[test]
+pub fn connect_by_uri() {
+    let server_address = common::get_unused_server_addr();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context.add_endpoint_udp(server_address).unwrap();
+        context
+    });
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_uri(&mut context, &format!("coap://{}", server_address)).unwrap();
+    assert_eq!(session.addr_remote(), server_address);
+
+    let request = common::gen_test_request();
+    let req_handle = session.send_request(request).unwrap();
+    loop {
+        assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            server_handle.join().unwrap();
+            return;
+        }
+    }
+}
+
+#[test]
+pub fn connect_by_uri_errors() {
+    let mut context = CoapContext::new().unwrap();
+    assert_eq!(
+        CoapClientSession::connect_uri(&mut context, "https://127.0.0.1").unwrap_err(),
+        SessionCreationError::UnsupportedScheme(CoapUriScheme::Https)
+    );
+    assert!(matches!(
+        CoapClientSession::connect_uri(&mut context, "coap://nonexistent.invalid").unwrap_err(),
+        SessionCreationError::DnsFailure(host, _) if host == "nonexistent.invalid"
+    ));
+    assert!(matches!(
+        CoapClientSession::connect_uri(&mut context, "not a uri").unwrap_err(),
+        SessionCreationError::InvalidUri(_) | SessionCreationError::MissingHost
+    ));
+    // Already parsed URIs are accepted as well.
+    let uri: CoapUri = "coap://127.0.0.1:5700/test".parse().unwrap();
+    let session = CoapClientSession::connect_uri(&mut context, &uri).unwrap();
+    assert_eq!(session.addr_remote(), "127.0.0.1:5700".parse().unwrap());
+}
+
+#[test]
+pub fn one_shot_client_request() {
+    let server_address = common::get_unused_server_addr();
+
+    let server_handle = common::spawn_test_server(move |mut context| {
+        context.add_endpoint_udp(server_address).unwrap();
+        context
+    });
+
+    let response = client::get(&format!("coap://{}/test1", server_address), RequestOptions::new()).unwrap();
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), "Hello World!".as_bytes());
+    server_handle.join().unwrap();
+
+    assert_eq!(
+        client::get("https://127.0.0.1/test1", RequestOptions::new()).unwrap_err(),
+        ClientRequestError::Session(SessionCreationError::UnsupportedScheme(CoapUriScheme::Https))
+    );
+    assert_eq!(
+        client::get("coaps://127.0.0.1/test1", RequestOptions::new()).unwrap_err(),
+        ClientRequestError::MissingCredentials
+    );
+    // The timeout includes the time required to establish the session.
+    assert_eq!(
+        client::delete(
+            &format!("coap://{}/test1", server_address),
+            RequestOptions::new().timeout(Duration::ZERO)
+        )
+        .unwrap_err(),
+        ClientRequestError::TimedOut
+    );
+}
+
+#[test]
+pub fn request_values() {
+    let silent_peer = UdpSocket::bind("localhost:0").unwrap();
+    let mut context = CoapContext::new().unwrap();
+    context.set_max_pending_requests(Some(2));
+    assert_eq!(context.max_pending_requests(), Some(2));
+    let session = CoapClientSession::connect_udp(&mut context, silent_peer.local_addr().unwrap()).unwrap();
+
+    let mut timed_out_request = common::gen_test_request();
+    timed_out_request.set_timeout(Some(Duration::from_millis(100)));
+    let timed_out_handle = session
+        .send_request_with_value(timed_out_request, String::from("first"))
+        .unwrap();
+    let pending_handle = session
+        .send_request_with_value(common::gen_test_request(), 42u32)
+        .unwrap();
+    assert_eq!(session.pending_requests(), 2);
+    assert_eq!(
+        session.send_request(common::gen_test_request()),
+        Err(MessageConversionError::TooManyPendingRequests(2))
+    );
+
+    let incremented = session.with_request_value(&pending_handle, |value: &mut u32| {
+        *value += 1;
+        *value
+    });
+    assert_eq!(incremented, Ok(Some(43)));
+    assert_eq!(
+        session.with_request_value(&pending_handle, |_: &mut String| ()),
+        Err(SessionGetAppDataError::WrongType)
+    );
+
+    common::run_until(&mut [&mut context], "request timeout", |_| {
+        session.try_poll_handle(&timed_out_handle).err()
+    });
+    // Failed requests keep their values (and count against the limit) until their handles are
+    // removed.
+    assert_eq!(session.pending_requests(), 2);
+    let value = session.remove_handle_with_value(timed_out_handle).unwrap();
+    assert_eq!(value.downcast_ref::<String>().map(String::as_str), Some("first"));
+    assert_eq!(session.pending_requests(), 1);
+    session.send_request(common::gen_test_request()).unwrap();
+    let value = session.remove_handle_with_value(pending_handle).unwrap();
+    assert_eq!(value.downcast_ref::<u32>(), Some(&43));
+}
+
+#[test]
+pub fn request_timeout() {
+    // Use a plain UDP socket as the peer so that responses can be sent after the timeout elapsed.
+    let peer_socket = UdpSocket::bind("localhost:0").expect("Failed to bind peer socket");
+    peer_socket.set_nonblocking(true).unwrap();
+    let peer_address = peer_socket.local_addr().unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, peer_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .confirmable(false)
+        .uri_path(["test1"])
+        .timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    assert_eq!(request.timeout(), Some(Duration::from_millis(200)));
+    let req_handle = session.send_request(request).unwrap();
+    assert_eq!(session.try_poll_handle(&req_handle).unwrap().count(), 0);
+
+    // do_io() returns once the timeout has elapsed instead of waiting for the full duration.
+    let start = Instant::now();
+    context.do_io(Some(Duration::from_secs(10))).unwrap();
+    let error = common::run_until(&mut [&mut context], "request timeout", |_| {
+        session.try_poll_handle(&req_handle).err()
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(error, RequestPollError::TimedOut);
+    assert_eq!(session.poll_handle(&req_handle).count(), 0);
+
+    // Late responses are rejected.
+    let mut buf = [0u8; 64];
+    let len = peer_socket.recv(&mut buf).expect("request was not sent");
+    let token_len = (buf[0] & 0x0F) as usize;
+    assert!(len >= 4 + token_len);
+    let mut response = vec![0x50 | token_len as u8, 0x45, 0x12, 0x34];
+    response.extend_from_slice(&buf[4..4 + token_len]);
+    peer_socket.send_to(&response, session.addr_local()).unwrap();
+    context.do_io(Some(Duration::from_millis(100))).unwrap();
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::TimedOut
+    );
+    assert!(!session.is_waiting_for_token(&Box::from(&buf[4..4 + token_len])));
+
+    session.remove_handle(req_handle);
+}
+
+#[test]
+pub fn non_confirmable_request_and_response() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_data: &mut (), sess: &mut CoapServerSession, req: &CoapRequest, mut rsp: CoapResponse| {
+                assert_eq!(req.type_(), CoapMessageType::Non);
+                rsp.set_data(Some("Hello World!".as_bytes().to_vec()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    let inspected_types: Rc<RefCell<Vec<(CoapPduDirection, CoapMessageType)>>> = Rc::default();
+    let inspected_types_inspector = inspected_types.clone();
+
+    let mut context = CoapContext::new().unwrap();
+    context.set_pdu_inspector(move |direction, _session, pdu| {
+        inspected_types_inspector.borrow_mut().push((direction, pdu.type_()))
+    });
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.default_message_type(), CoapMessageType::Con);
+    assert!(session.set_default_message_type(CoapMessageType::Ack).is_err());
+    session.set_default_message_type(CoapMessageType::Non).unwrap();
+
+    // Requests without an explicit message type use the default of the session.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.type_(), CoapMessageType::Non);
+    assert_eq!(response.data(), Some("Hello World!".as_bytes()));
+    assert_eq!(
+        *inspected_types.borrow(),
+        vec![
+            (CoapPduDirection::Sent, CoapMessageType::Non),
+            (CoapPduDirection::Received, CoapMessageType::Non)
+        ]
+    );
+
+    // An explicit message type overrides the default of the session.
+    inspected_types.borrow_mut().clear();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .confirmable(true)
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    assert_eq!(
+        inspected_types.borrow()[0],
+        (CoapPduDirection::Sent, CoapMessageType::Con)
+    );
+    session.remove_handle(req_handle);
+}
+
+#[test]
+pub fn confirmable_request_reset() {
+    // Use a plain UDP socket as the peer so that the request can be rejected with a Reset.
+    let peer_socket = UdpSocket::bind("localhost:0").expect("Failed to bind peer socket");
+    peer_socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let peer_address = peer_socket.local_addr().unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, peer_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .build()
+        .unwrap();
+    assert_eq!(request.type_(), CoapMessageType::Con);
+    let req_handle = session.send_request(request).unwrap();
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+
+    let mut buf = [0u8; 64];
+    let len = peer_socket.recv(&mut buf).expect("request was not sent");
+    assert!(len >= 4);
+    // Confirmable (0b00) message type.
+    assert_eq!((buf[0] >> 4) & 0x03, 0);
+    peer_socket
+        .send_to(&[0x70, 0x00, buf[2], buf[3]], session.addr_local())
+        .unwrap();
+
+    let error = common::run_until(&mut [&mut context], "reset", |_| {
+        match session.try_poll_handle(&req_handle) {
+            Ok(mut responses) => {
+                assert!(responses.next().is_none());
+                None
+            },
+            Err(e) => Some(e),
+        }
+    });
+    assert_eq!(error, RequestPollError::Reset);
+    // The error is reported until the handle is removed.
+    assert_eq!(
+        session.try_poll_handle(&req_handle).unwrap_err(),
+        RequestPollError::Reset
+    );
+    session.remove_handle(req_handle);
+}
+
+#[test]
+pub fn separate_and_non_confirmable_responses() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    type SendResults = Vec<Result<(), MessageConversionError>>;
+    let send_results: Rc<RefCell<SendResults>> = Rc::default();
+    let last_server_session: Rc<RefCell<Option<CoapServerSession<'static>>>> = Rc::new(RefCell::new(None));
+    let last_server_session_handler = last_server_session.clone();
+    let resource = CoapResource::new("test1", send_results.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            move |send_results: &mut Rc<RefCell<SendResults>>,
+                  sess: &mut CoapServerSession,
+                  req: &CoapRequest,
+                  mut rsp: CoapResponse| {
+                match req.query_value("type").as_deref() {
+                    Some("non") => rsp.set_separate(false),
+                    Some("ack") => rsp.set_type_(CoapMessageType::Ack),
+                    _ => {},
+                }
+                rsp.set_data(Some("Hello World!".as_bytes().to_vec()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                send_results.borrow_mut().push(sess.send(rsp).map(|_| ()));
+                *last_server_session_handler.borrow_mut() = Some(sess.clone());
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    // Retransmit quickly, so that a missing ACK would be noticed.
+    session.set_ack_timeout(0, 100);
+    session.set_ack_random_factor(1, 0);
+
+    // By default, responses to confirmable requests are piggybacked.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.type_(), CoapMessageType::Ack);
+    assert!(response.is_piggybacked());
+
+    // A confirmable request answered with a non-confirmable response is acknowledged separately.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .uri_query([("type", "non")])
+        .confirmable(true)
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.type_(), CoapMessageType::Non);
+    assert!(!response.is_piggybacked());
+    assert_eq!(response.data(), Some("Hello World!".as_bytes()));
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+        context.do_io(Some(Duration::from_millis(10))).unwrap();
+    }
+    assert_eq!(session.stats().messages_sent, 2);
+    assert_eq!(session.stats().failed_deliveries, 0);
+    let server_stats = last_server_session.borrow().as_ref().unwrap().stats();
+    assert_eq!(server_stats.retransmissions_received, 0);
+    assert_eq!(send_results.borrow().len(), 2);
+
+    // Responses to non-confirmable requests can't be piggybacked.
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .uri_query([("type", "ack")])
+        .confirmable(false)
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    common::run_until(&mut [&mut context, &mut server_context], "request", |_| {
+        (send_results.borrow().len() >= 3).then_some(())
+    });
+    assert_eq!(
+        send_results.borrow().as_slice(),
+        &[
+            Ok(()),
+            Ok(()),
+            Err(MessageConversionError::InvalidResponseType(CoapMessageType::Ack))
+        ]
+    );
+    session.remove_handle(req_handle);
+
+    std::mem::drop(last_server_session.borrow_mut().take());
+}
+
+#[test]
+pub fn send_and_wait() {
+    let server_address = common::get_unused_server_addr();
+    let body: Vec<u8> = (0..16 * 1024).map(|i| i as u8).collect();
+    // The context also serves the requests, which only works if the server-side sessions are
+    // serviced while waiting for the response.
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("large", Arc::<[u8]>::from(body.as_slice()), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |body: &mut Arc<[u8]>, sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data_shared(Some(Arc::clone(body)));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    context.add_resource(resource);
+
+    // Block-wise responses are returned once they have been reassembled.
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["large"])
+        .build()
+        .unwrap();
+    let response = context
+        .send_and_wait(&session, request, Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.payload(), body.as_slice());
+    assert_eq!(session.pending_requests(), 0);
+
+    // Requests to peers that do not respond fail once the timeout has elapsed.
+    let peer_socket = UdpSocket::bind("localhost:0").expect("Failed to bind peer socket");
+    let silent_session = CoapClientSession::connect_udp(&mut context, peer_socket.local_addr().unwrap()).unwrap();
+    let start = Instant::now();
+    assert_eq!(
+        context.send_and_wait(&silent_session, common::gen_test_request(), Duration::from_millis(200)),
+        Err(RequestWaitError::Timeout)
+    );
+    assert!(start.elapsed() >= Duration::from_millis(200) && start.elapsed() < Duration::from_secs(5));
+    assert_eq!(silent_session.pending_requests(), 0);
+
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["large"])
+        .no_response(CoapNoResponse::ALL)
+        .build()
+        .unwrap();
+    assert_eq!(
+        context.send_and_wait(&session, request, Duration::from_secs(10)),
+        Err(RequestWaitError::NoResponseExpected)
+    );
+}
+
+#[test]
+pub fn large_payloads() {
+    static STATIC_BODY: [u8; 4096] = [0x5a; 4096];
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let echo_resource = CoapResource::new("echo", (), false);
+    echo_resource.set_method_handler(
+        CoapRequestCode::Post,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data_shared(Some(Arc::<[u8]>::from(req.payload())));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(echo_resource);
+    let static_resource = CoapResource::new("static", (), false);
+    static_resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |_: &mut (), sess, _req, mut rsp: CoapResponse| {
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                rsp.set_data_static(Some(&STATIC_BODY));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(static_resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    // Both the request and the response are transferred using multiple blocks.
+    let body: Vec<u8> = (0..64 * 1024).map(|i| i as u8).collect();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Post)
+        .uri_path(["echo"])
+        .payload(body.clone())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.payload(), body.as_slice());
+
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["static"])
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.payload(), STATIC_BODY.as_slice());
+
+    // Messages with shared or borrowed payloads compare equal to ones that own their payload.
+    let mut shared = CoapResponse::new(CoapMessageType::Con, CoapResponseCode::Content).unwrap();
+    shared.set_data_shared(Some(body.clone()));
+    let mut owned = shared.clone();
+    owned.set_data(Some(body));
+    assert_eq!(shared, owned);
+}
+
+#[test]
+pub fn extended_method_round_trip() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", (), false);
+    for code in [
+        CoapRequestCode::Delete,
+        CoapRequestCode::Fetch,
+        CoapRequestCode::Patch,
+        CoapRequestCode::IPatch,
+    ] {
+        resource.set_method_handler(
+            code,
+            Some(CoapRequestHandler::new(
+                |_: &mut (), sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                    // Echo the request method and payload so that the client can check that the
+                    // request was dispatched to the correct handler.
+                    let mut data = format!("{:?}:", req.code()).into_bytes();
+                    data.extend_from_slice(req.data().map_or(&[][..], |v| v.as_ref()));
+                    rsp.set_data(Some(data));
+                    rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                    sess.send(rsp).unwrap();
+                },
+            )),
+        );
+    }
+    server_context.add_resource(resource);
+    server_context.add_resource(CoapResource::new("test2", (), false));
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    for code in [
+        CoapRequestCode::Delete,
+        CoapRequestCode::Fetch,
+        CoapRequestCode::Patch,
+        CoapRequestCode::IPatch,
+    ] {
+        let request = CoapRequestBuilder::new(code)
+            .uri_path(["test1"])
+            .content_format(CoapContentFormat::TextPlain)
+            .payload("body".as_bytes().to_vec())
+            .build()
+            .unwrap();
+        assert_eq!(request.code(), CoapMessageCode::Request(code));
+        let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+        assert_eq!(
+            response.data().unwrap().as_ref(),
+            format!("{:?}:body", CoapMessageCode::Request(code)).as_bytes()
+        );
+    }
+
+    // Methods without a registered handler are rejected by libcoap.
+    for code in [CoapRequestCode::Fetch, CoapRequestCode::Patch, CoapRequestCode::IPatch] {
+        let request = CoapRequestBuilder::new(code).uri_path(["test2"]).build().unwrap();
+        let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::NotAllowed));
+    }
+}
+
+#[test]
+pub fn custom_critical_options() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let received_options = Rc::new(RefCell::new(Vec::new()));
+    let resource = CoapResource::new("test1", received_options.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |received_options: &mut Rc<RefCell<Vec<CoapOption>>>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                received_options.replace(req.options().get_all(65001).cloned().collect());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let gen_request = || {
+        let mut request = CoapRequestBuilder::new(CoapRequestCode::Get)
+            .uri_path(["test1"])
+            .build()
+            .unwrap();
+        request.add_option(CoapOption::Other(65001, Box::new([7])));
+        request
+    };
+
+    // Unknown critical options are rejected by libcoap without calling the handler.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, gen_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::BadOption));
+    assert!(received_options.borrow().is_empty());
+
+    server_context.register_custom_option(65001).unwrap();
+    server_context.register_custom_option(65001).unwrap();
+    assert_eq!(server_context.custom_options(), vec![65001]);
+    let response = common::exchange_request(&mut server_context, &mut context, &session, gen_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(
+        *received_options.borrow(),
+        vec![CoapOption::Other(65001, Box::new([7]))]
+    );
+
+    // libcoap only has space for two custom options with a number above 255.
+    assert_eq!(
+        server_context.register_custom_options([65003, 65005]),
+        Err(ContextConfigurationError::TooManyCustomOptions(65005))
+    );
+    assert_eq!(server_context.custom_options(), vec![65001]);
+    server_context.register_custom_options([65003, 201, 203]).unwrap();
+    assert_eq!(server_context.custom_options(), vec![65001, 65003, 201, 203]);
+}
+
+#[test]
+pub fn retransmitted_requests_are_detected() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    type ReceivedRequest = (Option<CoapMessageId>, CoapMessageType, Vec<u8>, bool);
+    let received: Rc<RefCell<Vec<ReceivedRequest>>> = Rc::new(RefCell::new(Vec::new()));
+    let resource = CoapResource::new("test1", received.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |received: &mut Rc<RefCell<Vec<ReceivedRequest>>>, sess, req: &CoapRequest, mut rsp: CoapResponse| {
+                received.borrow_mut().push((
+                    req.mid(),
+                    req.type_(),
+                    req.token().unwrap_or_default().to_vec(),
+                    req.is_retransmission(),
+                ));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    // Send the same confirmable GET request twice (as a retransmission would), followed by a
+    // new request with a different message ID and a duplicated non-confirmable request.
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    for (type_bits, mid) in [
+        (0x40u8, 0x1234u16),
+        (0x40, 0x1234),
+        (0x40, 0x1235),
+        (0x50, 0x1236),
+        (0x50, 0x1236),
+    ] {
+        let mut datagram = vec![type_bits | 2, 0x01];
+        datagram.extend_from_slice(&mid.to_be_bytes());
+        datagram.extend_from_slice(&[0xAB, mid as u8]);
+        datagram.push(0xB5);
+        datagram.extend_from_slice("test1".as_bytes());
+        peer_socket.send_to(&datagram, server_address).unwrap();
+    }
+
+    common::run_until(&mut [&mut server_context], "requests", |_| {
+        (received.borrow().len() >= 5).then_some(())
+    });
+    let con = CoapMessageType::Con;
+    let non = CoapMessageType::Non;
+    assert_eq!(
+        *received.borrow(),
+        vec![
+            (Some(0x1234), con, vec![0xAB, 0x34], false),
+            (Some(0x1234), con, vec![0xAB, 0x34], true),
+            (Some(0x1235), con, vec![0xAB, 0x35], false),
+            (Some(0x1236), non, vec![0xAB, 0x36], false),
+            (Some(0x1236), non, vec![0xAB, 0x36], true),
+        ]
+    );
+    assert_eq!(server_context.duplicate_request_count(), 2);
+
+    // Tracking windows derived from the default transmission parameters (RFC 7252, Section 4.8.2).
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    assert_eq!(session.exchange_lifetime(), Duration::from_secs(247));
+    assert_eq!(session.non_lifetime(), Duration::from_secs(145));
+}
+
+#[test]
+pub fn no_response_suppresses_responses() {
+    assert!(CoapNoResponse::SUPPRESS_SUCCESS.suppresses(CoapResponseCode::Content));
+    assert!(!CoapNoResponse::SUPPRESS_SUCCESS.suppresses(CoapResponseCode::NotFound));
+    assert!(CoapNoResponse::SUPPRESS_CLIENT_ERROR.suppresses(CoapResponseCode::NotFound));
+    assert!(CoapNoResponse::SUPPRESS_SERVER_ERROR.suppresses(CoapResponseCode::InternalError));
+    assert!(!CoapNoResponse::empty().suppresses(CoapResponseCode::Content));
+
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let handled_requests = Rc::new(Cell::new(0usize));
+    let resource = CoapResource::new("test1", handled_requests.clone(), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |handled_requests: &mut Rc<Cell<usize>>, sess, _req, mut rsp: CoapResponse| {
+                handled_requests.set(handled_requests.get() + 1);
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    // Send a non-confirmable GET request that asks for successful responses to be suppressed
+    // (No-Response option with value 2), followed by the same request without this option.
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let mut buf = [0u8; 1152];
+    for (mid, no_response) in [(0x2001u16, true), (0x2002, false)] {
+        let mut datagram = vec![0x50, 0x01];
+        datagram.extend_from_slice(&mid.to_be_bytes());
+        datagram.push(0xB5);
+        datagram.extend_from_slice("test1".as_bytes());
+        if no_response {
+            // Option number 258 (delta 247 = 13 + 234), length 1.
+            datagram.extend_from_slice(&[0xD1, 234, CoapNoResponse::SUPPRESS_SUCCESS.bits()]);
+        }
+        peer_socket.send_to(&datagram, server_address).unwrap();
+
+        let handled_before = handled_requests.get();
+        common::run_until(&mut [&mut server_context], "request", |_| {
+            (handled_requests.get() != handled_before).then_some(())
+        });
+        server_context.do_io(Some(Duration::from_millis(10))).unwrap();
+        let received = peer_socket.recv_from(&mut buf);
+        assert_eq!(received.is_ok(), !no_response);
+    }
+
+    // Requests that suppress all responses are not tracked by the client.
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Get)
+        .uri_path(["test1"])
+        .confirmable(false)
+        .no_response(CoapNoResponse::ALL)
+        .build()
+        .unwrap();
+    let req_handle = session.send_request(request).unwrap();
+    assert!(!req_handle.expects_response());
+    let handled_before = handled_requests.get();
+    common::run_until(&mut [&mut server_context, &mut context], "request", |_| {
+        (handled_requests.get() != handled_before).then_some(())
+    });
+    context.do_io(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(session.poll_handle(&req_handle).count(), 0);
+}
+
+#[test]
+pub fn echo_challenges_unsafe_requests() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let received_echoes: Rc<RefCell<Vec<Option<Box<[u8]>>>>> = Rc::new(RefCell::new(Vec::new()));
+    let resource = CoapResource::new("test1", received_echoes.clone(), false);
+    resource.set_echo_policy(CoapEchoPolicy::UnsafeMethods);
+    let handler = || {
+        CoapRequestHandler::new(
+            |received_echoes: &mut Rc<RefCell<Vec<Option<Box<[u8]>>>>>,
+             sess: &mut CoapServerSession,
+             req: &CoapRequest,
+             mut rsp: CoapResponse| {
+                received_echoes.borrow_mut().push(req.echo().cloned());
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )
+    };
+    resource.set_method_handler(CoapRequestCode::Get, Some(handler()));
+    resource.set_method_handler(CoapRequestCode::Put, Some(handler()));
+    server_context.add_resource(resource);
+
+    // Requests without an Echo option are challenged without calling the handler.
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.set_nonblocking(true).unwrap();
+    let mut datagram = vec![0x40, 0x03, 0x12, 0x34, 0xB5];
+    datagram.extend_from_slice("test1".as_bytes());
+    peer_socket.send_to(&datagram, server_address).unwrap();
+    let mut buf = [0u8; 256];
+    let len = common::run_until(&mut [&mut server_context], "challenge", |_| {
+        peer_socket.recv(&mut buf).ok()
+    });
+    // Piggybacked 4.01 response for message ID 0x1234 containing an Echo option (number 252,
+    // encoded as extended delta 252 - 13 = 239).
+    assert!(len > 6);
+    assert_eq!(buf[1], 0x81);
+    assert_eq!(&buf[2..4], &[0x12, 0x34]);
+    assert_eq!(buf[4] >> 4, 13);
+    assert_eq!(buf[5], 239);
+    assert!(received_echoes.borrow().is_empty());
+
+    // libcoap repeats challenged requests including the Echo value, safe requests are not
+    // challenged.
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let request = CoapRequestBuilder::new(CoapRequestCode::Put)
+        .uri_path(["test1"])
+        .payload("value".as_bytes().to_vec())
+        .build()
+        .unwrap();
+    let response = common::exchange_request(&mut server_context, &mut context, &session, request);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Changed));
+    let received_echoes = received_echoes.borrow();
+    assert_eq!(received_echoes.len(), 2);
+    assert!(received_echoes[0].is_some());
+    assert!(received_echoes[1].is_none());
+}
+
+#[test]
+pub fn request_handler_panic() {
+    let server_address = common::get_unused_server_addr();
+
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let resource = CoapResource::new("test1", 0u32, false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |requests: &mut u32, sess: &mut CoapServerSession, _req: &CoapRequest, mut rsp: CoapResponse| {
+                *requests += 1;
+                if *requests == 1 {
+                    panic!("deliberate handler failure");
+                }
+                rsp.set_data(Some(requests.to_string().into_bytes()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+    assert!(server_context.take_last_handler_panic().is_none());
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(
+        response.code(),
+        CoapMessageCode::Response(CoapResponseCode::InternalError)
+    );
+    // The panic is only reported by the context of the handler, not by other contexts that
+    // performed IO on the same thread.
+    assert!(context.take_last_handler_panic().is_none());
+    let payload = server_context
+        .take_last_handler_panic()
+        .expect("panic of request handler was not recorded");
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"deliberate handler failure"));
+    assert!(server_context.take_last_handler_panic().is_none());
+
+    // The server must keep serving requests (using the same handler) after a handler panicked.
+    let response = common::exchange_request(&mut server_context, &mut context, &session, common::gen_test_request());
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    assert_eq!(response.data().unwrap().as_ref(), "2".as_bytes());
+    assert!(server_context.take_last_handler_panic().is_none());
+}
+
+#[test]
+pub fn bad_packets_are_counted() {
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+
+    // Datagram with an invalid CoAP version, which can't be parsed by libcoap.
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.send_to(&[0x00, 0x01, 0x00, 0x01], server_address).unwrap();
+
+    common::run_until(&mut [&mut server_context], "bad packet", |contexts| {
+        (contexts[0].bad_packet_count() > 0).then_some(())
+    });
+    assert_eq!(server_context.bad_packet_count(), 1);
+}
+
+#[derive(Debug)]
+struct TruncationRecorder {
+    truncated: Rc<RefCell<Vec<(SocketAddr, usize)>>>,
+}
+
+impl CoapEventHandler for TruncationRecorder {
+    fn handle_truncated_datagram(&mut self, session: &mut CoapSession, len: usize) {
+        self.truncated.borrow_mut().push((session.addr_remote(), len));
+    }
+}
+
+#[test]
+#[cfg(coap_rxbuffer_headroom)]
+pub fn truncated_datagrams_are_dropped() {
+    const PAYLOAD_LEN: usize = 8192;
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let truncated: Rc<RefCell<Vec<(SocketAddr, usize)>>> = Rc::default();
+    server_context.set_event_handler(TruncationRecorder {
+        truncated: Rc::clone(&truncated),
+    });
+    let payload_lens: Rc<RefCell<Vec<usize>>> = Rc::default();
+    let resource = CoapResource::new("test1", Rc::clone(&payload_lens), false);
+    resource.set_method_handler(
+        CoapRequestCode::Get,
+        Some(CoapRequestHandler::new(
+            |payload_lens: &mut Rc<RefCell<Vec<usize>>>,
+             sess: &mut CoapServerSession,
+             req: &CoapRequest,
+             mut rsp: CoapResponse| {
+                payload_lens.borrow_mut().push(req.data().map_or(0, |v| v.len()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Content));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    // Confirmable GET request for "test1" whose payload exceeds libcoap's receive buffer, so the
+    // socket truncates it.
+    let mut datagram = vec![0x40, 0x01, 0x12, 0x34, 0xb5];
+    datagram.extend_from_slice(b"test1");
+    datagram.push(0xff);
+    datagram.resize(datagram.len() + PAYLOAD_LEN, 0x2a);
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.send_to(&datagram, server_address).unwrap();
+    common::run_until(&mut [&mut server_context], "truncated datagram", |contexts| {
+        (contexts[0].bad_packet_count() > 0).then_some(())
+    });
+
+    // The truncated datagram is counted and reported, but never passed to the request handler.
+    assert_eq!(server_context.bad_packet_count(), 1);
+    assert_eq!(truncated.borrow().len(), 1);
+    let (peer, len) = truncated.borrow()[0];
+    assert_eq!(peer, peer_socket.local_addr().unwrap());
+    assert!(len < datagram.len());
+    assert!(payload_lens.borrow().is_empty());
+
+    // The context keeps handling regular requests.
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_udp(&mut context, server_address).unwrap();
+    let req_handle = session.send_request(common::gen_test_request()).unwrap();
+    common::wait_for_response(&mut server_context, &mut context, &session, &req_handle);
+    assert_eq!(*payload_lens.borrow(), vec![0]);
+    assert_eq!(server_context.bad_packet_count(), 1);
+}
+
+#[test]
+#[cfg(coap_rxbuffer_headroom)]
+pub fn datagrams_of_maximum_size_are_handled() {
+    // libcoap's receive buffer is one byte larger than the largest datagram it accepts.
+    let max_datagram_size = libcoap_sys::COAP_RXBUFFER_SIZE as usize - 1;
+    let server_address = common::get_unused_server_addr();
+    let mut server_context = CoapContext::new().unwrap();
+    server_context.add_endpoint_udp(server_address).unwrap();
+    let payload_lens: Rc<RefCell<Vec<usize>>> = Rc::default();
+    let resource = CoapResource::new("test1", Rc::clone(&payload_lens), false);
+    resource.set_method_handler(
+        CoapRequestCode::Put,
+        Some(CoapRequestHandler::new(
+            |payload_lens: &mut Rc<RefCell<Vec<usize>>>,
+             sess: &mut CoapServerSession,
+             req: &CoapRequest,
+             mut rsp: CoapResponse| {
+                payload_lens.borrow_mut().push(req.data().map_or(0, |v| v.len()));
+                rsp.set_code(CoapMessageCode::Response(CoapResponseCode::Changed));
+                sess.send(rsp).unwrap();
+            },
+        )),
+    );
+    server_context.add_resource(resource);
+
+    // Non-confirmable PUT request for "test1" that is exactly as large as the largest accepted
+    // datagram.
+    let mut datagram = vec![0x50, 0x03, 0x12, 0x34, 0xb5];
+    datagram.extend_from_slice(b"test1");
+    datagram.push(0xff);
+    let payload_len = max_datagram_size - datagram.len();
+    datagram.resize(max_datagram_size, 0x2a);
+    let peer_socket = UdpSocket::bind(SocketAddr::new(server_address.ip(), 0)).expect("Failed to bind peer socket");
+    peer_socket.send_to(&datagram, server_address).unwrap();
+    common::run_until(&mut [&mut server_context], "request", |_| {
+        (!payload_lens.borrow().is_empty()).then_some(())
+    });
+
+    assert_eq!(*payload_lens.borrow(), vec![payload_len]);
+    assert_eq!(server_context.bad_packet_count(), 0);
+}
@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * unix_client_server_test.rs - Tests for Unix domain socket clients+servers.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(all(feature = "af-unix", unix))]
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use libcoap_rs::session::CoapClientSession;
+use libcoap_rs::{
+    error::UnixSocketPathError,
+    message::{CoapMessageCommon, CoapResponse},
+    protocol::{CoapMessageCode, CoapResponseCode},
+    rate_limit::CoapRateLimit,
+    session::CoapSessionCommon,
+    types::CoapAddress,
+    CoapContext, CoapResource,
+};
+
+mod common;
+
+/// Temporary directory for the socket files of a single test, which is removed (along with any
+/// socket files left in it) once dropped.
+struct SocketDir(PathBuf);
+
+impl SocketDir {
+    // libcoap's sockaddr_un may be considerably smaller than the platform's, so we keep the paths
+    // short.
+    fn new(test: &str) -> SocketDir {
+        let path = std::env::temp_dir().join(format!("coaprs-{}-{}", std::process::id(), test));
+        // Remove leftovers of an earlier run that happened to have the same process ID.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir(&path).unwrap();
+        SocketDir(path)
+    }
+
+    fn socket_path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for SocketDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Creates a server context with an endpoint at `server_path` and a resource at `/test1`.
+fn test_server(server_path: &Path) -> CoapContext<'static> {
+    let mut context = CoapContext::new().unwrap();
+    context.add_endpoint_unix(server_path).unwrap();
+    let resource = CoapResource::builder("test1", ())
+        .get(|_resource, session, _request, mut response| {
+            response.set_code(CoapResponseCode::Content);
+            session.send(response).unwrap();
+        })
+        .build()
+        .unwrap();
+    context.add_resource(resource);
+    context
+}
+
+/// Sends a test request on the given session and performs the IO of both contexts until the
+/// response is received.
+fn exchange_request<'a>(
+    server_context: &mut CoapContext<'a>,
+    context: &mut CoapContext<'a>,
+    session: &CoapClientSession<'_>,
+) -> CoapResponse {
+    common::exchange_request(server_context, context, session, common::gen_test_request())
+}
+
+#[test]
+pub fn basic_client_server_request() {
+    let socket_dir = SocketDir::new("basic");
+    let server_path = socket_dir.socket_path("s");
+    let client_path = socket_dir.socket_path("c");
+
+    let server_handle = common::spawn_test_server({
+        let server_path = server_path.clone();
+        move |mut context| {
+            context.add_endpoint_unix(&server_path).unwrap();
+            context
+        }
+    });
+
+    // Wait until the server has bound its socket.
+    while !server_path.exists() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_unix(&mut context, &client_path, &server_path).unwrap();
+    assert_eq!(session.remote_address().unix_path(), Some(server_path.clone()));
+    assert_eq!(session.remote_address().socket_addr(), None);
+    assert_eq!(session.local_address().unix_path(), Some(client_path.clone()));
+
+    let request = common::gen_test_request();
+    let req_handle = session.send_request(request).unwrap();
+    loop {
+        assert!(context.do_io(Some(Duration::from_secs(10))).expect("error during IO") <= Duration::from_secs(10));
+        for response in session.poll_handle(&req_handle) {
+            assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+            assert_eq!(response.data().unwrap().as_ref(), "Hello World!".as_bytes());
+            server_handle.join().unwrap();
+            // The server endpoint removes its socket file once its context is dropped.
+            assert!(!server_path.exists());
+            std::mem::drop(session);
+            context.shutdown(Some(Duration::from_secs(0))).unwrap();
+            assert!(!client_path.exists());
+            return;
+        }
+    }
+}
+
+#[test]
+pub fn unix_addresses() {
+    let path = Path::new("/run/coap.sock");
+    let addr = CoapAddress::unix(path).unwrap();
+    assert_eq!(addr.unix_path().as_deref(), Some(path));
+    assert_eq!(addr.socket_addr(), None);
+
+    let too_long = PathBuf::from("a".repeat(CoapAddress::max_unix_path_len() + 1));
+    assert!(matches!(
+        CoapAddress::unix(&too_long),
+        Err(UnixSocketPathError::TooLong(len)) if len == CoapAddress::max_unix_path_len()
+    ));
+    assert!(matches!(
+        CoapAddress::unix(Path::new("/run/co\0ap.sock")),
+        Err(UnixSocketPathError::ContainsNullByte)
+    ));
+}
+
+#[test]
+pub fn rate_limit_ignores_unix_peers() {
+    let socket_dir = SocketDir::new("rate");
+    let server_path = socket_dir.socket_path("s");
+    let mut server_context = test_server(&server_path);
+    // Would throttle every request of an IP peer after the first one, but peers on Unix domain
+    // sockets have no IP address to apply it to.
+    server_context.set_rate_limit(Some(CoapRateLimit::new(0.0, 1)));
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_unix(&mut context, socket_dir.socket_path("c"), &server_path).unwrap();
+    for _ in 0..3 {
+        let response = exchange_request(&mut server_context, &mut context, &session);
+        assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    }
+}
+
+#[test]
+pub fn session_by_peer_skips_unix_sessions() {
+    let socket_dir = SocketDir::new("peer");
+    let server_path = socket_dir.socket_path("s");
+    let mut server_context = test_server(&server_path);
+
+    let mut context = CoapContext::new().unwrap();
+    let session = CoapClientSession::connect_unix(&mut context, socket_dir.socket_path("c"), &server_path).unwrap();
+    let response = exchange_request(&mut server_context, &mut context, &session);
+    assert_eq!(response.code(), CoapMessageCode::Response(CoapResponseCode::Content));
+    // The server-side session of the Unix domain socket peer has no IP address to compare.
+    assert!(server_context
+        .session_by_peer(common::get_unused_server_addr())
+        .is_none());
+}
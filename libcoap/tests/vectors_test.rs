@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: BSD-2-Clause
+/*
+ * vectors_test.rs - Conformance tests for message encoding using golden test vectors.
+ * This file is part of the libcoap-rs crate, see the README and LICENSE files for
+ * more information and terms of use.
+ * Copyright © 2021-2024 The NAMIB Project Developers, all rights reserved.
+ * See the README as well as the LICENSE file for more information.
+ */
+#![cfg(feature = "test-util")]
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use libcoap_rs::{
+    message::{CoapMessage, CoapMessageCommon},
+    session::{CoapClientSession, CoapSessionCommon},
+    test_vectors::message_vectors,
+    types::CoapProtocol,
+    CoapContext,
+};
+use libcoap_sys::{coap_delete_pdu, coap_pdu_code_t, coap_pdu_init, coap_pdu_parse, coap_pdu_type_t, coap_proto_t};
+
+/// Parses the given bytes using libcoap's parser.
+fn decode(proto: CoapProtocol, bytes: &[u8]) -> CoapMessage {
+    let raw_proto = match proto {
+        CoapProtocol::Udp => coap_proto_t::COAP_PROTO_UDP,
+        CoapProtocol::Tcp => coap_proto_t::COAP_PROTO_TCP,
+        _ => panic!("unsupported protocol for test vector: {}", proto),
+    };
+    // SAFETY: The PDU is checked for null, parsed into and deleted again before returning.
+    unsafe {
+        let pdu = coap_pdu_init(
+            coap_pdu_type_t::COAP_MESSAGE_CON,
+            coap_pdu_code_t::COAP_EMPTY_CODE,
+            0,
+            bytes.len(),
+        );
+        assert!(!pdu.is_null());
+        assert_ne!(coap_pdu_parse(raw_proto, bytes.as_ptr(), bytes.len(), pdu), 0);
+        let message = CoapMessage::from_raw_pdu(pdu);
+        coap_delete_pdu(pdu);
+        message.unwrap()
+    }
+}
+
+#[test]
+pub fn vector_names_are_unique() {
+    let vectors = message_vectors();
+    for (idx, vector) in vectors.iter().enumerate() {
+        assert!(
+            vectors[..idx].iter().all(|v| v.name != vector.name),
+            "duplicate vector name {}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+pub fn decode_vectors() {
+    for vector in message_vectors() {
+        let mut decoded = decode(vector.proto, &vector.bytes);
+        if vector.proto == CoapProtocol::Tcp {
+            // Messages over TCP have no message type and ID.
+            decoded.set_type_(vector.message.type_());
+            decoded.set_mid(vector.message.mid());
+        }
+        assert_eq!(
+            decoded, vector.message,
+            "decoded message differs for vector {}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+pub fn encode_vectors() {
+    let peer_socket = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind peer socket");
+    peer_socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let peer_address: SocketAddr = peer_socket.local_addr().unwrap();
+
+    let mut context = CoapContext::new().unwrap();
+    let mut buf = vec![0u8; 2048];
+    for vector in message_vectors().into_iter().filter(|v| v.proto == CoapProtocol::Udp) {
+        // Use a new session for each vector, as libcoap does not send further confirmable
+        // messages on a session until the previous one has been acknowledged.
+        let session = CoapClientSession::connect_udp(&mut context, peer_address).unwrap();
+        session.send(vector.message.clone()).unwrap();
+        let (len, _) = peer_socket
+            .recv_from(&mut buf)
+            .unwrap_or_else(|e| panic!("no message received for vector {}: {}", vector.name, e));
+        assert_eq!(
+            &buf[..len],
+            vector.bytes.as_slice(),
+            "encoding differs for vector {}",
+            vector.name
+        );
+    }
+    context.shutdown(Some(Duration::from_secs(0))).unwrap();
+}
+
+#[test]
+pub fn codec_vectors() {
+    for vector in message_vectors() {
+        assert_eq!(
+            vector.message.to_bytes(vector.proto).unwrap(),
+            vector.bytes,
+            "encoding differs for vector {}",
+            vector.name
+        );
+        // Messages over TCP are parsed without message type and ID, as in the vectors.
+        let decoded = CoapMessage::from_bytes(vector.proto, &vector.bytes).unwrap();
+        assert_eq!(
+            decoded, vector.message,
+            "decoded message differs for vector {}",
+            vector.name
+        );
+    }
+}